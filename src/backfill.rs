@@ -0,0 +1,202 @@
+//! Startup backfill of updates missed while the bot was offline.
+//!
+//! The last processed update_id is persisted in the data dir. On startup,
+//! before the Dispatcher runs, updates after that offset are fetched with a
+//! manual getUpdates loop and fed through the normal handlers. Messages older
+//! than the configured age are stored but don't trigger a response.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use teloxide::prelude::*;
+use teloxide::types::UpdateKind;
+use tracing::{info, warn};
+
+use crate::bot::{self as handlers, BotState};
+
+/// Max updates per getUpdates call (Telegram limit).
+const BATCH_LIMIT: u8 = 100;
+
+/// Last processed update_id, persisted to a file.
+pub struct UpdateOffset {
+    path: PathBuf,
+    /// 0 = nothing processed yet (Telegram update IDs are positive).
+    last: AtomicU32,
+}
+
+impl UpdateOffset {
+    /// Load from `path`, starting empty if missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let last = match std::fs::read_to_string(path) {
+            Ok(s) => match s.trim().parse::<u32>() {
+                Ok(id) => id,
+                Err(e) => {
+                    warn!("Ignoring invalid update offset in {:?}: {}", path, e);
+                    0
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => {
+                warn!("Failed to read update offset {:?}: {}", path, e);
+                0
+            }
+        };
+
+        Self { path: path.to_path_buf(), last: AtomicU32::new(last) }
+    }
+
+    /// Last processed update_id, if any.
+    pub fn last(&self) -> Option<u32> {
+        match self.last.load(Ordering::SeqCst) {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    /// Mark an update as processed. Returns false if it (or a later one)
+    /// was already processed, so the caller should skip it.
+    pub fn record(&self, update_id: u32) -> bool {
+        let previous = self.last.fetch_max(update_id, Ordering::SeqCst);
+        if update_id <= previous {
+            return false;
+        }
+        if let Err(e) = std::fs::write(&self.path, update_id.to_string()) {
+            warn!("Failed to persist update offset: {}", e);
+        }
+        true
+    }
+}
+
+/// Whether a backfilled message is too old to respond to.
+pub fn is_stale(sent_at: DateTime<Utc>, now: DateTime<Utc>, max_age: chrono::Duration) -> bool {
+    now - sent_at > max_age
+}
+
+/// Fetch and process updates missed since the last run. Returns the number processed.
+///
+/// Skipped on first run (no stored offset) - there's nothing to anchor to.
+pub async fn run(bot: &Bot, state: &Arc<BotState>) -> usize {
+    let Some(last) = state.update_offset.last() else {
+        info!("No stored update offset, skipping backfill");
+        return 0;
+    };
+
+    let mut offset = last + 1;
+    let mut processed = 0;
+
+    loop {
+        // Calling getUpdates with a higher offset confirms everything before it,
+        // so the Dispatcher won't receive these updates again.
+        let updates = match bot
+            .get_updates()
+            .offset(offset as i32)
+            .limit(BATCH_LIMIT)
+            .timeout(0)
+            .await
+        {
+            Ok(u) => u,
+            Err(e) => {
+                warn!("Backfill getUpdates failed: {}", e);
+                break;
+            }
+        };
+
+        if updates.is_empty() {
+            break;
+        }
+
+        for update in updates {
+            offset = offset.max(update.id.0 + 1);
+            if !state.update_offset.record(update.id.0) {
+                continue;
+            }
+            processed += 1;
+            dispatch(bot, state, update.kind).await;
+        }
+    }
+
+    if processed > 0 {
+        info!("⏪ Backfilled {} missed update(s)", processed);
+    }
+    processed
+}
+
+/// Route a backfilled update to the same handlers the Dispatcher uses.
+async fn dispatch(bot: &Bot, state: &Arc<BotState>, kind: UpdateKind) {
+    let result = match kind {
+        UpdateKind::Message(msg) => handlers::process_new_message(bot.clone(), msg, state.clone(), true).await,
+        UpdateKind::EditedMessage(msg) => handlers::handle_edited_message(msg, state.clone()).await,
+        UpdateKind::ChannelPost(msg) => handlers::process_channel_post(msg, state.clone(), true).await,
+        UpdateKind::ChatMember(update) => handlers::handle_chat_member(update, state.clone()).await,
+        UpdateKind::MyChatMember(update) => handlers::handle_my_chat_member(update, state.clone()).await,
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        warn!("Error handling backfilled update: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_missing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let offset = UpdateOffset::load(&dir.path().join("update_offset"));
+        assert_eq!(offset.last(), None);
+    }
+
+    #[test]
+    fn test_offset_persists_across_loads() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("update_offset");
+
+        let offset = UpdateOffset::load(&path);
+        assert!(offset.record(100));
+        assert!(offset.record(101));
+
+        let reloaded = UpdateOffset::load(&path);
+        assert_eq!(reloaded.last(), Some(101));
+    }
+
+    #[test]
+    fn test_offset_rejects_already_processed() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("update_offset");
+
+        let offset = UpdateOffset::load(&path);
+        assert!(offset.record(50));
+        // Same update delivered again (e.g. first live update after backfill)
+        assert!(!offset.record(50));
+        // Older update
+        assert!(!offset.record(49));
+        assert_eq!(offset.last(), Some(50));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "50");
+    }
+
+    #[test]
+    fn test_offset_invalid_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("update_offset");
+        std::fs::write(&path, "garbage").unwrap();
+
+        let offset = UpdateOffset::load(&path);
+        assert_eq!(offset.last(), None);
+        assert!(offset.record(7));
+        assert_eq!(UpdateOffset::load(&path).last(), Some(7));
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let now = Utc::now();
+        let max_age = chrono::Duration::minutes(30);
+
+        assert!(!is_stale(now - chrono::Duration::minutes(5), now, max_age));
+        assert!(!is_stale(now - max_age, now, max_age));
+        assert!(is_stale(now - chrono::Duration::minutes(31), now, max_age));
+        assert!(is_stale(now - chrono::Duration::days(1), now, max_age));
+    }
+}
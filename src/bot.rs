@@ -0,0 +1,1346 @@
+//! The bot: its state and the handlers the dispatcher runs for each
+//! update. Group messages go through the spam filter first; only what passes
+//! reaches the chatbot.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{oneshot, Mutex};
+
+use teloxide::dispatching::UpdateHandler;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommandScope, ChatKind, InlineQuery, InlineQueryResult, PhotoSize, Recipient, ReplyParameters};
+use tracing::{debug, info, warn};
+
+use crate::backfill;
+use crate::chatbot::{system_prompt, ChatMessage, ChatbotConfig, ChatbotEngine, ClaudeCode, ReplyTo, TelegramApi, TelegramClient, TrustedUser, Whisper};
+use crate::chatbot::bot_status::BotStatus;
+use crate::chatbot::claude_code::SessionStart;
+use crate::chatbot::config_store::ConfigStore;
+use crate::chatbot::commands::{self, CommandContext, Permission};
+use crate::chatbot::image_limits;
+use crate::chatbot::maintenance::Maintenance;
+use crate::chatbot::message::DocumentContent;
+use crate::chatbot::names;
+use crate::chatbot::metrics;
+use crate::chatbot::personality;
+use crate::chatbot::prompt;
+use crate::chatbot::session_transcript::SessionTranscript;
+use crate::chatbot::telegram::{dice_text, message_location};
+use crate::chatbot::tools::ToolCall;
+use crate::chatbot::voices;
+use crate::chatbot::whisper::TranscribeError;
+use crate::chatbot::message::extract_mentions;
+use crate::chatbot::reactions::reaction_changes;
+use crate::classifier::{classify, Classification};
+use crate::claude::Client as ClaudeClient;
+use crate::chatbot::lookups::{self, CommandChat};
+use crate::config::{self, Config};
+use crate::dm_policy::{self, DmAction, DmCounts};
+use crate::inline;
+use crate::links::{HttpResolver, LinkExpander};
+use crate::media::{DeliveryQueue, MediaLimiter};
+use crate::member_events::{service_invite_source, service_member_events, update_invite_source, InviteSource, MemberEventDedup, MemberEventKind};
+use crate::prefilter::{escalate_via_bot, prefilter, PrefilterResult};
+use crate::senders::{self, Sender};
+use crate::spam_images;
+use crate::spam_wave::{WaveDetector, NEW_ACCOUNT_AGE};
+use crate::startup::{self, StartupNotify, SummaryStore};
+
+pub struct BotState {
+    pub config: Config,
+    claude: ClaudeClient,
+    /// Spam strikes per sender (user, or channel posting in a group).
+    strikes: Mutex<HashMap<i64, u8>>,
+    pub chatbot: Option<ChatbotEngine>,
+    /// DMs from users who may not DM the bot, when there's no database to count them in.
+    dm_denied: Mutex<DmCounts>,
+    whisper: Option<Whisper>,
+    /// Bounds photo, voice and document downloads.
+    media: MediaLimiter,
+    /// Keeps each chat's messages in arrival order on their way to the chatbot.
+    deliveries: DeliveryQueue,
+    pub(crate) update_offset: backfill::UpdateOffset,
+    spam_wave: Mutex<WaveDetector>,
+    links: LinkExpander<HttpResolver>,
+    /// Joins/leaves seen recently, so service messages and chat_member
+    /// updates for the same event are only recorded once.
+    member_events: Mutex<MemberEventDedup>,
+    /// How the Claude session started, for the startup notification.
+    session_start: Mutex<Option<oneshot::Receiver<SessionStart>>>,
+}
+
+impl BotState {
+    pub async fn new(config: Config, bot: &Bot) -> Self {
+        let claude = ClaudeClient::new(config.openrouter_api_key.clone());
+
+        // Get bot info
+        let (bot_user_id, bot_username) = match bot.get_me().await {
+            Ok(me) => {
+                info!("Bot user ID: {}, username: @{}", me.id, me.username());
+                (me.id.0 as i64, Some(me.username().to_string()))
+            }
+            Err(e) => {
+                warn!("Failed to get bot info: {e}");
+                (0, None)
+            }
+        };
+
+        // Create chatbot if enabled
+        let (chatbot, session_start) = if !config.allowed_groups.is_empty() {
+            let primary_chat_id = config.primary_chat_id;
+            let telegram: Arc<dyn TelegramApi> = Arc::new(TelegramClient::new(bot.clone()));
+
+            // Fetch owner info from Telegram
+            let owner = if let Some(owner_id) = config.owner_ids.first() {
+                let username = telegram.get_chat_username(owner_id.0 as i64).await.ok().flatten();
+                let owner = TrustedUser::with_username(owner_id.0 as i64, username);
+                info!("Owner: {}", owner.display());
+                Some(owner)
+            } else {
+                None
+            };
+
+            // Fetch trusted DM users' usernames from Telegram and update the HashMap
+            // Collect IDs first to avoid holding lock across await
+            let trusted_ids: Vec<i64> = config.trusted_dm_users
+                .read()
+                .expect("trusted_dm_users lock poisoned")
+                .keys()
+                .copied()
+                .collect();
+
+            for user_id in trusted_ids {
+                let username = telegram.get_chat_username(user_id).await.ok().flatten();
+                // Update the HashMap with the fetched username
+                {
+                    let mut users = config.trusted_dm_users.write().expect("trusted_dm_users lock poisoned");
+                    users.insert(user_id, username.clone());
+                }
+                let user_display = match &username {
+                    Some(u) => format!("@{} ({})", u, user_id),
+                    None => user_id.to_string(),
+                };
+                info!("Trusted DM user: {}", user_display);
+            }
+
+            // The saved personality survives restarts; otherwise the schedule decides
+            let local_time = chrono::Utc::now().with_timezone(&config.scan_timezone).time();
+            let active_personality = config.personalities.initial(personality::load_active(&config.data_dir), local_time);
+
+            let chatbot_config = ChatbotConfig {
+                primary_chat_id,
+                allowed_groups: config.allowed_groups.iter().map(|g| g.0).collect(),
+                bot_user_id,
+                bot_username: bot_username.clone(),
+                owner,
+                trusted_dm_users: config.trusted_dm_users.clone(),
+                config_store: Some(Arc::new(ConfigStore::new(config.config_path.clone(), config::validate_json))),
+                chat_migrations: config.chat_migrations.clone(),
+                debounce_ms_dm: config.debounce_ms_dm,
+                debounce_ms_group: config.debounce_ms_group,
+                debounce_max_ms_group: config.debounce_max_ms_group,
+                data_dir: Some(config.data_dir.clone()),
+                gemini_api_key: if config.gemini_api_key.is_empty() { None } else { Some(config.gemini_api_key.clone()) },
+                tts_endpoint: config.tts_endpoint.clone(),
+                voice_include_transcript: config.voice_include_transcript,
+                compact_prompt: config.compact_prompt,
+                tts_voices: Default::default(),
+                personalities: config.personalities.clone(),
+                active_personality: Arc::new(std::sync::RwLock::new(active_personality)),
+                scan_interval_minutes: config.scan_interval_minutes,
+                scan_times: config.scan_times.clone(),
+                scan_timezone: config.scan_timezone,
+                quiet_hours: config.quiet_hours.clone(),
+                slow_mode: Default::default(),
+                ack_reactions: config.ack_reactions.clone(),
+                peer_bots: config.peer_bots.clone(),
+                wiki_default_lang: config.wiki_default_lang.clone(),
+                translate_max_chars: config.translate_max_chars,
+                compaction_summary_tokens: config.compaction_summary_tokens,
+                compaction_restore_tokens: config.compaction_restore_tokens,
+                compaction_cooldown: config.compaction_cooldown,
+                gemini_text_model: config.gemini_text_model.clone(),
+                response_language: config.response_language.clone(),
+                output_guard: config.output_guard.clone(),
+                max_pending_messages: config.max_pending_messages,
+                maintenance: Arc::new(Maintenance::load(&config.data_dir)),
+                maintenance_reply: config.maintenance_reply.clone(),
+                ocr_screenshots: config.ocr_screenshots,
+                ocr_visual_keywords: config.ocr_visual_keywords.clone(),
+                max_image_bytes: config.max_image_bytes,
+                error_digest_time: config.error_digest_time,
+                user_digest_time: config.user_digest_time,
+                user_digest_top: config.user_digest_top,
+                transparency_time: config.transparency_time,
+                transparency_chat_id: config.transparency_chat_id,
+                transparency_anonymize: config.transparency_anonymize,
+                tool_timeouts: config.tool_timeouts.clone(),
+                tool_result_limits: config.tool_result_limits.clone(),
+                backup_cron: config.backup_cron.clone(),
+                backup_keep: config.backup_keep,
+                protect_admins: config.protect_admins,
+                atomic_batches: config.atomic_batches,
+            };
+
+            // Fetch available TTS voices if endpoint configured
+            if let Some(ref endpoint) = config.tts_endpoint {
+                use crate::chatbot::tts::TtsClient;
+                let tts = TtsClient::new(endpoint.clone());
+                let voices = voices::discover(&tts, &config.data_dir, voices::STARTUP_BACKOFF).await;
+                if !voices.list.is_empty() {
+                    info!("TTS voices available: {}", voices.list.join(", "));
+                }
+                *chatbot_config.tts_voices.write().expect("tts_voices lock poisoned") = voices;
+            }
+
+            // Start Claude Code with system prompt and session persistence
+            let active_personality = chatbot_config.active_personality.read().expect("active_personality lock poisoned").clone();
+            if let Some(ref name) = active_personality {
+                info!("🎭 Personality: {}", name);
+            }
+            let identity = chatbot_config.personalities.fragment(active_personality.as_deref());
+            let prompt = system_prompt(&chatbot_config, identity);
+            let tokens = prompt::estimate_tokens(&prompt);
+            if chatbot_config.compact_prompt && tokens > prompt::COMPACT_TARGET_TOKENS {
+                warn!("📏 Compact system prompt: ~{} tokens, over the {} target", tokens, prompt::COMPACT_TARGET_TOKENS);
+            } else {
+                info!("📏 System prompt: ~{} tokens{}", tokens, if chatbot_config.compact_prompt { " (compact)" } else { "" });
+            }
+            let session_file = Some(config.data_dir.join("session_id"));
+            let transcript = SessionTranscript::new(
+                config.data_dir.join("transcripts"),
+                config.transcript_full,
+                config.transcript_max_bytes,
+                vec![config.telegram_bot_token.clone(), config.openrouter_api_key.clone(), config.gemini_api_key.clone()],
+            );
+            let mut claude_code = match ClaudeCode::start(config.claude_program.clone(), prompt, session_file, transcript) {
+                Ok(cc) => cc,
+                Err(e) => {
+                    panic!("Failed to start Claude Code: {}", e);
+                }
+            };
+
+            let session_start = claude_code.take_start_status();
+
+            let mut engine = ChatbotEngine::new(chatbot_config, telegram, claude_code);
+            engine.start_debouncer();
+            engine.refresh_stale_prompt().await;
+            engine.forget_trusted_unknown_dms().await;
+
+            info!("Chatbot enabled (primary chat: {})", primary_chat_id);
+            (Some(engine), session_start)
+        } else {
+            info!("Chatbot disabled (no allowed_groups)");
+            (None, None)
+        };
+
+        // Configure Whisper if model path is configured (loaded on first voice message)
+        let whisper = if let Some(ref model_path) = config.whisper_model_path {
+            info!("Whisper model {:?} will load on first use", model_path);
+            Some(Whisper::new(model_path))
+        } else {
+            info!("No Whisper model configured - voice transcription disabled");
+            None
+        };
+
+        let update_offset = backfill::UpdateOffset::load(&config.data_dir.join("update_offset"));
+        let spam_wave = Mutex::new(WaveDetector::new(config.raid_threshold, config.raid_duration));
+        let media = MediaLimiter::new(config.media_concurrency, config.media_timeout);
+
+        Self {
+            config,
+            claude,
+            strikes: Mutex::new(HashMap::new()),
+            chatbot,
+            dm_denied: Mutex::new(DmCounts::default()),
+            whisper,
+            media,
+            deliveries: DeliveryQueue::default(),
+            update_offset,
+            spam_wave,
+            links: LinkExpander::new(HttpResolver::new()),
+            member_events: Mutex::new(MemberEventDedup::new()),
+            session_start: Mutex::new(session_start),
+        }
+    }
+
+    async fn add_strike(&self, sender_id: i64) -> u8 {
+        let mut strikes = self.strikes.lock().await;
+        let count = strikes.entry(sender_id).or_insert(0);
+        *count += 1;
+        *count
+    }
+}
+
+/// The dispatcher's handlers. Updates already handled (e.g. during
+/// backfill) are dropped.
+pub fn handler() -> UpdateHandler<teloxide::RequestError> {
+    dptree::entry()
+        .branch(
+            dptree::filter(|upd: Update, state: Arc<BotState>| !state.update_offset.record(upd.id.0))
+                .endpoint(|| async { ResponseResult::Ok(()) }),
+        )
+        .branch(Update::filter_message().endpoint(handle_new_message))
+        .branch(Update::filter_edited_message().endpoint(handle_edited_message))
+        .branch(Update::filter_channel_post().endpoint(handle_channel_post))
+        .branch(Update::filter_chat_member().endpoint(handle_chat_member))
+        .branch(Update::filter_my_chat_member().endpoint(handle_my_chat_member))
+        .branch(Update::filter_message_reaction_updated().endpoint(handle_message_reaction))
+        .branch(Update::filter_inline_query().endpoint(handle_inline_query))
+}
+
+/// Tell the owner about this restart per `startup_notify`, once the Claude
+/// session has started. In daily_summary mode this keeps running to send the
+/// summary every morning.
+pub async fn notify_startup(state: Arc<BotState>, unclean_shutdown: bool) {
+    let Some(ref chatbot) = state.chatbot else {
+        return;
+    };
+
+    let mut issues = Vec::new();
+    if unclean_shutdown {
+        issues.push("the previous run didn't shut down cleanly (crash or kill)".to_string());
+    }
+    let session_start = state.session_start.lock().await.take();
+    if let Some(session_start) = session_start {
+        match session_start.await {
+            Ok(SessionStart::Fresh | SessionStart::Resumed) => {}
+            Ok(SessionStart::ResumeFailed(e)) => {
+                issues.push(format!("the Claude session failed to resume ({}), so it started fresh", e));
+            }
+            Ok(SessionStart::Failed(e)) => issues.push(format!("Claude Code failed to start: {}", e)),
+            Err(_) => issues.push("Claude Code exited during startup".to_string()),
+        }
+    }
+    if let Some(path) = &state.config.whisper_model_path
+        && !path.exists()
+    {
+        issues.push(format!("the Whisper model {} is missing", path.display()));
+    }
+
+    let mode = state.config.startup_notify;
+    if let Some(message) = startup::immediate_message(mode, &issues) {
+        chatbot.notify_owner(&message).await;
+    }
+    if mode != StartupNotify::DailySummary {
+        return;
+    }
+
+    let path = state.config.data_dir.join("restart_summary.json");
+    let mut store = SummaryStore::load(&path);
+    store.record(chrono::Local::now().naive_local(), issues);
+    loop {
+        let now = chrono::Local::now().naive_local();
+        if let Some(events) = store.take_due(now) {
+            chatbot.notify_owner(&startup::summary_message(&events)).await;
+        }
+        if let Err(e) = store.save(&path) {
+            warn!("Failed to save restart summary: {}", e);
+        }
+        let wait = (startup::next_summary_check(now) - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Remember the chat's new auto-delete timer if `msg` is the service message
+/// announcing it. Returns true if it was.
+async fn handle_auto_delete_timer(msg: &Message, chatbot: &ChatbotEngine) -> bool {
+    let Some(changed) = msg.message_auto_delete_timer_changed() else {
+        return false;
+    };
+    chatbot.set_auto_delete_timer(msg.chat.id.0, changed.message_auto_delete_time.seconds()).await;
+    true
+}
+
+/// The old and new chat IDs if `msg` announces a group becoming a
+/// supergroup. Telegram sends one in each chat.
+fn chat_migration(msg: &Message) -> Option<(ChatId, ChatId)> {
+    msg.migrate_to_chat_id().map(|&new| (msg.chat.id, new))
+        .or_else(|| msg.migrate_from_chat_id().map(|&old| (old, msg.chat.id)))
+}
+
+async fn handle_new_message(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    process_new_message(bot, msg, state, false).await
+}
+
+/// Prefilter and store a new message. `backfilled` marks messages fetched at
+/// startup; stale ones are stored without asking Claude to respond.
+pub(crate) async fn process_new_message(bot: Bot, msg: Message, state: Arc<BotState>, backfilled: bool) -> ResponseResult<()> {
+    let is_group = matches!(msg.chat.kind, ChatKind::Public(_));
+    let is_private = matches!(msg.chat.kind, ChatKind::Private(_));
+
+    if let Some((old, new)) = chat_migration(&msg) {
+        if state.config.allows_group(old)
+            && let Some(ref chatbot) = state.chatbot
+        {
+            chatbot.migrate_chat(old.0, new.0).await;
+        }
+        return Ok(());
+    }
+
+    // Anonymous admins and channels have a sender chat instead of a user
+    let Some(sender) = senders::sender(&msg) else {
+        return Ok(());
+    };
+    let username = sender.name();
+    let sender_id = sender.user_id();
+    let owner = matches!(sender, Sender::User(user) if state.config.is_owner(user.id));
+    let caller = match sender {
+        _ if owner => Permission::Owner,
+        Sender::User(user) if state.config.can_dm(user.id) => Permission::Trusted,
+        _ => Permission::Anyone,
+    };
+
+    // Handle DMs
+    if is_private {
+        let Sender::User(user) = sender else {
+            return Ok(());
+        };
+        if let Some(ref chatbot) = state.chatbot
+            && handle_command(&bot, &msg, &state, chatbot, caller).await
+        {
+            return Ok(());
+        }
+        if state.config.can_dm(user.id) {
+            info!("📨 DM from {} ({})", username, user.id);
+            if let Some(ref chatbot) = state.chatbot {
+                if handle_auto_delete_timer(&msg, chatbot).await {
+                    return Ok(());
+                }
+                deliver_with_media(&bot, msg, &state, backfilled);
+            }
+            return Ok(());
+        } else {
+            handle_unknown_dm(&bot, &msg, user, &state).await;
+            return Ok(());
+        }
+    }
+
+    if !is_group {
+        return Ok(());
+    }
+
+    // Check allowed group
+    if !state.config.allows_group(msg.chat.id) {
+        return Ok(());
+    }
+
+    if let Some(ref chatbot) = state.chatbot
+        && handle_auto_delete_timer(&msg, chatbot).await
+    {
+        return Ok(());
+    }
+
+    // Join/leave service messages: record the members, never pass them on
+    let member_events = service_member_events(&msg);
+    if !member_events.is_empty() {
+        if let Some(ref chatbot) = state.chatbot {
+            for (member, kind) in member_events {
+                let invite = service_invite_source(&msg, member);
+                record_member_event(&state, chatbot, msg.chat.id.0, member, kind, invite).await;
+            }
+        }
+        return Ok(());
+    }
+
+    // Bots that aren't peers (captcha, RSS) are stored but never answered,
+    // which also rules out bot-to-bot loops
+    if !state.config.process_bot_messages && sender.is_foreign_bot(&state.config.peer_bots) {
+        info!("🤖 Storing message from bot {} without response", username);
+        if let Some(ref chatbot) = state.chatbot {
+            chatbot.archive_message(telegram_to_chat_message_with_media(&msg, chatbot, None, None, vec![])).await;
+        }
+        return Ok(());
+    }
+
+    // Owner `/sys` commands are never shown to Claude as a group message
+    if owner
+        && let Some(ref chatbot) = state.chatbot
+        && handle_sys_command(&bot, &msg, chatbot).await
+    {
+        return Ok(());
+    }
+    if let Some(ref chatbot) = state.chatbot
+        && handle_command(&bot, &msg, &state, chatbot, caller).await
+    {
+        return Ok(());
+    }
+
+    // Slow mode set with set_slow_mode: the Bot API can't set it, so early
+    // posts are deleted here. Backfilled messages are too old to judge.
+    if !backfilled
+        && !owner
+        && let (Sender::User(user), Some(chatbot)) = (sender, &state.chatbot)
+        && let Some(wait) = chatbot.slow_mode_wait(msg.chat.id.0, user.id.0 as i64).await
+    {
+        info!("🐢 Slow mode: {} posted {}s too early in {}", username, wait.as_secs() + 1, msg.chat.id);
+        if state.config.dry_run {
+            info!("[DRY RUN] Would delete message {}", msg.id);
+        } else if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+            warn!("Failed to delete slow mode message: {e}");
+        }
+        metrics::inc(&metrics::MESSAGES, &[("outcome", "slow_mode")]);
+        return Ok(());
+    }
+
+    // Get text (or caption for images/voice/documents)
+    let text = msg.text().or_else(|| msg.caption());
+    let has_image = msg.photo().is_some();
+    let has_voice = msg.voice().is_some();
+    let has_document = msg.document().is_some_and(|d| {
+        d.file_name.as_deref().is_some_and(|f| f.to_lowercase().ends_with(".docx"))
+    });
+    let has_dice = msg.dice().is_some();
+    let has_location = msg.location().is_some() || msg.venue().is_some();
+
+    // Skip if no text, image, voice, document, dice or location
+    if text.is_none() && !has_image && !has_voice && !has_document && !has_dice && !has_location {
+        return Ok(());
+    }
+
+    // SPAM FILTER FIRST - spam messages must NEVER reach the chatbot
+    // Owners, anonymous admins and trusted channels bypass spam filter
+    let bypasses_filter = sender.bypasses_spam_filter(&state.config.owner_ids, &state.config.trusted_channels);
+    let image_hash = match (&state.chatbot, msg.photo()) {
+        (Some(_), Some(sizes)) if !bypasses_filter => spam_image_hash(&bot, &state.media, sizes).await,
+        _ => None,
+    };
+    let known_spam_image = match (&state.chatbot, &image_hash) {
+        (Some(chatbot), Some(hash)) => chatbot.is_spam_image(hash.clone()).await,
+        _ => false,
+    };
+    let is_spam = if known_spam_image {
+        info!("🖼️ Message from {username} ({}) reposts a spam image → ObviousSpam", sender_id);
+        true
+    } else if let Some(text) = text {
+        if bypasses_filter {
+            info!("Bypass spam filter for {username} ({})", sender_id);
+            false
+        } else {
+            // Track near-duplicate posts from new accounts to detect spam waves
+            let is_new_account = match (sender, &state.chatbot) {
+                (Sender::User(user), Some(chatbot)) => chatbot.is_new_member(user.id.0 as i64, NEW_ACCOUNT_AGE).await,
+                _ => false,
+            };
+            let (alert, in_raid) = {
+                let mut wave = state.spam_wave.lock().await;
+                let alert = wave.observe(sender_id.unsigned_abs(), text, is_new_account, msg.date);
+                (alert, wave.in_raid(msg.date))
+            };
+            if let Some(alert) = alert {
+                let message = alert.message(state.config.raid_duration.num_minutes());
+                match state.chatbot {
+                    Some(ref chatbot) => chatbot.notify_owner(&message).await,
+                    None => warn!("{}", message),
+                }
+            }
+
+            let mut prefilter_result = prefilter(text, &state.config);
+            // Shortened links can hide t.me links from the spam patterns
+            if prefilter_result != PrefilterResult::ObviousSpam
+                && state.links.expands_to_spam(text, &state.config.links, &state.config.spam_patterns).await
+            {
+                prefilter_result = PrefilterResult::ObviousSpam;
+            }
+            prefilter_result = escalate_via_bot(prefilter_result, msg.via_bot.is_some(), &state.config);
+            let text_preview: String = text.chars().take(100).collect();
+            info!("Message from {username} ({}): \"{text_preview}\" → {:?}", sender_id, prefilter_result);
+
+            match prefilter_result {
+                PrefilterResult::ObviousSpam => true,
+                PrefilterResult::ObviousSafe => false,
+                PrefilterResult::Ambiguous if in_raid && is_new_account => {
+                    info!("Raid mode: ambiguous message from new account treated as spam");
+                    true
+                }
+                PrefilterResult::Ambiguous => {
+                    match classify(text, &state.claude).await {
+                        Ok(Classification::Spam) => {
+                            info!("Haiku: spam");
+                            true
+                        }
+                        Ok(Classification::NotSpam) => {
+                            info!("Haiku: not spam");
+                            false
+                        }
+                        Err(e) => {
+                            warn!("Classification error: {e}");
+                            false
+                        }
+                    }
+                }
+            }
+        }
+    } else {
+        false // No text = not spam (image/voice only)
+    };
+
+    // Handle spam: delete, strike, ban - and DO NOT pass to chatbot
+    if is_spam {
+        let dry = state.config.dry_run;
+        metrics::inc(&metrics::MESSAGES, &[("outcome", "spam")]);
+        if let (Some(chatbot), Some(hash)) = (&state.chatbot, image_hash) {
+            chatbot.record_spam_image(hash, msg.chat.id.0, spam_images::TTL).await;
+        }
+
+        if dry {
+            info!("[DRY RUN] Would delete message {}", msg.id);
+        } else {
+            match bot.delete_message(msg.chat.id, msg.id).await {
+                Ok(_) => {
+                    metrics::inc(&metrics::SPAM_DELETED, &[]);
+                    if let Some(chatbot) = &state.chatbot {
+                        chatbot.record_spam_deletion(msg.chat.id.0).await;
+                    }
+                }
+                Err(e) => warn!("Failed to delete: {e}"),
+            }
+        }
+
+        // Telegram bans on bots behave oddly, so bots never collect strikes
+        if sender.is_bot() {
+            info!("Not striking bot {username}");
+            return Ok(());
+        }
+        let strikes = state.add_strike(sender_id).await;
+        info!("{username} has {strikes} strike(s)");
+
+        if strikes >= state.config.max_strikes {
+            if dry {
+                info!("[DRY RUN] Would ban {username}");
+            } else {
+                info!("Banning {username}");
+                let banned = match sender {
+                    Sender::User(user) => bot.ban_chat_member(msg.chat.id, user.id).await.map(|_| ()),
+                    Sender::Channel { id, .. } => bot.ban_chat_sender_chat(msg.chat.id, ChatId(id)).await.map(|_| ()),
+                    Sender::AnonymousAdmin { .. } => Ok(()),
+                };
+                if let Err(e) = banned {
+                    warn!("Failed to ban: {e}");
+                }
+            }
+        }
+
+        // CRITICAL: Do not pass spam to chatbot
+        return Ok(());
+    }
+
+    // Only non-spam messages reach the chatbot
+    if state.chatbot.is_some() {
+        deliver_with_media(&bot, msg, &state, backfilled);
+        metrics::inc(&metrics::MESSAGES, &[("outcome", "delivered")]);
+    }
+
+    Ok(())
+}
+
+async fn handle_channel_post(msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    process_channel_post(msg, state, false).await
+}
+
+pub(crate) async fn process_channel_post(msg: Message, state: Arc<BotState>, backfilled: bool) -> ResponseResult<()> {
+    // Only handle posts in allowed channels/groups
+    if !state.config.allows_group(msg.chat.id) {
+        return Ok(());
+    }
+
+    let text = msg.text().or_else(|| msg.caption());
+    let has_image = msg.photo().is_some();
+
+    if text.is_none() && !has_image {
+        return Ok(());
+    }
+
+    // Channel posts have sender_chat instead of from
+    let channel_title = msg.sender_chat.as_ref()
+        .and_then(|c| c.title())
+        .unwrap_or("channel");
+
+    info!("📢 Channel post in {} ({}): {:?}",
+        channel_title, msg.chat.id,
+        text.map(|t| t.chars().take(100).collect::<String>()));
+
+    if let Some(ref chatbot) = state.chatbot {
+        let (image, image_note) = download_photo(chatbot, &state.media, &msg).await;
+
+        let (mentions_bot, mentioned_user_ids) = entity_mentions(&msg, chatbot);
+        let mut chat_msg = ChatMessage {
+            message_id: msg.id.0 as i64,
+            chat_id: chatbot.current_chat_id(msg.chat.id.0),
+            user_id: 0,
+            username: channel_title.to_string(),
+            timestamp: msg.date.format("%Y-%m-%d %H:%M").to_string(),
+            text: text.unwrap_or("").to_string(),
+            reply_to: None,
+            image,
+            voice_transcription: None,
+            ocr_text: None,
+            documents: vec![],
+            backfilled: false,
+            from_bot: false,
+            forwarded: msg.forward_origin().is_some(),
+            via_bot: inline_bot(&msg),
+            mentions_bot,
+            mentioned_user_ids,
+            lang: None,
+            spoken: false,
+            location: None,
+        };
+        append_image_note(&mut chat_msg, image_note);
+        deliver_to_chatbot(chatbot, chat_msg, &msg, backfilled, &state).await;
+    }
+
+    Ok(())
+}
+
+/// Fetch the message's media, then hand it to the chatbot. The message is
+/// assembled in its own task, so downloads don't hold up the dispatcher,
+/// and delivered through `deliveries`, after the chat's earlier messages.
+fn deliver_with_media(bot: &Bot, msg: Message, state: &Arc<BotState>, backfilled: bool) {
+    let chat_id = msg.chat.id.0;
+    let (bot, assembly_state) = (bot.clone(), state.clone());
+    let assembled = tokio::spawn(async move {
+        let chatbot = assembly_state.chatbot.as_ref()?;
+        let ((image, image_note), voice_transcription, documents) = tokio::join!(
+            download_photo(chatbot, &assembly_state.media, &msg),
+            limited_voice(&bot, &assembly_state, &msg),
+            limited_documents(&bot, &assembly_state.media, &msg),
+        );
+        let mut chat_msg = telegram_to_chat_message_with_media(&msg, chatbot, image, voice_transcription, documents);
+        append_image_note(&mut chat_msg, image_note);
+        Some((chat_msg, msg))
+    });
+    let delivery_state = state.clone();
+    state.deliveries.push(chat_id, async move {
+        match assembled.await {
+            Ok(Some((chat_msg, msg))) => {
+                if let Some(ref chatbot) = delivery_state.chatbot {
+                    deliver_to_chatbot(chatbot, chat_msg, &msg, backfilled, &delivery_state).await;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to assemble message in chat {}: {}", chat_id, e),
+        }
+    });
+}
+
+/// Hand a message to the chatbot. Backfilled messages older than
+/// `backfill_max_age` are only stored, so Claude doesn't answer stale mentions.
+async fn deliver_to_chatbot(
+    chatbot: &ChatbotEngine,
+    mut chat_msg: ChatMessage,
+    msg: &Message,
+    backfilled: bool,
+    state: &BotState,
+) {
+    chat_msg.backfilled = backfilled;
+    if backfilled && backfill::is_stale(msg.date, chrono::Utc::now(), state.config.backfill_max_age) {
+        info!("⏪ Storing stale backfilled message {} without response", chat_msg.message_id);
+        chatbot.store_message(chat_msg).await;
+    } else {
+        chatbot.handle_message(chat_msg).await;
+    }
+}
+
+/// Handle an owner `/sys` or `/sysquiet` command in a group: the command
+/// message is deleted so members don't see it. Returns false if the
+/// message isn't one. In DMs these go through `chatbot::commands`.
+async fn handle_sys_command(bot: &Bot, msg: &Message, chatbot: &ChatbotEngine) -> bool {
+    let Some(parsed) = msg.text().and_then(commands::parse_sys_command) else {
+        return false;
+    };
+    if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+        warn!("Failed to delete /sys command: {}", e);
+    }
+
+    match parsed {
+        Ok(command) => {
+            info!("📢 Owner system message{}: {}", if command.quiet { " (quiet)" } else { "" }, command.text);
+            chatbot.handle_message(ChatMessage::system(command.message_text())).await;
+        }
+        Err(usage) => warn!("Ignoring malformed owner command in group: {}", usage),
+    }
+    true
+}
+
+/// Handle a command from `chatbot::commands`, in a DM or a group. Returns
+/// false if the message isn't one the sender may run there.
+async fn handle_command(bot: &Bot, msg: &Message, state: &BotState, chatbot: &ChatbotEngine, caller: Permission) -> bool {
+    let (Some(text), Some(user)) = (msg.text(), msg.from.as_ref()) else {
+        return false;
+    };
+    let chat = CommandChat {
+        chat_id: msg.chat.id.0,
+        is_group: matches!(msg.chat.kind, ChatKind::Public(_)),
+        primary_chat_id: state.config.primary_chat(),
+    };
+    let (_, bot_username) = chatbot.bot_identity();
+    let Some((command, args)) = commands::parse(text, caller, chat.is_group, bot_username) else {
+        return false;
+    };
+
+    info!("⌨️ /{} from {} in chat {}", command.name, user.id, chat.chat_id);
+    let transcripts = state.config.data_dir.join("transcripts");
+    let reload_guard = || reload_output_guard(&state.config);
+    let ctx = CommandContext {
+        engine: chatbot,
+        whisper: state.whisper.as_ref(),
+        caller,
+        user_id: user.id.0 as i64,
+        chat,
+        transcripts: &transcripts,
+        reload_guard: &reload_guard,
+    };
+    let reply = match args {
+        Ok(args) => (command.handler)(&ctx, args).await,
+        Err(usage) => Some(usage),
+    };
+    if let Some(reply) = reply
+        && let Err(e) = bot.send_message(msg.chat.id, reply).reply_parameters(ReplyParameters::new(msg.id)).await
+    {
+        warn!("Failed to reply to /{}: {}", command.name, e);
+    }
+    true
+}
+
+/// Answer an inline query from the owner or a trusted user (see `inline`).
+/// Anyone else gets no results.
+async fn handle_inline_query(bot: Bot, query: InlineQuery, state: Arc<BotState>) -> ResponseResult<()> {
+    let results = match &state.chatbot {
+        Some(chatbot) if state.config.can_dm(query.from.id) => {
+            info!("🔎 Inline query from {}: {:?}", query.from.id, query.query);
+            match tokio::time::timeout(inline::ANSWER_TIMEOUT, inline_result(chatbot, &state.config, &query)).await {
+                Ok(result) => vec![result],
+                Err(_) => {
+                    warn!("Inline query {:?} timed out", query.query);
+                    vec![inline::error_result("Timed out, try again.")]
+                }
+            }
+        }
+        _ => Vec::new(),
+    };
+
+    if let Err(e) = bot.answer_inline_query(query.id, results).is_personal(true).cache_time(0).await {
+        warn!("Failed to answer inline query: {}", e);
+    }
+    Ok(())
+}
+
+async fn inline_result(chatbot: &ChatbotEngine, config: &Config, query: &InlineQuery) -> InlineQueryResult {
+    match inline::parse(&query.query) {
+        inline::Intent::Quote(username) => match chatbot.last_message_by_username(username.clone()).await {
+            Ok(message) => inline::quote_result(&username, message.as_ref()),
+            Err(e) => {
+                warn!("Inline quote of {} failed: {}", username, e);
+                inline::error_result(&e)
+            }
+        },
+        inline::Intent::Stats => {
+            let user_id = query.from.id.0 as i64;
+            let call = ToolCall::ChatStats { chat_id: config.primary_chat(), days: None };
+            match chatbot.run_tool(call, user_id, user_id).await {
+                Ok(Some(output)) => match serde_json::from_str(&output) {
+                    Ok(value) => inline::stats_result(&lookups::render_stats(&value)),
+                    Err(_) => inline::stats_result(&output),
+                },
+                Ok(None) => inline::error_result("No stats."),
+                Err(e) => {
+                    warn!("Inline stats failed: {}", e);
+                    inline::error_result(&e)
+                }
+            }
+        }
+        inline::Intent::Unsupported => inline::unsupported_result(&query.query),
+    }
+}
+
+/// Show the slash commands in the Telegram menu, only to the users who can
+/// run them: in their DMs and, per allowed group, for them alone.
+pub async fn register_slash_commands(bot: &Bot, config: &Config) {
+    let mut users: Vec<UserId> = config.owner_ids.clone();
+    users.extend(
+        config.trusted_dm_users.read().expect("trusted_dm_users lock poisoned")
+            .keys()
+            .map(|id| UserId(*id as u64)),
+    );
+
+    for user_id in users {
+        let mut scopes = vec![(BotCommandScope::Chat { chat_id: Recipient::Id(ChatId(user_id.0 as i64)) }, false)];
+        scopes.extend(config.allowed_groups.iter().map(|group| {
+            (BotCommandScope::ChatMember { chat_id: Recipient::Id(*group), user_id }, true)
+        }));
+        for (scope, in_group) in scopes {
+            if let Err(e) = bot.set_my_commands(commands::bot_commands(in_group)).scope(scope).await {
+                warn!("Failed to register slash commands for {}: {}", user_id, e);
+            }
+        }
+    }
+}
+
+/// Re-read `forbidden_output_patterns` from the config file and swap them in.
+/// The old patterns stay active if the file doesn't load.
+fn reload_output_guard(config: &Config) -> String {
+    match Config::load(&config.config_path) {
+        Ok(fresh) => {
+            let guard = fresh.output_guard.read().expect("output_guard lock poisoned").clone();
+            *config.output_guard.write().expect("output_guard lock poisoned") = guard;
+            info!("Reloaded forbidden output patterns");
+            "Output guard reloaded.".to_string()
+        }
+        Err(e) => {
+            warn!("Failed to reload output guard: {}", e);
+            format!("Reload failed, keeping current patterns: {}", e)
+        }
+    }
+}
+
+/// Hash of the photo's thumbnail, to recognize images reposted by spam
+/// raids (see `spam_images`). None if it can't be downloaded.
+async fn spam_image_hash(bot: &Bot, media: &MediaLimiter, sizes: &[PhotoSize]) -> Option<String> {
+    use teloxide::net::Download;
+
+    let thumbnail = spam_images::thumbnail(sizes)?;
+    let download = async {
+        let file = bot.get_file(thumbnail.file.id.clone()).await.map_err(|e| e.to_string())?;
+        let mut data = Vec::new();
+        bot.download_file(&file.path, &mut data).await.map_err(|e| e.to_string())?;
+        Ok::<_, String>(data)
+    };
+    match media.run(download).await.and_then(|r| r) {
+        Ok(data) => Some(spam_images::hash(&data)),
+        Err(e) => {
+            warn!("Failed to fetch photo thumbnail for the spam image check: {}", e);
+            None
+        }
+    }
+}
+
+/// Download the message's photo, if any. When it's too large or the download
+/// fails, returns a placeholder for the message text instead.
+async fn download_photo(chatbot: &ChatbotEngine, media: &MediaLimiter, msg: &Message) -> (Option<(Vec<u8>, String)>, Option<String>) {
+    let Some(sizes) = msg.photo() else {
+        return (None, None);
+    };
+    match media.run(chatbot.download_photo(sizes)).await.and_then(|r| r) {
+        Ok(image) => (Some(image), None),
+        Err(e) => {
+            warn!("Image in msg {} not attached: {}", msg.id, e);
+            (None, Some(image_limits::image_placeholder(&e)))
+        }
+    }
+}
+
+/// Tell Claude about an image it didn't get.
+fn append_image_note(chat_msg: &mut ChatMessage, note: Option<String>) {
+    if let Some(note) = note {
+        if !chat_msg.text.is_empty() {
+            chat_msg.text.push('\n');
+        }
+        chat_msg.text.push_str(&note);
+    }
+}
+
+fn telegram_to_chat_message_with_media(
+    msg: &Message,
+    chatbot: &ChatbotEngine,
+    image: Option<(Vec<u8>, String)>,
+    voice_transcription: Option<String>,
+    documents: Vec<DocumentContent>,
+) -> ChatMessage {
+    let sender = senders::sender(msg);
+    let user_id = sender.map(|s| s.user_id()).unwrap_or(0);
+    let username = names::sanitize(sender.map(|s| s.name()).unwrap_or("unknown"));
+
+    let timestamp = msg.date.format("%Y-%m-%d %H:%M").to_string();
+    // Use text, or caption (for images/voice), or the dice roll, or empty
+    let text = msg.text()
+        .or_else(|| msg.caption())
+        .map(str::to_string)
+        .or_else(|| msg.dice().map(dice_text))
+        .unwrap_or_default();
+
+    let (mentions_bot, mentioned_user_ids) = entity_mentions(msg, chatbot);
+
+    let reply_to = msg.reply_to_message().map(|reply| {
+        let reply_username = names::sanitize(senders::sender(reply).map(|s| s.name()).unwrap_or("unknown"));
+
+        ReplyTo {
+            message_id: reply.id.0 as i64,
+            username: reply_username,
+            text: reply.text().or_else(|| reply.caption()).unwrap_or("").to_string(),
+            // Set on ingest, where the history is at hand
+            unknown_origin: false,
+        }
+    });
+
+    ChatMessage {
+        message_id: msg.id.0 as i64,
+        chat_id: chatbot.current_chat_id(msg.chat.id.0),
+        user_id,
+        username,
+        timestamp,
+        text,
+        reply_to,
+        image,
+        voice_transcription,
+        ocr_text: None,
+        documents,
+        backfilled: false,
+        from_bot: sender.is_some_and(|s| s.is_bot()),
+        forwarded: msg.forward_origin().is_some(),
+        via_bot: inline_bot(msg),
+        mentions_bot,
+        mentioned_user_ids,
+        lang: None,
+        spoken: false,
+        location: message_location(msg),
+    }
+}
+
+/// Username (or ID) of the inline bot a message was sent through.
+fn inline_bot(msg: &Message) -> Option<String> {
+    msg.via_bot.as_ref().map(|bot| bot.username.clone().unwrap_or_else(|| bot.id.to_string()))
+}
+
+/// Bot and user mentions from the text or caption entities.
+fn entity_mentions(msg: &Message, chatbot: &ChatbotEngine) -> (bool, Vec<i64>) {
+    let (text, entities) = match (msg.text(), msg.entities()) {
+        (Some(text), Some(entities)) => (text, entities),
+        _ => match (msg.caption(), msg.caption_entities()) {
+            (Some(text), Some(entities)) => (text, entities),
+            _ => return (false, vec![]),
+        },
+    };
+    let (bot_user_id, bot_username) = chatbot.bot_identity();
+    extract_mentions(text, entities, bot_user_id, bot_username)
+}
+
+/// Download and extract text from document attachments (.docx files).
+/// `transcribe_voice` through the media limiter.
+async fn limited_voice(bot: &Bot, state: &BotState, msg: &Message) -> Option<String> {
+    msg.voice()?;
+    match state.media.run(transcribe_voice(bot, state, msg)).await {
+        Ok(transcription) => transcription,
+        Err(e) => {
+            warn!("Voice in msg {} not transcribed: {}", msg.id, e);
+            Some(format!("[Voice message - download failed: {}]", e))
+        }
+    }
+}
+
+/// `extract_documents` through the media limiter.
+async fn limited_documents(bot: &Bot, media: &MediaLimiter, msg: &Message) -> Vec<DocumentContent> {
+    let Some(doc) = msg.document() else {
+        return vec![];
+    };
+    match media.run(extract_documents(bot, msg)).await {
+        Ok(documents) => documents,
+        Err(e) => {
+            warn!("Document in msg {} not extracted: {}", msg.id, e);
+            let filename = doc.file_name.as_deref().unwrap_or("document").to_string();
+            vec![DocumentContent { filename, text: format!("[Document download failed: {}]", e) }]
+        }
+    }
+}
+
+async fn extract_documents(bot: &Bot, msg: &Message) -> Vec<DocumentContent> {
+    use crate::chatbot::docx;
+    use teloxide::net::Download;
+
+    let doc = match msg.document() {
+        Some(d) => d,
+        None => return vec![],
+    };
+
+    // Only process .docx files
+    let filename = doc.file_name.as_deref().unwrap_or("document");
+    if !filename.to_lowercase().ends_with(".docx") {
+        info!("📄 Skipping non-docx document: {}", filename);
+        return vec![];
+    }
+
+    info!("📄 Processing document: {}", filename);
+
+    // Download the file
+    let file = match bot.get_file(doc.file.id.clone()).await {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("Failed to get document file info: {}", e);
+            return vec![DocumentContent {
+                filename: filename.to_string(),
+                text: format!("[Document download failed: {}]", e),
+            }];
+        }
+    };
+
+    let mut data = Vec::new();
+    if let Err(e) = bot.download_file(&file.path, &mut data).await {
+        warn!("Failed to download document: {}", e);
+        return vec![DocumentContent {
+            filename: filename.to_string(),
+            text: format!("[Document download failed: {}]", e),
+        }];
+    }
+
+    info!("📥 Downloaded document ({} bytes)", data.len());
+
+    // Extract text from docx
+    match docx::extract_text(&data) {
+        Ok(text) => {
+            let preview = docx::preview(&text, 100);
+            info!("📝 Extracted text: \"{}\"", preview);
+            vec![DocumentContent {
+                filename: filename.to_string(),
+                text,
+            }]
+        }
+        Err(e) => {
+            warn!("Document extraction failed: {}", e);
+            vec![DocumentContent {
+                filename: filename.to_string(),
+                text: format!("[Document extraction failed: {}]", e),
+            }]
+        }
+    }
+}
+
+/// Download and transcribe a voice message if present.
+/// Returns the transcription, or an error message if transcription failed.
+async fn transcribe_voice(bot: &Bot, state: &BotState, msg: &Message) -> Option<String> {
+    use teloxide::net::Download;
+
+    let voice = msg.voice()?;
+
+    let whisper = match state.whisper.as_ref() {
+        Some(w) => w,
+        None => {
+            warn!("Voice message received but Whisper not configured");
+            return Some("[Voice message - transcription not available (Whisper not configured)]".to_string());
+        }
+    };
+
+    info!("🎤 Voice message from user {} ({} seconds)",
+          msg.from.as_ref().map(|u| u.id.0).unwrap_or(0),
+          voice.duration);
+
+    // Download voice file
+    let file = match bot.get_file(voice.file.id.clone()).await {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("Failed to get voice file info: {}", e);
+            return Some(format!("[Voice message - download failed: {}]", e));
+        }
+    };
+
+    let mut data = Vec::new();
+    if let Err(e) = bot.download_file(&file.path, &mut data).await {
+        warn!("Failed to download voice file: {}", e);
+        return Some(format!("[Voice message - download failed: {}]", e));
+    }
+
+    info!("📥 Downloaded voice ({} bytes)", data.len());
+
+    // Transcribe (loads the model on first use)
+    match whisper.transcribe(data).await {
+        Ok(text) => {
+            let preview: String = text.chars().take(100).collect();
+            info!("📝 Transcribed: \"{}\"", preview);
+            Some(text)
+        }
+        Err(TranscribeError::NotAvailable(e)) => {
+            warn!("Voice message received but Whisper unavailable: {}", e);
+            Some(format!("[Voice message - transcription not available (Whisper failed to load: {})]", e))
+        }
+        Err(TranscribeError::Failed(e)) => {
+            warn!("Transcription failed: {}", e);
+            Some(format!("[Voice message - transcription failed: {}]", e))
+        }
+    }
+}
+
+pub(crate) async fn handle_edited_message(msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    let is_group = matches!(msg.chat.kind, ChatKind::Public(_));
+    if !is_group {
+        return Ok(());
+    }
+
+    if !state.config.allows_group(msg.chat.id) {
+        return Ok(());
+    }
+
+    let text = match msg.text() {
+        Some(t) => t,
+        None => return Ok(()),
+    };
+
+    if let Some(ref chatbot) = state.chatbot {
+        chatbot.handle_edit(msg.id.0 as i64, text).await;
+    }
+
+    Ok(())
+}
+
+async fn handle_message_reaction(
+    update: teloxide::types::MessageReactionUpdated,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    let is_group = matches!(update.chat.kind, ChatKind::Public(_));
+    if is_group && !state.config.allows_group(update.chat.id) {
+        return Ok(());
+    }
+
+    let Some(ref chatbot) = state.chatbot else {
+        return Ok(());
+    };
+    // Anonymous admins react on behalf of the chat; nothing to attribute
+    let Some(user) = update.user() else {
+        return Ok(());
+    };
+
+    let (added, removed) = reaction_changes(&update.old_reaction, &update.new_reaction);
+    if added.is_empty() && removed.is_empty() {
+        return Ok(());
+    }
+    let username = names::sanitize(user.username.as_deref().unwrap_or(&user.first_name));
+    chatbot.handle_reaction(
+        update.chat.id.0,
+        update.message_id.0 as i64,
+        user.id.0 as i64,
+        &username,
+        added,
+        removed,
+    ).await;
+
+    Ok(())
+}
+
+/// Answer a DM from a user who may not DM the bot, per `dm_policy`.
+async fn handle_unknown_dm(bot: &Bot, msg: &Message, user: &teloxide::types::User, state: &BotState) {
+    let username = user.username.as_deref().unwrap_or(&user.first_name);
+    let nth = count_unknown_dm(state, user.id).await;
+    match dm_policy::action(state.config.dm_policy, nth, state.config.dm_forward_limit) {
+        DmAction::Ignore => debug!("Ignoring DM #{} from non-trusted user {} ({})", nth, username, user.id),
+        DmAction::Deny => {
+            info!("DM from non-trusted user {} ({}) - denial", username, user.id);
+            if let Err(e) = bot.send_message(msg.chat.id, dm_policy::DENY_MESSAGE).await {
+                warn!("Failed to deny DM from {}: {}", user.id, e);
+            }
+        }
+        DmAction::Intro => {
+            info!("DM from non-trusted user {} ({}) - intro", username, user.id);
+            if let Err(e) = bot.send_message(msg.chat.id, &state.config.dm_intro_message).await {
+                warn!("Failed to send DM intro to {}: {}", user.id, e);
+            }
+            let text = msg.text().or(msg.caption());
+            let notice = dm_policy::owner_notice(user.id.0, &user.first_name, user.username.as_deref(), text);
+            match state.chatbot {
+                Some(ref chatbot) => chatbot.notify_owner(&notice).await,
+                None => warn!("{}", notice),
+            }
+        }
+        DmAction::Forward => {
+            info!("DM #{} from non-trusted user {} ({}) - forwarding to owner", nth, username, user.id);
+            let Some(&owner_id) = state.config.owner_ids.first() else {
+                return;
+            };
+            if let Err(e) = bot.forward_message(owner_id, msg.chat.id, msg.id).await {
+                warn!("Failed to forward DM from {} to owner: {}", user.id, e);
+            }
+        }
+    }
+}
+
+/// How many DMs `user_id` has sent without access, including this one.
+/// Counted in the database when there is one, so restarts don't reset it.
+async fn count_unknown_dm(state: &BotState, user_id: UserId) -> u32 {
+    if let Some(ref chatbot) = state.chatbot {
+        match chatbot.record_unknown_dm(user_id.0 as i64, state.config.dm_denied_expiry).await {
+            Ok(nth) => return nth,
+            Err(e) => warn!("Failed to record DM from {}: {}", user_id, e),
+        }
+    }
+    state.dm_denied.lock().await.record(user_id.0, chrono::Utc::now(), state.config.dm_denied_expiry)
+}
+
+/// Record a join or leave, unless the same event was just recorded from the
+/// other source (service message vs chat_member update). How a member got in
+/// is recorded from both, since each source knows different parts of it.
+async fn record_member_event(
+    state: &BotState,
+    chatbot: &ChatbotEngine,
+    chat_id: i64,
+    user: &teloxide::types::User,
+    kind: MemberEventKind,
+    invite: InviteSource,
+) {
+    let user_id = user.id.0 as i64;
+    let first_seen = state.member_events.lock().await.first_seen(chat_id, user_id, kind, chrono::Utc::now());
+    match kind {
+        MemberEventKind::Joined => {
+            if first_seen {
+                info!("👋 Member joined: {} ({})", user.first_name, user_id);
+                chatbot.handle_member_joined(user_id, user.username.clone(), user.first_name.clone()).await;
+            }
+            if !invite.is_empty() {
+                info!("🔗 {} joined via {:?}, invited by {:?}", user_id, invite.invite_link, invite.invited_by);
+                chatbot.handle_member_invite(user_id, invite.invite_link, invite.invited_by).await;
+            }
+        }
+        MemberEventKind::Left if !first_seen => {}
+        MemberEventKind::Left => {
+            info!("👋 Member left: {} ({})", user.first_name, user_id);
+            chatbot.handle_member_left(user_id).await;
+        }
+    }
+}
+
+pub(crate) async fn handle_chat_member(update: teloxide::types::ChatMemberUpdated, state: Arc<BotState>) -> ResponseResult<()> {
+    // Only track for allowed groups
+    if !state.config.allows_group(update.chat.id) {
+        return Ok(());
+    }
+
+    let Some(ref chatbot) = state.chatbot else {
+        return Ok(());
+    };
+
+    let user = &update.new_chat_member.user;
+    let user_id = user.id.0 as i64;
+    let first_name = user.first_name.clone();
+
+    use teloxide::types::ChatMemberStatus;
+    let is_admin = |status: ChatMemberStatus| matches!(status, ChatMemberStatus::Administrator | ChatMemberStatus::Owner);
+    let admin_changed = is_admin(update.old_chat_member.status()) || is_admin(update.new_chat_member.status());
+    chatbot.handle_member_updated(update.chat.id.0, user_id, admin_changed);
+
+    match update.new_chat_member.status() {
+        ChatMemberStatus::Member | ChatMemberStatus::Administrator | ChatMemberStatus::Owner => {
+            // User joined or was added
+            if matches!(update.old_chat_member.status(), ChatMemberStatus::Left | ChatMemberStatus::Banned) {
+                let invite = update_invite_source(&update);
+                record_member_event(&state, chatbot, update.chat.id.0, user, MemberEventKind::Joined, invite).await;
+            }
+        }
+        ChatMemberStatus::Left => {
+            record_member_event(&state, chatbot, update.chat.id.0, user, MemberEventKind::Left, InviteSource::default()).await;
+        }
+        ChatMemberStatus::Banned => {
+            info!("🚫 Member banned: {} ({})", first_name, user_id);
+            chatbot.handle_member_banned(user_id).await;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// The bot itself was added, removed, promoted or demoted.
+pub(crate) async fn handle_my_chat_member(update: teloxide::types::ChatMemberUpdated, state: Arc<BotState>) -> ResponseResult<()> {
+    let Some(ref chatbot) = state.chatbot else {
+        return Ok(());
+    };
+    let status = BotStatus::from_member(&update.new_chat_member.kind);
+    let label = update.chat.title().map(|title| format!("\"{}\" ({})", title, update.chat.id));
+    chatbot.handle_bot_status(update.chat.id.0, label, status).await;
+    Ok(())
+}
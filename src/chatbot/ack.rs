@@ -0,0 +1,129 @@
+//! Quick acknowledgment reactions while a turn runs.
+//!
+//! A heavy question can take Claude a minute, and people repeat themselves
+//! thinking the bot missed them. With `ack_reactions` set, the engine reacts
+//! to each message that @mentions the bot as soon as its turn starts, and
+//! swaps the reaction for the "done" one when the turn ends. These calls are
+//! the engine's own and don't count as tool calls. A chat where the bot may
+//! not react just doesn't get them.
+
+use serde::Deserialize;
+use tracing::debug;
+
+use super::message::ChatMessage;
+use super::telegram::TelegramApi;
+
+/// `ack_reactions` as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AckReactions {
+    #[serde(default = "default_pending")]
+    pub pending: String,
+    /// Replaces `pending` when the turn ends; null leaves `pending` on.
+    /// Telegram only allows its own reaction set, so ✅ isn't an option.
+    #[serde(default = "default_done")]
+    pub done: Option<String>,
+    /// Acknowledge DMs too.
+    #[serde(default)]
+    pub in_dms: bool,
+}
+
+fn default_pending() -> String {
+    "👀".to_string()
+}
+
+fn default_done() -> Option<String> {
+    Some("👌".to_string())
+}
+
+impl AckReactions {
+    /// The (chat_id, message_id) of each message in `batch` to acknowledge:
+    /// group messages mentioning the bot, and DMs if `in_dms` is set.
+    pub fn targets(&self, batch: &[ChatMessage]) -> Vec<(i64, i64)> {
+        batch
+            .iter()
+            .filter(|m| m.message_id > 0 && !m.backfilled)
+            .filter(|m| if m.chat_id < 0 { m.mentions_bot } else { self.in_dms })
+            .map(|m| (m.chat_id, m.message_id))
+            .collect()
+    }
+
+    /// React with `pending` before the turn.
+    pub async fn start(&self, telegram: &dyn TelegramApi, targets: &[(i64, i64)]) {
+        react(telegram, targets, &self.pending).await;
+    }
+
+    /// Swap to `done` after a turn. `replied_with_reaction` means Claude set
+    /// its own reaction in the turn, which mustn't be overwritten.
+    pub async fn finish(&self, telegram: &dyn TelegramApi, targets: &[(i64, i64)], replied_with_reaction: bool) {
+        if let Some(ref done) = self.done
+            && !replied_with_reaction
+        {
+            react(telegram, targets, done).await;
+        }
+    }
+}
+
+async fn react(telegram: &dyn TelegramApi, targets: &[(i64, i64)], emoji: &str) {
+    for &(chat_id, message_id) in targets {
+        if let Err(e) = telegram.set_message_reaction(chat_id, message_id, emoji).await {
+            // Usually the bot lacks the right to react there
+            debug!("Ack reaction {} on {} in {} failed: {}", emoji, message_id, chat_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::mock_telegram::MockTelegramApi;
+
+    fn ack(in_dms: bool) -> AckReactions {
+        AckReactions { pending: default_pending(), done: default_done(), in_dms }
+    }
+
+    fn message(chat_id: i64, message_id: i64, mentions_bot: bool) -> ChatMessage {
+        let mut msg = ChatMessage::system("hey".to_string());
+        msg.chat_id = chat_id;
+        msg.message_id = message_id;
+        msg.mentions_bot = mentions_bot;
+        msg
+    }
+
+    #[test]
+    fn test_targets_follow_mentions() {
+        let batch = [
+            message(-100, 1, true),
+            message(-100, 2, false),
+            message(42, 3, false),
+            // System notices have no message to react to
+            message(-100, 0, true),
+        ];
+        assert_eq!(ack(false).targets(&batch), vec![(-100, 1)]);
+        assert_eq!(ack(true).targets(&batch), vec![(-100, 1), (42, 3)]);
+
+        let mut old = message(-100, 4, true);
+        old.backfilled = true;
+        assert!(ack(true).targets(&[old]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_swap_on_completion() {
+        let telegram = MockTelegramApi::new();
+        let ack = ack(false);
+        let targets = [(-100, 1)];
+        ack.start(&telegram, &targets).await;
+        ack.finish(&telegram, &targets, false).await;
+        assert_eq!(telegram.calls(), ["set_message_reaction -100 1 👀", "set_message_reaction -100 1 👌"]);
+
+        // Claude's own reaction stays, and so does pending without a done emoji
+        let telegram = MockTelegramApi::new();
+        ack.finish(&telegram, &targets, true).await;
+        AckReactions { done: None, ..ack.clone() }.finish(&telegram, &targets, false).await;
+        assert!(telegram.calls().is_empty());
+
+        // A failed reaction is only logged
+        let telegram = MockTelegramApi::failing("Bad Request: REACTION_INVALID");
+        ack.start(&telegram, &targets).await;
+        assert_eq!(telegram.calls().len(), 1);
+    }
+}
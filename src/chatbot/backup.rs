@@ -0,0 +1,197 @@
+//! Backups of the database and memories.
+//!
+//! On a cron schedule (weekly by default) and on the owner's `/backup now`,
+//! the database is copied with SQLite's online backup and the memories
+//! directory is zipped, into `data_dir/backups/<timestamp>/`. Only the newest
+//! backups are kept. The owner is told where the backup went, or why it
+//! failed.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use tracing::{info, warn};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+use super::database::AsyncDatabase;
+use super::image_limits::format_mb;
+
+/// Default schedule: Sundays at 04:00 UTC.
+pub const DEFAULT_CRON: &str = "0 0 4 * * Sun *";
+
+/// Backups kept by default.
+pub const DEFAULT_KEEP: usize = 4;
+
+/// Backup directory names, which sort oldest first.
+const NAME_FORMAT: &str = "%Y-%m-%d_%H%M%S";
+
+/// A finished backup.
+#[derive(Debug)]
+pub struct BackupReport {
+    pub dir: PathBuf,
+    pub bytes: u64,
+}
+
+/// Directory name for a backup taken at `at`.
+fn backup_name(at: DateTime<Utc>) -> String {
+    at.format(NAME_FORMAT).to_string()
+}
+
+/// Backups to delete so that only the newest `keep` remain. Names that
+/// aren't backup timestamps are never touched.
+pub fn stale_backups(names: &[String], keep: usize) -> Vec<String> {
+    let mut backups: Vec<&String> = names
+        .iter()
+        .filter(|n| NaiveDateTime::parse_from_str(n, NAME_FORMAT).is_ok())
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    backups.into_iter().take(excess).cloned().collect()
+}
+
+/// Back up the database and memories under `data_dir/backups`, then drop
+/// old backups beyond `keep`.
+pub async fn run(database: &AsyncDatabase, data_dir: &Path, keep: usize) -> Result<BackupReport, String> {
+    let root = data_dir.join("backups");
+    let dir = root.join(backup_name(Utc::now()));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+
+    let db_path = dir.join("database.db");
+    let result = match database.call(move |db| db.backup_to(&db_path)).await.and_then(|r| r) {
+        Ok(()) => {
+            let memories = data_dir.join("memories");
+            let target = dir.clone();
+            tokio::task::spawn_blocking(move || archive_memories(&memories, &target))
+                .await
+                .map_err(|e| format!("Memories archive task failed: {e}"))
+                .and_then(|r| r)
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        // Don't leave a half-written backup that rotation would count
+        if let Err(rm) = fs::remove_dir_all(&dir) {
+            warn!("Failed to remove incomplete backup {}: {}", dir.display(), rm);
+        }
+        return Err(e);
+    }
+
+    rotate(&root, keep)?;
+    Ok(BackupReport { bytes: dir_size(&dir), dir })
+}
+
+/// Zip `memories` into `dir/memories.zip`, if there are any.
+fn archive_memories(memories: &Path, dir: &Path) -> Result<(), String> {
+    if !memories.is_dir() {
+        return Ok(());
+    }
+    let path = dir.join("memories.zip");
+    let file = File::create(&path).map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    add_dir(&mut zip, memories, "")?;
+    zip.finish().map_err(|e| format!("Failed to finish memories archive: {e}"))?;
+    Ok(())
+}
+
+fn add_dir(zip: &mut ZipWriter<File>, dir: &Path, prefix: &str) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {e}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {e}", dir.display()))?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            zip.add_directory(name.as_str(), SimpleFileOptions::default())
+                .map_err(|e| format!("Failed to archive {}: {e}", name))?;
+            add_dir(zip, &path, &format!("{}/", name))?;
+        } else {
+            let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+            zip.start_file(name.as_str(), SimpleFileOptions::default())
+                .and_then(|_| zip.write_all(&data).map_err(Into::into))
+                .map_err(|e| format!("Failed to archive {}: {e}", name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete backups in `root` beyond the newest `keep`.
+fn rotate(root: &Path, keep: usize) -> Result<(), String> {
+    let names: Vec<String> = fs::read_dir(root)
+        .map_err(|e| format!("Failed to list backups: {e}"))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    for name in stale_backups(&names, keep) {
+        let path = root.join(&name);
+        fs::remove_dir_all(&path).map_err(|e| format!("Failed to remove old backup {}: {e}", path.display()))?;
+        info!("🗑️ Removed old backup {}", name);
+    }
+    Ok(())
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()?.metadata().ok()).map(|m| m.len()).sum())
+        .unwrap_or(0)
+}
+
+/// What to tell the owner about a backup.
+pub fn owner_message(result: &Result<BackupReport, String>) -> String {
+    match result {
+        Ok(report) => format!("💾 Backup done: {} in {}", format_mb(report.bytes), report.dir.display()),
+        Err(e) => format!("⚠️ Backup failed: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::database::Database;
+
+    #[test]
+    fn test_stale_backups() {
+        let names: Vec<String> = [
+            "2024-01-21_040000",
+            "2024-01-07_040000",
+            "notes",
+            "2024-01-14_040000",
+            "2024-01-28_040000",
+            "2024-01-15_120312",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(stale_backups(&names, 3), ["2024-01-07_040000", "2024-01-14_040000"]);
+        assert!(stale_backups(&names, 5).is_empty());
+        // Foreign directories are never removed
+        assert_eq!(stale_backups(&names, 0).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_run_backs_up_and_rotates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("memories/notes")).unwrap();
+        fs::write(dir.path().join("memories/notes/alice.md"), "likes rust").unwrap();
+        for old in ["2024-01-07_040000", "2024-01-14_040000"] {
+            fs::create_dir_all(dir.path().join("backups").join(old)).unwrap();
+        }
+        let database = AsyncDatabase::new(Database::new());
+
+        let result = run(&database, dir.path(), 2).await;
+        let report = result.as_ref().unwrap();
+        assert!(report.dir.join("database.db").exists());
+        assert!(report.bytes > 0);
+        let archive = zip::ZipArchive::new(File::open(report.dir.join("memories.zip")).unwrap()).unwrap();
+        assert!(archive.file_names().any(|n| n == "notes/alice.md"));
+        assert!(owner_message(&result).starts_with("💾 Backup done:"));
+
+        let mut left: Vec<String> = fs::read_dir(dir.path().join("backups")).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left.len(), 2);
+        assert_eq!(left[0], "2024-01-14_040000");
+    }
+}
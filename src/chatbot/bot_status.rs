@@ -0,0 +1,213 @@
+//! The bot's own membership in each chat.
+//!
+//! Telegram sends a `my_chat_member` update when the bot is added, removed,
+//! promoted or demoted. The latest status per chat is kept in the database, so
+//! tools that need rights the bot no longer has fail up front with a message
+//! Claude can act on instead of an opaque Telegram error, and messages from
+//! chats the bot can't post in don't start turns.
+
+use chrono::{DateTime, Utc};
+use teloxide::types::ChatMemberKind;
+
+use crate::chatbot::tools::ToolCall;
+
+/// What the bot can do in a chat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BotStatus {
+    Admin,
+    Member,
+    /// Still in the chat but can't send messages.
+    Muted,
+    /// Left, kicked or banned.
+    Removed,
+}
+
+impl BotStatus {
+    pub fn from_member(kind: &ChatMemberKind) -> Self {
+        match kind {
+            ChatMemberKind::Owner(_) | ChatMemberKind::Administrator(_) => Self::Admin,
+            ChatMemberKind::Member(_) => Self::Member,
+            ChatMemberKind::Restricted(r) if !r.is_member => Self::Removed,
+            ChatMemberKind::Restricted(r) if r.can_send_messages => Self::Member,
+            ChatMemberKind::Restricted(_) => Self::Muted,
+            ChatMemberKind::Left | ChatMemberKind::Banned(_) => Self::Removed,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Member => "member",
+            Self::Muted => "muted",
+            Self::Removed => "removed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "admin" => Some(Self::Admin),
+            "member" => Some(Self::Member),
+            "muted" => Some(Self::Muted),
+            "removed" => Some(Self::Removed),
+            _ => None,
+        }
+    }
+
+    pub fn can_send(self) -> bool {
+        matches!(self, Self::Admin | Self::Member)
+    }
+}
+
+/// The bot's recorded status in a chat and when it last changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotChatStatus {
+    pub status: BotStatus,
+    pub since: DateTime<Utc>,
+}
+
+/// What a tool call needs from the bot in its chat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Need {
+    Admin,
+    Send,
+}
+
+/// The chat a tool call acts in and what it needs there, for tools that
+/// fail when the bot lacks rights.
+pub fn requirement(call: &ToolCall) -> Option<(i64, Need)> {
+    match call {
+        ToolCall::DeleteMessage { chat_id, .. }
+        | ToolCall::BulkDelete { chat_id, .. }
+        | ToolCall::MuteUser { chat_id, .. }
+        | ToolCall::UnmuteUser { chat_id, .. }
+        | ToolCall::BanUser { chat_id, .. }
+        | ToolCall::KickUser { chat_id, .. }
+        | ToolCall::CreateInviteLink { chat_id, .. }
+        | ToolCall::RevokeInviteLink { chat_id, .. }
+        | ToolCall::SetSlowMode { chat_id, .. }
+        | ToolCall::SetChatPermissions { chat_id, .. } => Some((*chat_id, Need::Admin)),
+        ToolCall::SendMessage { chat_id, .. }
+        | ToolCall::SendPhoto { chat_id, .. }
+        | ToolCall::SendVoice { chat_id, .. }
+        | ToolCall::SendDocument { chat_id, .. }
+        | ToolCall::SendRubricDocx { chat_id, .. }
+        | ToolCall::SendDice { chat_id, .. }
+        | ToolCall::SendLocation { chat_id, .. }
+        | ToolCall::RelayToGroup { chat_id, .. }
+        | ToolCall::AddReaction { chat_id, .. } => Some((*chat_id, Need::Send)),
+        _ => None,
+    }
+}
+
+/// Refuse a call the bot no longer has the rights for. Chats with no
+/// recorded status are left to Telegram.
+pub fn check(chat_id: i64, need: Need, status: Option<&BotChatStatus>) -> Result<(), String> {
+    let Some(recorded) = status else {
+        return Ok(());
+    };
+    let since = recorded.since.format("%Y-%m-%d %H:%M UTC");
+    match (recorded.status, need) {
+        (BotStatus::Removed, _) => Err(format!("I'm not in chat {} anymore (since {})", chat_id, since)),
+        (BotStatus::Member | BotStatus::Muted, Need::Admin) => {
+            Err(format!("I'm not an admin in chat {} anymore (since {})", chat_id, since))
+        }
+        (BotStatus::Muted, Need::Send) => Err(format!("I can't send messages in chat {} (since {})", chat_id, since)),
+        _ => Ok(()),
+    }
+}
+
+/// What to tell the owner when the bot's status in a chat changes, if
+/// anything: losing admin rights, posting rights or the chat itself, and
+/// getting them back.
+pub fn owner_notice(chat: &str, old: Option<BotStatus>, new: BotStatus) -> Option<String> {
+    match (old, new) {
+        (Some(old), new) if old == new => None,
+        (_, BotStatus::Removed) => Some(format!("🚪 I was removed from {}.", chat)),
+        (Some(BotStatus::Admin), BotStatus::Member) => Some(format!(
+            "⚠️ I'm no longer an admin in {}. Moderation tools are off there until my rights are restored.",
+            chat
+        )),
+        (_, BotStatus::Muted) => Some(format!("🔇 I can't send messages in {} anymore.", chat)),
+        (Some(_), BotStatus::Admin) => Some(format!("✅ I'm an admin in {} again.", chat)),
+        (Some(BotStatus::Muted | BotStatus::Removed), BotStatus::Member) => {
+            Some(format!("✅ I'm back in {} (not as admin).", chat))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::message_ref::MessageRef;
+    use teloxide::types::Member;
+
+    fn recorded(status: BotStatus) -> BotChatStatus {
+        let since = DateTime::parse_from_rfc3339("2024-01-15T10:30:00Z").unwrap().with_timezone(&Utc);
+        BotChatStatus { status, since }
+    }
+
+    #[test]
+    fn test_from_member() {
+        assert_eq!(BotStatus::from_member(&ChatMemberKind::Left), BotStatus::Removed);
+        assert_eq!(BotStatus::from_member(&ChatMemberKind::Member(Member { until_date: None })), BotStatus::Member);
+        for status in [BotStatus::Admin, BotStatus::Member, BotStatus::Muted, BotStatus::Removed] {
+            assert_eq!(BotStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(BotStatus::parse("kicked"), None);
+    }
+
+    #[test]
+    fn test_short_circuit_messages() {
+        let chat = -1001234567890;
+        assert!(check(chat, Need::Admin, None).is_ok());
+        assert!(check(chat, Need::Admin, Some(&recorded(BotStatus::Admin))).is_ok());
+        assert!(check(chat, Need::Send, Some(&recorded(BotStatus::Member))).is_ok());
+
+        assert_eq!(
+            check(chat, Need::Admin, Some(&recorded(BotStatus::Member))).unwrap_err(),
+            "I'm not an admin in chat -1001234567890 anymore (since 2024-01-15 10:30 UTC)"
+        );
+        assert_eq!(
+            check(chat, Need::Send, Some(&recorded(BotStatus::Removed))).unwrap_err(),
+            "I'm not in chat -1001234567890 anymore (since 2024-01-15 10:30 UTC)"
+        );
+        assert_eq!(
+            check(chat, Need::Send, Some(&recorded(BotStatus::Muted))).unwrap_err(),
+            "I can't send messages in chat -1001234567890 (since 2024-01-15 10:30 UTC)"
+        );
+    }
+
+    #[test]
+    fn test_requirement() {
+        let ban = ToolCall::BanUser { chat_id: -100, user_id: 1, revoke_messages: false };
+        assert_eq!(requirement(&ban), Some((-100, Need::Admin)));
+        let delete = ToolCall::DeleteMessage { chat_id: -100, message_id: 5 };
+        assert_eq!(requirement(&delete), Some((-100, Need::Admin)));
+        let bulk = ToolCall::BulkDelete { chat_id: -100, user_id: None, since_minutes: 60, contains: None, limit: 50 };
+        assert_eq!(requirement(&bulk), Some((-100, Need::Admin)));
+        let slow = ToolCall::SetSlowMode { chat_id: -100, seconds: 30 };
+        assert_eq!(requirement(&slow), Some((-100, Need::Admin)));
+        let permissions = ToolCall::SetChatPermissions { chat_id: -100, permissions: Default::default() };
+        assert_eq!(requirement(&permissions), Some((-100, Need::Admin)));
+        let react = ToolCall::AddReaction { chat_id: -100, message_id: MessageRef::Id(5), emoji: "👍".into() };
+        assert_eq!(requirement(&react), Some((-100, Need::Send)));
+        let relay = ToolCall::RelayToGroup { chat_id: -100, text: "hi".into(), on_behalf_of_user_id: 5 };
+        assert_eq!(requirement(&relay), Some((-100, Need::Send)));
+        let admins = ToolCall::GetChatAdmins { chat_id: -100 };
+        assert_eq!(requirement(&admins), None);
+    }
+
+    #[test]
+    fn test_owner_notice() {
+        let chat = "\"Rust Club\" (-100)";
+        assert_eq!(owner_notice(chat, Some(BotStatus::Admin), BotStatus::Admin), None);
+        assert_eq!(owner_notice(chat, Some(BotStatus::Admin), BotStatus::Removed).unwrap(), "🚪 I was removed from \"Rust Club\" (-100).");
+        assert!(owner_notice(chat, Some(BotStatus::Admin), BotStatus::Member).unwrap().contains("no longer an admin"));
+        assert!(owner_notice(chat, Some(BotStatus::Member), BotStatus::Admin).unwrap().contains("admin in \"Rust Club\" (-100) again"));
+        assert!(owner_notice(chat, Some(BotStatus::Removed), BotStatus::Member).unwrap().contains("back in"));
+        // Being added to a new chat isn't a loss or a restoration
+        assert_eq!(owner_notice(chat, None, BotStatus::Member), None);
+        assert_eq!(owner_notice(chat, None, BotStatus::Admin), None);
+    }
+}
@@ -0,0 +1,162 @@
+//! Deleting a batch of recent messages after a spam wave or a flood.
+//!
+//! `bulk_delete` picks stored messages of one chat by age, sender and text,
+//! newest first, and deletes them one at a time, spaced out to stay well
+//! under Telegram's flood limits. Telegram only lets bots delete messages
+//! younger than 48 hours, which bounds the window. Deleted messages are
+//! flagged in the database so they aren't picked again.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::telegram::TelegramApi;
+
+/// Most messages one call deletes.
+pub const MAX_LIMIT: i64 = 200;
+
+/// Oldest messages considered, in minutes: Telegram's 48-hour delete window.
+pub const MAX_SINCE_MINUTES: i64 = 48 * 60;
+
+/// Pause between deletions (20 per second).
+pub const DELETE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Failures listed by message in the result.
+const MAX_LISTED_FAILURES: usize = 5;
+
+/// Check the window and limit against the caps.
+pub fn check_caps(since_minutes: i64, limit: i64) -> Result<(), String> {
+    if !(1..=MAX_SINCE_MINUTES).contains(&since_minutes) {
+        return Err(format!(
+            "since_minutes must be 1-{} (Telegram only lets bots delete messages from the last 48 hours)",
+            MAX_SINCE_MINUTES
+        ));
+    }
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(format!("limit must be 1-{}", MAX_LIMIT));
+    }
+    Ok(())
+}
+
+/// What a bulk deletion did.
+#[derive(Debug, Default, PartialEq)]
+pub struct Outcome {
+    pub deleted: Vec<i64>,
+    pub failed: Vec<(i64, String)>,
+    /// Messages not tried before the time budget ran out.
+    pub remaining: usize,
+}
+
+impl Outcome {
+    /// The result for Claude: a count, and the first few failures.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Deleted {} message(s)", self.deleted.len());
+        if !self.failed.is_empty() {
+            let listed: Vec<String> = self.failed
+                .iter()
+                .take(MAX_LISTED_FAILURES)
+                .map(|(id, error)| format!("{}: {}", id, error))
+                .collect();
+            let more = self.failed.len().saturating_sub(MAX_LISTED_FAILURES);
+            summary.push_str(&format!("; {} failed ({}", self.failed.len(), listed.join("; ")));
+            if more > 0 {
+                summary.push_str(&format!("; and {} more", more));
+            }
+            summary.push(')');
+        }
+        if self.remaining > 0 {
+            summary.push_str(&format!("; ran out of time with {} left, call again to continue", self.remaining));
+        }
+        summary
+    }
+}
+
+/// Delete `message_ids` in `chat_id`, waiting `interval` between calls and
+/// stopping once `budget` is spent.
+pub async fn delete_paced(
+    telegram: &dyn TelegramApi,
+    chat_id: i64,
+    message_ids: &[i64],
+    interval: Duration,
+    budget: Duration,
+) -> Outcome {
+    let deadline = Instant::now() + budget;
+    let mut outcome = Outcome::default();
+    for (i, &message_id) in message_ids.iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(interval).await;
+        }
+        if Instant::now() >= deadline {
+            outcome.remaining = message_ids.len() - i;
+            break;
+        }
+        match telegram.delete_message(chat_id, message_id).await {
+            Ok(()) => outcome.deleted.push(message_id),
+            Err(e) => {
+                warn!("Bulk delete of {} in {} failed: {}", message_id, chat_id, e);
+                outcome.failed.push((message_id, e));
+            }
+        }
+    }
+    info!(
+        "🧹 Bulk delete in {}: {} deleted, {} failed, {} left",
+        chat_id, outcome.deleted.len(), outcome.failed.len(), outcome.remaining
+    );
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::mock_telegram::MockTelegramApi;
+
+    #[test]
+    fn test_caps() {
+        assert!(check_caps(60, 50).is_ok());
+        assert!(check_caps(MAX_SINCE_MINUTES, MAX_LIMIT).is_ok());
+        assert!(check_caps(MAX_SINCE_MINUTES + 1, 50).unwrap_err().contains("48 hours"));
+        assert!(check_caps(0, 50).is_err());
+        assert_eq!(check_caps(60, MAX_LIMIT + 1).unwrap_err(), "limit must be 1-200");
+        assert!(check_caps(60, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deletions_are_paced() {
+        let telegram = MockTelegramApi::new();
+        let outcome = delete_paced(&telegram, -100, &[5, 4, 3, 2], DELETE_INTERVAL, Duration::from_secs(60)).await;
+        assert_eq!(outcome.deleted, vec![5, 4, 3, 2]);
+        assert_eq!(telegram.calls(), ["delete_message -100 5", "delete_message -100 4", "delete_message -100 3", "delete_message -100 2"]);
+
+        let times = telegram.call_times();
+        assert!(times.windows(2).all(|w| w[1] - w[0] >= DELETE_INTERVAL));
+        // No more than 20 in any second
+        assert!(times[times.len() - 1] - times[0] < Duration::from_secs(1));
+        assert_eq!(outcome.summary(), "Deleted 4 message(s)");
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_reported() {
+        let telegram = MockTelegramApi::failing("Bad Request: message to delete not found");
+        let ids: Vec<i64> = (1..=7).collect();
+        let outcome = delete_paced(&telegram, -100, &ids, DELETE_INTERVAL, Duration::from_secs(60)).await;
+        assert!(outcome.deleted.is_empty());
+        assert_eq!(outcome.failed.len(), 7);
+        assert_eq!(
+            outcome.summary(),
+            "Deleted 0 message(s); 7 failed (1: Bad Request: message to delete not found; \
+             2: Bad Request: message to delete not found; 3: Bad Request: message to delete not found; \
+             4: Bad Request: message to delete not found; 5: Bad Request: message to delete not found; and 2 more)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stops_when_out_of_time() {
+        let telegram = MockTelegramApi::new();
+        let ids: Vec<i64> = (1..=10).collect();
+        let outcome = delete_paced(&telegram, -100, &ids, Duration::from_millis(100), Duration::from_millis(150)).await;
+        assert_eq!(outcome.deleted, vec![1, 2]);
+        assert_eq!(outcome.remaining, 8);
+        assert_eq!(outcome.summary(), "Deleted 2 message(s); ran out of time with 8 left, call again to continue");
+    }
+}
@@ -0,0 +1,123 @@
+//! Groups upgraded to supergroups, which gives them a new chat ID.
+//!
+//! Telegram announces the move twice: a `migrate_to_chat_id` service message
+//! in the old group and a `migrate_from_chat_id` one in the new supergroup.
+//! The first one seen rewrites the stored history and the config file to the
+//! new ID; until the next restart, `ChatMigrations` maps the old ID to the
+//! new one, so stragglers on the old ID land in the same chat and the new ID
+//! passes the `allowed_groups` check.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Old chat ID to new, for moves seen this run.
+#[derive(Debug, Default)]
+pub struct ChatMigrations {
+    moved: RwLock<HashMap<i64, i64>>,
+}
+
+impl ChatMigrations {
+    /// Record that `old` is now `new`. False if it was already known.
+    pub fn record(&self, old: i64, new: i64) -> bool {
+        let mut moved = self.moved.write().expect("chat migrations lock poisoned");
+        if old == new || moved.get(&old) == Some(&new) {
+            return false;
+        }
+        moved.insert(old, new);
+        true
+    }
+
+    /// The chat's current ID: `chat_id` itself unless it moved.
+    pub fn current(&self, chat_id: i64) -> i64 {
+        let moved = self.moved.read().expect("chat migrations lock poisoned");
+        let mut id = chat_id;
+        // Bounded, in case of a cycle
+        for _ in 0..=moved.len() {
+            match moved.get(&id) {
+                Some(&next) => id = next,
+                None => break,
+            }
+        }
+        id
+    }
+
+    /// The IDs `chat_id` had before, most recent first.
+    pub fn previous(&self, chat_id: i64) -> Vec<i64> {
+        let moved = self.moved.read().expect("chat migrations lock poisoned");
+        let mut ids = Vec::new();
+        let mut id = chat_id;
+        while let Some((&old, _)) = moved.iter().find(|&(old, new)| *new == id && !ids.contains(old)) {
+            ids.push(old);
+            id = old;
+        }
+        ids
+    }
+}
+
+/// Point `allowed_groups` and `primary_chat_id` in a config file's JSON at
+/// `new` instead of `old`. Returns whether anything changed.
+pub fn rewrite_config(json: &mut serde_json::Value, old: i64, new: i64) -> bool {
+    let mut changed = false;
+    if let Some(groups) = json.get_mut("allowed_groups").and_then(|g| g.as_array_mut()) {
+        for group in groups.iter_mut().filter(|g| g.as_i64() == Some(old)) {
+            *group = new.into();
+            changed = true;
+        }
+        // In case the new ID was listed already
+        let mut seen = Vec::new();
+        groups.retain(|g| {
+            let first = !seen.contains(g);
+            seen.push(g.clone());
+            first
+        });
+    }
+    if let Some(primary) = json.get_mut("primary_chat_id")
+        && primary.as_i64() == Some(old)
+    {
+        *primary = new.into();
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations() {
+        let migrations = ChatMigrations::default();
+        assert_eq!(migrations.current(-100), -100);
+        assert!(migrations.record(-100, -1001));
+        // Both service messages report the same move
+        assert!(!migrations.record(-100, -1001));
+        assert!(!migrations.record(-5, -5));
+
+        assert_eq!(migrations.current(-100), -1001);
+        assert_eq!(migrations.current(-1001), -1001);
+        assert_eq!(migrations.previous(-1001), vec![-100]);
+        assert!(migrations.previous(-100).is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_config() {
+        let mut json = serde_json::json!({ "allowed_groups": [-200, -100], "primary_chat_id": -100, "owner_ids": [1] });
+        assert!(rewrite_config(&mut json, -100, -1001));
+        assert_eq!(json, serde_json::json!({ "allowed_groups": [-200, -1001], "primary_chat_id": -1001, "owner_ids": [1] }));
+        // Nothing left to change
+        assert!(!rewrite_config(&mut json, -100, -1001));
+
+        // Without an explicit primary_chat_id, the list alone changes
+        let mut json = serde_json::json!({ "allowed_groups": [-100] });
+        assert!(rewrite_config(&mut json, -100, -1001));
+        assert_eq!(json, serde_json::json!({ "allowed_groups": [-1001] }));
+
+        let mut json = serde_json::json!({ "allowed_groups": [-1001, -100] });
+        assert!(rewrite_config(&mut json, -100, -1001));
+        assert_eq!(json, serde_json::json!({ "allowed_groups": [-1001] }));
+
+        // A group the config doesn't list
+        let mut json = serde_json::json!({ "allowed_groups": [-200], "primary_chat_id": -200 });
+        assert!(!rewrite_config(&mut json, -100, -1001));
+    }
+}
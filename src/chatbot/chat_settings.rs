@@ -0,0 +1,210 @@
+//! Slow mode and member permissions for groups, changed by admins' request.
+//!
+//! The Bot API can't set a chat's slow mode delay, so the bot enforces it
+//! itself: a message from a non-admin sent sooner than the delay after their
+//! previous one in that chat is deleted. Delays snap to the values Telegram's
+//! own slow mode offers. Member permissions go through `setChatPermissions`;
+//! switches left out of a request keep their current value. Every change is
+//! logged in the `chat_setting_changes` table, and slow mode is restored
+//! from it at startup.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::ChatPermissions;
+
+/// Slow mode delays Telegram offers, in seconds (0 = off).
+pub const SLOW_MODE_DELAYS: [u32; 7] = [0, 10, 30, 60, 300, 900, 3600];
+
+/// `chat_setting_changes.setting` for slow mode.
+pub const SLOW_MODE_SETTING: &str = "slow_mode";
+/// `chat_setting_changes.setting` for member permissions.
+pub const PERMISSIONS_SETTING: &str = "permissions";
+
+/// Last-post times kept before old ones are dropped.
+const MAX_TRACKED_POSTS: usize = 10_000;
+
+/// The allowed delay nearest to `seconds`; ties go to the shorter one.
+pub fn snap(seconds: i64) -> u32 {
+    SLOW_MODE_DELAYS
+        .iter()
+        .copied()
+        .min_by_key(|&delay| (i64::from(delay) - seconds).abs())
+        .unwrap_or(0)
+}
+
+/// Slow mode delays by chat, and when each user last posted under them.
+#[derive(Debug, Default)]
+pub struct SlowMode {
+    delays: HashMap<i64, u32>,
+    last_post: HashMap<(i64, i64), Instant>,
+}
+
+impl SlowMode {
+    /// Set the delay for `chat_id` (0 turns slow mode off).
+    pub fn set(&mut self, chat_id: i64, seconds: u32) {
+        if seconds == 0 {
+            self.delays.remove(&chat_id);
+            self.last_post.retain(|&(chat, _), _| chat != chat_id);
+        } else {
+            self.delays.insert(chat_id, seconds);
+        }
+    }
+
+    /// How much longer `user_id` must wait to post in `chat_id`, or None if
+    /// they may post now, in which case the post is recorded.
+    pub fn check(&mut self, chat_id: i64, user_id: i64, now: Instant) -> Option<Duration> {
+        let delay = Duration::from_secs(u64::from(*self.delays.get(&chat_id)?));
+        if let Some(&last) = self.last_post.get(&(chat_id, user_id)) {
+            let elapsed = now.saturating_duration_since(last);
+            if elapsed < delay {
+                return Some(delay - elapsed);
+            }
+        }
+        if self.last_post.len() >= MAX_TRACKED_POSTS {
+            let longest = Duration::from_secs(u64::from(SLOW_MODE_DELAYS[SLOW_MODE_DELAYS.len() - 1]));
+            self.last_post.retain(|_, &mut t| now.saturating_duration_since(t) < longest);
+        }
+        self.last_post.insert((chat_id, user_id), now);
+        None
+    }
+}
+
+/// Member permission switches for `set_chat_permissions`. None leaves a
+/// switch as it is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PermissionFlags {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_send_messages: Option<bool>,
+    /// Photos, videos, audio, documents, video and voice notes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_send_media: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_send_polls: Option<bool>,
+    /// Stickers, GIFs, games and inline bots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_send_other: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_add_web_page_previews: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_invite_users: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_pin_messages: Option<bool>,
+}
+
+/// Names used in descriptions, with the Telegram permissions behind them.
+const PERMISSION_NAMES: [(&str, ChatPermissions); 7] = [
+    ("messages", ChatPermissions::SEND_MESSAGES),
+    ("media", ChatPermissions::SEND_MEDIA_MESSAGES),
+    ("polls", ChatPermissions::SEND_POLLS),
+    ("other", ChatPermissions::SEND_OTHER_MESSAGES),
+    ("link previews", ChatPermissions::ADD_WEB_PAGE_PREVIEWS),
+    ("invites", ChatPermissions::INVITE_USERS),
+    ("pins", ChatPermissions::PIN_MESSAGES),
+];
+
+impl PermissionFlags {
+    /// The requested changes, in `PERMISSION_NAMES` order.
+    fn changes(&self) -> Vec<(ChatPermissions, bool)> {
+        let flags = [
+            self.can_send_messages, self.can_send_media, self.can_send_polls, self.can_send_other,
+            self.can_add_web_page_previews, self.can_invite_users, self.can_pin_messages,
+        ];
+        PERMISSION_NAMES
+            .iter()
+            .zip(flags)
+            .filter_map(|((_, permission), flag)| flag.map(|allowed| (permission.clone(), allowed)))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes().is_empty()
+    }
+
+    /// `current` with the requested changes applied.
+    pub fn apply(&self, mut current: ChatPermissions) -> ChatPermissions {
+        for (permission, allowed) in self.changes() {
+            current.set(permission, allowed);
+        }
+        current
+    }
+
+    /// The requested changes, like "media off, polls off".
+    pub fn describe(&self) -> String {
+        let changes = self.changes();
+        PERMISSION_NAMES
+            .iter()
+            .filter_map(|(name, permission)| {
+                let &(_, allowed) = changes.iter().find(|(p, _)| p == permission)?;
+                Some(format!("{} {}", name, if allowed { "on" } else { "off" }))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// What members may do under `permissions`, like "messages, polls".
+pub fn describe_permissions(permissions: ChatPermissions) -> String {
+    let allowed: Vec<&str> = PERMISSION_NAMES
+        .iter()
+        .filter(|(_, permission)| permissions.contains(permission.clone()))
+        .map(|(name, _)| *name)
+        .collect();
+    if allowed.is_empty() { "nothing".to_string() } else { allowed.join(", ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_to_telegram_delays() {
+        assert_eq!(snap(0), 0);
+        assert_eq!(snap(-5), 0);
+        assert_eq!(snap(4), 0);
+        assert_eq!(snap(7), 10);
+        assert_eq!(snap(30), 30);
+        // Ties go to the shorter delay
+        assert_eq!(snap(20), 10);
+        assert_eq!(snap(45), 30);
+        assert_eq!(snap(120), 60);
+        assert_eq!(snap(600), 300);
+        assert_eq!(snap(1000), 900);
+        assert_eq!(snap(86_400), 3600);
+    }
+
+    #[test]
+    fn test_slow_mode_waits() {
+        let mut slow = SlowMode::default();
+        let start = Instant::now();
+        assert_eq!(slow.check(-100, 1, start), None);
+        assert_eq!(slow.check(-100, 1, start), None);
+
+        slow.set(-100, 30);
+        assert_eq!(slow.check(-100, 1, start), None);
+        assert_eq!(slow.check(-100, 1, start + Duration::from_secs(10)), Some(Duration::from_secs(20)));
+        // Other users and chats are unaffected
+        assert_eq!(slow.check(-100, 2, start + Duration::from_secs(10)), None);
+        assert_eq!(slow.check(-200, 1, start + Duration::from_secs(10)), None);
+        // A deleted early post doesn't restart the wait
+        assert_eq!(slow.check(-100, 1, start + Duration::from_secs(30)), None);
+
+        slow.set(-100, 0);
+        assert_eq!(slow.check(-100, 1, start + Duration::from_secs(31)), None);
+    }
+
+    #[test]
+    fn test_permission_flags() {
+        let flags = PermissionFlags { can_send_media: Some(false), can_send_polls: Some(false), ..Default::default() };
+        assert!(!flags.is_empty());
+        assert!(PermissionFlags::default().is_empty());
+        assert_eq!(flags.describe(), "media off, polls off");
+
+        let updated = flags.apply(ChatPermissions::all());
+        assert!(!updated.intersects(ChatPermissions::SEND_PHOTOS | ChatPermissions::SEND_POLLS));
+        assert!(updated.contains(ChatPermissions::SEND_MESSAGES | ChatPermissions::PIN_MESSAGES));
+        assert_eq!(describe_permissions(updated), "messages, other, link previews, invites, pins");
+        assert_eq!(describe_permissions(ChatPermissions::empty()), "nothing");
+    }
+}
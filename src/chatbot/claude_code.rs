@@ -2,19 +2,37 @@
 //!
 //! Spawns a persistent Claude Code process and relays messages to it.
 //! Claude Code maintains conversation history internally.
-//! Uses --resume to continue previous sessions across restarts.
+//! Uses --resume to continue previous sessions across restarts; a session
+//! that fails to resume is dropped for a fresh one rather than taking the
+//! chatbot down.
+//! Everything sent and received is mirrored to a `SessionTranscript`.
 //!
 //! SECURITY: Uses `--tools "WebSearch"` to allow only read-only web search.
 
+use std::collections::VecDeque;
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::process::{Child, ChildStderr, ChildStdin, Command, Stdio};
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
+use super::chat_settings::PermissionFlags;
+use super::message_ref::MessageRef;
+use super::session_transcript::{Direction, SessionTranscript};
 use super::tools::ToolCall;
+use super::validate;
+
+/// How long a new process gets to send its system message. A resume of a
+/// session the CLI no longer knows can hang instead of exiting.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Last stderr lines kept for start errors.
+const STDERR_TAIL_LINES: usize = 5;
 
 /// JSON schema for structured output - tool_calls array.
 const TOOL_CALLS_SCHEMA: &str = r#"{
@@ -28,9 +46,9 @@ const TOOL_CALLS_SCHEMA: &str = r#"{
           "tool": { "type": "string" },
           "chat_id": { "type": "integer" },
           "text": { "type": "string" },
-          "reply_to_message_id": { "type": "integer" },
+          "reply_to_message_id": { "type": ["integer", "string"] },
           "user_id": { "type": "integer" },
-          "message_id": { "type": "integer" },
+          "message_id": { "type": ["integer", "string"] },
           "emoji": { "type": "string" },
           "last_n": { "type": "integer" },
           "from_date": { "type": "string" },
@@ -38,11 +56,14 @@ const TOOL_CALLS_SCHEMA: &str = r#"{
           "username": { "type": "string" },
           "limit": { "type": "integer" },
           "duration_minutes": { "type": "integer" },
+          "until": { "type": "string" },
           "days_inactive": { "type": "integer" },
           "filter": { "type": "string" },
           "file_path": { "type": "string" },
           "path": { "type": "string" },
+          "offset": { "type": "integer" },
           "content": { "type": "string" },
+          "rubric_text": { "type": "string" },
           "old_string": { "type": "string" },
           "new_string": { "type": "string" },
           "pattern": { "type": "string" },
@@ -53,7 +74,40 @@ const TOOL_CALLS_SCHEMA: &str = r#"{
           "trigger_at": { "type": "string" },
           "repeat_cron": { "type": "string" },
           "reminder_id": { "type": "integer" },
-          "message": { "type": "string" }
+          "message": { "type": "string" },
+          "query": { "type": "string" },
+          "lang": { "type": "string" },
+          "target_lang": { "type": "string" },
+          "source_lang": { "type": "string" },
+          "instruction": { "type": "string" },
+          "input": { "type": "string" },
+          "max_output_chars": { "type": "integer" },
+          "max_depth": { "type": "integer" },
+          "include_replies": { "type": "boolean" },
+          "include_bot": { "type": "boolean" },
+          "revoke_messages": { "type": "boolean" },
+          "days": { "type": "integer" },
+          "delay_seconds": { "type": "integer" },
+          "scheduled_id": { "type": "integer" },
+          "delay": { "type": "string" },
+          "note": { "type": "string" },
+          "filename": { "type": "string" },
+          "format": { "type": "string" },
+          "expire_hours": { "type": "integer" },
+          "member_limit": { "type": "integer" },
+          "name": { "type": "string" },
+          "invite_link": { "type": "string" },
+          "on_behalf_of_user_id": { "type": "integer" },
+          "seconds": { "type": "integer" },
+          "can_send_messages": { "type": "boolean" },
+          "can_send_media": { "type": "boolean" },
+          "can_send_polls": { "type": "boolean" },
+          "can_send_other": { "type": "boolean" },
+          "can_add_web_page_previews": { "type": "boolean" },
+          "can_invite_users": { "type": "boolean" },
+          "can_pin_messages": { "type": "boolean" },
+          "since_minutes": { "type": "integer" },
+          "contains": { "type": "string" }
         },
         "required": ["tool"]
       }
@@ -62,6 +116,9 @@ const TOOL_CALLS_SCHEMA: &str = r#"{
   "required": ["tool_calls"]
 }"#;
 
+/// Most assistant text kept in `Response::prose`.
+const PROSE_SNIPPET_CHARS: usize = 500;
+
 /// Tool call with ID for tracking.
 #[derive(Debug, Clone)]
 pub struct ToolCallWithId {
@@ -86,12 +143,29 @@ pub struct Response {
     pub tool_calls: Vec<ToolCallWithId>,
     /// True if context compaction occurred during this response.
     pub compacted: bool,
+    /// Cost reported by Claude Code for this response (USD).
+    pub cost_usd: f64,
+    /// What the assistant wrote, when the response had no usable tool JSON
+    /// (the model answered in prose). Cut to `PROSE_SNIPPET_CHARS`.
+    pub prose: Option<String>,
+}
+
+/// How the Claude Code session started.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionStart {
+    Fresh,
+    Resumed,
+    /// The saved session couldn't be resumed; a fresh one was started.
+    ResumeFailed(String),
+    /// No session could be started; the worker exited.
+    Failed(String),
 }
 
 /// Claude Code client - maintains persistent subprocess.
 pub struct ClaudeCode {
     tx: mpsc::Sender<WorkerMessage>,
     rx: mpsc::Receiver<Response>,
+    started: Option<oneshot::Receiver<SessionStart>>,
 }
 
 enum WorkerMessage {
@@ -102,22 +176,42 @@ enum WorkerMessage {
 }
 
 impl ClaudeCode {
-    /// Start Claude Code, optionally resuming a previous session.
+    /// Start the Claude Code CLI `program`, optionally resuming a previous session.
     /// If session_file exists, resume that session. Otherwise start fresh with system_prompt.
-    pub fn start(system_prompt: String, session_file: Option<PathBuf>) -> Result<Self, String> {
+    /// Errs only if the CLI can't be run at all; how the session itself
+    /// started (including a failed resume) comes from `take_start_status`.
+    pub fn start(
+        program: PathBuf,
+        system_prompt: String,
+        session_file: Option<PathBuf>,
+        transcript: SessionTranscript,
+    ) -> Result<Self, String> {
+        let launcher = Launcher { program, handshake_timeout: HANDSHAKE_TIMEOUT, system_prompt };
+        Self::launch(launcher, session_file, transcript)
+    }
+
+    fn launch(launcher: Launcher, session_file: Option<PathBuf>, transcript: SessionTranscript) -> Result<Self, String> {
         let (msg_tx, msg_rx) = mpsc::channel::<WorkerMessage>(32);
         let (resp_tx, resp_rx) = mpsc::channel::<Response>(32);
+        let (started_tx, started_rx) = oneshot::channel::<SessionStart>();
 
         // Check for existing session
         let resume_session = session_file.as_ref().and_then(|p| load_session_id(p));
+        // Spawned here, so a missing CLI fails startup instead of the worker
+        let first = launcher.spawn(resume_session)?;
 
         std::thread::spawn(move || {
-            if let Err(e) = worker_loop(system_prompt, resume_session, session_file, msg_rx, resp_tx) {
+            if let Err(e) = worker_loop(launcher, first, session_file, transcript, msg_rx, resp_tx, started_tx) {
                 error!("Claude Code worker died: {}", e);
             }
         });
 
-        Ok(Self { tx: msg_tx, rx: resp_rx })
+        Ok(Self { tx: msg_tx, rx: resp_rx, started: Some(started_rx) })
+    }
+
+    /// Resolves once the session has started (or failed to). Can be taken once.
+    pub fn take_start_status(&mut self) -> Option<oneshot::Receiver<SessionStart>> {
+        self.started.take()
     }
 
     /// Send a user message and get response.
@@ -224,8 +318,9 @@ enum OutputMessage {
     Result {
         #[serde(default)]
         total_cost_usd: f64,
+        /// Kept raw so a malformed one doesn't drop the whole result.
         #[serde(default)]
-        structured_output: Option<StructuredOutput>,
+        structured_output: Option<serde_json::Value>,
         #[serde(default)]
         session_id: Option<String>,
     },
@@ -261,9 +356,51 @@ struct ContextManagement {
     truncated_content_length: Option<usize>,
 }
 
-#[derive(Debug, Deserialize)]
-struct StructuredOutput {
-    tool_calls: Vec<RawToolCall>,
+/// The raw tool calls of a structured output, or None if it's null or has
+/// no `tool_calls` array. Calls are parsed one by one, so one malformed
+/// call doesn't lose the others.
+fn raw_tool_calls(mut structured_output: serde_json::Value) -> Option<Vec<serde_json::Value>> {
+    match structured_output.get_mut("tool_calls")?.take() {
+        serde_json::Value::Array(calls) => Some(calls),
+        _ => None,
+    }
+}
+
+/// Fields the schema declares as plain integers.
+fn integer_fields() -> &'static [String] {
+    static FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+    FIELDS.get_or_init(|| {
+        let schema: serde_json::Value = serde_json::from_str(TOOL_CALLS_SCHEMA).expect("tool schema is valid JSON");
+        schema["properties"]["tool_calls"]["items"]["properties"]
+            .as_object()
+            .map(|props| props.iter()
+                .filter(|(_, p)| p["type"] == "integer")
+                .map(|(name, _)| name.clone())
+                .collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Parse one tool call, after coercing numeric strings in integer fields.
+fn parse_tool_call(mut raw: serde_json::Value) -> ToolCall {
+    let tool = raw.get("tool").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+    if let Some(fields) = raw.as_object_mut()
+        && let Err(message) = validate::coerce_integers(&tool, fields, integer_fields())
+    {
+        warn!("Tool parse error for '{}': {}", tool, message);
+        return ToolCall::ParseError { message };
+    }
+    match serde_json::from_value::<RawToolCall>(raw) {
+        Ok(call) => call.to_tool_call(),
+        Err(e) => {
+            let mut message = format!("{}: malformed arguments ({})", tool, e);
+            if let Some(example) = validate::example(&tool) {
+                message.push_str(&format!(". Example: {}", example));
+            }
+            warn!("Tool parse error for '{}': {}", tool, message);
+            ToolCall::ParseError { message }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -274,11 +411,11 @@ struct RawToolCall {
     #[serde(default)]
     text: Option<String>,
     #[serde(default)]
-    reply_to_message_id: Option<i64>,
+    reply_to_message_id: Option<MessageRef>,
     #[serde(default)]
     user_id: Option<i64>,
     #[serde(default)]
-    message_id: Option<i64>,
+    message_id: Option<MessageRef>,
     #[serde(default)]
     emoji: Option<String>,
     #[serde(default)]
@@ -288,6 +425,8 @@ struct RawToolCall {
     #[serde(default)]
     duration_minutes: Option<i64>,
     #[serde(default)]
+    until: Option<String>,
+    #[serde(default)]
     days_inactive: Option<i64>,
     #[serde(default)]
     filter: Option<String>,
@@ -297,6 +436,8 @@ struct RawToolCall {
     #[serde(default)]
     path: Option<String>,
     #[serde(default)]
+    offset: Option<i64>,
+    #[serde(default)]
     content: Option<String>,
     #[serde(default)]
     old_string: Option<String>,
@@ -332,6 +473,106 @@ struct RawToolCall {
     // youtube_info field
     #[serde(default)]
     url: Option<String>,
+    // wiki_lookup fields
+    #[serde(default)]
+    query: Option<String>,
+    #[serde(default)]
+    lang: Option<String>,
+    // translate fields
+    #[serde(default)]
+    target_lang: Option<String>,
+    #[serde(default)]
+    source_lang: Option<String>,
+    // delegate fields
+    #[serde(default)]
+    instruction: Option<String>,
+    #[serde(default)]
+    input: Option<String>,
+    #[serde(default)]
+    max_output_chars: Option<i64>,
+    // get_thread fields
+    #[serde(default)]
+    max_depth: Option<i64>,
+    #[serde(default)]
+    include_replies: Option<bool>,
+    // top_messages field
+    #[serde(default)]
+    include_bot: Option<bool>,
+    // ban_user field
+    #[serde(default)]
+    revoke_messages: Option<bool>,
+    // chat_stats field
+    #[serde(default)]
+    days: Option<i64>,
+    // send_later fields
+    #[serde(default)]
+    delay_seconds: Option<i64>,
+    #[serde(default)]
+    scheduled_id: Option<i64>,
+    // follow_up fields
+    #[serde(default)]
+    delay: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    // send_document and send_rubric_docx field
+    #[serde(default)]
+    filename: Option<String>,
+    // send_rubric_docx field
+    #[serde(default)]
+    rubric_text: Option<String>,
+    // read_messages field
+    #[serde(default)]
+    last_n: Option<i64>,
+    // export_transcript and read_messages fields
+    #[serde(default)]
+    from_date: Option<String>,
+    #[serde(default)]
+    to_date: Option<String>,
+    #[serde(default)]
+    format: Option<String>,
+    // invite link fields
+    #[serde(default)]
+    expire_hours: Option<i64>,
+    #[serde(default)]
+    member_limit: Option<i64>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    invite_link: Option<String>,
+    // relay_to_group field
+    #[serde(default)]
+    on_behalf_of_user_id: Option<i64>,
+    // set_slow_mode field
+    #[serde(default)]
+    seconds: Option<i64>,
+    // set_chat_permissions fields
+    #[serde(flatten)]
+    permissions: PermissionFlags,
+    // bulk_delete fields
+    #[serde(default)]
+    since_minutes: Option<i64>,
+    #[serde(default)]
+    contains: Option<String>,
+    // send_location fields
+    #[serde(default)]
+    latitude: Option<f64>,
+    #[serde(default)]
+    longitude: Option<f64>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+/// A message ID field of a tool that doesn't resolve message references.
+fn numeric_id(tool: &str, value: Option<&MessageRef>, field: &str) -> Result<Option<i64>, String> {
+    match value {
+        None => Ok(None),
+        Some(r) => r.id().map(Some).ok_or_else(|| format!(
+            "{} needs a numeric {}; message references like \"last\" only work with send_message and add_reaction",
+            tool, field
+        )),
+    }
 }
 
 impl RawToolCall {
@@ -343,7 +584,7 @@ impl RawToolCall {
                 "send_message" => Ok(ToolCall::SendMessage {
                     chat_id: self.chat_id.ok_or("send_message requires chat_id")?,
                     text: self.text.clone().unwrap_or_default(),
-                    reply_to_message_id: self.reply_to_message_id,
+                    reply_to_message_id: self.reply_to_message_id.clone(),
                 }),
                 "get_user_info" => {
                     if self.user_id.is_none() && self.username.is_none() {
@@ -358,23 +599,37 @@ impl RawToolCall {
                 "query" => Ok(ToolCall::Query {
                     sql: self.sql.clone().ok_or("query requires sql")?,
                 }),
+                "read_messages" => Ok(ToolCall::ReadMessages {
+                    chat_id: self.chat_id,
+                    last_n: self.last_n,
+                    from_date: self.from_date.clone(),
+                    to_date: self.to_date.clone(),
+                    username: self.username.clone(),
+                    limit: self.limit,
+                }),
                 "add_reaction" => Ok(ToolCall::AddReaction {
                     chat_id: self.chat_id.ok_or("add_reaction requires chat_id")?,
-                    message_id: self.message_id.ok_or("add_reaction requires message_id")?,
+                    message_id: self.message_id.clone().ok_or("add_reaction requires message_id")?,
                     emoji: self.emoji.clone().unwrap_or_default(),
                 }),
                 "delete_message" => Ok(ToolCall::DeleteMessage {
                     chat_id: self.chat_id.ok_or("delete_message requires chat_id")?,
-                    message_id: self.message_id.ok_or("delete_message requires message_id")?,
+                    message_id: numeric_id(&self.tool, self.message_id.as_ref(), "message_id")?.ok_or("delete_message requires message_id")?,
                 }),
                 "mute_user" => Ok(ToolCall::MuteUser {
                     chat_id: self.chat_id.ok_or("mute_user requires chat_id")?,
                     user_id: self.user_id.ok_or("mute_user requires user_id")?,
                     duration_minutes: self.duration_minutes.unwrap_or(5),
+                    until: self.until.clone(),
+                }),
+                "unmute_user" => Ok(ToolCall::UnmuteUser {
+                    chat_id: self.chat_id.ok_or("unmute_user requires chat_id")?,
+                    user_id: self.user_id.ok_or("unmute_user requires user_id")?,
                 }),
                 "ban_user" => Ok(ToolCall::BanUser {
                     chat_id: self.chat_id.ok_or("ban_user requires chat_id")?,
                     user_id: self.user_id.ok_or("ban_user requires user_id")?,
+                    revoke_messages: self.revoke_messages.unwrap_or(false),
                 }),
                 "kick_user" => Ok(ToolCall::KickUser {
                     chat_id: self.chat_id.ok_or("kick_user requires chat_id")?,
@@ -383,6 +638,19 @@ impl RawToolCall {
                 "get_chat_admins" => Ok(ToolCall::GetChatAdmins {
                     chat_id: self.chat_id.ok_or("get_chat_admins requires chat_id")?,
                 }),
+                "get_rules" => Ok(ToolCall::GetRules {
+                    chat_id: self.chat_id.ok_or("get_rules requires chat_id")?,
+                }),
+                "create_invite_link" => Ok(ToolCall::CreateInviteLink {
+                    chat_id: self.chat_id.ok_or("create_invite_link requires chat_id")?,
+                    expire_hours: self.expire_hours,
+                    member_limit: self.member_limit,
+                    name: self.name.clone(),
+                }),
+                "revoke_invite_link" => Ok(ToolCall::RevokeInviteLink {
+                    chat_id: self.chat_id.ok_or("revoke_invite_link requires chat_id")?,
+                    invite_link: self.invite_link.clone().ok_or("revoke_invite_link requires invite_link")?,
+                }),
                 "get_members" => Ok(ToolCall::GetMembers {
                     filter: self.filter.clone(),
                     days_inactive: self.days_inactive,
@@ -395,13 +663,25 @@ impl RawToolCall {
                     chat_id: self.chat_id.ok_or("send_photo requires chat_id")?,
                     prompt: self.prompt.clone().ok_or("send_photo requires prompt")?,
                     caption: self.caption.clone(),
-                    reply_to_message_id: self.reply_to_message_id,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
                 }),
                 "send_voice" => Ok(ToolCall::SendVoice {
                     chat_id: self.chat_id.ok_or("send_voice requires chat_id")?,
                     text: self.text.clone().ok_or("send_voice requires text")?,
                     voice: self.voice.clone(),
-                    reply_to_message_id: self.reply_to_message_id,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
+                }),
+                "send_document" => Ok(ToolCall::SendDocument {
+                    chat_id: self.chat_id.ok_or("send_document requires chat_id")?,
+                    filename: self.filename.clone().ok_or("send_document requires filename")?,
+                    content: self.content.clone().ok_or("send_document requires content")?,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
+                }),
+                "send_rubric_docx" => Ok(ToolCall::SendRubricDocx {
+                    chat_id: self.chat_id.ok_or("send_rubric_docx requires chat_id")?,
+                    rubric_text: self.rubric_text.clone().ok_or("send_rubric_docx requires rubric_text")?,
+                    filename: self.filename.clone().ok_or("send_rubric_docx requires filename")?,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
                 }),
                 // Memory tools
                 "create_memory" => Ok(ToolCall::CreateMemory {
@@ -410,6 +690,8 @@ impl RawToolCall {
                 }),
                 "read_memory" => Ok(ToolCall::ReadMemory {
                     path: self.path.clone().ok_or("read_memory requires path")?,
+                    offset: self.offset,
+                    limit: self.limit,
                 }),
                 "edit_memory" => Ok(ToolCall::EditMemory {
                     path: self.path.clone().ok_or("edit_memory requires path")?,
@@ -445,17 +727,105 @@ impl RawToolCall {
                 "cancel_reminder" => Ok(ToolCall::CancelReminder {
                     reminder_id: self.reminder_id.ok_or("cancel_reminder requires reminder_id")?,
                 }),
+                "send_later" => Ok(ToolCall::SendLater {
+                    chat_id: self.chat_id.ok_or("send_later requires chat_id")?,
+                    text: self.text.clone().ok_or("send_later requires text")?,
+                    delay_seconds: self.delay_seconds.ok_or("send_later requires delay_seconds")?,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
+                }),
+                "cancel_send_later" => Ok(ToolCall::CancelSendLater {
+                    scheduled_id: self.scheduled_id.ok_or("cancel_send_later requires scheduled_id")?,
+                }),
+                "follow_up" => Ok(ToolCall::FollowUp {
+                    chat_id: self.chat_id.ok_or("follow_up requires chat_id")?,
+                    delay: self.delay.clone().ok_or("follow_up requires delay")?,
+                    note: self.note.clone().ok_or("follow_up requires note")?,
+                }),
                 "youtube_info" => Ok(ToolCall::YoutubeInfo {
                     url: self.url.clone().ok_or("youtube_info requires url")?,
                 }),
+                "wiki_lookup" => Ok(ToolCall::WikiLookup {
+                    query: self.query.clone().ok_or("wiki_lookup requires query")?,
+                    lang: self.lang.clone(),
+                }),
+                "translate" => Ok(ToolCall::Translate {
+                    text: self.text.clone().ok_or("translate requires text")?,
+                    target_lang: self.target_lang.clone().ok_or("translate requires target_lang")?,
+                    source_lang: self.source_lang.clone(),
+                }),
+                "delegate" => Ok(ToolCall::Delegate {
+                    instruction: self.instruction.clone().ok_or("delegate requires instruction")?,
+                    input: self.input.clone().ok_or("delegate requires input")?,
+                    max_output_chars: self.max_output_chars,
+                }),
+                "get_thread" => Ok(ToolCall::GetThread {
+                    message_id: numeric_id(&self.tool, self.message_id.as_ref(), "message_id")?.ok_or("get_thread requires message_id")?,
+                    max_depth: self.max_depth,
+                    include_replies: self.include_replies.unwrap_or(false),
+                }),
+                "send_dice" => Ok(ToolCall::SendDice {
+                    chat_id: self.chat_id.ok_or("send_dice requires chat_id")?,
+                    emoji: self.emoji.clone().ok_or("send_dice requires emoji")?,
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
+                }),
+                "send_location" => Ok(ToolCall::SendLocation {
+                    chat_id: self.chat_id.ok_or("send_location requires chat_id")?,
+                    latitude: self.latitude.ok_or("send_location requires latitude")?,
+                    longitude: self.longitude.ok_or("send_location requires longitude")?,
+                    title: self.title.clone(),
+                    address: self.address.clone(),
+                    reply_to_message_id: numeric_id(&self.tool, self.reply_to_message_id.as_ref(), "reply_to_message_id")?,
+                }),
+                "chat_stats" => Ok(ToolCall::ChatStats {
+                    chat_id: self.chat_id.ok_or("chat_stats requires chat_id")?,
+                    days: self.days,
+                }),
+                "top_messages" => Ok(ToolCall::TopMessages {
+                    chat_id: self.chat_id.ok_or("top_messages requires chat_id")?,
+                    days: self.days,
+                    limit: self.limit,
+                    include_bot: self.include_bot.unwrap_or(false),
+                }),
+                "export_transcript" => Ok(ToolCall::ExportTranscript {
+                    chat_id: self.chat_id.ok_or("export_transcript requires chat_id")?,
+                    from: self.from_date.clone().ok_or("export_transcript requires from_date")?,
+                    to: self.to_date.clone().ok_or("export_transcript requires to_date")?,
+                    format: self.format.clone(),
+                }),
+                "set_personality" => Ok(ToolCall::SetPersonality {
+                    name: self.name.clone().ok_or("set_personality requires name")?,
+                }),
+                "relay_to_group" => Ok(ToolCall::RelayToGroup {
+                    chat_id: self.chat_id.ok_or("relay_to_group requires chat_id")?,
+                    text: self.text.clone().ok_or("relay_to_group requires text")?,
+                    on_behalf_of_user_id: self.on_behalf_of_user_id.ok_or("relay_to_group requires on_behalf_of_user_id")?,
+                }),
+                "set_slow_mode" => Ok(ToolCall::SetSlowMode {
+                    chat_id: self.chat_id.ok_or("set_slow_mode requires chat_id")?,
+                    seconds: self.seconds.ok_or("set_slow_mode requires seconds")?,
+                }),
+                "set_chat_permissions" => Ok(ToolCall::SetChatPermissions {
+                    chat_id: self.chat_id.ok_or("set_chat_permissions requires chat_id")?,
+                    permissions: self.permissions.clone(),
+                }),
+                "bulk_delete" => Ok(ToolCall::BulkDelete {
+                    chat_id: self.chat_id.ok_or("bulk_delete requires chat_id")?,
+                    user_id: self.user_id,
+                    since_minutes: self.since_minutes.ok_or("bulk_delete requires since_minutes")?,
+                    contains: self.contains.clone(),
+                    limit: self.limit.ok_or("bulk_delete requires limit")?,
+                }),
                 "WebSearch" => Err("WebSearch is a Claude Code built-in tool. Use it BEFORE outputting tool_calls (it runs automatically when you search). Don't include it in the tool_calls array.".to_string()),
-                _ => Err(format!("Unknown tool: '{}'. Available tools: send_message, get_user_info, query, add_reaction, delete_message, mute_user, ban_user, kick_user, get_chat_admins, get_members, import_members, send_photo, send_voice, create_memory, read_memory, edit_memory, list_memories, search_memories, delete_memory, report_bug, youtube_info, set_reminder, list_reminders, cancel_reminder, noop, done", self.tool)),
+                _ => Err(format!("Unknown tool: '{}'. Available tools: send_message, get_user_info, query, read_messages, add_reaction, delete_message, mute_user, unmute_user, ban_user, kick_user, get_chat_admins, get_rules, create_invite_link, revoke_invite_link, get_members, import_members, send_photo, send_voice, send_document, send_rubric_docx, create_memory, read_memory, edit_memory, list_memories, search_memories, delete_memory, report_bug, youtube_info, wiki_lookup, translate, delegate, get_thread, chat_stats, top_messages, send_dice, send_location, set_reminder, list_reminders, cancel_reminder, send_later, cancel_send_later, export_transcript, set_personality, relay_to_group, set_slow_mode, set_chat_permissions, bulk_delete, noop, done", self.tool)),
             }
         };
 
         match parse() {
             Ok(tool_call) => tool_call,
-            Err(message) => {
+            Err(mut message) => {
+                if let Some(example) = validate::example(&self.tool) {
+                    message.push_str(&format!(". Example: {}", example));
+                }
                 warn!("Tool parse error for '{}': {}", self.tool, message);
                 ToolCall::ParseError { message }
             }
@@ -486,14 +856,41 @@ struct Session {
     session_id: Option<String>,
 }
 
-/// Start a Claude Code session (fresh or resumed).
-fn start_session(
-    system_prompt: &str,
-    resume_session: Option<&str>,
-) -> Result<Session, String> {
-    let mut process = spawn_process(resume_session)?;
+/// How sessions are started: the CLI, how long a new process gets to
+/// announce itself, and the prompt for fresh sessions.
+struct Launcher {
+    program: PathBuf,
+    handshake_timeout: Duration,
+    system_prompt: String,
+}
+
+/// A spawned process that hasn't started its session yet.
+struct Spawned {
+    process: Child,
+    resume_session: Option<String>,
+}
+
+impl Launcher {
+    fn spawn(&self, resume_session: Option<String>) -> Result<Spawned, String> {
+        let process = spawn_process(&self.program, resume_session.as_deref())?;
+        Ok(Spawned { process, resume_session })
+    }
+
+    /// Spawn and start a fresh session.
+    fn start_fresh(&self, transcript: &mut SessionTranscript) -> Result<Session, String> {
+        start_session(self, self.spawn(None)?, transcript)
+    }
+}
+
+/// Start a Claude Code session (fresh or resumed) in a spawned process.
+/// On failure the process is killed, and its last stderr lines are part of
+/// the error.
+fn start_session(launcher: &Launcher, spawned: Spawned, transcript: &mut SessionTranscript) -> Result<Session, String> {
+    let Spawned { mut process, resume_session } = spawned;
+    transcript.new_session(resume_session.as_deref());
     let mut stdin = process.stdin.take().ok_or("No stdin")?;
     let stdout = process.stdout.take().ok_or("No stdout")?;
+    let stderr = process.stderr.take().ok_or("No stderr")?;
 
     info!("🚀 Claude Code started (PID {})", process.id());
 
@@ -525,20 +922,75 @@ fn start_session(
             }
         }
     });
+    let (stderr_reader, stderr_tail) = read_stderr(stderr);
+
+    match handshake(launcher, resume_session.is_some(), &mut stdin, &mut out_rx, transcript) {
+        Ok(session_id) => Ok(Session { process, stdin, out_rx, session_id }),
+        Err(e) => {
+            drop(stdin);
+            if let Err(kill_err) = process.kill() {
+                debug!("Failed to kill Claude Code: {}", kill_err);
+            }
+            if let Err(wait_err) = process.wait() {
+                warn!("Failed to wait for Claude Code: {}", wait_err);
+            }
+            if stderr_reader.join().is_err() {
+                warn!("Claude Code stderr reader panicked");
+            }
+            let tail: Vec<String> = stderr_tail.lock().expect("stderr lock poisoned").drain(..).collect();
+            if tail.is_empty() {
+                Err(e)
+            } else {
+                Err(format!("{} (stderr: {})", e, tail.join(" | ")))
+            }
+        }
+    }
+}
 
+/// Log Claude Code's stderr, keeping the last lines for start errors.
+fn read_stderr(stderr: ChildStderr) -> (std::thread::JoinHandle<()>, Arc<Mutex<VecDeque<String>>>) {
+    let tail = Arc::new(Mutex::new(VecDeque::new()));
+    let lines = Arc::clone(&tail);
+    let reader = std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            warn!("Claude Code stderr: {}", line);
+            let mut lines = lines.lock().expect("stderr lock poisoned");
+            if lines.len() == STDERR_TAIL_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    });
+    (reader, tail)
+}
+
+/// Send the first message and wait until the session is ready. Returns the
+/// session ID, if Claude Code reported one.
+fn handshake(
+    launcher: &Launcher,
+    resuming: bool,
+    stdin: &mut ChildStdin,
+    out_rx: &mut mpsc::Receiver<OutputMessage>,
+    transcript: &mut SessionTranscript,
+) -> Result<Option<String>, String> {
     // Send first message
-    let first_message = if resume_session.is_some() {
+    let first_message = if resuming {
         "Session resumed. Ready for new messages.".to_string()
     } else {
-        system_prompt.to_string()
+        launcher.system_prompt.clone()
     };
-    send_message(&mut stdin, &first_message)?;
+    transcript.record(Direction::Out, &first_message);
+    send_message(stdin, &first_message)?;
 
-    // Wait for system message
+    // Wait for system message, which a working process sends right away
+    let deadline = Instant::now() + launcher.handshake_timeout;
     let mut session_id: Option<String> = None;
     loop {
-        match out_rx.blocking_recv() {
-            Some(OutputMessage::System { tools, session_id: sid }) => {
+        match out_rx.try_recv() {
+            Ok(OutputMessage::System { tools, session_id: sid }) => {
                 let allowed = ["StructuredOutput", "WebSearch"];
                 let unexpected: Vec<_> = tools.iter().filter(|t| !allowed.contains(&t.as_str())).collect();
                 if !unexpected.is_empty() {
@@ -547,34 +999,69 @@ fn start_session(
                 }
                 if let Some(sid) = sid {
                     info!("Got session ID: {}", sid);
+                    transcript.set_session_id(&sid);
                     session_id = Some(sid);
                 }
                 info!("🤖 Claude Code session ready");
                 break;
             }
-            Some(_) => continue,
-            None => return Err("Output channel closed".to_string()),
+            Ok(_) => continue,
+            Err(TryRecvError::Disconnected) => return Err("Claude Code exited before the session started".to_string()),
+            Err(TryRecvError::Empty) if Instant::now() >= deadline => {
+                return Err(format!("no system message from Claude Code within {}s", launcher.handshake_timeout.as_secs_f32()));
+            }
+            Err(TryRecvError::Empty) => std::thread::sleep(Duration::from_millis(50)),
         }
     }
 
     // Wait for result of first message
-    let (_, new_sid) = wait_for_result(&mut out_rx)?;
+    let (_, new_sid) = wait_for_result(out_rx, transcript)?;
     if let Some(sid) = new_sid {
+        transcript.set_session_id(&sid);
         session_id = Some(sid);
     }
     info!("First message processed, ready for chat");
-
-    Ok(Session { process, stdin, out_rx, session_id })
+    Ok(session_id)
 }
 
 fn worker_loop(
-    system_prompt: String,
-    resume_session: Option<String>,
+    launcher: Launcher,
+    first: Spawned,
     session_file: Option<PathBuf>,
+    mut transcript: SessionTranscript,
     mut msg_rx: mpsc::Receiver<WorkerMessage>,
     resp_tx: mpsc::Sender<Response>,
+    started_tx: oneshot::Sender<SessionStart>,
 ) -> Result<(), String> {
-    let mut session = start_session(&system_prompt, resume_session.as_deref())?;
+    let resuming = first.resume_session.is_some();
+    let (session, status) = match start_session(&launcher, first, &mut transcript) {
+        Ok(session) => (Ok(session), if resuming { SessionStart::Resumed } else { SessionStart::Fresh }),
+        Err(e) if resuming => {
+            // A stale or broken session: start over rather than leaving the bot without Claude
+            warn!("🔄 Failed to resume session ({}) - starting a fresh one", e);
+            if let Some(ref path) = session_file
+                && let Err(e) = std::fs::remove_file(path)
+            {
+                warn!("Failed to delete session file: {}", e);
+            }
+            (launcher.start_fresh(&mut transcript), SessionStart::ResumeFailed(e))
+        }
+        Err(e) => (Err(e), SessionStart::Fresh),
+    };
+    let mut session = match session {
+        Ok(session) => {
+            if started_tx.send(status).is_err() {
+                debug!("Nobody waiting for the session start status");
+            }
+            session
+        }
+        Err(e) => {
+            if started_tx.send(SessionStart::Failed(e.clone())).is_err() {
+                debug!("Nobody waiting for the session start status");
+            }
+            return Err(e);
+        }
+    };
 
     // Save session ID if we have one
     if let (Some(sid), Some(path)) = (&session.session_id, &session_file) {
@@ -585,18 +1072,21 @@ fn worker_loop(
     while let Some(msg) = msg_rx.blocking_recv() {
         match msg {
             WorkerMessage::UserMessage(content) => {
+                transcript.record(Direction::Out, &content);
                 send_message(&mut session.stdin, &content)?;
             }
             WorkerMessage::ImageMessage(text, image_data, media_type) => {
+                transcript.record(Direction::Out, &format!("{}\n[image: {}, {} bytes]", text, media_type, image_data.len()));
                 send_message_with_image(&mut session.stdin, &text, &image_data, &media_type)?;
             }
             WorkerMessage::ToolResults(results) => {
                 let content = format_tool_results(&results);
+                transcript.record(Direction::Out, &content);
                 send_message(&mut session.stdin, &content)?;
             }
         }
 
-        let result = wait_for_result(&mut session.out_rx);
+        let result = wait_for_result(&mut session.out_rx, &mut transcript);
 
         // Handle session overflow by restarting with fresh session
         if let Err(ref e) = result
@@ -617,7 +1107,7 @@ fn worker_loop(
             }
 
             // Start fresh session (no resume)
-            session = start_session(&system_prompt, None)?;
+            session = launcher.start_fresh(&mut transcript)?;
 
             if let (Some(sid), Some(path)) = (&session.session_id, &session_file) {
                 save_session_id(path, sid);
@@ -625,7 +1115,7 @@ fn worker_loop(
 
             // Send an empty response for the failed message
             // The caller will see 0 tool calls and handle it
-            let empty = Response { tool_calls: vec![], compacted: false };
+            let empty = Response { tool_calls: vec![], compacted: false, cost_usd: 0.0, prose: None };
             if resp_tx.blocking_send(empty).is_err() {
                 break;
             }
@@ -638,6 +1128,7 @@ fn worker_loop(
         if let Some(sid) = new_sid
             && session.session_id.as_ref() != Some(&sid)
         {
+            transcript.set_session_id(&sid);
             session.session_id = Some(sid.clone());
             if let Some(ref path) = session_file {
                 save_session_id(path, &sid);
@@ -655,13 +1146,13 @@ fn worker_loop(
     Ok(())
 }
 
-fn spawn_process(resume_session: Option<&str>) -> Result<Child, String> {
+fn spawn_process(program: &Path, resume_session: Option<&str>) -> Result<Child, String> {
     let schema: serde_json::Value = serde_json::from_str(TOOL_CALLS_SCHEMA)
         .map_err(|e| format!("Bad schema: {}", e))?;
     let schema_str = serde_json::to_string(&schema)
         .map_err(|e| format!("Failed to serialize schema: {}", e))?;
 
-    let mut cmd = Command::new("claude");
+    let mut cmd = Command::new(program);
     cmd.args([
         "--print",
         "--input-format", "stream-json",
@@ -683,7 +1174,7 @@ fn spawn_process(resume_session: Option<&str>) -> Result<Child, String> {
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|e| format!("Spawn failed: {}", e))
+        .map_err(|e| format!("Failed to run {}: {}", program.display(), e))
 }
 
 fn send_message(stdin: &mut ChildStdin, content: &str) -> Result<(), String> {
@@ -731,8 +1222,12 @@ fn send_content(stdin: &mut ChildStdin, content: MessageContent) -> Result<(), S
 }
 
 /// Wait for result, return (Response, Option<session_id>)
-fn wait_for_result(out_rx: &mut mpsc::Receiver<OutputMessage>) -> Result<(Response, Option<String>), String> {
+fn wait_for_result(
+    out_rx: &mut mpsc::Receiver<OutputMessage>,
+    transcript: &mut SessionTranscript,
+) -> Result<(Response, Option<String>), String> {
     let mut compacted = false;
+    let mut assistant_text: Vec<String> = Vec::new();
 
     loop {
         match out_rx.blocking_recv() {
@@ -748,6 +1243,7 @@ fn wait_for_result(out_rx: &mut mpsc::Receiver<OutputMessage>) -> Result<(Respon
                     // Log and check text content
                     for block in &msg.content {
                         if let ContentBlock::Text { text } = block {
+                            transcript.record(Direction::In, text);
                             // Log first 200 chars of assistant text for debugging
                             let preview: String = text.chars().take(200).collect();
                             info!("📝 Assistant: {}", preview);
@@ -756,29 +1252,37 @@ fn wait_for_result(out_rx: &mut mpsc::Receiver<OutputMessage>) -> Result<(Respon
                                 error!("Session context overflow: {}", text);
                                 return Err("REQUEST_TOO_LARGE".to_string());
                             }
+                            assistant_text.push(text.clone());
                         }
                     }
                 }
             }
             Some(OutputMessage::Result { total_cost_usd, structured_output, session_id }) => {
-                info!("🤖 Response (cost: ${:.4})", total_cost_usd);
-
-                let tool_calls = match structured_output {
-                    Some(so) => {
-                        so.tool_calls
-                            .iter()
+                debug!("🤖 Response (cost: ${:.4})", total_cost_usd);
+                let raw = structured_output.unwrap_or_default();
+                transcript.record(Direction::In, &format!("result (cost ${:.4}): {}", total_cost_usd, raw));
+
+                let (tool_calls, prose) = match raw_tool_calls(raw) {
+                    Some(calls) => {
+                        let calls = calls
+                            .into_iter()
                             .enumerate()
-                            .map(|(i, tc)| ToolCallWithId {
+                            .map(|(i, raw)| ToolCallWithId {
                                 id: format!("tool_{}", i),
-                                call: tc.to_tool_call(),
+                                call: parse_tool_call(raw),
                             })
-                            .collect()
+                            .collect();
+                        (calls, None)
+                    }
+                    None => {
+                        let text = assistant_text.join("\n");
+                        warn!("No usable structured output ({} chars of assistant text)", text.len());
+                        (Vec::new(), Some(text.chars().take(PROSE_SNIPPET_CHARS).collect()))
                     }
-                    None => Vec::new()
                 };
 
-                info!("Got {} tool call(s){}", tool_calls.len(), if compacted { " (after compaction)" } else { "" });
-                return Ok((Response { tool_calls, compacted }, session_id));
+                debug!("Got {} tool call(s){}", tool_calls.len(), if compacted { " (after compaction)" } else { "" });
+                return Ok((Response { tool_calls, compacted, cost_usd: total_cost_usd, prose }, session_id));
             }
             Some(OutputMessage::System { .. }) => continue,
             Some(OutputMessage::Other) => continue,
@@ -800,3 +1304,154 @@ fn format_tool_results(results: &[ToolResult]) -> String {
     }
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed Claude Code output lines through `wait_for_result`.
+    fn result_of(lines: &[&str]) -> Response {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut transcript = SessionTranscript::new(dir.path().to_path_buf(), false, 1 << 20, Vec::new());
+        let (tx, mut rx) = mpsc::channel(16);
+        for line in lines {
+            tx.try_send(serde_json::from_str::<OutputMessage>(line).unwrap()).unwrap();
+        }
+        wait_for_result(&mut rx, &mut transcript).unwrap().0
+    }
+
+    /// A stand-in for the CLI: rejects session "stale", hangs on session
+    /// "hung", and otherwise answers every message with an empty result.
+    const FAKE_CLAUDE: &str = r#"#!/bin/sh
+resume=""
+while [ $# -gt 0 ]; do
+  if [ "$1" = "--resume" ]; then resume="$2"; fi
+  shift
+done
+case "$resume" in
+  stale) echo "No conversation found with session ID: stale" >&2; exit 1 ;;
+  hung) while read -r line; do :; done; exit 0 ;;
+  "") sid="fresh-session" ;;
+  *) sid="$resume" ;;
+esac
+while read -r line; do
+  echo '{"type":"system","tools":["StructuredOutput","WebSearch"],"session_id":"'"$sid"'"}'
+  echo '{"type":"result","total_cost_usd":0,"structured_output":{"tool_calls":[]},"session_id":"'"$sid"'"}'
+done
+"#;
+
+    /// Start against the fake CLI with `saved` as the saved session ID.
+    async fn start_fake(dir: &Path, saved: Option<&str>) -> (Result<ClaudeCode, String>, PathBuf) {
+        use std::os::unix::fs::PermissionsExt;
+        let program = dir.join("claude");
+        std::fs::write(&program, FAKE_CLAUDE).unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let session_file = dir.join("session_id");
+        if let Some(sid) = saved {
+            std::fs::write(&session_file, sid).unwrap();
+        }
+        let launcher = Launcher { program, handshake_timeout: Duration::from_millis(500), system_prompt: "prompt".to_string() };
+        let transcript = SessionTranscript::new(dir.join("transcripts"), false, 1 << 20, Vec::new());
+        (ClaudeCode::launch(launcher, Some(session_file.clone()), transcript), session_file)
+    }
+
+    async fn start_status(claude: &mut ClaudeCode) -> SessionStart {
+        claude.take_start_status().unwrap().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_resume_fallback() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (claude, session_file) = start_fake(dir.path(), None).await;
+        let mut claude = claude.unwrap();
+        assert_eq!(start_status(&mut claude).await, SessionStart::Fresh);
+        assert!(claude.send_message("hi".to_string()).await.unwrap().tool_calls.is_empty());
+        assert_eq!(load_session_id(&session_file).as_deref(), Some("fresh-session"));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut claude = start_fake(dir.path(), Some("known")).await.0.unwrap();
+        assert_eq!(start_status(&mut claude).await, SessionStart::Resumed);
+
+        // A session the CLI rejects is replaced, and its stderr explains why
+        let dir = tempfile::TempDir::new().unwrap();
+        let (claude, session_file) = start_fake(dir.path(), Some("stale")).await;
+        let mut claude = claude.unwrap();
+        let SessionStart::ResumeFailed(e) = start_status(&mut claude).await else { panic!("stale session resumed") };
+        assert!(e.ends_with("(stderr: No conversation found with session ID: stale)"), "{}", e);
+        assert!(claude.send_message("hi".to_string()).await.is_ok());
+        assert_eq!(load_session_id(&session_file).as_deref(), Some("fresh-session"));
+
+        // So is one that never gets going
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut claude = start_fake(dir.path(), Some("hung")).await.0.unwrap();
+        let SessionStart::ResumeFailed(e) = start_status(&mut claude).await else { panic!("hung session resumed") };
+        assert!(e.starts_with("no system message from Claude Code within"), "{}", e);
+        assert!(claude.send_message("hi".to_string()).await.is_ok());
+    }
+
+    #[test]
+    fn test_cannot_start() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let launcher = Launcher { program: dir.path().join("missing"), handshake_timeout: HANDSHAKE_TIMEOUT, system_prompt: String::new() };
+        let transcript = SessionTranscript::new(dir.path().join("transcripts"), false, 1 << 20, Vec::new());
+        let error = ClaudeCode::launch(launcher, None, transcript).err().unwrap();
+        assert!(error.starts_with("Failed to run"), "{}", error);
+    }
+
+    #[test]
+    fn test_prose_instead_of_tool_json() {
+        let response = result_of(&[
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Sure, I'll tell them "},{"type":"tool_use"}]}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"it's at noon."}]}}"#,
+            r#"{"type":"result","total_cost_usd":0.01}"#,
+        ]);
+        assert!(response.tool_calls.is_empty());
+        assert_eq!(response.prose.as_deref(), Some("Sure, I'll tell them \nit's at noon."));
+
+        // A structured output without a tool_calls array counts as missing
+        let response = result_of(&[
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"x"}]}}"#,
+            r#"{"type":"result","structured_output":{"tool_calls":"send_message"}}"#,
+        ]);
+        assert_eq!(response.prose.as_deref(), Some("x"));
+
+        let long = format!(r#"{{"type":"assistant","message":{{"content":[{{"type":"text","text":"{}"}}]}}}}"#, "y".repeat(2000));
+        let response = result_of(&[&long, r#"{"type":"result"}"#]);
+        assert_eq!(response.prose.unwrap().len(), PROSE_SNIPPET_CHARS);
+    }
+
+    #[test]
+    fn test_tool_json_has_no_prose() {
+        let response = result_of(&[
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"thinking out loud"}]}}"#,
+            r#"{"type":"result","structured_output":{"tool_calls":[{"tool":"done"}]}}"#,
+        ]);
+        assert_eq!(response.tool_calls.len(), 1);
+        assert!(matches!(response.tool_calls[0].call, ToolCall::Done));
+        assert!(response.prose.is_none());
+    }
+
+    #[test]
+    fn test_chat_settings_tools_parse() {
+        let call = parse_tool_call(serde_json::json!({"tool": "set_slow_mode", "chat_id": "-100", "seconds": "45"}));
+        assert!(matches!(call, ToolCall::SetSlowMode { chat_id: -100, seconds: 45 }), "{:?}", call);
+
+        let call = parse_tool_call(serde_json::json!({"tool": "set_chat_permissions", "chat_id": -100, "can_send_media": false}));
+        let ToolCall::SetChatPermissions { chat_id: -100, permissions } = call else {
+            panic!("expected set_chat_permissions, got {:?}", call);
+        };
+        assert_eq!(permissions, PermissionFlags { can_send_media: Some(false), ..Default::default() });
+    }
+
+    #[test]
+    fn test_bulk_delete_parse() {
+        let call = parse_tool_call(serde_json::json!({"tool": "bulk_delete", "chat_id": -100, "since_minutes": "30", "contains": "crypto", "limit": 50}));
+        let ToolCall::BulkDelete { chat_id: -100, user_id: None, since_minutes: 30, contains: Some(contains), limit: 50 } = call else {
+            panic!("expected bulk_delete, got {:?}", call);
+        };
+        assert_eq!(contains, "crypto");
+
+        let call = parse_tool_call(serde_json::json!({"tool": "bulk_delete", "chat_id": -100, "since_minutes": 30}));
+        assert!(matches!(&call, ToolCall::ParseError { message } if message.starts_with("bulk_delete requires limit")), "{:?}", call);
+    }
+}
@@ -0,0 +1,748 @@
+//! Slash commands: the owner's controls (`/status`, `/sys`, `/pause`, ...)
+//! and lookups for the owner and trusted users (`/stats`, `/whois`, ...).
+//!
+//! Each command declares its name, the arguments it takes, who may run it,
+//! whether it works in groups and an async handler. `parse` matches a
+//! message against `COMMANDS` and checks the sender's permission; a message
+//! that isn't a known command, or comes from someone who may not run it,
+//! goes through the normal flow. `/help` and the Telegram command menu are
+//! built from the table, so adding a command is one entry in it.
+
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use serde_json::Value;
+use teloxide::types::BotCommand;
+use tracing::{info, warn};
+
+use super::backup;
+use super::cost_attribution;
+use super::engine::ChatbotEngine;
+use super::lookups::{self, CommandChat};
+use super::message::ChatMessage;
+use super::session_transcript;
+use super::tools::ToolCall;
+use super::whisper::Whisper;
+
+/// Who may run a command. Ordered, so an owner can run trusted commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Anyone,
+    /// Users in `trusted_dm_users`.
+    Trusted,
+    Owner,
+}
+
+/// The arguments a command takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgSpec {
+    None,
+    /// `required` words, then up to `optional` more. "Double quotes" make
+    /// one argument of several words.
+    Words { required: usize, optional: usize },
+    /// Everything after the command, verbatim (newlines, markup). Required.
+    Text,
+}
+
+/// What a handler can reach.
+pub struct CommandContext<'a> {
+    pub engine: &'a ChatbotEngine,
+    pub whisper: Option<&'a Whisper>,
+    /// The sender's permission level.
+    pub caller: Permission,
+    /// Who sent the command.
+    pub user_id: i64,
+    /// Where it was sent.
+    pub chat: CommandChat,
+    /// Where the session transcripts are, for `/transcript tail`.
+    pub transcripts: &'a Path,
+    /// Re-read the output guard from the config file, for `/reload guard`.
+    pub reload_guard: &'a (dyn Fn() -> String + Sync),
+}
+
+/// The reply to send, if any.
+pub type Reply<'a> = Pin<Box<dyn Future<Output = Option<String>> + Send + 'a>>;
+
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub args: ArgSpec,
+    pub permission: Permission,
+    /// Also answered in groups; otherwise only in DMs.
+    pub in_groups: bool,
+    pub handler: for<'a> fn(&'a CommandContext<'a>, Vec<String>) -> Reply<'a>,
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "/help [command]",
+        description: "List the commands you can use, or show one's usage",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Trusted,
+        in_groups: false,
+        handler: help,
+    },
+    Command {
+        name: "status",
+        usage: "/status",
+        description: "Whisper, queue, last turn, caches and maintenance",
+        args: ArgSpec::None,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: status,
+    },
+    Command {
+        name: "sys",
+        usage: "/sys <text>",
+        description: "Send Claude a system message",
+        args: ArgSpec::Text,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: sys,
+    },
+    Command {
+        name: "sysquiet",
+        usage: "/sysquiet <text>",
+        description: "Send Claude a system message to act on without replying",
+        args: ArgSpec::Text,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: sys_quiet,
+    },
+    Command {
+        name: "setrules",
+        usage: "/setrules [chat_id] <rules>",
+        description: "Set a group's rules (the primary group's without a chat ID), which Claude cites when moderating",
+        args: ArgSpec::Text,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: set_rules,
+    },
+    Command {
+        name: "recap",
+        usage: "/recap [hours]",
+        description: "Replay the stored messages of the last hours into Claude (6 by default, up to 168)",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: recap,
+    },
+    Command {
+        name: "pause",
+        usage: "/pause",
+        description: "Maintenance mode: Claude sees and answers nothing until /resume",
+        args: ArgSpec::None,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: pause,
+    },
+    Command {
+        name: "resume",
+        usage: "/resume [recap]",
+        description: "End maintenance mode; with recap, replay what was missed first",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: resume,
+    },
+    Command {
+        name: "costs",
+        usage: "/costs [days]",
+        description: "Claude spend by chat and user over the last days (7 by default, up to 365)",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: costs,
+    },
+    Command {
+        name: "backup",
+        usage: "/backup now",
+        description: "Back up the database and memories right away",
+        args: ArgSpec::Words { required: 1, optional: 0 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: backup_now,
+    },
+    Command {
+        name: "personality",
+        usage: "/personality [name]",
+        description: "Show the active personality, or switch to another (default for none)",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: personality,
+    },
+    Command {
+        name: "transcript",
+        usage: "/transcript tail",
+        description: "The last entries of the Claude Code session transcript",
+        args: ArgSpec::Words { required: 1, optional: 0 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: transcript_tail,
+    },
+    Command {
+        name: "errors",
+        usage: "/errors",
+        description: "Failed tool calls of the last 24h",
+        args: ArgSpec::None,
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: errors,
+    },
+    Command {
+        name: "reload",
+        usage: "/reload whisper|guard",
+        description: "Reload the Whisper model, or re-read forbidden_output_patterns",
+        args: ArgSpec::Words { required: 1, optional: 0 },
+        permission: Permission::Owner,
+        in_groups: false,
+        handler: reload,
+    },
+    Command {
+        name: "reminders",
+        usage: "/reminders",
+        description: "Active reminders and scheduled messages",
+        args: ArgSpec::None,
+        permission: Permission::Trusted,
+        in_groups: true,
+        handler: reminders,
+    },
+    Command {
+        name: "stats",
+        usage: "/stats [days]",
+        description: "Chat activity stats (1-90 days)",
+        args: ArgSpec::Words { required: 0, optional: 1 },
+        permission: Permission::Trusted,
+        in_groups: true,
+        handler: stats,
+    },
+    Command {
+        name: "members",
+        usage: "/members [all|active|inactive|never_posted|left|banned] [days inactive]",
+        description: "Known members by activity",
+        args: ArgSpec::Words { required: 0, optional: 2 },
+        permission: Permission::Trusted,
+        in_groups: true,
+        handler: members,
+    },
+    Command {
+        name: "whois",
+        usage: "/whois @username|user_id",
+        description: "Look up a user",
+        args: ArgSpec::Words { required: 1, optional: 0 },
+        permission: Permission::Trusted,
+        in_groups: true,
+        handler: whois,
+    },
+];
+
+/// Default window for `/recap` without an argument.
+const DEFAULT_RECAP_HOURS: u32 = 6;
+
+/// Longest `/recap` window.
+const MAX_RECAP_HOURS: u32 = 24 * 7;
+
+/// Entries shown by `/transcript tail`.
+const TRANSCRIPT_TAIL: usize = 20;
+
+/// Split "/name@bot rest" into the name, the bot it's addressed to and the
+/// rest. None if the text isn't a command.
+fn split_command(text: &str) -> Option<(&str, Option<&str>, &str)> {
+    let text = text.trim_start().strip_prefix('/')?;
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    let (head, rest) = text.split_at(end);
+    Some(match head.split_once('@') {
+        Some((name, addressee)) => (name, Some(addressee), rest),
+        None => (head, None, rest),
+    })
+}
+
+/// Split arguments on whitespace; "double quotes" group words.
+fn split_words(rest: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = rest.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => return Err("Unclosed quote".to_string()),
+                }
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+fn parse_args(spec: ArgSpec, rest: &str) -> Result<Vec<String>, String> {
+    match spec {
+        ArgSpec::None if rest.trim().is_empty() => Ok(Vec::new()),
+        ArgSpec::None => Err("Takes no arguments".to_string()),
+        ArgSpec::Text => match rest.trim() {
+            "" => Err("Missing text".to_string()),
+            text => Ok(vec![text.to_string()]),
+        },
+        ArgSpec::Words { required, optional } => {
+            let words = split_words(rest)?;
+            if words.len() < required {
+                Err("Missing argument".to_string())
+            } else if words.len() > required + optional {
+                Err("Too many arguments".to_string())
+            } else {
+                Ok(words)
+            }
+        }
+    }
+}
+
+/// `error` followed by the usage of command `name`, as a reply.
+fn usage_error(name: &str, error: impl std::fmt::Display) -> String {
+    let usage = COMMANDS.iter().find(|c| c.name == name).map_or("", |c| c.usage);
+    format!("{}\nUsage: {}", error, usage)
+}
+
+/// Match `text` against `COMMANDS`. None if it isn't one of them, is
+/// addressed to another bot, `caller` may not run it or it doesn't work in
+/// groups and was sent in one. Otherwise the command and its arguments, or
+/// the error and usage to reply with.
+pub fn parse(
+    text: &str,
+    caller: Permission,
+    in_group: bool,
+    bot_username: Option<&str>,
+) -> Option<(&'static Command, Result<Vec<String>, String>)> {
+    let (name, addressee, rest) = split_command(text)?;
+    if let Some(addressee) = addressee
+        && !bot_username.is_some_and(|b| b.eq_ignore_ascii_case(addressee))
+    {
+        return None;
+    }
+    let command = COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(name))?;
+    if caller < command.permission || (in_group && !command.in_groups) {
+        return None;
+    }
+    let args = parse_args(command.args, rest).map_err(|e| usage_error(command.name, e));
+    Some((command, args))
+}
+
+/// The Telegram command menu for trusted users: the commands they can run
+/// in DMs, or in groups with `in_group`.
+pub fn bot_commands(in_group: bool) -> Vec<BotCommand> {
+    COMMANDS.iter()
+        .filter(|c| c.permission <= Permission::Trusted && (c.in_groups || !in_group))
+        .map(|c| BotCommand::new(c.name, c.description))
+        .collect()
+}
+
+fn help<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move { Some(help_text(ctx.caller, args.first().map(String::as_str))) })
+}
+
+/// The commands `caller` may run, or one command's usage.
+fn help_text(caller: Permission, command: Option<&str>) -> String {
+    let mut allowed = COMMANDS.iter().filter(|c| caller >= c.permission);
+    match command {
+        None => {
+            let lines: Vec<String> = allowed.map(|c| format!("{} — {}", c.usage, c.description)).collect();
+            format!("Commands:\n{}", lines.join("\n"))
+        }
+        Some(name) => {
+            let name = name.trim_start_matches('/');
+            match allowed.find(|c| c.name.eq_ignore_ascii_case(name)) {
+                Some(c) => format!("{}\n{}", c.usage, c.description),
+                None => format!("No command /{}. Send /help for the list.", name),
+            }
+        }
+    }
+}
+
+fn status<'a>(ctx: &'a CommandContext<'a>, _args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        let engine = ctx.engine;
+        let whisper = match ctx.whisper {
+            Some(w) => w.status().to_string(),
+            None => "not configured".to_string(),
+        };
+        let last_turn = engine.last_turn_summary().unwrap_or_else(|| "none yet".to_string());
+        let (depth, busy) = engine.queue_status().await;
+        let queue = format!("{} pending{}", depth, if busy { ", turn in progress" } else { "" });
+        Some(format!(
+            "Whisper: {}\nQueue: {}\nLast turn: {}\nProse replies: {} since start\nCache: {}\nMaintenance: {}",
+            whisper, queue, last_turn, engine.prose_reply_count(), engine.cache_summary(), engine.maintenance_summary()
+        ))
+    })
+}
+
+fn sys<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(send_sys(ctx, SysCommand { text: args.concat(), quiet: false }))
+}
+
+fn sys_quiet<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(send_sys(ctx, SysCommand { text: args.concat(), quiet: true }))
+}
+
+async fn send_sys(ctx: &CommandContext<'_>, command: SysCommand) -> Option<String> {
+    info!("📢 Owner system message{}: {}", if command.quiet { " (quiet)" } else { "" }, command.text);
+    ctx.engine.handle_message(ChatMessage::system(command.message_text())).await;
+    None
+}
+
+fn set_rules<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move { Some(ctx.engine.set_rules(&args.concat()).unwrap_or_else(|e| e)) })
+}
+
+/// A number of `unit` in 1..=`max`, or `default` when not given.
+fn count_arg(arg: Option<&String>, default: u32, max: u32, unit: &str) -> Result<u32, String> {
+    match arg {
+        None => Ok(default),
+        Some(arg) => arg.parse::<u32>().ok()
+            .filter(|n| (1..=max).contains(n))
+            .ok_or_else(|| format!("Invalid number of {} '{}' (1-{})", unit, arg, max)),
+    }
+}
+
+fn recap<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        let hours = match count_arg(args.first(), DEFAULT_RECAP_HOURS, MAX_RECAP_HOURS, "hours") {
+            Ok(hours) => hours,
+            Err(e) => return Some(usage_error("recap", e)),
+        };
+        Some(match ctx.engine.replay_context(hours).await {
+            Ok(r) if r.messages == 0 => format!("No messages in the last {}h to replay.", hours),
+            Ok(r) => format!("Replayed {} message(s) (~{} tokens) from the last {}h.", r.messages, r.approx_tokens, hours),
+            Err(e) => {
+                warn!("Context replay failed: {}", e);
+                format!("Replay failed: {}", e)
+            }
+        })
+    })
+}
+
+fn pause<'a>(ctx: &'a CommandContext<'a>, _args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        Some(ctx.engine.pause().unwrap_or_else(|e| {
+            warn!("Pause failed: {}", e);
+            format!("Pause failed: {}", e)
+        }))
+    })
+}
+
+fn resume<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        let recap = match args.first().map(String::as_str) {
+            None => false,
+            Some("recap") => true,
+            Some(other) => return Some(usage_error("resume", format!("Unknown option '{}'", other))),
+        };
+        Some(ctx.engine.resume(recap).await.unwrap_or_else(|e| {
+            warn!("Resume failed: {}", e);
+            format!("Resume failed: {}", e)
+        }))
+    })
+}
+
+fn costs<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        let days = match count_arg(args.first(), cost_attribution::DEFAULT_DAYS, cost_attribution::MAX_DAYS, "days") {
+            Ok(days) => days,
+            Err(e) => return Some(usage_error("costs", e)),
+        };
+        Some(ctx.engine.cost_report(days).await.unwrap_or_else(|e| {
+            warn!("Reading costs failed: {}", e);
+            format!("Failed to read costs: {}", e)
+        }))
+    })
+}
+
+fn backup_now<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        if args.concat() != "now" {
+            return Some(usage_error("backup", format!("Unknown option '{}'", args.concat())));
+        }
+        let result = ctx.engine.run_backup().await;
+        if let Err(e) = &result {
+            warn!("Manual backup failed: {}", e);
+        }
+        Some(backup::owner_message(&result))
+    })
+}
+
+fn personality<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        let Some(name) = args.first() else {
+            return Some(ctx.engine.personality_summary());
+        };
+        let target = (name != "default").then_some(name.as_str());
+        Some(match ctx.engine.set_personality(target).await {
+            Ok(()) => format!("🎭 Personality switched to {}.", name),
+            Err(e) => {
+                warn!("Personality switch failed: {}", e);
+                e
+            }
+        })
+    })
+}
+
+fn transcript_tail<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        if args.concat() != "tail" {
+            return Some(usage_error("transcript", format!("Unknown option '{}'", args.concat())));
+        }
+        Some(session_transcript::tail(ctx.transcripts, TRANSCRIPT_TAIL).unwrap_or_else(|e| {
+            warn!("Reading the session transcript failed: {}", e);
+            format!("Failed to read the transcript: {}", e)
+        }))
+    })
+}
+
+fn errors<'a>(ctx: &'a CommandContext<'a>, _args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        Some(match ctx.engine.tool_error_digest().await {
+            Ok(Some(digest)) => digest,
+            Ok(None) => "No tool errors in the last 24h.".to_string(),
+            Err(e) => {
+                warn!("Reading tool errors failed: {}", e);
+                format!("Failed to read tool errors: {}", e)
+            }
+        })
+    })
+}
+
+fn reload<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(async move {
+        Some(match args.concat().as_str() {
+            "whisper" => match ctx.whisper {
+                Some(w) => format!("Whisper: {}", w.reload().await),
+                None => "Whisper is not configured.".to_string(),
+            },
+            "guard" => (ctx.reload_guard)(),
+            other => usage_error("reload", format!("Unknown option '{}'", other)),
+        })
+    })
+}
+
+fn reminders<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(lookup(ctx, "reminders", lookups::reminders_call(&args, ctx.chat), lookups::render_reminders))
+}
+
+fn stats<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(lookup(ctx, "stats", lookups::stats_call(&args, ctx.chat), lookups::render_stats))
+}
+
+fn members<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(lookup(ctx, "members", lookups::members_call(&args, ctx.chat), lookups::render_members))
+}
+
+fn whois<'a>(ctx: &'a CommandContext<'a>, args: Vec<String>) -> Reply<'a> {
+    Box::pin(lookup(ctx, "whois", lookups::whois_call(&args, ctx.chat), lookups::render_user))
+}
+
+/// Run a lookup's tool call, as the sender, and render its JSON output.
+async fn lookup(
+    ctx: &CommandContext<'_>,
+    name: &str,
+    call: Result<ToolCall, String>,
+    render: fn(&Value) -> String,
+) -> Option<String> {
+    let call = match call {
+        Ok(call) => call,
+        Err(e) => return Some(usage_error(name, e)),
+    };
+    Some(match ctx.engine.run_tool(call, ctx.user_id, ctx.chat.chat_id).await {
+        Ok(Some(output)) => match serde_json::from_str(&output) {
+            Ok(value) => render(&value),
+            Err(_) => output,
+        },
+        Ok(None) => "No result.".to_string(),
+        Err(e) => {
+            warn!("/{} failed: {}", name, e);
+            format!("/{} failed: {}", name, e)
+        }
+    })
+}
+
+/// Owner `/sys <text>` or `/sysquiet <text>`: inject a system message at runtime.
+#[derive(Debug, PartialEq)]
+pub struct SysCommand {
+    pub text: String,
+    /// Ask Claude to act on the message without responding visibly.
+    pub quiet: bool,
+}
+
+/// Appended to `/sysquiet` messages.
+const SYS_QUIET_NOTE: &str = "(Do not respond visibly to this message. Act on it silently if needed.)";
+
+impl SysCommand {
+    /// Text delivered to Claude.
+    pub fn message_text(&self) -> String {
+        if self.quiet {
+            format!("{}\n\n{}", self.text, SYS_QUIET_NOTE)
+        } else {
+            self.text.clone()
+        }
+    }
+}
+
+/// Parse an owner `/sys` or `/sysquiet` sent in a group, where it isn't
+/// routed through `COMMANDS`. None if the text isn't one.
+pub fn parse_sys_command(text: &str) -> Option<Result<SysCommand, String>> {
+    let (name, _, rest) = split_command(text)?;
+    let quiet = match name {
+        "sys" => false,
+        "sysquiet" => true,
+        _ => return None,
+    };
+    match parse_args(ArgSpec::Text, rest) {
+        Ok(args) => Some(Ok(SysCommand { text: args.concat(), quiet })),
+        Err(_) => Some(Err(format!("Usage: /{} <text>", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str, caller: Permission) -> Option<Result<Vec<String>, String>> {
+        parse(text, caller, false, Some("claudima_bot")).map(|(_, args)| args)
+    }
+
+    #[test]
+    fn test_arg_parsing() {
+        assert_eq!(split_words(r#"one "two words" three"#), Ok(vec!["one".to_string(), "two words".to_string(), "three".to_string()]));
+        assert_eq!(split_words(r#""" x"#), Ok(vec![String::new(), "x".to_string()]));
+        assert_eq!(split_words("  "), Ok(vec![]));
+        assert_eq!(split_words(r#"say "unclosed"#), Err("Unclosed quote".to_string()));
+
+        assert_eq!(args("/help", Permission::Owner), Some(Ok(vec![])));
+        assert_eq!(args(r#"/help "status""#, Permission::Owner), Some(Ok(vec!["status".to_string()])));
+        let err = args("/help sys status", Permission::Owner).unwrap().unwrap_err();
+        assert_eq!(err, "Too many arguments\nUsage: /help [command]");
+        assert_eq!(args("/status now", Permission::Owner), Some(Err("Takes no arguments\nUsage: /status".to_string())));
+    }
+
+    #[test]
+    fn test_text_argument() {
+        assert_eq!(args("/sys line one\n<b>two</b>", Permission::Owner), Some(Ok(vec!["line one\n<b>two</b>".to_string()])));
+        assert_eq!(args("/sysquiet@Claudima_Bot\nnote", Permission::Owner), Some(Ok(vec!["note".to_string()])));
+        // Missing text
+        assert_eq!(args("/sys   ", Permission::Owner), Some(Err("Missing text\nUsage: /sys <text>".to_string())));
+    }
+
+    #[test]
+    fn test_permission_enforcement() {
+        // Not permitted: falls through to the normal flow, never an error reply
+        assert_eq!(args("/status", Permission::Trusted), None);
+        assert_eq!(args("/sys hi", Permission::Anyone), None);
+        assert_eq!(args("/help", Permission::Anyone), None);
+        assert!(args("/help", Permission::Trusted).is_some());
+        assert!(args("/status", Permission::Owner).is_some());
+
+        // Not ours
+        assert_eq!(args("/uptime", Permission::Owner), None);
+        assert_eq!(args("/status@other_bot", Permission::Owner), None);
+        assert_eq!(args("status", Permission::Owner), None);
+        assert!(args("/STATUS@claudima_bot", Permission::Owner).is_some());
+    }
+
+    #[test]
+    fn test_group_commands() {
+        let group = |text: &str, caller| parse(text, caller, true, Some("claudima_bot")).map(|(c, args)| (c.name, args));
+        // Lookups work in groups, for trusted users and the owner
+        assert_eq!(group("/stats@Claudima_Bot 3", Permission::Trusted), Some(("stats", Ok(vec!["3".to_string()]))));
+        assert!(group("/whois @alice", Permission::Owner).is_some());
+        assert_eq!(group("/stats", Permission::Anyone), None);
+        assert_eq!(group("/stats@other_bot", Permission::Trusted), None);
+        // Controls are DM-only
+        assert_eq!(group("/pause", Permission::Owner), None);
+        assert_eq!(group("/help", Permission::Owner), None);
+
+        let names = |commands: Vec<BotCommand>| commands.into_iter().map(|c| c.command).collect::<Vec<_>>();
+        assert_eq!(names(bot_commands(true)), ["reminders", "stats", "members", "whois"]);
+        assert_eq!(names(bot_commands(false)), ["help", "reminders", "stats", "members", "whois"]);
+    }
+
+    #[test]
+    fn test_owner_command_arguments() {
+        assert_eq!(args("/recap", Permission::Owner), Some(Ok(vec![])));
+        assert_eq!(args("/recap 6 12", Permission::Owner), Some(Err("Too many arguments\nUsage: /recap [hours]".to_string())));
+        assert_eq!(args("/backup", Permission::Owner), Some(Err("Missing argument\nUsage: /backup now".to_string())));
+        assert_eq!(args("/reload guard", Permission::Owner), Some(Ok(vec!["guard".to_string()])));
+        assert_eq!(args("/pause", Permission::Trusted), None);
+
+        let count = |arg: Option<&str>| count_arg(arg.map(str::to_string).as_ref(), 7, 365, "days");
+        assert_eq!(count(None), Ok(7));
+        assert_eq!(count(Some("30")), Ok(30));
+        assert_eq!(count(Some("0")), Err("Invalid number of days '0' (1-365)".to_string()));
+        assert_eq!(count(Some("week")), Err("Invalid number of days 'week' (1-365)".to_string()));
+        assert_eq!(usage_error("resume", "Unknown option 'later'"), "Unknown option 'later'\nUsage: /resume [recap]");
+    }
+
+    #[test]
+    fn test_help_lists_permitted_commands() {
+        let trusted = help_text(Permission::Trusted, None);
+        assert!(trusted.starts_with("Commands:\n/help [command] — List the commands you can use, or show one's usage\n/reminders — "));
+        assert!(trusted.contains("/whois @username|user_id — Look up a user"));
+        assert!(!trusted.contains("/status"));
+        let owner = help_text(Permission::Owner, None);
+        for command in COMMANDS {
+            assert!(owner.contains(command.usage), "{}", owner);
+        }
+        assert_eq!(help_text(Permission::Owner, Some("/sys")), "/sys <text>\nSend Claude a system message");
+        assert_eq!(help_text(Permission::Trusted, Some("sys")), "No command /sys. Send /help for the list.");
+    }
+
+    #[test]
+    fn test_parse_sys_command() {
+        assert_eq!(
+            parse_sys_command("/sys Bot restarted, check the queue"),
+            Some(Ok(SysCommand { text: "Bot restarted, check the queue".to_string(), quiet: false }))
+        );
+        assert_eq!(
+            parse_sys_command("/sysquiet@claudima_bot stop the giveaway"),
+            Some(Ok(SysCommand { text: "stop the giveaway".to_string(), quiet: true }))
+        );
+        assert!(matches!(parse_sys_command("/sys"), Some(Err(_))));
+        assert!(matches!(parse_sys_command("/sysquiet   "), Some(Err(_))));
+        assert_eq!(parse_sys_command("/system prompt"), None);
+        assert_eq!(parse_sys_command("/status"), None);
+        assert_eq!(parse_sys_command("hello /sys"), None);
+    }
+
+    #[test]
+    fn test_sys_payload_kept_verbatim() {
+        let command = parse_sys_command("/sys line one\n<b>bold</b> & more\nline three").unwrap().unwrap();
+        assert_eq!(command.text, "line one\n<b>bold</b> & more\nline three");
+        assert_eq!(command.message_text(), command.text);
+
+        // Command followed directly by a newline
+        let command = parse_sys_command("/sysquiet\nnote this").unwrap().unwrap();
+        assert_eq!(command.text, "note this");
+        assert!(command.message_text().starts_with("note this\n\n"));
+        assert!(command.message_text().ends_with(SYS_QUIET_NOTE));
+    }
+
+    #[test]
+    fn test_sys_message_is_system() {
+        let command = parse_sys_command("/sys <ping>").unwrap().unwrap();
+        let msg = ChatMessage::system(command.message_text());
+        assert_eq!(msg.user_id, 0);
+        assert_eq!(msg.chat_id, 0);
+        assert!(msg.format().contains("&lt;ping&gt;"));
+    }
+}
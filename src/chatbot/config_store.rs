@@ -0,0 +1,111 @@
+//! Safe changes to the config file at runtime.
+//!
+//! Runtime changes to the config (trusted users, supergroup migrations) go through
+//! `ConfigStore::update`. Updates run one at a time, and each new version is
+//! checked with the config deserializer before it replaces the file. It's
+//! written to a temp file and renamed over the original, so a crash mid-write
+//! can't leave a truncated config. The previous version is kept as `<file>.bak`.
+
+use std::path::{Path, PathBuf};
+
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// Checks a complete config file's text, as the config loader would read it.
+pub type Validator = fn(&str) -> Result<(), String>;
+
+#[derive(Debug)]
+pub struct ConfigStore {
+    path: PathBuf,
+    validate: Validator,
+    /// Held for a whole read-edit-write cycle.
+    lock: Mutex<()>,
+}
+
+/// `path` with `suffix` appended to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+impl ConfigStore {
+    pub fn new(path: PathBuf, validate: Validator) -> Self {
+        Self { path, validate, lock: Mutex::new(()) }
+    }
+
+    /// Apply `edit` to the config's JSON and save it. The file is left as it
+    /// was if the result doesn't validate or can't be written.
+    pub async fn update<F>(&self, edit: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut serde_json::Value),
+    {
+        let _guard = self.lock.lock().await;
+
+        let content = tokio::fs::read_to_string(&self.path).await
+            .map_err(|e| format!("Failed to read config: {e}"))?;
+        let mut json: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse config: {e}"))?;
+        edit(&mut json);
+
+        let output = serde_json::to_string_pretty(&json)
+            .map_err(|e| format!("Failed to serialize config: {e}"))?;
+        (self.validate)(&output).map_err(|e| format!("Refusing to save an invalid config: {e}"))?;
+
+        let tmp = sibling(&self.path, ".tmp");
+        let written = async {
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            tokio::io::AsyncWriteExt::write_all(&mut file, output.as_bytes()).await?;
+            file.sync_all().await
+        }.await;
+        if let Err(e) = written {
+            if let Err(rm) = tokio::fs::remove_file(&tmp).await {
+                warn!("Failed to remove {}: {}", tmp.display(), rm);
+            }
+            return Err(format!("Failed to write config: {e}"));
+        }
+
+        let backup = sibling(&self.path, ".bak");
+        tokio::fs::copy(&self.path, &backup).await
+            .map_err(|e| format!("Failed to back up config to {}: {e}", backup.display()))?;
+        tokio::fs::rename(&tmp, &self.path).await
+            .map_err(|e| format!("Failed to replace config: {e}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the real config deserializer.
+    fn validate(content: &str) -> Result<(), String> {
+        let json: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+        serde_json::from_value::<Vec<u64>>(json["owner_ids"].clone()).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    fn store(dir: &Path) -> ConfigStore {
+        let path = dir.join("claudima.json");
+        std::fs::write(&path, r#"{"owner_ids": [1], "trusted_dm_users": []}"#).unwrap();
+        ConfigStore::new(path, validate)
+    }
+
+    #[tokio::test]
+    async fn test_invalid_update_leaves_file_untouched() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = store(dir.path());
+        let before = std::fs::read_to_string(&store.path).unwrap();
+
+        let err = store.update(|json| json["owner_ids"] = "oops".into()).await.unwrap_err();
+        assert!(err.starts_with("Refusing to save an invalid config"));
+        assert_eq!(std::fs::read_to_string(&store.path).unwrap(), before);
+        assert!(!sibling(&store.path, ".tmp").exists());
+        assert!(!sibling(&store.path, ".bak").exists());
+
+        store.update(|json| json["trusted_dm_users"] = serde_json::json!([42])).await.unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&store.path).unwrap()).unwrap();
+        assert_eq!(saved["trusted_dm_users"], serde_json::json!([42]));
+        // The previous version is kept
+        assert_eq!(std::fs::read_to_string(sibling(&store.path, ".bak")).unwrap(), before);
+    }
+}
@@ -9,13 +9,47 @@
 use crate::chatbot::message::ChatMessage;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::path::Path;
 use tracing::{info, warn};
 
+/// Approximate token budget kept in the buffer. Older messages are dropped
+/// (the Database is the archive).
+const DEFAULT_TOKEN_BUDGET: usize = 50_000;
+
+/// Rough token estimate for a message (~4 chars per token, plus metadata).
+fn estimate_tokens(msg: &ChatMessage) -> usize {
+    let reply_len = msg.reply_to.as_ref().map(|r| r.text.len() + r.username.len()).unwrap_or(0);
+    (msg.text.len() + msg.username.len() + reply_len) / 4 + 10
+}
+
 /// Buffer for recent messages.
 pub struct ContextBuffer {
     messages: Vec<ChatMessage>,
     index: HashMap<i64, usize>,
+    /// Approximate tokens across all buffered messages.
+    total_tokens: usize,
+    max_tokens: usize,
+    /// True if messages changed since the last save/load.
+    dirty: bool,
+}
+
+/// Summary of buffer contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextStats {
+    pub messages: usize,
+    pub approx_tokens: usize,
+    pub oldest_timestamp: Option<String>,
+}
+
+impl fmt::Display for ContextStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} messages, ~{} tokens", self.messages, self.approx_tokens)?;
+        if let Some(ref oldest) = self.oldest_timestamp {
+            write!(f, ", oldest {}", oldest)?;
+        }
+        Ok(())
+    }
 }
 
 impl ContextBuffer {
@@ -23,14 +57,20 @@ impl ContextBuffer {
         Self {
             messages: Vec::new(),
             index: HashMap::new(),
+            total_tokens: 0,
+            max_tokens: DEFAULT_TOKEN_BUDGET,
+            dirty: false,
         }
     }
 
-    /// Add a message.
+    /// Add a message, evicting the oldest ones if over budget.
     pub fn add_message(&mut self, msg: ChatMessage) {
         let idx = self.messages.len();
         self.index.insert(msg.message_id, idx);
+        self.total_tokens += estimate_tokens(&msg);
         self.messages.push(msg);
+        self.dirty = true;
+        self.enforce_budget();
     }
 
     /// Edit a message by ID.
@@ -38,10 +78,35 @@ impl ContextBuffer {
         if let Some(&idx) = self.index.get(&message_id)
             && idx < self.messages.len()
         {
+            let old_tokens = estimate_tokens(&self.messages[idx]);
             self.messages[idx].text = new_text.to_string();
+            self.total_tokens = self.total_tokens - old_tokens + estimate_tokens(&self.messages[idx]);
+            self.dirty = true;
+            self.enforce_budget();
         }
     }
 
+    /// Move messages from chat `old` to `new` (a group that became a supergroup).
+    pub fn migrate_chat_id(&mut self, old: i64, new: i64) {
+        for msg in self.messages.iter_mut().filter(|m| m.chat_id == old) {
+            msg.chat_id = new;
+            self.dirty = true;
+        }
+    }
+
+    /// Drop `user_id`'s messages in `chat_id`. Returns how many were removed.
+    pub fn remove_user_messages(&mut self, chat_id: i64, user_id: i64) -> usize {
+        let before = self.messages.len();
+        self.messages.retain(|m| m.chat_id != chat_id || m.user_id != user_id);
+        let removed = before - self.messages.len();
+        if removed > 0 {
+            self.total_tokens = self.messages.iter().map(estimate_tokens).sum();
+            self.dirty = true;
+            self.rebuild_index();
+        }
+        removed
+    }
+
     /// Get a message by ID.
     pub fn get_message(&self, message_id: i64) -> Option<&ChatMessage> {
         self.index
@@ -49,6 +114,31 @@ impl ContextBuffer {
             .and_then(|&idx| self.messages.get(idx))
     }
 
+    /// Current size of the buffer.
+    pub fn stats(&self) -> ContextStats {
+        ContextStats {
+            messages: self.messages.len(),
+            approx_tokens: self.total_tokens,
+            oldest_timestamp: self.messages.first().map(|m| m.timestamp.clone()),
+        }
+    }
+
+    /// Drop oldest messages until within the token budget. Always keeps the newest message.
+    fn enforce_budget(&mut self) {
+        let mut evict = 0;
+        let mut tokens = self.total_tokens;
+        while tokens > self.max_tokens && evict + 1 < self.messages.len() {
+            tokens -= estimate_tokens(&self.messages[evict]);
+            evict += 1;
+        }
+        if evict > 0 {
+            self.messages.drain(..evict);
+            self.total_tokens = tokens;
+            self.dirty = true;
+            self.rebuild_index();
+        }
+    }
+
     fn rebuild_index(&mut self) {
         self.index.clear();
         for (idx, msg) in self.messages.iter().enumerate() {
@@ -69,18 +159,24 @@ struct ContextState {
 }
 
 impl ContextBuffer {
-    pub fn save(&self, path: &Path) -> Result<(), String> {
+    /// Save to disk. Skips writing if nothing changed since the last save.
+    pub fn save(&mut self, path: &Path) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+
         let state = ContextState {
             messages: self.messages.clone(),
         };
 
-        let json = serde_json::to_string_pretty(&state)
+        let json = serde_json::to_string(&state)
             .map_err(|e| format!("Failed to serialize: {e}"))?;
 
         std::fs::write(path, json)
             .map_err(|e| format!("Failed to write: {e}"))?;
 
-        info!("💾 Saved context ({} messages)", self.messages.len());
+        self.dirty = false;
+        info!("💾 Saved context ({})", self.stats());
         Ok(())
     }
 
@@ -91,22 +187,32 @@ impl ContextBuffer {
         let state: ContextState = serde_json::from_str(&json)
             .map_err(|e| format!("Failed to parse: {e}"))?;
 
-        let mut buffer = Self {
-            messages: state.messages,
-            index: HashMap::new(),
-        };
+        let mut buffer = Self::new();
+        buffer.total_tokens = state.messages.iter().map(estimate_tokens).sum();
+        buffer.messages = state.messages;
         buffer.rebuild_index();
+        // Trims oversized files from before the budget existed (marks dirty if so)
+        buffer.enforce_budget();
 
-        info!("Loaded context from {:?} ({} messages)", path, buffer.messages.len());
+        info!("Loaded context from {:?} ({})", path, buffer.stats());
         Ok(buffer)
     }
 
+    /// Load from disk, or start fresh. A corrupted file is renamed aside.
     pub fn load_or_new(path: &Path) -> Self {
         if path.exists() {
             match Self::load(path) {
                 Ok(buffer) => buffer,
                 Err(e) => {
                     warn!("Failed to load context: {e}");
+                    let aside = path.with_extension(format!(
+                        "json.corrupt-{}",
+                        chrono::Utc::now().format("%Y%m%d%H%M%S")
+                    ));
+                    match std::fs::rename(path, &aside) {
+                        Ok(()) => warn!("Moved unreadable context file to {:?}", aside),
+                        Err(e) => warn!("Failed to move unreadable context file aside: {e}"),
+                    }
                     Self::new()
                 }
             }
@@ -132,7 +238,17 @@ mod tests {
             reply_to: None,
             image: None,
             voice_transcription: None,
+            ocr_text: None,
             documents: vec![],
+            backfilled: false,
+            from_bot: false,
+            forwarded: false,
+            via_bot: None,
+            mentions_bot: false,
+            mentioned_user_ids: vec![],
+            lang: None,
+            spoken: false,
+            location: None,
         }
     }
 
@@ -154,4 +270,131 @@ mod tests {
         let msg = ctx.get_message(1).unwrap();
         assert_eq!(msg.text, "world");
     }
+
+    #[test]
+    fn test_remove_user_messages() {
+        let mut ctx = ContextBuffer::new();
+        let mut other_user = make_msg(2, "hi");
+        other_user.user_id = 200;
+        let mut other_chat = make_msg(4, "spam");
+        other_chat.chat_id = -999;
+        for msg in [make_msg(1, "spam"), other_user, make_msg(3, "more spam"), other_chat] {
+            ctx.add_message(msg);
+        }
+
+        assert_eq!(ctx.remove_user_messages(-12345, 100), 2);
+        assert!(ctx.get_message(1).is_none() && ctx.get_message(3).is_none());
+        // The index still points at the right messages
+        assert_eq!(ctx.get_message(2).unwrap().text, "hi");
+        assert_eq!(ctx.get_message(4).unwrap().text, "spam");
+        assert_eq!(ctx.stats().approx_tokens, estimate_tokens(&make_msg(2, "hi")) + estimate_tokens(&make_msg(4, "spam")));
+        assert_eq!(ctx.remove_user_messages(-12345, 100), 0);
+    }
+
+    #[test]
+    fn test_eviction_drops_oldest_first() {
+        let mut ctx = ContextBuffer::new();
+        let per_msg = estimate_tokens(&make_msg(0, &"x".repeat(40)));
+        ctx.max_tokens = per_msg * 3;
+
+        for id in 1..=5 {
+            ctx.add_message(make_msg(id, &"x".repeat(40)));
+        }
+
+        assert!(ctx.get_message(1).is_none());
+        assert!(ctx.get_message(2).is_none());
+        for id in 3..=5 {
+            assert_eq!(ctx.get_message(id).unwrap().message_id, id);
+        }
+        let stats = ctx.stats();
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.approx_tokens, per_msg * 3);
+    }
+
+    #[test]
+    fn test_eviction_keeps_newest_even_if_over_budget() {
+        let mut ctx = ContextBuffer::new();
+        ctx.max_tokens = 1;
+        ctx.add_message(make_msg(1, "hello"));
+        ctx.add_message(make_msg(2, &"x".repeat(1000)));
+
+        assert!(ctx.get_message(1).is_none());
+        assert!(ctx.get_message(2).is_some());
+    }
+
+    #[test]
+    fn test_edit_updates_token_count() {
+        let mut ctx = ContextBuffer::new();
+        ctx.add_message(make_msg(1, "hi"));
+        let before = ctx.stats().approx_tokens;
+        ctx.edit_message(1, &"x".repeat(400));
+        assert_eq!(ctx.stats().approx_tokens, before + 100);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut ctx = ContextBuffer::new();
+        assert_eq!(ctx.stats(), ContextStats { messages: 0, approx_tokens: 0, oldest_timestamp: None });
+
+        ctx.add_message(make_msg(1, "hello"));
+        let stats = ctx.stats();
+        assert_eq!(stats.messages, 1);
+        assert_eq!(stats.oldest_timestamp.as_deref(), Some("10:00"));
+        assert_eq!(stats.to_string(), format!("1 messages, ~{} tokens, oldest 10:00", stats.approx_tokens));
+    }
+
+    #[test]
+    fn test_save_skips_when_clean() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("context.json");
+
+        let mut ctx = ContextBuffer::new();
+        ctx.save(&path).unwrap();
+        assert!(!path.exists(), "empty clean buffer should not be written");
+
+        ctx.add_message(make_msg(1, "hello"));
+        ctx.save(&path).unwrap();
+        assert!(path.exists());
+
+        // Clean again: an external change to the file must not be overwritten
+        std::fs::write(&path, "sentinel").unwrap();
+        ctx.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "sentinel");
+
+        ctx.edit_message(1, "changed");
+        ctx.save(&path).unwrap();
+        assert_ne!(std::fs::read_to_string(&path).unwrap(), "sentinel");
+    }
+
+    #[test]
+    fn test_load_round_trip_is_clean() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("context.json");
+
+        let mut ctx = ContextBuffer::new();
+        ctx.add_message(make_msg(1, "hello"));
+        ctx.save(&path).unwrap();
+
+        let loaded = ContextBuffer::load(&path).unwrap();
+        assert_eq!(loaded.get_message(1).unwrap().text, "hello");
+        assert_eq!(loaded.stats(), ctx.stats());
+        assert!(!loaded.dirty);
+    }
+
+    #[test]
+    fn test_load_or_new_recovers_from_corrupted_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("context.json");
+        std::fs::write(&path, "{ not json").unwrap();
+
+        let ctx = ContextBuffer::load_or_new(&path);
+        assert_eq!(ctx.stats().messages, 0);
+        assert!(!path.exists(), "corrupted file should be moved aside");
+
+        let moved: Vec<_> = std::fs::read_dir(dir.path()).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(moved.len(), 1);
+        assert!(moved[0].starts_with("context.json.corrupt-"));
+    }
 }
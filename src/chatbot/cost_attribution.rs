@@ -0,0 +1,175 @@
+//! Which chats and users drive Claude spend.
+//!
+//! Each turn's cost is split evenly across the batch's messages from people
+//! (system messages don't count) and added up per user, chat and UTC day in
+//! the `cost_attribution` table, which `/costs` summarizes. The split is done
+//! in micro-dollars, so the shares add up to exactly the turn's cost.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use super::message::ChatMessage;
+
+/// Window `/costs` covers without an argument (days).
+pub const DEFAULT_DAYS: u32 = 7;
+
+/// Longest window `/costs` accepts (days).
+pub const MAX_DAYS: u32 = 365;
+
+/// Chats and users listed by `/costs`.
+pub const TOP_SPENDERS: usize = 5;
+
+/// One user's part of a turn's cost in one chat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostShare {
+    pub user_id: i64,
+    pub chat_id: i64,
+    pub micro_usd: i64,
+}
+
+/// Split `cost_usd` evenly over the messages from people, one share per user
+/// and chat. The leftover micro-dollars go to the earliest messages.
+pub fn split(messages: &[ChatMessage], cost_usd: f64) -> Vec<CostShare> {
+    // System messages have user_id 0
+    let senders: Vec<&ChatMessage> = messages.iter().filter(|m| m.user_id != 0).collect();
+    let total = (cost_usd * 1e6).round() as i64;
+    if senders.is_empty() || total <= 0 {
+        return Vec::new();
+    }
+    let count = senders.len() as i64;
+    let (base, leftover) = (total / count, total % count);
+
+    let mut shares: Vec<CostShare> = Vec::new();
+    for (i, msg) in senders.iter().enumerate() {
+        let micro_usd = base + i64::from((i as i64) < leftover);
+        match shares.iter_mut().find(|s| s.user_id == msg.user_id && s.chat_id == msg.chat_id) {
+            Some(share) => share.micro_usd += micro_usd,
+            None => shares.push(CostShare { user_id: msg.user_id, chat_id: msg.chat_id, micro_usd }),
+        }
+    }
+    shares
+}
+
+/// The day a turn finishing at `at` is counted on.
+pub fn bucket(at: DateTime<Utc>) -> NaiveDate {
+    at.date_naive()
+}
+
+/// The first day of a `days`-day window ending with today.
+pub fn window_start(now: DateTime<Utc>, days: u32) -> NaiveDate {
+    bucket(now) - Duration::days(i64::from(days.max(1)) - 1)
+}
+
+/// Spend by one chat or user over a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spender {
+    pub id: i64,
+    /// The user's name, or for DMs the other side's.
+    pub name: Option<String>,
+    pub usd: f64,
+    /// Turns they had messages in; for a chat, summed over its users.
+    pub turns: i64,
+}
+
+/// Attributed spend over a window, with the top chats and users.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostSummary {
+    pub total_usd: f64,
+    pub chats: Vec<Spender>,
+    pub users: Vec<Spender>,
+}
+
+/// The `/costs` reply for a `days`-day window.
+pub fn render(summary: &CostSummary, days: u32) -> String {
+    if summary.chats.is_empty() {
+        return format!("No attributed Claude spend in the last {} day(s).", days);
+    }
+    let mut out = format!("💸 Claude spend in the last {} day(s): ${:.2}\n\nTop chats:", days, summary.total_usd);
+    for chat in &summary.chats {
+        let label = match (&chat.name, chat.id > 0) {
+            (Some(name), true) => format!("DM with {}", name),
+            _ => chat.id.to_string(),
+        };
+        out.push_str(&format!("\n• {} — ${:.2}", label, chat.usd));
+    }
+    out.push_str("\n\nTop users:");
+    for user in &summary.users {
+        let name = user.name.clone().unwrap_or_else(|| user.id.to_string());
+        out.push_str(&format!("\n• {} ({}) — ${:.2} over {} turn(s)", name, user.id, user.usd, user.turns));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(user_id: i64, chat_id: i64) -> ChatMessage {
+        let mut msg = ChatMessage::system("hi".to_string());
+        (msg.user_id, msg.chat_id) = (user_id, chat_id);
+        msg
+    }
+
+    fn shares(split: &[CostShare]) -> Vec<(i64, i64, i64)> {
+        split.iter().map(|s| (s.user_id, s.chat_id, s.micro_usd)).collect()
+    }
+
+    #[test]
+    fn test_even_split() {
+        // $0.30 over three messages, the system message left out
+        let batch = [msg(1, -100), msg(0, 0), msg(2, -100), msg(3, 5)];
+        assert_eq!(shares(&split(&batch, 0.30)), vec![(1, -100, 100_000), (2, -100, 100_000), (3, 5, 100_000)]);
+
+        // Several messages from one user in a chat add up
+        let batch = [msg(1, -100), msg(2, -100), msg(1, -100), msg(1, -200)];
+        assert_eq!(shares(&split(&batch, 0.04)), vec![(1, -100, 20_000), (2, -100, 10_000), (1, -200, 10_000)]);
+
+        // Nothing to attribute
+        assert!(split(&[msg(0, 0)], 0.5).is_empty());
+        assert!(split(&[msg(1, -100)], 0.0).is_empty());
+    }
+
+    #[test]
+    fn test_split_rounding() {
+        // $0.10 over three: the leftover micro-dollar goes to the first
+        let batch = [msg(1, -100), msg(2, -100), msg(3, -100)];
+        let parts = split(&batch, 0.10);
+        assert_eq!(shares(&parts), vec![(1, -100, 33_334), (2, -100, 33_333), (3, -100, 33_333)]);
+        assert_eq!(parts.iter().map(|s| s.micro_usd).sum::<i64>(), 100_000);
+
+        // Sub-micro-dollar noise from float sums is rounded away
+        let total = 0.1 + 0.2;
+        assert_eq!(split(&batch[..1], total)[0].micro_usd, 300_000);
+    }
+
+    #[test]
+    fn test_date_bucketing() {
+        let late = "2024-03-10T23:59:59Z".parse::<DateTime<Utc>>().unwrap();
+        let early = "2024-03-11T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(bucket(late).to_string(), "2024-03-10");
+        assert_eq!(bucket(early).to_string(), "2024-03-11");
+
+        // A 7-day window includes today and the 6 days before
+        assert_eq!(window_start(early, 7).to_string(), "2024-03-05");
+        assert_eq!(window_start(early, 1).to_string(), "2024-03-11");
+        assert_eq!(window_start(early, 0).to_string(), "2024-03-11");
+    }
+
+    #[test]
+    fn test_render() {
+        let empty = CostSummary { total_usd: 0.0, chats: vec![], users: vec![] };
+        assert_eq!(render(&empty, 7), "No attributed Claude spend in the last 7 day(s).");
+        let summary = CostSummary {
+            total_usd: 1.75,
+            chats: vec![
+                Spender { id: -100, name: None, usd: 1.5, turns: 10 },
+                Spender { id: 42, name: Some("alice".to_string()), usd: 0.25, turns: 2 },
+            ],
+            users: vec![Spender { id: 42, name: Some("alice".to_string()), usd: 0.9, turns: 5 }],
+        };
+        let text = render(&summary, 30);
+        assert!(text.starts_with("💸 Claude spend in the last 30 day(s): $1.75"), "{}", text);
+        assert!(text.contains("• -100 — $1.50"), "{}", text);
+        assert!(text.contains("• DM with alice — $0.25"), "{}", text);
+        assert!(text.contains("• alice (42) — $0.90 over 5 turn(s)"), "{}", text);
+    }
+}
@@ -1,15 +1,51 @@
 //! Persistent SQLite database for messages, members, and reminders.
 //!
-//! Note: This Database struct is NOT thread-safe on its own. It must be wrapped
-//! in a `tokio::sync::Mutex` (as done in `ChatbotEngine`) for safe concurrent access.
-//! The mutex is intentionally kept external to allow async-aware locking.
-
-use crate::chatbot::message::{ChatMessage, ReplyTo};
-use crate::chatbot::reminders::Reminder;
-use chrono::{DateTime, Utc};
-use rusqlite::{Connection, params};
+//! `Database` is synchronous and owns the connection. Async code goes through
+//! `AsyncDatabase`, which moves the `Database` onto a dedicated worker thread
+//! and runs closures against it there, so SQLite I/O (including WAL
+//! checkpoints) never blocks the tokio runtime.
+
+use crate::chatbot::bot_status::{BotChatStatus, BotStatus};
+use crate::chatbot::chat_settings::SLOW_MODE_SETTING;
+use crate::chatbot::cost_attribution::{CostShare, CostSummary, Spender};
+use crate::chatbot::message::{ChatMessage, Location, ReplyTo};
+use crate::chatbot::message_features::MessageFeatures;
+use crate::chatbot::language;
+use crate::chatbot::names;
+use crate::chatbot::read_only_sql;
+use crate::chatbot::reminders::{self, Reminder, ReminderKind, ScheduledMessage};
+use crate::chatbot::tool_errors::ToolError;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
-use tracing::{info, warn, debug};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+/// How long SQLite waits on a locked database before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Max queued jobs before callers wait for the worker.
+const WORKER_QUEUE_SIZE: usize = 256;
+
+/// Max messages `get_thread` walks up a reply chain.
+pub const MAX_THREAD_DEPTH: usize = 30;
+
+/// Max formatted chars returned by `get_thread`.
+const THREAD_CHAR_BUDGET: usize = 8000;
+
+/// Users whose DMs to the bot without access are tracked at most.
+pub const MAX_UNKNOWN_DMS: usize = 10_000;
+
+/// Columns selected for building a `ChatMessage` (see `row_to_message`).
+const MESSAGE_COLUMNS: &str =
+    "message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text, ocr_text, from_bot, forwarded, via_bot, reply_to_unknown, spoken,
+     location_lat, location_lon, location_title, location_address";
+
+/// Columns selected for building a `Member` (see `row_to_member`).
+const MEMBER_COLUMNS: &str =
+    "user_id, username, first_name, join_date, last_message_date, message_count, status, last_rejoin_date, rejoin_count, invite_link, invited_by";
 
 /// Member status in the group.
 #[derive(Debug, Clone, PartialEq)]
@@ -29,6 +65,15 @@ impl MemberStatus {
     }
 }
 
+/// Size of one table, from `table_stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub name: String,
+    pub rows: usize,
+    /// Pages used by the table and its indexes.
+    pub bytes: u64,
+}
+
 /// A group member.
 #[derive(Debug, Clone)]
 pub struct Member {
@@ -39,11 +84,165 @@ pub struct Member {
     pub last_message_date: Option<String>,
     pub message_count: u32,
     pub status: MemberStatus,
+    /// When they last joined again after the first time.
+    pub last_rejoin_date: Option<String>,
+    /// Joins after the first one.
+    pub rejoin_count: u32,
+    /// Invite link of their latest join, if Telegram said which.
+    pub invite_link: Option<String>,
+    /// Who added them or made the link, for their latest join.
+    pub invited_by: Option<i64>,
+}
+
+/// Recent joins through one invite link, from `Database::joins_by_invite_link`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InviteLinkJoins {
+    pub invite_link: String,
+    pub joins: usize,
+    /// Of those, how many are banned by now.
+    pub banned: usize,
+}
+
+/// What the database knows about a user, from `user_activity`.
+#[derive(Debug, Clone)]
+pub struct UserActivity {
+    pub member: Option<Member>,
+    /// Group messages since the window start.
+    pub recent_messages: i64,
+}
+
+impl Member {
+    /// "rejoined (3rd time, originally joined 2024-05-01)", or None for a
+    /// member who only joined once.
+    pub fn join_history(&self) -> Option<String> {
+        if self.rejoin_count == 0 {
+            return None;
+        }
+        let n = self.rejoin_count;
+        let suffix = match (n % 10, n % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        let original: String = self.join_date.chars().take(10).collect();
+        Some(format!("rejoined ({}{} time, originally joined {})", n, suffix, original))
+    }
+}
+
+/// Recent messages formatted for replaying into Claude's context.
+#[derive(Debug)]
+pub struct ContextReplay {
+    pub messages: usize,
+    pub approx_tokens: usize,
+    /// Messages in `ChatMessage::format()` form, oldest first, one per line.
+    pub text: String,
+}
+
+/// Message counts for one poster, from `Database::chat_stats` and
+/// `Database::top_group_posters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PosterCount {
+    pub user_id: i64,
+    pub username: String,
+    pub messages: usize,
+}
+
+/// Activity in one chat over a window, from `Database::chat_stats`.
+#[derive(Debug, Clone)]
+pub struct ChatStats {
+    pub total_messages: usize,
+    pub active_users: usize,
+    /// Top 5 posters, most messages first.
+    pub top_posters: Vec<PosterCount>,
+    /// Messages per hour of day (UTC), index 0-23.
+    pub hourly: [usize; 24],
+    /// Average message length in characters.
+    pub avg_length: f64,
+    /// Reactions standing on the window's messages, by emoji, most first.
+    pub reactions: Vec<(String, usize)>,
+}
+
+/// A moderation action from `moderation_log` with the user's names as last
+/// known, from `Database::moderation_between`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModerationEntry {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    pub action: String,
+}
+
+/// A message with the reactions standing on it, from `Database::top_reacted_messages`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReactedMessage {
+    pub message_id: i64,
+    pub user_id: i64,
+    pub username: String,
+    pub text: String,
+    pub timestamp: String,
+    /// Reactions by emoji, most first.
+    pub reactions: Vec<(String, usize)>,
+}
+
+impl ReactedMessage {
+    pub fn total_reactions(&self) -> usize {
+        self.reactions.iter().map(|(_, n)| n).sum()
+    }
+}
+
+impl ChatStats {
+    /// Hour of day (UTC) with the most messages, if any.
+    pub fn busiest_hour(&self) -> Option<usize> {
+        (self.total_messages > 0).then(|| {
+            (0..24).max_by_key(|&h| (self.hourly[h], std::cmp::Reverse(h))).unwrap_or(0)
+        })
+    }
+}
+
+/// A reply chain around one message, from `Database::get_thread`.
+#[derive(Debug)]
+pub struct Thread {
+    /// Ancestors followed by the message itself, oldest first.
+    pub chain: Vec<ChatMessage>,
+    /// Direct replies to the message, oldest first.
+    pub replies: Vec<ChatMessage>,
+    /// A message referenced by the chain that isn't stored (older history or deleted).
+    pub missing: Option<i64>,
+    /// Older messages or replies were left out to stay within the depth/size caps.
+    pub truncated: bool,
+}
+
+impl Thread {
+    /// Format for Claude: messages in `ChatMessage::format()` form, one per line.
+    pub fn format(&self) -> String {
+        let mut lines = Vec::new();
+        if self.truncated {
+            lines.push("[Older messages omitted: depth or size limit reached]".to_string());
+        }
+        if let Some(id) = self.missing {
+            lines.push(format!("[Message {} is not in the database; the thread starts after it]", id));
+        }
+        lines.extend(self.chain.iter().map(|m| m.format()));
+        if !self.replies.is_empty() {
+            lines.push("[Direct replies:]".to_string());
+            lines.extend(self.replies.iter().map(|m| m.format()));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Which end of a range `Database::read_messages` reads from, and how many.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Take {
+    Latest(usize),
+    Earliest(usize),
 }
 
 /// Persistent SQLite database for the chatbot.
 ///
-/// Must be wrapped in a `tokio::sync::Mutex` for concurrent access.
+/// Synchronous; wrap in `AsyncDatabase` for use from async code.
 pub struct Database {
     conn: Connection,
 }
@@ -62,10 +261,7 @@ impl Database {
         // Check if we need to migrate from JSON
         let json_path = path.with_extension("json");
         let db_exists = path.exists();
-
-        let conn = Connection::open(path).expect("Failed to open database");
-        let mut db = Self { conn };
-        db.init_schema();
+        let db = Self::open(path);
 
         // Migrate from JSON if database is new and JSON exists
         if !db_exists && json_path.exists() {
@@ -81,6 +277,21 @@ impl Database {
         db
     }
 
+    /// Open (or create) the database file without the JSON migration.
+    pub fn open(path: &Path) -> Self {
+        let conn = Connection::open(path).expect("Failed to open database");
+        match conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0)) {
+            Ok(mode) => debug!("SQLite journal_mode = {}", mode),
+            Err(e) => warn!("Failed to enable WAL mode: {e}"),
+        }
+        if let Err(e) = conn.busy_timeout(BUSY_TIMEOUT) {
+            warn!("Failed to set busy_timeout: {e}");
+        }
+        let mut db = Self { conn };
+        db.init_schema();
+        db
+    }
+
     fn init_schema(&mut self) {
         self.conn.execute_batch(r"
             CREATE TABLE IF NOT EXISTS messages (
@@ -92,7 +303,23 @@ impl Database {
                 text TEXT NOT NULL,
                 reply_to_id INTEGER,
                 reply_to_username TEXT,
-                reply_to_text TEXT
+                reply_to_text TEXT,
+                ocr_text TEXT,
+                from_bot INTEGER NOT NULL DEFAULT 0,
+                deleted INTEGER NOT NULL DEFAULT 0,
+                url_count INTEGER NOT NULL DEFAULT 0,
+                has_mention INTEGER NOT NULL DEFAULT 0,
+                forwarded INTEGER NOT NULL DEFAULT 0,
+                length_class TEXT,
+                emoji_ratio REAL NOT NULL DEFAULT 0,
+                via_bot TEXT,
+                expired INTEGER NOT NULL DEFAULT 0,
+                reply_to_unknown INTEGER NOT NULL DEFAULT 0,
+                spoken INTEGER NOT NULL DEFAULT 0,
+                location_lat REAL,
+                location_lon REAL,
+                location_title TEXT,
+                location_address TEXT
             );
 
             CREATE TABLE IF NOT EXISTS users (
@@ -108,6 +335,8 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp);
             CREATE INDEX IF NOT EXISTS idx_messages_user_id ON messages(user_id);
             CREATE INDEX IF NOT EXISTS idx_messages_username ON messages(username);
+            CREATE INDEX IF NOT EXISTS idx_messages_reply_to_id ON messages(reply_to_id);
+            CREATE INDEX IF NOT EXISTS idx_messages_chat_timestamp ON messages(chat_id, timestamp);
             CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
             CREATE INDEX IF NOT EXISTS idx_users_status ON users(status);
 
@@ -120,10 +349,207 @@ impl Database {
                 repeat_cron TEXT,
                 created_at TEXT NOT NULL,
                 last_triggered_at TEXT,
-                active INTEGER DEFAULT 1
+                active INTEGER DEFAULT 1,
+                kind TEXT NOT NULL DEFAULT 'message'
             );
             CREATE INDEX IF NOT EXISTS idx_reminders_active ON reminders(trigger_at) WHERE active = 1;
+
+            CREATE TABLE IF NOT EXISTS scheduled_messages (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                reply_to_message_id INTEGER,
+                send_at TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                active INTEGER DEFAULT 1
+            );
+            CREATE INDEX IF NOT EXISTS idx_scheduled_messages_active ON scheduled_messages(send_at) WHERE active = 1;
+
+            CREATE TABLE IF NOT EXISTS reactions (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                message_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                emoji TEXT NOT NULL,
+                added INTEGER NOT NULL,
+                timestamp TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_reactions_message_id ON reactions(message_id);
+
+            CREATE TABLE IF NOT EXISTS mutes (
+                chat_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                until TEXT NOT NULL,
+                muted_at TEXT NOT NULL,
+                PRIMARY KEY (chat_id, user_id)
+            );
+
+            CREATE TABLE IF NOT EXISTS invite_links (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                invite_link TEXT NOT NULL,
+                name TEXT,
+                creator_id INTEGER NOT NULL,
+                expire_at TEXT,
+                member_limit INTEGER,
+                created_at TEXT NOT NULL,
+                revoked_at TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_invite_links_chat ON invite_links(chat_id);
+
+            CREATE TABLE IF NOT EXISTS chat_setting_changes (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                setting TEXT NOT NULL,
+                value TEXT NOT NULL,
+                changed_by INTEGER NOT NULL,
+                changed_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_chat_setting_changes_chat ON chat_setting_changes(chat_id, setting);
+
+            CREATE TABLE IF NOT EXISTS chat_languages (
+                chat_id INTEGER NOT NULL,
+                lang TEXT NOT NULL,
+                count INTEGER NOT NULL,
+                PRIMARY KEY (chat_id, lang)
+            );
+
+            CREATE TABLE IF NOT EXISTS tool_errors (
+                id INTEGER PRIMARY KEY,
+                tool TEXT NOT NULL,
+                error TEXT NOT NULL,
+                chat_id INTEGER,
+                timestamp TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_tool_errors_timestamp ON tool_errors(timestamp);
+
+            CREATE TABLE IF NOT EXISTS bot_status (
+                chat_id INTEGER PRIMARY KEY,
+                status TEXT NOT NULL,
+                since TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS unknown_dms (
+                user_id INTEGER PRIMARY KEY,
+                messages INTEGER NOT NULL,
+                first_seen TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS cost_attribution (
+                user_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                usd REAL NOT NULL,
+                turns INTEGER NOT NULL,
+                PRIMARY KEY (user_id, chat_id, date)
+            );
+
+            CREATE TABLE IF NOT EXISTS auto_delete_timers (
+                chat_id INTEGER PRIMARY KEY,
+                seconds INTEGER NOT NULL,
+                since TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS spam_image_hashes (
+                hash TEXT PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                expires_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS moderation_log (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_moderation_log_user ON moderation_log(chat_id, user_id);
+
+            CREATE TABLE IF NOT EXISTS spam_deletions (
+                id INTEGER PRIMARY KEY,
+                chat_id INTEGER NOT NULL,
+                at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_spam_deletions_at ON spam_deletions(chat_id, at);
         ").expect("Failed to initialize database schema");
+
+        // Columns added after a table's first release
+        self.add_column_if_missing("messages", "ocr_text", "TEXT");
+        self.add_column_if_missing("messages", "from_bot", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "deleted", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "url_count", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "has_mention", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "forwarded", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "length_class", "TEXT");
+        self.add_column_if_missing("messages", "emoji_ratio", "REAL NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "via_bot", "TEXT");
+        self.add_column_if_missing("messages", "expired", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "reply_to_unknown", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "spoken", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("messages", "location_lat", "REAL");
+        self.add_column_if_missing("messages", "location_lon", "REAL");
+        self.add_column_if_missing("messages", "location_title", "TEXT");
+        self.add_column_if_missing("messages", "location_address", "TEXT");
+        self.add_column_if_missing("reminders", "kind", "TEXT NOT NULL DEFAULT 'message'");
+        self.add_column_if_missing("users", "normalized_username", "TEXT");
+        self.add_column_if_missing("users", "last_rejoin_date", "TEXT");
+        self.add_column_if_missing("users", "rejoin_count", "INTEGER NOT NULL DEFAULT 0");
+        self.add_column_if_missing("users", "invite_link", "TEXT");
+        self.add_column_if_missing("users", "invited_by", "INTEGER");
+        if let Err(e) = self.conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_users_normalized_username ON users(normalized_username);"
+        ) {
+            warn!("Failed to index users.normalized_username: {e}");
+        }
+        self.fill_normalized_usernames();
+    }
+
+    /// Set `normalized_username` on users stored before it existed.
+    fn fill_normalized_usernames(&self) {
+        let rows: rusqlite::Result<Vec<(i64, String)>> = self.conn
+            .prepare("SELECT user_id, COALESCE(username, first_name) FROM users WHERE normalized_username IS NULL")
+            .and_then(|mut stmt| stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect());
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Failed to read users to normalize: {e}");
+                return;
+            }
+        };
+        for (user_id, name) in &rows {
+            if let Err(e) = self.conn.execute(
+                "UPDATE users SET normalized_username = ?2 WHERE user_id = ?1",
+                params![user_id, names::normalize(name)]
+            ) {
+                warn!("Failed to normalize username of {}: {e}", user_id);
+            }
+        }
+        if !rows.is_empty() {
+            info!("Normalized {} stored usernames", rows.len());
+        }
+    }
+
+    /// Add a column to an existing table (databases created by older versions).
+    fn add_column_if_missing(&self, table: &str, column: &str, decl: &str) {
+        let exists = self.conn
+            .prepare(&format!("SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1"))
+            .and_then(|mut stmt| stmt.exists(params![column]));
+        match exists {
+            Ok(true) => {}
+            Ok(false) => match self.conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"), []) {
+                Ok(_) => info!("Added column {}.{}", table, column),
+                Err(e) => warn!("Failed to add column {}.{}: {e}", table, column),
+            },
+            Err(e) => warn!("Failed to inspect table {}: {e}", table),
+        }
+    }
+
+    /// Write a consistent copy of the database to `path` with SQLite's online
+    /// backup, safe while the connection is in use.
+    pub fn backup_to(&self, path: &Path) -> Result<(), String> {
+        self.conn
+            .backup(rusqlite::DatabaseName::Main, path, None)
+            .map_err(|e| format!("Failed to back up database: {e}"))
     }
 
     fn get_counts(&self) -> (usize, usize) {
@@ -137,8 +563,9 @@ impl Database {
         (msg_count as usize, member_count as usize)
     }
 
-    /// Migrate data from a JSON database file.
-    fn migrate_from_json(&self, json_path: &Path) -> Result<(), String> {
+    /// Migrate data from a JSON database file. Rows already present are
+    /// replaced, so running it twice is harmless.
+    pub fn migrate_from_json(&self, json_path: &Path) -> Result<(usize, usize), String> {
         use serde::{Deserialize, Serialize};
 
         #[derive(Serialize, Deserialize)]
@@ -190,9 +617,10 @@ impl Database {
 
         // Import members
         for m in &data.members {
+            let normalized = names::normalize(m.username.as_deref().unwrap_or(&m.first_name));
             conn.execute(
-                "INSERT OR REPLACE INTO users (user_id, username, first_name, join_date, last_message_date, message_count, status) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                params![m.user_id, m.username, m.first_name, m.join_date, m.last_message_date, m.message_count, m.status]
+                "INSERT OR REPLACE INTO users (user_id, username, first_name, join_date, last_message_date, message_count, status, normalized_username) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![m.user_id, m.username, m.first_name, m.join_date, m.last_message_date, m.message_count, m.status, normalized]
             ).map_err(|e| format!("Failed to insert member: {e}"))?;
         }
 
@@ -210,7 +638,100 @@ impl Database {
         }
 
         info!("Migrated {} messages and {} members from JSON", data.messages.len(), data.members.len());
-        Ok(())
+        Ok((data.messages.len(), data.members.len()))
+    }
+
+    /// Row count and size of every table. Sizes include the table's indexes.
+    pub fn table_stats(&self) -> Result<Vec<TableStats>, String> {
+        let err = |e: rusqlite::Error| format!("Failed to read table stats: {e}");
+        let mut stmt = self.conn.prepare(
+            "SELECT m.tbl_name, COALESCE(SUM(s.pgsize), 0) FROM sqlite_master m
+             LEFT JOIN dbstat s ON s.name = m.name
+             WHERE m.type IN ('table', 'index') AND m.tbl_name NOT LIKE 'sqlite_%'
+             GROUP BY m.tbl_name ORDER BY m.tbl_name"
+        ).map_err(err)?;
+        let sizes = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)?;
+
+        sizes.into_iter().map(|(name, bytes)| {
+            // Table names come from sqlite_master, not from input
+            let rows: i64 = self.conn
+                .query_row(&format!("SELECT COUNT(*) FROM \"{name}\""), [], |row| row.get(0))
+                .map_err(err)?;
+            Ok(TableStats { name, rows: rows as usize, bytes: bytes as u64 })
+        }).collect()
+    }
+
+    /// Delete messages sent before `before`, and reactions recorded before
+    /// it. Returns how many of each were removed.
+    pub fn prune_before(&mut self, before: DateTime<Utc>) -> Result<(usize, usize), String> {
+        let err = |e: rusqlite::Error| format!("Failed to prune: {e}");
+        let tx = self.conn.transaction().map_err(err)?;
+        let messages = tx.execute(
+            "DELETE FROM messages WHERE timestamp < ?1",
+            params![before.format("%Y-%m-%d %H:%M").to_string()]
+        ).map_err(err)?;
+        let reactions = tx.execute(
+            "DELETE FROM reactions WHERE timestamp < ?1",
+            params![before.to_rfc3339()]
+        ).map_err(err)?;
+        tx.commit().map_err(err)?;
+        Ok((messages, reactions))
+    }
+
+    /// Rebuild the file to give freed pages back to the filesystem.
+    pub fn vacuum(&self) -> Result<(), String> {
+        self.conn.execute_batch("VACUUM").map_err(|e| format!("Failed to vacuum: {e}"))
+    }
+
+    /// Write all messages and members to `out` as JSON, in the format
+    /// `migrate_from_json` reads. Returns the message and member counts.
+    pub fn export_json(&self, out: &mut impl std::io::Write) -> Result<(usize, usize), String> {
+        let err = |e: rusqlite::Error| format!("Failed to export: {e}");
+        let write_err = |e: std::io::Error| format!("Failed to write export: {e}");
+
+        out.write_all(b"{\"messages\":[").map_err(write_err)?;
+        let mut stmt = self.conn
+            .prepare(&format!("SELECT {MESSAGE_COLUMNS} FROM messages ORDER BY timestamp, message_id"))
+            .map_err(err)?;
+        let mut messages = 0;
+        for msg in stmt.query_map([], Self::row_to_message).map_err(err)? {
+            if messages > 0 {
+                out.write_all(b",").map_err(write_err)?;
+            }
+            serde_json::to_writer(&mut *out, &msg.map_err(err)?).map_err(|e| format!("Failed to write export: {e}"))?;
+            messages += 1;
+        }
+
+        out.write_all(b"],\"members\":[").map_err(write_err)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT user_id, username, first_name, join_date, last_message_date, message_count, status
+             FROM users ORDER BY user_id"
+        ).map_err(err)?;
+        let rows = stmt.query_map([], |row| {
+            Ok(serde_json::json!({
+                "user_id": row.get::<_, i64>(0)?,
+                "username": row.get::<_, Option<String>>(1)?,
+                "first_name": row.get::<_, String>(2)?,
+                "join_date": row.get::<_, String>(3)?,
+                "last_message_date": row.get::<_, Option<String>>(4)?,
+                "message_count": row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+                "status": row.get::<_, Option<String>>(6)?.unwrap_or_else(|| "member".to_string()),
+            }))
+        }).map_err(err)?;
+        let mut members = 0;
+        for member in rows {
+            if members > 0 {
+                out.write_all(b",").map_err(write_err)?;
+            }
+            serde_json::to_writer(&mut *out, &member.map_err(err)?).map_err(|e| format!("Failed to write export: {e}"))?;
+            members += 1;
+        }
+        out.write_all(b"]}").map_err(write_err)?;
+        Ok((messages, members))
     }
 
     /// Save is a no-op for SQLite (auto-committed).
@@ -226,13 +747,14 @@ impl Database {
 
         // Insert or update user
         conn.execute(
-            "INSERT INTO users (user_id, username, first_name, join_date, last_message_date, message_count, status)
-             VALUES (?1, ?2, ?2, ?3, ?3, 1, 'member')
+            "INSERT INTO users (user_id, username, first_name, join_date, last_message_date, message_count, status, normalized_username)
+             VALUES (?1, ?2, ?2, ?3, ?3, 1, 'member', ?4)
              ON CONFLICT(user_id) DO UPDATE SET
-                username = COALESCE(?2, username),
+                username = ?2,
+                normalized_username = ?4,
                 last_message_date = ?3,
                 message_count = message_count + 1",
-            params![msg.user_id, msg.username, msg.timestamp]
+            params![msg.user_id, msg.username, msg.timestamp, names::normalize(&msg.username)]
         ).unwrap_or_else(|e| {
             warn!("Failed to update user: {e}");
             0
@@ -243,11 +765,22 @@ impl Database {
             Some(r) => (Some(r.message_id), Some(r.username.clone()), Some(r.text.clone())),
             None => (None, None, None),
         };
+        let reply_unknown = msg.reply_to.as_ref().is_some_and(|r| r.unknown_origin);
 
+        let location = msg.location.as_ref();
+
+        let features = MessageFeatures::of(&msg);
         conn.execute(
-            "INSERT OR REPLACE INTO messages (message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-            params![msg.message_id, msg.chat_id, msg.user_id, msg.username, msg.timestamp, msg.text, reply_id, reply_user, reply_text]
+            "INSERT OR REPLACE INTO messages (message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text, ocr_text, from_bot,
+                                              url_count, has_mention, forwarded, length_class, emoji_ratio, via_bot, reply_to_unknown, spoken,
+                                              location_lat, location_lon, location_title, location_address)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23)",
+            params![
+                msg.message_id, msg.chat_id, msg.user_id, msg.username, msg.timestamp, msg.text, reply_id, reply_user, reply_text, msg.ocr_text, msg.from_bot,
+                features.url_count as i64, features.has_mention, features.forwarded, features.length_class, features.emoji_ratio, features.via_bot, reply_unknown,
+                msg.spoken, location.map(|l| l.latitude), location.map(|l| l.longitude),
+                location.and_then(|l| l.title.clone()), location.and_then(|l| l.address.clone())
+            ]
         ).unwrap_or_else(|e| {
             warn!("Failed to insert message: {e}");
             0
@@ -262,40 +795,24 @@ impl Database {
             .unwrap_or(0) as usize
     }
 
-    /// Get recent messages up to a token budget.
-    pub fn get_recent_by_tokens(&self, max_tokens: usize) -> Vec<ChatMessage> {
+    /// Get recent messages up to a token budget, optionally only those sent at or after `since`.
+    pub fn get_recent_by_tokens(&self, max_tokens: usize, since: Option<DateTime<Utc>>) -> Vec<ChatMessage> {
         let chars_budget = max_tokens * 4;
         let conn = &self.conn;
+        // Timestamps are stored as "YYYY-MM-DD HH:MM", which compares correctly as text
+        let since = since.map(|t| t.format("%Y-%m-%d %H:%M").to_string());
 
         // Get recent messages in reverse order
-        let mut stmt = conn.prepare(
-            "SELECT message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text
-             FROM messages ORDER BY timestamp DESC, message_id DESC"
-        ).unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS}
+             FROM messages WHERE (?1 IS NULL OR timestamp >= ?1) AND expired = 0
+             ORDER BY timestamp DESC, message_id DESC"
+        )).unwrap();
 
         let mut total_chars = 0;
         let mut result: Vec<ChatMessage> = Vec::new();
 
-        let rows = stmt.query_map([], |row| {
-            let reply_to = row.get::<_, Option<i64>>(6)?.map(|id| ReplyTo {
-                message_id: id,
-                username: row.get::<_, String>(7).unwrap_or_default(),
-                text: row.get::<_, String>(8).unwrap_or_default(),
-            });
-
-            Ok(ChatMessage {
-                message_id: row.get(0)?,
-                chat_id: row.get(1)?,
-                user_id: row.get(2)?,
-                username: row.get(3)?,
-                timestamp: row.get(4)?,
-                text: row.get(5)?,
-                reply_to,
-                image: None,
-                voice_transcription: None,
-                documents: vec![],
-            })
-        }).unwrap();
+        let rows = stmt.query_map(params![since], Self::row_to_message).unwrap();
 
         for msg in rows.flatten() {
             let msg_chars = msg.format().len();
@@ -310,80 +827,624 @@ impl Database {
         result
     }
 
-    /// Execute a raw SELECT query and return results as formatted strings.
-    /// SECURITY: Only SELECT queries are allowed.
-    pub fn query(&self, sql: &str) -> Result<String, String> {
-        let sql_trimmed = sql.trim();
-
-        // Validate it's a SELECT query
-        if !sql_trimmed.to_uppercase().starts_with("SELECT") {
-            return Err("Only SELECT queries are allowed".to_string());
+    /// Select recent messages (see `get_recent_by_tokens`) and format them for
+    /// replaying into Claude. Shared by compaction restore and `/recap`.
+    pub fn replay_recent(&self, max_tokens: usize, since: Option<DateTime<Utc>>) -> ContextReplay {
+        let messages = self.get_recent_by_tokens(max_tokens, since);
+        let text = messages.iter().map(|m| m.format()).collect::<Vec<_>>().join("\n");
+        ContextReplay {
+            messages: messages.len(),
+            approx_tokens: text.len() / 4,
+            text,
         }
+    }
+
+    /// Look up a stored message by ID, unless it has expired.
+    fn get_message(&self, message_id: i64) -> Result<Option<ChatMessage>, String> {
+        self.conn
+            .query_row(
+                &format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE message_id = ?1 AND expired = 0"),
+                params![message_id],
+                Self::row_to_message,
+            )
+            .optional()
+            .map_err(|e| format!("Failed to load message {}: {}", message_id, e))
+    }
+
+    /// Whether the message was ever stored, expired or not.
+    pub fn has_message(&self, message_id: i64) -> Result<bool, String> {
+        self.conn
+            .query_row("SELECT 1 FROM messages WHERE message_id = ?1", params![message_id], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+            .map_err(|e| format!("Failed to look up message {}: {}", message_id, e))
+    }
 
-        // Block dangerous patterns
-        let sql_upper = sql_trimmed.to_uppercase();
-        for pattern in ["INSERT", "UPDATE", "DELETE", "DROP", "ALTER", "CREATE", "ATTACH", "DETACH"] {
-            if sql_upper.contains(pattern) {
-                return Err(format!("Query contains forbidden keyword: {pattern}"));
+    /// Walk the reply chain up from `message_id` (up to `max_depth` messages,
+    /// including the message itself), optionally with its direct replies.
+    /// Depth is capped at `MAX_THREAD_DEPTH` and output at a char budget.
+    pub fn get_thread(&self, message_id: i64, max_depth: usize, include_replies: bool) -> Result<Thread, String> {
+        let max_depth = max_depth.clamp(1, MAX_THREAD_DEPTH);
+        let mut chain = Vec::new();
+        let mut missing = None;
+        let mut truncated = false;
+        let mut chars = 0;
+
+        let mut next = Some(message_id);
+        while let Some(id) = next {
+            if chain.len() >= max_depth {
+                truncated = true;
+                break;
+            }
+            let Some(msg) = self.get_message(id)? else {
+                if chain.is_empty() {
+                    return Err(format!("Message {} not found", message_id));
+                }
+                missing = Some(id);
+                break;
+            };
+            let len = msg.format().len();
+            if !chain.is_empty() && chars + len > THREAD_CHAR_BUDGET {
+                truncated = true;
+                break;
+            }
+            chars += len;
+            next = msg.reply_to.as_ref().map(|r| r.message_id);
+            chain.push(msg);
+        }
+        chain.reverse();
+
+        let mut replies = Vec::new();
+        if include_replies {
+            let mut stmt = self.conn
+                .prepare(&format!(
+                    "SELECT {MESSAGE_COLUMNS} FROM messages WHERE reply_to_id = ?1 AND expired = 0
+                     ORDER BY timestamp, message_id LIMIT ?2"
+                ))
+                .map_err(|e| format!("Failed to query replies: {}", e))?;
+            let rows = stmt
+                .query_map(params![message_id, max_depth as i64], Self::row_to_message)
+                .map_err(|e| format!("Failed to query replies: {}", e))?;
+            for msg in rows {
+                let msg = msg.map_err(|e| format!("Failed to read reply: {}", e))?;
+                let len = msg.format().len();
+                if chars + len > THREAD_CHAR_BUDGET {
+                    truncated = true;
+                    break;
+                }
+                chars += len;
+                replies.push(msg);
             }
         }
 
-        let conn = &self.conn;
-        let mut stmt = conn.prepare(sql_trimmed)
-            .map_err(|e| format!("Query error: {e}"))?;
+        Ok(Thread { chain, replies, missing, truncated })
+    }
 
-        let column_count = stmt.column_count();
-        let column_names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
+    fn row_to_message(row: &rusqlite::Row) -> rusqlite::Result<ChatMessage> {
+        let reply_to = row.get::<_, Option<i64>>(6)?.map(|id| ReplyTo {
+            message_id: id,
+            username: row.get::<_, String>(7).unwrap_or_default(),
+            text: row.get::<_, String>(8).unwrap_or_default(),
+            unknown_origin: row.get(13).unwrap_or_default(),
+        });
+        let location = match (row.get::<_, Option<f64>>(15)?, row.get::<_, Option<f64>>(16)?) {
+            (Some(latitude), Some(longitude)) => Some(Location { latitude, longitude, title: row.get(17)?, address: row.get(18)? }),
+            _ => None,
+        };
 
-        let mut rows = stmt.query([])
-            .map_err(|e| format!("Query execution error: {e}"))?;
+        Ok(ChatMessage {
+            message_id: row.get(0)?,
+            chat_id: row.get(1)?,
+            user_id: row.get(2)?,
+            username: row.get(3)?,
+            timestamp: row.get(4)?,
+            text: row.get(5)?,
+            reply_to,
+            image: None,
+            voice_transcription: None,
+            ocr_text: row.get(9)?,
+            documents: vec![],
+            backfilled: false,
+            from_bot: row.get(10)?,
+            forwarded: row.get(11)?,
+            via_bot: row.get(12)?,
+            mentions_bot: false,
+            mentioned_user_ids: vec![],
+            lang: None,
+            spoken: row.get(14)?,
+            location,
+        })
+    }
 
-        let mut results: Vec<String> = Vec::new();
-        let mut row_count = 0;
-        const MAX_ROWS: usize = 100;
+    /// The last `limit` stored messages in `chat_id`, newest first.
+    pub fn recent_in_chat(&self, chat_id: i64, limit: usize) -> Result<Vec<ChatMessage>, String> {
+        let err = |e: rusqlite::Error| format!("Failed to read recent messages: {}", e);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE chat_id = ?1
+             ORDER BY timestamp DESC, message_id DESC LIMIT ?2"
+        )).map_err(err)?;
+        stmt.query_map(params![chat_id, limit as i64], Self::row_to_message)
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)
+    }
 
-        while let Some(row) = rows.next().map_err(|e| format!("Row fetch error: {e}"))? {
-            if row_count >= MAX_ROWS {
-                results.push(format!("... (truncated, showing first {} rows)", MAX_ROWS));
-                break;
-            }
+    /// The latest message `user_id` posted in a group (DMs are never quoted).
+    pub fn last_group_message_by(&self, user_id: i64) -> Result<Option<ChatMessage>, String> {
+        self.conn.query_row(
+            &format!(
+                "SELECT {MESSAGE_COLUMNS} FROM messages WHERE user_id = ?1 AND chat_id < 0
+                 ORDER BY timestamp DESC, message_id DESC LIMIT 1"
+            ),
+            params![user_id],
+            Self::row_to_message,
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read last message: {}", e))
+    }
 
-            let mut values: Vec<String> = Vec::new();
-            for (i, col_name) in column_names.iter().enumerate().take(column_count) {
-                let value: String = row.get::<_, rusqlite::types::Value>(i)
-                    .map(|v| match v {
-                        rusqlite::types::Value::Null => "NULL".to_string(),
-                        rusqlite::types::Value::Integer(n) => n.to_string(),
-                        rusqlite::types::Value::Real(f) => f.to_string(),
-                        rusqlite::types::Value::Text(s) => {
-                            // Use chars() to respect UTF-8 character boundaries
-                            if s.chars().count() > 100 {
-                                format!("{}...", s.chars().take(100).collect::<String>())
-                            } else {
-                                s
-                            }
-                        }
-                        rusqlite::types::Value::Blob(b) => format!("<blob {} bytes>", b.len()),
-                    })
-                    .unwrap_or_else(|_| "?".to_string());
-                values.push(format!("{}: {}", col_name, value));
+    /// Chats with stored messages.
+    pub fn known_chat_ids(&self) -> Result<Vec<i64>, String> {
+        let err = |e: rusqlite::Error| format!("Failed to read known chats: {}", e);
+        let mut stmt = self.conn.prepare("SELECT DISTINCT chat_id FROM messages ORDER BY chat_id").map_err(err)?;
+        stmt.query_map([], |row| row.get(0))
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)
+    }
+
+    /// Highest stored message ID in `chat_id`, if any.
+    pub fn latest_message_id(&self, chat_id: i64) -> Result<Option<i64>, String> {
+        self.conn.query_row("SELECT MAX(message_id) FROM messages WHERE chat_id = ?1", params![chat_id], |row| row.get(0))
+            .map_err(|e| format!("Failed to read latest message: {}", e))
+    }
+
+    /// Message statistics for `chat_id` since `since`.
+    pub fn chat_stats(&self, chat_id: i64, since: DateTime<Utc>) -> Result<ChatStats, String> {
+        let conn = &self.conn;
+        // Timestamps are stored as "YYYY-MM-DD HH:MM", which compares correctly as text
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let err = |e: rusqlite::Error| format!("Failed to compute chat stats: {}", e);
+
+        let (total_messages, active_users, avg_length) = conn.query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT user_id), COALESCE(AVG(LENGTH(text)), 0)
+             FROM messages WHERE chat_id = ?1 AND timestamp >= ?2",
+            params![chat_id, since],
+            |row| Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as usize, row.get::<_, f64>(2)?)),
+        ).map_err(err)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT user_id, MAX(username), COUNT(*) AS n
+             FROM messages WHERE chat_id = ?1 AND timestamp >= ?2
+             GROUP BY user_id ORDER BY n DESC, user_id LIMIT 5"
+        ).map_err(err)?;
+        let top_posters = stmt
+            .query_map(params![chat_id, since], |row| {
+                Ok(PosterCount {
+                    user_id: row.get(0)?,
+                    username: row.get(1)?,
+                    messages: row.get::<_, i64>(2)? as usize,
+                })
+            })
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)?;
+
+        let mut hourly = [0usize; 24];
+        let mut stmt = conn.prepare(
+            "SELECT CAST(substr(timestamp, 12, 2) AS INTEGER) AS hour, COUNT(*)
+             FROM messages WHERE chat_id = ?1 AND timestamp >= ?2
+             GROUP BY hour"
+        ).map_err(err)?;
+        let rows = stmt
+            .query_map(params![chat_id, since], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))
+            .map_err(err)?;
+        for row in rows {
+            let (hour, count) = row.map_err(err)?;
+            if let Some(slot) = hourly.get_mut(hour as usize) {
+                *slot = count as usize;
             }
-            results.push(values.join(" | "));
-            row_count += 1;
         }
 
-        if results.is_empty() {
-            Ok("No results".to_string())
-        } else {
-            Ok(format!("{} row(s):\n{}", row_count, results.join("\n")))
+        let mut reactions: Vec<(String, usize)> = Vec::new();
+        for (emoji, n) in self.standing_reactions(chat_id, &since, false)?.into_iter().flat_map(|m| m.reactions) {
+            match reactions.iter_mut().find(|(e, _)| *e == emoji) {
+                Some((_, total)) => *total += n,
+                None => reactions.push((emoji, n)),
+            }
         }
+        reactions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(ChatStats { total_messages, active_users, top_posters, hourly, avg_length, reactions })
     }
 
-    // ==================== MEMBER METHODS ====================
+    /// The `limit` messages of `chat_id` sent since `since` with the most
+    /// reactions standing on them, most first (ties: the earlier message).
+    /// `exclude_bot` leaves out the bot's own messages.
+    pub fn top_reacted_messages(
+        &self,
+        chat_id: i64,
+        since: DateTime<Utc>,
+        limit: usize,
+        exclude_bot: bool,
+    ) -> Result<Vec<ReactedMessage>, String> {
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let mut messages = self.standing_reactions(chat_id, &since, exclude_bot)?;
+        messages.sort_by_key(|m| (std::cmp::Reverse(m.total_reactions()), m.message_id));
+        messages.truncate(limit);
+        Ok(messages)
+    }
 
-    /// Import members from a JSON array.
-    pub fn import_members(&mut self, members_json: &str) -> Result<usize, String> {
-        #[derive(serde::Deserialize)]
+    /// Messages of `chat_id` sent since `since` (stored format) that have
+    /// reactions, in message order. A reaction stands if the user's latest
+    /// change of that emoji on the message added it.
+    fn standing_reactions(&self, chat_id: i64, since: &str, exclude_bot: bool) -> Result<Vec<ReactedMessage>, String> {
+        let err = |e: rusqlite::Error| format!("Failed to count reactions: {}", e);
+        let mut stmt = self.conn.prepare(
+            "SELECT m.message_id, m.user_id, m.username, m.text, m.timestamp, r.emoji, COUNT(*)
+             FROM reactions r JOIN messages m ON m.message_id = r.message_id AND m.chat_id = r.chat_id
+             WHERE r.id IN (SELECT MAX(id) FROM reactions WHERE chat_id = ?1 GROUP BY message_id, user_id, emoji)
+               AND r.added = 1 AND m.timestamp >= ?2 AND m.deleted = 0 AND (?3 = 0 OR m.from_bot = 0)
+             GROUP BY m.message_id, r.emoji
+             ORDER BY m.message_id, COUNT(*) DESC, r.emoji"
+        ).map_err(err)?;
+        let rows = stmt.query_map(params![chat_id, since, exclude_bot], |row| Ok((
+            ReactedMessage {
+                message_id: row.get(0)?,
+                user_id: row.get(1)?,
+                username: row.get(2)?,
+                text: row.get(3)?,
+                timestamp: row.get(4)?,
+                reactions: Vec::new(),
+            },
+            row.get::<_, String>(5)?,
+            row.get::<_, i64>(6)? as usize,
+        ))).map_err(err)?;
+
+        let mut messages: Vec<ReactedMessage> = Vec::new();
+        for row in rows {
+            let (message, emoji, n) = row.map_err(err)?;
+            match messages.last_mut() {
+                Some(last) if last.message_id == message.message_id => last.reactions.push((emoji, n)),
+                _ => messages.push(ReactedMessage { reactions: vec![(emoji, n)], ..message }),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The `limit` people (not bots) with the most group messages since
+    /// `since`, across all groups, most messages first.
+    pub fn top_group_posters(&self, since: DateTime<Utc>, limit: usize) -> Result<Vec<PosterCount>, String> {
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let err = |e: rusqlite::Error| format!("Failed to read top posters: {}", e);
+        let mut stmt = self.conn.prepare(
+            "SELECT user_id, MAX(username), COUNT(*) AS n
+             FROM messages WHERE chat_id < 0 AND user_id != 0 AND from_bot = 0 AND timestamp >= ?1
+             GROUP BY user_id ORDER BY n DESC, user_id LIMIT ?2"
+        ).map_err(err)?;
+        stmt.query_map(params![since, limit as i64], |row| {
+            Ok(PosterCount {
+                user_id: row.get(0)?,
+                username: row.get(1)?,
+                messages: row.get::<_, i64>(2)? as usize,
+            })
+        })
+        .map_err(err)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(err)
+    }
+
+    /// Texts of `user_id`'s group messages since `since`, oldest first.
+    pub fn group_texts_by(&self, user_id: i64, since: DateTime<Utc>) -> Result<Vec<String>, String> {
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let err = |e: rusqlite::Error| format!("Failed to read messages: {}", e);
+        let mut stmt = self.conn.prepare(
+            "SELECT text FROM messages WHERE user_id = ?1 AND chat_id < 0 AND timestamp >= ?2
+             ORDER BY timestamp, message_id"
+        ).map_err(err)?;
+        stmt.query_map(params![user_id, since], |row| row.get(0))
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)
+    }
+
+    /// IDs of `chat_id`'s messages since `since` not yet bulk deleted, newest
+    /// first, at most `limit`. Optionally only `user_id`'s, and only those
+    /// whose text contains `contains` (case-insensitive).
+    pub fn bulk_delete_candidates(
+        &self,
+        chat_id: i64,
+        user_id: Option<i64>,
+        since: DateTime<Utc>,
+        contains: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<i64>, String> {
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let err = |e: rusqlite::Error| format!("Failed to read messages: {}", e);
+        // System messages have user_id 0 and synthetic IDs <= 0
+        let mut stmt = self.conn.prepare(
+            "SELECT message_id, text FROM messages
+             WHERE chat_id = ?1 AND (?2 IS NULL OR user_id = ?2) AND user_id != 0 AND message_id > 0
+               AND deleted = 0 AND timestamp >= ?3
+             ORDER BY timestamp DESC, message_id DESC"
+        ).map_err(err)?;
+        let rows = stmt
+            .query_map(params![chat_id, user_id, since], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(err)?;
+        // SQLite's LIKE only folds ASCII case, so match in Rust
+        let needle = contains.map(str::to_lowercase);
+        let mut ids = Vec::new();
+        for row in rows {
+            let (message_id, text) = row.map_err(err)?;
+            if needle.as_ref().is_none_or(|needle| text.to_lowercase().contains(needle)) {
+                ids.push(message_id);
+                if ids.len() >= limit {
+                    break;
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Flag messages as bulk deleted.
+    pub fn mark_deleted(&self, chat_id: i64, message_ids: &[i64]) -> Result<(), String> {
+        let mut stmt = self.conn
+            .prepare("UPDATE messages SET deleted = 1 WHERE chat_id = ?1 AND message_id = ?2")
+            .map_err(|e| format!("Failed to mark messages deleted: {}", e))?;
+        for message_id in message_ids {
+            stmt.execute(params![chat_id, message_id]).map_err(|e| format!("Failed to mark messages deleted: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Flag all of `user_id`'s messages in `chat_id` as deleted, after a ban
+    /// that revoked them. Returns how many were newly flagged.
+    pub fn mark_user_deleted(&self, chat_id: i64, user_id: i64) -> Result<usize, String> {
+        self.conn
+            .execute(
+                "UPDATE messages SET deleted = 1 WHERE chat_id = ?1 AND user_id = ?2 AND deleted = 0",
+                params![chat_id, user_id],
+            )
+            .map_err(|e| format!("Failed to mark messages deleted: {}", e))
+    }
+
+    /// Move everything stored under chat `old` to `new`, for a group that
+    /// became a supergroup, in one transaction. Per-chat settings the new ID
+    /// already has win; counts and costs are added up. Returns how many
+    /// messages moved.
+    pub fn migrate_chat_id(&mut self, old: i64, new: i64) -> Result<usize, String> {
+        let err = |e: rusqlite::Error| format!("Failed to migrate chat {} to {}: {e}", old, new);
+        let tx = self.conn.transaction().map_err(err)?;
+        let messages = tx.execute("UPDATE messages SET chat_id = ?2 WHERE chat_id = ?1", params![old, new]).map_err(err)?;
+        tx.execute_batch(&format!("
+            UPDATE reminders SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE scheduled_messages SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE reactions SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE invite_links SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE chat_setting_changes SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE tool_errors SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE moderation_log SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE spam_image_hashes SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE spam_deletions SET chat_id = {new} WHERE chat_id = {old};
+            UPDATE OR IGNORE mutes SET chat_id = {new} WHERE chat_id = {old};
+            DELETE FROM mutes WHERE chat_id = {old};
+            UPDATE OR IGNORE bot_status SET chat_id = {new} WHERE chat_id = {old};
+            DELETE FROM bot_status WHERE chat_id = {old};
+            UPDATE OR IGNORE auto_delete_timers SET chat_id = {new} WHERE chat_id = {old};
+            DELETE FROM auto_delete_timers WHERE chat_id = {old};
+            INSERT INTO chat_languages (chat_id, lang, count)
+                SELECT {new}, lang, count FROM chat_languages WHERE chat_id = {old}
+                ON CONFLICT (chat_id, lang) DO UPDATE SET count = count + excluded.count;
+            DELETE FROM chat_languages WHERE chat_id = {old};
+            INSERT INTO cost_attribution (user_id, chat_id, date, usd, turns)
+                SELECT user_id, {new}, date, usd, turns FROM cost_attribution WHERE chat_id = {old}
+                ON CONFLICT (user_id, chat_id, date) DO UPDATE SET usd = usd + excluded.usd, turns = turns + excluded.turns;
+            DELETE FROM cost_attribution WHERE chat_id = {old};
+        ")).map_err(err)?;
+        tx.commit().map_err(err)?;
+        Ok(messages)
+    }
+
+    /// Remember `chat_id`'s auto-delete timer, set at `since`; 0 turns it off.
+    pub fn set_auto_delete_timer(&mut self, chat_id: i64, seconds: u32, since: DateTime<Utc>) -> Result<(), String> {
+        let result = if seconds == 0 {
+            self.conn.execute("DELETE FROM auto_delete_timers WHERE chat_id = ?1", params![chat_id])
+        } else {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO auto_delete_timers (chat_id, seconds, since) VALUES (?1, ?2, ?3)",
+                params![chat_id, seconds, since.format("%Y-%m-%d %H:%M").to_string()]
+            )
+        };
+        result.map(|_| ()).map_err(|e| format!("Failed to save auto-delete timer: {e}"))
+    }
+
+    /// Mark messages Telegram has auto-deleted by `now` as expired, so
+    /// context replays and threads skip them. Only messages sent since the
+    /// chat's timer was set count: it doesn't apply to older ones. Returns
+    /// how many were marked.
+    pub fn expire_auto_deleted(&mut self, now: DateTime<Utc>) -> Result<usize, String> {
+        let err = |e: rusqlite::Error| format!("Failed to expire auto-deleted messages: {e}");
+        let timers: Vec<(i64, u32, String)> = self.conn
+            .prepare("SELECT chat_id, seconds, since FROM auto_delete_timers")
+            .and_then(|mut stmt| stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?.collect())
+            .map_err(err)?;
+        let mut expired = 0;
+        for (chat_id, seconds, since) in timers {
+            let cutoff = (now - chrono::Duration::seconds(seconds.into())).format("%Y-%m-%d %H:%M").to_string();
+            expired += self.conn.execute(
+                "UPDATE messages SET expired = 1
+                 WHERE chat_id = ?1 AND expired = 0 AND timestamp >= ?2 AND timestamp < ?3",
+                params![chat_id, since, cutoff]
+            ).map_err(err)?;
+        }
+        Ok(expired)
+    }
+
+    /// Mark one message as expired, e.g. when Telegram says it's gone.
+    pub fn mark_expired(&self, chat_id: i64, message_id: i64) -> Result<(), String> {
+        self.conn
+            .execute("UPDATE messages SET expired = 1 WHERE chat_id = ?1 AND message_id = ?2", params![chat_id, message_id])
+            .map(|_| ())
+            .map_err(|e| format!("Failed to mark message {} expired: {e}", message_id))
+    }
+
+    /// Stream `chat_id`'s messages with `from <= timestamp <= to` (both in
+    /// "YYYY-MM-DD HH:MM") to `each`, oldest first, stopping after `limit`.
+    /// Returns true if there were more messages than `limit`.
+    pub fn export_messages(
+        &self,
+        chat_id: i64,
+        from: &str,
+        to: &str,
+        limit: usize,
+        mut each: impl FnMut(&ChatMessage) -> Result<(), String>,
+    ) -> Result<bool, String> {
+        let err = |e: rusqlite::Error| format!("Failed to export messages: {}", e);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages
+             WHERE chat_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3
+             ORDER BY timestamp, message_id LIMIT ?4"
+        )).map_err(err)?;
+        let rows = stmt
+            .query_map(params![chat_id, from, to, limit as i64 + 1], Self::row_to_message)
+            .map_err(err)?;
+
+        for (i, row) in rows.enumerate() {
+            if i == limit {
+                return Ok(true);
+            }
+            each(&row.map_err(err)?)?;
+        }
+        Ok(false)
+    }
+
+    /// Messages of a chat matching the filters, oldest first, cut short once
+    /// their formatted size passes `max_chars` (at least one is always kept).
+    /// `from`/`to` are inclusive "YYYY-MM-DD HH:MM" bounds. Also returns
+    /// whether matches were left out.
+    pub fn read_messages(
+        &self,
+        chat_id: i64,
+        from: Option<&str>,
+        to: Option<&str>,
+        username: Option<&str>,
+        take: Take,
+        max_chars: usize,
+    ) -> Result<(Vec<ChatMessage>, bool), String> {
+        let err = |e: rusqlite::Error| format!("Failed to read messages: {}", e);
+        let username = username.map(|u| u.trim_start_matches('@'));
+        let (order, count) = match take {
+            Take::Latest(count) => ("DESC", count),
+            Take::Earliest(count) => ("ASC", count),
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages
+             WHERE chat_id = ?1
+               AND (?2 IS NULL OR timestamp >= ?2)
+               AND (?3 IS NULL OR timestamp <= ?3)
+               AND (?4 IS NULL OR username = ?4 COLLATE NOCASE)
+             ORDER BY timestamp {order}, message_id {order} LIMIT ?5"
+        )).map_err(err)?;
+        let rows = stmt
+            .query_map(params![chat_id, from, to, username, count as i64 + 1], Self::row_to_message)
+            .map_err(err)?;
+
+        let mut total_chars = 0;
+        let mut messages = Vec::new();
+        let mut truncated = false;
+        for row in rows {
+            let msg = row.map_err(err)?;
+            let msg_chars = msg.format().len();
+            if messages.len() == count || (total_chars + msg_chars > max_chars && !messages.is_empty()) {
+                truncated = true;
+                break;
+            }
+            total_chars += msg_chars;
+            messages.push(msg);
+        }
+
+        if matches!(take, Take::Latest(_)) {
+            messages.reverse();
+        }
+        Ok((messages, truncated))
+    }
+
+    /// Execute a raw query and return results as formatted strings.
+    /// SECURITY: Only SELECT, EXPLAIN SELECT and schema pragmas are allowed
+    /// (see `read_only_sql`), and they run with `query_only` on.
+    pub fn query(&self, sql: &str) -> Result<String, String> {
+        let sql = read_only_sql::validate(sql)?;
+        self.query_read_only(sql)
+    }
+
+    /// Run `sql` with writes disabled on the connection.
+    fn query_read_only(&self, sql: &str) -> Result<String, String> {
+        self.conn.pragma_update(None, "query_only", true)
+            .map_err(|e| format!("Failed to make the connection read-only: {e}"))?;
+        let result = self.format_query(sql);
+        if let Err(e) = self.conn.pragma_update(None, "query_only", false) {
+            warn!("Failed to turn query_only back off: {e}");
+        }
+        result
+    }
+
+    fn format_query(&self, sql: &str) -> Result<String, String> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(sql)
+            .map_err(|e| format!("Query error: {e}"))?;
+
+        let column_count = stmt.column_count();
+        let column_names: Vec<String> = stmt.column_names().iter().map(|s| s.to_string()).collect();
+
+        let mut rows = stmt.query([])
+            .map_err(|e| format!("Query execution error: {e}"))?;
+
+        let mut results: Vec<String> = Vec::new();
+        let mut row_count = 0;
+        const MAX_ROWS: usize = 100;
+
+        while let Some(row) = rows.next().map_err(|e| format!("Row fetch error: {e}"))? {
+            if row_count >= MAX_ROWS {
+                results.push(format!("... (truncated, showing first {} rows)", MAX_ROWS));
+                break;
+            }
+
+            let mut values: Vec<String> = Vec::new();
+            for (i, col_name) in column_names.iter().enumerate().take(column_count) {
+                let value: String = row.get::<_, rusqlite::types::Value>(i)
+                    .map(|v| match v {
+                        rusqlite::types::Value::Null => "NULL".to_string(),
+                        rusqlite::types::Value::Integer(n) => n.to_string(),
+                        rusqlite::types::Value::Real(f) => f.to_string(),
+                        rusqlite::types::Value::Text(s) => {
+                            // Use chars() to respect UTF-8 character boundaries
+                            if s.chars().count() > 100 {
+                                format!("{}...", s.chars().take(100).collect::<String>())
+                            } else {
+                                s
+                            }
+                        }
+                        rusqlite::types::Value::Blob(b) => format!("<blob {} bytes>", b.len()),
+                    })
+                    .unwrap_or_else(|_| "?".to_string());
+                values.push(format!("{}: {}", col_name, value));
+            }
+            results.push(values.join(" | "));
+            row_count += 1;
+        }
+
+        if results.is_empty() {
+            Ok("No results".to_string())
+        } else {
+            Ok(format!("{} row(s):\n{}", row_count, results.join("\n")))
+        }
+    }
+
+    // ==================== MEMBER METHODS ====================
+
+    /// Import members from a JSON array.
+    pub fn import_members(&mut self, members_json: &str) -> Result<usize, String> {
+        #[derive(serde::Deserialize)]
         struct ImportMember {
             #[serde(alias = "id")]
             user_id: i64,
@@ -405,9 +1466,10 @@ impl Database {
                 .or_else(|| m.username.clone())
                 .unwrap_or_else(|| format!("User{}", m.user_id));
 
+            let normalized = names::normalize(m.username.as_deref().unwrap_or(&first_name));
             let result = conn.execute(
-                "INSERT OR IGNORE INTO users (user_id, username, first_name, join_date, status) VALUES (?1, ?2, ?3, ?4, 'member')",
-                params![m.user_id, m.username, first_name, timestamp]
+                "INSERT OR IGNORE INTO users (user_id, username, first_name, join_date, status, normalized_username) VALUES (?1, ?2, ?3, ?4, 'member', ?5)",
+                params![m.user_id, m.username, first_name, timestamp, normalized]
             );
 
             if let Ok(n) = result {
@@ -426,14 +1488,20 @@ impl Database {
     pub fn member_joined(&mut self, user_id: i64, username: Option<String>, first_name: String, timestamp: String) {
         let conn = &self.conn;
 
+        // A known user joining again keeps their original join date
         conn.execute(
-            "INSERT INTO users (user_id, username, first_name, join_date, status)
-             VALUES (?1, ?2, ?3, ?4, 'member')
+            "INSERT INTO users (user_id, username, first_name, join_date, status, normalized_username)
+             VALUES (?1, ?2, ?3, ?4, 'member', ?5)
              ON CONFLICT(user_id) DO UPDATE SET
                 username = ?2,
                 first_name = ?3,
-                status = 'member'",
-            params![user_id, username, first_name, timestamp]
+                status = 'member',
+                normalized_username = ?5,
+                last_rejoin_date = ?4,
+                rejoin_count = rejoin_count + 1,
+                invite_link = NULL,
+                invited_by = NULL",
+            params![user_id, username, first_name, timestamp, names::normalize(username.as_deref().unwrap_or(&first_name))]
         ).unwrap_or_else(|e| {
             warn!("Failed to record member join: {e}");
             0
@@ -442,6 +1510,38 @@ impl Database {
         info!("👋 Member joined: {} ({})", first_name, user_id);
     }
 
+    /// Record how a member's latest join happened. Each source (chat_member
+    /// update, service message) knows part of it, so missing parts keep
+    /// what's already stored.
+    pub fn record_invite(&mut self, user_id: i64, invite_link: Option<String>, invited_by: Option<i64>) -> Result<(), String> {
+        self.conn.execute(
+            "UPDATE users SET invite_link = COALESCE(?2, invite_link), invited_by = COALESCE(?3, invited_by)
+             WHERE user_id = ?1",
+            params![user_id, invite_link, invited_by]
+        ).map_err(|e| format!("Failed to record invite of {user_id}: {e}"))?;
+        Ok(())
+    }
+
+    /// Joins per invite link since `since`, most first. A rejoin counts at
+    /// its latest date, under its latest link.
+    pub fn joins_by_invite_link(&self, since: DateTime<Utc>) -> Result<Vec<InviteLinkJoins>, String> {
+        // Join dates are stored as "YYYY-MM-DD HH:MM", which compares correctly as text
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let err = |e: rusqlite::Error| format!("Failed to count joins by invite link: {e}");
+        let mut stmt = self.conn.prepare(
+            "SELECT invite_link, COUNT(*), SUM(status = 'banned') FROM users
+             WHERE invite_link IS NOT NULL AND COALESCE(last_rejoin_date, join_date) >= ?1
+             GROUP BY invite_link ORDER BY COUNT(*) DESC, invite_link"
+        ).map_err(err)?;
+        stmt.query_map(params![since], |row| Ok(InviteLinkJoins {
+            invite_link: row.get(0)?,
+            joins: row.get::<_, i64>(1)? as usize,
+            banned: row.get::<_, i64>(2)? as usize,
+        }))
+        .and_then(|rows| rows.collect())
+        .map_err(err)
+    }
+
     /// Record a member leaving.
     pub fn member_left(&mut self, user_id: i64) {
         let conn = &self.conn;
@@ -468,27 +1568,91 @@ impl Database {
         info!("🚫 Member banned: {}", user_id);
     }
 
+    /// Whether a user's recorded join date is at or after `since`.
+    /// False if the user is unknown or the join date isn't a timestamp (e.g. "imported").
+    pub fn joined_since(&self, user_id: i64, since: DateTime<Utc>) -> bool {
+        let join_date: Option<String> = self.conn.query_row(
+            "SELECT join_date FROM users WHERE user_id = ?1",
+            params![user_id],
+            |row| row.get(0),
+        ).ok();
+
+        join_date
+            .and_then(|d| chrono::NaiveDateTime::parse_from_str(&d, "%Y-%m-%d %H:%M").ok())
+            .is_some_and(|d| d.and_utc() >= since)
+    }
+
+    /// A user by ID, if they've been seen.
+    pub fn get_user(&self, user_id: i64) -> Result<Option<Member>, String> {
+        self.conn.query_row(
+            &format!("SELECT {MEMBER_COLUMNS}
+             FROM users WHERE user_id = ?1"),
+            params![user_id],
+            Self::row_to_member,
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read user {}: {}", user_id, e))
+    }
+
+    /// A user's stored record and group messages since `since`
+    /// ("YYYY-MM-DD HH:MM"), for get_user_info. Users never seen here have
+    /// no record and no messages.
+    pub fn user_activity(&self, user_id: i64, since: &str) -> Result<UserActivity, String> {
+        let member = self.get_user(user_id)?;
+        let recent_messages = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE user_id = ?1 AND chat_id < 0 AND timestamp >= ?2",
+            params![user_id, since],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to count messages of {}: {}", user_id, e))?;
+        Ok(UserActivity { member, recent_messages })
+    }
+
     /// Find a user by username (case-insensitive partial match).
     pub fn find_user_by_username(&self, username: &str) -> Option<Member> {
         let conn = &self.conn;
-        let pattern = format!("%{}%", username.to_lowercase());
+        // Normalized on both sides, so "Дмитрий", "🔥Дмитрий🔥" and "dmitriy" match;
+        // a "%" or "_" in the name is matched literally
+        let pattern = format!("%{}%", names::escape_like(&names::normalize(username)));
 
         conn.query_row(
-            "SELECT user_id, username, first_name, join_date, last_message_date, message_count, status
-             FROM users WHERE LOWER(username) LIKE ?1 LIMIT 1",
+            &format!("SELECT {MEMBER_COLUMNS}
+             FROM users WHERE normalized_username LIKE ?1 ESCAPE '\\' LIMIT 1"),
             params![pattern],
-            |row| Ok(Member {
-                user_id: row.get(0)?,
-                username: row.get(1)?,
-                first_name: row.get(2)?,
-                join_date: row.get(3)?,
-                last_message_date: row.get(4)?,
-                message_count: row.get::<_, i64>(5)? as u32,
-                status: MemberStatus::from_str(&row.get::<_, String>(6)?),
-            })
+            Self::row_to_member,
         ).ok()
     }
 
+    /// Current members whose recorded join date is at or after `since`, oldest first.
+    pub fn members_joined_since(&self, since: DateTime<Utc>) -> Result<Vec<Member>, String> {
+        // Join dates are stored as "YYYY-MM-DD HH:MM", which compares correctly as text
+        let since = since.format("%Y-%m-%d %H:%M").to_string();
+        let mut stmt = self.conn.prepare(
+            &format!("SELECT {MEMBER_COLUMNS}
+             FROM users WHERE status = 'member' AND join_date >= ?1 ORDER BY join_date")
+        ).map_err(|e| format!("Failed to query recent joiners: {}", e))?;
+        stmt.query_map(params![since], Self::row_to_member)
+            .and_then(|rows| rows.collect())
+            .map_err(|e| format!("Failed to query recent joiners: {}", e))
+    }
+
+    /// Map a `users` row (`MEMBER_COLUMNS`) to a `Member`.
+    fn row_to_member(row: &rusqlite::Row) -> rusqlite::Result<Member> {
+        Ok(Member {
+            user_id: row.get(0)?,
+            username: row.get(1)?,
+            first_name: row.get(2)?,
+            join_date: row.get(3)?,
+            last_message_date: row.get(4)?,
+            message_count: row.get::<_, i64>(5)? as u32,
+            status: MemberStatus::from_str(&row.get::<_, String>(6)?),
+            last_rejoin_date: row.get(7)?,
+            rejoin_count: row.get::<_, i64>(8)? as u32,
+            invite_link: row.get(9)?,
+            invited_by: row.get(10)?,
+        })
+    }
+
     /// Get members with optional filter.
     pub fn get_members(&self, filter: Option<&str>, days_inactive: Option<i64>, limit: usize) -> Vec<Member> {
         let conn = &self.conn;
@@ -497,16 +1661,17 @@ impl Database {
         let cutoff_str = cutoff.format("%Y-%m-%d %H:%M").to_string();
 
         let filter_str = filter.unwrap_or("all");
-        let sql = match filter_str {
-            "active" => "SELECT * FROM users WHERE status = 'member' AND last_message_date IS NOT NULL ORDER BY last_message_date ASC LIMIT ?1",
-            "inactive" => "SELECT * FROM users WHERE status = 'member' AND (last_message_date IS NULL OR last_message_date < ?2) ORDER BY COALESCE(last_message_date, join_date) ASC LIMIT ?1",
-            "never_posted" => "SELECT * FROM users WHERE status = 'member' AND last_message_date IS NULL ORDER BY join_date ASC LIMIT ?1",
-            "left" => "SELECT * FROM users WHERE status = 'left' ORDER BY join_date ASC LIMIT ?1",
-            "banned" => "SELECT * FROM users WHERE status = 'banned' ORDER BY join_date ASC LIMIT ?1",
-            _ => "SELECT * FROM users ORDER BY COALESCE(last_message_date, join_date) ASC LIMIT ?1",
+        let filter_sql = match filter_str {
+            "active" => "FROM users WHERE status = 'member' AND last_message_date IS NOT NULL ORDER BY last_message_date ASC LIMIT ?1",
+            "inactive" => "FROM users WHERE status = 'member' AND (last_message_date IS NULL OR last_message_date < ?2) ORDER BY COALESCE(last_message_date, join_date) ASC LIMIT ?1",
+            "never_posted" => "FROM users WHERE status = 'member' AND last_message_date IS NULL ORDER BY join_date ASC LIMIT ?1",
+            "left" => "FROM users WHERE status = 'left' ORDER BY join_date ASC LIMIT ?1",
+            "banned" => "FROM users WHERE status = 'banned' ORDER BY join_date ASC LIMIT ?1",
+            _ => "FROM users ORDER BY COALESCE(last_message_date, join_date) ASC LIMIT ?1",
         };
 
-        let mut stmt = conn.prepare(sql).unwrap();
+        let sql = format!("SELECT {MEMBER_COLUMNS} {filter_sql}");
+        let mut stmt = conn.prepare(&sql).unwrap();
         let limit_i64 = limit as i64;
 
         let mut results = Vec::new();
@@ -517,17 +1682,7 @@ impl Database {
         };
 
         while let Ok(Some(row)) = rows.next() {
-            if let Ok(member) = (|| -> rusqlite::Result<Member> {
-                Ok(Member {
-                    user_id: row.get(0)?,
-                    username: row.get(1)?,
-                    first_name: row.get(2)?,
-                    join_date: row.get(3)?,
-                    last_message_date: row.get(4)?,
-                    message_count: row.get::<_, i64>(5)? as u32,
-                    status: MemberStatus::from_str(&row.get::<_, String>(6)?),
-                })
-            })() {
+            if let Ok(member) = Self::row_to_member(row) {
                 results.push(member);
             }
         }
@@ -552,6 +1707,109 @@ impl Database {
             .unwrap_or(0) as usize
     }
 
+    // ==================== REACTION METHODS ====================
+
+    /// Store a reaction being added or removed by a user.
+    pub fn record_reaction(
+        &mut self,
+        chat_id: i64,
+        message_id: i64,
+        user_id: i64,
+        emoji: &str,
+        added: bool,
+    ) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT INTO reactions (chat_id, message_id, user_id, emoji, added, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![chat_id, message_id, user_id, emoji, added as i64, Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to record reaction: {e}"))?;
+        Ok(())
+    }
+
+    /// Whether the stored message was sent by the bot.
+    pub fn is_bot_message(&self, chat_id: i64, message_id: i64, bot_user_id: i64) -> Result<bool, String> {
+        self.conn.query_row(
+            "SELECT 1 FROM messages WHERE message_id = ?1 AND chat_id = ?2 AND user_id = ?3",
+            params![message_id, chat_id, bot_user_id],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(|e| format!("Failed to look up message {message_id}: {e}"))
+    }
+
+    /// Who sent a stored message, if it's stored.
+    pub fn message_author(&self, chat_id: i64, message_id: i64) -> Result<Option<i64>, String> {
+        self.conn.query_row(
+            "SELECT user_id FROM messages WHERE message_id = ?1 AND chat_id = ?2",
+            params![message_id, chat_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to look up message {message_id}: {e}"))
+    }
+
+    // ==================== LANGUAGE METHODS ====================
+
+    /// Count a detected language in the chat's histogram. Once the chat has
+    /// `HISTOGRAM_WINDOW` samples all its counts are halved, so the histogram
+    /// follows the chat's recent language.
+    pub fn record_language(&mut self, chat_id: i64, lang: &str) -> Result<(), String> {
+        let conn = &self.conn;
+        conn.execute(
+            "INSERT INTO chat_languages (chat_id, lang, count) VALUES (?1, ?2, 1)
+             ON CONFLICT(chat_id, lang) DO UPDATE SET count = count + 1",
+            params![chat_id, lang]
+        ).map_err(|e| format!("Failed to record language: {e}"))?;
+
+        let total: i64 = conn.query_row(
+            "SELECT SUM(count) FROM chat_languages WHERE chat_id = ?1",
+            params![chat_id],
+            |row| row.get(0),
+        ).map_err(|e| format!("Failed to sum language counts: {e}"))?;
+
+        if total >= language::HISTOGRAM_WINDOW {
+            conn.execute(
+                "UPDATE chat_languages SET count = count / 2 WHERE chat_id = ?1",
+                params![chat_id]
+            ).map_err(|e| format!("Failed to decay language counts: {e}"))?;
+            conn.execute(
+                "DELETE FROM chat_languages WHERE chat_id = ?1 AND count = 0",
+                params![chat_id]
+            ).map_err(|e| format!("Failed to prune language counts: {e}"))?;
+            debug!("Halved language histogram for chat {}", chat_id);
+        }
+        Ok(())
+    }
+
+    /// Most common language per chat, for chats with at least
+    /// `MIN_DOMINANT_SAMPLES` tagged messages where it holds a majority.
+    pub fn dominant_languages(&self) -> Result<Vec<(i64, String)>, String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT chat_id, lang, count, SUM(count) OVER (PARTITION BY chat_id) AS total
+             FROM chat_languages ORDER BY chat_id, count DESC, lang"
+        ).map_err(|e| format!("Failed to prepare dominant_languages query: {e}"))?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?))
+        }).map_err(|e| format!("Failed to query languages: {e}"))?;
+
+        let mut result: Vec<(i64, String)> = Vec::new();
+        let mut last_chat = None;
+        for row in rows {
+            let (chat_id, lang, count, total) = row.map_err(|e| format!("Failed to read language row: {e}"))?;
+            // Rows are sorted by count within each chat, so the first is the top language
+            if last_chat == Some(chat_id) {
+                continue;
+            }
+            last_chat = Some(chat_id);
+            if total >= language::MIN_DOMINANT_SAMPLES && count * 2 > total {
+                result.push((chat_id, lang));
+            }
+        }
+        Ok(result)
+    }
+
     // ==================== REMINDER METHODS ====================
 
     /// Create a new reminder. Returns the reminder ID.
@@ -578,14 +1836,41 @@ impl Database {
         Ok(id)
     }
 
+    /// Create a follow-up: a silent reminder that hands `note` back to Claude.
+    /// Fails once `MAX_FOLLOW_UPS` are pending. Returns the reminder ID.
+    pub fn create_follow_up(&mut self, chat_id: i64, note: &str, trigger_at: DateTime<Utc>) -> Result<i64, String> {
+        let conn = &self.conn;
+        let pending: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM reminders WHERE active = 1 AND kind = ?1",
+            params![ReminderKind::FollowUp.as_str()],
+            |row| row.get(0),
+        ).map_err(|e| format!("Failed to count follow-ups: {e}"))?;
+        if pending as usize >= reminders::MAX_FOLLOW_UPS {
+            return Err(format!(
+                "{} follow-ups already pending (max {}); cancel one with cancel_reminder first",
+                pending, reminders::MAX_FOLLOW_UPS
+            ));
+        }
+
+        conn.execute(
+            "INSERT INTO reminders (chat_id, user_id, message, trigger_at, created_at, active, kind)
+             VALUES (?1, 0, ?2, ?3, ?4, 1, ?5)",
+            params![chat_id, note, trigger_at.to_rfc3339(), Utc::now().to_rfc3339(), ReminderKind::FollowUp.as_str()]
+        ).map_err(|e| format!("Failed to create follow-up: {e}"))?;
+
+        let id = conn.last_insert_rowid();
+        info!("Created follow-up #{} for chat {} at {}", id, chat_id, trigger_at);
+        Ok(id)
+    }
+
     /// List active reminders, optionally filtered by chat_id.
     pub fn list_reminders(&self, chat_id: Option<i64>) -> Vec<Reminder> {
         let conn = &self.conn;
 
         let sql = match chat_id {
-            Some(_) => "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active
+            Some(_) => "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active, kind
                         FROM reminders WHERE active = 1 AND chat_id = ?1 ORDER BY trigger_at ASC",
-            None => "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active
+            None => "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active, kind
                      FROM reminders WHERE active = 1 ORDER BY trigger_at ASC",
         };
 
@@ -636,7 +1921,7 @@ impl Database {
         let now = Utc::now().to_rfc3339();
 
         let mut stmt = match conn.prepare(
-            "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active
+            "SELECT id, chat_id, user_id, message, trigger_at, repeat_cron, created_at, last_triggered_at, active, kind
              FROM reminders WHERE active = 1 AND trigger_at <= ?1 ORDER BY trigger_at ASC"
         ) {
             Ok(s) => s,
@@ -682,80 +1967,567 @@ impl Database {
         Ok(())
     }
 
-    /// Convert a database row to a Reminder struct.
-    fn row_to_reminder(row: &rusqlite::Row) -> rusqlite::Result<Reminder> {
-        let trigger_str: String = row.get(4)?;
-        let created_str: String = row.get(6)?;
-        let last_triggered_str: Option<String> = row.get(7)?;
+    // ==================== SCHEDULED MESSAGE METHODS ====================
 
-        let trigger_at = DateTime::parse_from_rfc3339(&trigger_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(|_| Utc::now());
-        let created_at = DateTime::parse_from_rfc3339(&created_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(|_| Utc::now());
-        let last_triggered_at = last_triggered_str.and_then(|s| {
-            DateTime::parse_from_rfc3339(&s)
-                .map(|dt| dt.with_timezone(&Utc))
-                .ok()
-        });
+    /// Schedule a one-off message. Fails once the chat has
+    /// `MAX_SCHEDULED_PER_CHAT` pending. Returns the scheduled message ID.
+    pub fn schedule_message(
+        &mut self,
+        chat_id: i64,
+        text: &str,
+        reply_to_message_id: Option<i64>,
+        send_at: DateTime<Utc>,
+    ) -> Result<i64, String> {
+        let conn = &self.conn;
+        let pending: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM scheduled_messages WHERE active = 1 AND chat_id = ?1",
+            params![chat_id],
+            |row| row.get(0),
+        ).map_err(|e| format!("Failed to count scheduled messages: {e}"))?;
+        if pending as usize >= reminders::MAX_SCHEDULED_PER_CHAT {
+            return Err(format!(
+                "Chat {} already has {} pending scheduled messages (max {})",
+                chat_id, pending, reminders::MAX_SCHEDULED_PER_CHAT
+            ));
+        }
 
-        Ok(Reminder {
-            id: row.get(0)?,
-            chat_id: row.get(1)?,
-            user_id: row.get(2)?,
-            message: row.get(3)?,
-            trigger_at,
-            repeat_cron: row.get(5)?,
-            created_at,
-            last_triggered_at,
-            active: row.get::<_, i64>(8)? == 1,
-        })
+        self.defer_message(chat_id, text, reply_to_message_id, send_at)
     }
-}
 
-impl Default for Database {
-    fn default() -> Self {
-        Self::new()
+    /// Queue a message the bot held back (quiet hours) until `send_at`. Not
+    /// capped, unlike `schedule_message`. Returns the scheduled message ID.
+    pub fn defer_message(
+        &mut self,
+        chat_id: i64,
+        text: &str,
+        reply_to_message_id: Option<i64>,
+        send_at: DateTime<Utc>,
+    ) -> Result<i64, String> {
+        self.conn.execute(
+            "INSERT INTO scheduled_messages (chat_id, text, reply_to_message_id, send_at, created_at, active)
+             VALUES (?1, ?2, ?3, ?4, ?5, 1)",
+            params![chat_id, text, reply_to_message_id, send_at.to_rfc3339(), Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to schedule message: {e}"))?;
+
+        let id = self.conn.last_insert_rowid();
+        info!("Scheduled message #{} for chat {} at {}", id, chat_id, send_at);
+        Ok(id)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// List pending scheduled messages, optionally filtered by chat_id.
+    pub fn list_scheduled(&self, chat_id: Option<i64>) -> Result<Vec<ScheduledMessage>, String> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+            "SELECT id, chat_id, text, reply_to_message_id, send_at FROM scheduled_messages
+             WHERE active = 1 AND (?1 IS NULL OR chat_id = ?1) ORDER BY send_at ASC"
+        ).map_err(|e| format!("Failed to prepare list_scheduled query: {e}"))?;
+
+        stmt.query_map(params![chat_id], Self::row_to_scheduled)
+            .map_err(|e| format!("Failed to list scheduled messages: {e}"))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| format!("Failed to read scheduled message: {e}"))
+    }
 
-    fn make_msg(id: i64, user_id: i64, username: &str, timestamp: &str, text: &str) -> ChatMessage {
-        ChatMessage {
-            message_id: id,
-            chat_id: -12345,
-            user_id,
-            username: username.to_string(),
-            timestamp: timestamp.to_string(),
-            text: text.to_string(),
-            reply_to: None,
-            image: None,
-            voice_transcription: None,
-            documents: vec![],
+    /// Cancel a scheduled message by ID. Returns true if found and cancelled.
+    pub fn cancel_scheduled(&mut self, scheduled_id: i64) -> Result<bool, String> {
+        let rows = self.conn.execute(
+            "UPDATE scheduled_messages SET active = 0 WHERE id = ?1 AND active = 1",
+            params![scheduled_id]
+        ).map_err(|e| format!("Failed to cancel scheduled message: {e}"))?;
+
+        if rows > 0 {
+            info!("Cancelled scheduled message #{}", scheduled_id);
         }
+        Ok(rows > 0)
     }
 
-    #[test]
-    fn test_add_message_creates_member() {
-        let mut db = Database::new();
-        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
+    /// Pending scheduled messages due at or before `now`, oldest first.
+    pub fn get_due_scheduled(&self, now: DateTime<Utc>) -> Result<Vec<ScheduledMessage>, String> {
+        let conn = &self.conn;
+        let mut stmt = conn.prepare(
+            "SELECT id, chat_id, text, reply_to_message_id, send_at FROM scheduled_messages
+             WHERE active = 1 AND send_at <= ?1 ORDER BY send_at ASC"
+        ).map_err(|e| format!("Failed to prepare get_due_scheduled query: {e}"))?;
+
+        stmt.query_map(params![now.to_rfc3339()], Self::row_to_scheduled)
+            .map_err(|e| format!("Failed to query due scheduled messages: {e}"))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| format!("Failed to read scheduled message: {e}"))
+    }
 
-        assert_eq!(db.message_count(), 1);
-        assert!(db.find_user_by_username("alice").is_some());
+    /// Mark a scheduled message as handled so it isn't sent again.
+    pub fn mark_scheduled_sent(&mut self, scheduled_id: i64) -> Result<(), String> {
+        self.conn.execute(
+            "UPDATE scheduled_messages SET active = 0 WHERE id = ?1",
+            params![scheduled_id]
+        ).map_err(|e| format!("Failed to mark scheduled message sent: {e}"))?;
+        debug!("Marked scheduled message #{} as sent", scheduled_id);
+        Ok(())
     }
 
-    #[test]
-    fn test_query_basic() {
-        let mut db = Database::new();
-        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
-        db.add_message(make_msg(2, 101, "bob", "2024-01-15 10:01", "world"));
+    /// Record an active mute, replacing any earlier one for the user in that chat.
+    pub fn record_mute(&mut self, chat_id: i64, user_id: i64, until: DateTime<Utc>) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO mutes (chat_id, user_id, until, muted_at) VALUES (?1, ?2, ?3, ?4)",
+            params![chat_id, user_id, until.to_rfc3339(), Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to record mute: {e}"))?;
+        Ok(())
+    }
 
-        let result = db.query("SELECT COUNT(*) as count FROM messages").unwrap();
-        assert!(result.contains("2"));
+    /// Forget a mute lifted early. Returns true if one was recorded.
+    pub fn remove_mute(&mut self, chat_id: i64, user_id: i64) -> Result<bool, String> {
+        let rows = self.conn.execute(
+            "DELETE FROM mutes WHERE chat_id = ?1 AND user_id = ?2",
+            params![chat_id, user_id]
+        ).map_err(|e| format!("Failed to remove mute: {e}"))?;
+        Ok(rows > 0)
+    }
+
+    /// Log a moderation action ("mute", "delete", "ban", "kick") taken
+    /// against a user. Returns how many were logged against them in that
+    /// chat before it, so 0 means a first offense.
+    pub fn record_moderation(&mut self, chat_id: i64, user_id: i64, action: &str, at: DateTime<Utc>) -> Result<usize, String> {
+        let err = |e: rusqlite::Error| format!("Failed to log moderation of {user_id}: {e}");
+        let prior: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM moderation_log WHERE chat_id = ?1 AND user_id = ?2",
+            params![chat_id, user_id],
+            |row| row.get(0),
+        ).map_err(err)?;
+        self.conn.execute(
+            "INSERT INTO moderation_log (chat_id, user_id, action, at) VALUES (?1, ?2, ?3, ?4)",
+            params![chat_id, user_id, action, at.to_rfc3339()]
+        ).map_err(err)?;
+        Ok(prior as usize)
+    }
+
+    /// Moderation actions in `chat_id` from `from` up to `to`, oldest first.
+    pub fn moderation_between(&self, chat_id: i64, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<ModerationEntry>, String> {
+        let err = |e: rusqlite::Error| format!("Failed to read moderation log: {e}");
+        let mut stmt = self.conn.prepare(
+            "SELECT m.user_id, u.username, u.first_name, m.action
+             FROM moderation_log m LEFT JOIN users u ON u.user_id = m.user_id
+             WHERE m.chat_id = ?1 AND m.at >= ?2 AND m.at < ?3
+             ORDER BY m.id"
+        ).map_err(err)?;
+        stmt.query_map(params![chat_id, from.to_rfc3339(), to.to_rfc3339()], |row| {
+            Ok(ModerationEntry { user_id: row.get(0)?, username: row.get(1)?, first_name: row.get(2)?, action: row.get(3)? })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(err)
+    }
+
+    /// Log a message the spam filter deleted.
+    pub fn record_spam_deletion(&mut self, chat_id: i64, at: DateTime<Utc>) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT INTO spam_deletions (chat_id, at) VALUES (?1, ?2)",
+            params![chat_id, at.to_rfc3339()]
+        ).map_err(|e| format!("Failed to log spam deletion: {e}"))?;
+        Ok(())
+    }
+
+    /// How many messages the spam filter deleted in `chat_id` from `from` up to `to`.
+    pub fn spam_deletions_between(&self, chat_id: i64, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<usize, String> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM spam_deletions WHERE chat_id = ?1 AND at >= ?2 AND at < ?3",
+            params![chat_id, from.to_rfc3339(), to.to_rfc3339()],
+            |row| row.get::<_, i64>(0),
+        ).map(|n| n as usize).map_err(|e| format!("Failed to count spam deletions: {e}"))
+    }
+
+    /// Drop mutes that ended at or before `now`, so the table only holds
+    /// users muted right now. Returns how many were removed.
+    pub fn prune_expired_mutes(&mut self, now: DateTime<Utc>) -> Result<usize, String> {
+        self.conn.execute(
+            "DELETE FROM mutes WHERE until <= ?1",
+            params![now.to_rfc3339()]
+        ).map_err(|e| format!("Failed to prune expired mutes: {e}"))
+    }
+
+    /// Remember the hash of an image posted with spam until `expires_at`,
+    /// extending it if the image was already known.
+    pub fn record_spam_image(&mut self, hash: &str, chat_id: i64, expires_at: DateTime<Utc>) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO spam_image_hashes (hash, chat_id, expires_at) VALUES (?1, ?2, ?3)",
+            params![hash, chat_id, expires_at.to_rfc3339()]
+        ).map_err(|e| format!("Failed to record spam image: {e}"))?;
+        Ok(())
+    }
+
+    /// Whether an image with `hash` was posted with spam and hasn't expired by `now`.
+    pub fn is_spam_image(&self, hash: &str, now: DateTime<Utc>) -> Result<bool, String> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM spam_image_hashes WHERE hash = ?1 AND expires_at > ?2)",
+            params![hash, now.to_rfc3339()],
+            |row| row.get(0),
+        ).map_err(|e| format!("Failed to look up spam image: {e}"))
+    }
+
+    /// Drop spam image hashes expired by `now`. Returns how many were removed.
+    pub fn prune_spam_images(&mut self, now: DateTime<Utc>) -> Result<usize, String> {
+        self.conn.execute(
+            "DELETE FROM spam_image_hashes WHERE expires_at <= ?1",
+            params![now.to_rfc3339()]
+        ).map_err(|e| format!("Failed to prune spam images: {e}"))
+    }
+
+    /// Record an invite link created on someone's behalf.
+    pub fn record_invite_link(
+        &mut self,
+        chat_id: i64,
+        invite_link: &str,
+        name: Option<&str>,
+        creator_id: i64,
+        expire_at: Option<DateTime<Utc>>,
+        member_limit: Option<u32>,
+    ) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT INTO invite_links (chat_id, invite_link, name, creator_id, expire_at, member_limit, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![chat_id, invite_link, name, creator_id, expire_at.map(|t| t.to_rfc3339()), member_limit, Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to record invite link: {e}"))?;
+        Ok(())
+    }
+
+    /// Mark an invite link revoked. Returns true if it was recorded and still active.
+    pub fn mark_invite_link_revoked(&mut self, chat_id: i64, invite_link: &str) -> Result<bool, String> {
+        let rows = self.conn.execute(
+            "UPDATE invite_links SET revoked_at = ?3 WHERE chat_id = ?1 AND invite_link = ?2 AND revoked_at IS NULL",
+            params![chat_id, invite_link, Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to mark invite link revoked: {e}"))?;
+        Ok(rows > 0)
+    }
+
+    /// Log a change to a chat setting (`chat_settings::SLOW_MODE_SETTING` or
+    /// `PERMISSIONS_SETTING`).
+    pub fn record_chat_setting(&mut self, chat_id: i64, setting: &str, value: &str, changed_by: i64) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT INTO chat_setting_changes (chat_id, setting, value, changed_by, changed_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![chat_id, setting, value, changed_by, Utc::now().to_rfc3339()]
+        ).map_err(|e| format!("Failed to record chat setting: {e}"))?;
+        Ok(())
+    }
+
+    /// Chats whose latest slow mode change left it on, with the delay in seconds.
+    pub fn active_slow_modes(&self) -> Result<Vec<(i64, u32)>, String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT chat_id, value FROM chat_setting_changes
+             WHERE id IN (SELECT MAX(id) FROM chat_setting_changes WHERE setting = ?1 GROUP BY chat_id)"
+        ).map_err(|e| format!("Failed to prepare slow mode query: {e}"))?;
+        let rows = stmt.query_map(params![SLOW_MODE_SETTING], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| format!("Failed to query slow modes: {e}"))?;
+        let mut active = Vec::new();
+        for row in rows {
+            let (chat_id, value) = row.map_err(|e| format!("Failed to read slow mode: {e}"))?;
+            match value.parse::<u32>() {
+                Ok(0) => {}
+                Ok(seconds) => active.push((chat_id, seconds)),
+                Err(_) => warn!("Ignoring slow mode '{}' recorded for chat {}", value, chat_id),
+            }
+        }
+        Ok(active)
+    }
+
+    /// Record the bot's status in a chat. `since` only moves when the status
+    /// changes. Returns the previous status, if one was recorded.
+    pub fn record_bot_status(&mut self, chat_id: i64, status: BotStatus, at: DateTime<Utc>) -> Result<Option<BotStatus>, String> {
+        let previous = self.bot_status(chat_id)?.map(|s| s.status);
+        if previous != Some(status) {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO bot_status (chat_id, status, since) VALUES (?1, ?2, ?3)",
+                params![chat_id, status.as_str(), at.to_rfc3339()]
+            ).map_err(|e| format!("Failed to record bot status: {e}"))?;
+        }
+        Ok(previous)
+    }
+
+    /// The bot's recorded status in a chat.
+    pub fn bot_status(&self, chat_id: i64) -> Result<Option<BotChatStatus>, String> {
+        let row: Option<(String, String)> = self.conn.query_row(
+            "SELECT status, since FROM bot_status WHERE chat_id = ?1",
+            params![chat_id],
+            |row| Ok((row.get(0)?, row.get(1)?))
+        ).optional().map_err(|e| format!("Failed to read bot status: {e}"))?;
+        let Some((status, since)) = row else {
+            return Ok(None);
+        };
+        let status = BotStatus::parse(&status).ok_or_else(|| format!("Unknown bot status '{}'", status))?;
+        let since = DateTime::parse_from_rfc3339(&since)
+            .map_err(|e| format!("Invalid bot status time '{}': {e}", since))?
+            .with_timezone(&Utc);
+        Ok(Some(BotChatStatus { status, since }))
+    }
+
+    /// Count a DM from a user who isn't allowed to DM the bot. Returns how
+    /// many they've sent, including this one, since their first DM less
+    /// than `expire_after` ago. Older records are dropped, as are the oldest
+    /// beyond `MAX_UNKNOWN_DMS`.
+    pub fn record_unknown_dm(&mut self, user_id: i64, at: DateTime<Utc>, expire_after: chrono::Duration) -> Result<u32, String> {
+        let err = |e: rusqlite::Error| format!("Failed to record unknown DM: {e}");
+        self.conn.execute(
+            "DELETE FROM unknown_dms WHERE first_seen < ?1",
+            params![(at - expire_after).to_rfc3339()],
+        ).map_err(err)?;
+        let nth = self.conn.query_row(
+            "INSERT INTO unknown_dms (user_id, messages, first_seen) VALUES (?1, 1, ?2)
+             ON CONFLICT(user_id) DO UPDATE SET messages = messages + 1
+             RETURNING messages",
+            params![user_id, at.to_rfc3339()],
+            |row| row.get(0)
+        ).map_err(err)?;
+        self.conn.execute(
+            "DELETE FROM unknown_dms WHERE user_id IN (
+                 SELECT user_id FROM unknown_dms ORDER BY first_seen DESC, user_id DESC LIMIT -1 OFFSET ?1
+             )",
+            params![MAX_UNKNOWN_DMS as i64],
+        ).map_err(err)?;
+        Ok(nth)
+    }
+
+    /// Forget the DM counts of users who now may DM the bot. Returns how
+    /// many were dropped.
+    pub fn forget_unknown_dms(&mut self, user_ids: &[i64]) -> Result<usize, String> {
+        let mut forgotten = 0;
+        for user_id in user_ids {
+            forgotten += self.conn.execute("DELETE FROM unknown_dms WHERE user_id = ?1", params![user_id])
+                .map_err(|e| format!("Failed to forget unknown DMs of {}: {e}", user_id))?;
+        }
+        Ok(forgotten)
+    }
+
+    /// Record a failed tool call.
+    pub fn record_tool_error(&mut self, failure: &ToolError) -> Result<(), String> {
+        self.conn.execute(
+            "INSERT INTO tool_errors (tool, error, chat_id, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            params![failure.tool, failure.error, failure.chat_id, failure.timestamp.to_rfc3339()]
+        ).map_err(|e| format!("Failed to record tool error: {e}"))?;
+        Ok(())
+    }
+
+    /// Tool failures at or after `since`, oldest first.
+    pub fn tool_errors_since(&self, since: DateTime<Utc>) -> Result<Vec<ToolError>, String> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tool, error, chat_id, timestamp FROM tool_errors WHERE timestamp >= ?1 ORDER BY timestamp, id"
+        ).map_err(|e| format!("Failed to prepare query: {e}"))?;
+        let rows = stmt.query_map(params![since.to_rfc3339()], |row| {
+            let timestamp: String = row.get(3)?;
+            Ok(ToolError {
+                tool: row.get(0)?,
+                error: row.get(1)?,
+                chat_id: row.get(2)?,
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now()),
+            })
+        }).map_err(|e| format!("Failed to read tool errors: {e}"))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(|e| format!("Failed to read tool errors: {e}"))
+    }
+
+    /// Add a turn's cost shares to `date`'s totals, one turn per share.
+    pub fn add_costs(&mut self, shares: &[CostShare], date: NaiveDate) -> Result<(), String> {
+        let err = |e: rusqlite::Error| format!("Failed to record costs: {e}");
+        let tx = self.conn.transaction().map_err(err)?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO cost_attribution (user_id, chat_id, date, usd, turns) VALUES (?1, ?2, ?3, ?4, 1)
+                 ON CONFLICT (user_id, chat_id, date) DO UPDATE SET usd = usd + excluded.usd, turns = turns + 1"
+            ).map_err(err)?;
+            for share in shares {
+                let usd = share.micro_usd as f64 / 1e6;
+                stmt.execute(params![share.user_id, share.chat_id, date.to_string(), usd]).map_err(err)?;
+            }
+        }
+        tx.commit().map_err(err)
+    }
+
+    /// Attributed spend on `since` and later days, with the `limit` most
+    /// expensive chats and users.
+    pub fn cost_summary(&self, since: NaiveDate, limit: usize) -> Result<CostSummary, String> {
+        let err = |e: rusqlite::Error| format!("Failed to read costs: {e}");
+        let since = since.to_string();
+        let total_usd: f64 = self.conn
+            .query_row("SELECT COALESCE(SUM(usd), 0) FROM cost_attribution WHERE date >= ?1", params![since], |row| row.get(0))
+            .map_err(err)?;
+        // DMs have the user's ID as chat ID, which names them
+        let top = |group_by: &str| -> Result<Vec<Spender>, String> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT c.{group_by}, COALESCE(u.username, u.first_name), SUM(c.usd), SUM(c.turns)
+                 FROM cost_attribution c LEFT JOIN users u ON u.user_id = c.{group_by}
+                 WHERE c.date >= ?1
+                 GROUP BY c.{group_by} ORDER BY SUM(c.usd) DESC LIMIT ?2"
+            )).map_err(err)?;
+            stmt.query_map(params![since, limit as i64], |row| {
+                Ok(Spender { id: row.get(0)?, name: row.get(1)?, usd: row.get(2)?, turns: row.get(3)? })
+            })
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)
+        };
+        Ok(CostSummary { total_usd, chats: top("chat_id")?, users: top("user_id")? })
+    }
+
+    /// Drop tool failures older than `before`. Returns how many were removed.
+    pub fn prune_tool_errors(&mut self, before: DateTime<Utc>) -> Result<usize, String> {
+        self.conn.execute(
+            "DELETE FROM tool_errors WHERE timestamp < ?1",
+            params![before.to_rfc3339()]
+        ).map_err(|e| format!("Failed to prune tool errors: {e}"))
+    }
+
+    fn row_to_scheduled(row: &rusqlite::Row) -> rusqlite::Result<ScheduledMessage> {
+        let send_at_str: String = row.get(4)?;
+        let send_at = DateTime::parse_from_rfc3339(&send_at_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        Ok(ScheduledMessage {
+            id: row.get(0)?,
+            chat_id: row.get(1)?,
+            text: row.get(2)?,
+            reply_to_message_id: row.get(3)?,
+            send_at,
+        })
+    }
+
+    /// Convert a database row to a Reminder struct.
+    fn row_to_reminder(row: &rusqlite::Row) -> rusqlite::Result<Reminder> {
+        let trigger_str: String = row.get(4)?;
+        let created_str: String = row.get(6)?;
+        let last_triggered_str: Option<String> = row.get(7)?;
+
+        let trigger_at = DateTime::parse_from_rfc3339(&trigger_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        let created_at = DateTime::parse_from_rfc3339(&created_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+        let last_triggered_at = last_triggered_str.and_then(|s| {
+            DateTime::parse_from_rfc3339(&s)
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok()
+        });
+
+        Ok(Reminder {
+            id: row.get(0)?,
+            chat_id: row.get(1)?,
+            user_id: row.get(2)?,
+            message: row.get(3)?,
+            trigger_at,
+            repeat_cron: row.get(5)?,
+            created_at,
+            last_triggered_at,
+            active: row.get::<_, i64>(8)? == 1,
+            kind: ReminderKind::parse(&row.get::<_, String>(9)?),
+        })
+    }
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Job = Box<dyn FnOnce(&mut Database) + Send>;
+
+/// Async handle to a `Database` running on a dedicated worker thread.
+///
+/// Cheap to clone. Jobs run one at a time in submission order.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    tx: mpsc::Sender<Job>,
+}
+
+impl AsyncDatabase {
+    /// Move `db` onto a new worker thread.
+    pub fn new(mut db: Database) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Job>(WORKER_QUEUE_SIZE);
+
+        std::thread::Builder::new()
+            .name("database".to_string())
+            .spawn(move || {
+                while let Some(job) = rx.blocking_recv() {
+                    // A panicking job fails its own call; the worker keeps serving the rest
+                    if let Err(panic) = std::panic::catch_unwind(AssertUnwindSafe(|| job(&mut db))) {
+                        let reason = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "unknown panic".to_string());
+                        error!("Database job panicked: {}", reason);
+                    }
+                }
+                debug!("Database worker stopped");
+            })
+            .expect("Failed to spawn database worker");
+
+        Self { tx }
+    }
+
+    /// Run `f` against the database on the worker thread and return its result.
+    pub async fn call<F, R>(&self, f: F) -> Result<R, String>
+    where
+        F: FnOnce(&mut Database) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let job: Job = Box::new(move |db| {
+            // Receiver may have been dropped (caller cancelled); nothing to do then
+            let _ = result_tx.send(f(db));
+        });
+
+        self.tx.send(job).await
+            .map_err(|_| "Database worker is not running".to_string())?;
+
+        result_rx.await
+            .map_err(|_| "Database worker dropped the request (panicked?)".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::chat_settings::PERMISSIONS_SETTING;
+    use std::time::Instant;
+
+    fn make_msg(id: i64, user_id: i64, username: &str, timestamp: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            message_id: id,
+            chat_id: -12345,
+            user_id,
+            username: username.to_string(),
+            timestamp: timestamp.to_string(),
+            text: text.to_string(),
+            reply_to: None,
+            image: None,
+            voice_transcription: None,
+            ocr_text: None,
+            documents: vec![],
+            backfilled: false,
+            from_bot: false,
+            forwarded: false,
+            via_bot: None,
+            mentions_bot: false,
+            mentioned_user_ids: vec![],
+            lang: None,
+            spoken: false,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_add_message_creates_member() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
+
+        assert_eq!(db.message_count(), 1);
+        assert!(db.find_user_by_username("alice").is_some());
+    }
+
+    #[test]
+    fn test_query_basic() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
+        db.add_message(make_msg(2, 101, "bob", "2024-01-15 10:01", "world"));
+
+        let result = db.query("SELECT COUNT(*) as count FROM messages").unwrap();
+        assert!(result.contains("2"));
     }
 
     #[test]
@@ -765,6 +2537,31 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_user_activity() {
+        let mut db = Database::new();
+        db.member_joined(100, Some("alice".to_string()), "Alice".to_string(), "2024-01-01 09:00".to_string());
+        db.add_message(make_msg(1, 100, "alice", "2024-01-05 10:00", "old"));
+        db.add_message(make_msg(2, 100, "alice", "2024-01-14 10:00", "recent"));
+        db.add_message(make_msg(3, 100, "alice", "2024-01-15 10:00", "recent too"));
+        db.add_message(make_msg(4, 101, "bob", "2024-01-15 10:00", "someone else"));
+        let mut dm = make_msg(5, 100, "alice", "2024-01-15 11:00", "a DM");
+        dm.chat_id = 100;
+        db.add_message(dm);
+
+        let activity = db.user_activity(100, "2024-01-08 12:00").unwrap();
+        // The DM isn't group activity
+        assert_eq!(activity.recent_messages, 2);
+        let member = activity.member.unwrap();
+        assert_eq!(member.join_date, "2024-01-01 09:00");
+        assert_eq!(member.last_message_date.as_deref(), Some("2024-01-15 11:00"));
+
+        // Never seen here
+        let activity = db.user_activity(999, "2024-01-08 12:00").unwrap();
+        assert!(activity.member.is_none());
+        assert_eq!(activity.recent_messages, 0);
+    }
+
     #[test]
     fn test_query_rejects_drop() {
         let db = Database::new();
@@ -772,6 +2569,34 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_query_schema_and_literals() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "i want to update you"));
+
+        let result = db.query("SELECT text FROM messages WHERE text LIKE '%update you%'").unwrap();
+        assert!(result.contains("text: i want to update you"), "{}", result);
+        let columns = db.query("PRAGMA table_info(messages)").unwrap();
+        assert!(columns.contains("name: reply_to_unknown"), "{}", columns);
+        assert!(db.query("EXPLAIN QUERY PLAN SELECT * FROM messages WHERE user_id = 100").is_ok());
+    }
+
+    #[test]
+    fn test_query_connection_is_read_only() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
+
+        // Even without validation, writes fail
+        let err = db.query_read_only("DELETE FROM messages").unwrap_err();
+        assert!(err.contains("readonly"), "{}", err);
+        assert!(db.query_read_only("CREATE TABLE evil (x)").is_err());
+        assert_eq!(db.message_count(), 1);
+
+        // And the connection is writable again afterwards
+        db.add_message(make_msg(2, 100, "alice", "2024-01-15 10:01", "again"));
+        assert_eq!(db.message_count(), 2);
+    }
+
     #[test]
     fn test_member_status_changes() {
         let mut db = Database::new();
@@ -793,6 +2618,77 @@ mod tests {
         assert_eq!(member.status, MemberStatus::Banned);
     }
 
+    #[test]
+    fn test_rejoin_keeps_original_join_date() {
+        let mut db = Database::new();
+        db.member_joined(100, Some("flaky".to_string()), "Flaky".to_string(), "2024-05-01 10:00".to_string());
+        let member = db.get_user(100).unwrap().unwrap();
+        assert_eq!((member.rejoin_count, member.last_rejoin_date.as_deref()), (0, None));
+        assert_eq!(member.join_history(), None);
+
+        for (day, n) in [("02", 1), ("03", 2), ("04", 3)] {
+            db.member_left(100);
+            db.member_joined(100, Some("flaky".to_string()), "Flaky".to_string(), format!("2024-05-{day} 10:00"));
+            assert_eq!(db.get_user(100).unwrap().unwrap().rejoin_count, n);
+        }
+
+        let member = db.get_user(100).unwrap().unwrap();
+        assert_eq!(member.join_date, "2024-05-01 10:00");
+        assert_eq!(member.last_rejoin_date.as_deref(), Some("2024-05-04 10:00"));
+        assert_eq!(member.status, MemberStatus::Member);
+        assert_eq!(member.join_history().as_deref(), Some("rejoined (3rd time, originally joined 2024-05-01)"));
+        assert_eq!(db.get_members(Some("all"), None, 10)[0].rejoin_count, 3);
+        assert_eq!(db.get_user(999).unwrap().map(|m| m.user_id), None);
+    }
+
+    #[test]
+    fn test_invites_merge_and_reset_on_rejoin() {
+        let mut db = Database::new();
+        db.member_joined(100, None, "Spammy".to_string(), "2024-05-01 10:00".to_string());
+        // Service message: who added them. chat_member update: the link.
+        db.record_invite(100, None, Some(7)).unwrap();
+        db.record_invite(100, Some("https://t.me/+abc...".to_string()), None).unwrap();
+        let member = db.get_user(100).unwrap().unwrap();
+        assert_eq!((member.invite_link.as_deref(), member.invited_by), (Some("https://t.me/+abc..."), Some(7)));
+
+        // A rejoin without invite details doesn't inherit the old ones
+        db.member_left(100);
+        db.member_joined(100, None, "Spammy".to_string(), "2024-05-02 10:00".to_string());
+        let member = db.get_user(100).unwrap().unwrap();
+        assert_eq!((member.invite_link, member.invited_by), (None, None));
+    }
+
+    #[test]
+    fn test_joins_by_invite_link() {
+        let mut db = Database::new();
+        let joins = [
+            (1, "2024-05-10 10:00", Some("https://t.me/+funnel")),
+            (2, "2024-05-10 11:00", Some("https://t.me/+funnel")),
+            (3, "2024-05-10 12:00", Some("https://t.me/+funnel")),
+            (4, "2024-05-10 13:00", Some("https://t.me/+friends")),
+            (5, "2024-05-10 14:00", None),
+            // Too old
+            (6, "2024-04-01 10:00", Some("https://t.me/+friends")),
+        ];
+        for (user_id, date, link) in joins {
+            db.member_joined(user_id, None, format!("User{user_id}"), date.to_string());
+            db.record_invite(user_id, link.map(String::from), None).unwrap();
+        }
+        db.member_banned(1);
+        db.member_banned(2);
+        // Rejoined recently through the old link: counts at the rejoin
+        db.member_left(6);
+        db.member_joined(6, None, "User6".to_string(), "2024-05-11 10:00".to_string());
+        db.record_invite(6, Some("https://t.me/+friends".to_string()), None).unwrap();
+
+        let since = "2024-05-01T00:00:00Z".parse().unwrap();
+        assert_eq!(db.joins_by_invite_link(since).unwrap(), vec![
+            InviteLinkJoins { invite_link: "https://t.me/+funnel".to_string(), joins: 3, banned: 2 },
+            InviteLinkJoins { invite_link: "https://t.me/+friends".to_string(), joins: 2, banned: 0 },
+        ]);
+        assert!(db.joins_by_invite_link("2024-06-01T00:00:00Z".parse().unwrap()).unwrap().is_empty());
+    }
+
     #[test]
     fn test_create_and_list_reminders() {
         let mut db = Database::new();
@@ -807,6 +2703,27 @@ mod tests {
         assert_eq!(reminders[0].chat_id, -12345);
     }
 
+    #[test]
+    fn test_follow_up_cap() {
+        let mut db = Database::new();
+        let trigger = Utc::now() + chrono::Duration::hours(1);
+        for i in 0..reminders::MAX_FOLLOW_UPS {
+            db.create_follow_up(-12345, &format!("check {i}"), trigger).unwrap();
+        }
+        // Plain reminders don't count against the cap
+        db.create_reminder(-12345, 100, "Test reminder", trigger, None).unwrap();
+        assert!(db.create_follow_up(-12345, "one too many", trigger).unwrap_err().contains("max 10"));
+
+        let follow_ups: Vec<i64> = db.list_reminders(Some(-12345)).iter()
+            .filter(|r| r.kind == ReminderKind::FollowUp)
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(follow_ups.len(), reminders::MAX_FOLLOW_UPS);
+        // Cancelling one frees a slot
+        assert!(db.cancel_reminder(follow_ups[0]).unwrap());
+        assert!(db.create_follow_up(-12345, "now it fits", trigger).is_ok());
+    }
+
     #[test]
     fn test_cancel_reminder() {
         let mut db = Database::new();
@@ -834,6 +2751,330 @@ mod tests {
         assert_eq!(due[0].message, "Past");
     }
 
+    #[test]
+    fn test_is_bot_message() {
+        let mut db = Database::new();
+        let mut bot_msg = make_msg(1, 999, "Claudima", "2024-01-15 10:00", "Here you go");
+        bot_msg.chat_id = -100;
+        db.add_message(bot_msg);
+        let mut user_msg = make_msg(2, 100, "alice", "2024-01-15 10:01", "thanks");
+        user_msg.chat_id = -100;
+        db.add_message(user_msg);
+
+        assert!(db.is_bot_message(-100, 1, 999).unwrap());
+        assert!(!db.is_bot_message(-100, 2, 999).unwrap());
+        // Unknown message, or the id in another chat
+        assert!(!db.is_bot_message(-100, 3, 999).unwrap());
+        assert!(!db.is_bot_message(-200, 1, 999).unwrap());
+
+        db.record_reaction(-100, 1, 100, "👍", true).unwrap();
+        db.record_reaction(-100, 2, 101, "❤", false).unwrap();
+        let stored: i64 = db.conn.query_row("SELECT COUNT(*) FROM reactions", [], |row| row.get(0)).unwrap();
+        assert_eq!(stored, 2);
+    }
+
+    #[test]
+    fn test_language_histogram() {
+        let mut db = Database::new();
+        for _ in 0..4 {
+            db.record_language(-100, "ru").unwrap();
+        }
+        // Not enough samples yet
+        assert!(db.dominant_languages().unwrap().is_empty());
+
+        db.record_language(-100, "en").unwrap();
+        db.record_language(-100, "ru").unwrap();
+        db.record_language(-200, "en").unwrap();
+        assert_eq!(db.dominant_languages().unwrap(), vec![(-100, "ru".to_string())]);
+
+        // No majority, no dominant language
+        for _ in 0..4 {
+            db.record_language(-100, "en").unwrap();
+        }
+        assert!(db.dominant_languages().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_language_histogram_decays() {
+        let mut db = Database::new();
+        for _ in 0..150 {
+            db.record_language(-100, "en").unwrap();
+        }
+        // The chat switches language: recent messages win after a halving
+        for _ in 0..49 {
+            db.record_language(-100, "ru").unwrap();
+        }
+        assert_eq!(db.dominant_languages().unwrap(), vec![(-100, "en".to_string())]);
+        db.record_language(-100, "ru").unwrap(); // 200 samples: halve to en 75, ru 25
+        for _ in 0..60 {
+            db.record_language(-100, "ru").unwrap();
+        }
+        assert_eq!(db.dominant_languages().unwrap(), vec![(-100, "ru".to_string())]);
+    }
+
+    #[test]
+    fn test_scheduled_messages_due_scan() {
+        let mut db = Database::new();
+        let now = Utc::now();
+
+        let past = db.schedule_message(-12345, "Past", Some(42), now - chrono::Duration::minutes(1)).unwrap();
+        db.schedule_message(-12345, "Future", None, now + chrono::Duration::hours(1)).unwrap();
+        db.schedule_message(-999, "Other chat", None, now + chrono::Duration::hours(2)).unwrap();
+
+        let due = db.get_due_scheduled(now).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, past);
+        assert_eq!(due[0].text, "Past");
+        // Reply threading survives the delay
+        assert_eq!(due[0].reply_to_message_id, Some(42));
+
+        db.mark_scheduled_sent(past).unwrap();
+        assert!(db.get_due_scheduled(now).unwrap().is_empty());
+        assert_eq!(db.get_due_scheduled(now + chrono::Duration::hours(1)).unwrap().len(), 1);
+
+        assert_eq!(db.list_scheduled(Some(-12345)).unwrap().len(), 1);
+        assert_eq!(db.list_scheduled(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_scheduled_message() {
+        let mut db = Database::new();
+        let id = db.schedule_message(-12345, "Later", None, Utc::now() + chrono::Duration::hours(1)).unwrap();
+
+        assert!(db.cancel_scheduled(id).unwrap());
+        assert!(!db.cancel_scheduled(id).unwrap());
+        assert!(db.list_scheduled(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_spam_image_hashes() {
+        let mut db = Database::new();
+        let now = "2024-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert!(!db.is_spam_image("abc", now).unwrap());
+
+        db.record_spam_image("abc", -100, now + chrono::Duration::hours(1)).unwrap();
+        assert!(db.is_spam_image("abc", now).unwrap());
+        assert!(!db.is_spam_image("def", now).unwrap());
+
+        // Expired, whether pruned yet or not
+        let later = now + chrono::Duration::hours(1);
+        assert!(!db.is_spam_image("abc", later).unwrap());
+        // Seen again with spam: the expiry moves on
+        db.record_spam_image("abc", -200, later + chrono::Duration::hours(1)).unwrap();
+        assert!(db.is_spam_image("abc", later).unwrap());
+
+        db.record_spam_image("old", -100, now).unwrap();
+        assert_eq!(db.prune_spam_images(later).unwrap(), 1);
+        assert!(db.is_spam_image("abc", later).unwrap());
+    }
+
+    #[test]
+    fn test_mutes_lifecycle() {
+        let mut db = Database::new();
+        let now = "2024-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let muted = |db: &Database| -> Vec<(i64, i64)> {
+            let mut stmt = db.conn.prepare("SELECT chat_id, user_id FROM mutes ORDER BY chat_id, user_id").unwrap();
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(|r| r.unwrap()).collect()
+        };
+
+        db.record_mute(-100, 1, now + chrono::Duration::minutes(30)).unwrap();
+        db.record_mute(-100, 2, now + chrono::Duration::hours(5)).unwrap();
+        db.record_mute(-200, 1, now + chrono::Duration::days(2)).unwrap();
+        assert_eq!(muted(&db), vec![(-200, 1), (-100, 1), (-100, 2)]);
+
+        // Re-muting extends rather than duplicates
+        db.record_mute(-100, 1, now + chrono::Duration::hours(10)).unwrap();
+        assert_eq!(muted(&db).len(), 3);
+
+        // Lifted early
+        assert!(db.remove_mute(-100, 2).unwrap());
+        assert!(!db.remove_mute(-100, 2).unwrap());
+
+        // Expiry: the 10h mute ends before the 2-day one
+        assert_eq!(db.prune_expired_mutes(now + chrono::Duration::hours(1)).unwrap(), 0);
+        assert_eq!(db.prune_expired_mutes(now + chrono::Duration::hours(10)).unwrap(), 1);
+        assert_eq!(muted(&db), vec![(-200, 1)]);
+    }
+
+    #[test]
+    fn test_moderation_log_first_offense() {
+        let mut db = Database::new();
+        let now = "2024-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(db.record_moderation(-100, 1, "delete", now).unwrap(), 0);
+        assert_eq!(db.record_moderation(-100, 1, "mute", now).unwrap(), 1);
+        assert_eq!(db.record_moderation(-100, 1, "ban", now).unwrap(), 2);
+        // Counted per user and per chat
+        assert_eq!(db.record_moderation(-100, 2, "mute", now).unwrap(), 0);
+        assert_eq!(db.record_moderation(-200, 1, "mute", now).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_moderation_and_spam_by_day() {
+        let mut db = Database::new();
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        db.member_joined(1, Some("spammy".to_string()), "Spam".to_string(), "2024-01-15 09:00".to_string());
+
+        db.record_moderation(-100, 1, "delete", at("2024-01-14T23:59:00Z")).unwrap();
+        db.record_moderation(-100, 1, "delete", at("2024-01-15T10:00:00Z")).unwrap();
+        db.record_moderation(-100, 2, "mute", at("2024-01-15T11:00:00Z")).unwrap();
+        db.record_moderation(-200, 1, "ban", at("2024-01-15T12:00:00Z")).unwrap();
+        for time in ["2024-01-15T08:00:00Z", "2024-01-15T09:00:00Z", "2024-01-16T00:00:00Z"] {
+            db.record_spam_deletion(-100, at(time)).unwrap();
+        }
+
+        let (from, to) = (at("2024-01-15T00:00:00Z"), at("2024-01-16T00:00:00Z"));
+        let entries = db.moderation_between(-100, from, to).unwrap();
+        assert_eq!(entries, vec![
+            ModerationEntry { user_id: 1, username: Some("spammy".to_string()), first_name: Some("Spam".to_string()), action: "delete".to_string() },
+            // Never seen in the chat: no names
+            ModerationEntry { user_id: 2, username: None, first_name: None, action: "mute".to_string() },
+        ]);
+        assert_eq!(db.spam_deletions_between(-100, from, to).unwrap(), 2);
+        assert_eq!(db.spam_deletions_between(-200, from, to).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_invite_links_lifecycle() {
+        let mut db = Database::new();
+        let expire = "2024-01-16T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        db.record_invite_link(-100, "https://t.me/+abc", Some("meetup"), 1, Some(expire), Some(10)).unwrap();
+        db.record_invite_link(-100, "https://t.me/+def", None, 2, None, None).unwrap();
+
+        assert!(db.mark_invite_link_revoked(-100, "https://t.me/+abc").unwrap());
+        // Already revoked, unknown or in another chat
+        assert!(!db.mark_invite_link_revoked(-100, "https://t.me/+abc").unwrap());
+        assert!(!db.mark_invite_link_revoked(-200, "https://t.me/+def").unwrap());
+
+        let active = db.query("SELECT invite_link, creator_id FROM invite_links WHERE revoked_at IS NULL").unwrap();
+        assert!(active.contains("+def"));
+        assert!(!active.contains("+abc"));
+        let row: (Option<String>, Option<i64>) = db.conn.query_row(
+            "SELECT expire_at, member_limit FROM invite_links WHERE name = 'meetup'", [], |r| Ok((r.get(0)?, r.get(1)?))
+        ).unwrap();
+        assert_eq!(row, (Some("2024-01-16T10:00:00+00:00".to_string()), Some(10)));
+    }
+
+    #[test]
+    fn test_slow_mode_restored_from_latest_change() {
+        let mut db = Database::new();
+        db.record_chat_setting(-100, SLOW_MODE_SETTING, "30", 1).unwrap();
+        db.record_chat_setting(-100, SLOW_MODE_SETTING, "300", 2).unwrap();
+        db.record_chat_setting(-200, SLOW_MODE_SETTING, "60", 1).unwrap();
+        db.record_chat_setting(-200, SLOW_MODE_SETTING, "0", 1).unwrap();
+        db.record_chat_setting(-300, PERMISSIONS_SETTING, "media off", 1).unwrap();
+        assert_eq!(db.active_slow_modes().unwrap(), vec![(-100, 300)]);
+
+        let started = db.query("SELECT changed_at FROM chat_setting_changes WHERE chat_id = -100 AND value = '300'").unwrap();
+        assert!(started.contains(&Utc::now().format("%Y-%m-%d").to_string()));
+    }
+
+    #[test]
+    fn test_scheduled_messages_capped_per_chat() {
+        let mut db = Database::new();
+        let later = Utc::now() + chrono::Duration::hours(1);
+        for i in 0..reminders::MAX_SCHEDULED_PER_CHAT {
+            db.schedule_message(-12345, &format!("msg {i}"), None, later).unwrap();
+        }
+        assert!(db.schedule_message(-12345, "one too many", None, later).is_err());
+        // Other chats are unaffected
+        assert!(db.schedule_message(-999, "fine", None, later).is_ok());
+        // Messages held back for quiet hours aren't capped
+        assert!(db.defer_message(-12345, "good morning", None, later).is_ok());
+    }
+
+    #[test]
+    fn test_known_chats_and_latest_message() {
+        let mut db = Database::new();
+        assert_eq!(db.known_chat_ids().unwrap(), Vec::<i64>::new());
+        assert_eq!(db.latest_message_id(-12345).unwrap(), None);
+
+        db.add_message(make_msg(40, 100, "alice", "2024-01-15 10:00", "hi"));
+        db.add_message(make_msg(41, 100, "alice", "2024-01-15 10:01", "again"));
+        let mut dm = make_msg(7, 100, "alice", "2024-01-15 10:02", "psst");
+        dm.chat_id = 100;
+        db.add_message(dm);
+
+        assert_eq!(db.known_chat_ids().unwrap(), vec![-12345, 100]);
+        assert_eq!(db.latest_message_id(-12345).unwrap(), Some(41));
+        assert_eq!(db.latest_message_id(100).unwrap(), Some(7));
+    }
+
+    #[test]
+    fn test_tool_errors_window_and_pruning() {
+        let mut db = Database::new();
+        let now = Utc::now();
+        let failure = |tool: &str, hours_ago: i64| ToolError {
+            tool: tool.to_string(),
+            error: "boom".to_string(),
+            chat_id: Some(-100),
+            timestamp: now - chrono::Duration::hours(hours_ago),
+        };
+        db.record_tool_error(&failure("send_voice", 30)).unwrap();
+        db.record_tool_error(&failure("send_voice", 2)).unwrap();
+        db.record_tool_error(&failure("wiki_lookup", 1)).unwrap();
+
+        let recent = db.tool_errors_since(now - chrono::Duration::hours(24)).unwrap();
+        assert_eq!(recent.iter().map(|e| e.tool.as_str()).collect::<Vec<_>>(), vec!["send_voice", "wiki_lookup"]);
+        assert_eq!(recent[0], failure("send_voice", 2));
+
+        assert_eq!(db.prune_tool_errors(now - chrono::Duration::hours(24)).unwrap(), 1);
+        assert_eq!(db.tool_errors_since(now - chrono::Duration::days(7)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_cost_attribution() {
+        let mut db = Database::new();
+        let mut dm = make_msg(1, 42, "alice", "2024-03-10 09:00", "hi");
+        dm.chat_id = 42;
+        db.add_message(dm);
+        let day = |s: &str| s.parse::<NaiveDate>().unwrap();
+        let share = |user_id, chat_id, micro_usd| CostShare { user_id, chat_id, micro_usd };
+
+        db.add_costs(&[share(42, -100, 300_000), share(7, -100, 100_000)], day("2024-03-08")).unwrap();
+        db.add_costs(&[share(42, 42, 250_000)], day("2024-03-10")).unwrap();
+        db.add_costs(&[share(7, -100, 500_000)], day("2024-03-10")).unwrap();
+        db.add_costs(&[share(7, -100, 500_000)], day("2024-03-10")).unwrap();
+
+        let summary = db.cost_summary(day("2024-03-08"), 5).unwrap();
+        assert!((summary.total_usd - 1.65).abs() < 1e-9);
+        let chats: Vec<(i64, Option<&str>)> = summary.chats.iter().map(|c| (c.id, c.name.as_deref())).collect();
+        assert_eq!(chats, vec![(-100, None), (42, Some("alice"))]);
+        let users: Vec<(i64, i64)> = summary.users.iter().map(|u| (u.id, u.turns)).collect();
+        assert_eq!(users, vec![(7, 3), (42, 2)]);
+        assert!((summary.users[0].usd - 1.1).abs() < 1e-9);
+
+        // Earlier days fall out of a shorter window
+        let summary = db.cost_summary(day("2024-03-09"), 1).unwrap();
+        assert!((summary.total_usd - 1.25).abs() < 1e-9);
+        assert_eq!(summary.users.iter().map(|u| u.id).collect::<Vec<_>>(), vec![7]);
+    }
+
+    #[test]
+    fn test_bot_status_transitions() {
+        let mut db = Database::new();
+        let t = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert_eq!(db.bot_status(-100).unwrap(), None);
+
+        // Added as admin
+        assert_eq!(db.record_bot_status(-100, BotStatus::Admin, t("2024-01-15T10:00:00Z")).unwrap(), None);
+        // Repeated status keeps the original time
+        assert_eq!(db.record_bot_status(-100, BotStatus::Admin, t("2024-01-15T11:00:00Z")).unwrap(), Some(BotStatus::Admin));
+        assert_eq!(db.bot_status(-100).unwrap().unwrap().since, t("2024-01-15T10:00:00Z"));
+
+        // Demoted, then kicked
+        assert_eq!(db.record_bot_status(-100, BotStatus::Member, t("2024-01-16T09:00:00Z")).unwrap(), Some(BotStatus::Admin));
+        assert_eq!(db.record_bot_status(-100, BotStatus::Removed, t("2024-01-17T09:00:00Z")).unwrap(), Some(BotStatus::Member));
+        let status = db.bot_status(-100).unwrap().unwrap();
+        assert_eq!(status.status, BotStatus::Removed);
+        assert_eq!(status.since, t("2024-01-17T09:00:00Z"));
+
+        // Re-added as admin clears it; other chats are unaffected
+        assert_eq!(db.record_bot_status(-100, BotStatus::Admin, t("2024-01-18T09:00:00Z")).unwrap(), Some(BotStatus::Removed));
+        assert_eq!(db.bot_status(-100).unwrap().unwrap().status, BotStatus::Admin);
+        assert_eq!(db.bot_status(-200).unwrap(), None);
+    }
+
     #[test]
     fn test_get_recent_by_tokens() {
         let mut db = Database::new();
@@ -843,13 +3084,554 @@ mod tests {
         }
 
         // Request with small token budget - should get fewer messages
-        let recent = db.get_recent_by_tokens(50); // ~200 chars
+        let recent = db.get_recent_by_tokens(50, None); // ~200 chars
         assert!(!recent.is_empty());
         assert!(recent.len() < 10);
         // Should be in chronological order (oldest first)
         assert!(recent[0].text.contains("Message"));
     }
 
+    #[test]
+    fn test_get_recent_by_tokens_since() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-14 23:59", "yesterday"));
+        db.add_message(make_msg(2, 100, "alice", "2024-01-15 10:00", "morning"));
+        db.add_message(make_msg(3, 100, "alice", "2024-01-15 16:30", "afternoon"));
+
+        let since = "2024-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let recent = db.get_recent_by_tokens(10_000, Some(since));
+        let texts: Vec<_> = recent.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["morning", "afternoon"]);
+
+        let since = "2024-01-16T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert!(db.get_recent_by_tokens(10_000, Some(since)).is_empty());
+    }
+
+    #[test]
+    fn test_replay_recent_trims_to_budget() {
+        let mut db = Database::new();
+        for i in 0..20 {
+            db.add_message(make_msg(i, 100, "alice", &format!("2024-01-15 10:{:02}", i), &"x".repeat(100)));
+        }
+
+        let all = db.replay_recent(100_000, None);
+        assert_eq!(all.messages, 20);
+
+        // Each formatted message is ~200 chars (~50 tokens)
+        let trimmed = db.replay_recent(200, None);
+        assert!(trimmed.messages > 0 && trimmed.messages < 20);
+        assert!(trimmed.approx_tokens <= 200);
+        assert_eq!(trimmed.text.lines().count(), trimmed.messages);
+        // Newest messages are kept, oldest first
+        assert!(trimmed.text.ends_with("</msg>"));
+        assert!(trimmed.text.lines().last().unwrap().contains("id=\"19\""));
+    }
+
+    #[test]
+    fn test_replay_recent_keeps_newest_over_budget() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", &"x".repeat(1000)));
+
+        let replay = db.replay_recent(10, None);
+        assert_eq!(replay.messages, 1);
+    }
+
+    fn make_reply(id: i64, reply_to: i64, timestamp: &str) -> ChatMessage {
+        let mut msg = make_msg(id, 100 + id, &format!("user{}", id), timestamp, &format!("turn {}", id));
+        msg.reply_to = Some(ReplyTo { message_id: reply_to, username: format!("user{}", reply_to), text: String::new(), unknown_origin: false });
+        msg
+    }
+
+    #[test]
+    fn test_get_thread_walks_chain_oldest_first() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 101, "user1", "2024-01-15 10:00", "turn 1"));
+        for id in 2..=10 {
+            db.add_message(make_reply(id, id - 1, &format!("2024-01-15 10:{:02}", id)));
+        }
+
+        let thread = db.get_thread(10, 20, false).unwrap();
+        let ids: Vec<i64> = thread.chain.iter().map(|m| m.message_id).collect();
+        assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+        assert!(!thread.truncated);
+        assert_eq!(thread.missing, None);
+
+        let formatted = thread.format();
+        assert_eq!(formatted.lines().count(), 10);
+        assert!(formatted.lines().next().unwrap().contains("id=\"1\""));
+
+        // Depth limit keeps the newest messages
+        let thread = db.get_thread(10, 3, false).unwrap();
+        let ids: Vec<i64> = thread.chain.iter().map(|m| m.message_id).collect();
+        assert_eq!(ids, vec![8, 9, 10]);
+        assert!(thread.truncated);
+
+        // Depth is capped
+        assert!(db.get_thread(10, 10_000, false).unwrap().chain.len() <= MAX_THREAD_DEPTH);
+    }
+
+    #[test]
+    fn test_get_thread_broken_link() {
+        let mut db = Database::new();
+        // Message 3 replies to 2, which was never stored
+        let mut orphan = make_reply(3, 2, "2024-01-15 10:03");
+        orphan.reply_to.as_mut().unwrap().unknown_origin = true;
+        db.add_message(orphan);
+        db.add_message(make_reply(4, 3, "2024-01-15 10:04"));
+        assert!(!db.has_message(2).unwrap());
+        assert!(db.has_message(3).unwrap());
+
+        let thread = db.get_thread(4, 10, false).unwrap();
+        assert_eq!(thread.chain.len(), 2);
+        assert_eq!(thread.missing, Some(2));
+        assert!(thread.format().contains("Message 2 is not in the database"));
+        // The unknown flag survives storage
+        assert!(thread.chain[0].reply_to.as_ref().unwrap().unknown_origin);
+        assert!(!thread.chain[1].reply_to.as_ref().unwrap().unknown_origin);
+
+        assert!(db.get_thread(99, 10, false).is_err());
+    }
+
+    #[test]
+    fn test_get_thread_replies() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 101, "user1", "2024-01-15 10:00", "question"));
+        db.add_message(make_reply(2, 1, "2024-01-15 10:01"));
+        db.add_message(make_reply(3, 1, "2024-01-15 10:02"));
+        db.add_message(make_reply(4, 2, "2024-01-15 10:03"));
+
+        let thread = db.get_thread(1, 10, true).unwrap();
+        assert_eq!(thread.chain.len(), 1);
+        let reply_ids: Vec<i64> = thread.replies.iter().map(|m| m.message_id).collect();
+        assert_eq!(reply_ids, vec![2, 3]);
+        assert!(thread.format().contains("[Direct replies:]"));
+
+        assert!(db.get_thread(1, 10, false).unwrap().replies.is_empty());
+    }
+
+    #[test]
+    fn test_get_thread_char_budget() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 101, "user1", "2024-01-15 10:00", &"x".repeat(3000)));
+        for id in 2..=5 {
+            let mut msg = make_reply(id, id - 1, &format!("2024-01-15 10:{:02}", id));
+            msg.text = "y".repeat(3000);
+            db.add_message(msg);
+        }
+
+        let thread = db.get_thread(5, 10, false).unwrap();
+        assert!(thread.truncated);
+        assert!(thread.chain.len() < 5);
+        assert!(thread.chain.iter().map(|m| m.format().len()).sum::<usize>() <= THREAD_CHAR_BUDGET);
+    }
+
+    #[test]
+    fn test_chat_stats() {
+        let mut db = Database::new();
+        let start = "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        // 300 messages over 10 days: user i%6 posts at hour (i*7)%24
+        for i in 0..300i64 {
+            let at = start + chrono::Duration::days(i / 30) + chrono::Duration::hours((i * 7) % 24);
+            let user = 100 + i % 6;
+            let mut msg = make_msg(i, user, &format!("user{}", user), &at.format("%Y-%m-%d %H:%M").to_string(), &"x".repeat(10 + (i % 3) as usize * 10));
+            if i % 10 == 0 {
+                // Another chat doesn't count
+                msg.chat_id = -999;
+            }
+            db.add_message(msg);
+        }
+
+        let stats = db.chat_stats(-12345, start).unwrap();
+        assert_eq!(stats.total_messages, 270);
+        assert_eq!(stats.active_users, 6);
+        assert_eq!(stats.top_posters.len(), 5);
+        assert!(stats.top_posters.windows(2).all(|w| w[0].messages >= w[1].messages));
+        assert_eq!(stats.hourly.iter().sum::<usize>(), 270);
+        assert!(stats.busiest_hour().is_some());
+        // Lengths 10/20/30 cycle evenly over the included messages
+        assert!((stats.avg_length - 20.0).abs() < 1.0);
+
+        // Only the last 3 days (days 7-9: messages 210..300, minus every tenth)
+        let since = start + chrono::Duration::days(7);
+        let recent = db.chat_stats(-12345, since).unwrap();
+        assert_eq!(recent.total_messages, 81);
+
+        let empty = db.chat_stats(-12345, start + chrono::Duration::days(30)).unwrap();
+        assert_eq!(empty.total_messages, 0);
+        assert_eq!(empty.avg_length, 0.0);
+        assert!(empty.top_posters.is_empty());
+        assert_eq!(empty.busiest_hour(), None);
+    }
+
+    #[test]
+    fn test_export_messages_range_and_order() {
+        let mut db = Database::new();
+        db.add_message(make_msg(3, 1, "alice", "2024-01-15 12:00", "third"));
+        db.add_message(make_msg(1, 1, "alice", "2024-01-14 23:59", "before"));
+        db.add_message(make_msg(2, 2, "bob", "2024-01-15 00:00", "first"));
+        db.add_message(make_msg(4, 2, "bob", "2024-01-16 00:00", "after"));
+        let mut other = make_msg(5, 2, "bob", "2024-01-15 10:00", "other chat");
+        other.chat_id = -999;
+        db.add_message(other);
+
+        let mut seen = Vec::new();
+        let truncated = db.export_messages(-12345, "2024-01-15 00:00", "2024-01-15 23:59", 100, |m| {
+            seen.push(m.text.clone());
+            Ok(())
+        }).unwrap();
+        assert!(!truncated);
+        assert_eq!(seen, vec!["first", "third"]);
+    }
+
+    #[test]
+    fn test_export_messages_cap() {
+        let mut db = Database::new();
+        for i in 0..10 {
+            db.add_message(make_msg(i, 1, "alice", &format!("2024-01-15 10:{:02}", i), "hi"));
+        }
+
+        let mut count = 0;
+        let truncated = db.export_messages(-12345, "2024-01-15 00:00", "2024-01-15 23:59", 4, |_| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+        assert!(truncated);
+        assert_eq!(count, 4);
+
+        // Exactly at the cap isn't truncated
+        let truncated = db.export_messages(-12345, "2024-01-15 00:00", "2024-01-15 23:59", 10, |_| Ok(())).unwrap();
+        assert!(!truncated);
+
+        // Writer errors stop the export
+        let result = db.export_messages(-12345, "2024-01-15 00:00", "2024-01-15 23:59", 10, |_| Err("disk full".to_string()));
+        assert_eq!(result.unwrap_err(), "disk full");
+    }
+
+    #[test]
+    fn test_read_messages_filters() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 1, "alice", "2024-01-14 23:59", "a1"));
+        db.add_message(make_msg(2, 2, "bob", "2024-01-15 09:00", "b1"));
+        db.add_message(make_msg(3, 1, "Alice", "2024-01-15 12:00", "a2"));
+        db.add_message(make_msg(4, 2, "bob", "2024-01-16 08:00", "b2"));
+        let mut other = make_msg(5, 1, "alice", "2024-01-15 10:00", "other chat");
+        other.chat_id = -999;
+        db.add_message(other);
+
+        let texts = |(messages, _): (Vec<ChatMessage>, bool)| -> Vec<String> {
+            messages.into_iter().map(|m| m.text).collect()
+        };
+
+        // Latest N, oldest first
+        assert_eq!(texts(db.read_messages(-12345, None, None, None, Take::Latest(2), 10_000).unwrap()), ["a2", "b2"]);
+        // Date range
+        let day = db.read_messages(-12345, Some("2024-01-15 00:00"), Some("2024-01-15 23:59"), None, Take::Latest(50), 10_000);
+        assert_eq!(texts(day.unwrap()), ["b1", "a2"]);
+        // Open-ended range
+        let since = db.read_messages(-12345, Some("2024-01-15 12:00"), None, None, Take::Latest(50), 10_000);
+        assert_eq!(texts(since.unwrap()), ["a2", "b2"]);
+        let until = db.read_messages(-12345, None, Some("2024-01-15 09:00"), None, Take::Latest(50), 10_000);
+        assert_eq!(texts(until.unwrap()), ["a1", "b1"]);
+        // Username, case-insensitive and with or without @
+        let alice = db.read_messages(-12345, None, None, Some("@ALICE"), Take::Latest(50), 10_000);
+        assert_eq!(texts(alice.unwrap()), ["a1", "a2"]);
+        // Username within a range
+        let both = db.read_messages(-12345, Some("2024-01-15 00:00"), None, Some("bob"), Take::Latest(50), 10_000);
+        assert_eq!(texts(both.unwrap()), ["b1", "b2"]);
+        // Reading forward from a start date
+        let first = db.read_messages(-12345, Some("2024-01-15 00:00"), None, None, Take::Earliest(1), 10_000);
+        assert_eq!(texts(first.unwrap()), ["b1"]);
+        // Other chats aren't mixed in
+        assert_eq!(texts(db.read_messages(-999, None, None, None, Take::Latest(50), 10_000).unwrap()), ["other chat"]);
+        assert!(db.read_messages(-12345, None, None, Some("carol"), Take::Latest(50), 10_000).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_read_messages_caps() {
+        let mut db = Database::new();
+        for i in 0..10 {
+            db.add_message(make_msg(i, 1, "alice", &format!("2024-01-15 10:{:02}", i), "hello there"));
+        }
+
+        let (messages, truncated) = db.read_messages(-12345, None, None, None, Take::Latest(4), 100_000).unwrap();
+        assert!(truncated);
+        assert_eq!(messages.iter().map(|m| m.message_id).collect::<Vec<_>>(), [6, 7, 8, 9]);
+        let (messages, truncated) = db.read_messages(-12345, None, None, None, Take::Latest(10), 100_000).unwrap();
+        assert!(!truncated);
+        assert_eq!(messages.len(), 10);
+
+        // The size cap keeps the newest messages that fit
+        let one = make_msg(0, 1, "alice", "2024-01-15 10:00", "hello there").format().len();
+        let (messages, truncated) = db.read_messages(-12345, None, None, None, Take::Latest(10), one * 3).unwrap();
+        assert!(truncated);
+        assert_eq!(messages.iter().map(|m| m.message_id).collect::<Vec<_>>(), [7, 8, 9]);
+        let (messages, truncated) = db.read_messages(-12345, None, None, None, Take::Earliest(10), one * 3).unwrap();
+        assert!(truncated);
+        assert_eq!(messages.iter().map(|m| m.message_id).collect::<Vec<_>>(), [0, 1, 2]);
+        // ...but always returns at least one
+        let (messages, _) = db.read_messages(-12345, None, None, None, Take::Latest(10), 1).unwrap();
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn test_top_group_posters() {
+        let mut db = Database::new();
+        let mut id = 0;
+        for (user, count) in [(1, 5), (2, 12), (3, 8), (4, 1)] {
+            for _ in 0..count {
+                id += 1;
+                db.add_message(make_msg(id, user, &format!("u{}", user), "2024-01-15 09:30", "hi"));
+            }
+        }
+        // DMs, bots, system messages and older messages don't count
+        let mut dm = make_msg(100, 4, "u4", "2024-01-15 09:30", "hi");
+        dm.chat_id = 4;
+        let mut bot = make_msg(101, 9, "somebot", "2024-01-15 09:30", "beep");
+        bot.from_bot = true;
+        let mut old = make_msg(102, 4, "u4", "2024-01-13 09:30", "hi");
+        old.chat_id = -999;
+        for msg in [dm.clone(), dm.clone(), bot.clone(), bot, make_msg(103, 0, "system", "2024-01-15 09:30", "notice"), old] {
+            id += 1;
+            db.add_message(ChatMessage { message_id: id + 100, ..msg });
+        }
+        let mut other_group = make_msg(300, 4, "u4", "2024-01-15 10:00", "also counted");
+        other_group.chat_id = -999;
+        db.add_message(other_group);
+
+        let since = "2024-01-14T09:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let top: Vec<(i64, usize)> = db.top_group_posters(since, 3).unwrap().iter().map(|p| (p.user_id, p.messages)).collect();
+        assert_eq!(top, vec![(2, 12), (3, 8), (1, 5)]);
+        let all = db.top_group_posters(since, 10).unwrap();
+        assert_eq!(all.last().map(|p| (p.user_id, p.messages)), Some((4, 2)));
+
+        assert_eq!(db.group_texts_by(4, since).unwrap(), vec!["hi", "also counted"]);
+    }
+
+    #[test]
+    fn test_message_features_stored() {
+        let mut db = Database::new();
+        let mut msg = make_msg(1, 5, "alice", "2024-01-15 09:00", "🔥 free stuff at https://a.example @bob");
+        msg.via_bot = Some("gif".to_string());
+        msg.forwarded = true;
+        db.add_message(msg);
+
+        let row = db.conn.query_row(
+            "SELECT url_count, has_mention, forwarded, length_class, emoji_ratio > 0, via_bot FROM messages WHERE message_id = 1",
+            [],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, bool>(1)?, row.get::<_, bool>(2)?, row.get::<_, String>(3)?, row.get::<_, bool>(4)?, row.get::<_, String>(5)?)),
+        ).unwrap();
+        assert_eq!(row, (1, true, true, "medium".to_string(), true, "gif".to_string()));
+        let stored = db.get_message(1).unwrap().unwrap();
+        assert_eq!((stored.forwarded, stored.via_bot.as_deref()), (true, Some("gif")));
+    }
+
+    #[test]
+    fn test_bulk_delete_candidates() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 1, "alice", "2024-01-15 08:00", "buy CHEAP crypto"));
+        db.add_message(make_msg(2, 2, "bob", "2024-01-15 09:10", "morning"));
+        db.add_message(make_msg(3, 1, "alice", "2024-01-15 09:20", "Cheap crypto here"));
+        db.add_message(make_msg(4, 3, "carol", "2024-01-15 09:30", "дешёвая CRYPTO"));
+        db.add_message(make_msg(5, 1, "alice", "2024-01-15 09:40", "hello"));
+        let mut other_chat = make_msg(6, 1, "alice", "2024-01-15 09:50", "cheap crypto");
+        other_chat.chat_id = -999;
+        db.add_message(other_chat);
+        db.add_message(make_msg(-1, 0, "system", "2024-01-15 09:50", "cheap crypto notice"));
+
+        let since = "2024-01-15T09:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        // Newest first, only inside the window and the chat
+        assert_eq!(db.bulk_delete_candidates(-12345, None, since, None, 10).unwrap(), vec![5, 4, 3, 2]);
+        assert_eq!(db.bulk_delete_candidates(-12345, None, since, None, 2).unwrap(), vec![5, 4]);
+        assert_eq!(db.bulk_delete_candidates(-12345, Some(1), since, None, 10).unwrap(), vec![5, 3]);
+        // Case-insensitive, beyond ASCII too
+        assert_eq!(db.bulk_delete_candidates(-12345, None, since, Some("crypto"), 10).unwrap(), vec![4, 3]);
+        assert_eq!(db.bulk_delete_candidates(-12345, None, since, Some("ДЕШЁВАЯ"), 10).unwrap(), vec![4]);
+        let earlier = "2024-01-15T07:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(db.bulk_delete_candidates(-12345, Some(1), earlier, Some("cheap"), 10).unwrap(), vec![3, 1]);
+
+        // Deleted messages aren't picked again
+        db.mark_deleted(-12345, &[3, 4]).unwrap();
+        assert_eq!(db.bulk_delete_candidates(-12345, None, since, Some("crypto"), 10).unwrap(), Vec::<i64>::new());
+
+        // A revoking ban flags the rest of the user's messages in that chat only
+        assert_eq!(db.mark_user_deleted(-12345, 1), Ok(2));
+        assert_eq!(db.mark_user_deleted(-12345, 1), Ok(0));
+        assert_eq!(db.bulk_delete_candidates(-12345, None, earlier, None, 10).unwrap(), vec![2]);
+        assert_eq!(db.bulk_delete_candidates(-999, None, earlier, None, 10).unwrap(), vec![6]);
+    }
+
+    #[test]
+    fn test_expire_auto_deleted() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 101, "user1", "2024-01-15 08:00", "before the timer"));
+        db.add_message(make_reply(2, 1, "2024-01-15 09:30"));
+        db.add_message(make_reply(3, 2, "2024-01-15 11:30"));
+        let mut other_chat = make_msg(4, 101, "user1", "2024-01-15 09:30", "no timer here");
+        other_chat.chat_id = -999;
+        db.add_message(other_chat);
+
+        let now = "2024-01-15T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(db.expire_auto_deleted(now), Ok(0));
+
+        // A 1-hour timer set at 09:00 removed message 2 but not the older 1
+        let since = "2024-01-15T09:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        db.set_auto_delete_timer(-12345, 3600, since).unwrap();
+        assert_eq!(db.expire_auto_deleted(now), Ok(1));
+        assert_eq!(db.expire_auto_deleted(now), Ok(0));
+
+        let recent: Vec<i64> = db.get_recent_by_tokens(1000, None).iter().map(|m| m.message_id).collect();
+        assert_eq!(recent, vec![1, 4, 3]);
+        let thread = db.get_thread(3, 10, false).unwrap();
+        assert_eq!((thread.chain.len(), thread.missing), (1, Some(2)));
+        assert!(db.get_thread(1, 10, true).unwrap().replies.is_empty());
+
+        // Turning the timer off stops the sweep
+        db.set_auto_delete_timer(-12345, 0, now).unwrap();
+        let later = "2024-01-15T18:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(db.expire_auto_deleted(later), Ok(0));
+
+        db.mark_expired(-999, 4).unwrap();
+        assert!(db.get_message(4).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_migrate_chat_id() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 101, "user1", "2024-01-15 09:00", "in the old group"));
+        db.add_message(make_msg(2, 102, "user2", "2024-01-15 09:01", "also old"));
+        let mut other_chat = make_msg(3, 101, "user1", "2024-01-15 09:02", "elsewhere");
+        other_chat.chat_id = -999;
+        db.add_message(other_chat);
+        let until = "2030-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        db.record_mute(-12345, 101, until).unwrap();
+        db.record_mute(-100_12345, 101, until).unwrap();
+        db.record_language(-12345, "en").unwrap();
+        db.record_language(-100_12345, "en").unwrap();
+        let day = "2024-01-15".parse::<NaiveDate>().unwrap();
+        db.add_costs(&[CostShare { user_id: 101, chat_id: -12345, micro_usd: 200_000 }], day).unwrap();
+        db.add_costs(&[CostShare { user_id: 101, chat_id: -100_12345, micro_usd: 100_000 }], day).unwrap();
+        let at = "2024-01-15T09:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        db.record_moderation(-12345, 102, "mute", at).unwrap();
+        db.record_spam_image("abc123", -12345, until).unwrap();
+        db.record_spam_deletion(-12345, at).unwrap();
+
+        assert_eq!(db.migrate_chat_id(-12345, -100_12345), Ok(2));
+        let count = |db: &Database, sql: &str| db.conn.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM messages WHERE chat_id = -10012345"), 2);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM messages WHERE chat_id = -999"), 1);
+        // Clashing per-chat rows merge into one
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM mutes"), 1);
+        assert_eq!(count(&db, "SELECT count FROM chat_languages WHERE chat_id = -10012345"), 2);
+        assert_eq!(count(&db, "SELECT turns FROM cost_attribution WHERE chat_id = -10012345"), 2);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM chat_languages WHERE chat_id = -12345"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM cost_attribution WHERE chat_id = -12345"), 0);
+        // Offenses keep counting in the supergroup
+        assert_eq!(db.record_moderation(-100_12345, 102, "delete", at), Ok(1));
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM spam_image_hashes WHERE chat_id = -10012345"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM spam_deletions WHERE chat_id = -10012345"), 1);
+
+        // A second run has nothing left to move
+        assert_eq!(db.migrate_chat_id(-12345, -100_12345), Ok(0));
+    }
+
+    #[test]
+    fn test_chat_stats_top_posters() {
+        let mut db = Database::new();
+        let mut id = 0;
+        for (user, count) in [(1, 5), (2, 12), (3, 8), (4, 1), (5, 3), (6, 2)] {
+            for _ in 0..count {
+                id += 1;
+                db.add_message(make_msg(id, user, &format!("u{}", user), "2024-01-15 09:30", "hi"));
+            }
+        }
+
+        let since = "2024-01-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let stats = db.chat_stats(-12345, since).unwrap();
+        let top: Vec<(i64, usize)> = stats.top_posters.iter().map(|p| (p.user_id, p.messages)).collect();
+        assert_eq!(top, vec![(2, 12), (3, 8), (1, 5), (5, 3), (6, 2)]);
+        assert_eq!(stats.top_posters[0].username, "u2");
+        assert_eq!(stats.hourly[9], 31);
+        assert_eq!(stats.busiest_hour(), Some(9));
+    }
+
+    #[test]
+    fn test_top_reacted_messages() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 10, "alice", "2024-01-10 09:00", "last week's joke"));
+        db.add_message(make_msg(2, 10, "alice", "2024-01-15 09:00", "cat picture"));
+        db.add_message(make_msg(3, 20, "bob", "2024-01-15 10:00", "hot take"));
+        db.add_message(make_msg(4, 30, "carol", "2024-01-15 11:00", "meh"));
+        let mut bot = make_msg(5, 99, "Claudima", "2024-01-15 12:00", "a bot pun");
+        bot.from_bot = true;
+        db.add_message(bot);
+        let mut elsewhere = make_msg(6, 20, "bob", "2024-01-15 12:00", "other group");
+        elsewhere.chat_id = -999;
+        db.add_message(elsewhere);
+
+        let react = |db: &mut Database, message_id: i64, users: std::ops::Range<i64>, emoji: &str| {
+            let chat_id = if message_id == 6 { -999 } else { -12345 };
+            for user in users {
+                db.record_reaction(chat_id, message_id, user, emoji, true).unwrap();
+            }
+        };
+        react(&mut db, 1, 100..110, "😂");
+        react(&mut db, 2, 100..103, "❤");
+        react(&mut db, 2, 103..104, "👍");
+        react(&mut db, 3, 100..104, "🔥");
+        react(&mut db, 4, 100..101, "👍");
+        react(&mut db, 5, 100..106, "😂");
+        react(&mut db, 6, 100..120, "🔥");
+        // Withdrawn reactions don't stand; re-added ones do
+        db.record_reaction(-12345, 3, 100, "🔥", false).unwrap();
+        db.record_reaction(-12345, 2, 100, "❤", false).unwrap();
+        db.record_reaction(-12345, 2, 100, "❤", true).unwrap();
+
+        let since = "2024-01-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let top = db.top_reacted_messages(-12345, since, 10, false).unwrap();
+        let order: Vec<(i64, usize)> = top.iter().map(|m| (m.message_id, m.total_reactions())).collect();
+        // Most reactions first, ties to the earlier message
+        assert_eq!(order, vec![(5, 6), (2, 4), (3, 3), (4, 1)]);
+        assert_eq!(top[1].reactions, vec![("❤".to_string(), 3), ("👍".to_string(), 1)]);
+        assert_eq!((top[1].username.as_str(), top[1].text.as_str()), ("alice", "cat picture"));
+
+        let people = db.top_reacted_messages(-12345, since, 2, true).unwrap();
+        assert_eq!(people.iter().map(|m| m.message_id).collect::<Vec<_>>(), vec![2, 3]);
+
+        let stats = db.chat_stats(-12345, since).unwrap();
+        assert_eq!(stats.reactions, vec![
+            ("😂".to_string(), 6), ("❤".to_string(), 3), ("🔥".to_string(), 3), ("👍".to_string(), 2),
+        ]);
+    }
+
+    #[test]
+    fn test_members_joined_since() {
+        let mut db = Database::new();
+        db.member_joined(100, Some("fresh".to_string()), "Fresh".to_string(), "2024-01-15 09:30".to_string());
+        db.member_joined(101, Some("old".to_string()), "Old".to_string(), "2023-06-01 12:00".to_string());
+        db.member_joined(102, None, "Gone".to_string(), "2024-01-15 09:45".to_string());
+        db.member_left(102);
+
+        let since = "2024-01-15T09:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let joiners = db.members_joined_since(since).unwrap();
+        assert_eq!(joiners.len(), 1);
+        assert_eq!(joiners[0].user_id, 100);
+        assert_eq!(joiners[0].first_name, "Fresh");
+    }
+
+    #[test]
+    fn test_joined_since() {
+        let mut db = Database::new();
+        db.member_joined(100, Some("fresh".to_string()), "Fresh".to_string(), "2024-01-15 09:30".to_string());
+        db.member_joined(101, Some("old".to_string()), "Old".to_string(), "2023-06-01 12:00".to_string());
+        db.import_members(r#"[{"user_id": 102, "username": "imported"}]"#).unwrap();
+
+        let since = "2024-01-14T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert!(db.joined_since(100, since));
+        assert!(!db.joined_since(101, since));
+        assert!(!db.joined_since(102, since));
+        assert!(!db.joined_since(999, since));
+    }
+
     #[test]
     fn test_import_members() {
         let mut db = Database::new();
@@ -926,6 +3708,28 @@ mod tests {
         std::fs::remove_file(&db_path).ok();
     }
 
+    #[test]
+    fn test_prune_and_table_stats() {
+        let mut db = Database::new();
+        for (id, timestamp) in [(1, "2024-01-10 09:00"), (2, "2024-01-20 09:00")] {
+            let mut msg = ChatMessage::system(format!("message {id}"));
+            (msg.message_id, msg.chat_id, msg.user_id, msg.timestamp) = (id, -100, 42, timestamp.to_string());
+            db.add_message(msg);
+        }
+        db.record_reaction(-100, 1, 42, "👍", true).unwrap();
+
+        let rows = |db: &Database, table: &str| db.table_stats().unwrap().into_iter().find(|t| t.name == table).unwrap().rows;
+        assert_eq!((rows(&db, "messages"), rows(&db, "reactions"), rows(&db, "users")), (2, 1, 1));
+        assert!(db.table_stats().unwrap().iter().all(|t| t.bytes > 0));
+
+        let cutoff = "2024-01-15T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(db.prune_before(cutoff).unwrap(), (1, 0));
+        assert_eq!(db.prune_before(Utc::now() + chrono::Duration::hours(1)).unwrap(), (1, 1));
+        // Members stay
+        assert_eq!((rows(&db, "messages"), rows(&db, "users")), (0, 1));
+        db.vacuum().unwrap();
+    }
+
     #[test]
     fn test_reschedule_reminder() {
         let mut db = Database::new();
@@ -979,4 +3783,294 @@ mod tests {
         let left = db.get_members(Some("left"), None, 100);
         assert!(left.iter().any(|m| m.username.as_deref() == Some("leaver")));
     }
+
+    #[tokio::test]
+    async fn test_async_call_returns_result() {
+        let db = AsyncDatabase::new(Database::new());
+        db.call(|db| db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"))).await.unwrap();
+
+        let count = db.call(|db| db.message_count()).await.unwrap();
+        assert_eq!(count, 1);
+
+        let result = db.call(|db| db.query("DELETE FROM messages")).await.unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_async_worker_survives_panicking_job() {
+        let db = AsyncDatabase::new(Database::new());
+        let result: Result<(), String> = db.call(|_| panic!("boom")).await;
+        assert_eq!(result.unwrap_err(), "Database worker dropped the request (panicked?)");
+        // The worker survives the panic and keeps answering
+        db.call(|db| db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"))).await.unwrap();
+        assert_eq!(db.call(|db| db.message_count()).await, Ok(1));
+    }
+
+    #[test]
+    fn test_find_user_by_either_spelling() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "🔥Дмитрий🔥", "2024-01-15 10:00", "привет"));
+        db.member_joined(200, None, "Жанна".to_string(), "2024-01-15 10:00".to_string());
+        db.member_joined(300, Some("alice_dev".to_string()), "Alice".to_string(), "2024-01-15 10:00".to_string());
+
+        for query in ["Дмитрий", "дмитрий", "🔥Дмитрий🔥", "dmitriy", "Dmitriy", "dmit"] {
+            assert_eq!(db.find_user_by_username(query).map(|m| m.user_id), Some(100), "{}", query);
+        }
+        // Raw values are kept
+        assert_eq!(db.find_user_by_username("dmitriy").unwrap().username.as_deref(), Some("🔥Дмитрий🔥"));
+        // No @username: found by first name
+        assert_eq!(db.find_user_by_username("zhanna").map(|m| m.user_id), Some(200));
+        assert_eq!(db.find_user_by_username("Жанна").map(|m| m.user_id), Some(200));
+        assert_eq!(db.find_user_by_username("@alice_dev").map(|m| m.user_id), Some(300));
+        assert!(db.find_user_by_username("boris").is_none());
+    }
+
+    #[test]
+    fn test_find_user_matches_like_characters_literally() {
+        let mut db = Database::new();
+        db.member_joined(100, None, "Alice".to_string(), "2024-01-15 10:00".to_string());
+        db.member_joined(200, None, "100%".to_string(), "2024-01-15 10:00".to_string());
+        db.member_joined(300, Some("bob_x".to_string()), "Bob".to_string(), "2024-01-15 10:00".to_string());
+
+        // "%" and "_" would match anyone as wildcards
+        assert_eq!(db.find_user_by_username("%").map(|m| m.user_id), Some(200));
+        assert_eq!(db.find_user_by_username("100%").map(|m| m.user_id), Some(200));
+        assert_eq!(db.find_user_by_username("_").map(|m| m.user_id), Some(300));
+        assert!(db.find_user_by_username("a_ice").is_none());
+        assert!(db.find_user_by_username("\\").is_none());
+    }
+
+    #[test]
+    fn test_normalized_usernames_filled_for_old_rows() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("old.db");
+        // A users table from before normalized_username
+        Connection::open(&path).unwrap().execute_batch(
+            "CREATE TABLE users (user_id INTEGER PRIMARY KEY, username TEXT, first_name TEXT NOT NULL,
+                join_date TEXT NOT NULL, last_message_date TEXT, message_count INTEGER DEFAULT 0,
+                status TEXT DEFAULT 'member');
+             INSERT INTO users (user_id, username, first_name, join_date) VALUES (1, NULL, 'Юлия', '2024-01-01');"
+        ).unwrap();
+
+        let db = Database::load_or_new(&path);
+        assert_eq!(db.find_user_by_username("yuliya").map(|m| m.user_id), Some(1));
+        // Rejoin tracking columns are added too
+        assert_eq!(db.get_user(1).unwrap().unwrap().rejoin_count, 0);
+    }
+
+    #[test]
+    fn test_last_group_message_by() {
+        let mut db = Database::new();
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "first"));
+        db.add_message(make_msg(2, 100, "alice", "2024-01-15 10:05", "latest"));
+        let mut dm = make_msg(3, 100, "alice", "2024-01-15 10:09", "private");
+        dm.chat_id = 100;
+        db.add_message(dm);
+
+        assert_eq!(db.last_group_message_by(100).unwrap().unwrap().text, "latest");
+        assert!(db.last_group_message_by(999).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_backup_is_openable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::load_or_new(&dir.path().join("live.db"));
+        db.add_message(make_msg(1, 100, "alice", "2024-01-15 10:00", "hello"));
+        db.add_message(make_msg(2, 101, "bob", "2024-01-15 10:01", "world"));
+
+        let backup = dir.path().join("backup.db");
+        db.backup_to(&backup).unwrap();
+        // Still usable after the backup
+        db.add_message(make_msg(3, 100, "alice", "2024-01-15 10:02", "again"));
+
+        let copy = Database::load_or_new(&backup);
+        assert_eq!(copy.get_counts().0, 2);
+        let integrity: String = copy.conn.query_row("PRAGMA integrity_check", [], |row| row.get(0)).unwrap();
+        assert_eq!(integrity, "ok");
+    }
+
+    #[test]
+    fn test_unknown_dms_persist() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let now = Utc::now();
+        let week = chrono::Duration::days(7);
+        {
+            let mut db = Database::load_or_new(&path);
+            assert_eq!(db.record_unknown_dm(42, now, week).unwrap(), 1);
+            assert_eq!(db.record_unknown_dm(42, now, week).unwrap(), 2);
+            assert_eq!(db.record_unknown_dm(7, now, week).unwrap(), 1);
+        }
+
+        // A restart doesn't make anyone new again
+        let mut db = Database::load_or_new(&path);
+        assert_eq!(db.record_unknown_dm(42, now, week).unwrap(), 3);
+        assert_eq!(db.record_unknown_dm(99, now, week).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_unknown_dms_expire_and_are_forgotten() {
+        let mut db = Database::new();
+        let week = chrono::Duration::days(7);
+        let start = Utc::now() - chrono::Duration::days(30);
+        assert_eq!(db.record_unknown_dm(42, start, week).unwrap(), 1);
+        assert_eq!(db.record_unknown_dm(7, start, week).unwrap(), 1);
+        assert_eq!(db.record_unknown_dm(42, start + chrono::Duration::days(6), week).unwrap(), 2);
+
+        // A week after the first DM the record is gone, for everyone
+        let later = start + chrono::Duration::days(8);
+        assert_eq!(db.record_unknown_dm(42, later, week).unwrap(), 1);
+        let tracked: i64 = db.conn.query_row("SELECT COUNT(*) FROM unknown_dms", [], |row| row.get(0)).unwrap();
+        assert_eq!(tracked, 1);
+
+        // Trusting them wipes the slate
+        assert_eq!(db.record_unknown_dm(42, later, week).unwrap(), 2);
+        assert_eq!(db.forget_unknown_dms(&[42, 5]).unwrap(), 1);
+        assert_eq!(db.record_unknown_dm(42, later, week).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_unknown_dms_capped() {
+        let mut db = Database::new();
+        let week = chrono::Duration::days(7);
+        let start = Utc::now();
+        db.conn.execute_batch("BEGIN").unwrap();
+        for user_id in 0..=MAX_UNKNOWN_DMS as i64 {
+            db.conn.execute(
+                "INSERT INTO unknown_dms (user_id, messages, first_seen) VALUES (?1, 1, ?2)",
+                params![user_id, (start + chrono::Duration::seconds(user_id)).to_rfc3339()],
+            ).unwrap();
+        }
+        db.conn.execute_batch("COMMIT").unwrap();
+
+        // The next DM trims the oldest records back to the cap
+        let now = start + chrono::Duration::hours(1);
+        assert_eq!(db.record_unknown_dm(-1, now, week).unwrap(), 1);
+        let oldest: i64 = db.conn.query_row("SELECT MIN(user_id) FROM unknown_dms WHERE user_id >= 0", [], |row| row.get(0)).unwrap();
+        assert_eq!(oldest, 2);
+        let tracked: i64 = db.conn.query_row("SELECT COUNT(*) FROM unknown_dms", [], |row| row.get(0)).unwrap();
+        assert_eq!(tracked, MAX_UNKNOWN_DMS as i64);
+    }
+
+    #[test]
+    fn test_file_database_uses_wal() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::load_or_new(&dir.path().join("test.db"));
+        let mode: String = db.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+    }
+
+    #[test]
+    fn test_ocr_text_stored_and_migrated() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("old.db");
+        // A database from before the ocr_text column
+        Connection::open(&path).unwrap().execute_batch(
+            "CREATE TABLE messages (message_id INTEGER PRIMARY KEY, chat_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL, username TEXT NOT NULL, timestamp TEXT NOT NULL,
+                text TEXT NOT NULL, reply_to_id INTEGER, reply_to_username TEXT, reply_to_text TEXT);
+             INSERT INTO messages VALUES (1, -12345, 100, 'alice', '2024-01-15 10:00', 'old', NULL, NULL, NULL);"
+        ).unwrap();
+
+        let mut db = Database::load_or_new(&path);
+        let mut msg = make_msg(2, 100, "alice", "2024-01-15 10:01", "");
+        msg.ocr_text = Some("Invoice #4417 total due 129.00 EUR".to_string());
+        db.add_message(msg);
+
+        let recent = db.recent_in_chat(-12345, 10).unwrap();
+        assert_eq!(recent[0].ocr_text.as_deref(), Some("Invoice #4417 total due 129.00 EUR"));
+        assert_eq!(recent[1].ocr_text, None);
+        assert!(db.query("SELECT message_id FROM messages WHERE ocr_text LIKE '%invoice%'").unwrap().contains('2'));
+        // Rows from before the from_bot column read as human
+        assert!(!recent[1].from_bot);
+    }
+
+    #[test]
+    fn test_locations_stored() {
+        let mut db = Database::new();
+        let venue = Location {
+            latitude: 41.311081,
+            longitude: 69.240562,
+            title: Some("Chorsu Bazaar".to_string()),
+            address: Some("Tashkent".to_string()),
+        };
+        let mut msg = make_msg(1, 100, "alice", "2024-01-15 10:00", "");
+        msg.location = Some(venue.clone());
+        db.add_message(msg);
+        let mut pin = make_msg(2, 100, "alice", "2024-01-15 10:01", "");
+        pin.location = Some(Location { latitude: -33.86, longitude: 151.21, title: None, address: None });
+        db.add_message(pin);
+        db.add_message(make_msg(3, 100, "alice", "2024-01-15 10:02", "see you there"));
+
+        let recent = db.recent_in_chat(-12345, 10).unwrap();
+        assert_eq!(recent[2].location.as_ref(), Some(&venue));
+        assert_eq!(recent[1].location.as_ref().map(|l| (l.latitude, l.title.clone())), Some((-33.86, None)));
+        assert_eq!(recent[0].location, None);
+        assert!(db.query("SELECT message_id FROM messages WHERE location_title = 'Chorsu Bazaar'").unwrap().contains('1'));
+    }
+
+    #[test]
+    fn test_from_bot_stored() {
+        let mut db = Database::new();
+        let mut msg = make_msg(1, 100, "PeerBot", "2024-01-15 10:00", "beep");
+        msg.from_bot = true;
+        db.add_message(msg);
+        db.add_message(make_msg(2, 101, "alice", "2024-01-15 10:01", "hi"));
+
+        let recent = db.recent_in_chat(-12345, 10).unwrap();
+        assert!(!recent[0].from_bot);
+        assert!(recent[1].from_bot);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_async_stress_concurrent_writes_and_queries() {
+        const WRITERS: i64 = 10;
+        const PER_WRITER: i64 = 1000;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = AsyncDatabase::new(Database::load_or_new(&dir.path().join("stress.db")));
+
+        let mut writers = Vec::new();
+        for w in 0..WRITERS {
+            let db = db.clone();
+            writers.push(tokio::spawn(async move {
+                for i in 0..PER_WRITER {
+                    let id = w * PER_WRITER + i;
+                    let msg = make_msg(id, 100 + w, &format!("user{w}"), "2024-01-15 10:00", "stress");
+                    db.call(move |db| db.add_message(msg)).await.unwrap();
+                }
+            }));
+        }
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                let mut max_latency = Duration::ZERO;
+                for _ in 0..200 {
+                    let start = Instant::now();
+                    db.call(|db| db.query("SELECT COUNT(*) FROM messages")).await.unwrap().unwrap();
+                    max_latency = max_latency.max(start.elapsed());
+                }
+                max_latency
+            })
+        }).collect();
+
+        let all = async {
+            for w in writers {
+                w.await.unwrap();
+            }
+            let mut worst = Duration::ZERO;
+            for r in readers {
+                worst = worst.max(r.await.unwrap());
+            }
+            worst
+        };
+        let worst = tokio::time::timeout(Duration::from_secs(60), all)
+            .await
+            .expect("stress test deadlocked");
+
+        assert!(worst < Duration::from_secs(5), "query latency too high: {worst:?}");
+        let count = db.call(|db| db.message_count()).await.unwrap();
+        assert_eq!(count, (WRITERS * PER_WRITER) as usize);
+    }
 }
@@ -1,34 +1,62 @@
-//! Debounce timer for batching rapid messages before Claude API calls.
+//! Debounce timer for batching rapid messages before Claude API calls, and
+//! the single worker that runs the resulting turns one at a time.
 
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, Notify};
-use tokio::time::sleep;
-use tracing::warn;
+use tokio::time::{sleep_until, Instant};
+use tracing::{debug, warn};
+
+/// When a debounce fires: `delay` after the latest trigger, and with a
+/// `max_wait` cap, no later than that after the first one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Deadline {
+    first: Instant,
+    at: Instant,
+}
+
+impl Deadline {
+    fn start(now: Instant, delay: Duration, max_wait: Option<Duration>) -> Self {
+        let mut deadline = Self { first: now, at: now };
+        deadline.extend(now, delay, max_wait);
+        deadline
+    }
+
+    /// Restart the timer for a trigger at `now`.
+    fn extend(&mut self, now: Instant, delay: Duration, max_wait: Option<Duration>) {
+        self.at = match max_wait {
+            Some(max_wait) => (now + delay).min(self.first + max_wait),
+            None => now + delay,
+        };
+    }
+}
 
 /// Debounce timer that triggers a callback after a period of inactivity.
 ///
-/// Each call to `trigger()` resets the timer. When the timer expires
-/// (no triggers for the specified duration), the callback is executed.
+/// Each call to `trigger()` resets the timer to that trigger's delay. When
+/// the timer expires (no triggers for the delay), the callback is executed.
+/// With a max wait, a steady stream of triggers can only hold the callback
+/// off that long after the first one.
 ///
 /// This struct is `Clone` - all clones share the same underlying timer.
 #[derive(Clone)]
 pub struct Debouncer {
-    /// Channel to signal reset
-    reset_tx: mpsc::Sender<()>,
+    /// Channel to signal reset, with the new delay
+    reset_tx: mpsc::Sender<Duration>,
     /// Notify to cancel the timer
     cancel: Arc<Notify>,
 }
 
 impl Debouncer {
-    /// Create a new debouncer with the given duration.
-    ///
-    /// The callback will be called after `duration` of inactivity.
-    pub fn new<F>(duration: Duration, callback: F) -> Self
+    /// Create a new debouncer, optionally capping how long triggers can
+    /// keep extending the timer.
+    pub fn new<F>(max_wait: Option<Duration>, callback: F) -> Self
     where
         F: Fn() + Send + Sync + 'static,
     {
-        let (reset_tx, mut reset_rx) = mpsc::channel::<()>(16);
+        let (reset_tx, mut reset_rx) = mpsc::channel::<Duration>(16);
         let cancel = Arc::new(Notify::new());
         let cancel_clone = cancel.clone();
         let callback = Arc::new(callback);
@@ -43,10 +71,11 @@ impl Debouncer {
                         break;
                     }
                     result = reset_rx.recv() => {
-                        if result.is_none() {
+                        let Some(delay) = result else {
                             // Channel closed, exit
                             break;
-                        }
+                        };
+                        let mut deadline = Deadline::start(Instant::now(), delay, max_wait);
 
                         // Debounce loop: keep resetting while triggers come in
                         loop {
@@ -54,13 +83,14 @@ impl Debouncer {
                                 biased;
 
                                 result = reset_rx.recv() => {
-                                    if result.is_none() {
+                                    let Some(delay) = result else {
                                         // Channel closed
                                         return;
-                                    }
+                                    };
                                     // Reset received, restart the timer
+                                    deadline.extend(Instant::now(), delay, max_wait);
                                 }
-                                _ = sleep(duration) => {
+                                _ = sleep_until(deadline.at) => {
                                     // Timer expired, call callback
                                     callback();
                                     break;
@@ -75,12 +105,12 @@ impl Debouncer {
         Self { reset_tx, cancel }
     }
 
-    /// Trigger/reset the debounce timer.
+    /// Trigger/reset the debounce timer to fire after `delay`.
     ///
     /// If the timer is running, it will be reset.
     /// If the timer is not running, it will start.
-    pub async fn trigger(&self) {
-        if self.reset_tx.send(()).await.is_err() {
+    pub async fn trigger(&self, delay: Duration) {
+        if self.reset_tx.send(delay).await.is_err() {
             warn!("Debounce channel closed");
         }
     }
@@ -92,23 +122,75 @@ impl Drop for Debouncer {
     }
 }
 
+/// Runs processing turns on a single worker task.
+///
+/// A turn can take minutes (Claude's tool loop), and debouncer fires keep
+/// coming meanwhile. Requests made while a turn runs collapse into one queued
+/// turn, so the worker never stacks up stale runs: the next turn starts after
+/// the current one and picks up everything that arrived in between.
+///
+/// A message can still land in pending with no request after it: pushed
+/// between a fire and the turn taking pending, or by a path that doesn't
+/// trigger. So each turn says whether anything is still pending when it
+/// ends, and if so the worker runs another one straight away instead of
+/// leaving it for whatever fires next.
+///
+/// This struct is `Clone` - all clones feed the same worker.
+#[derive(Clone)]
+pub struct TurnQueue {
+    tx: mpsc::Sender<()>,
+}
+
+impl TurnQueue {
+    /// Spawn the worker. `turn` is awaited once per processing turn and
+    /// returns whether work is left over for another.
+    pub fn spawn<F, Fut>(turn: F) -> Self
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = bool> + Send,
+    {
+        // Capacity 1: at most one turn queued behind the running one
+        let (tx, mut rx) = mpsc::channel::<()>(1);
+        tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                while turn().await {
+                    debug!("Messages left pending after the turn, running another");
+                    // The next turn takes everything, so a queued request is covered
+                    let _ = rx.try_recv();
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    /// Ask for a turn. No-op if one is already queued.
+    pub fn request(&self) {
+        match self.tx.try_send(()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(())) => debug!("Turn already queued"),
+            Err(TrySendError::Closed(())) => warn!("Turn worker stopped"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::Duration;
+    use tokio::time::sleep;
 
     #[tokio::test]
     async fn test_debounce_triggers_after_duration() {
         let counter = Arc::new(AtomicUsize::new(0));
         let counter_clone = counter.clone();
 
-        let debouncer = Debouncer::new(Duration::from_millis(50), move || {
+        let debouncer = Debouncer::new(None, move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
         });
 
         // Trigger once
-        debouncer.trigger().await;
+        debouncer.trigger(Duration::from_millis(50)).await;
 
         // Should not fire immediately
         assert_eq!(counter.load(Ordering::SeqCst), 0);
@@ -125,13 +207,13 @@ mod tests {
         let counter = Arc::new(AtomicUsize::new(0));
         let counter_clone = counter.clone();
 
-        let debouncer = Debouncer::new(Duration::from_millis(50), move || {
+        let debouncer = Debouncer::new(None, move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
         });
 
         // Trigger multiple times rapidly
         for _ in 0..5 {
-            debouncer.trigger().await;
+            debouncer.trigger(Duration::from_millis(50)).await;
             sleep(Duration::from_millis(20)).await;
         }
 
@@ -150,17 +232,17 @@ mod tests {
         let counter = Arc::new(AtomicUsize::new(0));
         let counter_clone = counter.clone();
 
-        let debouncer = Debouncer::new(Duration::from_millis(30), move || {
+        let debouncer = Debouncer::new(None, move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
         });
 
         // First cycle
-        debouncer.trigger().await;
+        debouncer.trigger(Duration::from_millis(30)).await;
         sleep(Duration::from_millis(60)).await;
         assert_eq!(counter.load(Ordering::SeqCst), 1);
 
         // Second cycle
-        debouncer.trigger().await;
+        debouncer.trigger(Duration::from_millis(30)).await;
         sleep(Duration::from_millis(60)).await;
         assert_eq!(counter.load(Ordering::SeqCst), 2);
     }
@@ -170,11 +252,11 @@ mod tests {
         let counter = Arc::new(AtomicUsize::new(0));
         let counter_clone = counter.clone();
 
-        let debouncer = Debouncer::new(Duration::from_millis(50), move || {
+        let debouncer = Debouncer::new(None, move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
         });
 
-        debouncer.trigger().await;
+        debouncer.trigger(Duration::from_millis(50)).await;
         drop(debouncer); // Drop cancels the debouncer
 
         // Wait past when it would have fired
@@ -183,4 +265,138 @@ mod tests {
         // Should not have fired due to drop
         assert_eq!(counter.load(Ordering::SeqCst), 0);
     }
+
+    #[test]
+    fn test_deadline_extension_cap() {
+        let start = Instant::now();
+        let delay = Duration::from_secs(3);
+        let cap = Some(Duration::from_secs(8));
+
+        // A message every 500ms keeps pushing the deadline out, up to the cap
+        let mut deadline = Deadline::start(start, delay, cap);
+        assert_eq!(deadline.at, start + delay);
+        deadline.extend(start + Duration::from_millis(500), delay, cap);
+        assert_eq!(deadline.at, start + Duration::from_millis(3500));
+        for ms in (1000..=20_000).step_by(500) {
+            deadline.extend(start + Duration::from_millis(ms), delay, cap);
+        }
+        assert_eq!(deadline.at, start + Duration::from_secs(8));
+
+        // Without a cap, the latest trigger always wins
+        let mut uncapped = Deadline::start(start, delay, None);
+        uncapped.extend(start + Duration::from_secs(20), delay, None);
+        assert_eq!(uncapped.at, start + Duration::from_secs(23));
+
+        // A cap shorter than the delay applies from the start
+        let short = Deadline::start(start, delay, Some(Duration::from_secs(1)));
+        assert_eq!(short.at, start + Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_debounce_max_wait_fires_during_stream() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let counter_clone = counter.clone();
+
+        let debouncer = Debouncer::new(Some(Duration::from_millis(100)), move || {
+            counter_clone.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // Triggers every 20ms for 300ms would hold off a plain debounce forever
+        for _ in 0..15 {
+            debouncer.trigger(Duration::from_millis(50)).await;
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(counter.load(Ordering::SeqCst) >= 2);
+    }
+
+    /// Worker whose turns drain `pending` and take 100ms, like a slow Claude.
+    /// Like the engine's, a turn reports whether anything is still pending.
+    fn slow_worker(
+        pending: Arc<std::sync::Mutex<Vec<u32>>>,
+        batches: Arc<std::sync::Mutex<Vec<Vec<u32>>>>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    ) -> TurnQueue {
+        TurnQueue::spawn(move || {
+            let pending = pending.clone();
+            let batches = batches.clone();
+            let running = running.clone();
+            let max_running = max_running.clone();
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+                let batch = std::mem::take(&mut *pending.lock().unwrap());
+                batches.lock().unwrap().push(batch);
+                sleep(Duration::from_millis(100)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                !pending.lock().unwrap().is_empty()
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_turn_queue_collapses_requests_while_busy() {
+        let pending = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let turns = slow_worker(pending.clone(), batches.clone(), running, max_running.clone());
+
+        pending.lock().unwrap().push(1);
+        turns.request();
+        sleep(Duration::from_millis(20)).await;
+
+        // Three rapid fires while the first turn is still running
+        for n in 2..=4 {
+            pending.lock().unwrap().push(n);
+            turns.request();
+        }
+        sleep(Duration::from_millis(350)).await;
+
+        // One queued turn picks up everything that arrived meanwhile
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1], vec![2, 3, 4]]);
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_turn_queue_rapid_fires_from_idle() {
+        let pending = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let turns = slow_worker(pending.clone(), batches.clone(), running, max_running.clone());
+
+        for n in 1..=3 {
+            pending.lock().unwrap().push(n);
+            turns.request();
+        }
+        sleep(Duration::from_millis(350)).await;
+
+        let batches = batches.lock().unwrap();
+        assert!(batches.len() <= 2, "expected at most two turns, got {:?}", *batches);
+        // Every message processed exactly once, in order
+        assert_eq!(batches.concat(), vec![1, 2, 3]);
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_turn_queue_runs_again_for_stranded_messages() {
+        let pending = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let turns = slow_worker(pending.clone(), batches.clone(), running, max_running.clone());
+
+        pending.lock().unwrap().push(1);
+        turns.request();
+        sleep(Duration::from_millis(20)).await;
+
+        // Arrives after the turn took pending, and nothing fires again
+        pending.lock().unwrap().push(2);
+        sleep(Duration::from_millis(350)).await;
+
+        assert_eq!(*batches.lock().unwrap(), vec![vec![1], vec![2]]);
+        assert!(pending.lock().unwrap().is_empty());
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
 }
@@ -1,10 +1,11 @@
-//! DOCX text extraction.
+//! DOCX text extraction, and writing simple table documents.
 //!
 //! Extracts plain text from .docx files (Office Open XML format).
 //! DOCX files are ZIP archives containing XML documents.
 
-use std::io::{Cursor, Read};
-use zip::ZipArchive;
+use std::io::{Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
 
 /// Extract plain text from a DOCX file.
 ///
@@ -14,22 +15,8 @@ use zip::ZipArchive;
 ///
 /// Returns the extracted text, or an error message if extraction fails.
 pub fn extract_text(data: &[u8]) -> Result<String, String> {
-    let cursor = Cursor::new(data);
-    let mut archive = ZipArchive::new(cursor)
-        .map_err(|e| format!("Invalid DOCX (not a valid ZIP): {e}"))?;
-
-    // Find and read word/document.xml
-    let mut document_xml = String::new();
-    {
-        let mut file = archive
-            .by_name("word/document.xml")
-            .map_err(|_| "Invalid DOCX: missing word/document.xml")?;
-        file.read_to_string(&mut document_xml)
-            .map_err(|e| format!("Failed to read document.xml: {e}"))?;
-    }
-
     // Parse XML and extract text from <w:t> elements
-    let text = extract_text_from_xml(&document_xml);
+    let text = extract_text_from_xml(&document_xml(data)?);
 
     if text.trim().is_empty() {
         return Err("DOCX appears to be empty or contains no text".to_string());
@@ -38,6 +25,21 @@ pub fn extract_text(data: &[u8]) -> Result<String, String> {
     Ok(text)
 }
 
+/// Read word/document.xml out of a DOCX file.
+pub fn document_xml(data: &[u8]) -> Result<String, String> {
+    let cursor = Cursor::new(data);
+    let mut archive = ZipArchive::new(cursor)
+        .map_err(|e| format!("Invalid DOCX (not a valid ZIP): {e}"))?;
+
+    let mut document_xml = String::new();
+    let mut file = archive
+        .by_name("word/document.xml")
+        .map_err(|_| "Invalid DOCX: missing word/document.xml")?;
+    file.read_to_string(&mut document_xml)
+        .map_err(|e| format!("Failed to read document.xml: {e}"))?;
+    Ok(document_xml)
+}
+
 /// Extract text content from Word XML.
 ///
 /// Finds all <w:t> (text) elements and joins them, preserving paragraph breaks.
@@ -110,22 +112,16 @@ fn extract_text_from_xml(xml: &str) -> String {
                         in_paragraph = true;
                         paragraph_text.clear();
                     }
-                    "w:t" => {
-                        if !is_self_closing {
-                            in_text_element = true;
-                        }
+                    "w:t" if !is_self_closing => {
+                        in_text_element = true;
                     }
                     // Handle line breaks within paragraphs
-                    "w:br" => {
-                        if in_paragraph {
-                            paragraph_text.push('\n');
-                        }
+                    "w:br" if in_paragraph => {
+                        paragraph_text.push('\n');
                     }
                     // Handle tabs
-                    "w:tab" => {
-                        if in_paragraph {
-                            paragraph_text.push('\t');
-                        }
+                    "w:tab" if in_paragraph => {
+                        paragraph_text.push('\t');
                     }
                     _ => {}
                 }
@@ -171,6 +167,64 @@ fn extract_text_from_xml(xml: &str) -> String {
     result
 }
 
+const CONTENT_TYPES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#;
+
+const RELS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/></Relationships>"#;
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// One paragraph, bold or not.
+fn paragraph(text: &str, bold: bool) -> String {
+    let props = if bold { "<w:rPr><w:b/></w:rPr>" } else { "" };
+    format!(r#"<w:p><w:r>{props}<w:t xml:space="preserve">{}</w:t></w:r></w:p>"#, escape_xml(text))
+}
+
+/// Build word/document.xml: a bold title, then a bordered table whose
+/// first row is a bold header.
+fn table_document_xml(title: &str, rows: &[Vec<String>]) -> String {
+    let border = r#"w:val="single" w:sz="4" w:space="0" w:color="000000""#;
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>"#);
+    xml.push_str(&paragraph(title, true));
+    xml.push_str("<w:tbl>");
+    xml.push_str(&format!(
+        r#"<w:tblPr><w:tblW w:w="5000" w:type="pct"/><w:tblBorders><w:top {border}/><w:left {border}/><w:bottom {border}/><w:right {border}/><w:insideH {border}/><w:insideV {border}/></w:tblBorders></w:tblPr>"#
+    ));
+    for (i, row) in rows.iter().enumerate() {
+        xml.push_str("<w:tr>");
+        for cell in row {
+            xml.push_str("<w:tc>");
+            xml.push_str(&paragraph(cell, i == 0));
+            xml.push_str("</w:tc>");
+        }
+        xml.push_str("</w:tr>");
+    }
+    xml.push_str("</w:tbl><w:sectPr/></w:body></w:document>");
+    xml
+}
+
+/// Write a DOCX file holding `title` and a table of `rows` (the first row
+/// is the header).
+pub fn write_table(title: &str, rows: &[Vec<String>]) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in [
+        ("[Content_Types].xml", CONTENT_TYPES_XML.to_string()),
+        ("_rels/.rels", RELS_XML.to_string()),
+        ("word/document.xml", table_document_xml(title, rows)),
+    ] {
+        zip.start_file(name, SimpleFileOptions::default())
+            .map_err(|e| format!("Failed to write {name}: {e}"))?;
+        zip.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write {name}: {e}"))?;
+    }
+    let cursor = zip.finish().map_err(|e| format!("Failed to finish DOCX: {e}"))?;
+    Ok(cursor.into_inner())
+}
+
 /// Get a preview of document content (first N chars).
 pub fn preview(text: &str, max_chars: usize) -> String {
     if text.len() <= max_chars {
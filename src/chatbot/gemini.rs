@@ -1,4 +1,6 @@
-//! Gemini API client for image generation (Nano Banana).
+//! Gemini API client for image generation (Nano Banana), OCR and cheap text tasks.
+
+use std::future::Future;
 
 use base64::Engine;
 use serde::{Deserialize, Serialize};
@@ -7,8 +9,27 @@ use tracing::{debug, info};
 const GEMINI_API_URL: &str =
     "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image:generateContent";
 
+const GEMINI_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+
+/// Default model for text generation (translation, delegation).
+pub const DEFAULT_TEXT_MODEL: &str = "gemini-2.5-flash";
+
+/// Text generation backend. Implemented by GeminiClient; mocked in tests.
+pub trait TextGenerator {
+    fn generate_text(&self, prompt: &str) -> impl Future<Output = Result<String, String>> + Send;
+}
+
+/// Reads text out of images. Implemented by GeminiClient; mocked in tests.
+pub trait TextExtractor {
+    fn extract_text(&self, image: &[u8], mime: &str) -> impl Future<Output = Result<String, String>> + Send;
+}
+
+const OCR_PROMPT: &str = "Transcribe all text in this image exactly as written, keeping line breaks. \
+    Output only the transcription, nothing else. If there is no text, output nothing.";
+
 pub struct GeminiClient {
     api_key: String,
+    text_model: String,
     client: reqwest::Client,
 }
 
@@ -24,9 +45,19 @@ struct Content {
     parts: Vec<Part>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 struct Part {
-    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(rename = "inlineData", skip_serializing_if = "Option::is_none")]
+    inline_data: Option<InlineDataRequest>,
+}
+
+#[derive(Serialize)]
+struct InlineDataRequest {
+    #[serde(rename = "mimeType")]
+    mime_type: String,
+    data: String,
 }
 
 #[derive(Serialize)]
@@ -58,6 +89,8 @@ struct CandidateContent {
 
 #[derive(Deserialize, Debug)]
 struct ResponsePart {
+    #[serde(default)]
+    text: Option<String>,
     #[serde(rename = "inlineData")]
     inline_data: Option<InlineData>,
 }
@@ -78,25 +111,52 @@ impl GeminiClient {
             .build()
             .expect("Failed to build HTTP client");
 
-        Self { api_key, client }
+        Self { api_key, text_model: DEFAULT_TEXT_MODEL.to_string(), client }
+    }
+
+    /// Use a different model for text generation.
+    pub fn with_text_model(mut self, model: &str) -> Self {
+        self.text_model = model.to_string();
+        self
     }
 
     /// Generate an image from a text prompt.
     pub async fn generate_image(&self, prompt: &str) -> Result<GeneratedImage, String> {
         info!("🎨 Generating image: {}", prompt);
 
+        let parts = self
+            .generate(GEMINI_API_URL, vec![Part::text(prompt)], vec!["TEXT".to_string(), "IMAGE".to_string()])
+            .await?;
+
+        // Find the image part
+        for part in &parts {
+            if let Some(ref inline_data) = part.inline_data {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(&inline_data.data)
+                    .map_err(|e| format!("Failed to decode base64: {e}"))?;
+
+                info!("🎨 Image generated: {} bytes", data.len());
+
+                return Ok(GeneratedImage { data });
+            }
+        }
+
+        Err("No image in response".to_string())
+    }
+
+    /// Send a single-turn request and return the response parts of the first candidate.
+    async fn generate(
+        &self,
+        api_url: &str,
+        parts: Vec<Part>,
+        response_modalities: Vec<String>,
+    ) -> Result<Vec<ResponsePart>, String> {
         let request = GenerateRequest {
-            contents: vec![Content {
-                parts: vec![Part {
-                    text: prompt.to_string(),
-                }],
-            }],
-            generation_config: GenerationConfig {
-                response_modalities: vec!["TEXT".to_string(), "IMAGE".to_string()],
-            },
+            contents: vec![Content { parts }],
+            generation_config: GenerationConfig { response_modalities },
         };
 
-        let url = format!("{}?key={}", GEMINI_API_URL, self.api_key);
+        let url = format!("{}?key={}", api_url, self.api_key);
 
         let response = self
             .client
@@ -126,25 +186,111 @@ impl GeminiClient {
         }
 
         let candidates = parsed.candidates.ok_or("No candidates in response")?;
-        let candidate = candidates.first().ok_or("Empty candidates array")?;
-        let content = candidate
-            .content
-            .as_ref()
-            .ok_or("No content in candidate")?;
+        let candidate = candidates.into_iter().next().ok_or("Empty candidates array")?;
+        let content = candidate.content.ok_or("No content in candidate")?;
 
-        // Find the image part
-        for part in &content.parts {
-            if let Some(ref inline_data) = part.inline_data {
-                let data = base64::engine::general_purpose::STANDARD
-                    .decode(&inline_data.data)
-                    .map_err(|e| format!("Failed to decode base64: {e}"))?;
+        Ok(content.parts)
+    }
+}
 
-                info!("🎨 Image generated: {} bytes", data.len());
+impl Part {
+    fn text(text: &str) -> Self {
+        Self { text: Some(text.to_string()), ..Default::default() }
+    }
+}
 
-                return Ok(GeneratedImage { data });
-            }
+impl TextGenerator for GeminiClient {
+    /// Generate text from a prompt using the configured text model.
+    async fn generate_text(&self, prompt: &str) -> Result<String, String> {
+        let url = format!("{}/{}:generateContent", GEMINI_MODELS_URL, self.text_model);
+        let parts = self
+            .generate(&url, vec![Part::text(prompt)], vec!["TEXT".to_string()])
+            .await?;
+
+        let text: String = parts.into_iter().filter_map(|p| p.text).collect();
+        if text.trim().is_empty() {
+            return Err("No text in response".to_string());
         }
 
-        Err("No image in response".to_string())
+        Ok(text)
+    }
+}
+
+impl TextExtractor for GeminiClient {
+    /// Transcribe the text in an image with the text model. Empty if there is none.
+    async fn extract_text(&self, image: &[u8], mime: &str) -> Result<String, String> {
+        let url = format!("{}/{}:generateContent", GEMINI_MODELS_URL, self.text_model);
+        let image_part = Part {
+            inline_data: Some(InlineDataRequest {
+                mime_type: mime.to_string(),
+                data: base64::engine::general_purpose::STANDARD.encode(image),
+            }),
+            ..Default::default()
+        };
+        let parts = self
+            .generate(&url, vec![image_part, Part::text(OCR_PROMPT)], vec!["TEXT".to_string()])
+            .await?;
+
+        Ok(parts.into_iter().filter_map(|p| p.text).collect::<String>().trim().to_string())
+    }
+}
+
+/// Check a model name is safe to put in the API URL (e.g. "gemini-2.5-flash").
+pub fn validate_model_name(model: &str) -> Result<(), String> {
+    let valid = !model.is_empty()
+        && model.len() <= 64
+        && model.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid Gemini model name '{}'", model))
+    }
+}
+
+/// Test double for TextGenerator: records prompts, returns a canned response.
+#[cfg(test)]
+pub(crate) struct MockGemini {
+    pub prompts: std::sync::Mutex<Vec<String>>,
+    response: Result<String, String>,
+}
+
+#[cfg(test)]
+impl MockGemini {
+    pub fn new(response: Result<&str, &str>) -> Self {
+        Self {
+            prompts: std::sync::Mutex::new(Vec::new()),
+            response: response.map(String::from).map_err(String::from),
+        }
+    }
+}
+
+#[cfg(test)]
+impl TextGenerator for MockGemini {
+    async fn generate_text(&self, prompt: &str) -> Result<String, String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        self.response.clone()
+    }
+}
+
+/// Records the image's MIME type in place of a prompt.
+#[cfg(test)]
+impl TextExtractor for MockGemini {
+    async fn extract_text(&self, _image: &[u8], mime: &str) -> Result<String, String> {
+        self.prompts.lock().unwrap().push(mime.to_string());
+        self.response.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_model_name() {
+        assert!(validate_model_name("gemini-2.5-flash").is_ok());
+        assert!(validate_model_name("gemini-2.0-flash-lite_001").is_ok());
+        assert!(validate_model_name("").is_err());
+        assert!(validate_model_name("../evil").is_err());
+        assert!(validate_model_name("model?key=x").is_err());
     }
 }
@@ -0,0 +1,244 @@
+//! Telegram HTML in outbound messages.
+//!
+//! Claude formats replies with Telegram's HTML subset. Before a message is
+//! sent, `prepare` turns `||spoiler||` shorthand into `<tg-spoiler>`, closes
+//! tags left open, and rejects markup Telegram would refuse (unsupported
+//! tags, stray closing tags, nested blockquotes) with an error Claude can fix
+//! instead of Telegram's "can't parse entities". The prompt's formatting rules
+//! are built from the same tag list. `split` cuts prepared messages longer
+//! than Telegram allows into parts that are each valid on their own.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// HTML tags Telegram accepts in HTML parse mode.
+pub const ALLOWED_TAGS: &[&str] = &[
+    "b", "strong", "i", "em", "u", "ins", "s", "strike", "del",
+    "code", "pre", "a", "span", "tg-spoiler", "tg-emoji", "blockquote",
+];
+
+/// Longest message Telegram accepts, in characters.
+pub const MAX_MESSAGE_CHARS: usize = 4096;
+
+/// Room left in each part for the closing tags added at a cut.
+const CLOSING_TAG_ROOM: usize = 64;
+
+static TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9-]*)[^>]*>").expect("valid tag regex"));
+
+/// Code spans, where `||` is literal.
+static CODE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<pre\b.*?</pre>|<code\b.*?</code>").expect("valid code regex"));
+
+static SPOILER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\|\|([^|\n]+?)\|\|").expect("valid spoiler regex"));
+
+/// Formatting rules for the system prompt.
+pub fn prompt_rules() -> String {
+    format!(
+        "Telegram HTML only: {}.\n\
+         - <tg-spoiler>hidden</tg-spoiler> hides spoilers (||hidden|| works too)\n\
+         - <blockquote>quote</blockquote> for quoting people; <blockquote expandable> for long quotes\n\
+         - Blockquotes can't be nested",
+        ALLOWED_TAGS.join(", ")
+    )
+}
+
+/// Escape plain text (e.g. a user's name) for Telegram HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Whether `text` contains a tag Telegram doesn't support (e.g. `<script>`, `<cite>`, `<div>`).
+pub fn has_unsupported_tags(text: &str) -> bool {
+    TAG_RE.captures_iter(text).any(|cap| !ALLOWED_TAGS.contains(&cap[2].to_ascii_lowercase().as_str()))
+}
+
+/// Get a message ready to send: convert spoiler shorthand, then check the
+/// tags and close any left open.
+pub fn prepare(text: &str) -> Result<String, String> {
+    balance(&convert_spoilers(text))
+}
+
+/// Turn `||text||` into `<tg-spoiler>text</tg-spoiler>`, outside code.
+fn convert_spoilers(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for code in CODE_RE.find_iter(text) {
+        out.push_str(&SPOILER_RE.replace_all(&text[last..code.start()], "<tg-spoiler>$1</tg-spoiler>"));
+        out.push_str(code.as_str());
+        last = code.end();
+    }
+    out.push_str(&SPOILER_RE.replace_all(&text[last..], "<tg-spoiler>$1</tg-spoiler>"));
+    out
+}
+
+/// Split a prepared message into parts of at most `max_chars`, cutting at
+/// line ends, else spaces, where it can. Tags open at a cut are closed there
+/// and reopened at the start of the next part.
+pub fn split(text: &str, max_chars: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut reopen = String::new();
+    let mut rest = text;
+    loop {
+        let budget = max_chars.saturating_sub(reopen.chars().count() + CLOSING_TAG_ROOM).max(1);
+        let Some((limit, _)) = rest.char_indices().nth(budget) else {
+            parts.push(format!("{}{}", reopen, rest));
+            return parts;
+        };
+        let cut = cut_point(&rest[..limit]);
+        let head = format!("{}{}", reopen, &rest[..cut]);
+
+        // Tags still open at the cut, with their attributes
+        let mut open: Vec<(String, String)> = Vec::new();
+        for cap in TAG_RE.captures_iter(&head) {
+            if cap[1].is_empty() {
+                open.push((cap[2].to_ascii_lowercase(), cap[0].to_string()));
+            } else if let Some(i) = open.iter().rposition(|(name, _)| name.eq_ignore_ascii_case(&cap[2])) {
+                open.truncate(i);
+            }
+        }
+        let closing: String = open.iter().rev().map(|(name, _)| format!("</{}>", name)).collect();
+        parts.push(format!("{}{}", head, closing));
+        reopen = open.into_iter().map(|(_, tag)| tag).collect();
+        rest = rest[cut..].strip_prefix(['\n', ' ']).unwrap_or(&rest[cut..]);
+    }
+}
+
+/// Where to end a part that may hold at most `head`: after its last line
+/// break or space in the second half, else at its end, but never inside a
+/// tag or an entity.
+fn cut_point(head: &str) -> usize {
+    let half = head.len() / 2;
+    let mut cut = head.rfind('\n').filter(|&i| i > half)
+        .or_else(|| head.rfind(' ').filter(|&i| i > half))
+        .unwrap_or(head.len());
+    if let Some(lt) = head[..cut].rfind('<').filter(|&lt| !head[lt..cut].contains('>')) {
+        cut = lt;
+    }
+    if let Some(amp) = head[..cut].rfind('&').filter(|&amp| !head[amp..cut].contains([';', ' ', '\n'])) {
+        cut = amp;
+    }
+    if cut == 0 { head.len() } else { cut }
+}
+
+fn balance(text: &str) -> Result<String, String> {
+    let mut open: Vec<String> = Vec::new();
+    for cap in TAG_RE.captures_iter(text) {
+        let name = cap[2].to_ascii_lowercase();
+        if !ALLOWED_TAGS.contains(&name.as_str()) {
+            return Err(format!("<{}> isn't supported by Telegram. Allowed tags: {}", name, ALLOWED_TAGS.join(", ")));
+        }
+        if cap[1].is_empty() {
+            if name == "blockquote" && open.iter().any(|t| t == "blockquote") {
+                return Err("Telegram doesn't allow a <blockquote> inside another <blockquote>".to_string());
+            }
+            open.push(name);
+        } else if open.last() == Some(&name) {
+            open.pop();
+        } else if let Some(inner) = open.last().filter(|_| open.contains(&name)) {
+            return Err(format!("</{}> closes <{}> while <{}> inside it is still open", name, name, inner));
+        } else {
+            return Err(format!("</{}> has no matching <{}>", name, name));
+        }
+    }
+
+    let mut balanced = text.to_string();
+    for name in open.iter().rev() {
+        balanced.push_str(&format!("</{}>", name));
+    }
+    Ok(balanced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spoiler_conversion() {
+        assert_eq!(prepare("the killer is ||the butler||!").unwrap(), "the killer is <tg-spoiler>the butler</tg-spoiler>!");
+        assert_eq!(
+            prepare("||one|| and ||two||").unwrap(),
+            "<tg-spoiler>one</tg-spoiler> and <tg-spoiler>two</tg-spoiler>"
+        );
+        // Code keeps its pipes
+        assert_eq!(prepare("run <code>a || b || c</code>").unwrap(), "run <code>a || b || c</code>");
+        assert_eq!(prepare("<pre>x ||y||</pre> ||z||").unwrap(), "<pre>x ||y||</pre> <tg-spoiler>z</tg-spoiler>");
+        // Not a spoiler: empty or across lines
+        assert_eq!(prepare("a |||| b").unwrap(), "a |||| b");
+        assert_eq!(prepare("||a\nb||").unwrap(), "||a\nb||");
+    }
+
+    #[test]
+    fn test_blockquotes() {
+        assert_eq!(prepare("<blockquote>hi</blockquote> said bob").unwrap(), "<blockquote>hi</blockquote> said bob");
+        assert!(prepare("<blockquote expandable>long\ntext</blockquote>").is_ok());
+        assert!(prepare("<blockquote>a</blockquote><blockquote>b</blockquote>").is_ok());
+
+        let nested = prepare("<blockquote>a <blockquote>b</blockquote></blockquote>").unwrap_err();
+        assert_eq!(nested, "Telegram doesn't allow a <blockquote> inside another <blockquote>");
+        assert!(prepare("<blockquote expandable><b>a<blockquote>b").is_err());
+    }
+
+    #[test]
+    fn test_balancing() {
+        // Unclosed tags are closed in order
+        assert_eq!(prepare("<b>bold <i>both").unwrap(), "<b>bold <i>both</i></b>");
+        assert_eq!(prepare("<blockquote>quote").unwrap(), "<blockquote>quote</blockquote>");
+        assert_eq!(prepare("a < b and c > d").unwrap(), "a < b and c > d");
+
+        assert_eq!(prepare("done</b>").unwrap_err(), "</b> has no matching <b>");
+        assert_eq!(prepare("<b><i>x</b></i>").unwrap_err(), "</b> closes <b> while <i> inside it is still open");
+        assert!(prepare("see <cite>[1]</cite>").unwrap_err().starts_with("<cite> isn't supported by Telegram"));
+    }
+
+    #[test]
+    fn test_split_short_message_is_one_part() {
+        assert_eq!(split("hello", MAX_MESSAGE_CHARS), ["hello"]);
+        assert_eq!(split("", MAX_MESSAGE_CHARS), [""]);
+    }
+
+    #[test]
+    fn test_split_at_line_ends() {
+        let text = (1..=40).map(|i| format!("line {i:02} of the digest")).collect::<Vec<_>>().join("\n");
+        let parts = split(&text, 300);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(part.chars().count() <= 300, "{part}");
+            assert!(part.starts_with("line ") && part.ends_with(" of the digest"), "{part}");
+        }
+        assert_eq!(parts.join("\n"), text);
+    }
+
+    #[test]
+    fn test_split_keeps_tags_valid() {
+        let text = format!("<b>intro</b>\n<blockquote expandable>{}</blockquote> &amp; done", "word ".repeat(100));
+        let parts = split(&text, 200);
+        assert!(parts.len() > 2);
+        for part in &parts {
+            assert!(part.chars().count() <= 200);
+            // Each part is valid on its own, with nothing left to close
+            assert_eq!(prepare(part).as_ref(), Ok(part));
+        }
+        assert!(parts[1].starts_with("<blockquote expandable>word"));
+        assert!(parts.last().unwrap().ends_with("</blockquote> &amp; done"));
+    }
+
+    #[test]
+    fn test_split_without_spaces() {
+        let text = "x".repeat(500);
+        let parts = split(&text, 200);
+        assert!(parts.iter().all(|p| p.chars().count() <= 200));
+        assert_eq!(parts.concat(), text);
+    }
+
+    #[test]
+    fn test_prompt_rules_use_allowed_tags() {
+        let rules = prompt_rules();
+        for tag in ALLOWED_TAGS {
+            assert!(rules.contains(tag));
+        }
+        assert!(has_unsupported_tags("<div>x</div>"));
+        assert!(!has_unsupported_tags("<tg-spoiler>x</tg-spoiler> <BLOCKQUOTE>y</BLOCKQUOTE>"));
+    }
+}
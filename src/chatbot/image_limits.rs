@@ -0,0 +1,112 @@
+//! Size limits for images passed to Claude.
+//!
+//! Large images blow the CLI's input limits and memory once base64'd, and
+//! Claude resizes anything over `MAX_IMAGE_EDGE` anyway. Telegram serves every
+//! photo in several pre-scaled sizes, so the largest one within the limits is
+//! downloaded instead of the original; anything still over the byte limit is
+//! refused and Claude gets a placeholder instead.
+
+use teloxide::types::PhotoSize;
+
+/// Longest edge (px) worth sending to Claude; its vision input is downscaled
+/// to this anyway.
+pub const MAX_IMAGE_EDGE: u32 = 1568;
+
+/// Default for `max_image_bytes`.
+pub const DEFAULT_MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+fn fits(size: &PhotoSize, max_bytes: u64) -> bool {
+    size.width.max(size.height) <= MAX_IMAGE_EDGE && u64::from(size.file.size) <= max_bytes
+}
+
+/// The size of a photo to download: the largest within `MAX_IMAGE_EDGE` and
+/// `max_bytes`, or the smallest if none fits.
+pub fn pick_photo_size(sizes: &[PhotoSize], max_bytes: u64) -> Option<&PhotoSize> {
+    let area = |s: &&PhotoSize| u64::from(s.width) * u64::from(s.height);
+    sizes.iter()
+        .filter(|s| fits(s, max_bytes))
+        .max_by_key(area)
+        .or_else(|| sizes.iter().min_by_key(area))
+}
+
+/// Refuse images over `max_bytes`. A size of 0 means Telegram didn't say.
+pub fn check_size(bytes: u64, max_bytes: u64) -> Result<(), String> {
+    if bytes > max_bytes {
+        return Err(format!("image is {}, over the {} limit", format_mb(bytes), format_mb(max_bytes)));
+    }
+    Ok(())
+}
+
+pub fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Note left in the message text when its image isn't attached.
+pub fn image_placeholder(reason: &str) -> String {
+    format!("[image not attached: {}]", reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::{FileId, FileMeta, FileUniqueId};
+
+    fn size(width: u32, height: u32, bytes: u32) -> PhotoSize {
+        PhotoSize {
+            file: FileMeta {
+                id: FileId(format!("{}x{}", width, height)),
+                unique_id: FileUniqueId(String::new()),
+                size: bytes,
+            },
+            width,
+            height,
+        }
+    }
+
+    /// The sizes Telegram sends for a 4000x3000 phone photo.
+    fn phone_photo() -> Vec<PhotoSize> {
+        vec![
+            size(90, 68, 1_200),
+            size(320, 240, 15_000),
+            size(800, 600, 70_000),
+            size(1280, 960, 180_000),
+            size(2560, 1920, 900_000),
+        ]
+    }
+
+    #[test]
+    fn test_picks_largest_within_edge() {
+        let sizes = phone_photo();
+        let picked = pick_photo_size(&sizes, DEFAULT_MAX_IMAGE_BYTES).unwrap();
+        assert_eq!((picked.width, picked.height), (1280, 960));
+
+        // Portrait: the long edge is the height
+        let portrait = vec![size(720, 1280, 100_000), size(1080, 1920, 300_000)];
+        assert_eq!(pick_photo_size(&portrait, DEFAULT_MAX_IMAGE_BYTES).unwrap().height, 1280);
+
+        // Exactly at the limit still fits
+        let exact = vec![size(1568, 1000, 200_000), size(800, 510, 50_000)];
+        assert_eq!(pick_photo_size(&exact, DEFAULT_MAX_IMAGE_BYTES).unwrap().width, 1568);
+    }
+
+    #[test]
+    fn test_byte_limit_and_fallback() {
+        // A tight byte limit picks a smaller size
+        let sizes = phone_photo();
+        assert_eq!(pick_photo_size(&sizes, 100_000).unwrap().width, 800);
+
+        // Nothing fits: the smallest, which the download then checks
+        let huge = vec![size(4000, 3000, 9_000_000), size(2000, 1500, 6_000_000)];
+        assert_eq!(pick_photo_size(&huge, DEFAULT_MAX_IMAGE_BYTES).unwrap().width, 2000);
+        assert!(pick_photo_size(&[], DEFAULT_MAX_IMAGE_BYTES).is_none());
+    }
+
+    #[test]
+    fn test_refusal() {
+        assert!(check_size(5 * 1024 * 1024, DEFAULT_MAX_IMAGE_BYTES).is_ok());
+        assert!(check_size(0, DEFAULT_MAX_IMAGE_BYTES).is_ok());
+        let err = check_size(20 * 1024 * 1024 + 100_000, DEFAULT_MAX_IMAGE_BYTES).unwrap_err();
+        assert_eq!(err, "image is 20.1 MB, over the 5.0 MB limit");
+        assert_eq!(image_placeholder(&err), "[image not attached: image is 20.1 MB, over the 5.0 MB limit]");
+    }
+}
@@ -0,0 +1,185 @@
+//! Detection of members impersonating admins with lookalike names.
+//!
+//! Scammers copy an admin's display name, often swapping letters for
+//! homoglyphs (Cyrillic "а" for Latin "a") or padding it with invisible
+//! characters. Names are normalized before comparing so those tricks
+//! don't hide the match.
+
+/// Minimum similarity (0-1) for a name to count as a lookalike.
+pub const MATCH_THRESHOLD: f64 = 0.85;
+
+/// Normalized names shorter than this are too generic to compare.
+const MIN_NAME_CHARS: usize = 3;
+
+/// Characters that look like Latin letters, mapped to the letter they imitate.
+const CONFUSABLES: &[(char, char)] = &[
+    // Cyrillic
+    ('а', 'a'), ('в', 'b'), ('е', 'e'), ('ё', 'e'), ('к', 'k'), ('м', 'm'), ('н', 'h'),
+    ('о', 'o'), ('р', 'p'), ('с', 'c'), ('т', 't'), ('у', 'y'), ('х', 'x'), ('і', 'i'),
+    ('ї', 'i'), ('ј', 'j'), ('ѕ', 's'), ('ԁ', 'd'), ('ԛ', 'q'), ('ԝ', 'w'), ('ɡ', 'g'),
+    // Greek
+    ('α', 'a'), ('β', 'b'), ('ε', 'e'), ('η', 'n'), ('ι', 'i'), ('κ', 'k'), ('ν', 'v'),
+    ('ο', 'o'), ('ρ', 'p'), ('τ', 't'), ('υ', 'u'), ('χ', 'x'),
+    // Digits and symbols used as letters
+    ('0', 'o'), ('1', 'l'), ('3', 'e'), ('4', 'a'), ('5', 's'), ('7', 't'), ('@', 'a'), ('$', 's'),
+    // Latin lookalikes
+    ('ı', 'i'), ('ł', 'l'), ('ø', 'o'), ('ß', 's'),
+];
+
+/// Invisible characters used to make copied names look distinct.
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{00ad}' | '\u{034f}' | '\u{061c}' | '\u{115f}' | '\u{1160}' | '\u{17b4}' | '\u{17b5}'
+        | '\u{180e}' | '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2064}'
+        | '\u{3164}' | '\u{fe00}'..='\u{fe0f}' | '\u{feff}')
+}
+
+/// Casefold, drop invisible characters, map homoglyphs to Latin letters and
+/// keep only letters and digits.
+pub fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !is_invisible(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| CONFUSABLES.iter().find(|(from, _)| *from == c).map(|(_, to)| *to).unwrap_or(c))
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Similarity of two names after normalization: 1.0 = identical, 0.0 = unrelated
+/// (or too short to judge).
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize_name(a).chars().collect();
+    let b: Vec<char> = normalize_name(b).chars().collect();
+    if a.len() < MIN_NAME_CHARS || b.len() < MIN_NAME_CHARS {
+        return 0.0;
+    }
+    let longest = a.len().max(b.len());
+    1.0 - edit_distance(&a, &b) as f64 / longest as f64
+}
+
+/// An admin's identity, for comparing names against.
+#[derive(Debug, Clone)]
+pub struct AdminIdentity {
+    pub user_id: i64,
+    pub first_name: String,
+    pub username: Option<String>,
+}
+
+/// A member whose name closely matches an admin's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpersonationMatch {
+    pub admin_id: i64,
+    pub admin_name: String,
+    pub score: f64,
+}
+
+impl ImpersonationMatch {
+    /// Warning shown to Claude and the owner.
+    pub fn warning(&self) -> String {
+        format!(
+            "Name closely matches admin {} ({}), similarity {:.0}% - possible impersonation",
+            self.admin_name, self.admin_id, self.score * 100.0
+        )
+    }
+}
+
+/// Best lookalike match between a user's names and the admins' names.
+/// Admins themselves never match.
+pub fn find_impersonation(user_id: i64, names: &[&str], admins: &[AdminIdentity]) -> Option<ImpersonationMatch> {
+    if admins.iter().any(|a| a.user_id == user_id) {
+        return None;
+    }
+
+    let mut best: Option<ImpersonationMatch> = None;
+    for admin in admins {
+        let admin_names = std::iter::once(admin.first_name.as_str()).chain(admin.username.as_deref());
+        for admin_name in admin_names {
+            for name in names {
+                let score = name_similarity(name, admin_name);
+                if score >= MATCH_THRESHOLD && best.as_ref().is_none_or(|b| score > b.score) {
+                    best = Some(ImpersonationMatch {
+                        admin_id: admin.user_id,
+                        admin_name: admin.first_name.clone(),
+                        score,
+                    });
+                }
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admins() -> Vec<AdminIdentity> {
+        vec![
+            AdminIdentity { user_id: 1, first_name: "Dima".to_string(), username: Some("dima_dev".to_string()) },
+            AdminIdentity { user_id: 2, first_name: "Alexandra".to_string(), username: None },
+        ]
+    }
+
+    #[test]
+    fn test_normalize_homoglyphs() {
+        // Cyrillic а, о, е, р, с
+        assert_eq!(normalize_name("Аlехаndrа"), "alexandra");
+        assert_eq!(normalize_name("Росо"), "poco");
+        // Greek ο and ι
+        assert_eq!(normalize_name("Dιmο"), "dimo");
+        assert_eq!(normalize_name("D1ma"), "dlma");
+    }
+
+    #[test]
+    fn test_normalize_strips_invisible_and_punctuation() {
+        assert_eq!(normalize_name("Di\u{200b}ma"), "dima");
+        assert_eq!(normalize_name("\u{feff}D i m a\u{2060}"), "dima");
+        assert_eq!(normalize_name("dima_dev ✅"), "dimadev");
+        assert_eq!(normalize_name("DIMA"), "dima");
+    }
+
+    #[test]
+    fn test_similarity_scores() {
+        assert_eq!(name_similarity("Dima", "Dima"), 1.0);
+        assert_eq!(name_similarity("Dіmа", "Dima"), 1.0);
+        assert_eq!(name_similarity("Alexandra", "Аlexandrа ✓"), 1.0);
+        // One edit in nine chars is above the threshold
+        assert!(name_similarity("Alexandr", "Alexandra") >= MATCH_THRESHOLD);
+        // Different names are well below
+        assert!(name_similarity("Dima", "Dina") < MATCH_THRESHOLD);
+        assert!(name_similarity("Alexandra", "Alexander") < MATCH_THRESHOLD);
+        // Too short to judge
+        assert_eq!(name_similarity("Al", "Al"), 0.0);
+    }
+
+    #[test]
+    fn test_find_impersonation() {
+        let admins = admins();
+
+        let m = find_impersonation(99, &["Dіmа"], &admins).unwrap();
+        assert_eq!(m.admin_id, 1);
+        assert_eq!(m.score, 1.0);
+        assert!(m.warning().contains("Dima"));
+
+        // Matches the admin's username too
+        assert_eq!(find_impersonation(99, &["Support", "dima_dev_"], &admins).unwrap().admin_id, 1);
+        assert_eq!(find_impersonation(99, &["Alexandr"], &admins).unwrap().admin_id, 2);
+
+        assert!(find_impersonation(99, &["Bob", "bobby"], &admins).is_none());
+        // Admins don't impersonate themselves
+        assert!(find_impersonation(1, &["Dima"], &admins).is_none());
+    }
+}
@@ -0,0 +1,190 @@
+//! Response language policy and a lightweight language detector.
+//!
+//! The detector looks at the dominant script and, for Latin and Cyrillic
+//! text, at common function words. It only runs on texts long enough to
+//! judge, so short replies ("ok", "lol") never skew a chat's histogram.
+
+/// Texts shorter than this (in chars) aren't detected.
+pub const MIN_DETECT_CHARS: usize = 20;
+
+/// Minimum tagged messages before a chat has a dominant language.
+pub const MIN_DOMINANT_SAMPLES: i64 = 5;
+
+/// Once a chat's histogram holds this many samples, all counts are halved
+/// so recent messages outweigh old ones.
+pub const HISTOGRAM_WINDOW: i64 = 200;
+
+/// Which language the bot answers in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseLanguage {
+    /// Follow the language of the conversation.
+    Auto,
+    /// Always answer in this language (ISO 639-1 code, e.g. "ru").
+    Fixed(String),
+}
+
+impl ResponseLanguage {
+    /// Parse a `response_language` config value: "auto" or a 2-3 letter code.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_lowercase();
+        if value == "auto" {
+            return Ok(Self::Auto);
+        }
+        if (2..=3).contains(&value.len()) && value.chars().all(|c| c.is_ascii_lowercase()) {
+            Ok(Self::Fixed(value))
+        } else {
+            Err(format!("invalid language '{}' (expected \"auto\" or a code like \"en\", \"ru\")", value))
+        }
+    }
+
+    /// Instruction for the system prompt.
+    pub fn prompt_note(&self) -> String {
+        match self {
+            Self::Auto => "Reply in the language the conversation is in. Messages carry a `lang` attribute \
+                           when their language was detected; in a chat that mostly writes one language, \
+                           answer in it even if the question was asked in English."
+                .to_string(),
+            Self::Fixed(code) => format!(
+                "Always reply in the language with code \"{}\", whatever language people write in.",
+                code
+            ),
+        }
+    }
+}
+
+/// Function words that identify Latin-script languages.
+const LATIN_STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "are", "you", "that", "this", "with", "have", "for", "not", "what", "it", "of"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "du", "ein", "eine", "mit", "auch", "sie", "zu"]),
+    ("fr", &["le", "la", "les", "et", "est", "je", "tu", "pas", "une", "des", "que", "avec", "pour", "il"]),
+    ("es", &["el", "los", "las", "es", "que", "una", "por", "con", "para", "pero", "como", "está", "yo", "y"]),
+    ("it", &["il", "di", "che", "non", "sono", "una", "per", "gli", "della", "anche", "io", "è", "ma", "con"]),
+    ("pt", &["o", "os", "que", "não", "uma", "com", "para", "você", "mas", "isso", "está", "eu", "é", "do"]),
+    ("nl", &["de", "het", "een", "en", "is", "niet", "ik", "je", "dat", "van", "met", "ook", "maar", "zijn"]),
+    ("pl", &["nie", "jest", "się", "że", "to", "na", "jak", "ale", "tak", "czy", "mnie", "już", "co", "ja"]),
+];
+
+/// Letters only used by Ukrainian among Cyrillic languages we tell apart.
+const UKRAINIAN_LETTERS: &[char] = &['і', 'ї', 'є', 'ґ'];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Thai,
+    Hangul,
+    Kana,
+    Han,
+}
+
+fn script_of(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00c0}'..='\u{024f}' => Some(Script::Latin),
+        '\u{0400}'..='\u{04ff}' => Some(Script::Cyrillic),
+        '\u{0370}'..='\u{03ff}' => Some(Script::Greek),
+        '\u{0590}'..='\u{05ff}' => Some(Script::Hebrew),
+        '\u{0600}'..='\u{06ff}' => Some(Script::Arabic),
+        '\u{0900}'..='\u{097f}' => Some(Script::Devanagari),
+        '\u{0e00}'..='\u{0e7f}' => Some(Script::Thai),
+        '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => Some(Script::Hangul),
+        '\u{3040}'..='\u{30ff}' => Some(Script::Kana),
+        '\u{4e00}'..='\u{9fff}' => Some(Script::Han),
+        _ => None,
+    }
+}
+
+/// Detect the language of `text` as an ISO 639-1 code. None for texts under
+/// `MIN_DETECT_CHARS` chars or when the language can't be told.
+pub fn detect(text: &str) -> Option<&'static str> {
+    if text.chars().count() < MIN_DETECT_CHARS {
+        return None;
+    }
+
+    let scripts = [
+        Script::Latin, Script::Cyrillic, Script::Greek, Script::Hebrew, Script::Arabic,
+        Script::Devanagari, Script::Thai, Script::Hangul, Script::Kana, Script::Han,
+    ];
+    let mut counts = [0usize; 10];
+    for script in text.chars().filter_map(script_of) {
+        if let Some(i) = scripts.iter().position(|s| *s == script) {
+            counts[i] += 1;
+        }
+    }
+    let (index, &count) = counts.iter().enumerate().max_by_key(|(_, n)| **n)?;
+    if count == 0 {
+        return None;
+    }
+
+    match scripts[index] {
+        Script::Latin => detect_latin(text),
+        Script::Cyrillic => {
+            let lower = text.to_lowercase();
+            Some(if lower.chars().any(|c| UKRAINIAN_LETTERS.contains(&c)) { "uk" } else { "ru" })
+        }
+        Script::Greek => Some("el"),
+        Script::Hebrew => Some("he"),
+        Script::Arabic => Some("ar"),
+        Script::Devanagari => Some("hi"),
+        Script::Thai => Some("th"),
+        Script::Hangul => Some("ko"),
+        Script::Kana => Some("ja"),
+        // Japanese mixes kanji with kana; pure Han is Chinese
+        Script::Han => Some(if counts[8] > 0 { "ja" } else { "zh" }),
+    }
+}
+
+/// Pick the Latin-script language whose function words appear most often.
+fn detect_latin(text: &str) -> Option<&'static str> {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut best: Option<(&'static str, usize)> = None;
+    for (lang, stopwords) in LATIN_STOPWORDS {
+        let hits = words.iter().filter(|w| stopwords.contains(w)).count();
+        if hits > 0 && best.is_none_or(|(_, b)| hits > b) {
+            best = Some((lang, hits));
+        }
+    }
+    best.map(|(lang, _)| lang)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_languages() {
+        assert_eq!(detect("Привет всем, как у вас дела сегодня?"), Some("ru"));
+        assert_eq!(detect("Привіт усім, як у вас справи сьогодні?"), Some("uk"));
+        assert_eq!(detect("Hey everyone, what is the plan for this weekend?"), Some("en"));
+        assert_eq!(detect("Ich weiß nicht, ob das eine gute Idee ist"), Some("de"));
+        assert_eq!(detect("Je ne sais pas si c'est une bonne idée pour le projet"), Some("fr"));
+        assert_eq!(detect("今日はとても良い天気ですね、散歩に行きましょう"), Some("ja"));
+        assert_eq!(detect("שלום לכולם, מה שלומכם היום בבוקר?"), Some("he"));
+    }
+
+    #[test]
+    fn test_detect_skips_short_and_unknown() {
+        assert_eq!(detect("ok lol"), None);
+        assert_eq!(detect("Привет, как дела"), None); // 16 chars
+        assert_eq!(detect("1234567890 !!! ??? 1234567890"), None);
+        assert_eq!(detect("xkcd qwrtz plmbk zzzzz frrrp"), None);
+    }
+
+    #[test]
+    fn test_response_language_parse() {
+        assert_eq!(ResponseLanguage::parse("auto").unwrap(), ResponseLanguage::Auto);
+        assert_eq!(ResponseLanguage::parse(" RU ").unwrap(), ResponseLanguage::Fixed("ru".to_string()));
+        assert!(ResponseLanguage::parse("russian").is_err());
+        assert!(ResponseLanguage::parse("r1").is_err());
+        assert!(ResponseLanguage::parse("").is_err());
+        assert!(ResponseLanguage::Fixed("de".to_string()).prompt_note().contains("\"de\""));
+    }
+}
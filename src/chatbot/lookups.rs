@@ -0,0 +1,279 @@
+//! Lookup commands answered without a Claude turn.
+//!
+//! Frequent lookups ("/reminders", "/stats") run the same tool Claude would
+//! call, and the JSON result is formatted here. They're registered in
+//! `commands::COMMANDS` for the owner and trusted users, in DMs and groups.
+
+use serde_json::Value;
+
+use crate::chatbot::tools::ToolCall;
+
+/// Where a command was sent.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandChat {
+    pub chat_id: i64,
+    pub is_group: bool,
+    /// The bot's main group, used by chat-specific commands sent in DMs.
+    pub primary_chat_id: i64,
+}
+
+impl CommandChat {
+    /// The chat a command is about: this group, or the primary group from a DM.
+    pub fn target(&self) -> i64 {
+        if self.is_group { self.chat_id } else { self.primary_chat_id }
+    }
+}
+
+/// Most members listed by /members.
+const MEMBERS_LIMIT: i64 = 30;
+
+const MEMBER_FILTERS: &[&str] = &["all", "active", "inactive", "never_posted", "left", "banned"];
+
+/// `/reminders`.
+pub fn reminders_call(_args: &[String], chat: CommandChat) -> Result<ToolCall, String> {
+    // In a group, that group's reminders; in DMs, all of them
+    Ok(ToolCall::ListReminders { chat_id: chat.is_group.then_some(chat.chat_id) })
+}
+
+/// `/stats [days]`.
+pub fn stats_call(args: &[String], chat: CommandChat) -> Result<ToolCall, String> {
+    let days = match args {
+        [] => None,
+        [days] => match days.parse::<i64>() {
+            Ok(d) if (1..=90).contains(&d) => Some(d),
+            _ => return Err(format!("Invalid number of days '{}'", days)),
+        },
+        _ => return Err("Too many arguments".to_string()),
+    };
+    Ok(ToolCall::ChatStats { chat_id: chat.target(), days })
+}
+
+/// `/members [filter] [days]`.
+pub fn members_call(args: &[String], _chat: CommandChat) -> Result<ToolCall, String> {
+    let (filter, days) = match args {
+        [] => ("all".to_string(), None),
+        [filter] => (filter.to_lowercase(), None),
+        [filter, days] => (filter.to_lowercase(), Some(days)),
+        _ => return Err("Too many arguments".to_string()),
+    };
+    if !MEMBER_FILTERS.contains(&filter.as_str()) {
+        return Err(format!("Unknown filter '{}'", filter));
+    }
+    let days_inactive = match days {
+        None => None,
+        Some(_) if filter != "inactive" => return Err("Days only apply to the inactive filter".to_string()),
+        Some(days) => Some(days.parse::<i64>().ok().filter(|d| *d >= 1).ok_or_else(|| format!("Invalid number of days '{}'", days))?),
+    };
+    Ok(ToolCall::GetMembers { filter: Some(filter), days_inactive, limit: Some(MEMBERS_LIMIT) })
+}
+
+/// `/whois @username|user_id`.
+pub fn whois_call(args: &[String], _chat: CommandChat) -> Result<ToolCall, String> {
+    let [who] = args else {
+        return Err("Give one username or user ID".to_string());
+    };
+    Ok(match who.parse::<i64>() {
+        Ok(user_id) => ToolCall::GetUserInfo { user_id: Some(user_id), username: None },
+        Err(_) => ToolCall::GetUserInfo { user_id: None, username: Some(who.trim_start_matches('@').to_string()) },
+    })
+}
+
+fn str_field<'a>(v: &'a Value, key: &str) -> Option<&'a str> {
+    v.get(key).and_then(Value::as_str)
+}
+
+/// "@alice (123)", or "Alice (123)" for users without a username.
+fn user_label(v: &Value, name_key: &str) -> String {
+    let id = v.get("user_id").and_then(Value::as_i64).unwrap_or(0);
+    match str_field(v, "username") {
+        Some(username) => format!("@{} ({})", username, id),
+        None => format!("{} ({})", str_field(v, name_key).unwrap_or("unknown"), id),
+    }
+}
+
+pub fn render_reminders(v: &Value) -> String {
+    let empty = Vec::new();
+    let reminders = v.get("reminders").and_then(Value::as_array).unwrap_or(&empty);
+    let scheduled = v.get("scheduled_messages").and_then(Value::as_array).unwrap_or(&empty);
+    if reminders.is_empty() && scheduled.is_empty() {
+        return "No active reminders or scheduled messages.".to_string();
+    }
+
+    let mut lines = Vec::new();
+    if !reminders.is_empty() {
+        lines.push("Reminders:".to_string());
+        for r in reminders {
+            let repeat = str_field(r, "repeat_cron").map(|c| format!(" (repeats: {})", c)).unwrap_or_default();
+            lines.push(format!(
+                "#{} {} in chat {}: {}{}",
+                r.get("id").and_then(Value::as_i64).unwrap_or(0),
+                str_field(r, "trigger_at").unwrap_or("?"),
+                r.get("chat_id").and_then(Value::as_i64).unwrap_or(0),
+                str_field(r, "message").unwrap_or(""),
+                repeat
+            ));
+        }
+    }
+    if !scheduled.is_empty() {
+        lines.push("Scheduled messages:".to_string());
+        for m in scheduled {
+            lines.push(format!(
+                "#{} {} in chat {}: {}",
+                m.get("id").and_then(Value::as_i64).unwrap_or(0),
+                str_field(m, "send_at").unwrap_or("?"),
+                m.get("chat_id").and_then(Value::as_i64).unwrap_or(0),
+                str_field(m, "text").unwrap_or("")
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn render_stats(v: &Value) -> String {
+    let num = |key: &str| v.get(key).and_then(Value::as_i64).unwrap_or(0);
+    let mut lines = vec![format!(
+        "Chat {}, last {} days: {} messages from {} users",
+        num("chat_id"), num("days"), num("total_messages"), num("active_users")
+    )];
+
+    let top: Vec<String> = v.get("top_posters").and_then(Value::as_array).into_iter().flatten()
+        .map(|p| format!("{} {}", str_field(p, "username").unwrap_or("?"), p.get("messages").and_then(Value::as_i64).unwrap_or(0)))
+        .collect();
+    if !top.is_empty() {
+        lines.push(format!("Top posters: {}", top.join(", ")));
+    }
+    if let Some(hour) = v.get("busiest_hour_utc").and_then(Value::as_i64) {
+        lines.push(format!("Busiest hour: {:02}:00 UTC", hour));
+    }
+    if let Some(avg) = v.get("avg_message_length").and_then(Value::as_f64).filter(|a| *a > 0.0) {
+        lines.push(format!("Average message: {} chars", avg));
+    }
+    lines.join("\n")
+}
+
+pub fn render_members(v: &Value) -> String {
+    let results = v.get("results").and_then(Value::as_array).cloned().unwrap_or_default();
+    let mut lines = vec![format!(
+        "Members ({}): {} shown, {} active of {} tracked",
+        str_field(v, "filter").unwrap_or("all"),
+        results.len(),
+        v.get("active_members").and_then(Value::as_i64).unwrap_or(0),
+        v.get("total_tracked").and_then(Value::as_i64).unwrap_or(0)
+    )];
+    for m in &results {
+        let last = str_field(m, "last_message_date").unwrap_or("never");
+        lines.push(format!(
+            "{}: {} msgs, last {}, {}",
+            user_label(m, "first_name"),
+            m.get("message_count").and_then(Value::as_i64).unwrap_or(0),
+            last,
+            str_field(m, "status").unwrap_or("?")
+        ));
+    }
+    lines.join("\n")
+}
+
+pub fn render_user(v: &Value) -> String {
+    let name = [str_field(v, "first_name"), str_field(v, "last_name")]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut lines = vec![format!("{} {}", user_label(v, "first_name"), name)];
+
+    let mut details = vec![str_field(v, "status").unwrap_or("unknown").to_string()];
+    if let Some(title) = str_field(v, "custom_title") {
+        details.push(format!("title \"{}\"", title));
+    }
+    if v.get("is_bot").and_then(Value::as_bool) == Some(true) {
+        details.push("bot".to_string());
+    }
+    if v.get("is_premium").and_then(Value::as_bool) == Some(true) {
+        details.push("premium".to_string());
+    }
+    if let Some(lang) = str_field(v, "language_code") {
+        details.push(format!("language {}", lang));
+    }
+    lines.push(details.join(", "));
+
+    if let Some(warning) = str_field(v, "impersonation_warning") {
+        lines.push(format!("⚠️ {}", warning));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DM: CommandChat = CommandChat { chat_id: 42, is_group: false, primary_chat_id: -100 };
+    const GROUP: CommandChat = CommandChat { chat_id: -200, is_group: true, primary_chat_id: -100 };
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_stats_and_reminders_arguments() {
+        assert!(matches!(stats_call(&[], GROUP), Ok(ToolCall::ChatStats { chat_id: -200, days: None })));
+        // From a DM, stats are about the primary group
+        assert!(matches!(stats_call(&words("30"), DM), Ok(ToolCall::ChatStats { chat_id: -100, days: Some(30) })));
+        assert_eq!(stats_call(&words("365"), DM).unwrap_err(), "Invalid number of days '365'");
+
+        assert!(matches!(reminders_call(&[], DM), Ok(ToolCall::ListReminders { chat_id: None })));
+        assert!(matches!(reminders_call(&[], GROUP), Ok(ToolCall::ListReminders { chat_id: Some(-200) })));
+    }
+
+    #[test]
+    fn test_members_and_whois_arguments() {
+        match members_call(&words("Inactive 60"), DM) {
+            Ok(ToolCall::GetMembers { filter, days_inactive, limit }) => {
+                assert_eq!(filter.as_deref(), Some("inactive"));
+                assert_eq!(days_inactive, Some(60));
+                assert_eq!(limit, Some(MEMBERS_LIMIT));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(members_call(&[], DM), Ok(ToolCall::GetMembers { filter: Some(ref f), .. }) if f == "all"));
+        assert!(members_call(&words("lurkers"), DM).unwrap_err().contains("Unknown filter"));
+        assert!(members_call(&words("active 30"), DM).unwrap_err().contains("only apply to the inactive"));
+        assert!(members_call(&words("inactive 0"), DM).is_err());
+
+        assert!(matches!(
+            whois_call(&words("@Alice"), GROUP),
+            Ok(ToolCall::GetUserInfo { user_id: None, username: Some(ref u) }) if u == "Alice"
+        ));
+        assert!(matches!(whois_call(&words("12345"), DM), Ok(ToolCall::GetUserInfo { user_id: Some(12345), username: None })));
+    }
+
+    #[test]
+    fn test_rendering() {
+        let stats = serde_json::json!({
+            "chat_id": -100, "days": 7, "total_messages": 120, "active_users": 9,
+            "top_posters": [{ "user_id": 1, "username": "alice", "messages": 40 }],
+            "busiest_hour_utc": 9, "avg_message_length": 42.5
+        });
+        assert_eq!(
+            render_stats(&stats),
+            "Chat -100, last 7 days: 120 messages from 9 users\nTop posters: alice 40\nBusiest hour: 09:00 UTC\nAverage message: 42.5 chars"
+        );
+
+        let empty = serde_json::json!({ "count": 0, "reminders": [], "scheduled_messages": [] });
+        assert_eq!(render_reminders(&empty), "No active reminders or scheduled messages.");
+
+        let user = serde_json::json!({
+            "user_id": 7, "username": null, "first_name": "Bob", "last_name": "Stone",
+            "status": "administrator", "is_bot": false, "is_premium": true, "language_code": "en"
+        });
+        assert_eq!(render_user(&user), "Bob (7) Bob Stone\nadministrator, premium, language en");
+
+        let members = serde_json::json!({
+            "total_tracked": 50, "active_members": 40, "filter": "inactive",
+            "results": [{ "user_id": 3, "username": "carol", "message_count": 2, "last_message_date": null, "status": "member" }]
+        });
+        assert_eq!(
+            render_members(&members),
+            "Members (inactive): 1 shown, 40 active of 50 tracked\n@carol (3): 2 msgs, last never, member"
+        );
+    }
+}
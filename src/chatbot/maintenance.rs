@@ -0,0 +1,199 @@
+//! Maintenance mode: Claude paused, moderation running.
+//!
+//! The owner's `/pause` stops turns without touching the rest of the bot:
+//! messages are still stored, spam filtered, members tracked. New messages
+//! go to the database only, so nothing piles up for Claude, and a direct
+//! mention gets the `maintenance_reply` at most once per chat per
+//! `AUTO_REPLY_INTERVAL`. The pause time is kept in the data dir, so a
+//! restart stays paused; `/resume` can replay what was missed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use super::message::ChatMessage;
+
+/// Pause time while paused, in the data dir.
+pub const STATE_FILE: &str = "maintenance";
+
+/// Default auto-reply to mentions while paused.
+pub const DEFAULT_REPLY: &str = "I'm undergoing maintenance and will be back soon.";
+
+/// Minimum gap between auto-replies in one chat.
+pub const AUTO_REPLY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// What happens to an incoming message.
+#[derive(Debug, PartialEq)]
+pub enum Intake {
+    /// Running: queued for Claude.
+    Process,
+    /// Paused: stored only.
+    Store,
+    /// Paused and addressed to the bot: stored, and the auto-reply is due.
+    StoreAndReply,
+}
+
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    /// When the bot was paused; None while running.
+    paused_since: RwLock<Option<DateTime<Utc>>>,
+    /// Last auto-reply per chat during this pause.
+    replied: Mutex<HashMap<i64, Instant>>,
+    /// Where the pause is saved (None = not saved).
+    path: Option<PathBuf>,
+}
+
+impl Maintenance {
+    /// The saved state in `data_dir`. An unreadable file still means paused.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(STATE_FILE);
+        let paused_since = match std::fs::read_to_string(&path) {
+            Ok(content) => Some(content.trim().parse::<DateTime<Utc>>().unwrap_or_else(|e| {
+                warn!("Unreadable pause time in {}, paused from now: {}", path.display(), e);
+                Utc::now()
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Failed to read {}, staying paused: {}", path.display(), e);
+                Some(Utc::now())
+            }
+        };
+        Self { paused_since: RwLock::new(paused_since), replied: Mutex::default(), path: Some(path) }
+    }
+
+    pub fn paused_since(&self) -> Option<DateTime<Utc>> {
+        *self.paused_since.read().expect("maintenance lock poisoned")
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_since().is_some()
+    }
+
+    /// Pause at `now`. Returns false if already paused.
+    pub fn pause(&self, now: DateTime<Utc>) -> Result<bool, String> {
+        let mut paused_since = self.paused_since.write().expect("maintenance lock poisoned");
+        if paused_since.is_some() {
+            return Ok(false);
+        }
+        if let Some(path) = &self.path {
+            std::fs::write(path, now.to_rfc3339()).map_err(|e| format!("Failed to save {}: {e}", path.display()))?;
+        }
+        *paused_since = Some(now);
+        Ok(true)
+    }
+
+    /// Resume. Returns when the pause started, or None if not paused.
+    pub fn resume(&self) -> Result<Option<DateTime<Utc>>, String> {
+        let mut paused_since = self.paused_since.write().expect("maintenance lock poisoned");
+        if paused_since.is_none() {
+            return Ok(None);
+        }
+        if let Some(path) = &self.path {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(format!("Failed to remove {}: {e}", path.display()));
+                }
+                _ => {}
+            }
+        }
+        self.replied.lock().expect("maintenance lock poisoned").clear();
+        Ok(paused_since.take())
+    }
+
+    /// Decide what happens to `msg` arriving at `now`. DMs and mentions of
+    /// the bot get the auto-reply once per chat per interval.
+    pub fn intake(&self, msg: &ChatMessage, now: Instant) -> Intake {
+        if !self.is_paused() {
+            Intake::Process
+        } else if (msg.chat_id > 0 || msg.mentions_bot) && self.take_auto_reply(msg.chat_id, now) {
+            Intake::StoreAndReply
+        } else {
+            Intake::Store
+        }
+    }
+
+    /// Whether a mention in `chat_id` at `now` gets the auto-reply, and if
+    /// so, start that chat's interval.
+    fn take_auto_reply(&self, chat_id: i64, now: Instant) -> bool {
+        let mut replied = self.replied.lock().expect("maintenance lock poisoned");
+        match replied.get(&chat_id) {
+            Some(last) if now.duration_since(*last) < AUTO_REPLY_INTERVAL => false,
+            _ => {
+                replied.insert(chat_id, now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_persists() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let since = "2024-03-10T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let maintenance = Maintenance::load(dir.path());
+        assert!(!maintenance.is_paused());
+        assert_eq!(maintenance.pause(since), Ok(true));
+        assert_eq!(maintenance.pause(Utc::now()), Ok(false));
+
+        // A restart stays paused since the same time
+        let restarted = Maintenance::load(dir.path());
+        assert_eq!(restarted.paused_since(), Some(since));
+        assert_eq!(restarted.resume(), Ok(Some(since)));
+        assert_eq!(restarted.resume(), Ok(None));
+        assert!(!Maintenance::load(dir.path()).is_paused());
+
+        // A damaged file is still a pause
+        std::fs::write(dir.path().join(STATE_FILE), "garbage").unwrap();
+        assert!(Maintenance::load(dir.path()).is_paused());
+    }
+
+    fn message(chat_id: i64, mentions_bot: bool) -> ChatMessage {
+        let mut msg = ChatMessage::system("hey bot".to_string());
+        (msg.chat_id, msg.user_id, msg.mentions_bot) = (chat_id, 5, mentions_bot);
+        msg
+    }
+
+    #[test]
+    fn test_pause_gate() {
+        let maintenance = Maintenance::default();
+        let now = Instant::now();
+        assert_eq!(maintenance.intake(&message(-100, true), now), Intake::Process);
+
+        maintenance.pause(Utc::now()).unwrap();
+        // Group chatter is stored only; mentions and DMs get one reply per chat
+        assert_eq!(maintenance.intake(&message(-100, false), now), Intake::Store);
+        assert_eq!(maintenance.intake(&message(-100, true), now), Intake::StoreAndReply);
+        assert_eq!(maintenance.intake(&message(-100, true), now), Intake::Store);
+        assert_eq!(maintenance.intake(&message(5, false), now), Intake::StoreAndReply);
+        assert_eq!(maintenance.intake(&message(5, false), now + AUTO_REPLY_INTERVAL), Intake::StoreAndReply);
+
+        maintenance.resume().unwrap();
+        assert_eq!(maintenance.intake(&message(-100, false), now), Intake::Process);
+    }
+
+    #[test]
+    fn test_auto_reply_throttle() {
+        let maintenance = Maintenance::default();
+        let start = Instant::now();
+        assert!(maintenance.take_auto_reply(-100, start));
+        assert!(!maintenance.take_auto_reply(-100, start + Duration::from_secs(60)));
+        // Each chat has its own interval
+        assert!(maintenance.take_auto_reply(-200, start + Duration::from_secs(60)));
+        assert!(!maintenance.take_auto_reply(-100, start + AUTO_REPLY_INTERVAL - Duration::from_secs(1)));
+        assert!(maintenance.take_auto_reply(-100, start + AUTO_REPLY_INTERVAL));
+
+        // Resuming starts over
+        maintenance.pause(Utc::now()).unwrap();
+        maintenance.resume().unwrap();
+        assert!(maintenance.take_auto_reply(-100, start + AUTO_REPLY_INTERVAL));
+    }
+}
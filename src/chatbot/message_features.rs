@@ -0,0 +1,122 @@
+//! Cheap spam-relevant features of a message, stored next to it.
+//!
+//! Only the raw text used to be stored, so there was no telling which traits
+//! go with spam. Each message now gets a few columns the owner can query:
+//! link and mention counts, whether it was forwarded or sent through an
+//! inline bot, a length class and how much of it is emoji. Everything is
+//! derived from the text and two Telegram flags; no model is involved.
+
+use super::message::ChatMessage;
+
+/// Length classes by chars: (upper bound, name). Longer is "huge".
+const LENGTH_CLASSES: &[(usize, &str)] = &[(1, "empty"), (30, "short"), (200, "medium"), (1000, "long")];
+
+/// Features of one message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageFeatures {
+    /// Links in the text (http(s)://, www., t.me/).
+    pub url_count: usize,
+    /// Mentions another account (@username or a text mention).
+    pub has_mention: bool,
+    pub forwarded: bool,
+    /// "empty", "short", "medium", "long" or "huge".
+    pub length_class: &'static str,
+    /// Emoji among the non-space chars, 0.0-1.0.
+    pub emoji_ratio: f64,
+    /// Username of the inline bot it was sent through.
+    pub via_bot: Option<String>,
+}
+
+impl MessageFeatures {
+    pub fn of(msg: &ChatMessage) -> Self {
+        let text = msg.text.as_str();
+        let words = || text.split_whitespace().map(|w| w.trim_start_matches(|c: char| !c.is_alphanumeric() && c != '@'));
+        let url_count = words()
+            .filter(|w| w.contains("://") || w.starts_with("www.") || w.starts_with("t.me/"))
+            .count();
+        let has_mention = !msg.mentioned_user_ids.is_empty() || words().any(|w| w.len() > 1 && w.starts_with('@'));
+        Self {
+            url_count,
+            has_mention,
+            forwarded: msg.forwarded,
+            length_class: length_class(text),
+            emoji_ratio: emoji_ratio(text),
+            via_bot: msg.via_bot.clone(),
+        }
+    }
+}
+
+fn length_class(text: &str) -> &'static str {
+    let chars = text.chars().count();
+    LENGTH_CLASSES.iter().find(|(max, _)| chars < *max).map_or("huge", |(_, name)| name)
+}
+
+/// Emoji and pictographs (modifiers and joiners aside).
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x2600..=0x27BF     // misc symbols, dingbats (☀ ✨ ❤)
+        | 0x2B00..=0x2BFF   // misc symbols and arrows (⭐)
+        | 0x1F000..=0x1F3FA // cards, flags, pictographs
+        | 0x1F400..=0x1FAFF // pictographs, emoticons, transport, extended-A
+    )
+}
+
+fn emoji_ratio(text: &str) -> f64 {
+    let chars: Vec<char> = text
+        .chars()
+        // Skin tones, variation selectors and joiners belong to the emoji before them
+        .filter(|c| !c.is_whitespace() && !matches!(*c as u32, 0x1F3FB..=0x1F3FF | 0xFE00..=0xFE0F | 0x200D))
+        .collect();
+    if chars.is_empty() {
+        return 0.0;
+    }
+    chars.iter().filter(|&&c| is_emoji(c)).count() as f64 / chars.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn features(text: &str) -> MessageFeatures {
+        MessageFeatures::of(&ChatMessage::system(text.to_string()))
+    }
+
+    #[test]
+    fn test_feature_matrix() {
+        // (text, url_count, has_mention, length_class, emoji_ratio)
+        let cases: &[(&str, usize, bool, &str, f64)] = &[
+            ("", 0, false, "empty", 0.0),
+            ("ok", 0, false, "short", 0.0),
+            ("see https://example.com and www.example.org", 2, false, "medium", 0.0),
+            ("join (t.me/cheapcrypto) now!!", 1, false, "short", 0.0),
+            ("ask @alice about it", 0, true, "short", 0.0),
+            ("mail me at bob@example.com", 0, false, "short", 0.0),
+            ("a lone @ sign", 0, false, "short", 0.0),
+            ("🔥🔥🔥🔥", 0, false, "short", 1.0),
+            ("hi 👋🏽", 0, false, "short", 1.0 / 3.0),
+            ("❤️ ok", 0, false, "short", 1.0 / 3.0),
+            ("Привет всем, как дела? 😀", 0, false, "short", 1.0 / 20.0),
+        ];
+        for &(text, urls, mention, class, ratio) in cases {
+            let f = features(text);
+            assert_eq!((f.url_count, f.has_mention, f.length_class), (urls, mention, class), "{:?}", text);
+            assert!((f.emoji_ratio - ratio).abs() < 1e-9, "{:?}: {}", text, f.emoji_ratio);
+        }
+
+        assert_eq!(features(&"word ".repeat(50)).length_class, "long");
+        assert_eq!(features(&"x".repeat(1000)).length_class, "huge");
+    }
+
+    #[test]
+    fn test_telegram_flags() {
+        let mut msg = ChatMessage::system("look at this".to_string());
+        assert_eq!((MessageFeatures::of(&msg).forwarded, MessageFeatures::of(&msg).via_bot), (false, None));
+
+        msg.forwarded = true;
+        msg.via_bot = Some("gif".to_string());
+        msg.mentioned_user_ids = vec![42];
+        let f = MessageFeatures::of(&msg);
+        assert!(f.forwarded && f.has_mention);
+        assert_eq!(f.via_bot.as_deref(), Some("gif"));
+    }
+}
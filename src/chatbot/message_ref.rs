@@ -0,0 +1,177 @@
+//! Message references in tool arguments.
+//!
+//! Message IDs are long and Claude sometimes copies them wrong, especially
+//! after compaction. `add_reaction` and `send_message` therefore also accept
+//! "last" (the latest message in the chat) or "last_from:<username>", which
+//! the engine resolves to a concrete ID before running the tool.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chatbot::database::Database;
+use crate::chatbot::message::ChatMessage;
+
+/// Stored messages searched when the current batch has no match.
+pub const REF_WINDOW: usize = 50;
+
+/// A message given by ID, or by reference ("last", "last_from:<username>").
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageRef {
+    Id(i64),
+    Ref(String),
+}
+
+impl MessageRef {
+    /// The message ID, if given as a number (or a numeric string).
+    pub fn id(&self) -> Option<i64> {
+        match self {
+            Self::Id(id) => Some(*id),
+            Self::Ref(s) => s.trim().parse().ok(),
+        }
+    }
+}
+
+/// Resolve a message reference in `chat_id` to a message ID. Looks in the
+/// current batch first, then at the chat's last `REF_WINDOW` stored messages.
+/// The bot's own messages and system notices never match.
+pub fn resolve_message_ref(
+    reference: &MessageRef,
+    chat_id: i64,
+    bot_user_id: i64,
+    batch: &[ChatMessage],
+    db: &Database,
+) -> Result<i64, String> {
+    if let Some(id) = reference.id() {
+        return Ok(id);
+    }
+    let spec = match reference {
+        MessageRef::Ref(spec) => spec.trim(),
+        MessageRef::Id(id) => return Ok(*id),
+    };
+    let from = match spec.strip_prefix("last_from:") {
+        Some(name) => Some(name.trim().trim_start_matches('@')),
+        None if spec == "last" => None,
+        None => {
+            return Err(format!(
+                "Unknown message reference '{}'. Use a message ID, \"last\" or \"last_from:<username>\"",
+                spec
+            ))
+        }
+    };
+    if from.is_some_and(str::is_empty) {
+        return Err("last_from needs a username, e.g. \"last_from:alice\"".to_string());
+    }
+
+    let eligible = |m: &&ChatMessage| {
+        m.chat_id == chat_id
+            && m.user_id != 0
+            && m.user_id != bot_user_id
+            && from.is_none_or(|name| m.username.eq_ignore_ascii_case(name))
+    };
+
+    // Newest first
+    let in_batch: Vec<&ChatMessage> = batch.iter().rev().filter(eligible).collect();
+    if !in_batch.is_empty() {
+        return pick(spec, from, &in_batch);
+    }
+    let stored = db.recent_in_chat(chat_id, REF_WINDOW)?;
+    let in_stored: Vec<&ChatMessage> = stored.iter().filter(eligible).collect();
+    pick(spec, from, &in_stored)
+}
+
+/// The newest candidate, unless a username matches several different users.
+fn pick(spec: &str, from: Option<&str>, candidates: &[&ChatMessage]) -> Result<i64, String> {
+    let newest = candidates.first()
+        .ok_or_else(|| format!("No recent message matches '{}' in this chat", spec))?;
+
+    if from.is_some() {
+        let mut users: Vec<i64> = candidates.iter().map(|m| m.user_id).collect();
+        users.sort_unstable();
+        users.dedup();
+        if users.len() > 1 {
+            let users: Vec<String> = users.iter().map(|id| id.to_string()).collect();
+            return Err(format!(
+                "'{}' is ambiguous: users {} share that name. Use the message ID instead",
+                spec,
+                users.join(", ")
+            ));
+        }
+    }
+    Ok(newest.message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT: i64 = 999;
+
+    fn msg(id: i64, user_id: i64, username: &str, minute: u32) -> ChatMessage {
+        let mut m = ChatMessage::system(format!("message {}", id));
+        m.message_id = id;
+        m.chat_id = -100;
+        m.user_id = user_id;
+        m.username = username.to_string();
+        m.timestamp = format!("2024-01-15 10:{:02}", minute);
+        m
+    }
+
+    fn resolve(spec: &str, batch: &[ChatMessage], db: &Database) -> Result<i64, String> {
+        resolve_message_ref(&MessageRef::Ref(spec.to_string()), -100, BOT, batch, db)
+    }
+
+    #[test]
+    fn test_ids_pass_through() {
+        let db = Database::new();
+        assert_eq!(resolve_message_ref(&MessageRef::Id(42), -100, BOT, &[], &db), Ok(42));
+        assert_eq!(resolve("4521", &[], &db), Ok(4521));
+        assert!(resolve("first", &[], &db).unwrap_err().contains("Unknown message reference"));
+    }
+
+    #[test]
+    fn test_last_from_with_multiple_users() {
+        let db = Database::new();
+        let batch = vec![
+            msg(1, 1, "alice", 0),
+            msg(2, 2, "bob", 1),
+            msg(3, 1, "alice", 2),
+            msg(4, BOT, "Claudima", 3),
+            msg(5, 2, "bob", 4),
+        ];
+
+        assert_eq!(resolve("last_from:alice", &batch, &db), Ok(3));
+        assert_eq!(resolve("last_from:@Bob", &batch, &db), Ok(5));
+        assert_eq!(resolve("last", &batch, &db), Ok(5));
+        // The bot's own messages never match
+        assert!(resolve("last_from:Claudima", &batch, &db).is_err());
+        assert!(resolve("last_from:", &batch, &db).unwrap_err().contains("needs a username"));
+    }
+
+    #[test]
+    fn test_falls_back_to_stored_messages() {
+        let mut db = Database::new();
+        db.add_message(msg(10, 3, "carol", 0));
+        db.add_message(msg(11, 1, "alice", 1));
+        let mut other_chat = msg(12, 3, "carol", 2);
+        other_chat.chat_id = -200;
+        db.add_message(other_chat);
+
+        // Batch only has alice; carol is found in the database, in this chat only
+        let batch = vec![msg(20, 1, "alice", 5)];
+        assert_eq!(resolve("last_from:carol", &batch, &db), Ok(10));
+        assert_eq!(resolve("last", &batch, &db), Ok(20));
+        assert!(resolve("last_from:dave", &batch, &db).unwrap_err().contains("No recent message"));
+    }
+
+    #[test]
+    fn test_ambiguous_name() {
+        let db = Database::new();
+        let batch = vec![msg(1, 1, "Alex", 0), msg(2, 2, "alex", 1), msg(3, 3, "sam", 2)];
+
+        let err = resolve("last_from:alex", &batch, &db).unwrap_err();
+        assert!(err.contains("ambiguous"));
+        assert!(err.contains("1, 2"));
+        // "last" itself is never ambiguous
+        assert_eq!(resolve("last", &batch, &db), Ok(3));
+    }
+}
@@ -0,0 +1,267 @@
+//! Prometheus metrics on a local HTTP endpoint.
+//!
+//! With `metrics_listen` set, `install` creates the process-wide registry
+//! and `serve` answers `GET /metrics` in the Prometheus text format. Without
+//! it nothing is installed and the recording functions return at once. The
+//! server is a few lines over a tokio listener rather than an HTTP framework:
+//! it only ever answers one path for a local scraper.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    /// Only `_sum` and `_count`; quantiles are left to the dashboard.
+    Summary,
+}
+
+/// A metric family: its name, help text and type.
+#[derive(Debug)]
+pub struct Family {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+}
+
+/// Group messages by what happened to them (`outcome`).
+pub const MESSAGES: Family = Family { name: "claudima_messages_total", help: "Group messages handled, by outcome.", kind: Kind::Counter };
+pub const SPAM_DELETED: Family = Family { name: "claudima_spam_deleted_total", help: "Spam messages deleted.", kind: Kind::Counter };
+/// Claude turns by `outcome` (as in turns.jsonl).
+pub const TURNS: Family = Family { name: "claudima_turns_total", help: "Claude turns, by outcome.", kind: Kind::Counter };
+pub const TURN_DURATION: Family = Family { name: "claudima_turn_duration_seconds", help: "Time from a turn's start to its end.", kind: Kind::Summary };
+/// Tool calls by `tool` and `result` ("ok" or "error").
+pub const TOOL_CALLS: Family = Family { name: "claudima_tool_calls_total", help: "Tool calls, by tool and result.", kind: Kind::Counter };
+pub const TELEGRAM_SEND_ERRORS: Family = Family { name: "claudima_telegram_send_errors_total", help: "Messages Telegram refused to send.", kind: Kind::Counter };
+pub const COST: Family = Family { name: "claudima_cost_usd_total", help: "Claude cost reported by Claude Code, in USD.", kind: Kind::Counter };
+pub const QUEUE_DEPTH: Family = Family { name: "claudima_queue_depth", help: "Messages waiting for the next turn.", kind: Kind::Gauge };
+
+/// Every family, in exposition order.
+const FAMILIES: [&Family; 8] = [&MESSAGES, &SPAM_DELETED, &TURNS, &TURN_DURATION, &TOOL_CALLS, &TELEGRAM_SEND_ERRORS, &COST, &QUEUE_DEPTH];
+
+/// Bytes of a request read before answering.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Current values by family name, then rendered label set.
+#[derive(Debug, Default)]
+pub struct Registry {
+    values: Mutex<BTreeMap<&'static str, BTreeMap<String, Value>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Value {
+    value: f64,
+    /// Observations, for summaries.
+    count: u64,
+}
+
+/// `{key="value",...}`, or "" without labels.
+fn label_set(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+impl Registry {
+    fn update(&self, family: &Family, labels: &[(&str, &str)], apply: impl FnOnce(&mut Value)) {
+        let mut values = self.values.lock().expect("metrics lock poisoned");
+        apply(values.entry(family.name).or_default().entry(label_set(labels)).or_default());
+    }
+
+    pub fn add(&self, family: &Family, labels: &[(&str, &str)], amount: f64) {
+        self.update(family, labels, |v| v.value += amount);
+    }
+
+    pub fn set(&self, family: &Family, labels: &[(&str, &str)], value: f64) {
+        self.update(family, labels, |v| v.value = value);
+    }
+
+    pub fn observe(&self, family: &Family, labels: &[(&str, &str)], value: f64) {
+        self.update(family, labels, |v| {
+            v.value += value;
+            v.count += 1;
+        });
+    }
+
+    /// Everything recorded so far, in the Prometheus text format. Families
+    /// without values are left out.
+    pub fn render(&self) -> String {
+        let values = self.values.lock().expect("metrics lock poisoned");
+        let mut out = String::new();
+        for family in FAMILIES {
+            let Some(series) = values.get(family.name) else {
+                continue;
+            };
+            let kind = match family.kind {
+                Kind::Counter => "counter",
+                Kind::Gauge => "gauge",
+                Kind::Summary => "summary",
+            };
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", family.name, family.help, family.name, kind);
+            for (labels, v) in series {
+                if family.kind == Kind::Summary {
+                    let _ = writeln!(out, "{}_sum{} {}\n{}_count{} {}", family.name, labels, v.value, family.name, labels, v.count);
+                } else {
+                    let _ = writeln!(out, "{}{} {}", family.name, labels, v.value);
+                }
+            }
+        }
+        out
+    }
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Create the process-wide registry, turning recording on.
+pub fn install() -> &'static Registry {
+    REGISTRY.get_or_init(Registry::default)
+}
+
+/// Count one event.
+pub fn inc(family: &Family, labels: &[(&str, &str)]) {
+    add(family, labels, 1.0);
+}
+
+pub fn add(family: &Family, labels: &[(&str, &str)], amount: f64) {
+    if let Some(registry) = REGISTRY.get() {
+        registry.add(family, labels, amount);
+    }
+}
+
+pub fn set(family: &Family, value: f64) {
+    if let Some(registry) = REGISTRY.get() {
+        registry.set(family, &[], value);
+    }
+}
+
+pub fn observe(family: &Family, labels: &[(&str, &str)], value: f64) {
+    if let Some(registry) = REGISTRY.get() {
+        registry.observe(family, labels, value);
+    }
+}
+
+/// The response to a request starting with `request_line`.
+fn respond(registry: &Registry, request_line: &str) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", registry.render()),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found: try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Only GET is supported\n".to_string()),
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )
+}
+
+/// Answer scrapes on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr, registry: &'static Registry) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Metrics endpoint disabled: can't listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!("📈 Metrics on http://{}/metrics", addr);
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Metrics accept failed: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // Read up to the end of the headers; scrapers send no body
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+                match stream.read(&mut buf).await {
+                    Ok(0) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                    Err(e) => {
+                        debug!("Metrics request from {} failed: {}", peer, e);
+                        return;
+                    }
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let response = respond(registry, request.lines().next().unwrap_or_default());
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                debug!("Metrics response to {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_and_gauges() {
+        let registry = Registry::default();
+        registry.add(&MESSAGES, &[("outcome", "spam")], 1.0);
+        registry.add(&MESSAGES, &[("outcome", "spam")], 1.0);
+        registry.add(&MESSAGES, &[("outcome", "delivered")], 1.0);
+        registry.set(&QUEUE_DEPTH, &[], 5.0);
+        registry.set(&QUEUE_DEPTH, &[], 2.0);
+        registry.add(&COST, &[], 0.25);
+        registry.add(&COST, &[], 0.5);
+
+        let text = registry.render();
+        assert!(text.contains("claudima_messages_total{outcome=\"delivered\"} 1\nclaudima_messages_total{outcome=\"spam\"} 2\n"));
+        assert!(text.contains("# TYPE claudima_queue_depth gauge\nclaudima_queue_depth 2\n"));
+        assert!(text.contains("claudima_cost_usd_total 0.75\n"));
+        // Nothing recorded, nothing exposed
+        assert!(!text.contains("claudima_turns_total"));
+    }
+
+    #[test]
+    fn test_exposition_format() {
+        let registry = Registry::default();
+        registry.add(&TOOL_CALLS, &[("tool", "send_message"), ("result", "ok")], 1.0);
+        registry.add(&TOOL_CALLS, &[("tool", "we\"ird\\"), ("result", "error")], 1.0);
+        registry.observe(&TURN_DURATION, &[], 1.5);
+        registry.observe(&TURN_DURATION, &[], 3.0);
+
+        assert_eq!(registry.render(), "\
+# HELP claudima_turn_duration_seconds Time from a turn's start to its end.
+# TYPE claudima_turn_duration_seconds summary
+claudima_turn_duration_seconds_sum 4.5
+claudima_turn_duration_seconds_count 2
+# HELP claudima_tool_calls_total Tool calls, by tool and result.
+# TYPE claudima_tool_calls_total counter
+claudima_tool_calls_total{tool=\"send_message\",result=\"ok\"} 1
+claudima_tool_calls_total{tool=\"we\\\"ird\\\\\",result=\"error\"} 1
+");
+    }
+
+    #[test]
+    fn test_responses() {
+        let registry = Registry::default();
+        registry.add(&SPAM_DELETED, &[], 3.0);
+        let ok = respond(&registry, "GET /metrics HTTP/1.1");
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n"));
+        assert!(ok.ends_with("claudima_spam_deleted_total 3\n"));
+        assert!(respond(&registry, "GET / HTTP/1.1").starts_with("HTTP/1.1 404"));
+        assert!(respond(&registry, "POST /metrics HTTP/1.1").starts_with("HTTP/1.1 405"));
+    }
+}
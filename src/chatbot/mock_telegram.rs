@@ -0,0 +1,214 @@
+//! In-memory `TelegramApi` for tests.
+//!
+//! Records every call as a line like `unmute_user -100 42` and answers with
+//! canned data, so tool tests can assert on what would have reached
+//! Telegram without a bot token.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use teloxide::types::{ChatPermissions, DiceEmoji, PhotoSize};
+use tokio::time::Instant;
+
+use super::chat_settings::PermissionFlags;
+use super::impersonation::AdminIdentity;
+use super::message::Location;
+use super::telegram::{ApiFuture, ChatMemberInfo, REPLY_NOT_FOUND, TelegramApi};
+
+/// Message IDs handed out by the mock start here.
+pub const FIRST_MESSAGE_ID: i64 = 1000;
+
+pub struct MockTelegramApi {
+    calls: Mutex<Vec<String>>,
+    /// When each call was made, for checking pacing.
+    call_times: Mutex<Vec<Instant>>,
+    next_message_id: AtomicI64,
+    admins: Vec<AdminIdentity>,
+    /// Every call fails with this error when set.
+    failure: Option<String>,
+    /// Messages that no longer exist: replies to them fail.
+    gone: Vec<i64>,
+}
+
+impl MockTelegramApi {
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::new(Vec::new()),
+            call_times: Mutex::new(Vec::new()),
+            next_message_id: AtomicI64::new(FIRST_MESSAGE_ID),
+            admins: Vec::new(),
+            failure: None,
+            gone: Vec::new(),
+        }
+    }
+
+    /// A mock whose chats have these admins.
+    pub fn with_admins(admins: Vec<AdminIdentity>) -> Self {
+        Self { admins, ..Self::new() }
+    }
+
+    /// A mock where every call fails with `error`, as when Telegram is down.
+    pub fn failing(error: &str) -> Self {
+        Self { failure: Some(error.to_string()), ..Self::new() }
+    }
+
+    /// A mock where these messages are gone, as if auto-deleted.
+    pub fn with_gone_messages(gone: Vec<i64>) -> Self {
+        Self { gone, ..Self::new() }
+    }
+
+    /// Calls made so far, oldest first.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().expect("mock calls lock poisoned").clone()
+    }
+
+    /// When each call was made, on tokio's clock.
+    pub fn call_times(&self) -> Vec<Instant> {
+        self.call_times.lock().expect("mock calls lock poisoned").clone()
+    }
+
+    /// Record a call and answer it with `ok` unless the mock is failing.
+    fn answer<T: Send + 'static>(&self, call: String, ok: T) -> ApiFuture<'_, T> {
+        self.calls.lock().expect("mock calls lock poisoned").push(call);
+        self.call_times.lock().expect("mock calls lock poisoned").push(Instant::now());
+        let result = match &self.failure {
+            Some(error) => Err(error.clone()),
+            None => Ok(ok),
+        };
+        Box::pin(async move { result })
+    }
+
+    fn message_id(&self) -> i64 {
+        self.next_message_id.fetch_add(1, Ordering::SeqCst)
+    }
+}
+
+impl Default for MockTelegramApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TelegramApi for MockTelegramApi {
+    fn send_message<'a>(&'a self, chat_id: i64, text: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        let call = format!("send_message {} {:?} reply_to={:?}", chat_id, text, reply_to_message_id);
+        if reply_to_message_id.is_some_and(|id| self.gone.contains(&id)) {
+            self.calls.lock().expect("mock calls lock poisoned").push(call);
+            self.call_times.lock().expect("mock calls lock poisoned").push(Instant::now());
+            return Box::pin(async { Err(format!("Failed to send: Bad Request: {REPLY_NOT_FOUND}")) });
+        }
+        self.answer(call, self.message_id())
+    }
+
+    fn get_chat_member(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ChatMemberInfo> {
+        let member = ChatMemberInfo {
+            user_id,
+            username: Some(format!("user{}", user_id)),
+            first_name: "Test".to_string(),
+            last_name: None,
+            is_bot: false,
+            is_premium: false,
+            language_code: Some("en".to_string()),
+            status: "member".to_string(),
+            custom_title: None,
+        };
+        self.answer(format!("get_chat_member {} {}", chat_id, user_id), member)
+    }
+
+    fn get_profile_photo(&self, user_id: i64, _max_bytes: u64) -> ApiFuture<'_, Option<Vec<u8>>> {
+        self.answer(format!("get_profile_photo {}", user_id), None)
+    }
+
+    fn set_message_reaction<'a>(&'a self, chat_id: i64, message_id: i64, emoji: &'a str) -> ApiFuture<'a, ()> {
+        self.answer(format!("set_message_reaction {} {} {}", chat_id, message_id, emoji), ())
+    }
+
+    fn send_dice(&self, chat_id: i64, _emoji: DiceEmoji, _reply_to_message_id: Option<i64>) -> ApiFuture<'_, (i64, u8)> {
+        self.answer(format!("send_dice {}", chat_id), (self.message_id(), 6))
+    }
+
+    fn send_location<'a>(&'a self, chat_id: i64, location: &'a Location, _reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        let title = location.title.as_deref().map(|t| format!(" {:?}", t)).unwrap_or_default();
+        self.answer(format!("send_location {} {},{}{}", chat_id, location.latitude, location.longitude, title), self.message_id())
+    }
+
+    fn delete_message(&self, chat_id: i64, message_id: i64) -> ApiFuture<'_, ()> {
+        self.answer(format!("delete_message {} {}", chat_id, message_id), ())
+    }
+
+    fn mute_user(&self, chat_id: i64, user_id: i64, until: chrono::DateTime<chrono::Utc>) -> ApiFuture<'_, ()> {
+        self.answer(format!("mute_user {} {} until={}", chat_id, user_id, until.format("%Y-%m-%d %H:%M")), ())
+    }
+
+    fn unmute_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()> {
+        self.answer(format!("unmute_user {} {}", chat_id, user_id), ())
+    }
+
+    fn ban_user(&self, chat_id: i64, user_id: i64, revoke_messages: bool) -> ApiFuture<'_, ()> {
+        self.answer(format!("ban_user {} {} revoke_messages={}", chat_id, user_id, revoke_messages), ())
+    }
+
+    fn kick_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()> {
+        self.answer(format!("kick_user {} {}", chat_id, user_id), ())
+    }
+
+    fn get_chat_admins(&self, chat_id: i64) -> ApiFuture<'_, String> {
+        let admins: Vec<serde_json::Value> = self.admins
+            .iter()
+            .map(|a| serde_json::json!({ "user_id": a.user_id, "username": a.username, "first_name": a.first_name }))
+            .collect();
+        self.answer(format!("get_chat_admins {}", chat_id), serde_json::Value::from(admins).to_string())
+    }
+
+    fn cached_admin_identities(&self, chat_id: i64) -> ApiFuture<'_, Vec<AdminIdentity>> {
+        self.answer(format!("cached_admin_identities {}", chat_id), self.admins.clone())
+    }
+
+    fn create_chat_invite_link<'a>(
+        &'a self,
+        chat_id: i64,
+        _expire_at: Option<chrono::DateTime<chrono::Utc>>,
+        member_limit: Option<u32>,
+        name: Option<&'a str>,
+    ) -> ApiFuture<'a, String> {
+        let link = format!("https://t.me/+mock{}", self.message_id());
+        self.answer(format!("create_chat_invite_link {} limit={:?} name={:?}", chat_id, member_limit, name), link)
+    }
+
+    fn revoke_chat_invite_link<'a>(&'a self, chat_id: i64, invite_link: &'a str) -> ApiFuture<'a, ()> {
+        self.answer(format!("revoke_chat_invite_link {} {}", chat_id, invite_link), ())
+    }
+
+    fn set_chat_permissions<'a>(&'a self, chat_id: i64, flags: &'a PermissionFlags) -> ApiFuture<'a, ChatPermissions> {
+        self.answer(format!("set_chat_permissions {} {}", chat_id, flags.describe()), flags.apply(ChatPermissions::all()))
+    }
+
+    fn send_image<'a>(&'a self, chat_id: i64, image_data: Vec<u8>, caption: Option<&'a str>, _reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        self.answer(format!("send_image {} {} bytes caption={:?}", chat_id, image_data.len(), caption), self.message_id())
+    }
+
+    fn send_document<'a>(&'a self, chat_id: i64, data: Vec<u8>, filename: &'a str, _reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        self.answer(format!("send_document {} {} {} bytes", chat_id, filename, data.len()), self.message_id())
+    }
+
+    fn send_voice<'a>(&'a self, chat_id: i64, voice_data: Vec<u8>, _caption: Option<&'a str>, _reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        self.answer(format!("send_voice {} {} bytes", chat_id, voice_data.len()), self.message_id())
+    }
+
+    fn download_photo<'a>(&'a self, sizes: &'a [PhotoSize], _max_bytes: u64) -> ApiFuture<'a, (Vec<u8>, String)> {
+        self.answer(format!("download_photo {} sizes", sizes.len()), (vec![0xff, 0xd8], "image/jpeg".to_string()))
+    }
+
+    fn get_chat_username(&self, user_id: i64) -> ApiFuture<'_, Option<String>> {
+        self.answer(format!("get_chat_username {}", user_id), Some(format!("user{}", user_id)))
+    }
+
+    fn invalidate_member(&self, chat_id: i64, user_id: i64, admin_changed: bool) {
+        self.calls.lock().expect("mock calls lock poisoned")
+            .push(format!("invalidate_member {} {} admin_changed={}", chat_id, user_id, admin_changed));
+    }
+
+    fn cache_summary(&self) -> String {
+        "mock".to_string()
+    }
+}
@@ -1,23 +1,71 @@
 //! Chatbot module - relays Telegram messages to Claude Code.
 
+pub mod ack;
+pub mod backup;
+pub mod bot_status;
+pub mod bulk_delete;
+pub mod chat_migration;
+pub mod chat_settings;
 pub mod claude_code;
+pub mod commands;
+pub mod compaction;
+pub mod config_store;
+pub mod cost_attribution;
 pub mod context;
 pub mod database;
 pub mod debounce;
+pub mod delegate;
 pub mod docx;
 pub mod engine;
 pub mod reminders;
+pub mod reply_target;
+pub mod requester;
 pub mod gemini;
+pub mod html;
+pub mod image_limits;
+pub mod impersonation;
+pub mod language;
+pub mod lookups;
+pub mod maintenance;
 pub mod message;
+pub mod message_features;
+pub mod message_ref;
+pub mod metrics;
+#[cfg(test)]
+pub mod mock_telegram;
+pub mod names;
+pub mod ocr;
+pub mod output_guard;
+pub mod owner_notices;
 pub mod peer;
+pub mod personality;
+pub mod prompt;
+pub mod quiet_hours;
+pub mod reactions;
+pub mod rubric;
+pub mod rules;
+pub mod read_only_sql;
+pub mod session_transcript;
 pub mod signals;
 pub mod telegram;
+pub mod tool_errors;
+pub mod tool_results;
+pub mod tool_timeouts;
 pub mod tools;
+pub mod transcript;
+pub mod translate;
+pub mod transparency;
 pub mod tts;
+pub mod ttl_cache;
+pub mod turn_report;
+pub mod user_digest;
+pub mod validate;
+pub mod voices;
 pub mod whisper;
+pub mod wiki;
 
 pub use claude_code::ClaudeCode;
 pub use engine::{system_prompt, ChatbotConfig, ChatbotEngine, TrustedUser};
 pub use message::{ChatMessage, ReplyTo};
-pub use telegram::TelegramClient;
+pub use telegram::{TelegramApi, TelegramClient};
 pub use whisper::Whisper;
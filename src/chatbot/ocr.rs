@@ -0,0 +1,132 @@
+//! OCR pre-pass for screenshots.
+//!
+//! Most images in chats are screenshots of text, and Claude reads the text far
+//! more cheaply than the image. When OCR finds enough text, the message carries
+//! the text and the image bytes are dropped, unless the caption asks about
+//! what the image looks like.
+
+use tracing::{debug, info, warn};
+
+use crate::chatbot::gemini::TextExtractor;
+use crate::chatbot::message::ChatMessage;
+
+/// OCR results shorter than this (in chars) are ignored: the image is
+/// probably a photo, and Claude gets it as is.
+pub const MIN_OCR_CHARS: usize = 40;
+
+/// Caption words that mean the sender is asking about the image itself.
+pub const DEFAULT_VISUAL_KEYWORDS: &[&str] = &[
+    "look", "photo", "image", "picture", "color", "colour", "screenshot",
+    "фото", "картинк", "смотри",
+];
+
+/// True if the caption asks about the visual content. A keyword matches
+/// the start of a word, so "look" also covers "looks" and "looking".
+pub fn wants_visual(caption: &str, keywords: &[String]) -> bool {
+    let caption = caption.to_lowercase();
+    caption
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| !word.is_empty() && keywords.iter().any(|k| word.starts_with(&k.to_lowercase())))
+}
+
+/// Run OCR on the message's image. Substantial text is attached as
+/// `ocr_text`, and the image is dropped unless the caption wants it.
+/// On OCR errors the message is left unchanged.
+pub async fn apply_ocr<E: TextExtractor>(extractor: &E, msg: &mut ChatMessage, keywords: &[String]) {
+    let Some((ref data, ref mime)) = msg.image else {
+        return;
+    };
+    let text = match extractor.extract_text(data, mime).await {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("OCR failed for msg {}: {}", msg.message_id, e);
+            return;
+        }
+    };
+    if text.chars().count() <= MIN_OCR_CHARS {
+        debug!("OCR found little text in msg {}; sending the image", msg.message_id);
+        return;
+    }
+
+    if wants_visual(&msg.text, keywords) {
+        info!("🔎 OCR: {} chars from msg {} (image kept, caption asks about it)", text.len(), msg.message_id);
+    } else {
+        info!("🔎 OCR: {} chars from msg {} (image dropped)", text.len(), msg.message_id);
+        msg.image = None;
+    }
+    msg.ocr_text = Some(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::gemini::MockGemini;
+
+    const SCREENSHOT_TEXT: &str = "Traceback (most recent call last):\n  File \"app.py\", line 3\nKeyError: 'user'";
+
+    fn keywords() -> Vec<String> {
+        DEFAULT_VISUAL_KEYWORDS.iter().map(|k| k.to_string()).collect()
+    }
+
+    fn image_msg(caption: &str) -> ChatMessage {
+        let mut msg = ChatMessage::system(caption.to_string());
+        msg.message_id = 7;
+        msg.image = Some((vec![1, 2, 3], "image/jpeg".to_string()));
+        msg
+    }
+
+    #[test]
+    fn test_wants_visual() {
+        let k = keywords();
+        assert!(wants_visual("Look at this", &k));
+        assert!(wants_visual("what do you think of the colours?", &k));
+        assert!(wants_visual("глянь на фото", &k));
+        assert!(!wants_visual("what does this error mean?", &k));
+        assert!(!wants_visual("", &k));
+        // Prefix of a word only, not anywhere inside it
+        assert!(!wants_visual("overlooked bug", &k));
+
+        let custom = vec!["zoom".to_string()];
+        assert!(wants_visual("zoom in please", &custom));
+        assert!(!wants_visual("look", &custom));
+    }
+
+    #[tokio::test]
+    async fn test_substantial_text_replaces_image() {
+        let gemini = MockGemini::new(Ok(SCREENSHOT_TEXT));
+        let mut msg = image_msg("what does this mean?");
+        apply_ocr(&gemini, &mut msg, &keywords()).await;
+
+        assert_eq!(msg.ocr_text.as_deref(), Some(SCREENSHOT_TEXT));
+        assert!(msg.image.is_none());
+        assert_eq!(gemini.prompts.lock().unwrap().as_slice(), ["image/jpeg"]);
+    }
+
+    #[tokio::test]
+    async fn test_visual_caption_keeps_image() {
+        let gemini = MockGemini::new(Ok(SCREENSHOT_TEXT));
+        let mut msg = image_msg("does this look right?");
+        apply_ocr(&gemini, &mut msg, &keywords()).await;
+
+        assert!(msg.ocr_text.is_some());
+        assert!(msg.image.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_short_text_or_error_keeps_image() {
+        let mut msg = image_msg("");
+        apply_ocr(&MockGemini::new(Ok("STOP")), &mut msg, &keywords()).await;
+        assert!(msg.ocr_text.is_none());
+        assert!(msg.image.is_some());
+
+        apply_ocr(&MockGemini::new(Err("quota exceeded")), &mut msg, &keywords()).await;
+        assert!(msg.ocr_text.is_none());
+        assert!(msg.image.is_some());
+
+        // No image, no call
+        let gemini = MockGemini::new(Ok(SCREENSHOT_TEXT));
+        let mut text_only = ChatMessage::system("hello".to_string());
+        apply_ocr(&gemini, &mut text_only, &keywords()).await;
+        assert!(gemini.prompts.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! Outbound content guard: phrases the bot must never send.
+//!
+//! Patterns are named so a blocked send can tell Claude which policy it hit
+//! without echoing the regex back (which would teach it how to dodge it).
+
+use std::collections::BTreeMap;
+
+use regex::{Regex, RegexBuilder};
+
+/// Patterns used when `forbidden_output_patterns` isn't configured: secrets
+/// that must never be posted, whatever someone talks the bot into.
+pub const DEFAULT_FORBIDDEN_PATTERNS: &[(&str, &str)] = &[
+    ("telegram_bot_token", r"\b\d{8,10}:[a-z0-9_-]{35}\b"),
+    ("openrouter_api_key", r"\bsk-or-v1-[a-f0-9]{32,}\b"),
+    ("google_api_key", r"\baiza[a-z0-9_-]{35}\b"),
+    ("private_key", r"-----begin [a-z ]*private key-----"),
+];
+
+/// A named forbidden pattern.
+#[derive(Debug, Clone)]
+pub struct ForbiddenPattern {
+    pub name: String,
+    regex: Regex,
+}
+
+/// Checks outgoing text against the forbidden patterns.
+#[derive(Debug, Clone)]
+pub struct OutputGuard {
+    patterns: Vec<ForbiddenPattern>,
+}
+
+impl OutputGuard {
+    /// Compile named patterns (case-insensitive).
+    pub fn new<'a>(patterns: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, String> {
+        let patterns = patterns.into_iter()
+            .map(|(name, pattern)| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map(|regex| ForbiddenPattern { name: name.to_string(), regex })
+                    .map_err(|e| format!("invalid pattern '{}': {}", name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Guard from config: the defaults when unset, otherwise exactly the given
+    /// patterns (an empty map disables the guard).
+    pub fn from_config(patterns: Option<&BTreeMap<String, String>>) -> Result<Self, String> {
+        match patterns {
+            Some(map) => Self::new(map.iter().map(|(n, p)| (n.as_str(), p.as_str()))),
+            None => Self::new(DEFAULT_FORBIDDEN_PATTERNS.iter().copied()),
+        }
+    }
+
+    /// Name of the first pattern matching the HTML-stripped text, if any.
+    pub fn violation(&self, text: &str) -> Option<&str> {
+        let plain = strip_html(text);
+        self.patterns.iter()
+            .find(|p| p.regex.is_match(&plain))
+            .map(|p| p.name.as_str())
+    }
+}
+
+impl Default for OutputGuard {
+    fn default() -> Self {
+        Self::from_config(None).expect("default forbidden patterns are valid")
+    }
+}
+
+/// Remove HTML tags and decode the entities Telegram HTML uses, so markup
+/// can't split a forbidden phrase ("b<b></b>ad").
+pub fn strip_html(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard() -> OutputGuard {
+        OutputGuard::new([("insult", r"\bidiot\b"), ("spoiler", r"snape kills dumbledore")]).unwrap()
+    }
+
+    #[test]
+    fn test_blocks_case_insensitive() {
+        let g = guard();
+        assert_eq!(g.violation("you IDIOT"), Some("insult"));
+        assert_eq!(g.violation("Snape Kills Dumbledore, sorry"), Some("spoiler"));
+        assert_eq!(g.violation("hello there"), None);
+    }
+
+    #[test]
+    fn test_word_boundaries_avoid_false_positives() {
+        let g = guard();
+        assert_eq!(g.violation("idiotic"), None);
+        assert_eq!(g.violation("the idiots"), None);
+        assert_eq!(g.violation("an idiot."), Some("insult"));
+    }
+
+    #[test]
+    fn test_html_stripped_before_matching() {
+        let g = guard();
+        assert_eq!(g.violation("you <b>id</b>iot"), Some("insult"));
+        assert_eq!(g.violation("<i>snape</i> kills &amp; dumbledore"), None);
+        assert_eq!(strip_html("a &lt;b&gt; <a href=\"x\">link</a> &amp; c"), "a <b> link & c");
+    }
+
+    #[test]
+    fn test_defaults_and_override() {
+        let defaults = OutputGuard::default();
+        assert_eq!(
+            defaults.violation("token: 123456789:AAHdqTcvCH1vGWJxfSeofSAs0K5PALDsaw1"),
+            Some("telegram_bot_token")
+        );
+        assert_eq!(defaults.violation("ordinary reply with 12:30 time"), None);
+
+        // Configured patterns replace the defaults entirely
+        let custom = BTreeMap::from([("insult".to_string(), r"\bidiot\b".to_string())]);
+        let g = OutputGuard::from_config(Some(&custom)).unwrap();
+        assert_eq!(g.violation("123456789:AAHdqTcvCH1vGWJxfSeofSAs0K5PALDsaw1"), None);
+        assert!(OutputGuard::from_config(Some(&BTreeMap::new())).unwrap().violation("idiot").is_none());
+
+        let bad = BTreeMap::from([("broken".to_string(), "(".to_string())]);
+        assert!(OutputGuard::from_config(Some(&bad)).unwrap_err().contains("broken"));
+    }
+}
@@ -0,0 +1,261 @@
+//! Owner notifications from admin tools, merged.
+//!
+//! Cleaning up a spam wave can take dozens of delete/ban calls. DMing the
+//! owner for each one trips Telegram's flood limits and buries the signal, so
+//! tools queue a `Notice` instead. The queue is flushed once at the end of a
+//! turn, and at most once per `MERGE_WINDOW`, so notices from turns close
+//! together are merged into one message too.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use super::telegram::ApiFuture;
+
+/// Sends a message to a chat. The engine's goes through its outbound path,
+/// so merged notices are prepared, split and stored like any bot message.
+pub type SendNotice = Arc<dyn Fn(i64, String) -> ApiFuture<'static, ()> + Send + Sync>;
+
+/// Minimum gap between two notification messages.
+pub const MERGE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Detail lines shown per action before "… and N more".
+const MAX_DETAIL_LINES: usize = 10;
+
+/// An admin action the owner is told about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Delete,
+    Mute,
+    Unmute,
+    Ban,
+    Kick,
+    CreateInvite,
+    RevokeInvite,
+    Relay,
+    SlowMode,
+    Permissions,
+    BulkDelete,
+}
+
+impl Action {
+    /// (emoji, verb, singular noun, plural noun, preposition before the chat)
+    fn wording(self) -> (&'static str, &'static str, &'static str, &'static str, &'static str) {
+        match self {
+            Self::Delete => ("🗑️", "Deleted", "message", "messages", "in"),
+            Self::Mute => ("🔇", "Muted", "user", "users", "in"),
+            Self::Unmute => ("🔊", "Unmuted", "user", "users", "in"),
+            Self::Ban => ("🚫", "Banned", "user", "users", "from"),
+            Self::Kick => ("👢", "Kicked", "user", "users", "from"),
+            Self::CreateInvite => ("🔗", "Created", "invite link", "invite links", "in"),
+            Self::RevokeInvite => ("🔗", "Revoked", "invite link", "invite links", "in"),
+            Self::Relay => ("📣", "Relayed", "message", "messages", "to"),
+            Self::SlowMode => ("🐢", "Set", "slow mode", "slow mode changes", "in"),
+            Self::Permissions => ("🔐", "Changed", "permissions", "permission changes", "in"),
+            Self::BulkDelete => ("🧹", "Bulk deleted", "batch", "batches", "in"),
+        }
+    }
+}
+
+/// One admin action. `detail` follows the noun: "4521" for a message,
+/// "123 until 2024-01-15 10:30 UTC" for a mute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notice {
+    pub action: Action,
+    pub chat_id: i64,
+    pub detail: String,
+}
+
+impl Notice {
+    pub fn new(action: Action, chat_id: i64, detail: impl Into<String>) -> Self {
+        Self { action, chat_id, detail: detail.into() }
+    }
+}
+
+/// Combine notices into one message: one line per action and chat, or a
+/// count with detail lines when there are several.
+pub fn compose(notices: &[Notice]) -> String {
+    let mut groups: Vec<(Action, i64, Vec<&str>)> = Vec::new();
+    for n in notices {
+        match groups.iter_mut().find(|(action, chat_id, _)| *action == n.action && *chat_id == n.chat_id) {
+            Some((_, _, details)) => details.push(&n.detail),
+            None => groups.push((n.action, n.chat_id, vec![&n.detail])),
+        }
+    }
+
+    let sections: Vec<String> = groups.into_iter()
+        .map(|(action, chat_id, details)| {
+            let (emoji, verb, noun, plural, prep) = action.wording();
+            if let [detail] = details[..] {
+                return format!("{} {} {} {} {} chat {}", emoji, verb, noun, detail, prep, chat_id);
+            }
+            let mut section = format!("{} {} {} {} {} chat {}:", emoji, verb, details.len(), plural, prep, chat_id);
+            for detail in details.iter().take(MAX_DETAIL_LINES) {
+                section.push_str(&format!("\n• {}", detail));
+            }
+            if details.len() > MAX_DETAIL_LINES {
+                section.push_str(&format!("\n… and {} more", details.len() - MAX_DETAIL_LINES));
+            }
+            section
+        })
+        .collect();
+    sections.join("\n")
+}
+
+/// Queued notices and when the last message went out.
+#[derive(Default)]
+pub struct NoticeBatcher {
+    pending: Vec<Notice>,
+    last_sent: Option<Instant>,
+    /// A flush is already waiting for the merge window to end.
+    scheduled: bool,
+}
+
+impl NoticeBatcher {
+    pub fn push(&mut self, notice: Notice) {
+        self.pending.push(notice);
+    }
+
+    /// When the pending notices may go out: `now`, or the end of the merge
+    /// window after the last message. None if nothing is pending.
+    pub fn ready_at(&self, now: Instant) -> Option<Instant> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        match self.last_sent {
+            Some(last) if now < last + MERGE_WINDOW => Some(last + MERGE_WINDOW),
+            _ => Some(now),
+        }
+    }
+
+    /// The combined message, if the pending notices may go out at `now`.
+    pub fn take(&mut self, now: Instant) -> Option<String> {
+        if self.ready_at(now)? > now {
+            return None;
+        }
+        self.last_sent = Some(now);
+        Some(compose(&std::mem::take(&mut self.pending)))
+    }
+}
+
+/// Sends queued notices to the owner's DM.
+#[derive(Clone)]
+pub struct OwnerNotices {
+    send: SendNotice,
+    owner_id: Option<i64>,
+    batcher: Arc<Mutex<NoticeBatcher>>,
+}
+
+impl OwnerNotices {
+    pub fn new(send: SendNotice, owner_id: Option<i64>) -> Self {
+        Self { send, owner_id, batcher: Arc::new(Mutex::new(NoticeBatcher::default())) }
+    }
+
+    /// Queue a notice until the next flush. Dropped if there's no owner.
+    pub fn push(&self, notice: Notice) {
+        if self.owner_id.is_some() {
+            self.batcher.lock().expect("notice batcher lock poisoned").push(notice);
+        }
+    }
+
+    /// Send the queued notices as one message, right away or once the merge
+    /// window since the last one ends. Notices queued meanwhile are included.
+    pub fn flush(&self) {
+        let Some(owner_id) = self.owner_id else {
+            return;
+        };
+        let delay = {
+            let mut batcher = self.batcher.lock().expect("notice batcher lock poisoned");
+            let now = Instant::now();
+            match batcher.ready_at(now) {
+                Some(at) if !batcher.scheduled => {
+                    batcher.scheduled = true;
+                    at.saturating_duration_since(now)
+                }
+                _ => return,
+            }
+        };
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let message = {
+                let mut batcher = this.batcher.lock().expect("notice batcher lock poisoned");
+                batcher.scheduled = false;
+                batcher.take(Instant::now())
+            };
+            let Some(message) = message else {
+                return;
+            };
+            info!("Notifying owner ({}) of admin actions", owner_id);
+            if let Err(e) = (this.send)(owner_id, message).await {
+                warn!("Failed to notify owner of admin actions: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intra_turn_merge() {
+        let chat = -1001234567890;
+        let mut batcher = NoticeBatcher::default();
+        for id in 1..=17 {
+            batcher.push(Notice::new(Action::Delete, chat, id.to_string()));
+        }
+        batcher.push(Notice::new(Action::Ban, chat, "111"));
+        batcher.push(Notice::new(Action::Ban, chat, "222"));
+        batcher.push(Notice::new(Action::Mute, -200, "333 until 2024-01-15 10:30 UTC"));
+
+        let message = batcher.take(Instant::now()).unwrap();
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(lines[0], "🗑️ Deleted 17 messages in chat -1001234567890:");
+        assert_eq!(lines[1], "• 1");
+        assert_eq!(lines[10], "• 10");
+        assert_eq!(lines[11], "… and 7 more");
+        assert_eq!(&lines[12..], [
+            "🚫 Banned 2 users from chat -1001234567890:",
+            "• 111",
+            "• 222",
+            "🔇 Muted user 333 until 2024-01-15 10:30 UTC in chat -200",
+        ]);
+        // Everything went out in one message
+        assert_eq!(batcher.take(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_single_notice_reads_as_before() {
+        let notices = [Notice::new(Action::Kick, -100, "42")];
+        assert_eq!(compose(&notices), "👢 Kicked user 42 from chat -100");
+    }
+
+    #[test]
+    fn test_cross_turn_window() {
+        let start = Instant::now();
+        let mut batcher = NoticeBatcher::default();
+        assert_eq!(batcher.ready_at(start), None);
+
+        // First turn: sent right away
+        batcher.push(Notice::new(Action::Delete, -100, "1"));
+        assert_eq!(batcher.ready_at(start), Some(start));
+        assert!(batcher.take(start).is_some());
+
+        // Next turn 10s later waits for the window, merging a third turn's notice
+        let t10 = start + Duration::from_secs(10);
+        batcher.push(Notice::new(Action::Delete, -100, "2"));
+        assert_eq!(batcher.ready_at(t10), Some(start + MERGE_WINDOW));
+        assert_eq!(batcher.take(t10), None);
+        batcher.push(Notice::new(Action::Delete, -100, "3"));
+        let t30 = start + MERGE_WINDOW;
+        assert_eq!(batcher.take(t30).unwrap(), "🗑️ Deleted 2 messages in chat -100:\n• 2\n• 3");
+
+        // After a quiet window, sent right away again
+        let t70 = t30 + MERGE_WINDOW + Duration::from_secs(10);
+        batcher.push(Notice::new(Action::Unmute, -100, "5"));
+        assert_eq!(batcher.ready_at(t70), Some(t70));
+    }
+}
@@ -0,0 +1,222 @@
+//! Named personalities, switched by the owner or on a daily schedule.
+//!
+//! The `personalities` config maps names to prompt fragments that replace
+//! the "Who You Are" section. `personality_schedule` picks one by local time
+//! of day (in `scan_timezone`); outside every window the plain `personality`
+//! (or the built-in identity) applies. The owner can switch at any time with
+//! `/personality <name>` or the `set_personality` tool, which holds until
+//! the next window boundary. The active name is saved in the data dir so a
+//! restart keeps it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::NaiveTime;
+use serde::Deserialize;
+use tracing::warn;
+
+/// File in the data dir holding the active personality's name.
+pub const ACTIVE_FILE: &str = "personality";
+
+/// A `personality_schedule` entry as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleEntry {
+    /// "HH:MM", inclusive.
+    pub start: String,
+    /// "HH:MM", exclusive. Earlier than `start` wraps past midnight.
+    pub end: String,
+    pub personality: String,
+}
+
+/// A daily time window with its personality.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub personality: String,
+}
+
+impl Window {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            // Equal bounds cover the whole day
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Personalities {
+    /// Identity used when no named personality is active.
+    pub default: Option<String>,
+    /// Name → prompt fragment.
+    pub named: BTreeMap<String, String>,
+    /// First matching window wins.
+    pub schedule: Vec<Window>,
+}
+
+impl Personalities {
+    /// Check the schedule against the named personalities.
+    pub fn new(default: Option<String>, named: BTreeMap<String, String>, schedule: &[ScheduleEntry]) -> Result<Self, String> {
+        let parse = |value: &str| {
+            NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| format!("invalid time '{}' (expected HH:MM)", value))
+        };
+        let schedule = schedule
+            .iter()
+            .map(|entry| {
+                if !named.contains_key(&entry.personality) {
+                    return Err(format!("unknown personality '{}'", entry.personality));
+                }
+                Ok(Window { start: parse(&entry.start)?, end: parse(&entry.end)?, personality: entry.personality.clone() })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { default, named, schedule })
+    }
+
+    /// The personality scheduled at local `time`, if any.
+    pub fn scheduled(&self, time: NaiveTime) -> Option<&str> {
+        self.schedule.iter().find(|w| w.contains(time)).map(|w| w.personality.as_str())
+    }
+
+    /// Times of day at which the scheduled personality may change.
+    pub fn boundaries(&self) -> Vec<NaiveTime> {
+        self.schedule.iter().flat_map(|w| [w.start, w.end]).collect()
+    }
+
+    /// The identity to use while `active` is active.
+    pub fn fragment(&self, active: Option<&str>) -> Option<&str> {
+        active.and_then(|name| self.named.get(name)).or(self.default.as_ref()).map(String::as_str)
+    }
+
+    /// Error unless `name` is a configured personality.
+    pub fn check(&self, name: &str) -> Result<(), String> {
+        if self.named.contains_key(name) {
+            return Ok(());
+        }
+        if self.named.is_empty() {
+            return Err("No personalities are configured".to_string());
+        }
+        let names: Vec<&str> = self.named.keys().map(String::as_str).collect();
+        Err(format!("Unknown personality '{}'. Available: {}", name, names.join(", ")))
+    }
+
+    /// The personality to start with: the saved one if it still exists,
+    /// else the one scheduled at local `time`.
+    pub fn initial(&self, saved: Option<String>, time: NaiveTime) -> Option<String> {
+        saved
+            .filter(|name| self.named.contains_key(name))
+            .or_else(|| self.scheduled(time).map(String::from))
+    }
+
+    /// The system message telling Claude about a switch to `active`.
+    pub fn update_message(&self, active: Option<&str>) -> String {
+        let name = active.unwrap_or("default");
+        match self.fragment(active) {
+            Some(fragment) => format!("Personality update: you are now \"{}\".\n\n{}", name, fragment),
+            None => format!("Personality update: back to your default identity (\"{}\").", name),
+        }
+    }
+}
+
+/// The saved active personality in `data_dir`, if any.
+pub fn load_active(data_dir: &Path) -> Option<String> {
+    let path = data_dir.join(ACTIVE_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(name) => Some(name.trim().to_string()).filter(|n| !n.is_empty()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Save the active personality in `data_dir` (None = the default).
+pub fn save_active(data_dir: &Path, active: Option<&str>) -> Result<(), String> {
+    let path = data_dir.join(ACTIVE_FILE);
+    let result = match active {
+        Some(name) => std::fs::write(&path, name),
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        },
+    };
+    result.map_err(|e| format!("Failed to save {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hm: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(hm, "%H:%M").unwrap()
+    }
+
+    fn personalities() -> Personalities {
+        let named = BTreeMap::from([
+            ("moderator".to_string(), "You are a serious moderator.".to_string()),
+            ("gremlin".to_string(), "You are a chaotic gremlin.".to_string()),
+        ]);
+        let schedule = [
+            ScheduleEntry { start: "09:00".into(), end: "18:00".into(), personality: "moderator".into() },
+            ScheduleEntry { start: "20:00".into(), end: "02:00".into(), personality: "gremlin".into() },
+        ];
+        Personalities::new(Some("You are Claudima.".to_string()), named, &schedule).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_boundaries() {
+        let p = personalities();
+        assert_eq!(p.scheduled(time("08:59")), None);
+        assert_eq!(p.scheduled(time("09:00")), Some("moderator"));
+        assert_eq!(p.scheduled(time("17:59")), Some("moderator"));
+        assert_eq!(p.scheduled(time("18:00")), None);
+        // Wraps past midnight
+        assert_eq!(p.scheduled(time("20:00")), Some("gremlin"));
+        assert_eq!(p.scheduled(time("00:00")), Some("gremlin"));
+        assert_eq!(p.scheduled(time("01:59")), Some("gremlin"));
+        assert_eq!(p.scheduled(time("02:00")), None);
+        assert_eq!(p.boundaries().len(), 4);
+
+        let all_day = Window { start: time("06:00"), end: time("06:00"), personality: "x".into() };
+        assert!(all_day.contains(time("05:59")));
+
+        let bad = [ScheduleEntry { start: "9am".into(), end: "18:00".into(), personality: "moderator".into() }];
+        assert!(Personalities::new(None, p.named.clone(), &bad).unwrap_err().contains("invalid time '9am'"));
+        let unknown = [ScheduleEntry { start: "09:00".into(), end: "18:00".into(), personality: "pirate".into() }];
+        assert_eq!(Personalities::new(None, p.named.clone(), &unknown).unwrap_err(), "unknown personality 'pirate'");
+    }
+
+    #[test]
+    fn test_fragments_and_names() {
+        let p = personalities();
+        assert_eq!(p.fragment(Some("gremlin")), Some("You are a chaotic gremlin."));
+        assert_eq!(p.fragment(None), Some("You are Claudima."));
+        assert!(p.check("gremlin").is_ok());
+        assert_eq!(p.check("pirate").unwrap_err(), "Unknown personality 'pirate'. Available: gremlin, moderator");
+        assert!(p.update_message(Some("gremlin")).starts_with("Personality update: you are now \"gremlin\"."));
+        assert!(Personalities::default().update_message(None).contains("default identity"));
+    }
+
+    #[test]
+    fn test_active_name_persists() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let p = personalities();
+        assert_eq!(load_active(dir.path()), None);
+        assert_eq!(p.initial(load_active(dir.path()), time("10:00")), Some("moderator".to_string()));
+
+        save_active(dir.path(), Some("gremlin")).unwrap();
+        assert_eq!(load_active(dir.path()), Some("gremlin".to_string()));
+        // The saved name beats the schedule after a restart
+        assert_eq!(p.initial(load_active(dir.path()), time("10:00")), Some("gremlin".to_string()));
+        // A personality removed from the config is ignored
+        save_active(dir.path(), Some("pirate")).unwrap();
+        assert_eq!(p.initial(load_active(dir.path()), time("19:00")), None);
+
+        save_active(dir.path(), None).unwrap();
+        save_active(dir.path(), None).unwrap();
+        assert_eq!(load_active(dir.path()), None);
+    }
+}
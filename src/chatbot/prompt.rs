@@ -0,0 +1,67 @@
+//! System prompt assembly.
+//!
+//! The prompt is resent in full with every fresh session, so its size is a
+//! real cost. It's built from `# Title` sections, and sections for
+//! integrations that aren't configured are left out. `compact_prompt`
+//! trims further: one-line tool descriptions and no rubric instructions.
+
+/// Tokens the compact prompt should stay under; startup warns beyond it.
+pub const COMPACT_TARGET_TOKENS: usize = 6500;
+
+/// Rough token count of `text`: about four characters per token, which is
+/// close for English prose and markup.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Builds the system prompt one `# Title` section at a time.
+#[derive(Debug, Default)]
+pub struct PromptBuilder {
+    sections: Vec<(&'static str, String)>,
+}
+
+impl PromptBuilder {
+    /// Add a section.
+    pub fn section(mut self, title: &'static str, body: impl Into<String>) -> Self {
+        self.sections.push((title, body.into()));
+        self
+    }
+
+    /// Add a section if `include` is set.
+    pub fn section_if(self, include: bool, title: &'static str, body: impl Into<String>) -> Self {
+        if include { self.section(title, body) } else { self }
+    }
+
+    /// The prompt: the sections in the order added.
+    pub fn build(&self) -> String {
+        self.sections
+            .iter()
+            .map(|(title, body)| format!("# {}\n\n{}\n", title, body))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let prompt = PromptBuilder::default()
+            .section("One", "first")
+            .section_if(false, "Two", "skipped")
+            .section_if(true, "Three", format!("third {}", 3))
+            .build();
+        assert_eq!(prompt, "# One\n\nfirst\n\n# Three\n\nthird 3\n");
+        assert_eq!(PromptBuilder::default().build(), "");
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("привет"), 2);
+    }
+}
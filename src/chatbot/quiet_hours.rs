@@ -0,0 +1,150 @@
+//! Quiet hours: no unsolicited bot messages in groups at night.
+//!
+//! During the `quiet_hours` window (local time in `scan_timezone`) reminder
+//! fires, scan results, peer chatter and anything else the bot posts on its
+//! own are queued as scheduled messages due when the window ends. Replies to
+//! a message from the last `ACTIVE_CONVERSATION` are sent as usual, so people
+//! talking to the bot at night still get answers. `defer_until` is the one
+//! place that decides; the reminder checker and the send path both ask it.
+
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A reply to a message at most this old is part of an active conversation.
+pub const ACTIVE_CONVERSATION: chrono::Duration = chrono::Duration::minutes(10);
+
+/// `quiet_hours` as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHoursConfig {
+    /// "HH:MM", inclusive.
+    pub start: String,
+    /// "HH:MM", exclusive. Earlier than `start` wraps past midnight.
+    pub end: String,
+    /// Chats that never get quiet hours.
+    #[serde(default)]
+    pub exempt_chats: Vec<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    tz: Tz,
+    exempt_chats: Vec<i64>,
+}
+
+impl QuietHours {
+    pub fn new(config: &QuietHoursConfig, tz: Tz) -> Result<Self, String> {
+        let parse = |value: &str| {
+            NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| format!("invalid time '{}' (expected HH:MM)", value))
+        };
+        let (start, end) = (parse(&config.start)?, parse(&config.end)?);
+        if start == end {
+            return Err("start and end are the same time".to_string());
+        }
+        Ok(Self { start, end, tz, exempt_chats: config.exempt_chats.clone() })
+    }
+
+    /// Whether local `time` falls in the window.
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// When the quiet hours covering `now` end, or None outside them.
+    pub fn end_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = now.with_timezone(&self.tz).naive_local();
+        if !self.contains(local.time()) {
+            return None;
+        }
+        let mut end = local.date().and_time(self.end);
+        if end <= local {
+            end += chrono::Duration::days(1);
+        }
+        // A DST gap can swallow the end time; an hour later always exists
+        let end = end.and_local_timezone(self.tz).earliest()
+            .or_else(|| (end + chrono::Duration::hours(1)).and_local_timezone(self.tz).earliest())?;
+        Some(end.with_timezone(&Utc))
+    }
+
+    /// When a bot message to `chat_id` should be sent instead of now, or None
+    /// to send it now. `replying_to` is when the message it replies to was
+    /// sent, if it replies to one.
+    pub fn defer_until(&self, chat_id: i64, replying_to: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // DMs are always answered, and exempt chats never go quiet
+        if chat_id >= 0 || self.exempt_chats.contains(&chat_id) {
+            return None;
+        }
+        if replying_to.is_some_and(|sent| now - sent <= ACTIVE_CONVERSATION) {
+            return None;
+        }
+        self.end_after(now)
+    }
+}
+
+/// When a stored message was sent, parsed from its "YYYY-MM-DD HH:MM" (UTC)
+/// timestamp. None for anything else.
+pub fn message_time(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M").ok().map(|t| t.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap().and_utc()
+    }
+
+    fn quiet(start: &str, end: &str, tz: Tz) -> QuietHours {
+        let config = QuietHoursConfig { start: start.into(), end: end.into(), exempt_chats: vec![-200] };
+        QuietHours::new(&config, tz).unwrap()
+    }
+
+    #[test]
+    fn test_window_across_midnight() {
+        let q = quiet("23:00", "07:00", chrono_tz::UTC);
+        assert_eq!(q.end_after(at("2024-01-15 22:59")), None);
+        assert_eq!(q.end_after(at("2024-01-15 23:00")), Some(at("2024-01-16 07:00")));
+        assert_eq!(q.end_after(at("2024-01-16 03:00")), Some(at("2024-01-16 07:00")));
+        assert_eq!(q.end_after(at("2024-01-16 06:59")), Some(at("2024-01-16 07:00")));
+        assert_eq!(q.end_after(at("2024-01-16 07:00")), None);
+
+        let daytime = quiet("13:00", "15:00", chrono_tz::UTC);
+        assert_eq!(daytime.end_after(at("2024-01-15 14:00")), Some(at("2024-01-15 15:00")));
+        assert_eq!(daytime.end_after(at("2024-01-15 03:00")), None);
+
+        // Local time: 22:00 UTC is 23:00 in Paris (UTC+1 in winter)
+        let paris = quiet("23:00", "07:00", chrono_tz::Europe::Paris);
+        assert_eq!(paris.end_after(at("2024-01-15 21:59")), None);
+        assert_eq!(paris.end_after(at("2024-01-15 22:00")), Some(at("2024-01-16 06:00")));
+
+        let same = QuietHoursConfig { start: "07:00".into(), end: "07:00".into(), exempt_chats: vec![] };
+        assert!(QuietHours::new(&same, chrono_tz::UTC).is_err());
+        let bad = QuietHoursConfig { start: "11pm".into(), end: "07:00".into(), exempt_chats: vec![] };
+        assert!(QuietHours::new(&bad, chrono_tz::UTC).unwrap_err().contains("invalid time '11pm'"));
+    }
+
+    #[test]
+    fn test_active_conversation_exemption() {
+        let q = quiet("23:00", "07:00", chrono_tz::UTC);
+        let now = at("2024-01-16 03:00");
+        let morning = Some(at("2024-01-16 07:00"));
+
+        // Unsolicited, or replying to something old: wait for the morning
+        assert_eq!(q.defer_until(-100, None, now), morning);
+        assert_eq!(q.defer_until(-100, Some(at("2024-01-16 02:49")), now), morning);
+        // Replying to someone who just wrote
+        assert_eq!(q.defer_until(-100, Some(at("2024-01-16 02:50")), now), None);
+        // Exempt chats and DMs
+        assert_eq!(q.defer_until(-200, None, now), None);
+        assert_eq!(q.defer_until(42, None, now), None);
+
+        assert_eq!(message_time("2024-01-16 02:50"), Some(at("2024-01-16 02:50")));
+        assert_eq!(message_time("02:50"), None);
+    }
+}
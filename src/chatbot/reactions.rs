@@ -0,0 +1,152 @@
+//! Reactions on the bot's messages, batched into one notice per turn.
+//!
+//! A pile-on of 👍 becomes a single line ("5 reactions on msg 4521: 👍x3 ❤️x2")
+//! instead of one system message per reaction.
+
+use teloxide::types::ReactionType;
+
+use crate::chatbot::message::ChatMessage;
+
+/// Text for a reaction: the emoji itself, or a label for custom and paid ones.
+pub fn reaction_label(reaction: &ReactionType) -> String {
+    match reaction {
+        ReactionType::Emoji { emoji } => emoji.clone(),
+        ReactionType::CustomEmoji { .. } => "[custom emoji]".to_string(),
+        ReactionType::Paid => "⭐".to_string(),
+    }
+}
+
+/// Reactions added and removed between a user's old and new reaction lists.
+pub fn reaction_changes(old: &[ReactionType], new: &[ReactionType]) -> (Vec<String>, Vec<String>) {
+    let added = new.iter().filter(|r| !old.contains(r)).map(reaction_label).collect();
+    let removed = old.iter().filter(|r| !new.contains(r)).map(reaction_label).collect();
+    (added, removed)
+}
+
+struct PendingReaction {
+    chat_id: i64,
+    message_id: i64,
+    username: String,
+    emoji: String,
+}
+
+/// Reactions to the bot's messages waiting for the next debounce turn.
+#[derive(Default)]
+pub struct ReactionBatch {
+    pending: Vec<PendingReaction>,
+}
+
+impl ReactionBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, chat_id: i64, message_id: i64, username: &str, emoji: &str) {
+        self.pending.push(PendingReaction {
+            chat_id,
+            message_id,
+            username: username.to_string(),
+            emoji: emoji.to_string(),
+        });
+    }
+
+    /// One line per reacted-to message, in the order first reacted to.
+    fn lines(&self) -> Vec<String> {
+        let mut messages: Vec<(i64, i64)> = Vec::new();
+        for r in &self.pending {
+            if !messages.contains(&(r.chat_id, r.message_id)) {
+                messages.push((r.chat_id, r.message_id));
+            }
+        }
+
+        messages.into_iter().map(|(chat_id, message_id)| {
+            let reactions: Vec<&PendingReaction> = self.pending.iter()
+                .filter(|r| r.chat_id == chat_id && r.message_id == message_id)
+                .collect();
+            if let [single] = reactions.as_slice() {
+                return format!(
+                    "[reaction] {} reacted {} to your msg {} (chat {})",
+                    single.username, single.emoji, message_id, chat_id
+                );
+            }
+
+            let mut counts: Vec<(&str, usize)> = Vec::new();
+            for r in &reactions {
+                match counts.iter_mut().find(|(emoji, _)| *emoji == r.emoji) {
+                    Some((_, n)) => *n += 1,
+                    None => counts.push((&r.emoji, 1)),
+                }
+            }
+            let summary: Vec<String> = counts.iter().map(|(emoji, n)| format!("{}x{}", emoji, n)).collect();
+            format!(
+                "[reaction] {} reactions on msg {} (chat {}): {}",
+                reactions.len(), message_id, chat_id, summary.join(" ")
+            )
+        }).collect()
+    }
+
+    /// Drain the batch into a single system message, if anything is pending.
+    pub fn take_message(&mut self) -> Option<ChatMessage> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let text = self.lines().join("\n");
+        self.pending.clear();
+        Some(ChatMessage::system(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emoji(e: &str) -> ReactionType {
+        ReactionType::Emoji { emoji: e.to_string() }
+    }
+
+    #[test]
+    fn test_reaction_changes() {
+        let (added, removed) = reaction_changes(&[emoji("👍")], &[emoji("👍"), emoji("❤")]);
+        assert_eq!(added, vec!["❤"]);
+        assert!(removed.is_empty());
+
+        let (added, removed) = reaction_changes(&[emoji("👍")], &[ReactionType::Paid]);
+        assert_eq!(added, vec!["⭐"]);
+        assert_eq!(removed, vec!["👍"]);
+
+        // Custom emoji are labelled, not dropped
+        let custom = ReactionType::CustomEmoji { custom_emoji_id: "5368324170671202286".into() };
+        let (added, removed) = reaction_changes(&[], &[custom]);
+        assert_eq!(added, vec!["[custom emoji]"]);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn test_single_reaction_line() {
+        let mut batch = ReactionBatch::new();
+        batch.add(-100, 4521, "alice", "❤");
+
+        let msg = batch.take_message().unwrap();
+        assert_eq!(msg.user_id, 0);
+        assert_eq!(msg.text, "[reaction] alice reacted ❤ to your msg 4521 (chat -100)");
+        assert!(batch.take_message().is_none());
+    }
+
+    #[test]
+    fn test_pile_on_aggregated_per_message() {
+        let mut batch = ReactionBatch::new();
+        batch.add(-100, 4521, "alice", "👍");
+        batch.add(-100, 4521, "bob", "❤");
+        batch.add(-100, 4530, "carol", "🔥");
+        batch.add(-100, 4521, "dave", "👍");
+        batch.add(-100, 4521, "erin", "❤");
+        batch.add(-100, 4521, "frank", "👍");
+
+        let msg = batch.take_message().unwrap();
+        assert_eq!(
+            msg.text,
+            "[reaction] 5 reactions on msg 4521 (chat -100): 👍x3 ❤x2\n\
+             [reaction] carol reacted 🔥 to your msg 4530 (chat -100)"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Which SQL the `query` tool may run.
+//!
+//! Exactly one statement: a SELECT, an EXPLAIN (QUERY PLAN) of a SELECT, or
+//! `PRAGMA table_info(...)` / `PRAGMA index_list(...)`, so Claude can look at
+//! the real schema instead of guessing column names. The SQL is tokenized,
+//! so keywords inside string literals, quoted names and comments don't
+//! count ("WHERE text LIKE '%update you%'" is fine). The query itself then
+//! runs with `query_only` on, which stops writes that slip past this check.
+
+/// Pragmas the tool may read.
+const ALLOWED_PRAGMAS: [&str; 2] = ["TABLE_INFO", "INDEX_LIST"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keyword or bare name, uppercased.
+    Word(String),
+    /// String literal, quoted name or number.
+    Value,
+    Semicolon,
+    Symbol(char),
+}
+
+/// Split `sql` into tokens, skipping whitespace and comments.
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '-' if chars.peek() == Some(&'-') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => prev = c,
+                        None => return Err("Unterminated comment".to_string()),
+                    }
+                }
+            }
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                loop {
+                    match chars.next() {
+                        // A doubled quote is an escaped one
+                        Some(q) if q == close && c != '[' && chars.peek() == Some(&close) => {
+                            chars.next();
+                        }
+                        Some(q) if q == close => break,
+                        Some(_) => {}
+                        None => return Err(format!("Unterminated {c} quote")),
+                    }
+                }
+                tokens.push(Token::Value);
+            }
+            c if c.is_ascii_digit() => {
+                while chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.').is_some() {}
+                tokens.push(Token::Value);
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_uppercase().to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '$') {
+                    word.extend(c.to_uppercase());
+                }
+                tokens.push(Token::Word(word));
+            }
+            ';' => tokens.push(Token::Semicolon),
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_word(token: Option<&Token>, word: &str) -> bool {
+    matches!(token, Some(Token::Word(w)) if w == word)
+}
+
+/// Check that `sql` is a single statement the `query` tool allows. Returns
+/// it without trailing semicolons, ready to prepare.
+pub fn validate(sql: &str) -> Result<&str, String> {
+    let sql = sql.trim().trim_end_matches(|c: char| c == ';' || c.is_whitespace());
+    let mut tokens = tokenize(sql)?;
+    while tokens.last() == Some(&Token::Semicolon) {
+        tokens.pop();
+    }
+    if tokens.contains(&Token::Semicolon) {
+        return Err("Only one statement per query is allowed".to_string());
+    }
+
+    let mut rest = tokens.as_slice();
+    if is_word(rest.first(), "EXPLAIN") {
+        rest = &rest[1..];
+        if is_word(rest.first(), "QUERY") && is_word(rest.get(1), "PLAN") {
+            rest = &rest[2..];
+        }
+    }
+    match rest.first() {
+        Some(Token::Word(w)) if w == "SELECT" => Ok(sql),
+        Some(Token::Word(w)) if w == "PRAGMA" && !is_word(tokens.first(), "EXPLAIN") => match &rest[1..] {
+            [Token::Word(name), Token::Symbol('('), Token::Word(_) | Token::Value, Token::Symbol(')')]
+                if ALLOWED_PRAGMAS.contains(&name.as_str()) => Ok(sql),
+            _ => Err("Only PRAGMA table_info(<table>) and PRAGMA index_list(<table>) are allowed".to_string()),
+        },
+        _ => Err("Only SELECT, EXPLAIN SELECT and PRAGMA table_info/index_list are allowed".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keywords_in_literals() {
+        // Used to trip the keyword blocklist
+        let sql = "SELECT text FROM messages WHERE text LIKE '%i want to update you%'";
+        assert_eq!(validate(sql), Ok(sql));
+        assert!(validate("SELECT * FROM messages WHERE text = 'create; drop table users'").is_ok());
+        assert!(validate("SELECT \"delete\" FROM [insert] -- drop it; now\n WHERE 1").is_ok());
+        assert!(validate("SELECT 'it''s; fine' /* ; */ FROM users").is_ok());
+        assert!(validate("SELECT last_update, created_at FROM users").is_ok());
+    }
+
+    #[test]
+    fn test_explain_and_pragma() {
+        assert!(validate("EXPLAIN SELECT * FROM messages").is_ok());
+        assert!(validate("explain query plan select * from messages where user_id = 1").is_ok());
+        assert!(validate("PRAGMA table_info(messages)").is_ok());
+        assert!(validate("pragma index_list('users');").is_ok());
+        assert_eq!(validate("PRAGMA table_info(messages);  "), Ok("PRAGMA table_info(messages)"));
+
+        assert!(validate("PRAGMA journal_mode = DELETE").is_err());
+        assert!(validate("PRAGMA query_only(0)").is_err());
+        assert!(validate("PRAGMA table_info = messages").is_err());
+        assert!(validate("EXPLAIN DELETE FROM messages").is_err());
+        assert!(validate("EXPLAIN PRAGMA table_info(messages)").is_err());
+    }
+
+    #[test]
+    fn test_multiple_statements() {
+        assert!(validate("SELECT 1; SELECT 2").is_err());
+        assert!(validate("SELECT * FROM messages;DELETE FROM messages").is_err());
+        assert!(validate("PRAGMA table_info(users); DROP TABLE users").is_err());
+        // Trailing semicolons are just the end of the statement
+        assert_eq!(validate("SELECT 1;;"), Ok("SELECT 1"));
+    }
+
+    #[test]
+    fn test_rejects_other_statements() {
+        for sql in [
+            "INSERT INTO users (user_id) VALUES (1)",
+            "UPDATE users SET status = 'banned'",
+            "WITH x AS (SELECT 1) DELETE FROM messages",
+            "ATTACH DATABASE 'x.db' AS x",
+            "VACUUM",
+            "",
+            "SELECT 'unterminated",
+            "SELECT 1 /* unterminated",
+        ] {
+            assert!(validate(sql).is_err(), "{sql}");
+        }
+    }
+}
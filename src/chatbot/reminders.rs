@@ -16,6 +16,83 @@ pub struct Reminder {
     pub created_at: DateTime<Utc>,
     pub last_triggered_at: Option<DateTime<Utc>>,
     pub active: bool,
+    pub kind: ReminderKind,
+}
+
+/// What a reminder does when it fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReminderKind {
+    /// Posts its message in the chat.
+    Message,
+    /// Posts nothing: its note comes back to Claude as a system message, and
+    /// Claude decides whether to say anything (`follow_up`).
+    FollowUp,
+}
+
+impl ReminderKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReminderKind::Message => "message",
+            ReminderKind::FollowUp => "follow_up",
+        }
+    }
+
+    /// The kind stored as `s`; anything unknown posts, as reminders did before kinds.
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "follow_up" => ReminderKind::FollowUp,
+            _ => ReminderKind::Message,
+        }
+    }
+}
+
+/// Longest delay for `follow_up`.
+pub const MAX_FOLLOW_UP_DELAY: Duration = Duration::hours(48);
+
+/// Max pending follow-ups, across all chats.
+pub const MAX_FOLLOW_UPS: usize = 10;
+
+/// When a follow-up with `delay` ("+30m", "+2h", ...) is due, validating it.
+pub fn follow_up_time(delay: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let at = parse_trigger_time(delay)?;
+    // Whole minutes, since parse_trigger_time reads the clock a moment after `now`
+    if at <= now || (at - now).num_minutes() > MAX_FOLLOW_UP_DELAY.num_minutes() {
+        return Err(format!("Follow-up delay must be in the next {} hours, got '{}'", MAX_FOLLOW_UP_DELAY.num_hours(), delay));
+    }
+    Ok(at)
+}
+
+/// The system message a follow-up hands back to Claude.
+pub fn follow_up_message(note: &str) -> String {
+    format!("[follow-up] you asked to revisit: {}", note)
+}
+
+/// Longest delay for `send_later`.
+pub const MAX_SEND_LATER_DELAY: Duration = Duration::days(7);
+
+/// Max pending `send_later` messages per chat.
+pub const MAX_SCHEDULED_PER_CHAT: usize = 20;
+
+/// A one-off message to send later (`send_later`), stored in the database.
+#[derive(Debug, Clone)]
+pub struct ScheduledMessage {
+    pub id: i64,
+    pub chat_id: i64,
+    pub text: String,
+    pub reply_to_message_id: Option<i64>,
+    pub send_at: DateTime<Utc>,
+}
+
+/// When a `send_later` message with `delay_seconds` is due, validating the delay.
+pub fn send_later_time(delay_seconds: i64, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let delay = Duration::seconds(delay_seconds);
+    if delay_seconds < 1 || delay > MAX_SEND_LATER_DELAY {
+        return Err(format!(
+            "delay_seconds must be between 1 and {} (7 days), got {}",
+            MAX_SEND_LATER_DELAY.num_seconds(), delay_seconds
+        ));
+    }
+    Ok(now + delay)
 }
 
 /// Parse trigger time: "+30m", "+2h", "+1d" or absolute "2026-01-25 15:00"
@@ -75,6 +152,27 @@ pub fn next_cron_trigger(expr: &str, after: DateTime<Utc>) -> Result<DateTime<Ut
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_send_later_time() {
+        let now = Utc::now();
+        assert_eq!(send_later_time(90, now).unwrap(), now + Duration::seconds(90));
+        assert!(send_later_time(7 * 24 * 3600, now).is_ok());
+        assert!(send_later_time(7 * 24 * 3600 + 1, now).is_err());
+        assert!(send_later_time(0, now).is_err());
+        assert!(send_later_time(-5, now).is_err());
+    }
+
+    #[test]
+    fn test_follow_up_time() {
+        let now = Utc::now();
+        let at = follow_up_time("+2h", now).unwrap();
+        assert!((at - now - Duration::hours(2)).num_seconds().abs() <= 1);
+        assert!(follow_up_time("+48h", now).is_ok());
+        assert!(follow_up_time("+3d", now).is_err());
+        assert!(follow_up_time("2020-01-01 00:00", now).is_err());
+        assert!(follow_up_time("later", now).is_err());
+    }
+
     #[test]
     fn test_parse_relative_minutes() {
         let now = Utc::now();
@@ -0,0 +1,123 @@
+//! Which batch message a reply threads onto when Claude doesn't say.
+//!
+//! By default a reply goes onto the batch's last message, which is wrong
+//! when several people asked something within one debounce window: every
+//! answer would land under the last question. A reply that names one of
+//! the batch's senders, as an @mention or by starting with their name
+//! ("Alice, ..."), goes onto that sender's latest message instead.
+
+use super::message::ChatMessage;
+use super::names;
+
+/// Longest name prefix considered, in chars.
+const MAX_NAME_PREFIX: usize = 40;
+
+/// Comparable form of a name: normalized, without underscores or spaces,
+/// so "@John_Doe", "john doe" and "JohnDoe" all match.
+fn key(name: &str) -> String {
+    names::normalize(name).chars().filter(|c| *c != '_' && !c.is_whitespace()).collect()
+}
+
+/// The names `text` addresses: its @mentions, and the words before the
+/// first comma, colon, "!" or line break ("Alice: ...").
+fn addressed(text: &str) -> Vec<String> {
+    let mut keys: Vec<String> = text
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_'))
+        .filter(|name| !name.is_empty())
+        .map(key)
+        .collect();
+    if let Some(end) = text.find([',', ':', '!', '\n'])
+        && text[..end].chars().count() <= MAX_NAME_PREFIX
+    {
+        let prefix = text[..end].trim();
+        if !prefix.is_empty() && !prefix.starts_with('@') {
+            keys.push(key(prefix));
+        }
+    }
+    keys
+}
+
+/// Whether `key` names `sender`: their whole name, or its first word.
+fn names_sender(key: &str, sender: &str) -> bool {
+    let first = sender.split(|c: char| c == '_' || c.is_whitespace()).find(|w| !w.is_empty()).unwrap_or(sender);
+    !key.is_empty() && (key == self::key(sender) || key == self::key(first))
+}
+
+/// The message in `chat_id` a reply with `text` should thread onto: the
+/// latest message of the one sender it names. None if it names no sender
+/// of the batch, or several.
+pub fn choose_reply_target(text: &str, chat_id: i64, batch: &[ChatMessage]) -> Option<i64> {
+    let keys = addressed(text);
+    if keys.is_empty() {
+        return None;
+    }
+    // System messages have user_id 0
+    let named: Vec<&ChatMessage> = batch
+        .iter()
+        .filter(|m| m.chat_id == chat_id && m.user_id != 0)
+        .filter(|m| keys.iter().any(|k| names_sender(k, &m.username)))
+        .collect();
+    let last = named.last()?;
+    named.iter().all(|m| m.user_id == last.user_id).then_some(last.message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(message_id: i64, user_id: i64, username: &str) -> ChatMessage {
+        let mut msg = ChatMessage::system(format!("question {message_id}"));
+        (msg.message_id, msg.chat_id, msg.user_id, msg.username) = (message_id, -100, user_id, username.to_string());
+        msg
+    }
+
+    fn batch() -> Vec<ChatMessage> {
+        vec![msg(1, 10, "Alice Smith"), msg(2, 20, "bob_builder"), msg(3, 30, "Дмитрий"), msg(4, 40, "carol")]
+    }
+
+    #[test]
+    fn test_mention() {
+        let batch = batch();
+        assert_eq!(choose_reply_target("@bob_builder it's on Tuesday", -100, &batch), Some(2));
+        // Case, underscores and trailing punctuation don't matter
+        assert_eq!(choose_reply_target("it's on Tuesday, @Bob_Builder!", -100, &batch), Some(2));
+        assert_eq!(choose_reply_target("thanks @bobbuilder", -100, &batch), Some(2));
+        assert_eq!(choose_reply_target("@Carol: yes", -100, &batch), Some(4));
+    }
+
+    #[test]
+    fn test_name_prefix() {
+        let batch = batch();
+        assert_eq!(choose_reply_target("Alice, the meetup moved to Friday", -100, &batch), Some(1));
+        assert_eq!(choose_reply_target("alice smith: see the pinned message", -100, &batch), Some(1));
+        assert_eq!(choose_reply_target("Bob, no idea", -100, &batch), Some(2));
+        // Matched by transliteration too
+        assert_eq!(choose_reply_target("Dmitriy, da", -100, &batch), Some(3));
+        assert_eq!(choose_reply_target("Дмитрий, да", -100, &batch), Some(3));
+    }
+
+    #[test]
+    fn test_no_match_falls_back() {
+        let batch = batch();
+        assert_eq!(choose_reply_target("The meetup moved to Friday", -100, &batch), None);
+        assert_eq!(choose_reply_target("Sure, Alice", -100, &batch), None);
+        assert_eq!(choose_reply_target("@dave hi", -100, &batch), None);
+        assert_eq!(choose_reply_target("", -100, &batch), None);
+        // Only senders in the reply's chat count
+        assert_eq!(choose_reply_target("@carol yes", -200, &batch), None);
+        // Naming two senders is no better than the default
+        assert_eq!(choose_reply_target("@carol @bob_builder both right", -100, &batch), None);
+    }
+
+    #[test]
+    fn test_latest_message_of_sender() {
+        let mut batch = batch();
+        batch.push(msg(5, 10, "Alice Smith"));
+        batch.push(msg(6, 40, "carol"));
+        assert_eq!(choose_reply_target("Alice, yes", -100, &batch), Some(5));
+        assert_eq!(choose_reply_target("@alice yes", -100, &batch), Some(5));
+        assert_eq!(choose_reply_target("carol: both work", -100, &batch), Some(6));
+    }
+}
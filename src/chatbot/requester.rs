@@ -0,0 +1,118 @@
+//! Who asked for an action, for tools that check the requester.
+//!
+//! A turn answers a debounce batch, which can hold messages from several
+//! users in several chats, so the batch's last sender isn't necessarily who
+//! asked: the owner's DM request followed by a member's "lol" in a group
+//! would be checked against the member, and a member's request could pass
+//! on the strength of an unrelated owner DM. Only messages addressed to the
+//! bot (DMs and @mentions) name the requester:
+//!
+//! - one user addressed the bot: they asked, from their DM if they wrote
+//!   one, else from their last message;
+//! - several users did: the batch is ambiguous and checked tools refuse,
+//!   since any of them may have asked;
+//! - nobody did (replies in a group thread): the last sender asked.
+
+use super::message::ChatMessage;
+
+/// The requester resolved from a batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Requester {
+    User { user_id: i64, chat_id: i64 },
+    /// Several users addressed the bot.
+    Ambiguous,
+    /// Only system messages.
+    Unknown,
+}
+
+impl Requester {
+    pub fn resolve(batch: &[ChatMessage]) -> Self {
+        // System messages have user_id 0
+        let senders: Vec<&ChatMessage> = batch.iter().filter(|m| m.user_id != 0).collect();
+        let addressed: Vec<&ChatMessage> = senders.iter().copied().filter(|m| m.chat_id > 0 || m.mentions_bot).collect();
+        let Some(last) = addressed.last().or(senders.last()) else {
+            return Self::Unknown;
+        };
+        if addressed.iter().any(|m| m.user_id != last.user_id) {
+            return Self::Ambiguous;
+        }
+        let chat_id = addressed.iter().rev().find(|m| m.chat_id > 0).map_or(last.chat_id, |m| m.chat_id);
+        Self::User { user_id: last.user_id, chat_id }
+    }
+
+    pub fn user_id(self) -> Result<i64, String> {
+        match self {
+            Self::User { user_id, .. } => Ok(user_id),
+            Self::Ambiguous => Err("Several users addressed the bot in this batch, so it's unclear who asked. \
+                                    The requester should ask again on their own."
+                .to_string()),
+            Self::Unknown => Err("Cannot determine requesting user".to_string()),
+        }
+    }
+
+    /// The chat the request came from.
+    pub fn chat_id(self) -> Result<i64, String> {
+        match self {
+            Self::User { chat_id, .. } => Ok(chat_id),
+            _ => self.user_id(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: i64 = 1;
+    const MEMBER: i64 = 2;
+    const GROUP: i64 = -100;
+
+    fn message(user_id: i64, chat_id: i64, mentions_bot: bool) -> ChatMessage {
+        let mut msg = ChatMessage::system("hi".to_string());
+        msg.user_id = user_id;
+        msg.chat_id = chat_id;
+        msg.mentions_bot = mentions_bot;
+        msg
+    }
+
+    #[test]
+    fn test_group_chatter_after_owner_dm() {
+        // The owner asks in DM, a member chats in the group a moment later
+        let batch = [message(OWNER, OWNER, false), message(MEMBER, GROUP, false)];
+        assert_eq!(Requester::resolve(&batch), Requester::User { user_id: OWNER, chat_id: OWNER });
+    }
+
+    #[test]
+    fn test_member_request_beside_owner_dm() {
+        // A member asks the bot in the group while the owner DMs it, either order
+        let member_first = [message(MEMBER, GROUP, true), message(OWNER, OWNER, false)];
+        let owner_first = [message(OWNER, OWNER, false), message(MEMBER, GROUP, true)];
+        for batch in [member_first, owner_first] {
+            let requester = Requester::resolve(&batch);
+            assert_eq!(requester, Requester::Ambiguous);
+            assert!(requester.user_id().unwrap_err().starts_with("Several users addressed the bot"));
+        }
+
+        // Another member's DM is just as ambiguous
+        let batch = [message(OWNER, OWNER, false), message(MEMBER, MEMBER, false)];
+        assert_eq!(Requester::resolve(&batch), Requester::Ambiguous);
+    }
+
+    #[test]
+    fn test_single_requester() {
+        // A member's request isn't helped by an owner message not addressed to the bot
+        let batch = [message(OWNER, GROUP, false), message(MEMBER, GROUP, true)];
+        assert_eq!(Requester::resolve(&batch), Requester::User { user_id: MEMBER, chat_id: GROUP });
+
+        // The owner's DM wins over their own mention in a group
+        let batch = [message(OWNER, OWNER, false), message(OWNER, GROUP, true)];
+        assert_eq!(Requester::resolve(&batch).chat_id(), Ok(OWNER));
+
+        // Without messages addressed to the bot, the last sender asked
+        let batch = [message(MEMBER, GROUP, false), message(OWNER, GROUP, false), ChatMessage::system("notice".to_string())];
+        assert_eq!(Requester::resolve(&batch), Requester::User { user_id: OWNER, chat_id: GROUP });
+
+        let system_only = [ChatMessage::system("notice".to_string())];
+        assert_eq!(Requester::resolve(&system_only).user_id().unwrap_err(), "Cannot determine requesting user");
+    }
+}
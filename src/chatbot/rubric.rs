@@ -0,0 +1,222 @@
+//! Rubrics in the format the system prompt asks for, rendered as .docx.
+//!
+//! ```text
+//! 1. Category Name (X pts)
+//! Exemplary (4): What excellent work looks like
+//! Proficient (3): What good work looks like
+//! Basic (2): What acceptable work looks like
+//! Needs Improvement (1): What poor work looks like
+//! ```
+//!
+//! 3-6 categories, each with the four levels in that order. Spacing, blank
+//! lines and letter case don't matter; anything else is an error naming the
+//! line, so Claude can fix the text and call `send_rubric_docx` again.
+
+use super::docx;
+
+/// Performance levels every category has, best first.
+pub const LEVELS: [(&str, u32); 4] = [("Exemplary", 4), ("Proficient", 3), ("Basic", 2), ("Needs Improvement", 1)];
+
+/// Fewest and most categories a rubric may have.
+pub const MIN_CATEGORIES: usize = 3;
+pub const MAX_CATEGORIES: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub name: String,
+    pub points: u32,
+    /// One description per entry of `LEVELS`, in the same order.
+    pub levels: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rubric {
+    pub categories: Vec<Category>,
+}
+
+impl Rubric {
+    pub fn total_points(&self) -> u32 {
+        self.categories.iter().map(|c| c.points).sum()
+    }
+
+    /// Render as a .docx with a title and one table row per category.
+    pub fn to_docx(&self, title: &str) -> Result<Vec<u8>, String> {
+        let mut rows = vec![
+            std::iter::once("Category".to_string())
+                .chain(LEVELS.iter().map(|(name, score)| format!("{name} ({score})")))
+                .collect::<Vec<_>>(),
+        ];
+        rows.extend(self.categories.iter().map(|c| {
+            std::iter::once(format!("{} ({} pts)", c.name, c.points))
+                .chain(c.levels.iter().cloned())
+                .collect()
+        }));
+        docx::write_table(&format!("{} ({} pts total)", title, self.total_points()), &rows)
+    }
+}
+
+/// Collapse runs of whitespace to single spaces.
+fn squash(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// "1. Name (X pts)" → (name, points).
+fn parse_header(line: &str) -> Option<(String, u32)> {
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == line.len() {
+        return None;
+    }
+    let rest = rest.trim_start().strip_prefix('.')?;
+    let (name, points) = rest.trim_end().strip_suffix(')')?.rsplit_once('(')?;
+    let points = points.trim().to_lowercase();
+    let points = ["points", "pts", "pt"].iter().find_map(|unit| points.strip_suffix(unit))?;
+    let name = squash(name);
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, points.trim().parse().ok().filter(|&p| p > 0)?))
+}
+
+/// "Level (n): description" → description, if the label is `level`.
+fn parse_level(line: &str, level: (&str, u32)) -> Option<String> {
+    let (label, description) = line.split_once(':')?;
+    let (name, score) = label.trim().strip_suffix(')')?.rsplit_once('(')?;
+    if !squash(name).eq_ignore_ascii_case(level.0) || score.trim().parse() != Ok(level.1) {
+        return None;
+    }
+    Some(squash(description)).filter(|d| !d.is_empty())
+}
+
+/// Parse rubric text, or say which line breaks the format.
+pub fn parse(text: &str) -> Result<Rubric, String> {
+    let mut lines = text.lines().enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let mut categories = Vec::new();
+
+    while let Some((n, line)) = lines.next() {
+        let (name, points) = parse_header(line).ok_or_else(|| format!(
+            "Line {n}: expected a category like '{}. Category Name (X pts)', got '{line}'",
+            categories.len() + 1
+        ))?;
+        let mut levels = Vec::new();
+        for level in LEVELS {
+            let expected = format!("{} ({}): <description>", level.0, level.1);
+            let (n, line) = lines.next()
+                .ok_or_else(|| format!("Category '{name}' ends before '{expected}'"))?;
+            levels.push(parse_level(line, level)
+                .ok_or_else(|| format!("Line {n}: expected '{expected}' in category '{name}', got '{line}'"))?);
+        }
+        categories.push(Category { name, points, levels });
+    }
+
+    if !(MIN_CATEGORIES..=MAX_CATEGORIES).contains(&categories.len()) {
+        return Err(format!(
+            "A rubric needs {MIN_CATEGORIES}-{MAX_CATEGORIES} categories, got {}",
+            categories.len()
+        ));
+    }
+    Ok(Rubric { categories })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUBRIC: &str = "\
+1. Thesis (5 pts)
+Exemplary (4): Clear, arguable claim
+Proficient (3): Clear claim
+Basic (2): Vague claim
+Needs Improvement (1): No claim
+
+2. Evidence (10 pts)
+Exemplary (4): Strong & varied sources
+Proficient (3): Relevant sources
+Basic (2): Few sources
+Needs Improvement (1): No sources
+
+3. Style (5 pts)
+Exemplary (4): Engaging <voice>
+Proficient (3): Readable
+Basic (2): Awkward
+Needs Improvement (1): Hard to follow
+";
+
+    #[test]
+    fn test_parse() {
+        let rubric = parse(RUBRIC).unwrap();
+        assert_eq!(rubric.categories.len(), 3);
+        assert_eq!(rubric.total_points(), 20);
+        assert_eq!(rubric.categories[1], Category {
+            name: "Evidence".to_string(),
+            points: 10,
+            levels: vec![
+                "Strong & varied sources".to_string(),
+                "Relevant sources".to_string(),
+                "Few sources".to_string(),
+                "No sources".to_string(),
+            ],
+        });
+    }
+
+    #[test]
+    fn test_parse_tolerates_spacing_and_case() {
+        let messy = RUBRIC
+            .replace("1. Thesis (5 pts)", "  1 .  Thesis   Statement ( 5 PTS )  ")
+            .replace("Proficient (3): Clear claim", "proficient(3) :   Clear   claim")
+            .replace("\n\n", "\n\n\n");
+        let rubric = parse(&messy).unwrap();
+        assert_eq!(rubric.categories[0].name, "Thesis Statement");
+        assert_eq!(rubric.categories[0].points, 5);
+        assert_eq!(rubric.categories[0].levels[1], "Clear claim");
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = parse(&RUBRIC.replace("2. Evidence (10 pts)", "Evidence: 10 points")).unwrap_err();
+        assert!(err.starts_with("Line 7: expected a category like '2. Category Name (X pts)'"), "{err}");
+
+        let err = parse(&RUBRIC.replace("Basic (2): Few sources", "Developing (2): Few sources")).unwrap_err();
+        assert!(err.starts_with("Line 10: expected 'Basic (2): <description>' in category 'Evidence'"), "{err}");
+
+        let err = parse(&RUBRIC.replace("Proficient (3): Readable", "Proficient (3):")).unwrap_err();
+        assert!(err.starts_with("Line 15: expected 'Proficient (3)"), "{err}");
+
+        let err = parse(RUBRIC.trim_end().rsplit_once('\n').unwrap().0).unwrap_err();
+        assert_eq!(err, "Category 'Style' ends before 'Needs Improvement (1): <description>'");
+
+        let err = parse(&RUBRIC.replace("(5 pts)", "(0 pts)")).unwrap_err();
+        assert!(err.starts_with("Line 1:"), "{err}");
+    }
+
+    #[test]
+    fn test_category_count() {
+        let two: String = RUBRIC.split("\n\n").take(2).collect::<Vec<_>>().join("\n\n");
+        assert_eq!(parse(&two).unwrap_err(), "A rubric needs 3-6 categories, got 2");
+        let seven = (0..7).map(|_| RUBRIC.split("\n\n").next().unwrap()).collect::<Vec<_>>().join("\n\n");
+        assert_eq!(parse(&seven).unwrap_err(), "A rubric needs 3-6 categories, got 7");
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_docx_golden() {
+        let data = parse(RUBRIC).unwrap().to_docx("Essay Rubric").unwrap();
+
+        let text = docx::extract_text(&data).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), [
+            "Essay Rubric (20 pts total)",
+            "Category", "Exemplary (4)", "Proficient (3)", "Basic (2)", "Needs Improvement (1)",
+            "Thesis (5 pts)", "Clear, arguable claim", "Clear claim", "Vague claim", "No claim",
+            "Evidence (10 pts)", "Strong & varied sources", "Relevant sources", "Few sources", "No sources",
+            "Style (5 pts)", "Engaging <voice>", "Readable", "Awkward", "Hard to follow",
+        ]);
+
+        let xml = docx::document_xml(&data).unwrap();
+        assert_eq!(xml.matches("<w:tbl>").count(), 1);
+        assert_eq!(xml.matches("<w:tr>").count(), 4);
+        assert_eq!(xml.matches("<w:tc>").count(), 20);
+        assert!(xml.contains("Strong &amp; varied sources"));
+        assert!(xml.contains("Engaging &lt;voice&gt;"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Group rules, one memory file per chat: `memories/rules/<chat_id>.md`.
+//!
+//! The owner writes them with `/setrules`, or asks Claude to with the
+//! memory tools. Claude reads them with `get_rules` before moderating, and
+//! gets them with the result of a user's first mute or deletion, so the
+//! warning can cite the rule that was broken.
+
+use std::path::{Path, PathBuf};
+
+/// Where a chat's rules live, under the data directory.
+pub fn path(data_dir: &Path, chat_id: i64) -> PathBuf {
+    data_dir.join("memories").join("rules").join(format!("{}.md", chat_id))
+}
+
+/// The path as the memory tools name it, for messages.
+pub fn memory_path(chat_id: i64) -> String {
+    format!("rules/{}.md", chat_id)
+}
+
+/// A chat's rules, or None if it has none (or only whitespace).
+pub fn read(data_dir: &Path, chat_id: i64) -> Result<Option<String>, String> {
+    match std::fs::read_to_string(path(data_dir, chat_id)) {
+        Ok(text) if text.trim().is_empty() => Ok(None),
+        Ok(text) => Ok(Some(text.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read rules of chat {}: {}", chat_id, e)),
+    }
+}
+
+/// Replace a chat's rules.
+pub fn write(data_dir: &Path, chat_id: i64, text: &str) -> Result<(), String> {
+    let path = path(data_dir, chat_id);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create rules directory: {}", e))?;
+    }
+    std::fs::write(&path, format!("{}\n", text.trim()))
+        .map_err(|e| format!("Failed to write rules of chat {}: {}", chat_id, e))
+}
+
+/// Move chat `old`'s rules to `new` after it became a supergroup. Rules
+/// already saved for `new` are kept. Returns whether a file was moved.
+pub fn migrate(data_dir: &Path, old: i64, new: i64) -> Result<bool, String> {
+    let (from, to) = (path(data_dir, old), path(data_dir, new));
+    if !from.exists() || to.exists() {
+        return Ok(false);
+    }
+    std::fs::rename(&from, &to)
+        .map(|()| true)
+        .map_err(|e| format!("Failed to move rules of chat {} to {}: {}", old, new, e))
+}
+
+/// Split `/setrules` text into the group it names, if the first word is a
+/// group's (negative) chat ID, and the rules.
+pub fn parse_set_rules(text: &str) -> Result<(Option<i64>, &str), String> {
+    let text = text.trim();
+    let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    match first.parse::<i64>().ok().filter(|id| *id < 0) {
+        Some(_) if rest.trim().is_empty() => Err("Missing rules text".to_string()),
+        Some(chat_id) => Ok((Some(chat_id), rest.trim())),
+        None => Ok((None, text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_per_chat() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(read(dir.path(), -100).unwrap(), None);
+
+        write(dir.path(), -100, "1. Be kind\n2. No ads\n\n").unwrap();
+        write(dir.path(), -200, "English only").unwrap();
+        assert_eq!(read(dir.path(), -100).unwrap().as_deref(), Some("1. Be kind\n2. No ads"));
+        assert_eq!(read(dir.path(), -200).unwrap().as_deref(), Some("English only"));
+        assert!(dir.path().join("memories/rules/-100.md").exists());
+
+        // Rewritten, not appended; blank counts as none
+        write(dir.path(), -100, "No spam").unwrap();
+        assert_eq!(read(dir.path(), -100).unwrap().as_deref(), Some("No spam"));
+        write(dir.path(), -200, "  ").unwrap();
+        assert_eq!(read(dir.path(), -200).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_rules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(migrate(dir.path(), -100, -100100), Ok(false));
+
+        write(dir.path(), -100, "No spam").unwrap();
+        assert_eq!(migrate(dir.path(), -100, -100100), Ok(true));
+        assert_eq!(read(dir.path(), -100).unwrap(), None);
+        assert_eq!(read(dir.path(), -100100).unwrap().as_deref(), Some("No spam"));
+
+        // Rules the supergroup already has win
+        write(dir.path(), -200, "Old rules").unwrap();
+        write(dir.path(), -100200, "New rules").unwrap();
+        assert_eq!(migrate(dir.path(), -200, -100200), Ok(false));
+        assert_eq!(read(dir.path(), -100200).unwrap().as_deref(), Some("New rules"));
+    }
+
+    #[test]
+    fn test_parse_set_rules() {
+        assert_eq!(parse_set_rules("-100123 1. Be kind\n2. No ads"), Ok((Some(-100123), "1. Be kind\n2. No ads")));
+        assert_eq!(parse_set_rules("-100123\nNo spam"), Ok((Some(-100123), "No spam")));
+        assert_eq!(parse_set_rules("1. Be kind"), Ok((None, "1. Be kind")));
+        assert_eq!(parse_set_rules("3 strikes and you're out"), Ok((None, "3 strikes and you're out")));
+        assert_eq!(parse_set_rules("-100123"), Err("Missing rules text".to_string()));
+    }
+}
@@ -0,0 +1,296 @@
+//! Transcript of the Claude Code session, for debugging.
+//!
+//! Claude's input is assembled from many pieces (system prompt, restores,
+//! tool results), so when it misbehaves the logs alone can't say what it
+//! was shown. Everything sent to Claude Code and every assistant or result
+//! event it returns is appended as a JSON line to
+//! `data_dir/transcripts/session-<id>.jsonl`, one file per session. Secrets
+//! are redacted before anything is written, and the oldest files are deleted
+//! once the directory outgrows its cap.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Default cap on the transcripts directory.
+pub const DEFAULT_MAX_MB: u64 = 100;
+
+/// Characters kept per entry unless full transcripts are on.
+const PREVIEW_CHARS: usize = 300;
+
+/// Characters shown per entry by `/transcript tail`.
+const TAIL_PREVIEW_CHARS: usize = 120;
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Sent to Claude Code.
+    Out,
+    /// Received from Claude Code.
+    In,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    direction: Direction,
+    timestamp: String,
+    bytes: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+/// Replace every occurrence of a secret in `text`.
+fn redact(text: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(text.to_string(), |text, secret| text.replace(secret.as_str(), REDACTED))
+}
+
+/// Writes the transcript of the current session.
+pub struct SessionTranscript {
+    dir: PathBuf,
+    full: bool,
+    max_bytes: u64,
+    secrets: Vec<String>,
+    /// The current session's file, once its ID is known.
+    path: Option<PathBuf>,
+    /// Lines recorded before the session ID was known.
+    pending: Vec<String>,
+    /// Bytes in `dir` as of the last prune, plus what's been written since.
+    total: u64,
+}
+
+impl SessionTranscript {
+    /// A transcript in `dir` keeping full text or previews, capped at
+    /// `max_bytes`, with `secrets` redacted.
+    pub fn new(dir: PathBuf, full: bool, max_bytes: u64, secrets: Vec<String>) -> Self {
+        let secrets = secrets.into_iter().filter(|s| !s.is_empty()).collect();
+        Self { dir, full, max_bytes, secrets, path: None, pending: Vec::new(), total: 0 }
+    }
+
+    /// A new Claude Code process is starting, resuming `session_id` if given.
+    /// Without one, entries wait until `set_session_id`.
+    pub fn new_session(&mut self, session_id: Option<&str>) {
+        self.path = None;
+        self.pending.clear();
+        if let Some(id) = session_id {
+            self.set_session_id(id);
+        }
+    }
+
+    /// Switch to the file of `session_id`, writing out anything recorded
+    /// before it was known.
+    pub fn set_session_id(&mut self, session_id: &str) {
+        let path = self.dir.join(format!("session-{}.jsonl", session_id));
+        if self.path.as_ref() == Some(&path) {
+            return;
+        }
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            warn!("Failed to create {}: {}", self.dir.display(), e);
+        }
+        info!("📜 Session transcript: {}", path.display());
+        self.path = Some(path);
+        for line in std::mem::take(&mut self.pending) {
+            self.append(&line);
+        }
+        self.prune();
+    }
+
+    /// Record `text` going in `direction`.
+    pub fn record(&mut self, direction: Direction, text: &str) {
+        let text = redact(text, &self.secrets);
+        let bytes = text.len();
+        let (preview, text) = if self.full {
+            (None, Some(text))
+        } else {
+            (Some(text.chars().take(PREVIEW_CHARS).collect()), None)
+        };
+        let entry = Entry { direction, timestamp: Utc::now().to_rfc3339(), bytes, preview, text };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize transcript entry: {}", e);
+                return;
+            }
+        };
+        if self.path.is_some() {
+            self.append(&line);
+            if self.total > self.max_bytes {
+                self.prune();
+            }
+        } else {
+            self.pending.push(line);
+        }
+    }
+
+    fn append(&mut self, line: &str) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        match written {
+            Ok(()) => self.total += line.len() as u64 + 1,
+            Err(e) => warn!("Failed to write transcript {}: {}", path.display(), e),
+        }
+    }
+
+    fn prune(&mut self) {
+        match prune(&self.dir, self.max_bytes, self.path.as_deref()) {
+            Ok(total) => self.total = total,
+            Err(e) => warn!("Failed to prune transcripts: {}", e),
+        }
+    }
+}
+
+/// Transcript files in `dir` with their size, oldest first.
+fn transcript_files(dir: &Path) -> Result<Vec<(PathBuf, u64)>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to list {}: {e}", dir.display())),
+    };
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("session-") && name.ends_with(".jsonl")
+        })
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            Some((entry.path(), meta.len(), meta.modified().ok()?))
+        })
+        .collect();
+    files.sort_by_key(|(_, _, modified)| *modified);
+    Ok(files.into_iter().map(|(path, len, _)| (path, len)).collect())
+}
+
+/// Delete the oldest transcripts in `dir` until it holds at most
+/// `max_bytes`, never `current`. Returns the bytes left.
+fn prune(dir: &Path, max_bytes: u64, current: Option<&Path>) -> Result<u64, String> {
+    let files = transcript_files(dir)?;
+    let mut total: u64 = files.iter().map(|(_, len)| len).sum();
+    for (path, len) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if Some(path.as_path()) == current {
+            continue;
+        }
+        fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+        info!("🗑️ Removed old transcript {}", path.display());
+        total -= len;
+    }
+    Ok(total)
+}
+
+/// The last `n` entries of the newest transcript in `dir`, for the owner.
+pub fn tail(dir: &Path, n: usize) -> Result<String, String> {
+    let Some((path, _)) = transcript_files(dir)?.pop() else {
+        return Ok("No transcript yet.".to_string());
+    };
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let lines: Vec<&str> = content.lines().collect();
+    let mut out = vec![path.file_name().unwrap_or_default().to_string_lossy().into_owned()];
+    for line in &lines[lines.len().saturating_sub(n)..] {
+        let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+            out.push("(unreadable entry)".to_string());
+            continue;
+        };
+        let arrow = match entry.direction {
+            Direction::Out => "→",
+            Direction::In => "←",
+        };
+        let time = entry.timestamp.get(11..19).unwrap_or(&entry.timestamp);
+        let text = entry.text.or(entry.preview).unwrap_or_default();
+        let preview: String = text.chars().take(TAIL_PREVIEW_CHARS).collect();
+        out.push(format!("{} {} {}B: {}", time, arrow, entry.bytes, preview.replace('\n', " ")));
+    }
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TOKEN: &str = "123456789:ABCdefGHIjklMNOpqrsTUVwxyz";
+
+    #[test]
+    fn test_redaction() {
+        let secrets = vec![TOKEN.to_string(), "sk-or-secret".to_string()];
+        let text = format!("token {} and key sk-or-secret, token again {}", TOKEN, TOKEN);
+        assert_eq!(redact(&text, &secrets), "token [REDACTED] and key [REDACTED], token again [REDACTED]");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut transcript = SessionTranscript::new(dir.path().to_path_buf(), true, 1 << 20, vec![TOKEN.to_string(), String::new()]);
+        transcript.new_session(Some("abc"));
+        transcript.record(Direction::Out, &format!("the token is {}", TOKEN));
+        let written = fs::read_to_string(dir.path().join("session-abc.jsonl")).unwrap();
+        assert!(!written.contains(TOKEN));
+        assert!(written.contains("the token is [REDACTED]"));
+    }
+
+    #[test]
+    fn test_entries_wait_for_session_id() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut transcript = SessionTranscript::new(dir.path().to_path_buf(), false, 1 << 20, Vec::new());
+        transcript.new_session(None);
+        transcript.record(Direction::Out, &"x".repeat(1000));
+        assert!(transcript_files(dir.path()).unwrap().is_empty());
+
+        transcript.set_session_id("first");
+        transcript.record(Direction::In, "{\"tool_calls\":[]}");
+        let written = fs::read_to_string(dir.path().join("session-first.jsonl")).unwrap();
+        let entries: Vec<Entry> = written.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Out);
+        assert_eq!(entries[0].bytes, 1000);
+        assert_eq!(entries[0].preview.as_ref().unwrap().len(), PREVIEW_CHARS);
+        assert!(entries[0].text.is_none());
+        assert_eq!(entries[1].direction, Direction::In);
+
+        // A new session gets its own file
+        fs::File::options().write(true).open(dir.path().join("session-first.jsonl")).unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60)).unwrap();
+        transcript.set_session_id("second");
+        transcript.record(Direction::Out, "hello");
+        assert_eq!(transcript_files(dir.path()).unwrap().len(), 2);
+        let tail = tail(dir.path(), 20).unwrap();
+        assert!(tail.starts_with("session-second.jsonl\n"));
+        assert!(tail.ends_with("→ 5B: hello"));
+    }
+
+    #[test]
+    fn test_prune_removes_oldest_but_not_current() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let start = SystemTime::now() - Duration::from_secs(3600);
+        for (i, name) in ["session-a.jsonl", "session-b.jsonl", "session-c.jsonl", "notes.txt"].iter().enumerate() {
+            let path = dir.path().join(name);
+            fs::write(&path, vec![b'x'; 100]).unwrap();
+            fs::File::options().write(true).open(&path).unwrap()
+                .set_modified(start + Duration::from_secs(60 * i as u64)).unwrap();
+        }
+
+        assert_eq!(prune(dir.path(), 250, None).unwrap(), 200);
+        assert!(!dir.path().join("session-a.jsonl").exists());
+        assert!(dir.path().join("session-b.jsonl").exists());
+        assert!(dir.path().join("notes.txt").exists());
+
+        // The current file is kept even when it's the oldest
+        let current = dir.path().join("session-b.jsonl");
+        assert_eq!(prune(dir.path(), 50, Some(&current)).unwrap(), 100);
+        assert!(current.exists());
+        assert!(!dir.path().join("session-c.jsonl").exists());
+    }
+}
@@ -1,13 +1,23 @@
 //! Telegram client using teloxide.
 
+use std::future::Future;
+use std::pin::Pin;
 use std::time::Duration;
 
 use teloxide::net::Download;
 use teloxide::prelude::*;
-use teloxide::types::{ChatPermissions, FileId, InputFile, MessageId, ParseMode, ReactionType, ReplyParameters};
+use teloxide::types::{ChatMember, ChatPermissions, Dice, DiceEmoji, FileId, InputFile, MessageId, ParseMode, PhotoSize, ReactionType, ReplyParameters};
 use tracing::{info, warn};
 
+use super::chat_settings::PermissionFlags;
+use super::image_limits;
+use super::impersonation::AdminIdentity;
+use super::message::Location;
+use super::metrics;
+use super::ttl_cache::TtlCache;
+
 /// User info from Telegram.
+#[derive(Clone)]
 pub struct ChatMemberInfo {
     pub user_id: i64,
     pub username: Option<String>,
@@ -20,11 +30,149 @@ pub struct ChatMemberInfo {
     pub custom_title: Option<String>,
 }
 
+/// Dice emojis Telegram can send.
+const DICE_EMOJIS: &[(DiceEmoji, &str)] = &[
+    (DiceEmoji::Dice, "🎲"),
+    (DiceEmoji::Darts, "🎯"),
+    (DiceEmoji::Bowling, "🎳"),
+    (DiceEmoji::Basketball, "🏀"),
+    (DiceEmoji::Football, "⚽"),
+    (DiceEmoji::SlotMachine, "🎰"),
+];
+
+/// Parse a dice emoji, rejecting ones Telegram doesn't support.
+pub fn parse_dice_emoji(emoji: &str) -> Result<DiceEmoji, String> {
+    // Tolerate a trailing variation selector ("⚽️")
+    let emoji = emoji.trim().trim_end_matches('\u{fe0f}');
+    DICE_EMOJIS
+        .iter()
+        .find(|(_, symbol)| *symbol == emoji)
+        .map(|(kind, _)| *kind)
+        .ok_or_else(|| {
+            let supported: Vec<&str> = DICE_EMOJIS.iter().map(|(_, s)| *s).collect();
+            format!("Unsupported dice emoji '{}'. Use one of: {}", emoji, supported.join(" "))
+        })
+}
+
+fn dice_symbol(kind: DiceEmoji) -> &'static str {
+    DICE_EMOJIS.iter().find(|(k, _)| *k == kind).map(|(_, s)| *s).unwrap_or("🎲")
+}
+
+/// Text standing in for a dice message, e.g. "[dice 🎲 rolled 5]".
+pub fn dice_text(dice: &Dice) -> String {
+    format!("[dice {} rolled {}]", dice_symbol(dice.emoji), dice.value)
+}
+
+/// The location or venue shared in a message, if any.
+pub fn message_location(msg: &Message) -> Option<Location> {
+    if let Some(venue) = msg.venue() {
+        return Some(Location {
+            latitude: venue.location.latitude,
+            longitude: venue.location.longitude,
+            title: Some(venue.title.clone()).filter(|t| !t.is_empty()),
+            address: Some(venue.address.clone()).filter(|a| !a.is_empty()),
+        });
+    }
+    msg.location().map(|location| Location {
+        latitude: location.latitude,
+        longitude: location.longitude,
+        title: None,
+        address: None,
+    })
+}
+
+/// A pending Telegram call.
+pub type ApiFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+/// Telegram's error for a reply to a message that no longer exists.
+pub const REPLY_NOT_FOUND: &str = "message to be replied not found";
+
+/// A message sent by `send_reply`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SentReply {
+    pub message_id: i64,
+    /// The reply target was gone, so it went out as a plain message.
+    pub reply_dropped: bool,
+}
+
+/// The Telegram calls the engine makes, so tools can run against a mock.
+pub trait TelegramApi: Send + Sync {
+    fn send_message<'a>(&'a self, chat_id: i64, text: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64>;
+    /// `send_message`, sent without the reply if the message it replies to
+    /// is gone (deleted, or removed by the chat's auto-delete timer).
+    fn send_reply<'a>(&'a self, chat_id: i64, text: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, SentReply> {
+        Box::pin(async move {
+            match self.send_message(chat_id, text, reply_to_message_id).await {
+                Ok(message_id) => Ok(SentReply { message_id, reply_dropped: false }),
+                Err(e) if reply_to_message_id.is_some() && e.contains(REPLY_NOT_FOUND) => {
+                    warn!("Reply target {:?} in {} is gone, sending without the reply", reply_to_message_id, chat_id);
+                    let message_id = self.send_message(chat_id, text, None).await?;
+                    Ok(SentReply { message_id, reply_dropped: true })
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+    /// A chat member, cached for 10 minutes.
+    fn get_chat_member(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ChatMemberInfo>;
+    /// A user's profile photo within `max_bytes`, cached for 6 hours.
+    fn get_profile_photo(&self, user_id: i64, max_bytes: u64) -> ApiFuture<'_, Option<Vec<u8>>>;
+    fn set_message_reaction<'a>(&'a self, chat_id: i64, message_id: i64, emoji: &'a str) -> ApiFuture<'a, ()>;
+    /// Returns the message ID and the rolled value.
+    fn send_dice(&self, chat_id: i64, emoji: DiceEmoji, reply_to_message_id: Option<i64>) -> ApiFuture<'_, (i64, u8)>;
+    /// A venue if the location has a title, else a plain pin. Returns the message ID.
+    fn send_location<'a>(&'a self, chat_id: i64, location: &'a Location, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64>;
+    fn delete_message(&self, chat_id: i64, message_id: i64) -> ApiFuture<'_, ()>;
+    fn mute_user(&self, chat_id: i64, user_id: i64, until: chrono::DateTime<chrono::Utc>) -> ApiFuture<'_, ()>;
+    fn unmute_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()>;
+    fn ban_user(&self, chat_id: i64, user_id: i64, revoke_messages: bool) -> ApiFuture<'_, ()>;
+    fn kick_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()>;
+    /// The chat's admins as a JSON array.
+    fn get_chat_admins(&self, chat_id: i64) -> ApiFuture<'_, String>;
+    /// The chat's human admins, for impersonation checks.
+    fn cached_admin_identities(&self, chat_id: i64) -> ApiFuture<'_, Vec<AdminIdentity>>;
+    fn create_chat_invite_link<'a>(
+        &'a self,
+        chat_id: i64,
+        expire_at: Option<chrono::DateTime<chrono::Utc>>,
+        member_limit: Option<u32>,
+        name: Option<&'a str>,
+    ) -> ApiFuture<'a, String>;
+    fn revoke_chat_invite_link<'a>(&'a self, chat_id: i64, invite_link: &'a str) -> ApiFuture<'a, ()>;
+    /// Change members' default permissions, keeping switches `flags` leaves
+    /// out. Returns the permissions now in effect.
+    fn set_chat_permissions<'a>(&'a self, chat_id: i64, flags: &'a PermissionFlags) -> ApiFuture<'a, ChatPermissions>;
+    fn send_image<'a>(&'a self, chat_id: i64, image_data: Vec<u8>, caption: Option<&'a str>, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64>;
+    fn send_document<'a>(&'a self, chat_id: i64, data: Vec<u8>, filename: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64>;
+    fn send_voice<'a>(&'a self, chat_id: i64, voice_data: Vec<u8>, caption: Option<&'a str>, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64>;
+    /// A photo at the largest size within `max_bytes`, as (bytes, media_type).
+    fn download_photo<'a>(&'a self, sizes: &'a [PhotoSize], max_bytes: u64) -> ApiFuture<'a, (Vec<u8>, String)>;
+    fn get_chat_username(&self, user_id: i64) -> ApiFuture<'_, Option<String>>;
+    /// Drop cached lookups a `chat_member` update made stale.
+    fn invalidate_member(&self, chat_id: i64, user_id: i64, admin_changed: bool);
+    /// Hit and miss counts of the lookup caches, for `/status`.
+    fn cache_summary(&self) -> String;
+}
+
 /// Telegram API client.
 pub struct TelegramClient {
     bot: Bot,
+    /// get_chat_member results by (chat_id, user_id).
+    member_cache: TtlCache<(i64, i64), ChatMemberInfo>,
+    /// Admin lists by chat, for get_chat_admins and impersonation checks.
+    admin_cache: TtlCache<i64, Vec<ChatMember>>,
+    /// Profile photos by (user_id, max_bytes); None when the user has none.
+    photo_cache: TtlCache<(i64, u64), Option<Vec<u8>>>,
 }
 
+const MEMBER_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+const MEMBER_CACHE_MAX_ENTRIES: usize = 5000;
+const ADMIN_CACHE_TTL: Duration = Duration::from_secs(30 * 60);
+const ADMIN_CACHE_MAX_ENTRIES: usize = 500;
+const PHOTO_CACHE_TTL: Duration = Duration::from_secs(6 * 3600);
+/// Total size of cached profile photos.
+const PHOTO_CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
 /// Max retries for transient failures
 const MAX_RETRIES: u32 = 3;
 /// Base delay for exponential backoff (ms)
@@ -32,7 +180,33 @@ const RETRY_BASE_DELAY_MS: u64 = 500;
 
 impl TelegramClient {
     pub fn new(bot: Bot) -> Self {
-        Self { bot }
+        Self {
+            bot,
+            member_cache: TtlCache::new(MEMBER_CACHE_TTL, MEMBER_CACHE_MAX_ENTRIES),
+            admin_cache: TtlCache::new(ADMIN_CACHE_TTL, ADMIN_CACHE_MAX_ENTRIES),
+            photo_cache: TtlCache::weighted(PHOTO_CACHE_TTL, PHOTO_CACHE_MAX_BYTES, |photo| {
+                photo.as_ref().map_or(1, Vec::len)
+            }),
+        }
+    }
+
+    /// Drop cached lookups a `chat_member` update made stale: the member
+    /// itself, and the chat's admin list if they were or became an admin.
+    fn invalidate_member(&self, chat_id: i64, user_id: i64, admin_changed: bool) {
+        self.member_cache.invalidate(|&key| key == (chat_id, user_id));
+        if admin_changed {
+            self.admin_cache.invalidate(|&chat| chat == chat_id);
+        }
+    }
+
+    /// Hit and miss counts of the lookup caches, for `/status`.
+    fn cache_summary(&self) -> String {
+        format!(
+            "members {}; admins {}; photos {}",
+            self.member_cache.stats(),
+            self.admin_cache.stats(),
+            self.photo_cache.stats()
+        )
     }
 
     /// Check if an error is retryable (transient)
@@ -49,14 +223,13 @@ impl TelegramClient {
         }
     }
 
-    pub async fn send_message(
+    async fn send_message(
         &self,
         chat_id: i64,
         text: &str,
         reply_to_message_id: Option<i64>,
     ) -> Result<i64, String> {
         let chat_id_obj = ChatId(chat_id);
-        let mut current_reply_to = reply_to_message_id;
 
         for attempt in 0..=MAX_RETRIES {
             let mut request = self
@@ -64,23 +237,15 @@ impl TelegramClient {
                 .send_message(chat_id_obj, text)
                 .parse_mode(ParseMode::Html);
 
-            if let Some(msg_id) = current_reply_to {
+            if let Some(msg_id) = reply_to_message_id {
                 let reply_params = ReplyParameters::new(MessageId(msg_id as i32));
                 request = request.reply_parameters(reply_params);
             }
 
+            // A missing reply target is left to `send_reply`, which says so
             match request.await {
                 Ok(msg) => return Ok(msg.id.0 as i64),
                 Err(e) => {
-                    let err_str = format!("{e}");
-
-                    // If reply message not found, retry without reply_to
-                    if err_str.contains("message to be replied not found") && current_reply_to.is_some() {
-                        warn!("Reply target not found, retrying without reply_to");
-                        current_reply_to = None;
-                        continue;
-                    }
-
                     if attempt < MAX_RETRIES && Self::is_retryable_error(&e) {
                         let delay = RETRY_BASE_DELAY_MS * 2u64.pow(attempt);
                         warn!("Send failed (attempt {}), retrying in {}ms: {}", attempt + 1, delay, e);
@@ -89,6 +254,7 @@ impl TelegramClient {
                     }
                     let msg = format!("Failed to send: {e}");
                     warn!("{}", msg);
+                    metrics::inc(&metrics::TELEGRAM_SEND_ERRORS, &[]);
                     return Err(msg);
                 }
             }
@@ -96,7 +262,18 @@ impl TelegramClient {
         unreachable!()
     }
 
-    pub async fn get_chat_member(
+    /// A chat member, cached for 10 minutes.
+    async fn get_chat_member(
+        &self,
+        chat_id: i64,
+        user_id: i64,
+    ) -> Result<ChatMemberInfo, String> {
+        self.member_cache
+            .get_or_fetch((chat_id, user_id), || self.fetch_chat_member(chat_id, user_id))
+            .await
+    }
+
+    async fn fetch_chat_member(
         &self,
         chat_id: i64,
         user_id: i64,
@@ -138,8 +315,15 @@ impl TelegramClient {
         })
     }
 
-    /// Get user's profile photo as bytes.
-    pub async fn get_profile_photo(&self, user_id: i64) -> Result<Option<Vec<u8>>, String> {
+    /// Get user's profile photo as bytes, at the largest size within the image
+    /// limits. Cached for 6 hours.
+    async fn get_profile_photo(&self, user_id: i64, max_bytes: u64) -> Result<Option<Vec<u8>>, String> {
+        self.photo_cache
+            .get_or_fetch((user_id, max_bytes), || self.fetch_profile_photo(user_id, max_bytes))
+            .await
+    }
+
+    async fn fetch_profile_photo(&self, user_id: i64, max_bytes: u64) -> Result<Option<Vec<u8>>, String> {
         info!("Getting profile photo for user {}", user_id);
         let user_id = UserId(user_id as u64);
 
@@ -154,26 +338,14 @@ impl TelegramClient {
             return Ok(None);
         }
 
-        // Get the smallest photo (first in the array, usually 160x160)
-        let photo_sizes = &photos.photos[0];
-        if photo_sizes.is_empty() {
+        let Some(photo) = image_limits::pick_photo_size(&photos.photos[0], max_bytes) else {
             return Ok(None);
-        }
-
-        // Get the largest available size for better quality
-        let photo = photo_sizes.last().unwrap();
-        let file = self.bot.get_file(photo.file.id.clone()).await
-            .map_err(|e| format!("Failed to get photo file: {e}"))?;
-
-        let mut data = Vec::new();
-        self.bot.download_file(&file.path, &mut data).await
-            .map_err(|e| format!("Failed to download photo: {e}"))?;
-
-        info!("Downloaded profile photo ({} bytes)", data.len());
+        };
+        let (data, _) = self.download_image(&photo.file.id.0, max_bytes).await?;
         Ok(Some(data))
     }
 
-    pub async fn set_message_reaction(
+    async fn set_message_reaction(
         &self,
         chat_id: i64,
         message_id: i64,
@@ -200,8 +372,71 @@ impl TelegramClient {
         Ok(())
     }
 
+    /// Send an animated dice. Returns (message_id, rolled value).
+    async fn send_dice(
+        &self,
+        chat_id: i64,
+        emoji: DiceEmoji,
+        reply_to_message_id: Option<i64>,
+    ) -> Result<(i64, u8), String> {
+        info!("{} Rolling dice in chat {}", dice_symbol(emoji), chat_id);
+
+        let mut request = self.bot.send_dice(ChatId(chat_id)).emoji(emoji);
+        if let Some(msg_id) = reply_to_message_id {
+            request = request.reply_parameters(ReplyParameters::new(MessageId(msg_id as i32)));
+        }
+
+        let msg = request.await.map_err(|e| {
+            let msg = format!("Failed to send dice: {e}");
+            warn!("{}", msg);
+            msg
+        })?;
+        let value = msg.dice().map(|d| d.value).ok_or("Telegram returned no dice value")?;
+
+        Ok((msg.id.0 as i64, value))
+    }
+
+    /// Share a location: sendVenue when it has a title, sendLocation
+    /// otherwise. Returns the message ID.
+    async fn send_location(
+        &self,
+        chat_id: i64,
+        location: &Location,
+        reply_to_message_id: Option<i64>,
+    ) -> Result<i64, String> {
+        info!("📍 Sharing location {},{} in chat {}", location.latitude, location.longitude, chat_id);
+
+        let reply = reply_to_message_id.map(|msg_id| ReplyParameters::new(MessageId(msg_id as i32)));
+        let sent = match &location.title {
+            Some(title) => {
+                // Venues need an address; the coordinates stand in for a missing one
+                let address = location.address.clone()
+                    .unwrap_or_else(|| format!("{}, {}", location.latitude, location.longitude));
+                let mut request = self.bot.send_venue(ChatId(chat_id), location.latitude, location.longitude, title, address);
+                if let Some(reply) = reply {
+                    request = request.reply_parameters(reply);
+                }
+                request.await
+            }
+            None => {
+                let mut request = self.bot.send_location(ChatId(chat_id), location.latitude, location.longitude);
+                if let Some(reply) = reply {
+                    request = request.reply_parameters(reply);
+                }
+                request.await
+            }
+        };
+        let msg = sent.map_err(|e| {
+            let msg = format!("Failed to send location: {e}");
+            warn!("{}", msg);
+            msg
+        })?;
+
+        Ok(msg.id.0 as i64)
+    }
+
     /// Delete a message.
-    pub async fn delete_message(&self, chat_id: i64, message_id: i64) -> Result<(), String> {
+    async fn delete_message(&self, chat_id: i64, message_id: i64) -> Result<(), String> {
         info!("🗑️ Deleting message {} in chat {}", message_id, chat_id);
 
         self.bot
@@ -217,15 +452,13 @@ impl TelegramClient {
     }
 
     /// Mute a user temporarily.
-    pub async fn mute_user(
+    async fn mute_user(
         &self,
         chat_id: i64,
         user_id: i64,
-        duration_minutes: i64,
+        until: chrono::DateTime<chrono::Utc>,
     ) -> Result<(), String> {
-        info!("🔇 Muting user {} in chat {} for {} minutes", user_id, chat_id, duration_minutes);
-
-        let until = chrono::Utc::now() + Duration::from_secs((duration_minutes * 60) as u64);
+        info!("🔇 Muting user {} in chat {} until {}", user_id, chat_id, until.format("%Y-%m-%d %H:%M UTC"));
 
         // Remove all permissions (mute)
         let permissions = ChatPermissions::empty();
@@ -243,12 +476,29 @@ impl TelegramClient {
         Ok(())
     }
 
-    /// Ban a user permanently.
-    pub async fn ban_user(&self, chat_id: i64, user_id: i64) -> Result<(), String> {
-        info!("🚫 Banning user {} from chat {}", user_id, chat_id);
+    /// Lift a mute by restoring all member permissions.
+    async fn unmute_user(&self, chat_id: i64, user_id: i64) -> Result<(), String> {
+        info!("🔊 Unmuting user {} in chat {}", user_id, chat_id);
+
+        self.bot
+            .restrict_chat_member(ChatId(chat_id), UserId(user_id as u64), ChatPermissions::all())
+            .await
+            .map_err(|e| {
+                let msg = format!("Failed to unmute user: {e}");
+                warn!("{}", msg);
+                msg
+            })?;
+
+        Ok(())
+    }
+
+    /// Ban a user permanently, optionally deleting all their messages in the chat.
+    async fn ban_user(&self, chat_id: i64, user_id: i64, revoke_messages: bool) -> Result<(), String> {
+        info!("🚫 Banning user {} from chat {} (revoke messages: {})", user_id, chat_id, revoke_messages);
 
         self.bot
             .ban_chat_member(ChatId(chat_id), UserId(user_id as u64))
+            .revoke_messages(revoke_messages)
             .await
             .map_err(|e| {
                 let msg = format!("Failed to ban user: {e}");
@@ -260,7 +510,7 @@ impl TelegramClient {
     }
 
     /// Kick a user (ban + immediate unban so they can rejoin).
-    pub async fn kick_user(&self, chat_id: i64, user_id: i64) -> Result<(), String> {
+    async fn kick_user(&self, chat_id: i64, user_id: i64) -> Result<(), String> {
         info!("👢 Kicking user {} from chat {}", user_id, chat_id);
 
         // Ban first
@@ -286,19 +536,23 @@ impl TelegramClient {
         Ok(())
     }
 
-    /// Get list of chat administrators.
-    pub async fn get_chat_admins(&self, chat_id: i64) -> Result<String, String> {
-        info!("👥 Getting admins for chat {}", chat_id);
-
-        let admins = self
-            .bot
-            .get_chat_administrators(ChatId(chat_id))
+    /// Administrators of a chat, cached for 30 minutes.
+    async fn chat_administrators(&self, chat_id: i64) -> Result<Vec<ChatMember>, String> {
+        self.admin_cache
+            .get_or_fetch(chat_id, || async {
+                info!("👥 Getting admins for chat {}", chat_id);
+                self.bot.get_chat_administrators(ChatId(chat_id)).await.map_err(|e| {
+                    let msg = format!("Failed to get chat admins: {e}");
+                    warn!("{}", msg);
+                    msg
+                })
+            })
             .await
-            .map_err(|e| {
-                let msg = format!("Failed to get chat admins: {e}");
-                warn!("{}", msg);
-                msg
-            })?;
+    }
+
+    /// Get list of chat administrators.
+    async fn get_chat_admins(&self, chat_id: i64) -> Result<String, String> {
+        let admins = self.chat_administrators(chat_id).await?;
 
         let admin_list: Vec<serde_json::Value> = admins
             .iter()
@@ -315,8 +569,93 @@ impl TelegramClient {
         Ok(serde_json::to_string(&admin_list).unwrap_or_else(|_| "[]".to_string()))
     }
 
+    /// Admins of a chat, for impersonation checks.
+    async fn cached_admin_identities(&self, chat_id: i64) -> Result<Vec<AdminIdentity>, String> {
+        let admins = self.chat_administrators(chat_id).await?;
+
+        Ok(admins
+            .iter()
+            .filter(|m| !m.user.is_bot)
+            .map(|m| AdminIdentity {
+                user_id: m.user.id.0 as i64,
+                first_name: m.user.first_name.clone(),
+                username: m.user.username.clone(),
+            })
+            .collect())
+    }
+
+    /// Create an additional invite link. Returns the link.
+    async fn create_chat_invite_link(
+        &self,
+        chat_id: i64,
+        expire_at: Option<chrono::DateTime<chrono::Utc>>,
+        member_limit: Option<u32>,
+        name: Option<&str>,
+    ) -> Result<String, String> {
+        info!("🔗 Creating invite link for chat {}", chat_id);
+
+        let mut request = self.bot.create_chat_invite_link(ChatId(chat_id));
+        if let Some(expire_at) = expire_at {
+            request = request.expire_date(expire_at);
+        }
+        if let Some(limit) = member_limit {
+            request = request.member_limit(limit);
+        }
+        if let Some(name) = name {
+            request = request.name(name);
+        }
+
+        let link = request.await.map_err(|e| {
+            let msg = format!("Failed to create invite link: {e}");
+            warn!("{}", msg);
+            msg
+        })?;
+
+        Ok(link.invite_link)
+    }
+
+    /// Revoke an invite link created by the bot.
+    async fn revoke_chat_invite_link(&self, chat_id: i64, invite_link: &str) -> Result<(), String> {
+        info!("🔗 Revoking invite link {} in chat {}", invite_link, chat_id);
+
+        self.bot
+            .revoke_chat_invite_link(ChatId(chat_id), invite_link)
+            .await
+            .map_err(|e| {
+                let msg = format!("Failed to revoke invite link: {e}");
+                warn!("{}", msg);
+                msg
+            })?;
+
+        Ok(())
+    }
+
+    /// Apply `flags` to the chat's current member permissions.
+    async fn set_chat_permissions(&self, chat_id: i64, flags: &PermissionFlags) -> Result<ChatPermissions, String> {
+        let chat = self.bot.get_chat(ChatId(chat_id)).await.map_err(|e| {
+            let msg = format!("Failed to read chat permissions: {e}");
+            warn!("{}", msg);
+            msg
+        })?;
+        let current = chat.permissions().ok_or("Telegram didn't return the chat's permissions")?;
+        let updated = flags.apply(current);
+        info!("🔐 Setting permissions in chat {}: {}", chat_id, flags.describe());
+
+        self.bot
+            .set_chat_permissions(ChatId(chat_id), updated.clone())
+            .use_independent_chat_permissions(true)
+            .await
+            .map_err(|e| {
+                let msg = format!("Failed to set chat permissions: {e}");
+                warn!("{}", msg);
+                msg
+            })?;
+
+        Ok(updated)
+    }
+
     /// Send an image from bytes.
-    pub async fn send_image(
+    async fn send_image(
         &self,
         chat_id: i64,
         image_data: Vec<u8>,
@@ -368,13 +707,65 @@ impl TelegramClient {
         unreachable!()
     }
 
-    /// Download an image by file_id.
+    /// Send a file from bytes as a document.
+    async fn send_document(
+        &self,
+        chat_id: i64,
+        data: Vec<u8>,
+        filename: &str,
+        reply_to_message_id: Option<i64>,
+    ) -> Result<i64, String> {
+        info!("📎 Sending document {} to chat {} ({} bytes)", filename, chat_id, data.len());
+
+        let mut current_reply_to = reply_to_message_id;
+
+        for attempt in 0..=MAX_RETRIES {
+            let input_file = InputFile::memory(data.clone()).file_name(filename.to_string());
+            let mut request = self.bot.send_document(ChatId(chat_id), input_file);
+
+            if let Some(msg_id) = current_reply_to {
+                request = request.reply_parameters(ReplyParameters::new(MessageId(msg_id as i32)));
+            }
+
+            match request.await {
+                Ok(msg) => return Ok(msg.id.0 as i64),
+                Err(e) => {
+                    if format!("{e}").contains("message to be replied not found") && current_reply_to.is_some() {
+                        warn!("Reply target not found, retrying document send without reply_to");
+                        current_reply_to = None;
+                        continue;
+                    }
+
+                    if attempt < MAX_RETRIES && Self::is_retryable_error(&e) {
+                        let delay = RETRY_BASE_DELAY_MS * 2u64.pow(attempt);
+                        warn!("Send document failed (attempt {}), retrying in {}ms: {}", attempt + 1, delay, e);
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                        continue;
+                    }
+                    let msg = format!("Failed to send document: {e}");
+                    warn!("{}", msg);
+                    return Err(msg);
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    /// Download a photo at the largest size within the image limits.
     /// Returns (bytes, media_type).
-    pub async fn download_image(&self, file_id: &str) -> Result<(Vec<u8>, String), String> {
+    async fn download_photo(&self, sizes: &[PhotoSize], max_bytes: u64) -> Result<(Vec<u8>, String), String> {
+        let photo = image_limits::pick_photo_size(sizes, max_bytes).ok_or("Photo has no sizes")?;
+        self.download_image(&photo.file.id.0, max_bytes).await
+    }
+
+    /// Download an image by file_id, refusing files over `max_bytes`.
+    /// Returns (bytes, media_type).
+    async fn download_image(&self, file_id: &str, max_bytes: u64) -> Result<(Vec<u8>, String), String> {
         // Get file info
         let file = self.bot.get_file(FileId(file_id.to_string())).await.map_err(|e| {
             format!("Failed to get file info: {e}")
         })?;
+        image_limits::check_size(u64::from(file.size), max_bytes)?;
 
         let file_path = &file.path;
 
@@ -383,6 +774,7 @@ impl TelegramClient {
         self.bot.download_file(file_path, &mut data).await.map_err(|e| {
             format!("Failed to download file: {e}")
         })?;
+        image_limits::check_size(data.len() as u64, max_bytes)?;
 
         // Determine media type from extension
         let media_type = if file_path.ends_with(".jpg") || file_path.ends_with(".jpeg") {
@@ -400,7 +792,7 @@ impl TelegramClient {
     }
 
     /// Send a voice message from bytes (OGG Opus format).
-    pub async fn send_voice(
+    async fn send_voice(
         &self,
         chat_id: i64,
         voice_data: Vec<u8>,
@@ -453,7 +845,7 @@ impl TelegramClient {
     }
 
     /// Get username for a user ID via getChat.
-    pub async fn get_chat_username(&self, user_id: i64) -> Result<Option<String>, String> {
+    async fn get_chat_username(&self, user_id: i64) -> Result<Option<String>, String> {
         match self.bot.get_chat(ChatId(user_id)).await {
             Ok(chat) => Ok(chat.username().map(|s| s.to_string())),
             Err(e) => {
@@ -464,3 +856,157 @@ impl TelegramClient {
     }
 
 }
+
+impl TelegramApi for TelegramClient {
+    fn send_message<'a>(&'a self, chat_id: i64, text: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        Box::pin(TelegramClient::send_message(self, chat_id, text, reply_to_message_id))
+    }
+
+    fn get_chat_member(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ChatMemberInfo> {
+        Box::pin(TelegramClient::get_chat_member(self, chat_id, user_id))
+    }
+
+    fn get_profile_photo(&self, user_id: i64, max_bytes: u64) -> ApiFuture<'_, Option<Vec<u8>>> {
+        Box::pin(TelegramClient::get_profile_photo(self, user_id, max_bytes))
+    }
+
+    fn set_message_reaction<'a>(&'a self, chat_id: i64, message_id: i64, emoji: &'a str) -> ApiFuture<'a, ()> {
+        Box::pin(TelegramClient::set_message_reaction(self, chat_id, message_id, emoji))
+    }
+
+    fn send_dice(&self, chat_id: i64, emoji: DiceEmoji, reply_to_message_id: Option<i64>) -> ApiFuture<'_, (i64, u8)> {
+        Box::pin(TelegramClient::send_dice(self, chat_id, emoji, reply_to_message_id))
+    }
+
+    fn send_location<'a>(&'a self, chat_id: i64, location: &'a Location, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        Box::pin(TelegramClient::send_location(self, chat_id, location, reply_to_message_id))
+    }
+
+    fn delete_message(&self, chat_id: i64, message_id: i64) -> ApiFuture<'_, ()> {
+        Box::pin(TelegramClient::delete_message(self, chat_id, message_id))
+    }
+
+    fn mute_user(&self, chat_id: i64, user_id: i64, until: chrono::DateTime<chrono::Utc>) -> ApiFuture<'_, ()> {
+        Box::pin(TelegramClient::mute_user(self, chat_id, user_id, until))
+    }
+
+    fn unmute_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()> {
+        Box::pin(TelegramClient::unmute_user(self, chat_id, user_id))
+    }
+
+    fn ban_user(&self, chat_id: i64, user_id: i64, revoke_messages: bool) -> ApiFuture<'_, ()> {
+        Box::pin(TelegramClient::ban_user(self, chat_id, user_id, revoke_messages))
+    }
+
+    fn kick_user(&self, chat_id: i64, user_id: i64) -> ApiFuture<'_, ()> {
+        Box::pin(TelegramClient::kick_user(self, chat_id, user_id))
+    }
+
+    fn get_chat_admins(&self, chat_id: i64) -> ApiFuture<'_, String> {
+        Box::pin(TelegramClient::get_chat_admins(self, chat_id))
+    }
+
+    fn cached_admin_identities(&self, chat_id: i64) -> ApiFuture<'_, Vec<AdminIdentity>> {
+        Box::pin(TelegramClient::cached_admin_identities(self, chat_id))
+    }
+
+    fn create_chat_invite_link<'a>(
+        &'a self,
+        chat_id: i64,
+        expire_at: Option<chrono::DateTime<chrono::Utc>>,
+        member_limit: Option<u32>,
+        name: Option<&'a str>,
+    ) -> ApiFuture<'a, String> {
+        Box::pin(TelegramClient::create_chat_invite_link(self, chat_id, expire_at, member_limit, name))
+    }
+
+    fn revoke_chat_invite_link<'a>(&'a self, chat_id: i64, invite_link: &'a str) -> ApiFuture<'a, ()> {
+        Box::pin(TelegramClient::revoke_chat_invite_link(self, chat_id, invite_link))
+    }
+
+    fn set_chat_permissions<'a>(&'a self, chat_id: i64, flags: &'a PermissionFlags) -> ApiFuture<'a, ChatPermissions> {
+        Box::pin(TelegramClient::set_chat_permissions(self, chat_id, flags))
+    }
+
+    fn send_image<'a>(&'a self, chat_id: i64, image_data: Vec<u8>, caption: Option<&'a str>, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        Box::pin(TelegramClient::send_image(self, chat_id, image_data, caption, reply_to_message_id))
+    }
+
+    fn send_document<'a>(&'a self, chat_id: i64, data: Vec<u8>, filename: &'a str, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        Box::pin(TelegramClient::send_document(self, chat_id, data, filename, reply_to_message_id))
+    }
+
+    fn send_voice<'a>(&'a self, chat_id: i64, voice_data: Vec<u8>, caption: Option<&'a str>, reply_to_message_id: Option<i64>) -> ApiFuture<'a, i64> {
+        Box::pin(TelegramClient::send_voice(self, chat_id, voice_data, caption, reply_to_message_id))
+    }
+
+    fn download_photo<'a>(&'a self, sizes: &'a [PhotoSize], max_bytes: u64) -> ApiFuture<'a, (Vec<u8>, String)> {
+        Box::pin(TelegramClient::download_photo(self, sizes, max_bytes))
+    }
+
+    fn get_chat_username(&self, user_id: i64) -> ApiFuture<'_, Option<String>> {
+        Box::pin(TelegramClient::get_chat_username(self, user_id))
+    }
+
+    fn invalidate_member(&self, chat_id: i64, user_id: i64, admin_changed: bool) {
+        TelegramClient::invalidate_member(self, chat_id, user_id, admin_changed)
+    }
+
+    fn cache_summary(&self) -> String {
+        TelegramClient::cache_summary(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dice_emoji() {
+        assert_eq!(parse_dice_emoji("🎲"), Ok(DiceEmoji::Dice));
+        assert_eq!(parse_dice_emoji("🎯"), Ok(DiceEmoji::Darts));
+        assert_eq!(parse_dice_emoji("🏀"), Ok(DiceEmoji::Basketball));
+        assert_eq!(parse_dice_emoji(" 🎰 "), Ok(DiceEmoji::SlotMachine));
+        // With variation selector
+        assert_eq!(parse_dice_emoji("⚽\u{fe0f}"), Ok(DiceEmoji::Football));
+
+        let err = parse_dice_emoji("🃏").unwrap_err();
+        assert!(err.contains("🎲"));
+        assert!(parse_dice_emoji("dice").is_err());
+        assert!(parse_dice_emoji("").is_err());
+    }
+
+    #[test]
+    fn test_dice_text() {
+        assert_eq!(dice_text(&Dice { emoji: DiceEmoji::Dice, value: 5 }), "[dice 🎲 rolled 5]");
+        assert_eq!(dice_text(&Dice { emoji: DiceEmoji::Basketball, value: 4 }), "[dice 🏀 rolled 4]");
+        assert_eq!(dice_text(&Dice { emoji: DiceEmoji::SlotMachine, value: 64 }), "[dice 🎰 rolled 64]");
+    }
+
+    #[test]
+    fn test_message_location() {
+        let message = |media: serde_json::Value| -> Message {
+            let mut json = serde_json::json!({
+                "message_id": 7,
+                "date": 1_700_000_000,
+                "chat": { "id": -100, "type": "supergroup", "title": "Meetups" },
+                "from": { "id": 42, "is_bot": false, "first_name": "Alice" },
+            });
+            json.as_object_mut().unwrap().extend(media.as_object().unwrap().clone());
+            serde_json::from_value(json).unwrap()
+        };
+        let pin = message(serde_json::json!({ "location": { "latitude": 41.31, "longitude": 69.28 } }));
+        let venue = message(serde_json::json!({
+            "location": { "latitude": 41.31, "longitude": 69.28 },
+            "venue": {
+                "location": { "latitude": 41.31, "longitude": 69.28 },
+                "title": "Cafe Central",
+                "address": "Amir Temur 1",
+            },
+        }));
+
+        assert_eq!(message_location(&pin).unwrap().format(), "[location 41.31,69.28]");
+        assert_eq!(message_location(&venue).unwrap().format(), "[location 41.31,69.28 \"Cafe Central\" \"Amir Temur 1\"]");
+        assert_eq!(message_location(&message(serde_json::json!({ "text": "hi" }))), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Tool failures, collected for the owner.
+//!
+//! Failed tool calls (Telegram errors, Gemini quota, TTS down) are stored in
+//! the `tool_errors` table and summarized in a daily digest and on `/errors`.
+//! Within a tool, errors are grouped by `error_key`, so the same failure with
+//! a different message ID or timestamp counts as one kind.
+
+use std::cmp::Reverse;
+
+use chrono::{DateTime, Utc};
+
+/// Window covered by the digest and `/errors` (hours).
+pub const DIGEST_WINDOW_HOURS: i64 = 24;
+
+/// How long failures are kept (days).
+pub const RETENTION_DAYS: i64 = 7;
+
+/// Error prefix length used for grouping.
+const KEY_CHARS: usize = 60;
+
+/// Error kinds listed per tool.
+const TOP_ERRORS: usize = 3;
+
+/// Longest error text shown in the digest.
+const MAX_ERROR_CHARS: usize = 200;
+
+/// One failed tool call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolError {
+    pub tool: String,
+    pub error: String,
+    pub chat_id: Option<i64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Grouping key for an error: its start, with each number (IDs, counts,
+/// times) replaced by `#`.
+pub fn error_key(error: &str) -> String {
+    let mut key = String::new();
+    for c in error.chars() {
+        if !c.is_ascii_digit() {
+            key.push(c);
+        } else if !key.ends_with('#') {
+            key.push('#');
+        }
+    }
+    key.chars().take(KEY_CHARS).collect::<String>().trim().to_lowercase()
+}
+
+/// Failures per tool, most frequent first, each with its most frequent kinds.
+struct ToolSummary<'a> {
+    tool: &'a str,
+    count: usize,
+    /// (count, latest error text) per kind
+    kinds: Vec<(usize, &'a str)>,
+}
+
+fn summarize(errors: &[ToolError]) -> Vec<ToolSummary<'_>> {
+    let mut tools: Vec<ToolSummary<'_>> = Vec::new();
+    let mut keys: Vec<Vec<String>> = Vec::new();
+    for e in errors {
+        let key = error_key(&e.error);
+        let idx = match tools.iter().position(|t| t.tool == e.tool) {
+            Some(idx) => idx,
+            None => {
+                tools.push(ToolSummary { tool: &e.tool, count: 0, kinds: Vec::new() });
+                keys.push(Vec::new());
+                tools.len() - 1
+            }
+        };
+        let summary = &mut tools[idx];
+        summary.count += 1;
+        let tool_keys = &mut keys[idx];
+        match tool_keys.iter().position(|k| *k == key) {
+            Some(k) => {
+                summary.kinds[k].0 += 1;
+                summary.kinds[k].1 = &e.error;
+            }
+            None => {
+                tool_keys.push(key);
+                summary.kinds.push((1, &e.error));
+            }
+        }
+    }
+    for summary in &mut tools {
+        // Stable sort keeps first-seen order among equal counts
+        summary.kinds.sort_by_key(|&(count, _)| Reverse(count));
+        summary.kinds.truncate(TOP_ERRORS);
+    }
+    tools.sort_by_key(|t| Reverse(t.count));
+    tools
+}
+
+/// The owner's digest of `errors` (oldest first) over the last `hours`, or
+/// None if there were none.
+pub fn digest(errors: &[ToolError], hours: i64) -> Option<String> {
+    if errors.is_empty() {
+        return None;
+    }
+    let mut out = format!("🧰 Tool errors in the last {}h: {}", hours, errors.len());
+    for summary in summarize(errors) {
+        out.push_str(&format!("\n\n{} ×{}", summary.tool, summary.count));
+        for (count, error) in summary.kinds {
+            let mut text: String = error.chars().take(MAX_ERROR_CHARS).collect();
+            if text.len() < error.len() {
+                text.push('…');
+            }
+            out.push_str(&format!("\n  {}× {}", count, text));
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure(tool: &str, error: &str) -> ToolError {
+        ToolError { tool: tool.to_string(), error: error.to_string(), chat_id: Some(-100), timestamp: Utc::now() }
+    }
+
+    #[test]
+    fn test_error_key() {
+        assert_eq!(
+            error_key("Telegram error: message 4521 not found"),
+            error_key("Telegram error: message 88 not found")
+        );
+        assert_ne!(error_key("Telegram error: message not found"), error_key("Telegram error: chat not found"));
+        // Only the prefix counts
+        let long_a = format!("Gemini quota exceeded for project claudima-bot, please retry after the reset. {}", "a".repeat(100));
+        let long_b = format!("Gemini quota exceeded for project claudima-bot, please retry after the reset. {}", "b".repeat(100));
+        assert_eq!(error_key(&long_a), error_key(&long_b));
+        assert_eq!(error_key("  TTS DOWN "), "tts down");
+    }
+
+    #[test]
+    fn test_digest_formatting() {
+        assert_eq!(digest(&[], 24), None);
+
+        let errors = vec![
+            failure("send_voice", "TTS request failed: connection refused"),
+            failure("delete_message", "message 12 can't be deleted"),
+            failure("send_voice", "TTS request failed: connection refused"),
+            failure("delete_message", "message 13 can't be deleted"),
+            failure("send_voice", "TTS returned 503"),
+            failure("delete_message", "not enough rights"),
+            failure("send_voice", "TTS request failed: timeout"),
+            failure("send_voice", "TTS returned 500"),
+            failure("send_voice", "voice too long"),
+        ];
+        assert_eq!(
+            digest(&errors, 24).unwrap(),
+            "🧰 Tool errors in the last 24h: 9\n\n\
+             send_voice ×6\n  \
+             2× TTS request failed: connection refused\n  \
+             2× TTS returned 500\n  \
+             1× TTS request failed: timeout\n\n\
+             delete_message ×3\n  \
+             2× message 13 can't be deleted\n  \
+             1× not enough rights"
+        );
+    }
+
+    #[test]
+    fn test_digest_truncates_long_errors() {
+        let errors = vec![failure("wiki_lookup", &"x".repeat(500))];
+        let text = digest(&errors, 24).unwrap();
+        assert!(text.ends_with(&format!("1× {}…", "x".repeat(MAX_ERROR_CHARS))));
+    }
+}
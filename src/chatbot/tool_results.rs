@@ -0,0 +1,129 @@
+//! Size limits on tool results.
+//!
+//! Everything a tool returns goes into Claude's context, so one query over
+//! a huge table or a read_memory of a huge file can push the session
+//! straight into compaction. Each result is cut to its tool's limit
+//! (`tool_result_limits` config map, default `DEFAULT_LIMIT` chars), and a
+//! turn's results together get `TURN_BUDGET` chars: once that is spent,
+//! further results are withheld with an error asking Claude to narrow down.
+//!
+//! Errors and action tools (no content) don't count and are never cut.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::tools::get_tool_definitions;
+
+/// Characters a tool's result may have unless configured otherwise.
+pub const DEFAULT_LIMIT: usize = 8000;
+
+/// Characters all of a turn's results may have together.
+pub const TURN_BUDGET: usize = 30_000;
+
+/// How many characters a result of `tool` may have.
+pub fn limit(tool: &str, overrides: &HashMap<String, usize>) -> usize {
+    overrides.get(tool).copied().unwrap_or(DEFAULT_LIMIT)
+}
+
+/// Check the `tool_result_limits` config map (tool name → characters).
+pub fn parse_overrides(chars: &BTreeMap<String, usize>) -> Result<HashMap<String, usize>, String> {
+    let tools: Vec<String> = get_tool_definitions().into_iter().map(|t| t.name).collect();
+    chars
+        .iter()
+        .map(|(tool, &limit)| {
+            if !tools.contains(tool) {
+                return Err(format!("unknown tool '{}'", tool));
+            }
+            if limit < 100 {
+                return Err(format!("{} must be at least 100 characters", tool));
+            }
+            Ok((tool.clone(), limit))
+        })
+        .collect()
+}
+
+/// Result characters left in a turn.
+#[derive(Debug)]
+pub struct Budget {
+    left: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self { left: TURN_BUDGET }
+    }
+}
+
+impl Budget {
+    /// Fit a result of `tool` into its `limit` and what's left of the turn's
+    /// budget, or Err when the budget is spent.
+    pub fn admit(&mut self, tool: &str, content: String, limit: usize) -> Result<String, String> {
+        if self.left == 0 {
+            return Err(format!(
+                "{} result withheld, too much data this turn. Refine the request \
+                 (narrower query, LIMIT, fewer columns) and call it again.",
+                tool
+            ));
+        }
+        let content = truncate(tool, content, limit.min(self.left));
+        self.left -= content.chars().count().min(self.left);
+        Ok(content)
+    }
+}
+
+/// Cut `content` to `max` characters, saying how much was dropped.
+fn truncate(tool: &str, content: String, max: usize) -> String {
+    let total = content.chars().count();
+    if total <= max {
+        return content;
+    }
+    let hint = match tool {
+        "read_memory" => "read fewer lines with offset and limit",
+        _ => "narrow your query",
+    };
+    let mut cut: String = content.chars().take(max).collect();
+    cut.push_str(&format!("\n[truncated, {} chars omitted — {}]", total - max, hint));
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let overrides = parse_overrides(&BTreeMap::from([("read_memory".to_string(), 20_000)])).unwrap();
+        assert_eq!(limit("read_memory", &overrides), 20_000);
+        assert_eq!(limit("query", &overrides), DEFAULT_LIMIT);
+
+        let unknown = BTreeMap::from([("read_mind".to_string(), 5000)]);
+        assert_eq!(parse_overrides(&unknown).unwrap_err(), "unknown tool 'read_mind'");
+        assert!(parse_overrides(&BTreeMap::from([("query".to_string(), 10)])).is_err());
+    }
+
+    #[test]
+    fn test_truncates_to_the_tool_limit() {
+        let mut budget = Budget::default();
+        assert_eq!(budget.admit("query", "short".to_string(), 100).unwrap(), "short");
+
+        let cut = budget.admit("query", "ж".repeat(150), 100).unwrap();
+        assert!(cut.starts_with(&"ж".repeat(100)));
+        assert!(cut.ends_with("\n[truncated, 50 chars omitted — narrow your query]"), "{}", cut);
+
+        let memory = budget.admit("read_memory", "x".repeat(120), 100).unwrap();
+        assert!(memory.contains("read fewer lines with offset and limit"), "{}", memory);
+    }
+
+    #[test]
+    fn test_turn_budget_runs_out() {
+        let mut budget = Budget::default();
+        for _ in 0..3 {
+            budget.admit("query", "x".repeat(DEFAULT_LIMIT), DEFAULT_LIMIT).unwrap();
+        }
+        // The fourth result only gets what's left, and then nothing does
+        let left = TURN_BUDGET - 3 * DEFAULT_LIMIT;
+        let fourth = budget.admit("read_memory", "x".repeat(DEFAULT_LIMIT), DEFAULT_LIMIT).unwrap();
+        assert!(fourth.starts_with(&format!("{}\n[truncated, {} chars omitted", "x".repeat(left), DEFAULT_LIMIT - left)));
+        let error = budget.admit("query", "1 row".to_string(), DEFAULT_LIMIT).unwrap_err();
+        assert!(error.starts_with("query result withheld, too much data this turn"), "{}", error);
+    }
+}
@@ -0,0 +1,161 @@
+//! Time limits on tool calls.
+//!
+//! Every tool call in a turn runs under a deadline, so a hung TTS endpoint
+//! or Gemini request fails that one call instead of freezing the turn while
+//! the debounce backlog piles up. Limits default by category and can be
+//! overridden per tool with the `tool_timeouts` config map.
+//!
+//! On expiry the call's future is dropped. Anything it already did stays
+//! done (a message may have been sent, a user muted), so the error tells
+//! Claude to check before retrying.
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::time::Duration;
+
+use super::tools::get_tool_definitions;
+
+/// Timeout for a delegate call to Gemini.
+pub const DELEGATE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Overall timeout for a wiki_lookup (search + summary + disambiguation links).
+pub const WIKI_LOOKUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How long a bulk_delete keeps deleting before it stops and reports.
+pub const BULK_DELETE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Extra time on top of a tool's own deadline, so its own message wins.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Kinds of tool calls, by what they wait on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    /// Telegram Bot API actions.
+    Telegram,
+    /// HTTP lookups (YouTube, Wikipedia, Gemini text, profile photos).
+    Network,
+    ImageGeneration,
+    Speech,
+    /// Database and data directory.
+    Local,
+}
+
+impl Category {
+    pub fn of(tool: &str) -> Self {
+        match tool {
+            "send_message" | "add_reaction" | "delete_message" | "mute_user" | "unmute_user" | "ban_user"
+            | "kick_user" | "get_chat_admins" | "create_invite_link" | "revoke_invite_link" | "send_document"
+            | "send_rubric_docx" | "send_dice" | "send_location" | "add_trusted_user" | "bulk_delete" => Self::Telegram,
+            "get_user_info" | "youtube_info" | "wiki_lookup" | "translate" | "delegate" => Self::Network,
+            "send_photo" => Self::ImageGeneration,
+            "send_voice" => Self::Speech,
+            _ => Self::Local,
+        }
+    }
+
+    pub fn default_limit(self) -> Duration {
+        match self {
+            Self::Telegram => Duration::from_secs(10),
+            Self::Network | Self::Local => Duration::from_secs(15),
+            Self::ImageGeneration => Duration::from_secs(60),
+            Self::Speech => Duration::from_secs(45),
+        }
+    }
+}
+
+/// How long a call to `tool` may run: the configured override, else the
+/// tool's own deadline plus grace, else its category's default.
+pub fn limit(tool: &str, overrides: &HashMap<String, Duration>) -> Duration {
+    if let Some(limit) = overrides.get(tool) {
+        return *limit;
+    }
+    match tool {
+        "delegate" => DELEGATE_TIMEOUT + GRACE,
+        "wiki_lookup" => WIKI_LOOKUP_TIMEOUT + GRACE,
+        "bulk_delete" => BULK_DELETE_TIMEOUT + GRACE,
+        _ => Category::of(tool).default_limit(),
+    }
+}
+
+/// Check the `tool_timeouts` config map (tool name → seconds).
+pub fn parse_overrides(seconds: &BTreeMap<String, u64>) -> Result<HashMap<String, Duration>, String> {
+    let tools: Vec<String> = get_tool_definitions().into_iter().map(|t| t.name).collect();
+    seconds
+        .iter()
+        .map(|(tool, &secs)| {
+            if !tools.contains(tool) {
+                return Err(format!("unknown tool '{}'", tool));
+            }
+            if secs == 0 {
+                return Err(format!("{} must be at least 1 second", tool));
+            }
+            Ok((tool.clone(), Duration::from_secs(secs)))
+        })
+        .collect()
+}
+
+/// The error Claude sees when a call runs out of time.
+pub fn timeout_message(tool: &str, limit: Duration) -> String {
+    format!(
+        "{} timed out after {:?} and was abandoned. It may still have taken effect \
+         (e.g. the message sent or the action applied); check before retrying.",
+        tool, limit
+    )
+}
+
+/// Run `call` for at most `limit`, dropping it on expiry.
+pub async fn bounded<T>(tool: &str, limit: Duration, call: impl Future<Output = T>) -> Result<T, String> {
+    tokio::time::timeout(limit, call).await.map_err(|_| timeout_message(tool, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+
+    #[test]
+    fn test_limits() {
+        assert!(timeout_message("send_photo", Duration::from_secs(60)).starts_with("send_photo timed out after 60s"));
+
+        let none = HashMap::new();
+        assert_eq!(limit("send_voice", &none), Duration::from_secs(45));
+        assert_eq!(limit("send_photo", &none), Duration::from_secs(60));
+        assert_eq!(limit("ban_user", &none), Duration::from_secs(10));
+        assert_eq!(limit("youtube_info", &none), Duration::from_secs(15));
+        assert_eq!(limit("delegate", &none), DELEGATE_TIMEOUT + GRACE);
+        assert_eq!(limit("bulk_delete", &none), BULK_DELETE_TIMEOUT + GRACE);
+
+        let seconds = BTreeMap::from([("send_voice".to_string(), 90)]);
+        let overrides = parse_overrides(&seconds).unwrap();
+        assert_eq!(limit("send_voice", &overrides), Duration::from_secs(90));
+        assert_eq!(limit("send_photo", &overrides), Duration::from_secs(60));
+
+        let unknown = BTreeMap::from([("send_fax".to_string(), 5)]);
+        assert_eq!(parse_overrides(&unknown).unwrap_err(), "unknown tool 'send_fax'");
+        assert!(parse_overrides(&BTreeMap::from([("query".to_string(), 0)])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_call_times_out_and_loop_continues() {
+        type Handler = Pin<Box<dyn Future<Output = Result<&'static str, String>>>>;
+        let handlers: Vec<(&str, Handler)> = vec![
+            ("query", Box::pin(async { Ok("rows") })),
+            ("send_voice", Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(600)).await;
+                Ok("sent")
+            })),
+            ("send_message", Box::pin(async { Ok("sent") })),
+        ];
+
+        let mut results = Vec::new();
+        for (tool, handler) in handlers {
+            results.push(bounded(tool, Duration::from_millis(50), handler).await.and_then(|r| r));
+        }
+
+        assert_eq!(results[0], Ok("rows"));
+        let error = results[1].as_ref().unwrap_err();
+        assert!(error.starts_with("send_voice timed out after 50ms"));
+        assert!(error.contains("may still have taken effect"));
+        assert_eq!(results[2], Ok("sent"));
+    }
+}
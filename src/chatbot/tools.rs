@@ -2,11 +2,16 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::chatbot::chat_settings::PermissionFlags;
+use crate::chatbot::message_ref::MessageRef;
+
 /// Tool definition for Claude.
 #[derive(Debug, Clone, Serialize)]
 pub struct Tool {
     pub name: String,
     pub description: String,
+    /// One line, for the compact system prompt.
+    pub short_description: String,
     pub parameters: serde_json::Value,
 }
 
@@ -19,8 +24,9 @@ pub enum ToolCall {
         /// Target chat ID (required - use the chat_id from the message you're responding to)
         chat_id: i64,
         text: String,
+        /// Message ID, "last" or "last_from:<username>"
         #[serde(skip_serializing_if = "Option::is_none")]
-        reply_to_message_id: Option<i64>,
+        reply_to_message_id: Option<MessageRef>,
     },
 
     /// Get info about a user by ID or username.
@@ -31,19 +37,42 @@ pub enum ToolCall {
         username: Option<String>,
     },
 
-    /// Execute a SQL SELECT query on the database.
+    /// Execute a read-only SQL query on the database.
     Query {
-        /// SQL SELECT query. Must start with SELECT. Max 100 rows returned.
+        /// One SELECT, EXPLAIN SELECT or PRAGMA table_info/index_list. Max 100 rows returned.
         sql: String,
     },
 
+    /// Read recent messages from a chat, optionally filtered by date range and user.
+    ReadMessages {
+        /// Chat to read (defaults to the current chat)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        chat_id: Option<i64>,
+        /// Read the N most recent matching messages (max 200)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_n: Option<i64>,
+        /// Start: "YYYY-MM-DD" or "YYYY-MM-DD HH:MM" (UTC)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from_date: Option<String>,
+        /// End, inclusive: "YYYY-MM-DD" (whole day) or "YYYY-MM-DD HH:MM" (UTC)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        to_date: Option<String>,
+        /// Only messages from this username
+        #[serde(skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        /// Max messages when `last_n` isn't set (default 50, max 200). With
+        /// `from_date`, reads forward from the start.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limit: Option<i64>,
+    },
+
     /// Add a reaction emoji to a message.
     AddReaction {
         /// Target chat ID (use the chat_id from the message you're reacting to)
         chat_id: i64,
-        /// Message ID to react to
-        message_id: i64,
-        /// Emoji to react with (e.g. "👍", "❤", "🔥", "😂")
+        /// Message to react to: ID, "last" or "last_from:<username>"
+        message_id: MessageRef,
+        /// Emoji to react with (e.g. "👍", "❤", "🔥", "🤣")
         emoji: String,
     },
 
@@ -59,12 +88,25 @@ pub enum ToolCall {
         user_id: i64,
         /// Duration in minutes (1-1440, i.e. up to 24 hours)
         duration_minutes: i64,
+        /// End of the mute, relative ("+2h") or absolute ("2026-01-25 08:00", UTC).
+        /// Takes precedence over duration_minutes; up to 7 days ahead.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        until: Option<String>,
+    },
+
+    /// Lift a mute early, restoring the user's permissions.
+    UnmuteUser {
+        chat_id: i64,
+        user_id: i64,
     },
 
     /// Ban a user permanently (admin action - use for severe abuse).
     BanUser {
         chat_id: i64,
         user_id: i64,
+        /// Also delete all of the user's messages in the chat
+        #[serde(default)]
+        revoke_messages: bool,
     },
 
     /// Kick a user from the group (softer than ban - they can rejoin).
@@ -78,6 +120,31 @@ pub enum ToolCall {
         chat_id: i64,
     },
 
+    /// Read a group's rules (memories/rules/<chat_id>.md).
+    GetRules {
+        chat_id: i64,
+    },
+
+    /// Create an invite link (owner, trusted users and the group's admins only).
+    CreateInviteLink {
+        chat_id: i64,
+        /// Hours until the link expires (clamped to 1-168); never expires if omitted
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expire_hours: Option<i64>,
+        /// How many people can join with the link (clamped to 1-99999); unlimited if omitted
+        #[serde(skip_serializing_if = "Option::is_none")]
+        member_limit: Option<i64>,
+        /// Label shown to admins (up to 32 chars)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+
+    /// Revoke an invite link (owner, trusted users and the group's admins only).
+    RevokeInviteLink {
+        chat_id: i64,
+        invite_link: String,
+    },
+
     /// Get list of known members from the database.
     GetMembers {
         /// Filter: "all", "active", "inactive", "never_posted", "left", "banned" (default "all")
@@ -125,6 +192,52 @@ pub enum ToolCall {
         reply_to_message_id: Option<i64>,
     },
 
+    /// Send generated text (a CSV, a long write-up) as a file attachment.
+    SendDocument {
+        chat_id: i64,
+        /// File name; must end in .txt, .md, .csv, .json or .html
+        filename: String,
+        /// UTF-8 file content (max 512KB)
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_message_id: Option<i64>,
+    },
+
+    /// Send a rubric as a .docx with one table row per category.
+    SendRubricDocx {
+        chat_id: i64,
+        /// Rubric in the prompt's numbered format
+        rubric_text: String,
+        /// File name; .docx is added if missing
+        filename: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_message_id: Option<i64>,
+    },
+
+    /// Send an animated dice and learn the rolled value.
+    SendDice {
+        chat_id: i64,
+        /// One of 🎲 🎯 🎳 🏀 ⚽ 🎰
+        emoji: String,
+        /// Optional message ID to reply to
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_message_id: Option<i64>,
+    },
+
+    /// Share a location pin; with a title it's sent as a venue.
+    SendLocation {
+        chat_id: i64,
+        latitude: f64,
+        longitude: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+        /// Optional message ID to reply to
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_message_id: Option<i64>,
+    },
+
     // === Memory Tools ===
 
     /// Create a new memory file. Fails if file already exists.
@@ -135,10 +248,14 @@ pub enum ToolCall {
         content: String,
     },
 
-    /// Read a memory file with line numbers.
+    /// Read a memory file with line numbers, a window of lines at a time.
     ReadMemory {
         /// Relative path within memories directory
         path: String,
+        /// First line to return, 1-based (default 1)
+        offset: Option<i64>,
+        /// Lines to return (default 200, max 500)
+        limit: Option<i64>,
     },
 
     /// Edit a memory file. Requires the file to have been read first.
@@ -188,6 +305,70 @@ pub enum ToolCall {
         url: String,
     },
 
+    /// Look up a topic on Wikipedia.
+    WikiLookup {
+        /// Search query (topic, person, place, etc.)
+        query: String,
+        /// Optional Wikipedia language code (e.g. "en", "de", "ru")
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lang: Option<String>,
+    },
+
+    /// Translate text using the Gemini text model.
+    Translate {
+        /// Text to translate
+        text: String,
+        /// Target language (e.g. "English", "de", "Russian")
+        target_lang: String,
+        /// Optional source language (auto-detected if omitted)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source_lang: Option<String>,
+    },
+
+    /// Offload bulk text work (summarize, extract, reformat) to Gemini.
+    Delegate {
+        /// What to do with the input (written by Claude)
+        instruction: String,
+        /// The text to process
+        input: String,
+        /// Optional output length cap in chars (default 4000, max 8000)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_output_chars: Option<i64>,
+    },
+
+    /// Fetch the reply chain a message belongs to, oldest first.
+    GetThread {
+        message_id: i64,
+        /// Max messages up the chain, including this one (default 10, max 30)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_depth: Option<i64>,
+        /// Also include direct replies to the message
+        #[serde(default)]
+        include_replies: bool,
+    },
+
+    /// Message statistics for a chat over the last N days.
+    ChatStats {
+        chat_id: i64,
+        /// Window in days (1-90, default 7)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        days: Option<i64>,
+    },
+
+    /// A chat's messages with the most reactions over the last N days.
+    TopMessages {
+        chat_id: i64,
+        /// Window in days (1-90, default 7)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        days: Option<i64>,
+        /// Messages to return (1-20, default 5)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        limit: Option<i64>,
+        /// Also rank the bot's own messages
+        #[serde(default)]
+        include_bot: bool,
+    },
+
     // === Reminder Tools ===
 
     /// Set a reminder to send a message at a future time.
@@ -216,6 +397,33 @@ pub enum ToolCall {
         reminder_id: i64,
     },
 
+    /// Send a one-off message after a delay.
+    SendLater {
+        chat_id: i64,
+        text: String,
+        /// Delay in seconds (1 to 604800, i.e. 7 days)
+        delay_seconds: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reply_to_message_id: Option<i64>,
+    },
+
+    /// Cancel a pending send_later message by ID.
+    CancelSendLater {
+        /// The scheduled message ID to cancel
+        scheduled_id: i64,
+    },
+
+    /// Come back to something later without posting anything: the note
+    /// returns as a system message and Claude decides then what to do.
+    FollowUp {
+        /// Chat the follow-up is about
+        chat_id: i64,
+        /// When to come back: relative ("+30m", "+2h"), at most 48 hours
+        delay: String,
+        /// What to revisit
+        note: String,
+    },
+
     // === Signal Tracking Tools ===
 
     /// Add a new signal to track.
@@ -270,6 +478,64 @@ pub enum ToolCall {
         username: Option<String>,
     },
 
+    /// Export a chat's messages between two dates as a file sent to the owner's
+    /// DM. Owner only, must be used in DM.
+    ExportTranscript {
+        chat_id: i64,
+        /// Start: "YYYY-MM-DD" or "YYYY-MM-DD HH:MM" (UTC)
+        from: String,
+        /// End, inclusive: "YYYY-MM-DD" (whole day) or "YYYY-MM-DD HH:MM" (UTC)
+        to: String,
+        /// "markdown" (default) or "html"
+        #[serde(skip_serializing_if = "Option::is_none")]
+        format: Option<String>,
+    },
+
+    /// Switch to a named personality ("default" for the plain identity).
+    /// Owner only, must be used in DM.
+    SetPersonality { name: String },
+
+    /// Post a message to a group with a "📣 from @username:" attribution.
+    /// The owner may relay for anyone, a trusted user only for themselves.
+    RelayToGroup {
+        chat_id: i64,
+        text: String,
+        /// The user the message is attributed to
+        on_behalf_of_user_id: i64,
+    },
+
+    /// Turn slow mode on or off in a group (owner and the group's admins only).
+    /// The bot enforces it by deleting messages sent too soon.
+    SetSlowMode {
+        chat_id: i64,
+        /// Seconds between a member's messages; 0 turns slow mode off.
+        /// Snapped to 10, 30, 60, 300, 900 or 3600.
+        seconds: i64,
+    },
+
+    /// Change what members may post in a group (owner and the group's admins only).
+    SetChatPermissions {
+        chat_id: i64,
+        #[serde(flatten)]
+        permissions: PermissionFlags,
+    },
+
+    /// Delete a group's recent messages, newest first, paced under Telegram's
+    /// flood limits (owner and the group's admins only).
+    BulkDelete {
+        chat_id: i64,
+        /// Only this user's messages
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user_id: Option<i64>,
+        /// How far back to look, at most 2880 (48 hours)
+        since_minutes: i64,
+        /// Only messages whose text contains this (case-insensitive)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        contains: Option<String>,
+        /// Most messages to delete, at most 200
+        limit: i64,
+    },
+
     /// Do nothing - acknowledge a message without taking action.
     Noop,
 
@@ -281,12 +547,55 @@ pub enum ToolCall {
     ParseError { message: String },
 }
 
+impl ToolCall {
+    /// Tool name as Claude sees it (e.g. "send_message").
+    pub fn name(&self) -> String {
+        if let ToolCall::ParseError { .. } = self {
+            return "parse_error".to_string();
+        }
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.get("tool").and_then(|t| t.as_str()).map(String::from))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Whether the tool only reads (the database, memories, Telegram, the
+    /// web) and changes nothing. Everything else, parse errors included,
+    /// counts as an action for `atomic_batches`.
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            ToolCall::GetUserInfo { .. }
+                | ToolCall::Query { .. }
+                | ToolCall::ReadMessages { .. }
+                | ToolCall::GetChatAdmins { .. }
+                | ToolCall::GetRules { .. }
+                | ToolCall::GetMembers { .. }
+                | ToolCall::ReadMemory { .. }
+                | ToolCall::ListMemories { .. }
+                | ToolCall::SearchMemories { .. }
+                | ToolCall::YoutubeInfo { .. }
+                | ToolCall::WikiLookup { .. }
+                | ToolCall::Translate { .. }
+                | ToolCall::Delegate { .. }
+                | ToolCall::GetThread { .. }
+                | ToolCall::ChatStats { .. }
+                | ToolCall::TopMessages { .. }
+                | ToolCall::ListReminders { .. }
+                | ToolCall::ListSignals { .. }
+                | ToolCall::Noop
+                | ToolCall::Done
+        )
+    }
+}
+
 /// Get the tool definitions for Claude.
 pub fn get_tool_definitions() -> Vec<Tool> {
     vec![
         Tool {
             name: "send_message".to_string(),
             description: "Send a message to a chat. Use the chat_id from the message you're responding to.".to_string(),
+            short_description: "Send a message to a chat (use the incoming chat_id).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -299,8 +608,8 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                         "description": "The message text to send"
                     },
                     "reply_to_message_id": {
-                        "type": "integer",
-                        "description": "Optional message ID to reply to"
+                        "type": ["integer", "string"],
+                        "description": "Optional message to reply to: its ID, \"last\" (latest message in the chat) or \"last_from:<username>\""
                     }
                 },
                 "required": ["chat_id", "text"]
@@ -308,7 +617,8 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         },
         Tool {
             name: "get_user_info".to_string(),
-            description: "Get detailed information about a user including their profile photo. Returns: user_id, username, first_name, last_name, is_bot, is_premium, language_code, status (owner/administrator/member/restricted/banned), custom_title, and profile_photo_base64. Username lookup only works for users seen in the group.".to_string(),
+            description: "Get detailed information about a user including their profile photo. Returns: user_id, username, first_name, last_name, is_bot, is_premium, language_code, status (owner/administrator/member/restricted/banned), custom_title, and profile_photo_base64, plus local activity: join_date (first seen), last_message_date, message_count, messages_last_7_days (group messages), rejoin_count (null when never seen here), and is_trusted_dm_user. Username lookup only works for users seen in the group.".to_string(),
+            short_description: "A user's profile, status and activity here.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -325,21 +635,39 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         },
         Tool {
             name: "query".to_string(),
-            description: "Execute a SQL SELECT query on the database. Tables: 'messages' (message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text) and 'users' (user_id, username, first_name, join_date, last_message_date, message_count, status). Indexes exist on timestamp, user_id, username. Max 100 rows returned, text truncated to 100 chars.".to_string(),
+            description: "Execute a SQL SELECT query on the database. Tables: 'messages' (message_id, chat_id, user_id, username, timestamp, text, reply_to_id, reply_to_username, reply_to_text, url_count, has_mention, forwarded, length_class: empty/short/medium/long/huge, emoji_ratio: 0-1, via_bot: inline bot username) and 'users' (user_id, username, first_name, join_date, last_message_date, message_count, status, normalized_username: lowercase, emoji-free, Cyrillic transliterated, last_rejoin_date, rejoin_count). Indexes exist on timestamp, user_id, username. PRAGMA table_info(<table>) and index_list(<table>) show the real schema; EXPLAIN SELECT is allowed too. Max 100 rows returned, text truncated to 100 chars.".to_string(),
+            short_description: "Read-only SQL on the database.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
                     "sql": {
                         "type": "string",
-                        "description": "SQL SELECT query. Only SELECT is allowed. Examples: 'SELECT * FROM messages ORDER BY timestamp DESC LIMIT 10', 'SELECT username, message_count FROM users WHERE status = \"member\" ORDER BY message_count DESC LIMIT 20'"
+                        "description": "One SQL statement: SELECT, EXPLAIN SELECT, or PRAGMA table_info/index_list. Examples: 'SELECT * FROM messages ORDER BY timestamp DESC LIMIT 10', 'SELECT username, message_count FROM users WHERE status = \"member\" ORDER BY message_count DESC LIMIT 20'"
                     }
                 },
                 "required": ["sql"]
             }),
         },
+        Tool {
+            name: "read_messages".to_string(),
+            description: "Read messages from a chat, oldest first, formatted like the conversation. Returns the most recent matches (or the first ones after from_date), optionally within a date range and/or from one user. Output is capped in size. Prefer this over `query` for reading chat history.".to_string(),
+            short_description: "Read a chat's messages by date range and/or user.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat ID (default: the current chat)" },
+                    "last_n": { "type": "integer", "description": "Read the N most recent matching messages (max 200)" },
+                    "from_date": { "type": "string", "description": "Start: \"YYYY-MM-DD\" or \"YYYY-MM-DD HH:MM\" (UTC)" },
+                    "to_date": { "type": "string", "description": "End, inclusive: \"YYYY-MM-DD\" (whole day) or \"YYYY-MM-DD HH:MM\" (UTC)" },
+                    "username": { "type": "string", "description": "Only messages from this username" },
+                    "limit": { "type": "integer", "description": "Max messages when last_n isn't set (default 50, max 200). With from_date, reads forward from the start" }
+                }
+            }),
+        },
         Tool {
             name: "add_reaction".to_string(),
             description: "Add an emoji reaction to a message. Use sparingly - only when a reaction is more appropriate than a reply.".to_string(),
+            short_description: "React to a message with an emoji.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -348,12 +676,12 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                         "description": "Target chat ID (use the chat_id from the message)"
                     },
                     "message_id": {
-                        "type": "integer",
-                        "description": "Message ID to react to"
+                        "type": ["integer", "string"],
+                        "description": "Message to react to: its ID, \"last\" (latest message in the chat) or \"last_from:<username>\""
                     },
                     "emoji": {
                         "type": "string",
-                        "description": "Emoji to react with (e.g. 👍, ❤, 🔥, 😂, 🎉, 👀, 🤔)"
+                        "description": "Emoji to react with (e.g. 👍, ❤, 🔥, 🤣, 🎉, 👀, 🤔). Telegram only allows a fixed set; common others are swapped for the closest allowed one"
                     }
                 },
                 "required": ["chat_id", "message_id", "emoji"]
@@ -362,6 +690,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "delete_message".to_string(),
             description: "Delete a message. Use for spam, abuse, or rule violations. Owner will be notified.".to_string(),
+            short_description: "Delete a message (spam, abuse, rule violations).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -373,25 +702,42 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         },
         Tool {
             name: "mute_user".to_string(),
-            description: "Temporarily mute a user (prevent them from posting). Use for minor violations. Duration 1-1440 minutes. Owner will be notified.".to_string(),
+            description: "Temporarily mute a user (prevent them from posting). Use for minor violations. Give either duration_minutes (1-1440) or until (e.g. \"until tomorrow morning\" → an absolute time). Owner will be notified.".to_string(),
+            short_description: "Mute a user for duration_minutes or until a time.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
                     "chat_id": { "type": "integer", "description": "Chat ID" },
                     "user_id": { "type": "integer", "description": "User ID to mute" },
-                    "duration_minutes": { "type": "integer", "description": "Duration in minutes (1-1440)" }
+                    "duration_minutes": { "type": "integer", "description": "Duration in minutes (1-1440)" },
+                    "until": { "type": "string", "description": "End of the mute: relative (\"+2h\", \"+1d\") or absolute UTC (\"2026-01-25 08:00\"), max 7 days ahead. Overrides duration_minutes" }
                 },
-                "required": ["chat_id", "user_id", "duration_minutes"]
+                "required": ["chat_id", "user_id"]
+            }),
+        },
+        Tool {
+            name: "unmute_user".to_string(),
+            description: "Lift a mute early, restoring the user's permissions. Owner will be notified.".to_string(),
+            short_description: "Lift a mute early.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat ID" },
+                    "user_id": { "type": "integer", "description": "User ID to unmute" }
+                },
+                "required": ["chat_id", "user_id"]
             }),
         },
         Tool {
             name: "ban_user".to_string(),
-            description: "Permanently ban a user. Use only for severe abuse (spam bots, repeated violations). Owner will be notified.".to_string(),
+            description: "Permanently ban a user. Use only for severe abuse (spam bots, repeated violations). Set revoke_messages to also delete all their messages in the chat, instead of deleting them one by one. Owner will be notified.".to_string(),
+            short_description: "Ban a user; revoke_messages deletes their messages too.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
                     "chat_id": { "type": "integer", "description": "Chat ID" },
-                    "user_id": { "type": "integer", "description": "User ID to ban" }
+                    "user_id": { "type": "integer", "description": "User ID to ban" },
+                    "revoke_messages": { "type": "boolean", "description": "Also delete all of the user's messages in the chat (default false)" }
                 },
                 "required": ["chat_id", "user_id"]
             }),
@@ -399,6 +745,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "kick_user".to_string(),
             description: "Kick a user from the group. Softer than ban - they can rejoin via invite link. Use for inactive members or minor issues. Owner will be notified.".to_string(),
+            short_description: "Remove a user from the group; they can rejoin.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -411,6 +758,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "get_chat_admins".to_string(),
             description: "Get list of chat administrators.".to_string(),
+            short_description: "List a chat's administrators.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -419,9 +767,50 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 "required": ["chat_id"]
             }),
         },
+        Tool {
+            name: "get_rules".to_string(),
+            description: "Read a group's rules, set by the owner (stored as memories/rules/<chat_id>.md). Check them before moderating, so warnings can cite the rule that was broken.".to_string(),
+            short_description: "Read a group's rules before moderating.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group chat ID" }
+                },
+                "required": ["chat_id"]
+            }),
+        },
+        Tool {
+            name: "create_invite_link".to_string(),
+            description: "Create an invite link for a group, e.g. a one-day link for 10 people during an event. Only for the owner, trusted users and admins of that group; the owner is notified. Active links are in the invite_links table.".to_string(),
+            short_description: "Create an invite link (owner, trusted users, group admins only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group chat ID" },
+                    "expire_hours": { "type": "integer", "description": "Hours until the link expires (1-168, i.e. up to 7 days). Omit for no expiry" },
+                    "member_limit": { "type": "integer", "description": "How many people can join with the link (1-99999). Omit for no limit" },
+                    "name": { "type": "string", "description": "Optional label for the link (up to 32 chars)" }
+                },
+                "required": ["chat_id"]
+            }),
+        },
+        Tool {
+            name: "revoke_invite_link".to_string(),
+            description: "Revoke an invite link so nobody else can join with it. Only for the owner, trusted users and admins of that group; the owner is notified.".to_string(),
+            short_description: "Revoke an invite link (owner, trusted users, group admins only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group chat ID" },
+                    "invite_link": { "type": "string", "description": "The full link, e.g. https://t.me/+AbCdEf" }
+                },
+                "required": ["chat_id", "invite_link"]
+            }),
+        },
         Tool {
             name: "get_members".to_string(),
             description: "Get list of known members from the database. Only includes members tracked since this feature was enabled.".to_string(),
+            short_description: "List known members from the database.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -438,6 +827,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "import_members".to_string(),
             description: "Import members from a JSON file (for backfilling from browser extension export). Only Dima can use this.".to_string(),
+            short_description: "Import members from a JSON export (owner only).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -449,6 +839,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "send_photo".to_string(),
             description: "Generate an AI image and send it to a chat. Uses Gemini/Nano Banana for image generation.".to_string(),
+            short_description: "Generate an image from a prompt and send it.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -463,6 +854,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "send_voice".to_string(),
             description: "Send a voice message using text-to-speech. Use this to speak to users instead of typing. Good for greetings, announcements, or when a voice reply feels more personal.".to_string(),
+            short_description: "Send text as a voice message.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -474,10 +866,41 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 "required": ["chat_id", "text"]
             }),
         },
+        Tool {
+            name: "send_document".to_string(),
+            description: "Send text content as a file attachment. Use this for content that belongs in a file (a CSV of stats, a long rubric or write-up) instead of pasting a wall of text. Allowed extensions: .txt, .md, .csv, .json, .html. Max 512KB.".to_string(),
+            short_description: "Send text as a file (.txt .md .csv .json .html, max 512KB).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Target chat ID" },
+                    "filename": { "type": "string", "description": "File name with extension, e.g. 'member_stats.csv'" },
+                    "content": { "type": "string", "description": "UTF-8 file content" },
+                    "reply_to_message_id": { "type": "integer", "description": "Optional message ID to reply to" }
+                },
+                "required": ["chat_id", "filename", "content"]
+            }),
+        },
+        Tool {
+            name: "send_rubric_docx".to_string(),
+            description: "Send a rubric as a Word document (.docx) with one table row per category. rubric_text must use the rubric format from the system prompt: 3-6 numbered categories like '1. Category Name (X pts)', each followed by the Exemplary (4), Proficient (3), Basic (2) and Needs Improvement (1) lines. A format error names the line to fix.".to_string(),
+            short_description: "Send a numbered rubric as a Word document.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Target chat ID" },
+                    "rubric_text": { "type": "string", "description": "The rubric in the required format" },
+                    "filename": { "type": "string", "description": "File name, e.g. 'essay_rubric.docx'" },
+                    "reply_to_message_id": { "type": "integer", "description": "Optional message ID to reply to" }
+                },
+                "required": ["chat_id", "rubric_text", "filename"]
+            }),
+        },
         // === Memory Tools ===
         Tool {
             name: "create_memory".to_string(),
             description: "Create a new memory file. Fails if file already exists - use edit_memory to modify existing files.".to_string(),
+            short_description: "Create a memory file (fails if it exists).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -489,11 +912,14 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         },
         Tool {
             name: "read_memory".to_string(),
-            description: "Read a memory file. Returns content with line numbers. Must read before editing.".to_string(),
+            description: "Read a memory file. Returns content with line numbers, 200 lines unless limit says otherwise, and the total line count when more remain. Must read before editing (any range counts).".to_string(),
+            short_description: "Read a memory file with line numbers (offset, limit).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
-                    "path": { "type": "string", "description": "Relative path within memories directory" }
+                    "path": { "type": "string", "description": "Relative path within memories directory" },
+                    "offset": { "type": "integer", "description": "First line to read, 1-based (default 1)" },
+                    "limit": { "type": "integer", "description": "Number of lines to read (default 200, max 500)" }
                 },
                 "required": ["path"]
             }),
@@ -501,6 +927,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "edit_memory".to_string(),
             description: "Edit a memory file by replacing a string. File must have been read first in this session.".to_string(),
+            short_description: "Replace a string in a memory file read this session.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -514,6 +941,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "list_memories".to_string(),
             description: "List files in the memories directory.".to_string(),
+            short_description: "List a memories directory.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -524,6 +952,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "search_memories".to_string(),
             description: "Search for a pattern across memory files (like grep).".to_string(),
+            short_description: "Grep the memory files.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -536,6 +965,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "delete_memory".to_string(),
             description: "Delete a memory file.".to_string(),
+            short_description: "Delete a memory file.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -547,6 +977,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "report_bug".to_string(),
             description: "Report a bug or issue to the developer (Claude Code). Use this when you encounter unexpected behavior, errors, or problems you can't resolve. The developer monitors these reports and will fix issues.".to_string(),
+            short_description: "Report a real bug to the developer.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -559,6 +990,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "youtube_info".to_string(),
             description: "Get metadata about a YouTube video (title, author, thumbnail). Works with youtube.com and youtu.be URLs.".to_string(),
+            short_description: "A YouTube video's title, author and thumbnail.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -567,9 +999,124 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 "required": ["url"]
             }),
         },
+        Tool {
+            name: "wiki_lookup".to_string(),
+            description: "Look up a topic on Wikipedia. Returns the article title, a summary extract, and the page URL. For ambiguous queries returns a list of options - look up one of them. Use this to check facts instead of guessing.".to_string(),
+            short_description: "Check a fact on Wikipedia.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look up (e.g. 'Rust programming language', 'Ada Lovelace')" },
+                    "lang": { "type": "string", "description": "Optional Wikipedia language code (e.g. 'en', 'de', 'ru'). Defaults to the configured language." }
+                },
+                "required": ["query"]
+            }),
+        },
+        Tool {
+            name: "translate".to_string(),
+            description: "Translate text with a cheaper model. Returns the translation to you - relay it with send_message. Prefer this over translating long messages yourself.".to_string(),
+            short_description: "Translate text with a cheaper model; relay the result yourself.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "Text to translate" },
+                    "target_lang": { "type": "string", "description": "Target language (e.g. 'English', 'German', 'ru')" },
+                    "source_lang": { "type": "string", "description": "Optional source language (auto-detected if omitted)" }
+                },
+                "required": ["text", "target_lang"]
+            }),
+        },
+        Tool {
+            name: "delegate".to_string(),
+            description: "Hand bulk or mechanical text work to a cheaper model: summarize a long pasted log, extract emails/links/numbers, reformat a list. Returns the output to you. Input is capped at 20000 chars.".to_string(),
+            short_description: "Hand bulk text work to a cheaper model (max 20000 chars).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "instruction": { "type": "string", "description": "What to do, precisely (e.g. 'List every email address, one per line')" },
+                    "input": { "type": "string", "description": "The text to process" },
+                    "max_output_chars": { "type": "integer", "description": "Optional output length cap (default 4000, max 8000)" }
+                },
+                "required": ["instruction", "input"]
+            }),
+        },
+        Tool {
+            name: "get_thread".to_string(),
+            description: "Fetch the reply chain a message belongs to (the messages it replies to, recursively), oldest first. Use it when a reply only makes sense with earlier turns of the thread.".to_string(),
+            short_description: "Fetch the reply chain of a message.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "message_id": { "type": "integer", "description": "Message to start from (usually the one you're replying to)" },
+                    "max_depth": { "type": "integer", "description": "Max messages up the chain, including this one (default 10, max 30)" },
+                    "include_replies": { "type": "boolean", "description": "Also include direct replies to the message (default false)" }
+                },
+                "required": ["message_id"]
+            }),
+        },
+        Tool {
+            name: "chat_stats".to_string(),
+            description: "Chat statistics over the last N days: total messages, active users, top 5 posters, messages per hour of day (UTC), average message length, joins per invite link (with how many of those joiners are banned, to spot spam funnels), and reaction emoji tallies. Use this for stats questions instead of writing SQL.".to_string(),
+            short_description: "Chat statistics over the last N days.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat ID" },
+                    "days": { "type": "integer", "description": "Window in days (1-90, default 7)" }
+                },
+                "required": ["chat_id"]
+            }),
+        },
+        Tool {
+            name: "top_messages".to_string(),
+            description: "A chat's most-reacted messages over the last N days, with author, text and reactions per emoji. Use it for \"best of the week\" posts and \"what did people like\" questions.".to_string(),
+            short_description: "A chat's most-reacted messages over the last N days.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat ID" },
+                    "days": { "type": "integer", "description": "Window in days (1-90, default 7)" },
+                    "limit": { "type": "integer", "description": "Messages to return (1-20, default 5)" },
+                    "include_bot": { "type": "boolean", "description": "Also rank your own messages (default false)" }
+                },
+                "required": ["chat_id"]
+            }),
+        },
+        Tool {
+            name: "send_dice".to_string(),
+            description: "Roll an animated Telegram dice. The rolled value comes back to you, so you can comment on it. Values: 1-6 for 🎲 🎯 🎳, 1-5 for 🏀 ⚽ (4-5 = scored), 1-64 for 🎰.".to_string(),
+            short_description: "Roll an animated Telegram dice.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Target chat ID" },
+                    "emoji": { "type": "string", "description": "Dice type", "enum": ["🎲", "🎯", "🎳", "🏀", "⚽", "🎰"] },
+                    "reply_to_message_id": { "type": "integer", "description": "Optional message ID to reply to" }
+                },
+                "required": ["chat_id", "emoji"]
+            }),
+        },
+        Tool {
+            name: "send_location".to_string(),
+            description: "Share a location pin on the map, e.g. a meetup spot. With a title it's sent as a venue card (title and address); without one, as a plain pin.".to_string(),
+            short_description: "Share a location pin or venue.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Target chat ID" },
+                    "latitude": { "type": "number", "description": "Latitude in degrees, -90 to 90" },
+                    "longitude": { "type": "number", "description": "Longitude in degrees, -180 to 180" },
+                    "title": { "type": "string", "description": "Optional venue name; sends a venue instead of a pin" },
+                    "address": { "type": "string", "description": "Optional venue address (used with title)" },
+                    "reply_to_message_id": { "type": "integer", "description": "Optional message ID to reply to" }
+                },
+                "required": ["chat_id", "latitude", "longitude"]
+            }),
+        },
         Tool {
             name: "noop".to_string(),
             description: "Do nothing - use this to acknowledge a system message or notification without taking any action.".to_string(),
+            short_description: "Acknowledge without doing anything.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {}
@@ -579,6 +1126,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "set_reminder".to_string(),
             description: "Set a reminder to send a message at a future time. Use for scheduling messages, alerts, or recurring announcements.".to_string(),
+            short_description: "Send a message at a future time, optionally recurring.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -593,6 +1141,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "list_reminders".to_string(),
             description: "List active reminders. Returns ID, message, trigger time, and whether it's recurring.".to_string(),
+            short_description: "List active reminders and pending messages.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -603,6 +1152,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "cancel_reminder".to_string(),
             description: "Cancel a reminder by its ID. Get the ID from list_reminders.".to_string(),
+            short_description: "Cancel a reminder or follow-up by ID.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -611,10 +1161,52 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 "required": ["reminder_id"]
             }),
         },
+        Tool {
+            name: "send_later".to_string(),
+            description: "Send a one-off message after a delay (up to 7 days). Delivered like send_message, including the reply thread. Max 20 pending per chat; list them with list_reminders.".to_string(),
+            short_description: "Send a message after a delay (up to 7 days).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat to send to" },
+                    "text": { "type": "string", "description": "Message text" },
+                    "delay_seconds": { "type": "integer", "description": "Seconds to wait before sending (1-604800)" },
+                    "reply_to_message_id": { "type": "integer", "description": "Optional message to reply to" }
+                },
+                "required": ["chat_id", "text", "delay_seconds"]
+            }),
+        },
+        Tool {
+            name: "cancel_send_later".to_string(),
+            description: "Cancel a pending send_later message by its ID. Get the ID from list_reminders.".to_string(),
+            short_description: "Cancel a pending send_later message by ID.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "scheduled_id": { "type": "integer", "description": "The scheduled message ID to cancel" }
+                },
+                "required": ["scheduled_id"]
+            }),
+        },
+        Tool {
+            name: "follow_up".to_string(),
+            description: "Check back on something later without posting anything now. After the delay you get the note back as a system message and decide then whether to say anything. Max 10 pending, up to 48 hours ahead; list them with list_reminders (kind \"follow_up\") and cancel with cancel_reminder.".to_string(),
+            short_description: "Get a note back later as a system message (up to 48 hours).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat the follow-up is about" },
+                    "delay": { "type": "string", "description": "When to come back: relative ('+30m', '+2h'), at most 48 hours" },
+                    "note": { "type": "string", "description": "What to revisit, for your future self" }
+                },
+                "required": ["chat_id", "delay", "note"]
+            }),
+        },
         // === Signal Tracking Tools ===
         Tool {
             name: "add_signal".to_string(),
             description: "Add a new signal/opportunity to track. Use this when you discover something worth investigating.".to_string(),
+            short_description: "Track a new signal/opportunity.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -632,6 +1224,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "update_signal".to_string(),
             description: "Update a tracked signal's status or notes. Use to progress signals through stages.".to_string(),
+            short_description: "Update a signal's status or notes.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -649,6 +1242,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "list_signals".to_string(),
             description: "List all tracked signals with their status and notes.".to_string(),
+            short_description: "List tracked signals.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -664,6 +1258,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "add_trusted_user".to_string(),
             description: "Add a user to the trusted DM users list. ONLY works in DM with owner. Provide either user_id or username.".to_string(),
+            short_description: "Let a user DM the bot (owner DM only).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -675,6 +1270,7 @@ pub fn get_tool_definitions() -> Vec<Tool> {
         Tool {
             name: "remove_trusted_user".to_string(),
             description: "Remove a user from the trusted DM users list. ONLY works in DM with owner. Provide either user_id or username.".to_string(),
+            short_description: "Stop a user from DMing the bot (owner DM only).".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -683,9 +1279,99 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 }
             }),
         },
+        Tool {
+            name: "export_transcript".to_string(),
+            description: "Export a chat's messages between two dates as a Markdown or HTML file, sent to the owner's DM. ONLY works in DM with owner. Capped at 50,000 messages.".to_string(),
+            short_description: "Send the owner a chat's messages between two dates (owner DM only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Chat to export" },
+                    "from_date": { "type": "string", "description": "Start, \"YYYY-MM-DD\" or \"YYYY-MM-DD HH:MM\" (UTC)" },
+                    "to_date": { "type": "string", "description": "End, inclusive; a bare date covers the whole day" },
+                    "format": { "type": "string", "enum": ["markdown", "html"], "description": "File format (default markdown)" }
+                },
+                "required": ["chat_id", "from_date", "to_date"]
+            }),
+        },
+        Tool {
+            name: "set_personality".to_string(),
+            description: "Switch to one of the configured personalities, which replaces your identity until the next scheduled switch. ONLY works in DM with owner.".to_string(),
+            short_description: "Switch to a configured personality (owner DM only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Personality name, or \"default\" for your plain identity" }
+                },
+                "required": ["name"]
+            }),
+        },
+        Tool {
+            name: "relay_to_group".to_string(),
+            description: "Post a message to a group on someone's behalf, prefixed with \"📣 from @username:\". Use this instead of send_message when a DM user asks you to pass something on. The owner can relay for anyone; a trusted user only for themselves.".to_string(),
+            short_description: "Post to a group on a DM user's behalf.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group to post in" },
+                    "text": { "type": "string", "description": "The message, without attribution" },
+                    "on_behalf_of_user_id": { "type": "integer", "description": "User the message is from" }
+                },
+                "required": ["chat_id", "text", "on_behalf_of_user_id"]
+            }),
+        },
+        Tool {
+            name: "set_slow_mode".to_string(),
+            description: "Turn slow mode on or off in a group: members must wait this long between messages, and you delete messages sent sooner. Admins are exempt. Only the owner and the group's admins can ask for this.".to_string(),
+            short_description: "Set a group's slow mode (owner or group admins only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group to change" },
+                    "seconds": { "type": "integer", "description": "Seconds between a member's messages (0 = off); snapped to 10, 30, 60, 300, 900 or 3600" }
+                },
+                "required": ["chat_id", "seconds"]
+            }),
+        },
+        Tool {
+            name: "set_chat_permissions".to_string(),
+            description: "Change what members of a group may post. Switches you leave out stay as they are. Only the owner and the group's admins can ask for this.".to_string(),
+            short_description: "Change what group members may post (owner or group admins only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group to change" },
+                    "can_send_messages": { "type": "boolean", "description": "Text messages" },
+                    "can_send_media": { "type": "boolean", "description": "Photos, videos, audio, documents, video and voice notes" },
+                    "can_send_polls": { "type": "boolean", "description": "Polls" },
+                    "can_send_other": { "type": "boolean", "description": "Stickers, GIFs, games and inline bots" },
+                    "can_add_web_page_previews": { "type": "boolean", "description": "Link previews" },
+                    "can_invite_users": { "type": "boolean", "description": "Adding members" },
+                    "can_pin_messages": { "type": "boolean", "description": "Pinning messages" }
+                },
+                "required": ["chat_id"]
+            }),
+        },
+        Tool {
+            name: "bulk_delete".to_string(),
+            description: "Delete a group's recent messages after a spam wave or a flood, newest first. Filter by sender and/or text; at most 200 messages from the last 48 hours per call. Only the owner and the group's admins can ask for this.".to_string(),
+            short_description: "Delete a group's recent messages by sender/text (owner or group admins only).".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "chat_id": { "type": "integer", "description": "Group to clean up" },
+                    "user_id": { "type": "integer", "description": "Only this user's messages" },
+                    "since_minutes": { "type": "integer", "description": "How far back to look, in minutes (1-2880)" },
+                    "contains": { "type": "string", "description": "Only messages whose text contains this (case-insensitive)" },
+                    "limit": { "type": "integer", "description": "Most messages to delete (1-200)" }
+                },
+                "required": ["chat_id", "since_minutes", "limit"]
+            }),
+        },
         Tool {
             name: "done".to_string(),
             description: "Signal that you're done processing. Call this when you have nothing more to do. You don't have to respond to every message - if there's nothing to say, just call done.".to_string(),
+            short_description: "Finish the turn; call it last.".to_string(),
             parameters: serde_json::json!({
                 "type": "object",
                 "properties": {}
@@ -703,7 +1389,7 @@ mod tests {
         let call = ToolCall::SendMessage {
             chat_id: -12345,
             text: "hello".to_string(),
-            reply_to_message_id: Some(123),
+            reply_to_message_id: Some(MessageRef::Id(123)),
         };
 
         let json = serde_json::to_string(&call).unwrap();
@@ -711,6 +1397,18 @@ mod tests {
         assert!(json.contains("hello"));
     }
 
+    #[test]
+    fn test_tool_call_name() {
+        let call = ToolCall::SendMessage {
+            chat_id: -12345,
+            text: "hello".to_string(),
+            reply_to_message_id: None,
+        };
+        assert_eq!(call.name(), "send_message");
+        assert_eq!(ToolCall::Done.name(), "done");
+        assert_eq!(ToolCall::ParseError { message: "bad".to_string() }.name(), "parse_error");
+    }
+
     #[test]
     fn test_tool_call_deserialize() {
         let json = r#"{"tool": "send_message", "chat_id": -12345, "text": "hello", "reply_to_message_id": 123}"#;
@@ -724,7 +1422,7 @@ mod tests {
             } => {
                 assert_eq!(chat_id, -12345);
                 assert_eq!(text, "hello");
-                assert_eq!(reply_to_message_id, Some(123));
+                assert_eq!(reply_to_message_id, Some(MessageRef::Id(123)));
             }
             _ => panic!("Wrong variant"),
         }
@@ -733,39 +1431,63 @@ mod tests {
     #[test]
     fn test_get_tool_definitions() {
         let tools = get_tool_definitions();
-        assert_eq!(tools.len(), 31);
+        assert_eq!(tools.len(), 55);
         assert_eq!(tools[0].name, "send_message");
         assert_eq!(tools[1].name, "get_user_info");
         assert_eq!(tools[2].name, "query");
-        assert_eq!(tools[3].name, "add_reaction");
-        assert_eq!(tools[4].name, "delete_message");
-        assert_eq!(tools[5].name, "mute_user");
-        assert_eq!(tools[6].name, "ban_user");
-        assert_eq!(tools[7].name, "kick_user");
-        assert_eq!(tools[8].name, "get_chat_admins");
-        assert_eq!(tools[9].name, "get_members");
-        assert_eq!(tools[10].name, "import_members");
-        assert_eq!(tools[11].name, "send_photo");
-        assert_eq!(tools[12].name, "send_voice");
-        assert_eq!(tools[13].name, "create_memory");
-        assert_eq!(tools[14].name, "read_memory");
-        assert_eq!(tools[15].name, "edit_memory");
-        assert_eq!(tools[16].name, "list_memories");
-        assert_eq!(tools[17].name, "search_memories");
-        assert_eq!(tools[18].name, "delete_memory");
-        assert_eq!(tools[19].name, "report_bug");
-        assert_eq!(tools[20].name, "youtube_info");
-        assert_eq!(tools[21].name, "noop");
-        assert_eq!(tools[22].name, "set_reminder");
-        assert_eq!(tools[23].name, "list_reminders");
-        assert_eq!(tools[24].name, "cancel_reminder");
+        assert_eq!(tools[3].name, "read_messages");
+        assert_eq!(tools[4].name, "add_reaction");
+        assert_eq!(tools[5].name, "delete_message");
+        assert_eq!(tools[6].name, "mute_user");
+        assert_eq!(tools[7].name, "unmute_user");
+        assert_eq!(tools[8].name, "ban_user");
+        assert_eq!(tools[9].name, "kick_user");
+        assert_eq!(tools[10].name, "get_chat_admins");
+        assert_eq!(tools[11].name, "get_rules");
+        assert_eq!(tools[12].name, "create_invite_link");
+        assert_eq!(tools[13].name, "revoke_invite_link");
+        assert_eq!(tools[14].name, "get_members");
+        assert_eq!(tools[15].name, "import_members");
+        assert_eq!(tools[16].name, "send_photo");
+        assert_eq!(tools[17].name, "send_voice");
+        assert_eq!(tools[18].name, "send_document");
+        assert_eq!(tools[19].name, "send_rubric_docx");
+        assert_eq!(tools[20].name, "create_memory");
+        assert_eq!(tools[21].name, "read_memory");
+        assert_eq!(tools[22].name, "edit_memory");
+        assert_eq!(tools[23].name, "list_memories");
+        assert_eq!(tools[24].name, "search_memories");
+        assert_eq!(tools[25].name, "delete_memory");
+        assert_eq!(tools[26].name, "report_bug");
+        assert_eq!(tools[27].name, "youtube_info");
+        assert_eq!(tools[28].name, "wiki_lookup");
+        assert_eq!(tools[29].name, "translate");
+        assert_eq!(tools[30].name, "delegate");
+        assert_eq!(tools[31].name, "get_thread");
+        assert_eq!(tools[32].name, "chat_stats");
+        assert_eq!(tools[33].name, "top_messages");
+        assert_eq!(tools[34].name, "send_dice");
+        assert_eq!(tools[35].name, "send_location");
+        assert_eq!(tools[36].name, "noop");
+        assert_eq!(tools[37].name, "set_reminder");
+        assert_eq!(tools[38].name, "list_reminders");
+        assert_eq!(tools[39].name, "cancel_reminder");
+        assert_eq!(tools[40].name, "send_later");
+        assert_eq!(tools[41].name, "cancel_send_later");
+        assert_eq!(tools[42].name, "follow_up");
         // Signal tracking tools
-        assert_eq!(tools[25].name, "add_signal");
-        assert_eq!(tools[26].name, "update_signal");
-        assert_eq!(tools[27].name, "list_signals");
+        assert_eq!(tools[43].name, "add_signal");
+        assert_eq!(tools[44].name, "update_signal");
+        assert_eq!(tools[45].name, "list_signals");
         // Admin tools
-        assert_eq!(tools[28].name, "add_trusted_user");
-        assert_eq!(tools[29].name, "remove_trusted_user");
-        assert_eq!(tools[30].name, "done");
+        assert_eq!(tools[46].name, "add_trusted_user");
+        assert_eq!(tools[47].name, "remove_trusted_user");
+        assert_eq!(tools[48].name, "export_transcript");
+        assert_eq!(tools[49].name, "set_personality");
+        assert_eq!(tools[50].name, "relay_to_group");
+        assert_eq!(tools[51].name, "set_slow_mode");
+        assert_eq!(tools[52].name, "set_chat_permissions");
+        assert_eq!(tools[53].name, "bulk_delete");
+        assert_eq!(tools[54].name, "done");
     }
 }
@@ -0,0 +1,235 @@
+//! Readable chat transcripts (Markdown or HTML) for the owner's records.
+//!
+//! Messages are written one at a time as they're read from the database, so
+//! exporting a large range never builds the whole transcript in memory.
+
+use std::io::Write;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::chatbot::message::ChatMessage;
+use crate::chatbot::output_guard::strip_html;
+
+/// Most messages in one export; longer ranges are cut off with a warning.
+pub const MAX_EXPORT_MESSAGES: usize = 50_000;
+
+/// Quoted reply text is cut to this many chars.
+const REPLY_QUOTE_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Html,
+}
+
+impl TranscriptFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            other => Err(format!("Unknown format '{}' (expected \"markdown\" or \"html\")", other)),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// Parse an export range bound ("YYYY-MM-DD" or "YYYY-MM-DD HH:MM") into the
+/// database timestamp format. A bare end date covers the whole day.
+pub fn parse_range_bound(value: &str, is_end: bool) -> Result<String, String> {
+    let value = value.trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M") {
+        return Ok(dt.format("%Y-%m-%d %H:%M").to_string());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}' (expected YYYY-MM-DD or YYYY-MM-DD HH:MM)", value))?;
+    Ok(format!("{} {}", date.format("%Y-%m-%d"), if is_end { "23:59" } else { "00:00" }))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Message text as plain text (bot messages are stored with Telegram HTML).
+fn plain_text(msg: &ChatMessage) -> String {
+    if msg.text.trim().is_empty() {
+        "[media]".to_string()
+    } else {
+        strip_html(&msg.text)
+    }
+}
+
+/// Writes a transcript incrementally.
+pub struct TranscriptWriter<W: Write> {
+    out: W,
+    format: TranscriptFormat,
+    written: usize,
+}
+
+impl<W: Write> TranscriptWriter<W> {
+    /// Start a transcript, writing its header.
+    pub fn new(mut out: W, format: TranscriptFormat, chat_id: i64, from: &str, to: &str) -> Result<Self, String> {
+        let header = match format {
+            TranscriptFormat::Markdown => format!("# Chat {} transcript\n\n{} – {}\n\n", chat_id, from, to),
+            TranscriptFormat::Html => format!(
+                "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Chat {0} transcript</title></head><body>\n\
+                 <h1>Chat {0} transcript</h1>\n<p>{1} – {2}</p>\n",
+                chat_id, html_escape(from), html_escape(to)
+            ),
+        };
+        out.write_all(header.as_bytes()).map_err(|e| format!("Failed to write transcript: {e}"))?;
+        Ok(Self { out, format, written: 0 })
+    }
+
+    pub fn write_message(&mut self, msg: &ChatMessage) -> Result<(), String> {
+        let text = plain_text(msg);
+        let reply = msg.reply_to.as_ref().map(|r| {
+            let quote: String = strip_html(&r.text).chars().take(REPLY_QUOTE_CHARS).collect();
+            (r.message_id, r.username.as_str(), quote)
+        });
+
+        let entry = match self.format {
+            TranscriptFormat::Markdown => {
+                let mut entry = format!("**{}** · {} · #{}\n", msg.username, msg.timestamp, msg.message_id);
+                if let Some((id, username, quote)) = reply {
+                    entry.push_str(&format!("> ↩ {} (#{}): {}\n", username, id, quote));
+                }
+                entry.push_str(&format!("{}\n\n", text));
+                entry
+            }
+            TranscriptFormat::Html => {
+                let reply_html = reply
+                    .map(|(id, username, quote)| format!(
+                        "<blockquote>↩ {} (#{}): {}</blockquote>",
+                        html_escape(username), id, html_escape(&quote)
+                    ))
+                    .unwrap_or_default();
+                format!(
+                    "<div class=\"msg\" id=\"m{}\"><b>{}</b> <small>{} · #{}</small>{}<p>{}</p></div>\n",
+                    msg.message_id, html_escape(&msg.username), html_escape(&msg.timestamp),
+                    msg.message_id, reply_html, html_escape(&text).replace('\n', "<br>")
+                )
+            }
+        };
+        self.out.write_all(entry.as_bytes()).map_err(|e| format!("Failed to write transcript: {e}"))?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Write the footer (with a warning if the export was cut off) and return
+    /// the number of messages written.
+    pub fn finish(mut self, truncated: bool) -> Result<usize, String> {
+        let warning = truncated.then(|| format!(
+            "Export stopped at {} messages; narrow the date range for the rest.",
+            MAX_EXPORT_MESSAGES
+        ));
+        let footer = match self.format {
+            TranscriptFormat::Markdown => {
+                let mut footer = format!("---\n{} messages\n", self.written);
+                if let Some(w) = warning {
+                    footer.push_str(&format!("\n⚠️ {}\n", w));
+                }
+                footer
+            }
+            TranscriptFormat::Html => {
+                let mut footer = format!("<hr><p>{} messages</p>\n", self.written);
+                if let Some(w) = warning {
+                    footer.push_str(&format!("<p><b>⚠️ {}</b></p>\n", w));
+                }
+                footer.push_str("</body></html>\n");
+                footer
+            }
+        };
+        self.out.write_all(footer.as_bytes()).map_err(|e| format!("Failed to write transcript: {e}"))?;
+        self.out.flush().map_err(|e| format!("Failed to write transcript: {e}"))?;
+        Ok(self.written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::message::ReplyTo;
+
+    fn fixture() -> Vec<ChatMessage> {
+        let mut first = ChatMessage::system("Anyone up for <b>lunch</b> & coffee?".to_string());
+        first.message_id = 10;
+        first.chat_id = -100;
+        first.user_id = 1;
+        first.username = "alice".to_string();
+        first.timestamp = "2024-01-15 12:00".to_string();
+
+        let mut photo = first.clone();
+        photo.message_id = 11;
+        photo.username = "bob".to_string();
+        photo.timestamp = "2024-01-15 12:01".to_string();
+        photo.text = String::new();
+
+        let mut reply = first.clone();
+        reply.message_id = 12;
+        reply.username = "Claudima".to_string();
+        reply.timestamp = "2024-01-15 12:02".to_string();
+        reply.text = "<b>Count me in</b>".to_string();
+        reply.reply_to = Some(ReplyTo {
+            message_id: 10,
+            username: "alice".to_string(),
+            text: "Anyone up for lunch?".to_string(),
+            unknown_origin: false,
+        });
+
+        vec![first, photo, reply]
+    }
+
+    fn render(format: TranscriptFormat, truncated: bool) -> String {
+        let mut out = Vec::new();
+        let mut writer = TranscriptWriter::new(&mut out, format, -100, "2024-01-15 00:00", "2024-01-15 23:59").unwrap();
+        for msg in fixture() {
+            writer.write_message(&msg).unwrap();
+        }
+        assert_eq!(writer.finish(truncated).unwrap(), 3);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_markdown_transcript() {
+        let md = render(TranscriptFormat::Markdown, false);
+        assert!(md.starts_with("# Chat -100 transcript\n\n2024-01-15 00:00 – 2024-01-15 23:59\n\n"));
+        assert!(md.contains("**alice** · 2024-01-15 12:00 · #10\nAnyone up for lunch & coffee?\n"));
+        assert!(md.contains("**bob** · 2024-01-15 12:01 · #11\n[media]\n"));
+        assert!(md.contains("**Claudima** · 2024-01-15 12:02 · #12\n> ↩ alice (#10): Anyone up for lunch?\nCount me in\n"));
+        assert!(md.ends_with("---\n3 messages\n"));
+    }
+
+    #[test]
+    fn test_html_transcript_escapes() {
+        let html = render(TranscriptFormat::Html, false);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<p>Anyone up for lunch &amp; coffee?</p>"));
+        assert!(html.contains("<blockquote>↩ alice (#10): Anyone up for lunch?</blockquote><p>Count me in</p>"));
+        assert!(html.ends_with("</body></html>\n"));
+    }
+
+    #[test]
+    fn test_truncation_warning() {
+        assert!(!render(TranscriptFormat::Markdown, false).contains("Export stopped"));
+        assert!(render(TranscriptFormat::Markdown, true).contains("⚠️ Export stopped at 50000 messages"));
+        assert!(render(TranscriptFormat::Html, true).contains("<b>⚠️ Export stopped at 50000 messages"));
+    }
+
+    #[test]
+    fn test_parse_range_and_format() {
+        assert_eq!(parse_range_bound("2024-01-15", false).unwrap(), "2024-01-15 00:00");
+        assert_eq!(parse_range_bound("2024-01-15", true).unwrap(), "2024-01-15 23:59");
+        assert_eq!(parse_range_bound(" 2024-01-15 08:30 ", true).unwrap(), "2024-01-15 08:30");
+        assert!(parse_range_bound("15.01.2024", false).is_err());
+
+        assert_eq!(TranscriptFormat::parse("HTML").unwrap(), TranscriptFormat::Html);
+        assert_eq!(TranscriptFormat::parse("md").unwrap().extension(), "md");
+        assert!(TranscriptFormat::parse("pdf").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Daily transparency post: what the bot did as a moderator the day before.
+//!
+//! Opt-in with `transparency_hour`. Once a day the primary chat (or
+//! `transparency_chat_id`) gets a short summary of the previous day's
+//! `moderation_log` (deletions, mutes, kicks and bans, never message
+//! contents) and how many messages the spam filter deleted. With
+//! `transparency_anonymize`, members whose messages were deleted are only
+//! "a member"; muted, kicked and banned users are always named. Days with
+//! nothing to report are skipped. The last day reported is kept in the data
+//! dir, so restarts don't repeat it; a post that fails is retried. During
+//! quiet hours the post is queued until they end.
+
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use tracing::warn;
+
+use super::database::ModerationEntry;
+use super::html;
+
+/// Last day reported, in the data dir.
+pub const STATE_FILE: &str = "transparency_date";
+
+/// How long to wait before trying a failed post again.
+pub const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+/// Start and end (UTC) of `day` in `tz`.
+pub fn day_bounds(day: NaiveDate, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = |day: NaiveDate| {
+        day.and_time(NaiveTime::MIN)
+            .and_local_timezone(tz)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            // Midnight skipped by a DST change: close enough
+            .unwrap_or_else(|| day.and_time(NaiveTime::MIN).and_utc())
+    };
+    (start(day), start(day.succ_opt().unwrap_or(day)))
+}
+
+/// What an action is called in the post.
+fn label(action: &str) -> &str {
+    match action {
+        "delete" => "message deleted",
+        "mute" => "muted",
+        "kick" => "removed",
+        "ban" => "banned",
+        other => other,
+    }
+}
+
+/// Who an action was taken against.
+fn who(entry: &ModerationEntry, anonymize: bool) -> String {
+    if anonymize && entry.action == "delete" {
+        return "a member".to_string();
+    }
+    match (&entry.username, &entry.first_name) {
+        (Some(username), _) => format!("@{}", username),
+        (None, Some(first_name)) => first_name.clone(),
+        (None, None) => format!("user {}", entry.user_id),
+    }
+}
+
+/// The post for `day`: the actions of that day's moderation log, oldest
+/// first, and the spam filter's deletions. None when there were neither.
+pub fn compile(day: NaiveDate, actions: &[ModerationEntry], spam_deleted: usize, anonymize: bool) -> Option<String> {
+    if actions.is_empty() && spam_deleted == 0 {
+        return None;
+    }
+    let count = |action: &str| actions.iter().filter(|a| a.action == action).count();
+    let mut out = format!(
+        "🛡 <b>Moderation on {}</b>\nSpam deleted automatically: {}\nDeleted: {} · Muted: {} · Removed: {} · Banned: {}\n",
+        day.format("%Y-%m-%d"),
+        spam_deleted,
+        count("delete"),
+        count("mute"),
+        count("kick"),
+        count("ban"),
+    );
+
+    // One line per person and action, repeats counted
+    let mut lines: Vec<(String, &str, usize)> = Vec::new();
+    for entry in actions {
+        let name = who(entry, anonymize);
+        match lines.iter_mut().find(|(n, action, _)| *n == name && *action == entry.action) {
+            Some((_, _, times)) => *times += 1,
+            None => lines.push((name, &entry.action, 1)),
+        }
+    }
+    if !lines.is_empty() {
+        out.push('\n');
+    }
+    for (name, action, times) in lines {
+        let repeat = if times > 1 { format!(" ×{}", times) } else { String::new() };
+        out.push_str(&format!("• {}: {}{}\n", html::escape(&name), label(action), repeat));
+    }
+    Some(out.trim_end().to_string())
+}
+
+/// Whether `day` was already reported.
+pub fn already_sent(data_dir: &Path, day: NaiveDate) -> bool {
+    std::fs::read_to_string(data_dir.join(STATE_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<NaiveDate>().ok())
+        .is_some_and(|last| last >= day)
+}
+
+/// Record that `day` was reported.
+pub fn mark_sent(data_dir: &Path, day: NaiveDate) {
+    if let Err(e) = std::fs::write(data_dir.join(STATE_FILE), day.to_string()) {
+        warn!("Failed to record the transparency post date: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(user_id: i64, username: Option<&str>, action: &str) -> ModerationEntry {
+        ModerationEntry {
+            user_id,
+            username: username.map(String::from),
+            first_name: Some(format!("First{}", user_id)),
+            action: action.to_string(),
+        }
+    }
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+    }
+
+    #[test]
+    fn test_post_format() {
+        let actions = [
+            entry(1, Some("spammy"), "delete"),
+            entry(1, Some("spammy"), "delete"),
+            entry(2, None, "mute"),
+            entry(1, Some("spammy"), "ban"),
+            entry(3, Some("<b>x</b>"), "kick"),
+        ];
+        assert_eq!(
+            compile(day(), &actions, 12, false).unwrap(),
+            "🛡 <b>Moderation on 2024-03-10</b>\n\
+             Spam deleted automatically: 12\n\
+             Deleted: 2 · Muted: 1 · Removed: 1 · Banned: 1\n\
+             \n\
+             • @spammy: message deleted ×2\n\
+             • First2: muted\n\
+             • @spammy: banned\n\
+             • @&lt;b&gt;x&lt;/b&gt;: removed"
+        );
+    }
+
+    #[test]
+    fn test_anonymized_deletions() {
+        let actions = [entry(1, Some("alice"), "delete"), entry(2, Some("bob"), "delete"), entry(2, Some("bob"), "ban")];
+        let post = compile(day(), &actions, 0, true).unwrap();
+        assert!(post.ends_with("• a member: message deleted ×2\n• @bob: banned"), "{}", post);
+        assert!(!post.contains("alice"));
+    }
+
+    #[test]
+    fn test_nothing_to_report() {
+        assert_eq!(compile(day(), &[], 0, true), None);
+        let spam_only = compile(day(), &[], 3, true).unwrap();
+        assert!(spam_only.ends_with("Spam deleted automatically: 3\nDeleted: 0 · Muted: 0 · Removed: 0 · Banned: 0"));
+    }
+
+    #[test]
+    fn test_day_bounds() {
+        let (start, end) = day_bounds(day(), chrono_tz::Europe::Moscow);
+        assert_eq!(start.to_rfc3339(), "2024-03-09T21:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-10T21:00:00+00:00");
+    }
+}
@@ -0,0 +1,213 @@
+//! Short-lived caches for Telegram lookups.
+//!
+//! Claude looks up users and admin lists on almost every turn, and each
+//! lookup is a Telegram round trip that counts toward flood limits. Results
+//! are kept for a while and dropped early when a `chat_member` update says
+//! they changed. Hits and misses are counted for `/status`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Hit and miss counts of one cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} hits, {} misses, {} cached", self.hits, self.misses, self.entries)
+    }
+}
+
+struct Entries<K, V> {
+    map: HashMap<K, (Instant, V)>,
+    /// Total weight of the values in `map`.
+    weight: usize,
+    hits: u64,
+    misses: u64,
+}
+
+/// Values kept for `ttl` after they were fetched, up to `max_weight` in
+/// total. Each value weighs 1 unless the cache was built with `weighted`.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    max_weight: usize,
+    weigh: fn(&V) -> usize,
+    entries: Mutex<Entries<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    /// A cache holding at most `max_entries` values.
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self::weighted(ttl, max_entries, |_| 1)
+    }
+
+    /// A cache holding values up to a total of `max_weight` by `weigh`
+    /// (e.g. bytes).
+    pub fn weighted(ttl: Duration, max_weight: usize, weigh: fn(&V) -> usize) -> Self {
+        let entries = Entries { map: HashMap::new(), weight: 0, hits: 0, misses: 0 };
+        Self { ttl, max_weight, weigh, entries: Mutex::new(entries) }
+    }
+
+    /// The value for `key` if it was fetched within the TTL as of `now`.
+    pub fn get(&self, key: &K, now: Instant) -> Option<V> {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        let fresh = entries.map.get(key)
+            .filter(|(at, _)| now.saturating_duration_since(*at) < self.ttl)
+            .map(|(_, value)| value.clone());
+        match fresh {
+            Some(_) => entries.hits += 1,
+            None => entries.misses += 1,
+        }
+        fresh
+    }
+
+    /// Store `value`, evicting expired values and then the oldest ones until
+    /// it fits. Values heavier than the whole cache aren't stored.
+    pub fn insert(&self, key: K, value: V, now: Instant) {
+        let weight = (self.weigh)(&value);
+        if weight > self.max_weight {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        let entries = &mut *entries;
+        if let Some((_, old)) = entries.map.remove(&key) {
+            entries.weight -= (self.weigh)(&old);
+        }
+
+        let ttl = self.ttl;
+        let weigh = self.weigh;
+        let mut freed = 0;
+        entries.map.retain(|_, (at, v)| {
+            let keep = now.saturating_duration_since(*at) < ttl;
+            if !keep {
+                freed += weigh(v);
+            }
+            keep
+        });
+        entries.weight -= freed;
+
+        while entries.weight + weight > self.max_weight {
+            let Some(oldest) = entries.map.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone()) else {
+                break;
+            };
+            if let Some((_, old)) = entries.map.remove(&oldest) {
+                entries.weight -= weigh(&old);
+            }
+        }
+
+        entries.weight += weight;
+        entries.map.insert(key, (now, value));
+    }
+
+    /// Drop every value whose key matches.
+    pub fn invalidate(&self, matches: impl Fn(&K) -> bool) {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        let entries = &mut *entries;
+        let weigh = self.weigh;
+        let mut freed = 0;
+        entries.map.retain(|k, (_, v)| {
+            let keep = !matches(k);
+            if !keep {
+                freed += weigh(v);
+            }
+            keep
+        });
+        entries.weight -= freed;
+    }
+
+    /// The cached value for `key`, or the result of `fetch`, stored on success.
+    pub async fn get_or_fetch<F, Fut>(&self, key: K, fetch: F) -> Result<V, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, String>>,
+    {
+        if let Some(value) = self.get(&key, Instant::now()) {
+            return Ok(value);
+        }
+        let value = fetch().await?;
+        self.insert(key, value.clone(), Instant::now());
+        Ok(value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().expect("cache lock poisoned");
+        CacheStats { hits: entries.hits, misses: entries.misses, entries: entries.map.len() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Stands in for the Telegram API, counting calls.
+    struct MockApi {
+        calls: AtomicU32,
+    }
+
+    impl MockApi {
+        async fn get_chat_member(&self, chat_id: i64, user_id: i64) -> Result<String, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("member {} of {}", user_id, chat_id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_lookup_is_cached_until_invalidated() {
+        let api = MockApi { calls: AtomicU32::new(0) };
+        let cache: TtlCache<(i64, i64), String> = TtlCache::new(Duration::from_secs(600), 100);
+
+        let first = cache.get_or_fetch((-100, 1), || api.get_chat_member(-100, 1)).await.unwrap();
+        let second = cache.get_or_fetch((-100, 1), || api.get_chat_member(-100, 1)).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(api.calls.load(Ordering::SeqCst), 1);
+        cache.get_or_fetch((-100, 2), || api.get_chat_member(-100, 2)).await.unwrap();
+        assert_eq!(api.calls.load(Ordering::SeqCst), 2);
+
+        // A member update drops only that member
+        cache.invalidate(|&(chat, user)| chat == -100 && user == 1);
+        cache.get_or_fetch((-100, 1), || api.get_chat_member(-100, 1)).await.unwrap();
+        cache.get_or_fetch((-100, 2), || api.get_chat_member(-100, 2)).await.unwrap();
+        assert_eq!(api.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 3, entries: 2 });
+
+        // Failures aren't cached
+        let failed = cache.get_or_fetch((-100, 3), || async { Err("Bad Request".to_string()) }).await;
+        assert!(failed.is_err());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let start = Instant::now();
+        let cache = TtlCache::new(Duration::from_secs(600), 100);
+        cache.insert(-100, "admins", start);
+        assert_eq!(cache.get(&-100, start + Duration::from_secs(599)), Some("admins"));
+        assert_eq!(cache.get(&-100, start + Duration::from_secs(600)), None);
+    }
+
+    #[test]
+    fn test_weight_cap_evicts_oldest() {
+        let start = Instant::now();
+        let cache: TtlCache<i64, Vec<u8>> = TtlCache::weighted(Duration::from_secs(3600), 10, Vec::len);
+        cache.insert(1, vec![0; 4], start);
+        cache.insert(2, vec![0; 4], start + Duration::from_secs(1));
+        cache.insert(3, vec![0; 4], start + Duration::from_secs(2));
+        let now = start + Duration::from_secs(3);
+        assert_eq!(cache.get(&1, now), None);
+        assert!(cache.get(&2, now).is_some());
+        assert!(cache.get(&3, now).is_some());
+
+        // Too big for the whole cache: not stored, nothing evicted
+        cache.insert(4, vec![0; 11], now);
+        assert_eq!(cache.get(&4, now), None);
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
@@ -4,9 +4,43 @@
 //! Requires running the XTTS server: `python scripts/xtts_server.py`
 
 use std::process::Command;
+use std::time::Duration;
 
 use serde::Deserialize;
-use tracing::{debug, info, warn};
+use tracing::{debug, info};
+
+use super::telegram::ApiFuture;
+use super::voices::VoiceSource;
+
+/// Time limit on listing voices, so a hung server doesn't stall startup.
+const LIST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What becomes of the text of a voice message the bot sends
+/// (`voice_include_transcript`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceTranscript {
+    /// Nothing: only the audio exists.
+    Off,
+    /// Stored as the bot's message, so Claude and the history see what was said.
+    StoreOnly,
+    /// Stored, and also sent as a text reply to the voice message.
+    Always,
+}
+
+impl VoiceTranscript {
+    /// Parse a `voice_include_transcript` config value.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "store_only" => Ok(Self::StoreOnly),
+            "always" => Ok(Self::Always),
+            other => Err(format!(
+                "invalid value '{}' (expected \"off\", \"store_only\" or \"always\")",
+                other
+            )),
+        }
+    }
+}
 
 /// Response from /v1/references/list endpoint.
 #[derive(Debug, Deserialize)]
@@ -33,33 +67,6 @@ impl TtsClient {
         }
     }
 
-    /// Get list of available voice reference IDs from Fish Speech.
-    pub async fn list_voices(&self) -> Vec<String> {
-        match self.client
-            .get(format!("{}/v1/references/list", self.endpoint))
-            .header("Accept", "application/json")
-            .send()
-            .await
-        {
-            Ok(response) => {
-                if response.status().is_success()
-                    && let Ok(resp) = response.json::<ListReferencesResponse>().await
-                {
-                    if resp.success {
-                        return resp.reference_ids;
-                    }
-                    warn!("Voice list API returned success=false");
-                }
-                warn!("Failed to parse voice list response");
-                vec![]
-            }
-            Err(e) => {
-                warn!("Failed to fetch voice list: {}", e);
-                vec![]
-            }
-        }
-    }
-
     /// Generate speech from text.
     ///
     /// Returns OGG Opus audio data suitable for Telegram voice messages.
@@ -105,6 +112,32 @@ impl TtsClient {
     }
 }
 
+impl VoiceSource for TtsClient {
+    /// Available voice reference IDs.
+    fn list_voices(&self) -> ApiFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let response = self.client
+                .get(format!("{}/v1/references/list", self.endpoint))
+                .header("Accept", "application/json")
+                .timeout(LIST_TIMEOUT)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to fetch voice list: {e}"))?;
+            if !response.status().is_success() {
+                return Err(format!("Voice list error {}", response.status()));
+            }
+            let resp = response
+                .json::<ListReferencesResponse>()
+                .await
+                .map_err(|e| format!("Failed to parse voice list response: {e}"))?;
+            if !resp.success {
+                return Err("Voice list API returned success=false".to_string());
+            }
+            Ok(resp.reference_ids)
+        })
+    }
+}
+
 /// Convert WAV audio to OGG Opus format for Telegram voice messages.
 fn convert_wav_to_ogg(wav_data: &[u8]) -> Result<Vec<u8>, String> {
     // Write WAV to temp file
@@ -165,4 +198,13 @@ mod tests {
         let client = TtsClient::new("http://localhost:8880".to_string());
         assert_eq!(client.endpoint, "http://localhost:8880");
     }
+
+    #[test]
+    fn test_voice_transcript_parse() {
+        use super::VoiceTranscript;
+        assert_eq!(VoiceTranscript::parse("off"), Ok(VoiceTranscript::Off));
+        assert_eq!(VoiceTranscript::parse("store_only"), Ok(VoiceTranscript::StoreOnly));
+        assert_eq!(VoiceTranscript::parse(" Always "), Ok(VoiceTranscript::Always));
+        assert!(VoiceTranscript::parse("yes").is_err());
+    }
 }
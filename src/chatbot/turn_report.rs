@@ -0,0 +1,280 @@
+//! Per-turn summary of a debounce cycle: what was sent to Claude, which tools
+//! ran and how long they took, compactions, cost, and outcome.
+//!
+//! Emitted as one structured tracing event, appended to `turns.jsonl` and
+//! added to the metrics.
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::info;
+
+use super::metrics;
+
+/// Rotate `turns.jsonl` once it reaches this size; one old file is kept.
+pub const TURNS_LOG_MAX_BYTES: u64 = 5 * 1024 * 1024;
+
+/// A single executed tool call.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    pub duration_ms: u64,
+    pub is_error: bool,
+}
+
+/// Everything that happened in one debounce turn.
+#[derive(Debug, Clone, Serialize)]
+pub struct TurnReport {
+    /// RFC 3339 start time.
+    pub started_at: String,
+    pub messages: usize,
+    pub chars_sent: usize,
+    pub images: usize,
+    /// Tool calls per iteration of the tool loop.
+    pub iterations: Vec<Vec<ToolCallRecord>>,
+    pub compactions: usize,
+    /// Responses without tool JSON (the model answered in prose).
+    pub prose_replies: usize,
+    /// Sum of the costs Claude Code reported for each response.
+    pub cost_usd: f64,
+    pub duration_ms: u64,
+    /// "done", "no_response", "gave_up", "no_tool_json", "max_iterations" or "error".
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl TurnReport {
+    pub fn new() -> Self {
+        Self {
+            started_at: chrono::Utc::now().to_rfc3339(),
+            messages: 0,
+            chars_sent: 0,
+            images: 0,
+            iterations: Vec::new(),
+            compactions: 0,
+            prose_replies: 0,
+            cost_usd: 0.0,
+            duration_ms: 0,
+            outcome: "error",
+            error: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Account for a response from Claude.
+    pub fn add_response(&mut self, cost_usd: f64, compacted: bool) {
+        self.cost_usd += cost_usd;
+        if compacted {
+            self.compactions += 1;
+        }
+    }
+
+    /// Record a tool call in the current iteration.
+    pub fn add_tool_call(&mut self, tool: String, duration: Duration, is_error: bool) {
+        if let Some(iteration) = self.iterations.last_mut() {
+            iteration.push(ToolCallRecord {
+                tool,
+                duration_ms: duration.as_millis() as u64,
+                is_error,
+            });
+        }
+    }
+
+    /// Start a new iteration of the tool loop.
+    pub fn start_iteration(&mut self) {
+        self.iterations.push(Vec::new());
+    }
+
+    /// Stop the clock and set the outcome.
+    pub fn finish(&mut self, result: &Result<(), String>) {
+        self.duration_ms = self.started.elapsed().as_millis() as u64;
+        if let Err(e) = result {
+            self.outcome = "error";
+            self.error = Some(e.clone());
+        }
+    }
+
+    /// Whether `tool` ran without error in this turn.
+    pub fn used(&self, tool: &str) -> bool {
+        self.iterations.iter().flatten().any(|t| t.tool == tool && !t.is_error)
+    }
+
+    fn tool_call_count(&self) -> usize {
+        self.iterations.iter().map(Vec::len).sum()
+    }
+
+    fn tool_error_count(&self) -> usize {
+        self.iterations.iter().flatten().filter(|t| t.is_error).count()
+    }
+
+    /// Emit the report as a single structured tracing event and record it in
+    /// the metrics.
+    pub fn emit(&self) {
+        metrics::inc(&metrics::TURNS, &[("outcome", self.outcome)]);
+        metrics::observe(&metrics::TURN_DURATION, &[], self.duration_ms as f64 / 1000.0);
+        metrics::add(&metrics::COST, &[], self.cost_usd);
+        for call in self.iterations.iter().flatten() {
+            let result = if call.is_error { "error" } else { "ok" };
+            metrics::inc(&metrics::TOOL_CALLS, &[("tool", &call.tool), ("result", result)]);
+        }
+
+        let tools: Vec<&str> = self.iterations.iter().flatten().map(|t| t.tool.as_str()).collect();
+        info!(
+            messages = self.messages,
+            chars_sent = self.chars_sent,
+            images = self.images,
+            iterations = self.iterations.len(),
+            tool_calls = self.tool_call_count(),
+            tool_errors = self.tool_error_count(),
+            tools = %tools.join(","),
+            compactions = self.compactions,
+            prose_replies = self.prose_replies,
+            cost_usd = self.cost_usd,
+            duration_ms = self.duration_ms,
+            outcome = self.outcome,
+            error = self.error.as_deref(),
+            "turn"
+        );
+    }
+
+    /// Serialize as a single JSON line (no trailing newline).
+    pub fn to_json_line(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| format!("Failed to serialize turn report: {e}"))
+    }
+
+    /// Append to a JSONL file, rotating it to `<name>.1` once it reaches `max_bytes`.
+    pub fn append_jsonl(&self, path: &Path, max_bytes: u64) -> Result<(), String> {
+        let line = self.to_json_line()?;
+
+        if let Ok(meta) = std::fs::metadata(path)
+            && meta.len() >= max_bytes
+        {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(".1");
+            std::fs::rename(path, &rotated).map_err(|e| format!("Failed to rotate {:?}: {e}", path))?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {:?}: {e}", path))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write {:?}: {e}", path))
+    }
+}
+
+impl Default for TurnReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for TurnReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} msg(s), {} tool call(s) ({} failed) in {} iteration(s), {:.1}s, ${:.4}",
+            self.outcome,
+            self.messages,
+            self.tool_call_count(),
+            self.tool_error_count(),
+            self.iterations.len(),
+            self.duration_ms as f64 / 1000.0,
+            self.cost_usd
+        )?;
+        if self.compactions > 0 {
+            write!(f, ", {} compaction(s)", self.compactions)?;
+        }
+        if self.prose_replies > 0 {
+            write!(f, ", {} prose reply(ies)", self.prose_replies)?;
+        }
+        if let Some(ref e) = self.error {
+            write!(f, " - {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_report() -> TurnReport {
+        let mut report = TurnReport::new();
+        report.messages = 2;
+        report.chars_sent = 340;
+        report.add_response(0.012, false);
+        report.start_iteration();
+        report.add_tool_call("query".to_string(), Duration::from_millis(15), false);
+        report.add_tool_call("send_message".to_string(), Duration::from_millis(120), true);
+        report.add_response(0.008, true);
+        report.start_iteration();
+        report.add_tool_call("done".to_string(), Duration::ZERO, false);
+        report.outcome = "done";
+        report.finish(&Ok(()));
+        report
+    }
+
+    #[test]
+    fn test_json_line_fields() {
+        let line = sample_report().to_json_line().unwrap();
+        assert!(!line.contains('\n'));
+
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["messages"], 2);
+        assert_eq!(v["chars_sent"], 340);
+        assert_eq!(v["compactions"], 1);
+        assert_eq!(v["outcome"], "done");
+        assert!((v["cost_usd"].as_f64().unwrap() - 0.02).abs() < 1e-9);
+        assert_eq!(v["iterations"][0][1]["tool"], "send_message");
+        assert_eq!(v["iterations"][0][1]["duration_ms"], 120);
+        assert_eq!(v["iterations"][0][1]["is_error"], true);
+        assert!(v.get("error").is_none());
+        assert!(v.get("started").is_none());
+    }
+
+    #[test]
+    fn test_finish_with_error() {
+        let mut report = TurnReport::new();
+        report.outcome = "done";
+        report.finish(&Err("Claude error: closed".to_string()));
+
+        let v: serde_json::Value = serde_json::from_str(&report.to_json_line().unwrap()).unwrap();
+        assert_eq!(v["outcome"], "error");
+        assert_eq!(v["error"], "Claude error: closed");
+    }
+
+    #[test]
+    fn test_append_jsonl_rotates_at_cap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("turns.jsonl");
+        let rotated = dir.path().join("turns.jsonl.1");
+        let report = sample_report();
+        let line_len = report.to_json_line().unwrap().len() as u64 + 1;
+
+        // Cap fits two lines: third append rotates
+        let cap = line_len * 2;
+        report.append_jsonl(&path, cap).unwrap();
+        report.append_jsonl(&path, cap).unwrap();
+        assert!(!rotated.exists());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        report.append_jsonl(&path, cap).unwrap();
+        assert_eq!(std::fs::read_to_string(&rotated).unwrap().lines().count(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_display_summary() {
+        let summary = sample_report().to_string();
+        assert!(summary.starts_with("done: 2 msg(s), 3 tool call(s) (1 failed) in 2 iteration(s)"));
+        assert!(summary.contains("$0.0200"));
+        assert!(summary.contains("1 compaction(s)"));
+    }
+}
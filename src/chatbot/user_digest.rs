@@ -0,0 +1,199 @@
+//! Daily digest of the most active users, for their memory files.
+//!
+//! The prompt asks Claude to keep `memories/users/*.md` up to date, but in
+//! a busy chat it rarely stops to. Once a day, for the top posters of the
+//! last 24 hours, this builds a short digest (message count, frequent words,
+//! longer messages) without any model call, and hands it to Claude in one
+//! system message so updating a profile takes a single memory edit. The date
+//! of the last digest is kept in the data dir, so restarts don't repeat it.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use tracing::warn;
+
+use super::database::PosterCount;
+use super::message::truncate_safe;
+
+/// Last digest date, in the data dir.
+pub const STATE_FILE: &str = "user_digest_date";
+
+/// Keywords listed per user.
+const MAX_KEYWORDS: usize = 8;
+/// Messages longer than this (in chars) are notable.
+const NOTABLE_CHARS: usize = 200;
+/// Notable messages quoted per user, the longest ones.
+const MAX_NOTABLE: usize = 2;
+/// Bytes kept of a notable message.
+const NOTABLE_EXCERPT_BYTES: usize = 300;
+/// Shortest keyword, in chars.
+const MIN_KEYWORD_CHARS: usize = 4;
+
+/// Common words that say nothing about a topic (English and Russian).
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "also", "been", "before", "being", "could", "does", "doing",
+    "dont", "from", "going", "gonna", "have", "here", "just", "know", "like", "more", "much",
+    "only", "really", "same", "should", "some", "still", "than", "that", "thats", "their", "them",
+    "then", "there", "these", "they", "thing", "think", "this", "those", "very", "want", "well",
+    "were", "what", "when", "where", "which", "while", "will", "with", "would", "yeah", "your",
+    "будет", "вообще", "всех", "даже", "если", "есть", "меня", "можно", "может", "надо",
+    "нужно", "очень", "потом", "потому", "просто", "сейчас", "тебя", "тоже", "только", "чтобы", "этого",
+    "этот", "было", "была", "были", "когда", "какой", "кстати", "вроде", "типа",
+];
+
+/// Up to `max` words used in the most messages of `texts`, ties in
+/// alphabetical order. Words are lowercased; short words, numbers, links,
+/// @mentions and stopwords are skipped.
+pub fn keywords(texts: &[String], max: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for text in texts {
+        let mut words: Vec<String> = text
+            .split_whitespace()
+            .filter(|token| !token.starts_with('@') && !token.contains("://") && !token.starts_with("www."))
+            .flat_map(|token| token.split(|c: char| !c.is_alphanumeric()))
+            .map(str::to_lowercase)
+            .filter(|word| word.chars().count() >= MIN_KEYWORD_CHARS && !word.chars().all(|c| c.is_numeric()))
+            .filter(|word| !STOPWORDS.contains(&word.as_str()))
+            .collect();
+        // Count each word once per message, so one long rant doesn't dominate
+        words.sort();
+        words.dedup();
+        for word in words {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.into_iter().take(max).map(|(word, _)| word).collect()
+}
+
+/// One user's activity over the digest window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserDigest {
+    pub user_id: i64,
+    pub username: String,
+    pub messages: usize,
+    pub keywords: Vec<String>,
+    /// Excerpts of their longest messages over `NOTABLE_CHARS`.
+    pub notable: Vec<String>,
+}
+
+impl UserDigest {
+    pub fn new(poster: &PosterCount, texts: &[String]) -> Self {
+        let mut long: Vec<&String> = texts.iter().filter(|t| t.chars().count() > NOTABLE_CHARS).collect();
+        long.sort_by_key(|t| std::cmp::Reverse(t.chars().count()));
+        let notable = long
+            .into_iter()
+            .take(MAX_NOTABLE)
+            .map(|t| {
+                let excerpt = truncate_safe(t, NOTABLE_EXCERPT_BYTES);
+                if excerpt.len() < t.len() { format!("{}...", excerpt) } else { excerpt.to_string() }
+            })
+            .collect();
+        Self {
+            user_id: poster.user_id,
+            username: poster.username.clone(),
+            messages: poster.messages,
+            keywords: keywords(texts, MAX_KEYWORDS),
+            notable,
+        }
+    }
+
+    fn format(&self) -> String {
+        let mut out = format!("## {} (user {}): {} message(s)\n", self.username, self.user_id, self.messages);
+        if !self.keywords.is_empty() {
+            out.push_str(&format!("Frequent words: {}\n", self.keywords.join(", ")));
+        }
+        for excerpt in &self.notable {
+            out.push_str(&format!("Notable: \"{}\"\n", excerpt));
+        }
+        out
+    }
+}
+
+/// The system message for `digests`, or None when there's nobody to report.
+pub fn message(digests: &[UserDigest]) -> Option<String> {
+    if digests.is_empty() {
+        return None;
+    }
+    let sections: Vec<String> = digests.iter().map(UserDigest::format).collect();
+    Some(format!(
+        "[Daily user digest] The most active people in the groups over the last 24 hours. \
+         If anything here is worth remembering about them, update their files in memories/users/ \
+         (read_memory first; create_memory if missing, edit_memory otherwise). Skip anyone with \
+         nothing new. No need to post anything in the chats.\n\n{}",
+        sections.join("\n")
+    ))
+}
+
+/// Whether the digest for `day` was already handed to Claude.
+pub fn already_sent(data_dir: &Path, day: NaiveDate) -> bool {
+    std::fs::read_to_string(data_dir.join(STATE_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<NaiveDate>().ok())
+        .is_some_and(|last| last >= day)
+}
+
+/// Record that the digest for `day` went out.
+pub fn mark_sent(data_dir: &Path, day: NaiveDate) {
+    if let Err(e) = std::fs::write(data_dir.join(STATE_FILE), day.to_string()) {
+        warn!("Failed to record the user digest date: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_keywords() {
+        let texts = texts(&[
+            "Rust borrow checker again... the borrow checker wins",
+            "Anyone going to the Rust meetup? https://example.com/rustmeetup",
+            "@alice the meetup is at 1900, see www.meetup.com",
+            "Что думаете про Rust? Вообще просто шикарный язык",
+            "This is what I think",
+        ]);
+        assert_eq!(keywords(&texts, 4), vec!["rust", "meetup", "anyone", "borrow"]);
+        // Links, mentions, numbers, stopwords and short words are gone
+        let all = keywords(&texts, 100);
+        for skipped in ["https", "example", "rustmeetup", "alice", "1900", "www", "вообще", "просто", "this", "the"] {
+            assert!(!all.iter().any(|w| w == skipped), "{} wasn't skipped", skipped);
+        }
+        assert!(all.contains(&"шикарный".to_string()));
+        assert!(keywords(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn test_digest_message() {
+        let long = format!("I finally moved to Lisbon {}", "and it is great ".repeat(30));
+        let texts = texts(&["short one", &long, "lisbon weather is nice", "lisbon food"]);
+        let poster = PosterCount { user_id: 7, username: "alice".to_string(), messages: 4 };
+        let digest = UserDigest::new(&poster, &texts);
+        assert_eq!(digest.keywords[0], "lisbon");
+        assert_eq!(digest.notable.len(), 1);
+        assert!(digest.notable[0].ends_with("..."));
+        assert!(digest.notable[0].len() <= NOTABLE_EXCERPT_BYTES + 3);
+
+        let message = message(&[digest]).unwrap();
+        assert!(message.starts_with("[Daily user digest]"));
+        assert!(message.contains("## alice (user 7): 4 message(s)\nFrequent words: lisbon, "));
+        assert!(message.contains("Notable: \"I finally moved to Lisbon"));
+        assert_eq!(super::message(&[]), None);
+    }
+
+    #[test]
+    fn test_last_digest_date() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        assert!(!already_sent(dir.path(), day));
+        mark_sent(dir.path(), day);
+        assert!(already_sent(dir.path(), day));
+        assert!(!already_sent(dir.path(), day.succ_opt().unwrap()));
+    }
+}
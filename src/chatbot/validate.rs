@@ -0,0 +1,520 @@
+//! Tool-call argument checks, so Claude gets errors it can act on.
+//!
+//! Raw calls have numeric strings coerced to integers before parsing. Parsed
+//! calls have limits clamped (with a note saying so), reaction emoji checked
+//! against what Telegram accepts, chat IDs checked against the chats the bot
+//! knows, and reply targets checked for plausibility. Every error names the
+//! tool, the field and the value received, and shows a valid call.
+
+use std::fmt::Display;
+
+use serde_json::{Map, Value};
+
+use crate::chatbot::delegate::MAX_OUTPUT_CHARS;
+use crate::chatbot::engine::{MAX_INVITE_EXPIRE_HOURS, MAX_INVITE_MEMBERS, MAX_MEMORY_READ_LINES, MAX_READ_MESSAGES};
+use crate::chatbot::message_ref::MessageRef;
+use crate::chatbot::tools::ToolCall;
+
+/// Emoji Telegram accepts as message reactions (without variation selectors).
+pub const REACTION_EMOJI: &[&str] = &[
+    "👍", "👎", "❤", "🔥", "🥰", "👏", "😁", "🤔", "🤯", "😱", "🤬", "😢", "🎉", "🤩", "🤮", "💩",
+    "🙏", "👌", "🕊", "🤡", "🥱", "🥴", "😍", "🐳", "❤‍🔥", "🌚", "🌭", "💯", "🤣", "⚡", "🍌", "🏆",
+    "💔", "🤨", "😐", "🍓", "🍾", "💋", "🖕", "😈", "😴", "😭", "🤓", "👻", "👨‍💻", "👀", "🎃", "🙈",
+    "😇", "😨", "🤝", "✍", "🤗", "🫡", "🎅", "🎄", "☃", "💅", "🤪", "🗿", "🆒", "💘", "🙉", "🦄",
+    "😘", "💊", "🙊", "😎", "👾", "🤷‍♂", "🤷", "🤷‍♀", "😡",
+];
+
+/// Names Claude uses instead of the emoji itself.
+const EMOJI_NAMES: &[(&str, &str)] = &[
+    ("thumbs_up", "👍"), ("thumbsup", "👍"), ("+1", "👍"), ("like", "👍"),
+    ("thumbs_down", "👎"), ("thumbsdown", "👎"), ("-1", "👎"),
+    ("heart", "❤"), ("red_heart", "❤"), ("love", "❤"), ("fire", "🔥"), ("clap", "👏"),
+    ("thinking", "🤔"), ("thinking_face", "🤔"), ("party", "🎉"), ("tada", "🎉"), ("eyes", "👀"),
+    ("100", "💯"), ("pray", "🙏"), ("ok_hand", "👌"), ("joy", "🤣"), ("laughing", "🤣"), ("rofl", "🤣"),
+    ("cry", "😢"), ("sob", "😭"), ("shrug", "🤷"), ("heart_eyes", "😍"), ("sunglasses", "😎"),
+    ("handshake", "🤝"), ("zap", "⚡"), ("trophy", "🏆"), ("broken_heart", "💔"), ("ghost", "👻"),
+    ("clown", "🤡"), ("poop", "💩"), ("scream", "😱"), ("rage", "😡"), ("salute", "🫡"),
+];
+
+/// Common emoji Telegram doesn't allow as reactions, and the closest one it
+/// does (without variation selectors).
+const REACTION_FALLBACKS: &[(&str, &str)] = &[
+    ("😂", "🤣"), ("😆", "🤣"), ("😹", "🤣"), ("😀", "😁"), ("😃", "😁"), ("😄", "😁"), ("😅", "😁"),
+    ("🙂", "😁"), ("😊", "🥰"), ("☺", "🥰"), ("🤭", "🙊"), ("🥲", "😢"), ("😔", "😢"), ("😞", "😢"),
+    ("😥", "😢"), ("😿", "😢"), ("😠", "😡"), ("😤", "😡"), ("😮", "😱"), ("😲", "😱"), ("😬", "😨"),
+    ("😳", "😨"), ("😏", "😎"), ("🧐", "🤓"), ("💀", "👻"), ("☠", "👻"), ("🤦", "🤷"), ("🤦‍♂", "🤷‍♂"),
+    ("🤦‍♀", "🤷‍♀"), ("🥳", "🎉"), ("🎊", "🎉"), ("🙌", "👏"), ("💪", "👍"), ("👊", "👍"), ("✅", "👌"),
+    ("✔", "👌"), ("✌", "👌"), ("🤙", "👌"), ("❌", "👎"), ("🚀", "🔥"), ("⭐", "🤩"), ("🌟", "🤩"),
+    ("✨", "🤩"), ("💕", "❤"), ("💖", "❤"), ("💗", "❤"), ("💓", "❤"), ("💞", "❤"), ("🧡", "❤"),
+    ("💛", "❤"), ("💚", "❤"), ("💙", "❤"), ("💜", "❤"), ("🖤", "❤"), ("🤍", "❤"),
+];
+
+/// A valid call for each tool, shown in errors.
+const EXAMPLES: &[(&str, &str)] = &[
+    ("send_message", r#"{"tool": "send_message", "chat_id": -1001234567890, "text": "Hi!", "reply_to_message_id": 4521}"#),
+    ("get_user_info", r#"{"tool": "get_user_info", "username": "alice"}"#),
+    ("query", r#"{"tool": "query", "sql": "SELECT username, message_count FROM users LIMIT 5"}"#),
+    ("add_reaction", r#"{"tool": "add_reaction", "chat_id": -1001234567890, "message_id": 4521, "emoji": "👍"}"#),
+    ("delete_message", r#"{"tool": "delete_message", "chat_id": -1001234567890, "message_id": 4521}"#),
+    ("mute_user", r#"{"tool": "mute_user", "chat_id": -1001234567890, "user_id": 123456789, "duration_minutes": 30}"#),
+    ("unmute_user", r#"{"tool": "unmute_user", "chat_id": -1001234567890, "user_id": 123456789}"#),
+    ("ban_user", r#"{"tool": "ban_user", "chat_id": -1001234567890, "user_id": 123456789}"#),
+    ("kick_user", r#"{"tool": "kick_user", "chat_id": -1001234567890, "user_id": 123456789}"#),
+    ("get_chat_admins", r#"{"tool": "get_chat_admins", "chat_id": -1001234567890}"#),
+    ("get_rules", r#"{"tool": "get_rules", "chat_id": -1001234567890}"#),
+    ("create_invite_link", r#"{"tool": "create_invite_link", "chat_id": -1001234567890, "expire_hours": 24, "member_limit": 1}"#),
+    ("revoke_invite_link", r#"{"tool": "revoke_invite_link", "chat_id": -1001234567890, "invite_link": "https://t.me/+AbCdEf"}"#),
+    ("get_members", r#"{"tool": "get_members", "filter": "inactive", "days_inactive": 30, "limit": 20}"#),
+    ("import_members", r#"{"tool": "import_members", "file_path": "members.json"}"#),
+    ("send_photo", r#"{"tool": "send_photo", "chat_id": -1001234567890, "prompt": "a cat in a hat"}"#),
+    ("send_voice", r#"{"tool": "send_voice", "chat_id": -1001234567890, "text": "Good morning!"}"#),
+    ("send_document", r##"{"tool": "send_document", "chat_id": -1001234567890, "filename": "notes.md", "content": "# Notes"}"##),
+    ("send_rubric_docx", r#"{"tool": "send_rubric_docx", "chat_id": -1001234567890, "filename": "essay_rubric.docx", "rubric_text": "1. Thesis (5 pts)\nExemplary (4): ...\nProficient (3): ...\nBasic (2): ...\nNeeds Improvement (1): ...\n\n2. ..."}"#),
+    ("send_dice", r#"{"tool": "send_dice", "chat_id": -1001234567890, "emoji": "🎲"}"#),
+    ("send_location", r#"{"tool": "send_location", "chat_id": -1001234567890, "latitude": 41.3111, "longitude": 69.2797, "title": "Cafe Central"}"#),
+    ("create_memory", r#"{"tool": "create_memory", "path": "people/alice.md", "content": "Likes chess"}"#),
+    ("read_memory", r#"{"tool": "read_memory", "path": "people/alice.md", "offset": 200, "limit": 100}"#),
+    ("edit_memory", r#"{"tool": "edit_memory", "path": "people/alice.md", "old_string": "chess", "new_string": "go"}"#),
+    ("list_memories", r#"{"tool": "list_memories", "path": "people"}"#),
+    ("search_memories", r#"{"tool": "search_memories", "pattern": "chess"}"#),
+    ("delete_memory", r#"{"tool": "delete_memory", "path": "people/alice.md"}"#),
+    ("report_bug", r#"{"tool": "report_bug", "description": "get_thread misses replies", "severity": "low"}"#),
+    ("youtube_info", r#"{"tool": "youtube_info", "url": "https://youtu.be/dQw4w9WgXcQ"}"#),
+    ("wiki_lookup", r#"{"tool": "wiki_lookup", "query": "Rust (programming language)"}"#),
+    ("translate", r#"{"tool": "translate", "text": "Привет", "target_lang": "en"}"#),
+    ("delegate", r#"{"tool": "delegate", "instruction": "Summarize", "input": "...", "max_output_chars": 2000}"#),
+    ("get_thread", r#"{"tool": "get_thread", "message_id": 4521, "max_depth": 10}"#),
+    ("chat_stats", r#"{"tool": "chat_stats", "chat_id": -1001234567890, "days": 7}"#),
+    ("top_messages", r#"{"tool": "top_messages", "chat_id": -1001234567890, "days": 7, "limit": 5}"#),
+    ("set_reminder", r#"{"tool": "set_reminder", "chat_id": -1001234567890, "message": "Standup", "trigger_at": "+30m"}"#),
+    ("list_reminders", r#"{"tool": "list_reminders", "chat_id": -1001234567890}"#),
+    ("cancel_reminder", r#"{"tool": "cancel_reminder", "reminder_id": 3}"#),
+    ("send_later", r#"{"tool": "send_later", "chat_id": -1001234567890, "text": "Time's up!", "delay_seconds": 600}"#),
+    ("cancel_send_later", r#"{"tool": "cancel_send_later", "scheduled_id": 7}"#),
+    ("follow_up", r#"{"tool": "follow_up", "chat_id": -1001234567890, "delay": "+2h", "note": "Did Alice's build get fixed?"}"#),
+    ("read_messages", r#"{"tool": "read_messages", "chat_id": -1001234567890, "from_date": "2024-01-15", "username": "alice", "last_n": 20}"#),
+    ("export_transcript", r#"{"tool": "export_transcript", "chat_id": -1001234567890, "from_date": "2024-01-01", "to_date": "2024-01-31"}"#),
+    ("set_personality", r#"{"tool": "set_personality", "name": "moderator"}"#),
+    ("relay_to_group", r#"{"tool": "relay_to_group", "chat_id": -1001234567890, "text": "Meetup moved to Friday", "on_behalf_of_user_id": 923847}"#),
+    ("set_slow_mode", r#"{"tool": "set_slow_mode", "chat_id": -1001234567890, "seconds": 30}"#),
+    ("set_chat_permissions", r#"{"tool": "set_chat_permissions", "chat_id": -1001234567890, "can_send_media": false, "can_send_polls": false}"#),
+    ("bulk_delete", r#"{"tool": "bulk_delete", "chat_id": -1001234567890, "since_minutes": 30, "contains": "crypto", "limit": 100}"#),
+    ("done", r#"{"tool": "done"}"#),
+];
+
+/// A valid call of `tool`, if it's a known tool.
+pub fn example(tool: &str) -> Option<&'static str> {
+    EXAMPLES.iter().find(|(name, _)| *name == tool).map(|(_, example)| *example)
+}
+
+/// "<tool>.<field>: got <value>; <reason>. Example: <call>"
+pub fn field_error(tool: &str, field: &str, got: impl Display, reason: &str) -> String {
+    let mut error = format!("{}.{}: got {}; {}.", tool, field, got, reason);
+    if let Some(example) = example(tool) {
+        error.push_str(&format!(" Example: {}", example));
+    }
+    error
+}
+
+/// Parse an integer Claude wrote as a string: "-100123", " 42 ", "−5"
+/// (Unicode minus), "+3".
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim().replace('−', "-");
+    value.strip_prefix('+').unwrap_or(&value).parse().ok()
+}
+
+/// Turn numeric strings (and whole floats) in a raw call's integer fields
+/// into integers. Anything else in those fields is an error.
+pub fn coerce_integers(tool: &str, call: &mut Map<String, Value>, integer_fields: &[String]) -> Result<(), String> {
+    for field in integer_fields {
+        let Some(value) = call.get_mut(field) else {
+            continue;
+        };
+        let coerced = match &*value {
+            Value::Null => continue,
+            Value::Number(n) if n.is_i64() => continue,
+            Value::Number(n) => n.as_f64().filter(|f| f.fract() == 0.0 && f.abs() < 9e15).map(|f| f as i64),
+            Value::String(s) => parse_int(s),
+            _ => None,
+        };
+        match coerced {
+            Some(n) => *value = Value::from(n),
+            None => return Err(field_error(tool, field, &*value, "expected an integer")),
+        }
+    }
+    Ok(())
+}
+
+/// A reaction emoji Telegram accepts, converting emoji names ("fire"),
+/// dropping variation selectors ("❤️") and swapping disallowed emoji for
+/// the closest allowed one ("😂" → 🤣). Returns the emoji and, if it was
+/// converted, a note saying so.
+pub fn normalize_reaction(tool: &str, emoji: &str) -> Result<(String, Option<String>), String> {
+    let trimmed = emoji.trim();
+    let bare: String = trimmed.chars().filter(|c| *c != '\u{FE0F}').collect();
+    if REACTION_EMOJI.contains(&bare.as_str()) {
+        return Ok((bare, None));
+    }
+
+    let name = trimmed.trim_matches(':').to_lowercase().replace([' ', '-'], "_");
+    if let Some((_, emoji)) = EMOJI_NAMES.iter().find(|(n, _)| *n == name || *n == trimmed) {
+        return Ok((emoji.to_string(), Some(format!("emoji \"{}\" was read as {}", trimmed, emoji))));
+    }
+    if let Some((_, emoji)) = REACTION_FALLBACKS.iter().find(|(e, _)| *e == bare) {
+        return Ok((
+            emoji.to_string(),
+            Some(format!("{} isn't a reaction Telegram allows, so {} was used instead", trimmed, emoji)),
+        ));
+    }
+    Err(field_error(
+        tool,
+        "emoji",
+        format!("\"{}\"", trimmed),
+        &format!("not a reaction Telegram allows; use one of {}", REACTION_EMOJI.join(" ")),
+    ))
+}
+
+/// Clamp `value` to `min..=max`, noting the change.
+fn clamp_field(value: &mut i64, field: &str, min: i64, max: i64, notes: &mut Vec<String>) {
+    let clamped = (*value).clamp(min, max);
+    if clamped != *value {
+        notes.push(format!("{} {} was out of range and clamped to {} (allowed {}-{})", field, value, clamped, min, max));
+        *value = clamped;
+    }
+}
+
+fn clamp_optional(value: &mut Option<i64>, field: &str, min: i64, max: i64, notes: &mut Vec<String>) {
+    if let Some(v) = value {
+        clamp_field(v, field, min, max, notes);
+    }
+}
+
+/// Clamp durations and limits to what the tools accept. Returns a note for
+/// each value changed, so Claude knows what actually happened.
+pub fn clamp_arguments(call: &mut ToolCall) -> Vec<String> {
+    let mut notes = Vec::new();
+    match call {
+        ToolCall::MuteUser { duration_minutes, until: None, .. } => {
+            clamp_field(duration_minutes, "duration_minutes", 1, 1440, &mut notes);
+        }
+        ToolCall::GetMembers { days_inactive, limit, .. } => {
+            clamp_optional(days_inactive, "days_inactive", 1, 3650, &mut notes);
+            clamp_optional(limit, "limit", 1, 500, &mut notes);
+        }
+        ToolCall::CreateInviteLink { expire_hours, member_limit, .. } => {
+            clamp_optional(expire_hours, "expire_hours", 1, MAX_INVITE_EXPIRE_HOURS, &mut notes);
+            clamp_optional(member_limit, "member_limit", 1, MAX_INVITE_MEMBERS, &mut notes);
+        }
+        ToolCall::Delegate { max_output_chars, .. } => {
+            clamp_optional(max_output_chars, "max_output_chars", 1, MAX_OUTPUT_CHARS as i64, &mut notes);
+        }
+        ToolCall::GetThread { max_depth, .. } => clamp_optional(max_depth, "max_depth", 1, 50, &mut notes),
+        ToolCall::ChatStats { days, .. } => clamp_optional(days, "days", 1, 90, &mut notes),
+        ToolCall::TopMessages { days, limit, .. } => {
+            clamp_optional(days, "days", 1, 90, &mut notes);
+            clamp_optional(limit, "limit", 1, 20, &mut notes);
+        }
+        ToolCall::ReadMessages { last_n, limit, .. } => {
+            clamp_optional(last_n, "last_n", 1, MAX_READ_MESSAGES, &mut notes);
+            clamp_optional(limit, "limit", 1, MAX_READ_MESSAGES, &mut notes);
+        }
+        ToolCall::ReadMemory { offset, limit, .. } => {
+            clamp_optional(offset, "offset", 1, i64::MAX, &mut notes);
+            clamp_optional(limit, "limit", 1, MAX_MEMORY_READ_LINES, &mut notes);
+        }
+        _ => {}
+    }
+    notes
+}
+
+/// The chat a call acts on, if it names one.
+pub fn chat_id(call: &ToolCall) -> Option<i64> {
+    match call {
+        ToolCall::SendMessage { chat_id, .. }
+        | ToolCall::AddReaction { chat_id, .. }
+        | ToolCall::DeleteMessage { chat_id, .. }
+        | ToolCall::MuteUser { chat_id, .. }
+        | ToolCall::UnmuteUser { chat_id, .. }
+        | ToolCall::BanUser { chat_id, .. }
+        | ToolCall::KickUser { chat_id, .. }
+        | ToolCall::GetChatAdmins { chat_id }
+        | ToolCall::GetRules { chat_id }
+        | ToolCall::CreateInviteLink { chat_id, .. }
+        | ToolCall::RevokeInviteLink { chat_id, .. }
+        | ToolCall::SendPhoto { chat_id, .. }
+        | ToolCall::SendVoice { chat_id, .. }
+        | ToolCall::SendDocument { chat_id, .. }
+        | ToolCall::SendRubricDocx { chat_id, .. }
+        | ToolCall::SendDice { chat_id, .. }
+        | ToolCall::SendLocation { chat_id, .. }
+        | ToolCall::ChatStats { chat_id, .. }
+        | ToolCall::TopMessages { chat_id, .. }
+        | ToolCall::SetReminder { chat_id, .. }
+        | ToolCall::SendLater { chat_id, .. }
+        | ToolCall::FollowUp { chat_id, .. }
+        | ToolCall::ExportTranscript { chat_id, .. }
+        | ToolCall::RelayToGroup { chat_id, .. }
+        | ToolCall::SetSlowMode { chat_id, .. }
+        | ToolCall::SetChatPermissions { chat_id, .. }
+        | ToolCall::BulkDelete { chat_id, .. } => Some(*chat_id),
+        ToolCall::ListReminders { chat_id } | ToolCall::ReadMessages { chat_id, .. } => *chat_id,
+        _ => None,
+    }
+}
+
+/// The numeric message a call replies to, if any.
+pub fn reply_to(call: &ToolCall) -> Option<i64> {
+    match call {
+        ToolCall::SendMessage { reply_to_message_id, .. } => reply_to_message_id.as_ref().and_then(MessageRef::id),
+        ToolCall::SendPhoto { reply_to_message_id, .. }
+        | ToolCall::SendVoice { reply_to_message_id, .. }
+        | ToolCall::SendDocument { reply_to_message_id, .. }
+        | ToolCall::SendRubricDocx { reply_to_message_id, .. }
+        | ToolCall::SendDice { reply_to_message_id, .. }
+        | ToolCall::SendLocation { reply_to_message_id, .. }
+        | ToolCall::SendLater { reply_to_message_id, .. } => *reply_to_message_id,
+        _ => None,
+    }
+}
+
+/// Error unless `chat_id` is one of the `known` chats.
+pub fn check_chat_id(tool: &str, chat_id: i64, known: &[i64]) -> Result<(), String> {
+    if known.contains(&chat_id) {
+        return Ok(());
+    }
+    let list: Vec<String> = known.iter().map(|id| id.to_string()).collect();
+    let reason = if list.is_empty() {
+        "the bot doesn't know this chat".to_string()
+    } else {
+        format!("the bot doesn't know this chat; known chats are: {}", list.join(", "))
+    };
+    Err(field_error(tool, "chat_id", chat_id, &reason))
+}
+
+/// Error unless `reply_to` could be a message in the chat: positive, and no
+/// newer than the latest message stored for it (when there is one).
+pub fn check_reply_to(tool: &str, reply_to: i64, latest: Option<i64>) -> Result<(), String> {
+    if reply_to <= 0 {
+        return Err(field_error(tool, "reply_to_message_id", reply_to, "message IDs are positive"));
+    }
+    if let Some(latest) = latest
+        && reply_to > latest
+    {
+        return Err(field_error(
+            tool,
+            "reply_to_message_id",
+            reply_to,
+            &format!("the latest message in this chat is {}; use an ID from the conversation", latest),
+        ));
+    }
+    Ok(())
+}
+
+/// Error unless the coordinates are on the map: latitude -90 to 90,
+/// longitude -180 to 180.
+pub fn check_coordinates(tool: &str, latitude: f64, longitude: f64) -> Result<(), String> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(field_error(tool, "latitude", latitude, "latitude is -90 to 90 degrees"));
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(field_error(tool, "longitude", longitude, "longitude is -180 to 180 degrees"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_coerce_integers() {
+        let integer_fields = fields(&["chat_id", "user_id", "duration_minutes", "limit"]);
+        let mut call = serde_json::json!({
+            "tool": "mute_user", "chat_id": "-1001234567890", "user_id": " 42 ",
+            "duration_minutes": 30.0, "limit": null, "text": "5"
+        });
+        coerce_integers("mute_user", call.as_object_mut().unwrap(), &integer_fields).unwrap();
+        assert_eq!(call["chat_id"], serde_json::json!(-1001234567890i64));
+        assert_eq!(call["user_id"], serde_json::json!(42));
+        assert_eq!(call["duration_minutes"], serde_json::json!(30));
+        assert!(call["limit"].is_null());
+        // Non-integer fields are left alone
+        assert_eq!(call["text"], serde_json::json!("5"));
+
+        assert_eq!(parse_int("−5"), Some(-5));
+        assert_eq!(parse_int("+3"), Some(3));
+        assert_eq!(parse_int("3.5"), None);
+    }
+
+    #[test]
+    fn test_coerce_errors_name_field_value_and_example() {
+        let integer_fields = fields(&["chat_id", "duration_minutes"]);
+
+        let mut call = serde_json::json!({ "tool": "send_message", "chat_id": "main group" });
+        let err = coerce_integers("send_message", call.as_object_mut().unwrap(), &integer_fields).unwrap_err();
+        assert!(err.starts_with("send_message.chat_id: got \"main group\"; expected an integer. Example: {\"tool\": \"send_message\""));
+
+        let mut call = serde_json::json!({ "tool": "mute_user", "duration_minutes": 2.5 });
+        let err = coerce_integers("mute_user", call.as_object_mut().unwrap(), &integer_fields).unwrap_err();
+        assert!(err.starts_with("mute_user.duration_minutes: got 2.5; expected an integer."));
+
+        let mut call = serde_json::json!({ "tool": "kick_user", "chat_id": [1] });
+        assert!(coerce_integers("kick_user", call.as_object_mut().unwrap(), &integer_fields).is_err());
+    }
+
+    #[test]
+    fn test_add_reaction_emoji() {
+        assert_eq!(normalize_reaction("add_reaction", "👍").unwrap(), ("👍".to_string(), None));
+        // Variation selector dropped
+        assert_eq!(normalize_reaction("add_reaction", "❤️").unwrap().0, "❤");
+        assert_eq!(normalize_reaction("add_reaction", " ❤‍🔥 ").unwrap().0, "❤‍🔥");
+
+        let (emoji, note) = normalize_reaction("add_reaction", ":fire:").unwrap();
+        assert_eq!(emoji, "🔥");
+        assert_eq!(note.as_deref(), Some("emoji \":fire:\" was read as 🔥"));
+        assert_eq!(normalize_reaction("add_reaction", "Thumbs Up").unwrap().0, "👍");
+        assert_eq!(normalize_reaction("add_reaction", "+1").unwrap().0, "👍");
+
+        // Other emoji are refused with the whole allowed set
+        let err = normalize_reaction("add_reaction", "🦀").unwrap_err();
+        assert!(err.starts_with("add_reaction.emoji: got \"🦀\"; not a reaction Telegram allows; use one of 👍 👎 ❤ 🔥"));
+        assert!(REACTION_EMOJI.iter().all(|e| err.contains(e)));
+        assert!(err.contains("Example: {\"tool\": \"add_reaction\""));
+        assert!(normalize_reaction("add_reaction", "").is_err());
+        assert!(normalize_reaction("add_reaction", "not an emoji").is_err());
+    }
+
+    #[test]
+    fn test_reaction_fallbacks() {
+        // 😂 looks like a reaction but Telegram doesn't allow it
+        let (emoji, note) = normalize_reaction("add_reaction", "😂").unwrap();
+        assert_eq!(emoji, "🤣");
+        assert_eq!(note.as_deref(), Some("😂 isn't a reaction Telegram allows, so 🤣 was used instead"));
+        // Variation selectors don't get in the way
+        assert_eq!(normalize_reaction("add_reaction", "✔️").unwrap().0, "👌");
+        assert_eq!(normalize_reaction("add_reaction", "💙").unwrap().0, "❤");
+
+        // Every fallback is itself allowed, and none shadows an allowed emoji
+        for (from, to) in REACTION_FALLBACKS {
+            assert!(REACTION_EMOJI.contains(to), "{} -> {}", from, to);
+            assert!(!REACTION_EMOJI.contains(from), "{} is allowed", from);
+            assert!(!from.contains('\u{FE0F}') && !to.contains('\u{FE0F}'));
+        }
+    }
+
+    #[test]
+    fn test_clamp_mute_and_invite() {
+        let mut mute = ToolCall::MuteUser { chat_id: -100, user_id: 1, duration_minutes: -5, until: None };
+        let notes = clamp_arguments(&mut mute);
+        assert!(matches!(mute, ToolCall::MuteUser { duration_minutes: 1, .. }));
+        assert_eq!(notes, vec!["duration_minutes -5 was out of range and clamped to 1 (allowed 1-1440)"]);
+
+        // With `until`, duration_minutes isn't used
+        let mut until = ToolCall::MuteUser { chat_id: -100, user_id: 1, duration_minutes: -5, until: Some("+2h".to_string()) };
+        assert!(clamp_arguments(&mut until).is_empty());
+
+        let mut invite = ToolCall::CreateInviteLink { chat_id: -100, expire_hours: Some(1000), member_limit: Some(10), name: None };
+        let notes = clamp_arguments(&mut invite);
+        assert!(matches!(invite, ToolCall::CreateInviteLink { expire_hours: Some(168), member_limit: Some(10), .. }));
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn test_clamp_limits() {
+        let mut members = ToolCall::GetMembers { filter: None, days_inactive: Some(0), limit: Some(-1) };
+        assert_eq!(clamp_arguments(&mut members).len(), 2);
+        assert!(matches!(members, ToolCall::GetMembers { days_inactive: Some(1), limit: Some(1), .. }));
+
+        let mut stats = ToolCall::ChatStats { chat_id: -100, days: Some(365) };
+        assert_eq!(clamp_arguments(&mut stats), vec!["days 365 was out of range and clamped to 90 (allowed 1-90)"]);
+
+        let mut read = ToolCall::ReadMessages {
+            chat_id: None, last_n: Some(5000), from_date: None, to_date: None, username: None, limit: Some(0),
+        };
+        assert_eq!(clamp_arguments(&mut read).len(), 2);
+        assert!(matches!(read, ToolCall::ReadMessages { last_n: Some(200), limit: Some(1), .. }));
+
+        let mut memory = ToolCall::ReadMemory { path: "users/alice.md".into(), offset: Some(0), limit: Some(2000) };
+        assert_eq!(clamp_arguments(&mut memory).len(), 2);
+        assert!(matches!(memory, ToolCall::ReadMemory { offset: Some(1), limit: Some(500), .. }));
+
+        let mut thread = ToolCall::GetThread { message_id: 5, max_depth: Some(0), include_replies: false };
+        clamp_arguments(&mut thread);
+        assert!(matches!(thread, ToolCall::GetThread { max_depth: Some(1), .. }));
+
+        let mut delegate = ToolCall::Delegate { instruction: "x".into(), input: "y".into(), max_output_chars: Some(0) };
+        clamp_arguments(&mut delegate);
+        assert!(matches!(delegate, ToolCall::Delegate { max_output_chars: Some(1), .. }));
+
+        // In-range values and defaults untouched
+        let mut fine = ToolCall::GetMembers { filter: None, days_inactive: None, limit: Some(50) };
+        assert!(clamp_arguments(&mut fine).is_empty());
+    }
+
+    #[test]
+    fn test_chat_id_check() {
+        let known = [-100, 42];
+        assert!(check_chat_id("send_message", -100, &known).is_ok());
+        let err = check_chat_id("send_message", -1009, &known).unwrap_err();
+        assert!(err.starts_with("send_message.chat_id: got -1009; the bot doesn't know this chat; known chats are: -100, 42."));
+        assert!(check_chat_id("ban_user", -1, &[]).unwrap_err().contains("doesn't know this chat."));
+
+        // Tools that name a chat, and tools that don't
+        let send = ToolCall::SendMessage { chat_id: -100, text: "hi".into(), reply_to_message_id: None };
+        assert_eq!(chat_id(&send), Some(-100));
+        assert_eq!(chat_id(&ToolCall::ListReminders { chat_id: None }), None);
+        assert_eq!(chat_id(&ToolCall::ListReminders { chat_id: Some(-5) }), Some(-5));
+        assert_eq!(chat_id(&ToolCall::Query { sql: "SELECT 1".into() }), None);
+        assert_eq!(chat_id(&ToolCall::ExportTranscript { chat_id: -7, from: "a".into(), to: "b".into(), format: None }), Some(-7));
+    }
+
+    #[test]
+    fn test_reply_to_check() {
+        let send = ToolCall::SendMessage { chat_id: -100, text: "hi".into(), reply_to_message_id: Some(MessageRef::Id(50)) };
+        assert_eq!(reply_to(&send), Some(50));
+        // References are resolved later, not checked here
+        let by_ref = ToolCall::SendMessage { chat_id: -100, text: "hi".into(), reply_to_message_id: Some(MessageRef::Ref("last".into())) };
+        assert_eq!(reply_to(&by_ref), None);
+        let dice = ToolCall::SendDice { chat_id: -100, emoji: "🎲".into(), reply_to_message_id: Some(7) };
+        assert_eq!(reply_to(&dice), Some(7));
+
+        assert!(check_reply_to("send_message", 50, Some(60)).is_ok());
+        assert!(check_reply_to("send_message", 50, None).is_ok());
+        assert!(check_reply_to("send_dice", 0, Some(60)).unwrap_err().contains("message IDs are positive"));
+        let err = check_reply_to("send_message", 999_999, Some(4521)).unwrap_err();
+        assert!(err.starts_with("send_message.reply_to_message_id: got 999999; the latest message in this chat is 4521"));
+    }
+
+    #[test]
+    fn test_coordinates() {
+        assert!(check_coordinates("send_location", 41.31, 69.28).is_ok());
+        assert!(check_coordinates("send_location", -90.0, 180.0).is_ok());
+        let err = check_coordinates("send_location", 91.5, 69.28).unwrap_err();
+        assert!(err.starts_with("send_location.latitude: got 91.5; latitude is -90 to 90 degrees."), "{}", err);
+        let err = check_coordinates("send_location", 41.31, -181.0).unwrap_err();
+        assert!(err.starts_with("send_location.longitude: got -181; longitude is -180 to 180"), "{}", err);
+        assert!(check_coordinates("send_location", f64::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_every_parsed_tool_has_an_example() {
+        for tool in [
+            "send_message", "get_user_info", "query", "add_reaction", "delete_message", "mute_user",
+            "unmute_user", "ban_user", "kick_user", "get_chat_admins", "get_rules", "create_invite_link",
+            "revoke_invite_link", "get_members", "import_members", "send_photo", "send_voice",
+            "send_document", "send_rubric_docx", "send_dice", "send_location", "create_memory", "read_memory", "edit_memory", "list_memories",
+            "search_memories", "delete_memory", "report_bug", "youtube_info", "wiki_lookup", "translate",
+            "delegate", "get_thread", "chat_stats", "top_messages", "set_reminder", "list_reminders", "cancel_reminder",
+            "send_later", "cancel_send_later", "read_messages", "export_transcript", "set_personality", "relay_to_group",
+            "set_slow_mode", "set_chat_permissions", "bulk_delete", "done",
+        ] {
+            let example: Value = serde_json::from_str(example(tool).unwrap()).unwrap();
+            assert_eq!(example["tool"], tool);
+        }
+        assert_eq!(example("WebSearch"), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Discovering the TTS server's voices.
+//!
+//! The voice list goes into the system prompt, so a TTS server that was
+//! briefly down at boot used to leave Claude without voices until the next
+//! restart. Startup now retries a couple of times, then falls back to the
+//! list cached in the data dir by the last successful fetch. Until a fetch
+//! succeeds, the engine retries every `REVALIDATE_INTERVAL` and sends Claude
+//! the list once it has one.
+
+use std::path::Path;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use super::telegram::ApiFuture;
+
+/// Last fetched voice list, in the data dir.
+pub const CACHE_FILE: &str = "tts_voices.json";
+
+/// Waits before each retry at startup, after the first attempt.
+pub const STARTUP_BACKOFF: &[Duration] = &[Duration::from_secs(2), Duration::from_secs(5)];
+
+/// Time between fetches while the list is unconfirmed.
+pub const REVALIDATE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Where voices come from, so discovery can run against a mock.
+pub trait VoiceSource: Send + Sync {
+    fn list_voices(&self) -> ApiFuture<'_, Vec<String>>;
+}
+
+/// The voices Claude is told about.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Voices {
+    pub list: Vec<String>,
+    /// Fetched from the server this run, not only read from the cache.
+    pub confirmed: bool,
+}
+
+impl Voices {
+    /// The prompt line listing the voices, empty without any.
+    pub fn prompt_note(&self) -> String {
+        if self.list.is_empty() {
+            return String::new();
+        }
+        format!("Available voices: {}. Pass the voice name to the `voice` parameter.", self.list.join(", "))
+    }
+}
+
+/// Fetch the list, retrying after each wait in `backoff`.
+pub async fn fetch_with_retries(source: &dyn VoiceSource, backoff: &[Duration]) -> Result<Vec<String>, String> {
+    let mut result = source.list_voices().await;
+    for delay in backoff {
+        let Err(ref e) = result else { break };
+        warn!("Failed to list TTS voices, retrying in {:?}: {}", delay, e);
+        tokio::time::sleep(*delay).await;
+        result = source.list_voices().await;
+    }
+    result
+}
+
+/// The voices at startup: fetched with retries, or the cached list if the
+/// server stays unreachable.
+pub async fn discover(source: &dyn VoiceSource, data_dir: &Path, backoff: &[Duration]) -> Voices {
+    match fetch_with_retries(source, backoff).await {
+        Ok(list) => {
+            save_cache(data_dir, &list);
+            Voices { list, confirmed: true }
+        }
+        Err(e) => {
+            let list = load_cache(data_dir).unwrap_or_default();
+            warn!("TTS voices unavailable ({}), using {} cached", e, list.len());
+            Voices { list, confirmed: false }
+        }
+    }
+}
+
+/// Fetch every `interval` until it works, caching the result.
+pub async fn revalidate(source: &dyn VoiceSource, data_dir: Option<&Path>, interval: Duration) -> Vec<String> {
+    loop {
+        tokio::time::sleep(interval).await;
+        match source.list_voices().await {
+            Ok(list) => {
+                info!("🔊 TTS voices available again: {}", list.join(", "));
+                if let Some(dir) = data_dir {
+                    save_cache(dir, &list);
+                }
+                return list;
+            }
+            Err(e) => warn!("TTS voices still unavailable: {}", e),
+        }
+    }
+}
+
+/// Whether a synthesis error means the server doesn't know the voice.
+pub fn is_voice_not_found(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("404") || (error.contains("voice") && error.contains("not found"))
+}
+
+pub fn load_cache(data_dir: &Path) -> Option<Vec<String>> {
+    let path = data_dir.join(CACHE_FILE);
+    let content = std::fs::read_to_string(&path).ok()?;
+    serde_json::from_str(&content)
+        .inspect_err(|e| warn!("Ignoring unreadable {}: {}", path.display(), e))
+        .ok()
+}
+
+pub fn save_cache(data_dir: &Path, voices: &[String]) {
+    let result = serde_json::to_string(voices)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(data_dir.join(CACHE_FILE), json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Failed to cache TTS voices: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use tokio::time::Instant;
+
+    /// Answers with `responses` in order, then keeps failing.
+    struct MockVoices {
+        responses: Mutex<VecDeque<Result<Vec<String>, String>>>,
+        calls: Mutex<Vec<Instant>>,
+    }
+
+    impl MockVoices {
+        fn new(responses: Vec<Result<Vec<String>, String>>) -> Self {
+            Self { responses: Mutex::new(responses.into()), calls: Mutex::default() }
+        }
+
+        fn call_times(&self) -> Vec<Instant> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl VoiceSource for MockVoices {
+        fn list_voices(&self) -> ApiFuture<'_, Vec<String>> {
+            self.calls.lock().unwrap().push(Instant::now());
+            let response = self.responses.lock().unwrap().pop_front().unwrap_or(Err("connection refused".to_string()));
+            Box::pin(async move { response })
+        }
+    }
+
+    fn voices(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    const BACKOFF: &[Duration] = &[Duration::from_millis(20), Duration::from_millis(60)];
+
+    #[tokio::test]
+    async fn test_retry_schedule() {
+        // Succeeds on the last retry, after each wait
+        let source = MockVoices::new(vec![Err("down".into()), Err("down".into()), Ok(voices(&["anna"]))]);
+        assert_eq!(fetch_with_retries(&source, BACKOFF).await, Ok(voices(&["anna"])));
+        let times = source.call_times();
+        assert_eq!(times.len(), 3);
+        assert!(times[1] - times[0] >= BACKOFF[0]);
+        assert!(times[2] - times[1] >= BACKOFF[1]);
+
+        // No retries once it works
+        let source = MockVoices::new(vec![Ok(voices(&["anna"]))]);
+        assert!(fetch_with_retries(&source, BACKOFF).await.is_ok());
+        assert_eq!(source.call_times().len(), 1);
+
+        // Gives up after the last wait
+        let source = MockVoices::new(vec![]);
+        assert_eq!(fetch_with_retries(&source, BACKOFF).await, Err("connection refused".to_string()));
+        assert_eq!(source.call_times().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_fallback() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        // Nothing cached yet: no voices, unconfirmed
+        let down = MockVoices::new(vec![]);
+        assert_eq!(discover(&down, dir.path(), &[]).await, Voices::default());
+
+        // A successful fetch is cached for the next boot
+        let up = MockVoices::new(vec![Ok(voices(&["anna", "boris"]))]);
+        assert_eq!(discover(&up, dir.path(), &[]).await, Voices { list: voices(&["anna", "boris"]), confirmed: true });
+        let down = MockVoices::new(vec![]);
+        assert_eq!(discover(&down, dir.path(), &[]).await, Voices { list: voices(&["anna", "boris"]), confirmed: false });
+
+        // A damaged cache counts as none
+        std::fs::write(dir.path().join(CACHE_FILE), "{").unwrap();
+        assert_eq!(load_cache(dir.path()), None);
+    }
+
+    #[tokio::test]
+    async fn test_revalidate_until_available() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = MockVoices::new(vec![Err("down".into()), Ok(voices(&["anna"]))]);
+        let interval = Duration::from_millis(20);
+        assert_eq!(revalidate(&source, Some(dir.path()), interval).await, voices(&["anna"]));
+        let times = source.call_times();
+        assert_eq!(times.len(), 2);
+        assert!(times[1] - times[0] >= interval);
+        assert_eq!(load_cache(dir.path()), Some(voices(&["anna"])));
+    }
+
+    #[test]
+    fn test_voice_not_found() {
+        assert!(is_voice_not_found("TTS error 404 Not Found: {\"detail\":\"no such reference\"}"));
+        assert!(is_voice_not_found("TTS error 400 Bad Request: Voice 'anna' not found"));
+        assert!(!is_voice_not_found("TTS error 500 Internal Server Error: CUDA out of memory"));
+        assert!(!is_voice_not_found("TTS request failed: connection refused"));
+    }
+
+    #[test]
+    fn test_prompt_note() {
+        assert_eq!(Voices::default().prompt_note(), "");
+        let voices = Voices { list: voices(&["anna", "boris"]), confirmed: false };
+        assert_eq!(voices.prompt_note(), "Available voices: anna, boris. Pass the voice name to the `voice` parameter.");
+    }
+}
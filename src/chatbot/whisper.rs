@@ -2,21 +2,33 @@
 //!
 //! Converts voice messages (OGG Opus from Telegram) to text.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
 
-/// Whisper transcription engine.
-pub struct Whisper {
-    ctx: Arc<WhisperContext>,
+/// A loaded speech-to-text model.
+pub trait Transcriber: Send + Sync + 'static {
+    /// Transcribe audio data (OGG Opus format from Telegram). Blocking.
+    fn transcribe(&self, ogg_data: &[u8]) -> Result<String, String>;
 }
 
-impl Whisper {
-    /// Load a Whisper model from a .bin file.
-    pub fn new(model_path: &Path) -> Result<Self, String> {
+/// Loads a model from disk. Blocking; abstracted so the load state machine can be tested.
+pub trait ModelLoader: Send + Sync + 'static {
+    type Model: Transcriber;
+    fn load(&self, model_path: &Path) -> Result<Self::Model, String>;
+}
+
+/// Loads whisper.cpp models via whisper-rs.
+pub struct WhisperRsLoader;
+
+impl ModelLoader for WhisperRsLoader {
+    type Model = WhisperModel;
+
+    fn load(&self, model_path: &Path) -> Result<WhisperModel, String> {
         info!("Loading Whisper model from {:?}", model_path);
 
         if !model_path.exists() {
@@ -30,13 +42,18 @@ impl Whisper {
         .map_err(|e| format!("Failed to load Whisper model: {e}"))?;
 
         info!("Whisper model loaded successfully");
-        Ok(Self { ctx: Arc::new(ctx) })
+        Ok(WhisperModel { ctx })
     }
+}
+
+/// A loaded whisper.cpp model.
+pub struct WhisperModel {
+    ctx: WhisperContext,
+}
 
-    /// Transcribe audio data (OGG Opus format from Telegram).
-    ///
+impl Transcriber for WhisperModel {
     /// Converts to 16KHz mono PCM using ffmpeg, then runs Whisper.
-    pub fn transcribe(&self, ogg_data: &[u8]) -> Result<String, String> {
+    fn transcribe(&self, ogg_data: &[u8]) -> Result<String, String> {
         debug!("Transcribing {} bytes of audio", ogg_data.len());
 
         // Convert OGG to 16KHz mono f32 PCM using ffmpeg
@@ -75,6 +92,157 @@ impl Whisper {
     }
 }
 
+/// Model load state, as reported by `/status`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WhisperStatus {
+    Unloaded,
+    Loading,
+    Ready,
+    Failed(String),
+}
+
+impl std::fmt::Display for WhisperStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WhisperStatus::Unloaded => write!(f, "not loaded (loads on first voice message)"),
+            WhisperStatus::Loading => write!(f, "loading"),
+            WhisperStatus::Ready => write!(f, "ready"),
+            WhisperStatus::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+/// Why a transcription didn't produce text.
+#[derive(Debug, PartialEq)]
+pub enum TranscribeError {
+    /// The model couldn't be loaded.
+    NotAvailable(String),
+    /// The model is loaded but transcription failed.
+    Failed(String),
+}
+
+enum LoadState<M> {
+    Unloaded,
+    Loading,
+    /// `mtime` is the model file's modification time when it was loaded.
+    Ready { model: Arc<M>, mtime: Option<SystemTime> },
+    /// Not retried until the model file changes or a reload is requested.
+    Failed { error: String, mtime: Option<SystemTime> },
+}
+
+/// Whisper transcription engine.
+///
+/// The model is loaded lazily on first use (loading takes ~20s) and reloaded
+/// when the model file's mtime changes. A failed load doesn't take the bot
+/// down; transcriptions report the load error until the file changes.
+pub struct Whisper<L: ModelLoader = WhisperRsLoader> {
+    model_path: PathBuf,
+    loader: Arc<L>,
+    state: RwLock<LoadState<L::Model>>,
+    /// Serializes loads so concurrent voice messages don't load twice.
+    load_lock: tokio::sync::Mutex<()>,
+}
+
+impl Whisper {
+    /// Configure Whisper with a model .bin file. Nothing is loaded yet.
+    pub fn new(model_path: &Path) -> Self {
+        Self::with_loader(model_path, WhisperRsLoader)
+    }
+}
+
+impl<L: ModelLoader> Whisper<L> {
+    fn with_loader(model_path: &Path, loader: L) -> Self {
+        Self {
+            model_path: model_path.to_path_buf(),
+            loader: Arc::new(loader),
+            state: RwLock::new(LoadState::Unloaded),
+            load_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Current load state.
+    pub fn status(&self) -> WhisperStatus {
+        match &*self.state.read().expect("whisper state lock poisoned") {
+            LoadState::Unloaded => WhisperStatus::Unloaded,
+            LoadState::Loading => WhisperStatus::Loading,
+            LoadState::Ready { .. } => WhisperStatus::Ready,
+            LoadState::Failed { error, .. } => WhisperStatus::Failed(error.clone()),
+        }
+    }
+
+    /// Transcribe audio data (OGG Opus format from Telegram), loading the model if needed.
+    pub async fn transcribe(&self, ogg_data: Vec<u8>) -> Result<String, TranscribeError> {
+        let model = self.model().await.map_err(TranscribeError::NotAvailable)?;
+
+        tokio::task::spawn_blocking(move || model.transcribe(&ogg_data))
+            .await
+            .map_err(|e| TranscribeError::Failed(format!("Transcription task failed: {e}")))?
+            .map_err(TranscribeError::Failed)
+    }
+
+    /// Force a reload of the model file (owner `/reload whisper`).
+    pub async fn reload(&self) -> WhisperStatus {
+        let _guard = self.load_lock.lock().await;
+        self.load(file_mtime(&self.model_path)).await;
+        self.status()
+    }
+
+    /// Get the loaded model, (re)loading it if unloaded or the file changed.
+    async fn model(&self) -> Result<Arc<L::Model>, String> {
+        let mtime = file_mtime(&self.model_path);
+        if let Some(result) = self.current(mtime) {
+            return result;
+        }
+
+        let _guard = self.load_lock.lock().await;
+        // Another task may have loaded it while we waited
+        if let Some(result) = self.current(mtime) {
+            return result;
+        }
+
+        self.load(mtime).await;
+        self.current(mtime).unwrap_or_else(|| Err("Whisper model not loaded".to_string()))
+    }
+
+    /// The loaded model or load error, if still valid for the file's `mtime`.
+    fn current(&self, mtime: Option<SystemTime>) -> Option<Result<Arc<L::Model>, String>> {
+        match &*self.state.read().expect("whisper state lock poisoned") {
+            LoadState::Ready { model, mtime: loaded } if *loaded == mtime => Some(Ok(model.clone())),
+            LoadState::Failed { error, mtime: failed } if *failed == mtime => Some(Err(error.clone())),
+            _ => None,
+        }
+    }
+
+    /// Load the model on a blocking thread. Caller must hold `load_lock`.
+    async fn load(&self, mtime: Option<SystemTime>) {
+        let was_loaded = matches!(*self.state.read().expect("whisper state lock poisoned"), LoadState::Ready { .. });
+        if was_loaded {
+            info!("Whisper model file changed, reloading");
+        }
+        *self.state.write().expect("whisper state lock poisoned") = LoadState::Loading;
+
+        let loader = self.loader.clone();
+        let path = self.model_path.clone();
+        let result = tokio::task::spawn_blocking(move || loader.load(&path))
+            .await
+            .unwrap_or_else(|e| Err(format!("Model load task failed: {e}")));
+
+        let new_state = match result {
+            Ok(model) => LoadState::Ready { model: Arc::new(model), mtime },
+            Err(error) => {
+                warn!("Failed to load Whisper model: {}", error);
+                LoadState::Failed { error, mtime }
+            }
+        };
+        *self.state.write().expect("whisper state lock poisoned") = new_state;
+    }
+}
+
+/// Modification time of the model file, if it exists.
+fn file_mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 /// Convert OGG Opus audio to 16KHz mono f32 PCM samples using ffmpeg.
 fn convert_ogg_to_pcm(ogg_data: &[u8]) -> Result<Vec<f32>, String> {
     // Create temp file for input (ffmpeg needs seekable input for OGG)
@@ -141,6 +309,108 @@ fn truncate(s: &str, max: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Records loads; each loaded model transcribes to "model N".
+    struct FakeLoader {
+        loads: Arc<AtomicUsize>,
+        error: Arc<Mutex<Option<String>>>,
+    }
+
+    struct FakeModel(usize);
+
+    impl Transcriber for FakeModel {
+        fn transcribe(&self, _ogg_data: &[u8]) -> Result<String, String> {
+            Ok(format!("model {}", self.0))
+        }
+    }
+
+    impl ModelLoader for FakeLoader {
+        type Model = FakeModel;
+
+        fn load(&self, _model_path: &Path) -> Result<FakeModel, String> {
+            let n = self.loads.fetch_add(1, Ordering::SeqCst) + 1;
+            std::thread::sleep(Duration::from_millis(20));
+            match self.error.lock().unwrap().clone() {
+                Some(e) => Err(e),
+                None => Ok(FakeModel(n)),
+            }
+        }
+    }
+
+    fn fake_whisper(path: &Path) -> (Whisper<FakeLoader>, Arc<AtomicUsize>, Arc<Mutex<Option<String>>>) {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let error = Arc::new(Mutex::new(None));
+        let loader = FakeLoader { loads: loads.clone(), error: error.clone() };
+        (Whisper::with_loader(path, loader), loads, error)
+    }
+
+    #[tokio::test]
+    async fn test_lazy_load_on_first_use() {
+        let (whisper, loads, _) = fake_whisper(Path::new("/nonexistent/model.bin"));
+        assert_eq!(whisper.status(), WhisperStatus::Unloaded);
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 1");
+        assert_eq!(whisper.status(), WhisperStatus::Ready);
+
+        // Loaded model is reused
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 1");
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_first_use_loads_once() {
+        let (whisper, loads, _) = fake_whisper(Path::new("/nonexistent/model.bin"));
+        let (a, b) = tokio::join!(whisper.transcribe(vec![]), whisper.transcribe(vec![]));
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_load_reports_error_without_retrying() {
+        let (whisper, loads, error) = fake_whisper(Path::new("/nonexistent/model.bin"));
+        *error.lock().unwrap() = Some("bad model".to_string());
+
+        let err = whisper.transcribe(vec![]).await.unwrap_err();
+        assert_eq!(err, TranscribeError::NotAvailable("bad model".to_string()));
+        assert_eq!(whisper.status(), WhisperStatus::Failed("bad model".to_string()));
+
+        // File unchanged: don't hammer the loader
+        assert!(whisper.transcribe(vec![]).await.is_err());
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reload_recovers_from_failure() {
+        let (whisper, loads, error) = fake_whisper(Path::new("/nonexistent/model.bin"));
+        *error.lock().unwrap() = Some("bad model".to_string());
+        assert!(whisper.transcribe(vec![]).await.is_err());
+
+        *error.lock().unwrap() = None;
+        assert_eq!(whisper.reload().await, WhisperStatus::Ready);
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 2");
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reload_when_file_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("model.bin");
+        std::fs::write(&path, "v1").unwrap();
+
+        let (whisper, loads, _) = fake_whisper(&path);
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 1");
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 2");
+        assert_eq!(whisper.transcribe(vec![]).await.unwrap(), "model 2");
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
 
     #[test]
     fn test_truncate() {
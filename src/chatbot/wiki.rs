@@ -0,0 +1,326 @@
+//! Wikipedia lookups via the REST API.
+//!
+//! Searches for the best matching page, then fetches its summary extract.
+//! Disambiguation pages return the list of options instead of page text.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::info;
+
+/// Max chars of summary extract returned to Claude.
+const MAX_EXTRACT_CHARS: usize = 1500;
+
+/// Max options listed for a disambiguation page.
+const MAX_DISAMBIGUATION_OPTIONS: usize = 30;
+
+/// Per-request HTTP timeout.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of a Wikipedia lookup.
+#[derive(Debug, PartialEq)]
+pub enum WikiPage {
+    /// A regular article with its summary extract.
+    Article { title: String, extract: String, url: String },
+    /// A disambiguation page with the titles it links to.
+    Disambiguation { title: String, url: String, options: Vec<String> },
+}
+
+impl WikiPage {
+    /// Format for returning to Claude as a tool result.
+    pub fn format(&self) -> String {
+        match self {
+            WikiPage::Article { title, extract, url } => {
+                format!("Title: {}\nURL: {}\n\n{}", title, url, extract)
+            }
+            WikiPage::Disambiguation { title, url, options } => {
+                let list = options.iter()
+                    .map(|o| format!("- {}", o))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!(
+                    "\"{}\" is a disambiguation page ({}). Options:\n{}\n\nLook up one of these titles for details.",
+                    title, url, list
+                )
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    pages: Vec<SearchPage>,
+}
+
+#[derive(Deserialize)]
+struct SearchPage {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct SummaryResponse {
+    #[serde(rename = "type")]
+    page_type: String,
+    title: String,
+    #[serde(default)]
+    extract: String,
+    content_urls: Option<ContentUrls>,
+}
+
+#[derive(Deserialize)]
+struct ContentUrls {
+    desktop: PageUrls,
+}
+
+#[derive(Deserialize)]
+struct PageUrls {
+    page: String,
+}
+
+/// Parsed page summary (before disambiguation options are resolved).
+#[derive(Debug, PartialEq)]
+pub(crate) struct Summary {
+    pub title: String,
+    pub extract: String,
+    pub url: String,
+    pub is_disambiguation: bool,
+}
+
+/// Check a language code is safe to use as a Wikipedia subdomain (e.g. "en", "zh-yue").
+pub fn validate_lang(lang: &str) -> Result<(), String> {
+    let valid = (2..=12).contains(&lang.len())
+        && lang.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+        && !lang.starts_with('-')
+        && !lang.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid language code '{}' (expected e.g. 'en', 'de', 'ru')", lang))
+    }
+}
+
+/// Extract the top page key from a search response. Returns None if nothing matched.
+pub(crate) fn parse_search(body: &str) -> Result<Option<String>, String> {
+    let parsed: SearchResponse = serde_json::from_str(body)
+        .map_err(|e| format!("Failed to parse search response: {e}"))?;
+    Ok(parsed.pages.into_iter().next().map(|p| p.key))
+}
+
+/// Parse a page summary response.
+pub(crate) fn parse_summary(body: &str) -> Result<Summary, String> {
+    let parsed: SummaryResponse = serde_json::from_str(body)
+        .map_err(|e| format!("Failed to parse summary response: {e}"))?;
+
+    if parsed.page_type != "standard" && parsed.page_type != "disambiguation" {
+        return Err(format!("Unsupported page type '{}'", parsed.page_type));
+    }
+
+    let url = parsed.content_urls
+        .map(|u| u.desktop.page)
+        .ok_or("Summary has no page URL")?;
+
+    let extract = if parsed.extract.chars().count() > MAX_EXTRACT_CHARS {
+        let truncated: String = parsed.extract.chars().take(MAX_EXTRACT_CHARS).collect();
+        format!("{}…", truncated.trim_end())
+    } else {
+        parsed.extract
+    };
+
+    Ok(Summary {
+        title: parsed.title,
+        extract,
+        url,
+        is_disambiguation: parsed.page_type == "disambiguation",
+    })
+}
+
+/// Parse the article links of a disambiguation page (action=query&prop=links).
+pub(crate) fn parse_links(body: &str) -> Result<Vec<String>, String> {
+    let parsed: serde_json::Value = serde_json::from_str(body)
+        .map_err(|e| format!("Failed to parse links response: {e}"))?;
+
+    let pages = parsed["query"]["pages"]
+        .as_object()
+        .ok_or("Links response has no pages")?;
+
+    let options = pages.values()
+        .filter_map(|page| page["links"].as_array())
+        .flatten()
+        .filter_map(|link| link["title"].as_str())
+        .take(MAX_DISAMBIGUATION_OPTIONS)
+        .map(String::from)
+        .collect();
+
+    Ok(options)
+}
+
+/// GET a URL and return the body. 404 maps to None.
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<Option<String>, String> {
+    let response = client
+        .get(url)
+        .header("User-Agent", "claudima (Telegram bot)")
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| if e.is_timeout() {
+            "Wikipedia request timed out".to_string()
+        } else {
+            format!("Wikipedia request failed: {e}")
+        })?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(format!("Wikipedia returned status {}", status));
+    }
+
+    response
+        .text()
+        .await
+        .map(Some)
+        .map_err(|e| format!("Failed to read Wikipedia response: {e}"))
+}
+
+/// Look up a query on Wikipedia in the given language.
+pub async fn lookup(query: &str, lang: &str) -> Result<WikiPage, String> {
+    validate_lang(lang)?;
+    let query = query.trim();
+    if query.is_empty() {
+        return Err("Query is empty".to_string());
+    }
+
+    info!("📖 Wikipedia lookup ({}): {}", lang, query);
+
+    let client = reqwest::Client::new();
+    let not_found = || format!("No Wikipedia article found for '{}'", query);
+
+    let search_url = format!(
+        "https://{}.wikipedia.org/w/rest.php/v1/search/page?q={}&limit=1",
+        lang,
+        urlencoding::encode(query)
+    );
+    let search_body = fetch(&client, &search_url).await?.ok_or_else(not_found)?;
+    let key = parse_search(&search_body)?.ok_or_else(not_found)?;
+
+    let summary_url = format!(
+        "https://{}.wikipedia.org/api/rest_v1/page/summary/{}",
+        lang,
+        urlencoding::encode(&key)
+    );
+    let summary_body = fetch(&client, &summary_url).await?.ok_or_else(not_found)?;
+    let summary = parse_summary(&summary_body)?;
+
+    if !summary.is_disambiguation {
+        return Ok(WikiPage::Article {
+            title: summary.title,
+            extract: summary.extract,
+            url: summary.url,
+        });
+    }
+
+    let links_url = format!(
+        "https://{}.wikipedia.org/w/api.php?action=query&prop=links&plnamespace=0&pllimit=max&format=json&titles={}",
+        lang,
+        urlencoding::encode(&summary.title)
+    );
+    let links_body = fetch(&client, &links_url).await?.ok_or_else(not_found)?;
+
+    Ok(WikiPage::Disambiguation {
+        title: summary.title,
+        url: summary.url,
+        options: parse_links(&links_body)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEARCH_RUST: &str = r#"{"pages":[{"id":29414838,"key":"Rust_(programming_language)","title":"Rust (programming language)","excerpt":"<span class=\"searchmatch\">Rust</span> is a general-purpose","description":"General-purpose programming language"}]}"#;
+
+    const SEARCH_EMPTY: &str = r#"{"pages":[]}"#;
+
+    const SUMMARY_RUST: &str = r#"{"type":"standard","title":"Rust (programming language)","displaytitle":"Rust (programming language)","extract":"Rust is a general-purpose programming language emphasizing performance, type safety, and concurrency.","content_urls":{"desktop":{"page":"https://en.wikipedia.org/wiki/Rust_(programming_language)"},"mobile":{"page":"https://en.m.wikipedia.org/wiki/Rust_(programming_language)"}}}"#;
+
+    const SUMMARY_MERCURY: &str = r#"{"type":"disambiguation","title":"Mercury","extract":"Mercury commonly refers to:","content_urls":{"desktop":{"page":"https://en.wikipedia.org/wiki/Mercury"}}}"#;
+
+    const LINKS_MERCURY: &str = r#"{"batchcomplete":"","query":{"pages":{"19694":{"pageid":19694,"ns":0,"title":"Mercury","links":[{"ns":0,"title":"Mercury (element)"},{"ns":0,"title":"Mercury (mythology)"},{"ns":0,"title":"Mercury (planet)"}]}}}}"#;
+
+    const SUMMARY_NOT_FOUND: &str = r#"{"type":"https://mediawiki.org/wiki/HyperSwitch/errors/not_found","title":"Not found.","method":"get","detail":"Page or revision not found.","uri":"/en.wikipedia.org/v1/page/summary/Nope"}"#;
+
+    #[test]
+    fn test_parse_search_top_result() {
+        assert_eq!(parse_search(SEARCH_RUST).unwrap(), Some("Rust_(programming_language)".to_string()));
+    }
+
+    #[test]
+    fn test_parse_search_no_results() {
+        assert_eq!(parse_search(SEARCH_EMPTY).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_summary_standard() {
+        let summary = parse_summary(SUMMARY_RUST).unwrap();
+        assert_eq!(summary.title, "Rust (programming language)");
+        assert!(summary.extract.starts_with("Rust is a general-purpose"));
+        assert_eq!(summary.url, "https://en.wikipedia.org/wiki/Rust_(programming_language)");
+        assert!(!summary.is_disambiguation);
+    }
+
+    #[test]
+    fn test_parse_summary_truncates_extract() {
+        let long = "word ".repeat(1000);
+        let body = serde_json::json!({
+            "type": "standard",
+            "title": "Long",
+            "extract": long,
+            "content_urls": { "desktop": { "page": "https://en.wikipedia.org/wiki/Long" } }
+        }).to_string();
+        let summary = parse_summary(&body).unwrap();
+        assert!(summary.extract.chars().count() <= MAX_EXTRACT_CHARS + 1);
+        assert!(summary.extract.ends_with('…'));
+    }
+
+    #[test]
+    fn test_parse_summary_disambiguation() {
+        let summary = parse_summary(SUMMARY_MERCURY).unwrap();
+        assert!(summary.is_disambiguation);
+        let options = parse_links(LINKS_MERCURY).unwrap();
+        assert_eq!(options, vec!["Mercury (element)", "Mercury (mythology)", "Mercury (planet)"]);
+
+        let page = WikiPage::Disambiguation { title: summary.title, url: summary.url, options };
+        let text = page.format();
+        assert!(text.contains("disambiguation"));
+        assert!(text.contains("- Mercury (planet)"));
+        assert!(!text.contains("commonly refers to"));
+    }
+
+    #[test]
+    fn test_parse_summary_missing_page() {
+        let err = parse_summary(SUMMARY_NOT_FOUND).unwrap_err();
+        assert!(err.contains("Unsupported page type"));
+    }
+
+    #[test]
+    fn test_article_format() {
+        let page = WikiPage::Article {
+            title: "Rust".to_string(),
+            extract: "A language.".to_string(),
+            url: "https://en.wikipedia.org/wiki/Rust".to_string(),
+        };
+        assert_eq!(page.format(), "Title: Rust\nURL: https://en.wikipedia.org/wiki/Rust\n\nA language.");
+    }
+
+    #[test]
+    fn test_validate_lang() {
+        assert!(validate_lang("en").is_ok());
+        assert!(validate_lang("zh-yue").is_ok());
+        assert!(validate_lang("e").is_err());
+        assert!(validate_lang("EN").is_err());
+        assert!(validate_lang("evil.com/x").is_err());
+        assert!(validate_lang("-en").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Command-line arguments, and the offline `db` subcommands.
+//!
+//! `claudima [config.json] [--message "..."]` runs the bot. `claudima db ...`
+//! works on the database file alone: it reads the config for the data dir,
+//! opens the database, prints what it did and exits, without connecting to
+//! Telegram or starting Claude. SQLite locking makes it safe next to a
+//! running bot, but prune is best done with the bot stopped.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, Utc};
+
+use crate::chatbot::database::Database;
+use crate::chatbot::image_limits::format_mb;
+
+const DEFAULT_CONFIG: &str = "claudima.json";
+
+pub const USAGE: &str = "\
+Usage:
+  claudima [config.json] [--message TEXT]     run the bot
+  claudima db stats [config.json]             table row counts and sizes
+  claudima db prune [config.json] --days N    delete messages older than N days
+  claudima db export [config.json] --out FILE write messages and members as JSON
+  claudima db migrate [config.json]           import database.json into database.db";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Run the bot, optionally starting with a system message to Claude.
+    Run { config_path: String, system_message: Option<String> },
+    Db { config_path: String, action: DbAction },
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DbAction {
+    Stats,
+    Prune { days: u32 },
+    Export { out: PathBuf },
+    /// Import the old JSON database, even into an existing database.
+    Migrate,
+}
+
+/// Parse the arguments after the program name.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut config_path = None;
+    let mut system_message = None;
+    let mut days = None;
+    let mut out = None;
+
+    let (subcommand, rest) = match args.first().map(String::as_str) {
+        Some("db") => match args.get(1) {
+            Some(action) => (Some(action.as_str()), &args[2..]),
+            None => return Err("db needs an action: stats, prune, export or migrate".to_string()),
+        },
+        _ => (None, args),
+    };
+
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().cloned().ok_or(format!("{arg} requires an argument"));
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(Command::Help),
+            "--message" | "-m" if subcommand.is_none() => system_message = Some(value()?),
+            "--days" if subcommand == Some("prune") => {
+                let v = value()?;
+                days = Some(v.parse::<u32>().ok().filter(|d| *d > 0).ok_or(format!("--days must be a positive number, got {v}"))?);
+            }
+            "--out" if subcommand == Some("export") => out = Some(PathBuf::from(value()?)),
+            arg if !arg.starts_with('-') && config_path.is_none() => config_path = Some(arg.to_string()),
+            _ => return Err(format!("Unexpected argument: {arg}")),
+        }
+    }
+
+    let config_path = config_path.unwrap_or_else(|| DEFAULT_CONFIG.to_string());
+    let action = match subcommand {
+        None => return Ok(Command::Run { config_path, system_message }),
+        Some("stats") => DbAction::Stats,
+        Some("prune") => DbAction::Prune { days: days.ok_or("db prune requires --days")? },
+        Some("export") => DbAction::Export { out: out.ok_or("db export requires --out")? },
+        Some("migrate") => DbAction::Migrate,
+        Some(other) => return Err(format!("Unknown db action: {other}")),
+    };
+    Ok(Command::Db { config_path, action })
+}
+
+/// Run `action` on the database in `data_dir`. Returns the report to print.
+pub fn run_db(action: &DbAction, data_dir: &Path) -> Result<String, String> {
+    let db_path = data_dir.join("database.db");
+    match action {
+        DbAction::Stats => stats(&open_existing(&db_path)?, &db_path),
+        DbAction::Prune { days } => prune(&mut open_existing(&db_path)?, *days),
+        DbAction::Export { out } => export(&open_existing(&db_path)?, out),
+        DbAction::Migrate => {
+            let json_path = db_path.with_extension("json");
+            if !json_path.exists() {
+                return Err(format!("Nothing to migrate: {} not found", json_path.display()));
+            }
+            let (messages, members) = Database::open(&db_path).migrate_from_json(&json_path)?;
+            Ok(format!("Migrated {messages} message(s) and {members} member(s) into {}", db_path.display()))
+        }
+    }
+}
+
+/// The database at `path`, without creating one by mistake.
+fn open_existing(path: &Path) -> Result<Database, String> {
+    if !path.exists() {
+        return Err(format!("No database at {}", path.display()));
+    }
+    Ok(Database::open(path))
+}
+
+/// A table of row counts and sizes, after the file's total size.
+fn stats(db: &Database, db_path: &Path) -> Result<String, String> {
+    let file_size = std::fs::metadata(db_path).map(|m| m.len()).map_err(|e| format!("Failed to stat {}: {e}", db_path.display()))?;
+    let mut report = format!("{} ({})\n{:<24} {:>10} {:>10}\n", db_path.display(), format_mb(file_size), "table", "rows", "size");
+    for table in db.table_stats()? {
+        report.push_str(&format!("{:<24} {:>10} {:>10}\n", table.name, table.rows, format_mb(table.bytes)));
+    }
+    Ok(report.trim_end().to_string())
+}
+
+/// Delete messages older than `days`, then shrink the file.
+fn prune(db: &mut Database, days: u32) -> Result<String, String> {
+    let cutoff = Utc::now() - Duration::days(days.into());
+    let (messages, reactions) = db.prune_before(cutoff)?;
+    db.vacuum()?;
+    Ok(format!("Deleted {messages} message(s) and {reactions} reaction(s) older than {days} days"))
+}
+
+fn export(db: &Database, out: &Path) -> Result<String, String> {
+    let file = std::fs::File::create(out).map_err(|e| format!("Failed to create {}: {e}", out.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let (messages, members) = db.export_json(&mut writer)?;
+    std::io::Write::flush(&mut writer).map_err(|e| format!("Failed to write {}: {e}", out.display()))?;
+    Ok(format!("Exported {messages} message(s) and {members} member(s) to {}", out.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatbot::ChatMessage;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_args(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn run(config_path: &str, system_message: Option<&str>) -> Command {
+        Command::Run { config_path: config_path.to_string(), system_message: system_message.map(String::from) }
+    }
+
+    fn db(config_path: &str, action: DbAction) -> Command {
+        Command::Db { config_path: config_path.to_string(), action }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        // The bare invocation still runs the bot
+        assert_eq!(parse(""), Ok(run("claudima.json", None)));
+        assert_eq!(parse("prod.json"), Ok(run("prod.json", None)));
+        assert_eq!(parse("prod.json --message hello"), Ok(run("prod.json", Some("hello"))));
+        assert_eq!(parse("-m hello prod.json"), Ok(run("prod.json", Some("hello"))));
+        assert_eq!(parse("--help"), Ok(Command::Help));
+
+        assert_eq!(parse("db stats"), Ok(db("claudima.json", DbAction::Stats)));
+        assert_eq!(parse("db stats prod.json"), Ok(db("prod.json", DbAction::Stats)));
+        assert_eq!(parse("db prune prod.json --days 90"), Ok(db("prod.json", DbAction::Prune { days: 90 })));
+        assert_eq!(parse("db prune --days 30"), Ok(db("claudima.json", DbAction::Prune { days: 30 })));
+        assert_eq!(
+            parse("db export prod.json --out all.json"),
+            Ok(db("prod.json", DbAction::Export { out: PathBuf::from("all.json") }))
+        );
+        assert_eq!(parse("db migrate prod.json"), Ok(db("prod.json", DbAction::Migrate)));
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        for (args, error) in [
+            ("--message", "--message requires an argument"),
+            ("--verbose", "Unexpected argument: --verbose"),
+            ("a.json b.json", "Unexpected argument: b.json"),
+            ("db", "db needs an action: stats, prune, export or migrate"),
+            ("db vacuum", "Unknown db action: vacuum"),
+            ("db prune prod.json", "db prune requires --days"),
+            ("db prune --days 0", "--days must be a positive number, got 0"),
+            ("db prune --days lots", "--days must be a positive number, got lots"),
+            ("db export", "db export requires --out"),
+            // Flags only apply where they mean something
+            ("db stats --days 5", "Unexpected argument: --days"),
+            ("db stats --message hi", "Unexpected argument: --message"),
+            ("prod.json --out x.json", "Unexpected argument: --out"),
+        ] {
+            assert_eq!(parse(args), Err(error.to_string()), "{}", args);
+        }
+    }
+
+    fn message(message_id: i64, timestamp: String) -> ChatMessage {
+        let mut msg = ChatMessage::system(format!("message {message_id}"));
+        (msg.message_id, msg.chat_id, msg.user_id, msg.timestamp) = (message_id, -100, 7, timestamp);
+        msg
+    }
+
+    #[test]
+    fn test_db_commands() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(run_db(&DbAction::Stats, dir.path()).unwrap_err().starts_with("No database at"));
+        assert!(run_db(&DbAction::Migrate, dir.path()).unwrap_err().starts_with("Nothing to migrate"));
+
+        let mut database = Database::open(&dir.path().join("database.db"));
+        let old = (Utc::now() - Duration::days(100)).format("%Y-%m-%d %H:%M").to_string();
+        let recent = Utc::now().format("%Y-%m-%d %H:%M").to_string();
+        database.add_message(message(1, old));
+        database.add_message(message(2, recent));
+        drop(database);
+
+        let stats = run_db(&DbAction::Stats, dir.path()).unwrap();
+        assert!(stats.lines().any(|l| l.starts_with("messages ") && l.split_whitespace().nth(1) == Some("2")), "{}", stats);
+
+        let pruned = run_db(&DbAction::Prune { days: 90 }, dir.path()).unwrap();
+        assert_eq!(pruned, "Deleted 1 message(s) and 0 reaction(s) older than 90 days");
+
+        // An export migrates back into a fresh database
+        let out = dir.path().join("export.json");
+        let exported = run_db(&DbAction::Export { out: out.clone() }, dir.path()).unwrap();
+        assert!(exported.starts_with("Exported 1 message(s) and 1 member(s)"), "{}", exported);
+        let other = tempfile::TempDir::new().unwrap();
+        std::fs::copy(&out, other.path().join("database.json")).unwrap();
+        let migrated = run_db(&DbAction::Migrate, other.path()).unwrap();
+        assert!(migrated.starts_with("Migrated 1 message(s) and 1 member(s)"), "{}", migrated);
+    }
+}
@@ -1,11 +1,20 @@
 use regex::Regex;
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use teloxide::types::{ChatId, UserId};
 
+use crate::chatbot::chat_migration::ChatMigrations;
+use crate::chatbot::language::ResponseLanguage;
+use crate::chatbot::tts::VoiceTranscript;
+use crate::chatbot::maintenance;
+use crate::chatbot::output_guard::OutputGuard;
+use crate::dm_policy::{self, DmPolicy, DEFAULT_INTRO};
+use crate::links::{LinkPolicy, DEFAULT_SHORTENERS};
+use crate::startup::StartupNotify;
+
 /// Errors that can occur when loading configuration.
 #[derive(Debug)]
 pub enum ConfigError {
@@ -56,6 +65,9 @@ struct ConfigFile {
     /// Usernames of peer bots that can communicate with this bot (e.g., ["clauscout_bot", "clauoracle_bot"])
     #[serde(default)]
     peer_bots: Vec<String>,
+    /// Pass messages from other bots to Claude (by default only peers' are)
+    #[serde(default)]
+    process_bot_messages: bool,
     telegram_bot_token: String,
     /// OpenRouter API key for spam classification
     #[serde(default)]
@@ -84,9 +96,22 @@ struct ConfigFile {
     whisper_model_path: Option<String>,
     /// TTS endpoint for Kokoro-FastAPI (e.g., "http://localhost:8880").
     tts_endpoint: Option<String>,
+    /// What is kept of the bot's voice messages: "off", "store_only" or "always"
+    /// (also replies with the text). Defaults to "store_only".
+    #[serde(default)]
+    voice_include_transcript: Option<String>,
+    /// Shorter system prompt: one-line tool descriptions, no rubric instructions.
+    #[serde(default)]
+    compact_prompt: bool,
     /// Custom personality/identity override for the bot.
     /// If set, replaces the default "You are Claudima" description.
     personality: Option<String>,
+    /// Named personalities: name → prompt fragment replacing `personality`.
+    #[serde(default)]
+    personalities: BTreeMap<String, String>,
+    /// Daily windows (scan_timezone) with the personality active in each.
+    #[serde(default)]
+    personality_schedule: Vec<crate::chatbot::personality::ScheduleEntry>,
     /// Interval in minutes for scheduled scans (0 = disabled).
     #[serde(default)]
     scan_interval_minutes: u32,
@@ -97,12 +122,257 @@ struct ConfigFile {
     /// IANA timezone for scan_times (e.g., "Europe/Paris"). Defaults to "UTC".
     #[serde(default)]
     scan_timezone: Option<String>,
+    /// Nightly window (scan_timezone) without unsolicited bot messages in groups.
+    #[serde(default)]
+    quiet_hours: Option<crate::chatbot::quiet_hours::QuietHoursConfig>,
+    /// React to messages addressing the bot while their turn runs.
+    #[serde(default)]
+    ack_reactions: Option<crate::chatbot::ack::AckReactions>,
+    /// Address for the Prometheus metrics endpoint (e.g., "127.0.0.1:9464").
+    #[serde(default)]
+    metrics_listen: Option<String>,
+    /// Default Wikipedia language code for wiki_lookup (e.g., "de"). Defaults to "en".
+    #[serde(default)]
+    wiki_default_lang: Option<String>,
+    /// Max characters accepted by the translate tool.
+    #[serde(default = "default_translate_max_chars")]
+    translate_max_chars: usize,
+    /// Recent messages (in tokens) above which the compaction restore is summarized; 0 = never.
+    #[serde(default = "default_compaction_summary_tokens")]
+    compaction_summary_tokens: usize,
+    /// Token budget for recent messages replayed after a compaction (and by /recap).
+    #[serde(default = "default_compaction_restore_tokens")]
+    compaction_restore_tokens: usize,
+    /// Minutes after a restore during which another compaction gets only the memory file.
+    #[serde(default = "default_compaction_cooldown_mins")]
+    compaction_cooldown_mins: u64,
+    /// Gemini model for text tasks (translate, delegate). Defaults to "gemini-2.5-flash".
+    #[serde(default)]
+    gemini_text_model: Option<String>,
+    /// Reply language: "auto" (follow the chat) or a fixed code like "ru". Defaults to "auto".
+    #[serde(default)]
+    response_language: Option<String>,
+    /// Named regexes (name → pattern) the bot must never send, matched case-insensitively.
+    /// Replaces the defaults when set; `{}` disables the guard.
+    #[serde(default)]
+    forbidden_output_patterns: Option<BTreeMap<String, String>>,
+    /// Quiet time (ms) after a DM before the bot responds.
+    #[serde(default = "default_debounce_ms_dm")]
+    debounce_ms_dm: u64,
+    /// Quiet time (ms) after a group message before the bot responds.
+    #[serde(default = "default_debounce_ms_group")]
+    debounce_ms_group: u64,
+    /// Longest (ms) a stream of group messages can delay a response; null disables the cap.
+    #[serde(default = "default_debounce_max_ms_group")]
+    debounce_max_ms_group: Option<u64>,
+    /// Messages waiting for Claude beyond which the owner is warned about backpressure.
+    #[serde(default = "default_max_pending_messages")]
+    max_pending_messages: usize,
+    /// Reply to mentions while paused with /pause (empty = none).
+    #[serde(default = "default_maintenance_reply")]
+    maintenance_reply: String,
+    /// Read text from incoming images with Gemini and send Claude the text instead of the image.
+    #[serde(default)]
+    ocr_screenshots: bool,
+    /// Caption words that keep the image alongside its OCR text. Defaults to "look", "photo", ….
+    #[serde(default)]
+    ocr_visual_keywords: Option<Vec<String>>,
+    /// Images larger than this (bytes) aren't passed to Claude.
+    #[serde(default = "default_max_image_bytes")]
+    max_image_bytes: u64,
+    /// When to DM the owner about restarts: "always", "on_error", "daily_summary" or "never".
+    #[serde(default)]
+    startup_notify: Option<String>,
+    /// What users who may not DM the bot get: "deny", "intro" or "forward". Defaults to "deny".
+    #[serde(default)]
+    dm_policy: Option<String>,
+    /// Reply sent under the "intro" DM policy.
+    #[serde(default)]
+    dm_intro_message: Option<String>,
+    /// Messages per unknown user forwarded to the owner under the "forward" DM policy.
+    #[serde(default = "default_dm_forward_limit")]
+    dm_forward_limit: u32,
+    /// Days after a user's first denied DM until they're counted afresh.
+    #[serde(default = "default_dm_denied_expiry_days")]
+    dm_denied_expiry_days: u32,
+    /// Hour (0-23, in scan_timezone) of the owner's daily tool error digest; null disables it.
+    #[serde(default = "default_error_digest_hour")]
+    error_digest_hour: Option<u32>,
+    /// Hour (0-23, in scan_timezone) of the daily digest of active users for Claude's memory; null disables it.
+    #[serde(default)]
+    user_digest_hour: Option<u32>,
+    /// Users covered by the daily user digest.
+    #[serde(default = "default_user_digest_top")]
+    user_digest_top: usize,
+    /// Hour (0-23, in scan_timezone) of the daily post of yesterday's moderation actions; null disables it.
+    #[serde(default)]
+    transparency_hour: Option<u32>,
+    /// Chat the moderation post goes to, instead of the primary chat.
+    #[serde(default)]
+    transparency_chat_id: Option<i64>,
+    /// Name members whose messages were deleted only as "a member" in the moderation post.
+    #[serde(default = "default_transparency_anonymize")]
+    transparency_anonymize: bool,
+    /// Time limits (seconds) by tool name, overriding the per-category defaults.
+    #[serde(default)]
+    tool_timeouts: BTreeMap<String, u64>,
+    /// Result size limits (characters) by tool name, overriding the default.
+    #[serde(default)]
+    tool_result_limits: BTreeMap<String, usize>,
+    /// Cron schedule (UTC, 7 fields) of database and memories backups; null disables them.
+    #[serde(default = "default_backup_cron")]
+    backup_cron: Option<String>,
+    /// Backups kept in data_dir/backups.
+    #[serde(default = "default_backup_keep")]
+    backup_keep: usize,
+    /// Refuse to mute, ban, kick or delete messages of the owner, trusted users and chat admins.
+    #[serde(default = "default_protect_admins")]
+    protect_admins: bool,
+    /// Check all of a response's action calls before running any of them; one failure holds them all back.
+    #[serde(default)]
+    atomic_batches: bool,
+    /// Path of the Claude Code CLI. Defaults to "claude" on PATH.
+    #[serde(default = "default_claude_program")]
+    claude_program: String,
+    /// Write full text to the Claude Code session transcripts instead of previews.
+    #[serde(default)]
+    transcript_full: bool,
+    /// Cap (MB) on data_dir/transcripts; the oldest transcripts are deleted beyond it.
+    #[serde(default = "default_transcript_max_mb")]
+    transcript_max_mb: u64,
+    /// Backfilled messages older than this (minutes) are stored but not responded to.
+    #[serde(default = "default_backfill_max_age_minutes")]
+    backfill_max_age_minutes: u64,
+    /// Media downloads (photos, voice, documents) running at once.
+    #[serde(default = "default_media_concurrency")]
+    media_concurrency: usize,
+    /// Time one media download gets, including transcription or extraction (seconds).
+    #[serde(default = "default_media_timeout_secs")]
+    media_timeout_secs: u64,
+    /// Distinct new accounts posting near-identical text within 10 minutes that trigger raid mode (0 = off).
+    #[serde(default = "default_raid_threshold")]
+    raid_threshold: usize,
+    /// How long raid mode lasts once triggered (minutes).
+    #[serde(default = "default_raid_duration_minutes")]
+    raid_duration_minutes: u64,
+    /// Messages sent through an inline bot are never obviously safe.
+    #[serde(default)]
+    via_bot_ambiguous: bool,
+    /// Link shortener domains to expand before spam checks. Defaults to common shorteners.
+    #[serde(default)]
+    shortener_domains: Option<Vec<String>>,
+    /// Messages linking to these domains (or their subdomains) are spam.
+    #[serde(default)]
+    blocked_domains: Vec<String>,
+    /// Domains never blocked or expanded, and fine as a shortener target.
+    #[serde(default)]
+    allowed_domains: Vec<String>,
 }
 
 fn default_max_strikes() -> u8 {
     3
 }
 
+fn default_translate_max_chars() -> usize {
+    4000
+}
+
+fn default_compaction_summary_tokens() -> usize {
+    4000
+}
+
+fn default_compaction_restore_tokens() -> usize {
+    crate::chatbot::compaction::DEFAULT_RESTORE_TOKENS
+}
+
+fn default_compaction_cooldown_mins() -> u64 {
+    crate::chatbot::compaction::DEFAULT_COOLDOWN_MINS
+}
+
+fn default_debounce_ms_dm() -> u64 {
+    300
+}
+
+fn default_debounce_ms_group() -> u64 {
+    3000
+}
+
+fn default_debounce_max_ms_group() -> Option<u64> {
+    Some(8000)
+}
+
+fn default_dm_forward_limit() -> u32 {
+    3
+}
+
+fn default_dm_denied_expiry_days() -> u32 {
+    dm_policy::DEFAULT_EXPIRY_DAYS
+}
+
+fn default_max_pending_messages() -> usize {
+    100
+}
+
+fn default_maintenance_reply() -> String {
+    maintenance::DEFAULT_REPLY.to_string()
+}
+
+fn default_max_image_bytes() -> u64 {
+    crate::chatbot::image_limits::DEFAULT_MAX_IMAGE_BYTES
+}
+
+fn default_error_digest_hour() -> Option<u32> {
+    Some(9)
+}
+
+fn default_user_digest_top() -> usize {
+    5
+}
+
+fn default_transparency_anonymize() -> bool {
+    true
+}
+
+fn default_backup_cron() -> Option<String> {
+    Some(crate::chatbot::backup::DEFAULT_CRON.to_string())
+}
+
+fn default_backup_keep() -> usize {
+    crate::chatbot::backup::DEFAULT_KEEP
+}
+
+fn default_protect_admins() -> bool {
+    true
+}
+
+fn default_claude_program() -> String {
+    "claude".to_string()
+}
+
+fn default_transcript_max_mb() -> u64 {
+    crate::chatbot::session_transcript::DEFAULT_MAX_MB
+}
+
+fn default_backfill_max_age_minutes() -> u64 {
+    30
+}
+
+fn default_media_concurrency() -> usize {
+    crate::media::DEFAULT_CONCURRENCY
+}
+
+fn default_media_timeout_secs() -> u64 {
+    crate::media::DEFAULT_TIMEOUT_SECS
+}
+
+fn default_raid_threshold() -> usize {
+    5
+}
+
+fn default_raid_duration_minutes() -> u64 {
+    30
+}
+
 pub struct Config {
     /// Owner IDs - first ID is the primary owner (used for chatbot config).
     pub owner_ids: Vec<UserId>,
@@ -118,6 +388,8 @@ pub struct Config {
     pub allowed_groups: HashSet<ChatId>,
     /// Primary chat ID (first allowed_group or explicit override)
     pub primary_chat_id: i64,
+    /// Allowed groups that became supergroups this run, shared with ChatbotConfig.
+    pub chat_migrations: Arc<ChatMigrations>,
     pub trusted_channels: HashSet<ChatId>,
     pub spam_patterns: Vec<Regex>,
     pub safe_patterns: Vec<Regex>,
@@ -130,16 +402,115 @@ pub struct Config {
     pub whisper_model_path: Option<PathBuf>,
     /// TTS endpoint for Kokoro-FastAPI (e.g., "http://localhost:8880").
     pub tts_endpoint: Option<String>,
-    /// Custom personality/identity override for the bot.
-    pub personality: Option<String>,
+    /// What is kept of the bot's voice messages.
+    pub voice_include_transcript: VoiceTranscript,
+    /// Shorter system prompt: one-line tool descriptions, no rubric instructions.
+    pub compact_prompt: bool,
+    /// The bot's identity: the `personality` override and named personalities.
+    pub personalities: crate::chatbot::personality::Personalities,
     /// Interval in minutes for scheduled scans (0 = disabled).
     pub scan_interval_minutes: u32,
     /// Specific times of day to run scans (e.g., ["10:00", "20:00"]).
     pub scan_times: Vec<chrono::NaiveTime>,
     /// IANA timezone for scan_times (e.g., "Europe/Paris").
     pub scan_timezone: chrono_tz::Tz,
+    /// When unsolicited group messages are held back (None = never).
+    pub quiet_hours: Option<crate::chatbot::quiet_hours::QuietHours>,
+    /// Acknowledgment reactions while a turn runs (None = off).
+    pub ack_reactions: Option<crate::chatbot::ack::AckReactions>,
+    /// Where to serve /metrics (None = no metrics).
+    pub metrics_listen: Option<std::net::SocketAddr>,
     /// Usernames of peer bots (without @) that can communicate with this bot.
     pub peer_bots: Vec<String>,
+    /// Pass group messages from bots that aren't peers to Claude.
+    pub process_bot_messages: bool,
+    /// Default Wikipedia language code for wiki_lookup.
+    pub wiki_default_lang: String,
+    /// Max characters accepted by the translate tool.
+    pub translate_max_chars: usize,
+    /// Summarize the compaction restore above this many tokens (0 = never).
+    pub compaction_summary_tokens: usize,
+    /// Token budget for recent messages replayed after a compaction.
+    pub compaction_restore_tokens: usize,
+    /// After a restore, compactions within this long skip the messages.
+    pub compaction_cooldown: std::time::Duration,
+    /// Gemini model for text tasks (translate, delegate).
+    pub gemini_text_model: String,
+    /// Reply language policy.
+    pub response_language: ResponseLanguage,
+    /// Phrases the bot must never send.
+    /// Shared with ChatbotConfig so owner `/reload guard` takes effect immediately.
+    pub output_guard: Arc<RwLock<OutputGuard>>,
+    /// Quiet time after a DM before the bot responds (ms).
+    pub debounce_ms_dm: u64,
+    /// Quiet time after a group message before the bot responds (ms).
+    pub debounce_ms_group: u64,
+    /// Cap on how long a stream of group messages delays a response (ms, None = no cap).
+    pub debounce_max_ms_group: Option<u64>,
+    /// Messages waiting for Claude beyond which the owner is warned.
+    pub max_pending_messages: usize,
+    /// Reply to mentions while paused (empty = none).
+    pub maintenance_reply: String,
+    /// OCR incoming images with Gemini (needs gemini_api_key).
+    pub ocr_screenshots: bool,
+    /// Caption words (lowercase) that keep the image alongside its OCR text.
+    pub ocr_visual_keywords: Vec<String>,
+    /// Images larger than this (bytes) aren't passed to Claude.
+    pub max_image_bytes: u64,
+    /// When to DM the owner about restarts.
+    pub startup_notify: StartupNotify,
+    /// What users who may not DM the bot get.
+    pub dm_policy: DmPolicy,
+    /// Reply sent under the "intro" DM policy.
+    pub dm_intro_message: String,
+    /// Messages per unknown user forwarded to the owner under the "forward" DM policy.
+    pub dm_forward_limit: u32,
+    /// How long a user's unknown-DM count is kept.
+    pub dm_denied_expiry: chrono::Duration,
+    /// When to send the owner the daily tool error digest (None = off).
+    pub error_digest_time: Option<chrono::NaiveTime>,
+    /// When Claude gets the daily digest of active users (None = off).
+    pub user_digest_time: Option<chrono::NaiveTime>,
+    /// Users covered by the daily user digest.
+    pub user_digest_top: usize,
+    /// When yesterday's moderation actions are posted (None = off).
+    pub transparency_time: Option<chrono::NaiveTime>,
+    /// Chat of the moderation post (None = the primary chat).
+    pub transparency_chat_id: Option<i64>,
+    /// Deletions are attributed to "a member" in the moderation post.
+    pub transparency_anonymize: bool,
+    /// Per-tool time limits overriding the category defaults.
+    pub tool_timeouts: HashMap<String, std::time::Duration>,
+    /// Per-tool result size limits (characters) overriding the default.
+    pub tool_result_limits: HashMap<String, usize>,
+    /// Cron schedule of backups (None = off).
+    pub backup_cron: Option<String>,
+    /// Backups kept before the oldest is deleted.
+    pub backup_keep: usize,
+    /// Moderation tools refuse protected targets.
+    pub protect_admins: bool,
+    /// A response's actions run all or none.
+    pub atomic_batches: bool,
+    /// The Claude Code CLI.
+    pub claude_program: PathBuf,
+    /// Session transcripts keep full text, not previews.
+    pub transcript_full: bool,
+    /// Cap on the session transcripts directory.
+    pub transcript_max_bytes: u64,
+    /// Backfilled messages older than this are stored but not responded to.
+    pub backfill_max_age: chrono::Duration,
+    /// Media downloads running at once.
+    pub media_concurrency: usize,
+    /// Time one media download gets.
+    pub media_timeout: std::time::Duration,
+    /// Distinct new accounts posting near-identical text that trigger raid mode (0 = off).
+    pub raid_threshold: usize,
+    /// How long raid mode lasts once triggered.
+    pub raid_duration: chrono::Duration,
+    /// Messages sent through an inline bot are never obviously safe.
+    pub via_bot_ambiguous: bool,
+    /// Shortener/blocked/allowed domain lists for link analysis.
+    pub links: LinkPolicy,
 }
 
 impl Config {
@@ -166,7 +537,7 @@ impl Config {
         }
 
         let owner_ids = file.owner_ids.into_iter().map(UserId).collect();
-        // Initialize with None usernames - main.rs will fetch from Telegram
+        // Initialize with None usernames - BotState::new will fetch from Telegram
         let trusted_dm_users = Arc::new(RwLock::new(
             file.trusted_dm_users.into_iter()
                 .map(|id| (id as i64, None))
@@ -201,6 +572,19 @@ impl Config {
             .map(PathBuf::from)
             .unwrap_or_else(|| PathBuf::from("."));
 
+        let normalize_domains = |domains: Vec<String>| -> Vec<String> {
+            domains.into_iter()
+                .map(|d| d.trim().trim_start_matches("www.").to_lowercase())
+                .filter(|d| !d.is_empty())
+                .collect()
+        };
+        let links = LinkPolicy {
+            shorteners: normalize_domains(file.shortener_domains
+                .unwrap_or_else(|| DEFAULT_SHORTENERS.iter().map(|s| s.to_string()).collect())),
+            blocked: normalize_domains(file.blocked_domains),
+            allowed: normalize_domains(file.allowed_domains),
+        };
+
         // Parse scan times (HH:MM format)
         let scan_times = file.scan_times
             .into_iter()
@@ -214,6 +598,118 @@ impl Config {
                 .map_err(|_| ConfigError::Validation(format!("invalid scan_timezone '{}' (expected IANA timezone like 'Europe/Paris')", tz)))?,
             None => chrono_tz::UTC,
         };
+        let quiet_hours = file.quiet_hours
+            .map(|q| crate::chatbot::quiet_hours::QuietHours::new(&q, scan_timezone))
+            .transpose()
+            .map_err(|e| ConfigError::Validation(format!("quiet_hours: {}", e)))?;
+
+        let wiki_default_lang = file.wiki_default_lang
+            .map(|l| l.trim().to_lowercase())
+            .filter(|l| !l.is_empty())
+            .unwrap_or_else(|| "en".to_string());
+        crate::chatbot::wiki::validate_lang(&wiki_default_lang)
+            .map_err(|e| ConfigError::Validation(format!("wiki_default_lang: {}", e)))?;
+
+        let gemini_text_model = file.gemini_text_model
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| crate::chatbot::gemini::DEFAULT_TEXT_MODEL.to_string());
+        crate::chatbot::gemini::validate_model_name(&gemini_text_model)
+            .map_err(ConfigError::Validation)?;
+
+        let response_language = match file.response_language {
+            Some(l) => ResponseLanguage::parse(&l)
+                .map_err(|e| ConfigError::Validation(format!("response_language: {}", e)))?,
+            None => ResponseLanguage::Auto,
+        };
+
+        let voice_include_transcript = match file.voice_include_transcript {
+            Some(mode) => VoiceTranscript::parse(&mode)
+                .map_err(|e| ConfigError::Validation(format!("voice_include_transcript: {}", e)))?,
+            None => VoiceTranscript::StoreOnly,
+        };
+
+        let startup_notify = match file.startup_notify {
+            Some(mode) => StartupNotify::parse(&mode)
+                .map_err(|e| ConfigError::Validation(format!("startup_notify: {}", e)))?,
+            None => StartupNotify::Always,
+        };
+
+        let dm_policy = match file.dm_policy {
+            Some(policy) => DmPolicy::parse(&policy)
+                .map_err(|e| ConfigError::Validation(format!("dm_policy: {}", e)))?,
+            None => DmPolicy::Deny,
+        };
+        if file.dm_denied_expiry_days == 0 {
+            return Err(ConfigError::Validation("dm_denied_expiry_days must be at least 1".to_string()));
+        }
+
+        let error_digest_time = match file.error_digest_hour {
+            Some(hour) => Some(chrono::NaiveTime::from_hms_opt(hour, 0, 0).ok_or_else(|| {
+                ConfigError::Validation(format!("error_digest_hour: {} is not an hour (0-23)", hour))
+            })?),
+            None => None,
+        };
+
+        let user_digest_time = match file.user_digest_hour {
+            Some(hour) => Some(chrono::NaiveTime::from_hms_opt(hour, 0, 0).ok_or_else(|| {
+                ConfigError::Validation(format!("user_digest_hour: {} is not an hour (0-23)", hour))
+            })?),
+            None => None,
+        };
+
+        let transparency_time = match file.transparency_hour {
+            Some(hour) => Some(chrono::NaiveTime::from_hms_opt(hour, 0, 0).ok_or_else(|| {
+                ConfigError::Validation(format!("transparency_hour: {} is not an hour (0-23)", hour))
+            })?),
+            None => None,
+        };
+
+        let tool_timeouts = crate::chatbot::tool_timeouts::parse_overrides(&file.tool_timeouts)
+            .map_err(|e| ConfigError::Validation(format!("tool_timeouts: {}", e)))?;
+        let tool_result_limits = crate::chatbot::tool_results::parse_overrides(&file.tool_result_limits)
+            .map_err(|e| ConfigError::Validation(format!("tool_result_limits: {}", e)))?;
+
+        if let Some(cron) = &file.backup_cron {
+            crate::chatbot::reminders::validate_cron(cron)
+                .map_err(|e| ConfigError::Validation(format!("backup_cron: {}", e)))?;
+        }
+        let personalities = crate::chatbot::personality::Personalities::new(
+            file.personality,
+            file.personalities,
+            &file.personality_schedule,
+        )
+        .map_err(|e| ConfigError::Validation(format!("personality_schedule: {}", e)))?;
+
+        if file.backup_keep == 0 {
+            return Err(ConfigError::Validation("backup_keep must be at least 1".to_string()));
+        }
+        if file.transcript_max_mb == 0 {
+            return Err(ConfigError::Validation("transcript_max_mb must be at least 1".to_string()));
+        }
+
+        if let Some(max) = file.debounce_max_ms_group
+            && max < file.debounce_ms_group
+        {
+            return Err(ConfigError::Validation(format!(
+                "debounce_max_ms_group ({}) must be at least debounce_ms_group ({})",
+                max, file.debounce_ms_group
+            )));
+        }
+
+        let metrics_listen = file.metrics_listen
+            .map(|addr| addr.parse().map_err(|e| ConfigError::Validation(format!("metrics_listen '{}': {}", addr, e))))
+            .transpose()?;
+
+        let output_guard = OutputGuard::from_config(file.forbidden_output_patterns.as_ref())
+            .map_err(|e| ConfigError::Validation(format!("forbidden_output_patterns: {}", e)))?;
+
+        let ocr_visual_keywords = file.ocr_visual_keywords
+            .unwrap_or_else(|| crate::chatbot::ocr::DEFAULT_VISUAL_KEYWORDS.iter().map(|k| k.to_string()).collect())
+            .into_iter()
+            .map(|k| k.trim().to_lowercase())
+            .filter(|k| !k.is_empty())
+            .collect();
 
         Ok(Self {
             owner_ids,
@@ -224,6 +720,7 @@ impl Config {
             gemini_api_key: file.gemini_api_key,
             allowed_groups,
             primary_chat_id,
+            chat_migrations: Arc::new(ChatMigrations::default()),
             trusted_channels,
             spam_patterns,
             safe_patterns,
@@ -233,11 +730,60 @@ impl Config {
             data_dir,
             whisper_model_path: file.whisper_model_path.map(PathBuf::from),
             tts_endpoint: file.tts_endpoint,
-            personality: file.personality,
+            voice_include_transcript,
+            compact_prompt: file.compact_prompt,
+            personalities,
             scan_interval_minutes: file.scan_interval_minutes,
             scan_times,
             scan_timezone,
+            quiet_hours,
+            ack_reactions: file.ack_reactions,
+            metrics_listen,
             peer_bots: file.peer_bots.into_iter().map(|s| s.trim_start_matches('@').to_lowercase()).collect(),
+            process_bot_messages: file.process_bot_messages,
+            wiki_default_lang,
+            translate_max_chars: file.translate_max_chars,
+            compaction_summary_tokens: file.compaction_summary_tokens,
+            compaction_restore_tokens: file.compaction_restore_tokens,
+            compaction_cooldown: std::time::Duration::from_secs(file.compaction_cooldown_mins * 60),
+            gemini_text_model,
+            response_language,
+            output_guard: Arc::new(RwLock::new(output_guard)),
+            debounce_ms_dm: file.debounce_ms_dm,
+            debounce_ms_group: file.debounce_ms_group,
+            debounce_max_ms_group: file.debounce_max_ms_group,
+            max_pending_messages: file.max_pending_messages,
+            maintenance_reply: file.maintenance_reply,
+            ocr_screenshots: file.ocr_screenshots,
+            ocr_visual_keywords,
+            max_image_bytes: file.max_image_bytes,
+            startup_notify,
+            dm_policy,
+            dm_intro_message: file.dm_intro_message.unwrap_or_else(|| DEFAULT_INTRO.to_string()),
+            dm_forward_limit: file.dm_forward_limit,
+            dm_denied_expiry: chrono::Duration::days(file.dm_denied_expiry_days.into()),
+            error_digest_time,
+            user_digest_time,
+            user_digest_top: file.user_digest_top,
+            transparency_time,
+            transparency_chat_id: file.transparency_chat_id,
+            transparency_anonymize: file.transparency_anonymize,
+            tool_timeouts,
+            tool_result_limits,
+            backup_cron: file.backup_cron,
+            backup_keep: file.backup_keep,
+            protect_admins: file.protect_admins,
+            atomic_batches: file.atomic_batches,
+            claude_program: PathBuf::from(file.claude_program),
+            transcript_full: file.transcript_full,
+            transcript_max_bytes: file.transcript_max_mb * 1024 * 1024,
+            backfill_max_age: chrono::Duration::minutes(file.backfill_max_age_minutes as i64),
+            media_concurrency: file.media_concurrency,
+            media_timeout: std::time::Duration::from_secs(file.media_timeout_secs),
+            raid_threshold: file.raid_threshold,
+            raid_duration: chrono::Duration::minutes(file.raid_duration_minutes as i64),
+            via_bot_ambiguous: file.via_bot_ambiguous,
+            links,
         })
     }
 
@@ -245,6 +791,19 @@ impl Config {
         self.owner_ids.contains(&user_id)
     }
 
+    /// The primary chat's current ID, following a supergroup migration.
+    pub fn primary_chat(&self) -> i64 {
+        self.chat_migrations.current(self.primary_chat_id)
+    }
+
+    /// Whether the bot works in `chat_id`: no `allowed_groups` means every
+    /// group, and a listed group that became a supergroup keeps its place.
+    pub fn allows_group(&self, chat_id: ChatId) -> bool {
+        self.allowed_groups.is_empty()
+            || self.allowed_groups.contains(&chat_id)
+            || self.chat_migrations.previous(chat_id.0).into_iter().any(|id| self.allowed_groups.contains(&ChatId(id)))
+    }
+
     /// Check if user can DM the bot (owners + trusted DM users)
     pub fn can_dm(&self, user_id: UserId) -> bool {
         self.owner_ids.contains(&user_id)
@@ -252,10 +811,12 @@ impl Config {
                 .expect("trusted_dm_users lock poisoned")
                 .contains_key(&(user_id.0 as i64))
     }
+}
 
-    pub fn is_trusted_channel(&self, chat_id: ChatId) -> bool {
-        self.trusted_channels.contains(&chat_id)
-    }
+/// Check that `content` deserializes as a config file. Used to vet runtime
+/// config changes before they're saved.
+pub fn validate_json(content: &str) -> Result<(), String> {
+    serde_json::from_str::<ConfigFile>(content).map(|_| ()).map_err(|e| e.to_string())
 }
 
 fn default_spam_patterns() -> Vec<Regex> {
@@ -375,6 +936,17 @@ mod tests {
         assert!(matches!(err, ConfigError::InvalidRegex { .. }));
     }
 
+    #[test]
+    fn test_invalid_metrics_listen() {
+        let file = write_config(r#"{
+            "owner_ids": [123],
+            "telegram_bot_token": "123456789:ABCdef",
+            "metrics_listen": "localhost"
+        }"#);
+        let err = assert_err(Config::load(file.path()));
+        assert!(err.to_string().contains("metrics_listen"));
+    }
+
     #[test]
     fn test_file_not_found() {
         let err = assert_err(Config::load("/nonexistent/path/config.json"));
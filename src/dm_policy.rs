@@ -0,0 +1,186 @@
+//! DMs from users who aren't allowed to DM the bot.
+//!
+//! `dm_policy` decides what they get: a flat "Access denied." ("deny"), a
+//! friendly reply explaining how to get access with the owner told who asked
+//! ("intro"), or their first few messages forwarded to the owner without
+//! involving Claude ("forward"). Each user's DM count is kept in the database,
+//! so a restart doesn't greet them again. Counts are forgotten after
+//! `dm_denied_expiry_days`, so someone who writes again much later gets one
+//! fresh reply, and when the user is made a trusted DM user. At most
+//! `MAX_UNKNOWN_DMS` users are tracked; the oldest records go first.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::chatbot::database::MAX_UNKNOWN_DMS;
+
+/// Default reply under the "intro" policy.
+pub const DEFAULT_INTRO: &str = "Hi! I only chat in DMs with people my owner has added. \
+I've passed your message on to them; if they add you, I'll answer here.";
+
+/// Reply under the "deny" policy.
+pub const DENY_MESSAGE: &str = "Access denied.";
+
+/// Longest message text quoted in the owner's notice.
+const MAX_QUOTE_CHARS: usize = 500;
+
+/// Days a user's DM count is kept unless configured otherwise.
+pub const DEFAULT_EXPIRY_DAYS: u32 = 7;
+
+/// What to do with DMs from unknown users.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DmPolicy {
+    Deny,
+    Intro,
+    Forward,
+}
+
+impl DmPolicy {
+    /// Parse a `dm_policy` config value.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "deny" => Ok(Self::Deny),
+            "intro" => Ok(Self::Intro),
+            "forward" => Ok(Self::Forward),
+            other => Err(format!("invalid value '{}' (expected \"deny\", \"intro\" or \"forward\")", other)),
+        }
+    }
+}
+
+/// The response to one DM from an unknown user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DmAction {
+    /// Reply "Access denied."
+    Deny,
+    /// Reply with the intro and tell the owner.
+    Intro,
+    /// Forward the message to the owner.
+    Forward,
+    /// Already handled; stay quiet.
+    Ignore,
+}
+
+/// What to do with the `nth` DM (1-based) from an unknown user.
+pub fn action(policy: DmPolicy, nth: u32, forward_limit: u32) -> DmAction {
+    match policy {
+        DmPolicy::Deny if nth == 1 => DmAction::Deny,
+        DmPolicy::Intro if nth == 1 => DmAction::Intro,
+        DmPolicy::Forward if nth <= forward_limit => DmAction::Forward,
+        _ => DmAction::Ignore,
+    }
+}
+
+/// DM counts kept in memory when there's no database, with the same expiry
+/// and cap.
+#[derive(Debug, Default)]
+pub struct DmCounts {
+    /// DMs so far and when the first was, by user.
+    users: HashMap<u64, (u32, DateTime<Utc>)>,
+}
+
+impl DmCounts {
+    /// Count a DM from `user_id`. Returns how many they've sent, including
+    /// this one, since their count was last forgotten.
+    pub fn record(&mut self, user_id: u64, now: DateTime<Utc>, expire_after: chrono::Duration) -> u32 {
+        let live = |first: &DateTime<Utc>| now - *first < expire_after;
+        if self.users.get(&user_id).is_some_and(|(_, first)| !live(first)) {
+            self.users.remove(&user_id);
+        }
+        // Make room for a new user: drop expired records, else the oldest
+        if !self.users.contains_key(&user_id) && self.users.len() >= MAX_UNKNOWN_DMS {
+            self.users.retain(|_, (_, first)| live(first));
+            if self.users.len() >= MAX_UNKNOWN_DMS
+                && let Some(oldest) = self.users.iter().min_by_key(|(_, (_, first))| *first).map(|(id, _)| *id)
+            {
+                self.users.remove(&oldest);
+            }
+        }
+        let (nth, _) = self.users.entry(user_id).or_insert((0, now));
+        *nth += 1;
+        *nth
+    }
+}
+
+/// The owner's notice about a user who got the intro.
+pub fn owner_notice(user_id: u64, first_name: &str, username: Option<&str>, text: Option<&str>) -> String {
+    let who = match username {
+        Some(username) => format!("{} (@{}, {})", first_name, username, user_id),
+        None => format!("{} ({})", first_name, user_id),
+    };
+    let quote = match text.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(text) if text.chars().count() > MAX_QUOTE_CHARS => {
+            format!("\"{}…\"", text.chars().take(MAX_QUOTE_CHARS).collect::<String>())
+        }
+        Some(text) => format!("\"{}\"", text),
+        None => "(no text)".to_string(),
+    };
+    format!(
+        "✉️ {} DMed me but isn't allowed to:\n{}\n\nAdd them as a trusted user if they should have access.",
+        who, quote
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(DmPolicy::parse("deny"), Ok(DmPolicy::Deny));
+        assert_eq!(DmPolicy::parse(" Intro "), Ok(DmPolicy::Intro));
+        assert_eq!(DmPolicy::parse("forward"), Ok(DmPolicy::Forward));
+        assert!(DmPolicy::parse("ignore").unwrap_err().contains("expected"));
+    }
+
+    #[test]
+    fn test_policy_actions() {
+        // deny and intro answer once, then stay quiet
+        assert_eq!(action(DmPolicy::Deny, 1, 3), DmAction::Deny);
+        assert_eq!(action(DmPolicy::Deny, 2, 3), DmAction::Ignore);
+        assert_eq!(action(DmPolicy::Intro, 1, 3), DmAction::Intro);
+        assert_eq!(action(DmPolicy::Intro, 2, 3), DmAction::Ignore);
+
+        // forward relays the first N
+        assert_eq!(action(DmPolicy::Forward, 1, 3), DmAction::Forward);
+        assert_eq!(action(DmPolicy::Forward, 3, 3), DmAction::Forward);
+        assert_eq!(action(DmPolicy::Forward, 4, 3), DmAction::Ignore);
+        assert_eq!(action(DmPolicy::Forward, 1, 0), DmAction::Ignore);
+    }
+
+    #[test]
+    fn test_counts_expire() {
+        let mut counts = DmCounts::default();
+        let week = chrono::Duration::days(7);
+        let start = Utc::now();
+        assert_eq!(counts.record(42, start, week), 1);
+        assert_eq!(counts.record(42, start + chrono::Duration::days(6), week), 2);
+        // A week after the first DM, they're new again
+        assert_eq!(counts.record(42, start + week, week), 1);
+    }
+
+    #[test]
+    fn test_counts_evict_oldest() {
+        let mut counts = DmCounts::default();
+        let week = chrono::Duration::days(7);
+        let start = Utc::now();
+        for user_id in 0..=MAX_UNKNOWN_DMS as u64 {
+            counts.record(user_id, start + chrono::Duration::seconds(user_id as i64), week);
+        }
+        assert_eq!(counts.users.len(), MAX_UNKNOWN_DMS);
+        assert!(!counts.users.contains_key(&0));
+        assert_eq!(counts.record(1, start, week), 2);
+    }
+
+    #[test]
+    fn test_owner_notice() {
+        assert_eq!(
+            owner_notice(42, "Alice", Some("alice"), Some("hi, I'm from the Rust group")),
+            "✉️ Alice (@alice, 42) DMed me but isn't allowed to:\n\"hi, I'm from the Rust group\"\n\n\
+             Add them as a trusted user if they should have access."
+        );
+        assert!(owner_notice(42, "Bob", None, None).starts_with("✉️ Bob (42) DMed me but isn't allowed to:\n(no text)"));
+        let long = owner_notice(42, "Bob", None, Some(&"x".repeat(600)));
+        assert!(long.contains(&format!("\"{}…\"", "x".repeat(MAX_QUOTE_CHARS))));
+    }
+}
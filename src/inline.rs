@@ -0,0 +1,137 @@
+//! Inline queries: `@bot quote alice` or `@bot stats`, typed in any chat.
+//!
+//! Trusted users can pull canned results into chats the bot isn't in. The
+//! query is parsed here rather than by Claude and answered from the
+//! database, well within Telegram's time limit. Everyone else gets no
+//! results.
+
+use std::time::Duration;
+
+use teloxide::types::{InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText};
+
+use crate::chatbot::ChatMessage;
+
+/// Longest we spend building results; Telegram drops answers after ~10s.
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Longest quoted text before it's cut.
+const MAX_QUOTE_CHARS: usize = 1000;
+
+/// What an inline query asks for.
+#[derive(Debug, PartialEq)]
+pub enum Intent {
+    /// The user's last group message.
+    Quote(String),
+    /// Primary chat stats.
+    Stats,
+    Unsupported,
+}
+
+pub fn parse(query: &str) -> Intent {
+    let mut words = query.split_whitespace();
+    let Some(first) = words.next() else {
+        return Intent::Unsupported;
+    };
+    let rest: Vec<&str> = words.collect();
+    match (first.to_lowercase().as_str(), rest.as_slice()) {
+        ("quote", [username]) => {
+            let username = username.trim_start_matches('@');
+            if username.is_empty() {
+                Intent::Unsupported
+            } else {
+                Intent::Quote(username.to_string())
+            }
+        }
+        ("stats", []) => Intent::Stats,
+        _ => Intent::Unsupported,
+    }
+}
+
+fn article(id: &str, title: &str, description: &str, text: String) -> InlineQueryResult {
+    let content = InputMessageContent::Text(InputMessageContentText::new(text));
+    InlineQueryResult::Article(InlineQueryResultArticle::new(id, title, content).description(description))
+}
+
+/// A quote of `message`, or a note that `username` has nothing to quote.
+pub fn quote_result(username: &str, message: Option<&ChatMessage>) -> InlineQueryResult {
+    let Some(message) = message else {
+        let text = format!("No messages from {} to quote.", username);
+        return article("quote-none", &format!("Nothing to quote from {}", username), "", text);
+    };
+    let mut quoted: String = message.text.chars().take(MAX_QUOTE_CHARS).collect();
+    if quoted.len() < message.text.len() {
+        quoted.push('…');
+    }
+    let text = format!("«{}»\n— {}, {}", quoted, message.username, message.timestamp);
+    let preview: String = message.text.chars().take(100).collect();
+    article(&format!("quote-{}", message.message_id), &format!("Quote {}", message.username), &preview, text)
+}
+
+/// Chat stats, already rendered as text.
+pub fn stats_result(stats: &str) -> InlineQueryResult {
+    let summary = stats.lines().next().unwrap_or_default();
+    article("stats", "Chat stats", summary, stats.to_string())
+}
+
+/// A result for anything that couldn't be answered, with `reason`.
+pub fn error_result(reason: &str) -> InlineQueryResult {
+    article("error", "Not available", reason, reason.to_string())
+}
+
+pub fn unsupported_result(query: &str) -> InlineQueryResult {
+    let help = "Try \"quote <username>\" or \"stats\".";
+    let text = format!("\"{}\" isn't supported inline. {}", query.trim(), help);
+    article("unsupported", "Not supported", help, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_of(result: &InlineQueryResult) -> (String, String) {
+        let InlineQueryResult::Article(article) = result else {
+            panic!("expected an article");
+        };
+        let InputMessageContent::Text(content) = &article.input_message_content else {
+            panic!("expected text content");
+        };
+        (article.title.clone(), content.message_text.clone())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("quote alice"), Intent::Quote("alice".to_string()));
+        assert_eq!(parse("  Quote   @alice "), Intent::Quote("alice".to_string()));
+        assert_eq!(parse("stats"), Intent::Stats);
+        assert_eq!(parse("STATS"), Intent::Stats);
+        assert_eq!(parse(""), Intent::Unsupported);
+        assert_eq!(parse("quote"), Intent::Unsupported);
+        assert_eq!(parse("quote @"), Intent::Unsupported);
+        assert_eq!(parse("quote alice bob"), Intent::Unsupported);
+        assert_eq!(parse("remind me tomorrow"), Intent::Unsupported);
+    }
+
+    #[test]
+    fn test_results() {
+        let message: ChatMessage = serde_json::from_value(serde_json::json!({
+            "message_id": 42, "chat_id": -100, "user_id": 7, "username": "alice",
+            "timestamp": "2024-01-15 10:00", "text": "x".repeat(1200),
+        }))
+        .unwrap();
+        let quote = quote_result("alice", Some(&message));
+        let (title, text) = text_of(&quote);
+        assert_eq!(title, "Quote alice");
+        assert!(text.starts_with(&format!("«{}…»", "x".repeat(MAX_QUOTE_CHARS))));
+        assert!(text.ends_with("— alice, 2024-01-15 10:00"));
+
+        let (_, text) = text_of(&quote_result("bob", None));
+        assert_eq!(text, "No messages from bob to quote.");
+
+        let (title, text) = text_of(&stats_result("Chat -100, last 7 days: 5 messages\nTop posters: alice 5"));
+        assert_eq!(title, "Chat stats");
+        assert!(text.contains("Top posters"));
+
+        let (_, text) = text_of(&unsupported_result(" remind me "));
+        assert!(text.starts_with("\"remind me\" isn't supported inline."));
+    }
+}
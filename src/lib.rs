@@ -1,3 +1,21 @@
-//! Claudima library - exposes modules for integration tests.
+//! Claudima library - the bot and its modules. The binary only loads the
+//! config and runs the dispatcher; integration tests drive the same code.
 
+pub mod backfill;
+pub mod bot;
 pub mod chatbot;
+pub mod cli;
+pub mod config;
+pub mod startup;
+pub mod telegram_log;
+mod classifier;
+mod claude;
+mod dm_policy;
+mod inline;
+mod links;
+mod media;
+mod member_events;
+mod prefilter;
+mod senders;
+mod spam_images;
+mod spam_wave;
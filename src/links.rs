@@ -0,0 +1,280 @@
+//! Link analysis for the spam filter: domain block/allow lists and
+//! expansion of URL shorteners that hide t.me links.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Timeout for resolving a shortened link.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long an expansion (or failure) is cached.
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Shorteners expanded when `shortener_domains` isn't configured.
+pub const DEFAULT_SHORTENERS: &[&str] = &[
+    "bit.ly", "tinyurl.com", "telegra.ph", "t.co", "cutt.ly", "is.gd", "rb.gy", "clck.ru", "shorturl.at",
+];
+
+static URL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:https?://)?(?:[a-z0-9-]+\.)+[a-z]{2,}(?:/[^\s]*)?").expect("valid url regex")
+});
+
+/// Domain lists from config. Domains match themselves and their subdomains.
+#[derive(Debug, Clone)]
+pub struct LinkPolicy {
+    /// Shortener domains whose links are expanded before spam checks.
+    pub shorteners: Vec<String>,
+    /// Links to these domains make a message spam.
+    pub blocked: Vec<String>,
+    /// Never blocked or expanded, and fine as an expansion target.
+    pub allowed: Vec<String>,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        Self {
+            shorteners: DEFAULT_SHORTENERS.iter().map(|s| s.to_string()).collect(),
+            blocked: vec![],
+            allowed: vec![],
+        }
+    }
+}
+
+impl LinkPolicy {
+    /// First blocked domain linked in `text`, if any.
+    pub fn blocked_domain(&self, text: &str) -> Option<String> {
+        extract_urls(text)
+            .iter()
+            .filter_map(|u| domain_of(u))
+            .find(|d| !domain_in(d, &self.allowed) && domain_in(d, &self.blocked))
+    }
+}
+
+/// All URL-like substrings in `text` (with or without scheme).
+fn extract_urls(text: &str) -> Vec<String> {
+    URL_RE
+        .find_iter(text)
+        .map(|m| m.as_str().trim_end_matches(['.', ',', '!', '?', ')', ';', ':']).to_string())
+        .collect()
+}
+
+/// Lowercased host of a URL, without "www.".
+fn domain_of(url: &str) -> Option<String> {
+    let lower = url.to_lowercase();
+    let rest = lower.split_once("://").map(|(_, r)| r).unwrap_or(&lower);
+    let host = rest.split(['/', '?', '#', ':']).next()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Whether `domain` equals or is a subdomain of an entry in `list`.
+fn domain_in(domain: &str, list: &[String]) -> bool {
+    list.iter().any(|d| domain == d || domain.ends_with(&format!(".{}", d)))
+}
+
+/// Resolves a URL to its final location after redirects.
+pub trait UrlResolver {
+    fn resolve(&self, url: &str) -> impl Future<Output = Result<String, String>> + Send;
+}
+
+/// Resolves URLs with a HEAD request, following redirects.
+pub struct HttpResolver {
+    client: reqwest::Client,
+}
+
+impl HttpResolver {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(RESOLVE_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        Self { client }
+    }
+}
+
+impl Default for HttpResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UrlResolver for HttpResolver {
+    async fn resolve(&self, url: &str) -> Result<String, String> {
+        let url = if url.contains("://") { url.to_string() } else { format!("https://{}", url) };
+        let response = self.client.head(&url).send().await.map_err(|e| format!("HEAD {url}: {e}"))?;
+        Ok(response.url().to_string())
+    }
+}
+
+/// Expands shortened links (cached) and checks where they lead.
+pub struct LinkExpander<R: UrlResolver> {
+    resolver: R,
+    /// URL → (expanded URL, or None if resolving failed; cached at).
+    cache: Mutex<HashMap<String, (Option<String>, Instant)>>,
+}
+
+impl<R: UrlResolver> LinkExpander<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Expand a URL, using the cache when fresh. None if it can't be resolved.
+    async fn expand(&self, url: &str) -> Option<String> {
+        {
+            let cache = self.cache.lock().await;
+            if let Some((expanded, at)) = cache.get(url)
+                && at.elapsed() < CACHE_TTL
+            {
+                return expanded.clone();
+            }
+        }
+
+        let expanded = match self.resolver.resolve(url).await {
+            Ok(u) => Some(u),
+            Err(e) => {
+                warn!("Failed to expand {}: {}", url, e);
+                None
+            }
+        };
+
+        let mut cache = self.cache.lock().await;
+        cache.retain(|_, (_, at)| at.elapsed() < CACHE_TTL);
+        cache.insert(url.to_string(), (expanded.clone(), Instant::now()));
+        expanded
+    }
+
+    /// Whether a shortened link in `text` leads to a blocked domain or to a URL
+    /// matching a spam pattern. Only shortener links trigger network requests.
+    pub async fn expands_to_spam(&self, text: &str, policy: &LinkPolicy, spam_patterns: &[Regex]) -> bool {
+        for url in extract_urls(text) {
+            let Some(domain) = domain_of(&url) else { continue };
+            if domain_in(&domain, &policy.allowed) || !domain_in(&domain, &policy.shorteners) {
+                continue;
+            }
+
+            let Some(expanded) = self.expand(&url).await else { continue };
+            let Some(target) = domain_of(&expanded) else { continue };
+            if domain_in(&target, &policy.allowed) {
+                continue;
+            }
+
+            if domain_in(&target, &policy.blocked) || spam_patterns.iter().any(|p| p.is_match(&expanded)) {
+                info!("🔗 Shortened link {} expands to spam target {}", url, expanded);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockResolver {
+        targets: HashMap<String, Result<String, String>>,
+        calls: AtomicUsize,
+    }
+
+    impl MockResolver {
+        fn new(targets: &[(&str, Result<&str, &str>)]) -> Self {
+            Self {
+                targets: targets.iter()
+                    .map(|(k, v)| (k.to_string(), v.map(String::from).map_err(String::from)))
+                    .collect(),
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl UrlResolver for MockResolver {
+        async fn resolve(&self, url: &str) -> Result<String, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.targets.get(url).cloned().unwrap_or_else(|| Err("not found".to_string()))
+        }
+    }
+
+    fn policy() -> LinkPolicy {
+        LinkPolicy {
+            blocked: vec!["scam.example".to_string()],
+            allowed: vec!["github.com".to_string()],
+            ..LinkPolicy::default()
+        }
+    }
+
+    fn spam_patterns() -> Vec<Regex> {
+        vec![Regex::new(r"(?i)t\.me/\S+").unwrap()]
+    }
+
+    #[test]
+    fn test_extract_urls_and_domains() {
+        let urls = extract_urls("see https://www.Example.com/a?b=1 and bit.ly/xyz, ok?");
+        assert_eq!(urls, vec!["https://www.Example.com/a?b=1", "bit.ly/xyz"]);
+        assert_eq!(domain_of(&urls[0]).as_deref(), Some("example.com"));
+        assert_eq!(domain_of(&urls[1]).as_deref(), Some("bit.ly"));
+        assert!(extract_urls("no links here").is_empty());
+    }
+
+    #[test]
+    fn test_blocked_domain() {
+        let p = policy();
+        assert_eq!(p.blocked_domain("go to https://scam.example/win").as_deref(), Some("scam.example"));
+        assert_eq!(p.blocked_domain("go to promo.scam.example now").as_deref(), Some("promo.scam.example"));
+        assert_eq!(p.blocked_domain("go to notscam.example"), None);
+        assert_eq!(p.blocked_domain("see github.com/rust-lang"), None);
+    }
+
+    #[tokio::test]
+    async fn test_shortener_expanding_to_telegram_is_spam() {
+        let resolver = MockResolver::new(&[("bit.ly/abc", Ok("https://t.me/cryptopump"))]);
+        let expander = LinkExpander::new(resolver);
+        assert!(expander.expands_to_spam("free money bit.ly/abc", &policy(), &spam_patterns()).await);
+    }
+
+    #[tokio::test]
+    async fn test_shortener_to_blocked_domain_is_spam() {
+        let resolver = MockResolver::new(&[("https://tinyurl.com/x", Ok("https://promo.scam.example/landing"))]);
+        let expander = LinkExpander::new(resolver);
+        assert!(expander.expands_to_spam("look https://tinyurl.com/x", &policy(), &[]).await);
+    }
+
+    #[tokio::test]
+    async fn test_harmless_or_allowed_targets_pass() {
+        let resolver = MockResolver::new(&[
+            ("bit.ly/docs", Ok("https://docs.rs/tokio")),
+            ("bit.ly/ours", Ok("https://github.com/org/repo")),
+        ]);
+        let expander = LinkExpander::new(resolver);
+        assert!(!expander.expands_to_spam("read bit.ly/docs", &policy(), &spam_patterns()).await);
+        assert!(!expander.expands_to_spam("repo bit.ly/ours", &policy(), &spam_patterns()).await);
+    }
+
+    #[tokio::test]
+    async fn test_non_shortener_links_not_resolved() {
+        let expander = LinkExpander::new(MockResolver::new(&[]));
+        assert!(!expander.expands_to_spam("see https://example.com/page", &policy(), &spam_patterns()).await);
+        assert_eq!(expander.resolver.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_expansion_cached() {
+        let resolver = MockResolver::new(&[("bit.ly/abc", Ok("https://t.me/cryptopump"))]);
+        let expander = LinkExpander::new(resolver);
+
+        assert!(expander.expands_to_spam("bit.ly/abc", &policy(), &spam_patterns()).await);
+        assert!(expander.expands_to_spam("again bit.ly/abc", &policy(), &spam_patterns()).await);
+        assert_eq!(expander.resolver.calls.load(Ordering::SeqCst), 1);
+
+        // Failures are cached too, so a dead shortener doesn't cost 3s per message
+        assert!(!expander.expands_to_spam("bit.ly/dead", &policy(), &spam_patterns()).await);
+        assert!(!expander.expands_to_spam("bit.ly/dead", &policy(), &spam_patterns()).await);
+        assert_eq!(expander.resolver.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,163 @@
+//! Fetching message media (photos, voice, documents) off the update handler.
+//!
+//! A burst of attachments used to be downloaded one after another inside the
+//! update handler, holding up the dispatcher for tens of seconds. Messages
+//! are now assembled in their own task, and every download goes through
+//! `MediaLimiter`: at most `concurrency` at a time, each given `timeout` once
+//! it starts. An item that fails or times out becomes the usual placeholder
+//! text; the rest of the message still goes through. `DeliveryQueue` then
+//! hands each chat's messages on in the order they arrived, so a text sent
+//! after a photo doesn't overtake it while the photo downloads.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Downloads running at once, by default.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Time a single download (and its transcription or extraction) gets, by default.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Bounds concurrent media downloads and how long each may take.
+#[derive(Debug)]
+pub struct MediaLimiter {
+    permits: Semaphore,
+    timeout: Duration,
+}
+
+impl MediaLimiter {
+    pub fn new(concurrency: usize, timeout: Duration) -> Self {
+        Self { permits: Semaphore::new(concurrency.max(1)), timeout }
+    }
+
+    /// Run `download` once a slot is free. Err if it doesn't finish within
+    /// the timeout, counted from when it starts.
+    pub async fn run<T>(&self, download: impl Future<Output = T>) -> Result<T, String> {
+        let _permit = self.permits.acquire().await
+            .map_err(|e| format!("media downloads stopped: {e}"))?;
+        tokio::time::timeout(self.timeout, download).await
+            .map_err(|_| format!("timed out after {:?}", self.timeout))
+    }
+}
+
+/// Runs each chat's deliveries one after another, in the order they were
+/// queued. Chats don't wait on each other.
+#[derive(Debug, Default)]
+pub struct DeliveryQueue {
+    /// The delivery queued last in each chat.
+    tails: Mutex<HashMap<i64, JoinHandle<()>>>,
+}
+
+impl DeliveryQueue {
+    /// Run `deliver` once every delivery queued before it in `chat_id` has finished.
+    pub fn push(&self, chat_id: i64, deliver: impl Future<Output = ()> + Send + 'static) {
+        let mut tails = self.tails.lock().expect("delivery queue lock poisoned");
+        tails.retain(|_, tail| !tail.is_finished());
+        let previous = tails.remove(&chat_id);
+        let task = tokio::spawn(async move {
+            if let Some(previous) = previous
+                && let Err(e) = previous.await
+            {
+                warn!("Earlier delivery in chat {} failed: {}", chat_id, e);
+            }
+            deliver.await;
+        });
+        tails.insert(chat_id, task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts downloads in flight and the most seen at once.
+    #[derive(Default)]
+    struct MockDownloader {
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl MockDownloader {
+        /// Item `id`: takes `delay`, fails if `id` is odd.
+        async fn download(&self, id: usize, delay: Duration) -> Result<usize, String> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if id % 2 == 1 { Err(format!("item {id} is broken")) } else { Ok(id) }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bounded_concurrency() {
+        let limiter = Arc::new(MediaLimiter::new(4, Duration::from_secs(5)));
+        let downloader = Arc::new(MockDownloader::default());
+        let tasks: Vec<_> = (0..15).map(|id| {
+            let (limiter, downloader) = (limiter.clone(), downloader.clone());
+            tokio::spawn(async move { limiter.run(downloader.download(id, Duration::from_millis(20))).await })
+        }).collect();
+
+        let mut results = Vec::new();
+        for task in tasks {
+            results.push(task.await.unwrap());
+        }
+        assert_eq!(downloader.peak.load(Ordering::SeqCst), 4);
+
+        // A failed item doesn't affect the others
+        assert_eq!(results[0], Ok(Ok(0)));
+        assert_eq!(results[1], Ok(Err("item 1 is broken".to_string())));
+        assert_eq!(results.iter().filter(|r| matches!(r, Ok(Ok(_)))).count(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_per_download() {
+        let limiter = Arc::new(MediaLimiter::new(1, Duration::from_millis(50)));
+        let downloader = Arc::new(MockDownloader::default());
+        let slow = {
+            let (limiter, downloader) = (limiter.clone(), downloader.clone());
+            tokio::spawn(async move { limiter.run(downloader.download(0, Duration::from_secs(5))).await })
+        };
+        let fast = {
+            let (limiter, downloader) = (limiter.clone(), downloader.clone());
+            tokio::spawn(async move { limiter.run(downloader.download(2, Duration::from_millis(10))).await })
+        };
+
+        assert_eq!(slow.await.unwrap(), Err("timed out after 50ms".to_string()));
+        // Waiting for the slot doesn't count against the timeout
+        assert_eq!(fast.await.unwrap(), Ok(Ok(2)));
+    }
+
+    #[test]
+    fn test_zero_concurrency_still_runs() {
+        let limiter = MediaLimiter::new(0, Duration::from_secs(1));
+        assert_eq!(limiter.permits.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_order_per_chat() {
+        let queue = DeliveryQueue::default();
+        let delivered = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let deliver = |label: &'static str, delay: Duration| {
+            let delivered = delivered.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                delivered.lock().unwrap().push(label);
+            }
+        };
+        queue.push(1, deliver("slow photo", Duration::from_millis(100)));
+        queue.push(1, deliver("text", Duration::ZERO));
+        queue.push(2, deliver("other chat", Duration::ZERO));
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // The text waits for the photo before it; the other chat doesn't
+        assert_eq!(*delivered.lock().unwrap(), ["other chat", "slow photo", "text"]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Join/leave events from "new_chat_members" / "left_chat_member" service
+//! messages, the fallback for groups where the bot isn't admin and gets no
+//! chat_member updates.
+//!
+//! Where both arrive, the same join is reported twice; a short-lived seen-set
+//! drops the second copy. Each copy can say something different about how
+//! the member got in (`InviteSource`), so that part is kept from both.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use teloxide::types::{ChatMemberUpdated, Message, User};
+
+/// How long a join/leave is remembered for deduplication.
+const DEDUP_WINDOW: Duration = Duration::minutes(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemberEventKind {
+    Joined,
+    Left,
+}
+
+/// Members joining or leaving, from a service message. Empty for other messages.
+pub fn service_member_events(msg: &Message) -> Vec<(&User, MemberEventKind)> {
+    if let Some(users) = msg.new_chat_members() {
+        return users.iter().map(|u| (u, MemberEventKind::Joined)).collect();
+    }
+    if let Some(user) = msg.left_chat_member() {
+        return vec![(user, MemberEventKind::Left)];
+    }
+    Vec::new()
+}
+
+/// How a member got in, as far as one update says.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InviteSource {
+    pub invite_link: Option<String>,
+    /// Who added them, or made the (non-primary) link they used.
+    pub invited_by: Option<i64>,
+}
+
+impl InviteSource {
+    pub fn is_empty(&self) -> bool {
+        self.invite_link.is_none() && self.invited_by.is_none()
+    }
+}
+
+/// From a chat_member join: the link used, and who brought the member in.
+/// That's whoever performed the join if it wasn't the member themselves or
+/// an admin approving their join request, else the link's creator. Primary
+/// links are the chat's public-facing one, so their creator says nothing.
+pub fn update_invite_source(update: &ChatMemberUpdated) -> InviteSource {
+    let link = update.invite_link.as_ref();
+    let invited_by = if update.from.id != update.new_chat_member.user.id && !update.via_join_request {
+        Some(update.from.id)
+    } else {
+        link.filter(|l| !l.is_primary).map(|l| l.creator.id)
+    };
+    InviteSource {
+        invite_link: link.map(|l| l.invite_link.clone()),
+        invited_by: invited_by.map(|id| id.0 as i64),
+    }
+}
+
+/// From a "new_chat_members" service message: who added `member`, unless
+/// they joined by themselves. These never say which link was used.
+pub fn service_invite_source(msg: &Message, member: &User) -> InviteSource {
+    InviteSource {
+        invite_link: None,
+        invited_by: msg.from.as_ref().filter(|from| from.id != member.id).map(|from| from.id.0 as i64),
+    }
+}
+
+/// Recently seen joins/leaves per chat and user.
+pub struct MemberEventDedup {
+    seen: HashMap<(i64, i64, MemberEventKind), DateTime<Utc>>,
+}
+
+impl MemberEventDedup {
+    pub fn new() -> Self {
+        Self { seen: HashMap::new() }
+    }
+
+    /// Record an event; false if the same event was seen within the window.
+    pub fn first_seen(&mut self, chat_id: i64, user_id: i64, kind: MemberEventKind, now: DateTime<Utc>) -> bool {
+        self.seen.retain(|_, at| now - *at < DEDUP_WINDOW);
+        self.seen.insert((chat_id, user_id, kind), now).is_none()
+    }
+}
+
+impl Default for MemberEventDedup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t0() -> DateTime<Utc> {
+        "2024-01-15T10:00:00Z".parse().unwrap()
+    }
+
+    fn service_message(extra: &str) -> Message {
+        let json = format!(
+            r#"{{
+                "message_id": 10,
+                "date": 1705312800,
+                "chat": {{ "id": -100123, "title": "Test", "type": "supergroup" }},
+                "from": {{ "id": 1, "is_bot": false, "first_name": "Alice" }},
+                {}
+            }}"#,
+            extra
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_multi_user_join_routed_per_user() {
+        let msg = service_message(
+            r#""new_chat_members": [
+                { "id": 2, "is_bot": false, "first_name": "Bob", "username": "bob" },
+                { "id": 3, "is_bot": false, "first_name": "Carol" }
+            ]"#,
+        );
+        let events = service_member_events(&msg);
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].0.id.0, events[0].1), (2, MemberEventKind::Joined));
+        assert_eq!(events[0].0.username.as_deref(), Some("bob"));
+        assert_eq!((events[1].0.id.0, events[1].1), (3, MemberEventKind::Joined));
+    }
+
+    #[test]
+    fn test_left_member_and_regular_messages() {
+        let msg = service_message(r#""left_chat_member": { "id": 2, "is_bot": false, "first_name": "Bob" }"#);
+        let events = service_member_events(&msg);
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].0.id.0, events[0].1), (2, MemberEventKind::Left));
+
+        assert!(service_member_events(&service_message(r#""text": "hello""#)).is_empty());
+    }
+
+    fn join_update(from_id: u64, extra: &str) -> ChatMemberUpdated {
+        let json = format!(
+            r#"{{
+                "chat": {{ "id": -100123, "title": "Test", "type": "supergroup" }},
+                "from": {{ "id": {from_id}, "is_bot": false, "first_name": "From" }},
+                "date": 1705312800,
+                "old_chat_member": {{ "user": {{ "id": 2, "is_bot": false, "first_name": "Bob" }}, "status": "left" }},
+                "new_chat_member": {{ "user": {{ "id": 2, "is_bot": false, "first_name": "Bob" }}, "status": "member" }}
+                {extra}
+            }}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn link(creator_id: u64, is_primary: bool) -> String {
+        format!(
+            r#", "invite_link": {{
+                "invite_link": "https://t.me/+AbCd...",
+                "creator": {{ "id": {creator_id}, "is_bot": false, "first_name": "Creator" }},
+                "creates_join_request": false,
+                "is_primary": {is_primary},
+                "is_revoked": false
+            }}"#
+        )
+    }
+
+    #[test]
+    fn test_update_invite_source() {
+        // Joined through someone's named link
+        let source = update_invite_source(&join_update(2, &link(7, false)));
+        assert_eq!(source, InviteSource { invite_link: Some("https://t.me/+AbCd...".to_string()), invited_by: Some(7) });
+
+        // Through the chat's primary link: nobody in particular invited them
+        let source = update_invite_source(&join_update(2, &link(1, true)));
+        assert_eq!(source, InviteSource { invite_link: Some("https://t.me/+AbCd...".to_string()), invited_by: None });
+
+        // Added by a member
+        assert_eq!(update_invite_source(&join_update(5, "")), InviteSource { invite_link: None, invited_by: Some(5) });
+
+        // Join request approved by an admin: the admin didn't invite them, the link's creator did
+        let source = update_invite_source(&join_update(9, &format!(r#"{}, "via_join_request": true"#, link(7, false))));
+        assert_eq!(source.invited_by, Some(7));
+        let source = update_invite_source(&join_update(9, r#", "via_join_request": true"#));
+        assert!(source.is_empty());
+
+        // Joined a public group by themselves
+        assert!(update_invite_source(&join_update(2, "")).is_empty());
+    }
+
+    #[test]
+    fn test_service_invite_source() {
+        let msg = service_message(
+            r#""new_chat_members": [
+                { "id": 1, "is_bot": false, "first_name": "Alice" },
+                { "id": 3, "is_bot": false, "first_name": "Carol" }
+            ]"#,
+        );
+        let events = service_member_events(&msg);
+        // Alice joined herself and added Carol
+        assert!(service_invite_source(&msg, events[0].0).is_empty());
+        assert_eq!(service_invite_source(&msg, events[1].0), InviteSource { invite_link: None, invited_by: Some(1) });
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut dedup = MemberEventDedup::new();
+        let now = t0();
+
+        assert!(dedup.first_seen(-100, 2, MemberEventKind::Joined, now));
+        // Same join via the other update type
+        assert!(!dedup.first_seen(-100, 2, MemberEventKind::Joined, now + Duration::seconds(1)));
+        // Different kind, user or chat are separate events
+        assert!(dedup.first_seen(-100, 2, MemberEventKind::Left, now));
+        assert!(dedup.first_seen(-100, 3, MemberEventKind::Joined, now));
+        assert!(dedup.first_seen(-200, 2, MemberEventKind::Joined, now));
+
+        // Rejoining after the window counts again
+        assert!(dedup.first_seen(-100, 2, MemberEventKind::Joined, now + Duration::minutes(6)));
+    }
+}
@@ -14,6 +14,11 @@ pub fn prefilter(text: &str, config: &Config) -> PrefilterResult {
         return PrefilterResult::ObviousSpam;
     }
 
+    // Links to blocked domains
+    if config.links.blocked_domain(text).is_some() {
+        return PrefilterResult::ObviousSpam;
+    }
+
     // Check spam patterns first
     for pattern in &config.spam_patterns {
         if pattern.is_match(text) {
@@ -36,6 +41,16 @@ pub fn prefilter(text: &str, config: &Config) -> PrefilterResult {
     PrefilterResult::Ambiguous
 }
 
+/// With `via_bot_ambiguous`, a message sent through an inline bot goes to the
+/// classifier even when it looked safe: inline bots are a common spam vector.
+pub fn escalate_via_bot(result: PrefilterResult, via_bot: bool, config: &Config) -> PrefilterResult {
+    if via_bot && config.via_bot_ambiguous && result == PrefilterResult::ObviousSafe {
+        PrefilterResult::Ambiguous
+    } else {
+        result
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,12 +76,65 @@ mod tests {
             data_dir: std::path::PathBuf::from("."),
             whisper_model_path: None,
             tts_endpoint: None,
-            personality: None,
+            voice_include_transcript: crate::chatbot::tts::VoiceTranscript::StoreOnly,
+            compact_prompt: false,
+            personalities: Default::default(),
             scan_interval_minutes: 0,
             scan_times: vec![],
             scan_timezone: chrono_tz::UTC,
+            quiet_hours: None,
+            ack_reactions: None,
+            metrics_listen: None,
             peer_bots: vec![],
+            process_bot_messages: false,
+            wiki_default_lang: "en".to_string(),
+            translate_max_chars: 4000,
+            compaction_summary_tokens: 4000,
+            compaction_restore_tokens: 10000,
+            compaction_cooldown: std::time::Duration::from_secs(600),
+            gemini_text_model: "gemini-2.5-flash".to_string(),
+            response_language: crate::chatbot::language::ResponseLanguage::Auto,
+            output_guard: Default::default(),
+            debounce_ms_dm: 300,
+            debounce_ms_group: 3000,
+            debounce_max_ms_group: Some(8000),
+            max_pending_messages: 100,
+            maintenance_reply: String::new(),
+            ocr_screenshots: false,
+            ocr_visual_keywords: vec![],
+            max_image_bytes: 5 * 1024 * 1024,
+            startup_notify: crate::startup::StartupNotify::Always,
+            dm_policy: crate::dm_policy::DmPolicy::Deny,
+            dm_intro_message: crate::dm_policy::DEFAULT_INTRO.to_string(),
+            dm_forward_limit: 3,
+            dm_denied_expiry: chrono::Duration::days(7),
+            error_digest_time: None,
+            user_digest_time: None,
+            user_digest_top: 5,
+            transparency_time: None,
+            transparency_chat_id: None,
+            transparency_anonymize: true,
+            tool_timeouts: std::collections::HashMap::new(),
+            tool_result_limits: std::collections::HashMap::new(),
+            backup_cron: None,
+            backup_keep: 4,
+            protect_admins: true,
+            atomic_batches: false,
+            transcript_full: false,
+            transcript_max_bytes: 100 * 1024 * 1024,
+            backfill_max_age: chrono::Duration::minutes(30),
+            media_concurrency: 4,
+            media_timeout: std::time::Duration::from_secs(60),
+            raid_threshold: 5,
+            raid_duration: chrono::Duration::minutes(30),
+            via_bot_ambiguous: true,
+            links: crate::links::LinkPolicy {
+                blocked: vec!["scam.example".to_string()],
+                ..Default::default()
+            },
             primary_chat_id: 0,
+            chat_migrations: std::sync::Arc::default(),
+            claude_program: std::path::PathBuf::from("claude"),
         }
     }
 
@@ -83,6 +151,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_blocked_domain_is_spam() {
+        let config = test_config();
+        // Short enough to be ObviousSafe otherwise
+        assert_eq!(prefilter("scam.example/win", &config), PrefilterResult::ObviousSpam);
+        assert_eq!(prefilter("see https://free.scam.example", &config), PrefilterResult::ObviousSpam);
+        assert_eq!(prefilter("see https://example.com", &config), PrefilterResult::ObviousSafe);
+    }
+
     #[test]
     fn test_magic_string_injection() {
         let config = test_config();
@@ -107,6 +184,20 @@ mod tests {
         assert_eq!(prefilter("ok", &config), PrefilterResult::ObviousSafe);
     }
 
+    #[test]
+    fn test_via_bot_escalation() {
+        let mut config = test_config();
+        let safe = prefilter("ok", &config);
+        assert_eq!(escalate_via_bot(safe, true, &config), PrefilterResult::Ambiguous);
+        assert_eq!(escalate_via_bot(safe, false, &config), PrefilterResult::ObviousSafe);
+        // Spam stays spam
+        let spam = prefilter("Join us at t.me/scamgroup", &config);
+        assert_eq!(escalate_via_bot(spam, true, &config), PrefilterResult::ObviousSpam);
+
+        config.via_bot_ambiguous = false;
+        assert_eq!(escalate_via_bot(safe, true, &config), PrefilterResult::ObviousSafe);
+    }
+
     #[test]
     fn test_ambiguous() {
         let config = test_config();
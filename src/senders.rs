@@ -0,0 +1,181 @@
+//! Who sent a group message.
+//!
+//! Not every group message comes from a person. Anonymous admins post as the
+//! group itself, and users of groups with a linked channel can post on behalf
+//! of a channel. Telegram marks both with `sender_chat` and puts a
+//! placeholder account in `from`. These senders are stored under a reserved
+//! user ID (the group's or the channel's ID) so they're never mistaken for
+//! the placeholder or for each other.
+
+use std::collections::HashSet;
+
+use teloxide::types::{ChatId, Message, User, UserId};
+
+/// Name shown for messages from anonymous admins.
+pub const ANONYMOUS_ADMIN: &str = "anonymous admin";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sender<'a> {
+    User(&'a User),
+    /// An admin posting as the group.
+    AnonymousAdmin { chat_id: i64 },
+    /// Posted on behalf of a channel (including the linked channel's auto-forwards).
+    Channel { id: i64, title: &'a str },
+}
+
+impl<'a> Sender<'a> {
+    /// The ID messages are stored under: the user's, or the group's or
+    /// channel's (negative) ID.
+    pub fn user_id(&self) -> i64 {
+        match *self {
+            Self::User(user) => user.id.0 as i64,
+            Self::AnonymousAdmin { chat_id } => chat_id,
+            Self::Channel { id, .. } => id,
+        }
+    }
+
+    /// @username or first name, the channel title, or "anonymous admin".
+    pub fn name(&self) -> &'a str {
+        match *self {
+            Self::User(user) => user.username.as_deref().unwrap_or(&user.first_name),
+            Self::AnonymousAdmin { .. } => ANONYMOUS_ADMIN,
+            Self::Channel { title, .. } => title,
+        }
+    }
+
+    /// A bot account. The placeholder accounts of anonymous admins and
+    /// channels don't count.
+    pub fn is_bot(&self) -> bool {
+        matches!(*self, Self::User(user) if user.is_bot)
+    }
+
+    /// A bot that isn't one of the configured peers (`peer_bots` is
+    /// lowercase, without @).
+    pub fn is_foreign_bot(&self, peer_bots: &[String]) -> bool {
+        match *self {
+            Self::User(user) if user.is_bot => !user
+                .username
+                .as_deref()
+                .is_some_and(|name| peer_bots.iter().any(|peer| peer.eq_ignore_ascii_case(name))),
+            _ => false,
+        }
+    }
+
+    /// Owners, anonymous admins and trusted channels skip the spam filter.
+    pub fn bypasses_spam_filter(&self, owner_ids: &[UserId], trusted_channels: &HashSet<ChatId>) -> bool {
+        match *self {
+            Self::User(user) => owner_ids.contains(&user.id),
+            Self::AnonymousAdmin { .. } => true,
+            Self::Channel { id, .. } => trusted_channels.contains(&ChatId(id)),
+        }
+    }
+}
+
+/// The sender of a message, or None if it has neither a user nor a sender chat.
+pub fn sender(msg: &Message) -> Option<Sender<'_>> {
+    match msg.sender_chat {
+        Some(ref chat) if chat.id == msg.chat.id => Some(Sender::AnonymousAdmin { chat_id: chat.id.0 }),
+        Some(ref chat) if chat.is_channel() => {
+            Some(Sender::Channel { id: chat.id.0, title: chat.title().unwrap_or("channel") })
+        }
+        _ => msg.from.as_ref().map(Sender::User),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP: i64 = -1001234567890;
+
+    fn group_message(sender: &str) -> Message {
+        let json = format!(
+            r#"{{
+                "message_id": 10,
+                "date": 1705312800,
+                "chat": {{ "id": {}, "title": "Rust Club", "type": "supergroup" }},
+                {},
+                "text": "please pin the rules"
+            }}"#,
+            GROUP, sender
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_anonymous_admin() {
+        let msg = group_message(&format!(
+            r#""from": {{ "id": 1087968824, "is_bot": true, "first_name": "Group", "username": "GroupAnonymousBot" }},
+               "sender_chat": {{ "id": {}, "title": "Rust Club", "type": "supergroup" }}"#,
+            GROUP
+        ));
+        let sender = sender(&msg).unwrap();
+        assert_eq!(sender, Sender::AnonymousAdmin { chat_id: GROUP });
+        assert_eq!(sender.user_id(), GROUP);
+        assert_eq!(sender.name(), "anonymous admin");
+
+        // Without the placeholder user too
+        let msg = group_message(&format!(
+            r#""sender_chat": {{ "id": {}, "title": "Rust Club", "type": "supergroup" }}"#,
+            GROUP
+        ));
+        assert_eq!(super::sender(&msg), Some(Sender::AnonymousAdmin { chat_id: GROUP }));
+    }
+
+    #[test]
+    fn test_channel_sender() {
+        let msg = group_message(
+            r#""from": { "id": 136817688, "is_bot": true, "first_name": "Channel", "username": "Channel_Bot" },
+               "sender_chat": { "id": -1009876543210, "title": "Rust News", "type": "channel" }"#,
+        );
+        let sender = sender(&msg).unwrap();
+        assert_eq!(sender, Sender::Channel { id: -1009876543210, title: "Rust News" });
+        assert_eq!(sender.user_id(), -1009876543210);
+        assert_eq!(sender.name(), "Rust News");
+    }
+
+    #[test]
+    fn test_plain_user() {
+        let msg = group_message(r#""from": { "id": 42, "is_bot": false, "first_name": "Alice", "username": "alice" }"#);
+        let sender = sender(&msg).unwrap();
+        assert_eq!(sender.user_id(), 42);
+        assert_eq!(sender.name(), "alice");
+
+        assert_eq!(super::sender(&group_message(r#""author_signature": "x""#)), None);
+    }
+
+    #[test]
+    fn test_spam_filter_bypass() {
+        let owners = [UserId(1)];
+        let trusted: HashSet<ChatId> = [ChatId(-1009876543210)].into();
+
+        assert!(Sender::AnonymousAdmin { chat_id: GROUP }.bypasses_spam_filter(&owners, &trusted));
+        assert!(Sender::Channel { id: -1009876543210, title: "Rust News" }.bypasses_spam_filter(&owners, &trusted));
+        // Anyone can post as their own channel
+        assert!(!Sender::Channel { id: -100555, title: "Crypto Gains" }.bypasses_spam_filter(&owners, &trusted));
+
+        let owner = group_message(r#""from": { "id": 1, "is_bot": false, "first_name": "Owner" }"#);
+        assert!(sender(&owner).unwrap().bypasses_spam_filter(&owners, &trusted));
+        let user = group_message(r#""from": { "id": 42, "is_bot": false, "first_name": "Alice" }"#);
+        assert!(!sender(&user).unwrap().bypasses_spam_filter(&owners, &trusted));
+    }
+
+    #[test]
+    fn test_foreign_bots() {
+        let peers = vec!["helperbot".to_string()];
+        let peer = group_message(r#""from": { "id": 7, "is_bot": true, "first_name": "Helper", "username": "HelperBot" }"#);
+        let peer = sender(&peer).unwrap();
+        assert!(peer.is_bot());
+        assert!(!peer.is_foreign_bot(&peers));
+        // Skipped unless configured as a peer
+        assert!(peer.is_foreign_bot(&[]));
+
+        let nameless = group_message(r#""from": { "id": 8, "is_bot": true, "first_name": "Relay" }"#);
+        assert!(sender(&nameless).unwrap().is_foreign_bot(&peers));
+
+        let user = group_message(r#""from": { "id": 42, "is_bot": false, "first_name": "Alice", "username": "helperbot" }"#);
+        assert!(!sender(&user).unwrap().is_foreign_bot(&[]));
+        // Placeholder bot accounts of anonymous admins aren't bots here
+        assert!(!Sender::AnonymousAdmin { chat_id: GROUP }.is_bot());
+    }
+}
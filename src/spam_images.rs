@@ -0,0 +1,61 @@
+//! Images reposted by spam raids.
+//!
+//! Raids post the same innocuous image again and again, with the spam in
+//! the caption. When a message with a photo is judged spam, a hash of the
+//! photo's smallest size (Telegram's thumbnail, cheap to fetch) is kept in
+//! the `spam_image_hashes` table for `TTL`. The same image from anyone who
+//! doesn't bypass the spam filter is then spam without asking the
+//! classifier, caption or not.
+//!
+//! The hash is exact, not perceptual: it catches reposts of the same file,
+//! which Telegram thumbnails identically, not edited copies.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use chrono::Duration;
+use teloxide::types::PhotoSize;
+
+/// How long an image stays known as spam after it was last seen with spam.
+pub const TTL: Duration = Duration::hours(24);
+
+/// The size to hash: the smallest one.
+pub fn thumbnail(sizes: &[PhotoSize]) -> Option<&PhotoSize> {
+    sizes.iter().min_by_key(|size| size.width * size.height)
+}
+
+/// Hex hash of image bytes.
+pub fn hash(bytes: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::{FileId, FileMeta, FileUniqueId};
+
+    fn size(id: &str, width: u32, height: u32) -> PhotoSize {
+        PhotoSize {
+            file: FileMeta { id: FileId(id.to_string()), unique_id: FileUniqueId(id.to_string()), size: width * height },
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_thumbnail_is_smallest() {
+        let sizes = [size("medium", 320, 240), size("small", 90, 67), size("large", 1280, 960)];
+        assert_eq!(thumbnail(&sizes).unwrap().file.id.0, "small");
+        assert!(thumbnail(&[]).is_none());
+    }
+
+    #[test]
+    fn test_hash_is_stable_per_content() {
+        let image = b"\xff\xd8\xff\xe0 same jpeg bytes";
+        assert_eq!(hash(image), hash(image));
+        assert_eq!(hash(image).len(), 16);
+        assert_ne!(hash(image), hash(b"\xff\xd8\xff\xe0 other jpeg bytes"));
+    }
+}
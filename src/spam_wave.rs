@@ -0,0 +1,235 @@
+//! Group-level spam wave ("raid") detection.
+//!
+//! Tracks recent messages from new accounts in a sliding window. When enough
+//! distinct new users post near-duplicate text, raid mode is entered for a
+//! while: ambiguous messages from new accounts are then treated as spam
+//! without asking the classifier.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use tracing::info;
+
+/// How far back messages are considered part of the same wave.
+const WAVE_WINDOW: Duration = Duration::minutes(10);
+
+/// Accounts that joined more recently than this count as new.
+pub const NEW_ACCOUNT_AGE: Duration = Duration::hours(24);
+
+/// Sample texts included in the owner alert.
+const ALERT_SAMPLES: usize = 3;
+
+static URL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(https?://\S+|www\.\S+|t\.me/\S+|@\w+)").expect("valid url regex")
+});
+
+/// Normalize text for duplicate detection: lowercase, drop URLs/@mentions,
+/// digits and punctuation, collapse whitespace. Spammers vary these per post.
+pub fn normalize(text: &str) -> String {
+    let lower = text.to_lowercase();
+    let without_urls = URL_RE.replace_all(&lower, " ");
+    without_urls
+        .chars()
+        .map(|c| if c.is_alphabetic() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Hash of the normalized text; equal hashes mean near-duplicate posts.
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    normalize(text).hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Post {
+    at: DateTime<Utc>,
+    user_id: u64,
+    hash: u64,
+    text: String,
+}
+
+/// Raised once when raid mode is entered.
+#[derive(Debug)]
+pub struct RaidAlert {
+    pub users: usize,
+    pub samples: Vec<String>,
+}
+
+impl RaidAlert {
+    /// Message for the owner.
+    pub fn message(&self, minutes: i64) -> String {
+        let samples: Vec<String> = self.samples.iter()
+            .map(|s| format!("• {}", s.chars().take(100).collect::<String>()))
+            .collect();
+        format!(
+            "🚨 Spam wave: {} new accounts posted near-identical messages. \
+             Raid mode on for {} min (ambiguous messages from new accounts are deleted without classification).\n\n{}",
+            self.users, minutes, samples.join("\n")
+        )
+    }
+}
+
+/// Sliding-window wave detector.
+pub struct WaveDetector {
+    /// Distinct new users posting the same text needed to trigger raid mode. 0 = disabled.
+    threshold: usize,
+    raid_duration: Duration,
+    posts: VecDeque<Post>,
+    raid_until: Option<DateTime<Utc>>,
+}
+
+impl WaveDetector {
+    pub fn new(threshold: usize, raid_duration: Duration) -> Self {
+        Self { threshold, raid_duration, posts: VecDeque::new(), raid_until: None }
+    }
+
+    /// Record a message. Only posts from new accounts count towards a wave.
+    /// Returns an alert when this post triggers raid mode (not while already in it).
+    pub fn observe(&mut self, user_id: u64, text: &str, is_new_account: bool, now: DateTime<Utc>) -> Option<RaidAlert> {
+        self.prune(now);
+        if self.threshold == 0 || !is_new_account {
+            return None;
+        }
+
+        let hash = text_hash(text);
+        self.posts.push_back(Post { at: now, user_id, hash, text: text.to_string() });
+
+        let group: Vec<&Post> = self.posts.iter().filter(|p| p.hash == hash).collect();
+        let users = group.iter().map(|p| p.user_id).collect::<HashSet<_>>().len();
+        if users < self.threshold {
+            return None;
+        }
+        let samples: Vec<String> = group.iter().take(ALERT_SAMPLES).map(|p| p.text.clone()).collect();
+
+        let was_in_raid = self.in_raid(now);
+        self.raid_until = Some(now + self.raid_duration);
+        if was_in_raid {
+            return None;
+        }
+
+        info!("🚨 Raid mode entered: {} new users posting near-duplicate text", users);
+        Some(RaidAlert { users, samples })
+    }
+
+    /// Whether raid mode is active; exits automatically once it expires.
+    pub fn in_raid(&mut self, now: DateTime<Utc>) -> bool {
+        match self.raid_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                info!("Raid mode ended");
+                self.raid_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        while self.posts.front().is_some_and(|p| now - p.at > WAVE_WINDOW) {
+            self.posts.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t0() -> DateTime<Utc> {
+        "2024-01-15T10:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("EARN $500 today!! https://scam.example/x?ref=1 DM @promo_bot"),
+            "earn today dm"
+        );
+        assert_eq!(normalize("Join t.me/abc123   now"), "join now");
+        assert_eq!(normalize("Привет, МИР 2024"), "привет мир");
+    }
+
+    #[test]
+    fn test_near_duplicates_share_hash() {
+        assert_eq!(
+            text_hash("Earn 500$ daily, write me https://a.example/1"),
+            text_hash("earn 900$ DAILY write me https://b.example/2")
+        );
+        assert_ne!(text_hash("earn money daily"), text_hash("learn rust daily"));
+    }
+
+    #[test]
+    fn test_enters_raid_at_threshold_distinct_users() {
+        let mut d = WaveDetector::new(3, Duration::minutes(30));
+        let now = t0();
+
+        assert!(d.observe(1, "Earn 500$ daily", true, now).is_none());
+        // Same user again doesn't count twice
+        assert!(d.observe(1, "Earn 600$ daily", true, now).is_none());
+        assert!(d.observe(2, "earn 700$ daily", true, now).is_none());
+        assert!(!d.in_raid(now));
+
+        let alert = d.observe(3, "EARN 800$ DAILY", true, now + Duration::minutes(1)).unwrap();
+        assert_eq!(alert.users, 3);
+        assert_eq!(alert.samples.len(), 3);
+        assert!(d.in_raid(now + Duration::minutes(1)));
+
+        // Further duplicates don't alert again
+        assert!(d.observe(4, "earn 900$ daily", true, now + Duration::minutes(2)).is_none());
+    }
+
+    #[test]
+    fn test_ignores_established_accounts_and_distinct_texts() {
+        let mut d = WaveDetector::new(2, Duration::minutes(30));
+        let now = t0();
+
+        assert!(d.observe(1, "same text here", false, now).is_none());
+        assert!(d.observe(2, "same text here", false, now).is_none());
+        assert!(d.observe(3, "first message", true, now).is_none());
+        assert!(d.observe(4, "another topic", true, now).is_none());
+        assert!(!d.in_raid(now));
+    }
+
+    #[test]
+    fn test_window_expiry() {
+        let mut d = WaveDetector::new(2, Duration::minutes(30));
+        let now = t0();
+
+        assert!(d.observe(1, "buy followers", true, now).is_none());
+        // Outside the 10-minute window: first post has expired
+        assert!(d.observe(2, "buy followers", true, now + Duration::minutes(11)).is_none());
+        assert!(d.observe(3, "buy followers", true, now + Duration::minutes(12)).is_some());
+    }
+
+    #[test]
+    fn test_raid_exits_after_duration() {
+        let mut d = WaveDetector::new(2, Duration::minutes(30));
+        let now = t0();
+
+        d.observe(1, "buy followers", true, now);
+        assert!(d.observe(2, "buy followers", true, now).is_some());
+        assert!(d.in_raid(now + Duration::minutes(29)));
+        assert!(!d.in_raid(now + Duration::minutes(30)));
+
+        // A new wave after exit alerts again
+        let later = now + Duration::hours(1);
+        d.observe(5, "cheap followers", true, later);
+        assert!(d.observe(6, "cheap followers", true, later).is_some());
+    }
+
+    #[test]
+    fn test_disabled_with_zero_threshold() {
+        let mut d = WaveDetector::new(0, Duration::minutes(30));
+        for user in 0..10 {
+            assert!(d.observe(user, "spam spam", true, t0()).is_none());
+        }
+        assert!(!d.in_raid(t0()));
+    }
+}
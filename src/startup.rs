@@ -0,0 +1,248 @@
+//! Owner notifications about restarts.
+//!
+//! `startup_notify` decides when the owner hears about a restart: every time,
+//! only when something went wrong, or batched into a daily summary. "Wrong"
+//! means the Claude session didn't resume, the Whisper model is missing, or
+//! the previous run didn't shut down cleanly. That last one is detected with a
+//! flag file written at startup and removed on clean shutdown.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Local time after which the daily restart summary is sent.
+pub const SUMMARY_TIME: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(t) => t,
+    None => panic!("invalid summary time"),
+};
+
+/// When to tell the owner about a restart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StartupNotify {
+    /// Every restart.
+    Always,
+    /// Only restarts with problems.
+    OnError,
+    /// Problems right away; all restarts in one summary after 09:00 local.
+    DailySummary,
+    Never,
+}
+
+impl StartupNotify {
+    /// Parse a `startup_notify` config value.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "on_error" => Ok(Self::OnError),
+            "daily_summary" => Ok(Self::DailySummary),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "invalid value '{}' (expected \"always\", \"on_error\", \"daily_summary\" or \"never\")",
+                other
+            )),
+        }
+    }
+}
+
+/// The message to send right away for a restart with these problems, if any.
+pub fn immediate_message(mode: StartupNotify, issues: &[String]) -> Option<String> {
+    let notify = match mode {
+        StartupNotify::Always => true,
+        StartupNotify::OnError | StartupNotify::DailySummary => !issues.is_empty(),
+        StartupNotify::Never => false,
+    };
+    notify.then(|| restart_message(issues))
+}
+
+fn restart_message(issues: &[String]) -> String {
+    if issues.is_empty() {
+        return "hey, just restarted".to_string();
+    }
+    let list: Vec<String> = issues.iter().map(|i| format!("- {}", i)).collect();
+    format!("hey, just restarted, but:\n{}", list.join("\n"))
+}
+
+/// Flag file that exists while the bot runs. Finding it at startup means
+/// the previous run crashed or was killed.
+pub struct RunningFlag {
+    path: PathBuf,
+}
+
+impl RunningFlag {
+    /// Create the flag in `data_dir`. Returns the flag and whether the
+    /// previous run left one behind.
+    pub fn acquire(data_dir: &Path) -> (Self, bool) {
+        let path = data_dir.join("running");
+        let unclean = path.exists();
+        let started = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        if let Err(e) = std::fs::write(&path, started) {
+            warn!("Failed to write running flag {:?}: {}", path, e);
+        }
+        (Self { path }, unclean)
+    }
+
+    /// Remove the flag on clean shutdown.
+    pub fn release(self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("Failed to remove running flag {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// A restart waiting for the daily summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestartEvent {
+    /// Local time, "YYYY-MM-DD HH:MM".
+    pub at: String,
+    pub issues: Vec<String>,
+}
+
+/// Restarts not yet summarized, persisted in the data dir.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SummaryStore {
+    pending: Vec<RestartEvent>,
+    /// Local date of the last summary, "YYYY-MM-DD".
+    last_summary: Option<String>,
+}
+
+impl SummaryStore {
+    /// Load from `path`, starting empty if missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+                warn!("Ignoring invalid restart summary file {:?}: {}", path, e);
+                Self::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                warn!("Failed to read restart summary file {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize restarts: {e}"))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {:?}: {e}", path))
+    }
+
+    pub fn record(&mut self, at: NaiveDateTime, issues: Vec<String>) {
+        self.pending.push(RestartEvent { at: at.format("%Y-%m-%d %H:%M").to_string(), issues });
+    }
+
+    /// Take the pending restarts if today's summary is due at `now`: it's
+    /// past `SUMMARY_TIME` and no summary was sent today.
+    pub fn take_due(&mut self, now: NaiveDateTime) -> Option<Vec<RestartEvent>> {
+        let today = now.format("%Y-%m-%d").to_string();
+        if now.time() < SUMMARY_TIME || self.last_summary.as_ref() == Some(&today) || self.pending.is_empty() {
+            return None;
+        }
+        self.last_summary = Some(today);
+        Some(std::mem::take(&mut self.pending))
+    }
+}
+
+/// The next time a summary could be due after `now`.
+pub fn next_summary_check(now: NaiveDateTime) -> NaiveDateTime {
+    let today = now.date().and_time(SUMMARY_TIME);
+    if now < today {
+        today
+    } else {
+        today + chrono::Duration::days(1)
+    }
+}
+
+pub fn summary_message(events: &[RestartEvent]) -> String {
+    let lines: Vec<String> = events.iter()
+        .map(|e| match e.issues.is_empty() {
+            true => format!("- {}", e.at),
+            false => format!("- {}: {}", e.at, e.issues.join("; ")),
+        })
+        .collect();
+    format!("Restarts since the last summary ({}):\n{}", events.len(), lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_mode_decision() {
+        let none: Vec<String> = vec![];
+        let issues = vec!["the Claude session failed to resume".to_string()];
+
+        assert_eq!(immediate_message(StartupNotify::Always, &none).as_deref(), Some("hey, just restarted"));
+        assert_eq!(
+            immediate_message(StartupNotify::Always, &issues).as_deref(),
+            Some("hey, just restarted, but:\n- the Claude session failed to resume")
+        );
+        assert_eq!(immediate_message(StartupNotify::OnError, &none), None);
+        assert!(immediate_message(StartupNotify::OnError, &issues).is_some());
+        assert_eq!(immediate_message(StartupNotify::DailySummary, &none), None);
+        assert!(immediate_message(StartupNotify::DailySummary, &issues).is_some());
+        assert_eq!(immediate_message(StartupNotify::Never, &issues), None);
+
+        assert_eq!(StartupNotify::parse(" On_Error ").unwrap(), StartupNotify::OnError);
+        assert!(StartupNotify::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn test_running_flag_lifecycle() {
+        let dir = tempfile::TempDir::new().unwrap();
+
+        // First start: clean
+        let (flag, unclean) = RunningFlag::acquire(dir.path());
+        assert!(!unclean);
+        assert!(dir.path().join("running").exists());
+
+        // Clean shutdown removes it
+        flag.release();
+        assert!(!dir.path().join("running").exists());
+        let (flag, unclean) = RunningFlag::acquire(dir.path());
+        assert!(!unclean);
+
+        // Crash: the flag is never released, so the next start sees it
+        drop(flag);
+        let (_flag, unclean) = RunningFlag::acquire(dir.path());
+        assert!(unclean);
+    }
+
+    #[test]
+    fn test_daily_summary_batching() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("restarts.json");
+
+        // Two restarts overnight are kept until 09:00
+        let mut store = SummaryStore::load(&path);
+        store.record(at("2024-01-15 02:10"), vec![]);
+        store.record(at("2024-01-15 02:12"), vec!["the previous shutdown was unclean".to_string()]);
+        assert_eq!(store.take_due(at("2024-01-15 08:59")), None);
+        store.save(&path).unwrap();
+
+        // Persisted across restarts, and summarized once per day
+        let mut store = SummaryStore::load(&path);
+        let events = store.take_due(at("2024-01-15 09:00")).unwrap();
+        assert_eq!(
+            summary_message(&events),
+            "Restarts since the last summary (2):\n- 2024-01-15 02:10\n- 2024-01-15 02:12: the previous shutdown was unclean"
+        );
+        store.record(at("2024-01-15 14:00"), vec![]);
+        assert_eq!(store.take_due(at("2024-01-15 18:00")), None);
+        assert_eq!(store.take_due(at("2024-01-16 09:30")).map(|e| e.len()), Some(1));
+        // Nothing pending: no empty summary
+        assert_eq!(store.take_due(at("2024-01-17 09:30")), None);
+    }
+
+    #[test]
+    fn test_next_summary_check() {
+        assert_eq!(next_summary_check(at("2024-01-15 03:00")), at("2024-01-15 09:00"));
+        assert_eq!(next_summary_check(at("2024-01-15 09:00")), at("2024-01-16 09:00"));
+        assert_eq!(next_summary_check(at("2024-01-15 22:00")), at("2024-01-16 09:00"));
+    }
+}
@@ -0,0 +1,426 @@
+//! A stand-in for the Telegram Bot API, for tests that go through
+//! teloxide's real HTTP path.
+//!
+//! `FakeBotApi::start` listens on a local port and `bot()` gives a `Bot`
+//! pointed at it. Every method call is recorded with its parameters.
+//! Answers can be scripted per method with `answer`/`answer_error` (each
+//! used once, in order); otherwise a default answer fits the methods the
+//! client uses: getMe, getUpdates (serving `push_update` fixtures),
+//! sendMessage (echoing the message), deleteMessage, banChatMember, getFile
+//! and file downloads (`add_file`, optionally made slow with `slow_download`).
+//!
+//! Plain HTTP/1.1 over a tokio listener, one request per connection, bodies
+//! by Content-Length. That is all reqwest needs here, without pulling in a
+//! web framework.
+//!
+//! `TestBot` runs the whole bot against it: the real dispatcher and
+//! handlers, with the Claude Code CLI replaced by `FakeClaude`, which
+//! answers from a script.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use claudima::bot::{self, BotState};
+use claudima::config::Config;
+use serde_json::{Value, json};
+use teloxide::Bot;
+use teloxide::prelude::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub const TOKEN: &str = "123456:TEST-TOKEN";
+
+/// The bot's own user ID, as getMe reports it.
+pub const BOT_USER_ID: i64 = 123456;
+
+/// ID of the first message the fake sends.
+pub const FIRST_MESSAGE_ID: i64 = 5000;
+
+/// A Bot API method call the fake received.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    /// Scripted response bodies by method, used first.
+    answers: HashMap<String, VecDeque<Value>>,
+    updates: Vec<Value>,
+    /// (file_path, contents) by file_id.
+    files: HashMap<String, (String, Vec<u8>)>,
+    /// How long downloading each slow file_path takes.
+    slow_files: HashMap<String, Duration>,
+    sent: i64,
+}
+
+pub struct FakeBotApi {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeBotApi {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind fake Bot API");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server_state.clone()));
+            }
+        });
+        Self { addr, state }
+    }
+
+    /// A bot talking to this server.
+    pub fn bot(&self) -> Bot {
+        let url = reqwest::Url::parse(&format!("http://{}/", self.addr)).unwrap();
+        Bot::new(TOKEN).set_api_url(url)
+    }
+
+    /// Answer the next call to `method` with `result`.
+    pub fn answer(&self, method: &str, result: Value) {
+        self.script(method, json!({ "ok": true, "result": result }));
+    }
+
+    /// Fail the next call to `method` the way Telegram does.
+    pub fn answer_error(&self, method: &str, code: u16, description: &str) {
+        self.script(method, json!({ "ok": false, "error_code": code, "description": description }));
+    }
+
+    fn script(&self, method: &str, body: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.answers.entry(method.to_string()).or_default().push_back(body);
+    }
+
+    /// Queue an update (fixture JSON) for getUpdates; its `update_id` is set
+    /// from the queue position.
+    pub fn push_update(&self, mut update: Value) {
+        let mut state = self.state.lock().unwrap();
+        update["update_id"] = json!(state.updates.len() + 1);
+        state.updates.push(update);
+    }
+
+    /// Serve `data` as file `file_id`, downloadable from `file_path`.
+    pub fn add_file(&self, file_id: &str, file_path: &str, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.files.insert(file_id.to_string(), (file_path.to_string(), data.to_vec()));
+    }
+
+    /// Make downloads of `file_path` take `delay`.
+    pub fn slow_download(&self, file_path: &str, delay: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.slow_files.insert(file_path.to_string(), delay);
+    }
+
+    /// Every call so far, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Calls of one method, oldest first.
+    pub fn calls_to(&self, method: &str) -> Vec<Call> {
+        self.calls().into_iter().filter(|c| c.method == method).collect()
+    }
+
+    /// The first call to `method` matching `found`, waiting up to `WAIT` for it.
+    pub async fn wait_for(&self, method: &str, found: impl Fn(&Call) -> bool) -> Call {
+        wait_until(|| self.calls_to(method).into_iter().find(&found), &format!("a matching {method} call")).await
+    }
+}
+
+/// How long a test waits for the bot to act.
+pub const WAIT: Duration = Duration::from_secs(10);
+
+/// Poll `check` until it returns something, failing after `WAIT`.
+async fn wait_until<T>(check: impl Fn() -> Option<T>, what: &str) -> T {
+    let deadline = tokio::time::Instant::now() + WAIT;
+    loop {
+        if let Some(found) = check() {
+            return found;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "no {what} within {WAIT:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// A stand-in for the Claude Code CLI. The first message (the system
+/// prompt) starts the session; every later one is appended to `received`
+/// and answered with the tool calls of the first line of `rules`
+/// ("trigger<TAB>tool calls") whose trigger it contains, or with `done`.
+const FAKE_CLAUDE: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+tab=$(printf '\t')
+started=""
+while IFS= read -r line; do
+  reply='[{"tool":"done"}]'
+  if [ -z "$started" ]; then
+    started=1
+    printf '%s\n' '{"type":"system","tools":["StructuredOutput","WebSearch"],"session_id":"fake-session"}'
+  else
+    printf '%s\n' "$line" >> "$dir/received"
+    if [ -f "$dir/rules" ]; then
+      while IFS="$tab" read -r trigger calls; do
+        case "$line" in *"$trigger"*) reply="$calls"; break ;; esac
+      done < "$dir/rules"
+    fi
+  fi
+  printf '%s\n' '{"type":"result","total_cost_usd":0,"structured_output":{"tool_calls":'"$reply"'},"session_id":"fake-session"}'
+done
+"#;
+
+/// The fake Claude Code CLI (see `FAKE_CLAUDE`), installed in a directory
+/// it keeps its script and what it received in.
+pub struct FakeClaude {
+    dir: PathBuf,
+}
+
+impl FakeClaude {
+    pub fn install(dir: &Path) -> Self {
+        use std::os::unix::fs::PermissionsExt;
+        let fake = Self { dir: dir.to_path_buf() };
+        std::fs::write(fake.program(), FAKE_CLAUDE).unwrap();
+        std::fs::set_permissions(fake.program(), std::fs::Permissions::from_mode(0o755)).unwrap();
+        fake
+    }
+
+    pub fn program(&self) -> PathBuf {
+        self.dir.join("claude")
+    }
+
+    /// Answer messages containing `trigger` with `tool_calls`.
+    pub fn reply(&self, trigger: &str, tool_calls: Value) {
+        use std::io::Write;
+        let mut rules = std::fs::OpenOptions::new().create(true).append(true).open(self.dir.join("rules")).unwrap();
+        writeln!(rules, "{trigger}\t{tool_calls}").unwrap();
+    }
+
+    /// The text of every message after the system prompt, oldest first.
+    pub fn received(&self) -> Vec<String> {
+        let lines = std::fs::read_to_string(self.dir.join("received")).unwrap_or_default();
+        lines
+            .lines()
+            .map(|line| {
+                let input: Value = serde_json::from_str(line).unwrap();
+                match &input["message"]["content"] {
+                    Value::String(text) => text.clone(),
+                    content => content.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// The first message containing `text`, waiting up to `WAIT` for it.
+    pub async fn wait_for(&self, text: &str) -> String {
+        wait_until(|| self.received().into_iter().find(|m| m.contains(text)), &format!("message to Claude containing {text:?}")).await
+    }
+}
+
+/// The group the test bot works in.
+pub const GROUP_ID: i64 = -100123;
+
+/// The bot's owner.
+pub const OWNER_ID: i64 = 1;
+
+/// The bot, dispatcher and all, polling a `FakeBotApi`, with Claude
+/// replaced by a `FakeClaude`.
+pub struct TestBot {
+    pub api: FakeBotApi,
+    pub claude: FakeClaude,
+    _dir: tempfile::TempDir,
+}
+
+impl TestBot {
+    /// Start with a config for `GROUP_ID`, short debounces and nothing
+    /// scheduled; `overrides` replace its fields.
+    pub async fn start(overrides: Value) -> Self {
+        let dir = tempfile::TempDir::new().unwrap();
+        let api = FakeBotApi::start().await;
+        let claude = FakeClaude::install(dir.path());
+
+        let mut config = json!({
+            "owner_ids": [OWNER_ID],
+            "telegram_bot_token": TOKEN,
+            "allowed_groups": [GROUP_ID],
+            "data_dir": dir.path().join("data"),
+            "claude_program": claude.program(),
+            "debounce_ms_dm": 50,
+            "debounce_ms_group": 50,
+            "debounce_max_ms_group": 500,
+            "startup_notify": "never",
+            "error_digest_hour": null,
+            "backup_cron": null,
+        });
+        for (key, value) in overrides.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        std::fs::create_dir_all(dir.path().join("data")).unwrap();
+        let path = dir.path().join("claudima.json");
+        std::fs::write(&path, config.to_string()).unwrap();
+        let config = Config::load(&path).unwrap();
+
+        let telegram = api.bot();
+        let state = Arc::new(BotState::new(config, &telegram).await);
+        let mut dispatcher = Dispatcher::builder(telegram, bot::handler())
+            .dependencies(dptree::deps![state])
+            .build();
+        tokio::spawn(async move { dispatcher.dispatch().await });
+        Self { api, claude, _dir: dir }
+    }
+}
+
+/// A fixture update: a text message from `user_id` in `chat_id`.
+pub fn text_update(chat_id: i64, message_id: i64, user_id: i64, username: &str, text: &str) -> Value {
+    json!({
+        "message": {
+            "message_id": message_id,
+            "date": 1_700_000_000,
+            "chat": chat_json(chat_id),
+            "from": { "id": user_id, "is_bot": false, "first_name": username, "username": username },
+            "text": text,
+        }
+    })
+}
+
+fn chat_json(chat_id: i64) -> Value {
+    if chat_id < 0 {
+        json!({ "id": chat_id, "type": "supergroup", "title": "Test group" })
+    } else {
+        json!({ "id": chat_id, "type": "private", "first_name": "Tester" })
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<State>>) {
+    let Some((path, body)) = read_request(&mut stream).await else {
+        return;
+    };
+    let delay = download_path(&path).and_then(|file_path| state.lock().unwrap().slow_files.get(&file_path).copied());
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    let (status, content_type, response) = route(&path, &body, &state);
+    // No updates: wait a little like long polling does, so the dispatcher
+    // doesn't spin
+    if path.to_lowercase().ends_with("/getupdates") && response == br#"{"ok":true,"result":[]}"# {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response).await;
+    let _ = stream.shutdown().await;
+}
+
+/// The path and body of one request.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let path = head.lines().next()?.split_whitespace().nth(1)?.to_string();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buf[header_end..].to_vec();
+    while body.len() < length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    Some((path, body))
+}
+
+/// The file_path a file download request is for.
+fn download_path(path: &str) -> Option<String> {
+    // teloxide sends the file path as one URL segment
+    path.strip_prefix(&format!("/file/bot{TOKEN}/")).map(|file_path| file_path.replace("%2F", "/"))
+}
+
+fn route(path: &str, body: &[u8], state: &Mutex<State>) -> (&'static str, &'static str, Vec<u8>) {
+    let mut state = state.lock().unwrap();
+    if let Some(file_path) = download_path(path) {
+        return match state.files.values().find(|(path, _)| *path == file_path) {
+            Some((_, data)) => ("200 OK", "application/octet-stream", data.clone()),
+            None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        };
+    }
+    let Some(method) = path.strip_prefix(&format!("/bot{TOKEN}/")) else {
+        return ("404 Not Found", "text/plain", b"not found".to_vec());
+    };
+    // Method names are case-insensitive; teloxide capitalizes them
+    let mut chars = method.chars();
+    let method: String = chars.next().map(|c| c.to_ascii_lowercase()).into_iter().chain(chars).collect();
+    let params: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+    state.calls.push(Call { method: method.clone(), params: params.clone() });
+
+    let scripted = state.answers.get_mut(&method).and_then(VecDeque::pop_front);
+    let response = scripted.unwrap_or_else(|| default_answer(&method, &params, &mut state));
+    ("200 OK", "application/json", response.to_string().into_bytes())
+}
+
+fn default_answer(method: &str, params: &Value, state: &mut State) -> Value {
+    let result = match method {
+        "getMe" => json!({
+            "id": BOT_USER_ID,
+            "is_bot": true,
+            "first_name": "Claudima",
+            "username": "claudima_test_bot",
+            "can_join_groups": true,
+            "can_read_all_group_messages": true,
+            "supports_inline_queries": false,
+            "has_main_web_app": false,
+        }),
+        "getUpdates" => {
+            let offset = params["offset"].as_i64().unwrap_or(0);
+            Value::Array(state.updates.iter().filter(|u| u["update_id"].as_i64() >= Some(offset)).cloned().collect())
+        }
+        "sendMessage" => {
+            let message_id = FIRST_MESSAGE_ID + state.sent;
+            state.sent += 1;
+            json!({
+                "message_id": message_id,
+                "date": 1_700_000_000,
+                "chat": chat_json(params["chat_id"].as_i64().unwrap_or(0)),
+                "from": { "id": BOT_USER_ID, "is_bot": true, "first_name": "Claudima", "username": "claudima_test_bot" },
+                "text": params["text"],
+            })
+        }
+        "getFile" => {
+            let file_id = params["file_id"].as_str().unwrap_or_default();
+            let Some((file_path, data)) = state.files.get(file_id) else {
+                return json!({ "ok": false, "error_code": 400, "description": "Bad Request: invalid file_id" });
+            };
+            json!({
+                "file_id": file_id,
+                "file_unique_id": format!("unique-{file_id}"),
+                "file_size": data.len(),
+                "file_path": file_path,
+            })
+        }
+        "deleteMessage" | "deleteWebhook" | "banChatMember" | "unbanChatMember" | "restrictChatMember" | "setMessageReaction" => json!(true),
+        _ => return json!({ "ok": false, "error_code": 404, "description": format!("Not Found: method {method} isn't faked") }),
+    };
+    json!({ "ok": true, "result": result })
+}